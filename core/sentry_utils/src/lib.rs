@@ -1,8 +1,86 @@
 use sentry::protocol::{Attachment, Event};
 use sentry::types::random_uuid;
 use sentry::{ClientInitGuard, Envelope, Level};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of breadcrumbs kept, older ones are dropped.
+pub const MAX_BREADCRUMBS: usize = 500;
+
+/*
+ * Tag set on the structured failure events, the logs are uploaded separately
+ * for them so `before_send` skips its upload.
+ */
+const FAILURE_EVENT_TAG: &str = "failure";
+
+/* Tag set on the heartbeat events, they are expected so no logs are uploaded for them. */
+const HEARTBEAT_EVENT_TAG: &str = "heartbeat";
+
+/* Number of failure events captured by the process, reported with the heartbeats. */
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/*
+ * The last significant events of the process, they are attached to the
+ * Sentry events so we get context without uploading the whole log file.
+ */
+static BREADCRUMBS: Mutex<VecDeque<Breadcrumb>> = Mutex::new(VecDeque::new());
+
+/// A significant event, e.g. a socket message or a state transition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breadcrumb {
+    /// Milliseconds since the unix epoch
+    pub timestamp_ms: u128,
+    /// Subsystem that recorded the event, e.g. "socket" or "stream"
+    pub category: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Breadcrumb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} [{}] {}",
+            self.timestamp_ms, self.category, self.message
+        )
+    }
+}
+
+/// Records a breadcrumb, dropping the oldest one when the buffer is full.
+pub fn add_breadcrumb(category: &str, message: impl Into<String>) {
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let mut breadcrumbs = match BREADCRUMBS.lock() {
+        Ok(breadcrumbs) => breadcrumbs,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if breadcrumbs.len() == MAX_BREADCRUMBS {
+        breadcrumbs.pop_front();
+    }
+    breadcrumbs.push_back(Breadcrumb {
+        timestamp_ms,
+        category: category.to_string(),
+        message: message.into(),
+    });
+}
+
+/// Returns the recorded breadcrumbs, oldest first.
+pub fn breadcrumbs() -> Vec<Breadcrumb> {
+    match BREADCRUMBS.lock() {
+        Ok(breadcrumbs) => breadcrumbs.iter().cloned().collect(),
+        Err(poisoned) => poisoned.into_inner().iter().cloned().collect(),
+    }
+}
+
+/// Returns the recorded breadcrumbs formatted one per line, oldest first.
+pub fn breadcrumb_lines() -> Vec<String> {
+    breadcrumbs().iter().map(|b| b.to_string()).collect()
+}
 
 pub fn get_log_path() -> Option<PathBuf> {
     #[cfg(target_os = "macos")]
@@ -36,27 +114,18 @@ pub fn upload_logs_event(failure_reason: String) {
         }
     };
 
-    let log_path = get_log_path();
-    if log_path.is_none() {
-        log::warn!("get_log_path: No log path found");
-        return;
-    }
-
-    let log_path = log_path.unwrap();
-    let logs = match std::fs::read(log_path) {
-        Ok(logs) => logs,
+    let logs = get_log_path().and_then(|log_path| match std::fs::read(log_path) {
+        Ok(logs) => Some(logs),
         Err(e) => {
             log::warn!("get_log_path: Error reading log file: {e}");
-            return;
+            None
         }
-    };
-
-    let log_attachment = Attachment {
-        buffer: logs,
-        filename: "logs.txt".to_string(),
-        content_type: Some("text/plain".to_string()),
-        ..Default::default()
-    };
+    });
+    let breadcrumbs = breadcrumb_lines();
+    if logs.is_none() && breadcrumbs.is_empty() {
+        log::warn!("upload_logs_event: No logs or breadcrumbs to upload");
+        return;
+    }
 
     let event = Event {
         event_id: random_uuid(),
@@ -66,11 +135,129 @@ pub fn upload_logs_event(failure_reason: String) {
     };
 
     let mut envelope: Envelope = event.into();
-    envelope.add_item(log_attachment);
+    if let Some(logs) = logs {
+        envelope.add_item(Attachment {
+            buffer: logs,
+            filename: "logs.txt".to_string(),
+            content_type: Some("text/plain".to_string()),
+            ..Default::default()
+        });
+    }
+    if !breadcrumbs.is_empty() {
+        envelope.add_item(Attachment {
+            buffer: breadcrumbs.join("\n").into_bytes(),
+            filename: "breadcrumbs.txt".to_string(),
+            content_type: Some("text/plain".to_string()),
+            ..Default::default()
+        });
+    }
+
+    client.send_envelope(envelope);
+}
+
+/// Reports a session that ended unexpectedly with its journal attached.
+///
+/// # Arguments
+///
+/// * `message` - Message of the event
+/// * `journal` - The session's journal, one transition per line
+pub fn upload_journal_event(message: String, journal: String) {
+    let Some(client) = sentry::Hub::current().client() else {
+        log::warn!("upload_journal_event: No client found");
+        return;
+    };
+
+    let event = Event {
+        event_id: random_uuid(),
+        message: Some(message),
+        level: Level::Warning,
+        ..Default::default()
+    };
 
+    let mut envelope: Envelope = event.into();
+    envelope.add_item(Attachment {
+        buffer: journal.into_bytes(),
+        filename: "session_journal.txt".to_string(),
+        content_type: Some("text/plain".to_string()),
+        ..Default::default()
+    });
     client.send_envelope(envelope);
 }
 
+/// Builds the event sent by `capture_failure`.
+fn failure_event(message: &str, tags: &[(&str, String)]) -> Event<'static> {
+    let mut event_tags = BTreeMap::new();
+    event_tags.insert("os".to_string(), std::env::consts::OS.to_string());
+    event_tags.insert("arch".to_string(), std::env::consts::ARCH.to_string());
+    for (key, value) in tags {
+        event_tags.insert(key.to_string(), value.clone());
+    }
+    event_tags.insert(FAILURE_EVENT_TAG.to_string(), message.to_string());
+
+    /* Group by the message and the error, so each cause gets its own issue. */
+    let mut fingerprint = vec![Cow::Owned(message.to_string())];
+    if let Some(error) = event_tags.get("error") {
+        fingerprint.push(Cow::Owned(error.clone()));
+    }
+
+    Event {
+        event_id: random_uuid(),
+        message: Some(message.to_string()),
+        level: Level::Error,
+        tags: event_tags,
+        fingerprint: Cow::Owned(fingerprint),
+        ..Default::default()
+    }
+}
+
+/// Sends a structured failure event, its tags are searchable in Sentry so the
+/// failures can be aggregated by cause across the user base.
+///
+/// # Arguments
+///
+/// * `message` - What failed, e.g. "Screen share failed"
+/// * `tags` - Context of the failure, the `error` tag is used for grouping
+pub fn capture_failure(message: &str, tags: &[(&str, String)]) {
+    FAILURES.fetch_add(1, Ordering::Relaxed);
+    add_breadcrumb("sentry", format!("{message}: {tags:?}"));
+    sentry::capture_event(failure_event(message, tags));
+}
+
+/// Returns the number of failures captured with `capture_failure` since the
+/// process started.
+pub fn failure_count() -> u64 {
+    FAILURES.load(Ordering::Relaxed)
+}
+
+/// Builds the event sent by `capture_heartbeat`.
+fn heartbeat_event(message: &str, tags: &[(&str, String)]) -> Event<'static> {
+    let mut event_tags = BTreeMap::new();
+    for (key, value) in tags {
+        event_tags.insert(key.to_string(), value.clone());
+    }
+    event_tags.insert(HEARTBEAT_EVENT_TAG.to_string(), message.to_string());
+
+    Event {
+        event_id: random_uuid(),
+        message: Some(message.to_string()),
+        level: Level::Info,
+        tags: event_tags,
+        /* All the heartbeats are grouped in one issue, its event rate is the health signal. */
+        fingerprint: Cow::Owned(vec![Cow::Owned(message.to_string())]),
+        ..Default::default()
+    }
+}
+
+/// Sends a heartbeat event, a process that stops sending them has died.
+///
+/// # Arguments
+///
+/// * `message` - Which process is alive, e.g. "Core heartbeat"
+/// * `tags` - Anonymous health counters of the process
+pub fn capture_heartbeat(message: &str, tags: &[(&str, String)]) {
+    sentry::capture_event(heartbeat_event(message, tags));
+}
+
 pub fn init_sentry(failure_reason: String, dsn: Option<String>) -> Option<ClientInitGuard> {
     if dsn.is_none() {
         log::warn!("init_sentry: No DSN provided");
@@ -82,10 +269,72 @@ pub fn init_sentry(failure_reason: String, dsn: Option<String>) -> Option<Client
         sentry::ClientOptions {
             release: sentry::release_name!(),
             before_send: Some(Arc::new(move |event| {
-                upload_logs_event(failure_reason.clone());
+                if !event.tags.contains_key(FAILURE_EVENT_TAG)
+                    && !event.tags.contains_key(HEARTBEAT_EVENT_TAG)
+                {
+                    upload_logs_event(failure_reason.clone());
+                }
                 Some(event)
             })),
             ..Default::default()
         },
     )))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breadcrumbs_keep_the_latest() {
+        for i in 0..MAX_BREADCRUMBS + 10 {
+            add_breadcrumb("test", format!("event {i}"));
+        }
+        let breadcrumbs = breadcrumbs();
+        assert_eq!(breadcrumbs.len(), MAX_BREADCRUMBS);
+        assert_eq!(breadcrumbs[0].message, "event 10");
+        assert_eq!(
+            breadcrumbs[MAX_BREADCRUMBS - 1].message,
+            format!("event {}", MAX_BREADCRUMBS + 9)
+        );
+        assert!(breadcrumb_lines()[0].ends_with("[test] event 10"));
+    }
+
+    #[test]
+    fn test_failure_event() {
+        let event = failure_event(
+            "Screen share failed",
+            &[
+                ("error", "StreamCreationError".to_string()),
+                ("display_count", "2".to_string()),
+            ],
+        );
+        assert_eq!(event.level, Level::Error);
+        assert_eq!(event.message.as_deref(), Some("Screen share failed"));
+        assert_eq!(event.tags["error"], "StreamCreationError");
+        assert_eq!(event.tags["display_count"], "2");
+        assert_eq!(event.tags["os"], std::env::consts::OS);
+        assert!(event.tags.contains_key(FAILURE_EVENT_TAG));
+        assert_eq!(
+            event.fingerprint.as_ref(),
+            ["Screen share failed", "StreamCreationError"]
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_event() {
+        let event = heartbeat_event("Core heartbeat", &[("sessions", "3".to_string())]);
+        assert_eq!(event.level, Level::Info);
+        assert_eq!(event.tags["sessions"], "3");
+        assert!(event.tags.contains_key(HEARTBEAT_EVENT_TAG));
+        assert!(!event.tags.contains_key(FAILURE_EVENT_TAG));
+        assert_eq!(event.fingerprint.as_ref(), ["Core heartbeat"]);
+    }
+
+    #[test]
+    fn test_failure_count() {
+        let before = failure_count();
+        capture_failure("Stream failed", &[]);
+        assert!(failure_count() > before);
+    }
+}
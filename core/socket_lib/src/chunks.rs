@@ -0,0 +1,438 @@
+//! Framing of messages too large for a single socket frame.
+//!
+//! A message whose JSON is larger than `CHUNK_SIZE` is split in
+//! `Message::Chunk`s, each one a frame of its own, and the receiver
+//! reassembles them before handing the message out. The receiver only
+//! buffers one chunk per frame, and a sender driving `ChunkedMessage` itself
+//! can send control messages between the chunks, so a thumbnails batch or a
+//! diagnostics bundle doesn't hold them back.
+//!
+//! The chunks of a message arrive in order on the stream. A chunk the
+//! receiver already has is skipped, so a sender can resume a message from
+//! any chunk up to `Reassembler::received`, a gap drops the message.
+//!
+//! The chunk bytes are base64 in the frame's JSON, a JSON array of numbers
+//! would take up to four times the chunk's size. The receiver reassembles
+//! messages up to `MAX_CHUNKED_MESSAGE_SIZE`, and at most
+//! `MAX_PENDING_BYTES` of all the messages it is reassembling, so a broken
+//! or hostile peer can't make it buffer more.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Message;
+
+/// Largest part of a message's JSON sent in one chunk
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/// Largest message that is reassembled, a larger one is dropped
+pub const MAX_CHUNKED_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+/// Bytes of all the messages being reassembled, the oldest are dropped past it
+const MAX_PENDING_BYTES: usize = 2 * MAX_CHUNKED_MESSAGE_SIZE;
+/// Messages being reassembled at once, the oldest is dropped past it
+const MAX_PENDING_MESSAGES: usize = 4;
+/// Largest frame on the stream, a chunk's base64 with its JSON fits with room to spare
+pub(crate) const MAX_FRAME_SIZE: usize = 2 * CHUNK_SIZE;
+
+/* Shared by the duplicates of a socket, they write to the same stream. */
+static NEXT_MESSAGE_ID: AtomicU64 = AtomicU64::new(1);
+
+fn invalid_data(error: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Serializes the bytes of `Message::Chunk` as a base64 string.
+pub(crate) mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = <&str>::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The chunks of a message that is being sent.
+#[derive(Debug)]
+pub struct ChunkedMessage {
+    id: u64,
+    /// The message's JSON
+    bytes: Vec<u8>,
+    /// Index of the next chunk to send
+    next: u32,
+    total: u32,
+}
+
+impl ChunkedMessage {
+    /// Splits the JSON of a message, see `CursorSocket::chunked`.
+    pub(crate) fn new(bytes: Vec<u8>) -> Self {
+        let total = bytes.len().div_ceil(CHUNK_SIZE).max(1) as u32;
+        Self {
+            id: NEXT_MESSAGE_ID.fetch_add(1, Ordering::Relaxed),
+            bytes,
+            next: 0,
+            total,
+        }
+    }
+
+    /// Identifies the message's chunks on the stream.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns `true` when all the chunks were handed out.
+    pub fn is_done(&self) -> bool {
+        self.next >= self.total
+    }
+
+    /// Hands out the chunks again starting from `index`, for a sender that
+    /// reconnected, see `Reassembler::received`.
+    pub fn resume_from(&mut self, index: u32) {
+        self.next = index.min(self.total);
+    }
+}
+
+impl Iterator for ChunkedMessage {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        if self.is_done() {
+            return None;
+        }
+        let start = self.next as usize * CHUNK_SIZE;
+        let end = (start + CHUNK_SIZE).min(self.bytes.len());
+        let chunk = Message::Chunk {
+            id: self.id,
+            index: self.next,
+            total: self.total,
+            bytes: self.bytes[start..end].to_vec(),
+        };
+        self.next += 1;
+        Some(chunk)
+    }
+}
+
+/// A message whose chunks are arriving.
+#[derive(Debug)]
+struct PartialMessage {
+    id: u64,
+    total: u32,
+    /// Chunks received so far
+    received: u32,
+    bytes: Vec<u8>,
+}
+
+/// Puts the chunks of the received messages back together.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    /// Oldest first
+    pending: VecDeque<PartialMessage>,
+}
+
+impl Reassembler {
+    /// Returns how many chunks of message `id` were received, a resumed
+    /// sender starts from there.
+    pub fn received(&self, id: u64) -> u32 {
+        self.pending
+            .iter()
+            .find(|partial| partial.id == id)
+            .map_or(0, |partial| partial.received)
+    }
+
+    /// Adds a chunk of message `id`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The message the chunk belongs to
+    /// * `index` - Position of the chunk in the message
+    /// * `total` - Number of chunks of the message
+    /// * `bytes` - The chunk's part of the message's JSON
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Message))` - The chunk completed the message
+    /// * `Ok(None)` - More chunks are needed, or the chunk was a duplicate
+    /// * `Err(io::Error)` - The chunk doesn't follow the received ones or
+    ///   the message is too large, it is dropped
+    pub fn push(
+        &mut self,
+        id: u64,
+        index: u32,
+        total: u32,
+        bytes: Vec<u8>,
+    ) -> io::Result<Option<Message>> {
+        if index >= total {
+            return Err(invalid_data(format!(
+                "chunk {index} of message {id} with {total} chunks"
+            )));
+        }
+        if bytes.len() > CHUNK_SIZE {
+            self.remove(id);
+            return Err(invalid_data(format!(
+                "chunk {index} of message {id} has {} bytes",
+                bytes.len()
+            )));
+        }
+        let position = match self.pending.iter().position(|partial| partial.id == id) {
+            Some(position) => position,
+            None if index == 0 => {
+                /* Chunks are at most CHUNK_SIZE, so the total bounds the message. */
+                if total as usize > MAX_CHUNKED_MESSAGE_SIZE.div_ceil(CHUNK_SIZE) {
+                    return Err(invalid_data(format!(
+                        "message {id} of {total} chunks is too large"
+                    )));
+                }
+                if self.pending.len() >= MAX_PENDING_MESSAGES {
+                    self.drop_pending(0, "too many messages");
+                }
+                self.pending.push_back(PartialMessage {
+                    id,
+                    total,
+                    received: 0,
+                    bytes: Vec::with_capacity(bytes.len()),
+                });
+                self.pending.len() - 1
+            }
+            None => {
+                return Err(invalid_data(format!(
+                    "chunk {index} of unknown message {id}"
+                )));
+            }
+        };
+
+        let partial = &mut self.pending[position];
+        if partial.total != total || index > partial.received {
+            let error = format!(
+                "chunk {index}/{total} of message {id} after {}/{}",
+                partial.received, partial.total
+            );
+            self.pending.remove(position);
+            return Err(invalid_data(error));
+        }
+        if index < partial.received {
+            log::debug!("Reassembler::push: skipping chunk {index} of message {id}");
+            return Ok(None);
+        }
+        if partial.bytes.len() + bytes.len() > MAX_CHUNKED_MESSAGE_SIZE {
+            self.pending.remove(position);
+            return Err(invalid_data(format!("message {id} is too large")));
+        }
+        partial.bytes.extend_from_slice(&bytes);
+        let position = self.make_room(position);
+        let partial = &mut self.pending[position];
+        partial.received += 1;
+        if partial.received < partial.total {
+            return Ok(None);
+        }
+
+        let Some(partial) = self.pending.remove(position) else {
+            return Ok(None);
+        };
+        let message = serde_json::from_slice(&partial.bytes)?;
+        Ok(Some(message))
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.pending.retain(|partial| partial.id != id);
+    }
+
+    /// Drops the oldest other messages until the pending ones fit in
+    /// `MAX_PENDING_BYTES`, returns the new position of the message at `position`.
+    fn make_room(&mut self, mut position: usize) -> usize {
+        while self.pending_bytes() > MAX_PENDING_BYTES && self.pending.len() > 1 {
+            let oldest = if position == 0 { 1 } else { 0 };
+            self.drop_pending(oldest, "too many pending bytes");
+            if oldest < position {
+                position -= 1;
+            }
+        }
+        position
+    }
+
+    fn drop_pending(&mut self, index: usize, reason: &str) {
+        if let Some(dropped) = self.pending.remove(index) {
+            log::warn!(
+                "Reassembler::push: {reason}, dropping message {} after {}/{}",
+                dropped.id,
+                dropped.received,
+                dropped.total
+            );
+        }
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.pending.iter().map(|partial| partial.bytes.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A message whose JSON takes `chunks` chunks.
+    fn large_message(chunks: usize) -> Message {
+        Message::MacroError("x".repeat(chunks * CHUNK_SIZE - CHUNK_SIZE / 2))
+    }
+
+    fn push_all(reassembler: &mut Reassembler, chunked: ChunkedMessage) -> Option<Message> {
+        let mut reassembled = None;
+        for chunk in chunked {
+            let Message::Chunk {
+                id,
+                index,
+                total,
+                bytes,
+            } = chunk
+            else {
+                panic!("not a chunk");
+            };
+            reassembled = reassembler.push(id, index, total, bytes).unwrap();
+        }
+        reassembled
+    }
+
+    fn chunk_parts(chunk: Message) -> (u64, u32, u32, Vec<u8>) {
+        match chunk {
+            Message::Chunk {
+                id,
+                index,
+                total,
+                bytes,
+            } => (id, index, total, bytes),
+            _ => panic!("not a chunk"),
+        }
+    }
+
+    #[test]
+    fn test_chunk_bytes_are_base64() {
+        let chunk = Message::Chunk {
+            id: 1,
+            index: 0,
+            total: 1,
+            bytes: vec![0, 1, 2, 255],
+        };
+        let json = serde_json::to_string(&chunk).unwrap();
+        assert!(json.contains("\"bytes\":\"AAEC/w==\""), "{json}");
+        let Message::Chunk { bytes, .. } = serde_json::from_str(&json).unwrap() else {
+            panic!("not a chunk");
+        };
+        assert_eq!(bytes, vec![0, 1, 2, 255]);
+
+        /* A full chunk with its JSON fits in a frame. */
+        let chunk = Message::Chunk {
+            id: u64::MAX,
+            index: u32::MAX,
+            total: u32::MAX,
+            bytes: vec![255; CHUNK_SIZE],
+        };
+        assert!(serde_json::to_vec(&chunk).unwrap().len() <= MAX_FRAME_SIZE);
+    }
+
+    #[test]
+    fn test_reassembles_messages() {
+        let message = large_message(3);
+        let chunked = ChunkedMessage::new(serde_json::to_vec(&message).unwrap());
+        assert_eq!(chunked.total, 3);
+
+        let mut reassembler = Reassembler::default();
+        let Some(Message::MacroError(text)) = push_all(&mut reassembler, chunked) else {
+            panic!("message not reassembled");
+        };
+        let Message::MacroError(expected) = message else {
+            unreachable!();
+        };
+        assert_eq!(text, expected);
+        assert!(reassembler.pending.is_empty());
+    }
+
+    #[test]
+    fn test_interleaved_messages() {
+        let mut first = ChunkedMessage::new(serde_json::to_vec(&large_message(2)).unwrap());
+        let mut second = ChunkedMessage::new(serde_json::to_vec(&large_message(2)).unwrap());
+        let mut reassembler = Reassembler::default();
+
+        let (id, index, total, bytes) = chunk_parts(first.next().unwrap());
+        assert!(reassembler.push(id, index, total, bytes).unwrap().is_none());
+        let (id, index, total, bytes) = chunk_parts(second.next().unwrap());
+        assert!(reassembler.push(id, index, total, bytes).unwrap().is_none());
+        let (id, index, total, bytes) = chunk_parts(first.next().unwrap());
+        assert!(reassembler.push(id, index, total, bytes).unwrap().is_some());
+        let (id, index, total, bytes) = chunk_parts(second.next().unwrap());
+        assert!(reassembler.push(id, index, total, bytes).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_duplicates_are_skipped() {
+        let mut chunked = ChunkedMessage::new(serde_json::to_vec(&large_message(3)).unwrap());
+        let mut reassembler = Reassembler::default();
+        for _ in 0..2 {
+            let (id, index, total, bytes) = chunk_parts(chunked.next().unwrap());
+            assert!(reassembler.push(id, index, total, bytes).unwrap().is_none());
+        }
+        assert_eq!(reassembler.received(chunked.id()), 2);
+
+        /* A reconnected sender resumes from the start. */
+        chunked.resume_from(0);
+        assert!(matches!(
+            push_all(&mut reassembler, chunked),
+            Some(Message::MacroError(_))
+        ));
+    }
+
+    #[test]
+    fn test_gap_drops_the_message() {
+        let mut chunked = ChunkedMessage::new(serde_json::to_vec(&large_message(3)).unwrap());
+        let id = chunked.id();
+        let mut reassembler = Reassembler::default();
+
+        let (_, index, total, bytes) = chunk_parts(chunked.next().unwrap());
+        reassembler.push(id, index, total, bytes).unwrap();
+        chunked.next();
+        let (_, index, total, bytes) = chunk_parts(chunked.next().unwrap());
+        let err = reassembler.push(id, index, total, bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(reassembler.received(id), 0);
+
+        /* Without its first chunk the message is unknown. */
+        let err = reassembler.push(id, 1, total, vec![0; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_oversized_messages_are_rejected() {
+        let mut reassembler = Reassembler::default();
+        let too_many = (MAX_CHUNKED_MESSAGE_SIZE / CHUNK_SIZE + 1) as u32;
+        let err = reassembler.push(1, 0, too_many, vec![0; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(reassembler.pending.is_empty());
+
+        reassembler.push(2, 0, 2, vec![0; 4]).unwrap();
+        let err = reassembler
+            .push(2, 1, 2, vec![0; CHUNK_SIZE + 1])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(reassembler.received(2), 0);
+    }
+
+    #[test]
+    fn test_pending_bytes_are_capped() {
+        let mut reassembler = Reassembler::default();
+        let total = (MAX_CHUNKED_MESSAGE_SIZE / CHUNK_SIZE) as u32;
+        /* Three messages one chunk short of the largest one. */
+        for id in 1..=3 {
+            for index in 0..total - 1 {
+                reassembler
+                    .push(id, index, total, vec![b' '; CHUNK_SIZE])
+                    .unwrap();
+            }
+        }
+        assert!(reassembler.pending_bytes() <= MAX_PENDING_BYTES);
+        assert_eq!(reassembler.received(1), 0);
+        assert_eq!(reassembler.received(3), total - 1);
+    }
+}
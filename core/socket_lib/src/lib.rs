@@ -1,8 +1,11 @@
+pub mod chunks;
+pub mod shm;
+
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fs;
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 // Platform-specific imports
@@ -12,6 +15,37 @@ use std::os::unix::net::{UnixListener, UnixStream};
 #[cfg(windows)]
 use std::net::{TcpListener, TcpStream};
 
+/*
+ * Units of the protocol's sizes and positions.
+ *
+ * Sizes (`Extent`, resolutions, monitor sizes) are always physical pixels,
+ * positions default to physical pixels in the global desktop space. Callers
+ * that only have logical points, like the webview, tag their message with
+ * `Units::Logical` and the core converts it with the display's scale factor.
+ */
+
+/// The unit of a position sent over the socket.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    /// Pixels of the display
+    #[default]
+    Physical,
+    /// Physical pixels divided by the display's scale factor, macOS points
+    Logical,
+}
+
+impl Units {
+    /// Converts `value` in these units to physical pixels.
+    pub fn to_physical(self, value: f64, scale_factor: f64) -> f64 {
+        match self {
+            Units::Physical => value,
+            Units::Logical => value * scale_factor,
+        }
+    }
+}
+
+/// A size in physical pixels.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
 pub struct Extent {
@@ -19,18 +53,32 @@ pub struct Extent {
     pub height: f64,
 }
 
+impl Extent {
+    /// Converts a size in logical points to physical pixels.
+    pub fn from_logical(width: f64, height: f64, scale_factor: f64) -> Self {
+        Self {
+            width: width * scale_factor,
+            height: height * scale_factor,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WindowFrameMessage {
     pub origin_x: f64,
     pub origin_y: f64,
     pub width: f64,
     pub height: f64,
+    #[serde(default)]
+    pub units: Units,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CursorPositionMessage {
     pub x: f32,
     pub y: f32,
+    #[serde(default)]
+    pub units: Units,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,12 +88,16 @@ pub struct MouseClickMessage {
     pub button: u32,
     pub clicks: f32,
     pub shift_key: bool,
+    #[serde(default)]
+    pub units: Units,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ScrollMessage {
     pub x: f64,
     pub y: f64,
+    #[serde(default)]
+    pub units: Units,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,18 +110,28 @@ pub struct KeystrokeMessage {
     pub down: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ContentType {
     Display,
     Window { display_id: u32 },
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Content {
     pub content_type: ContentType,
     pub id: u32,
 }
 
+impl Content {
+    /// Returns the display showing the content, the window's display for a window.
+    pub fn display_id(&self) -> u32 {
+        match self.content_type {
+            ContentType::Display => self.id,
+            ContentType::Window { display_id } => display_id,
+        }
+    }
+}
+
 impl fmt::Display for Content {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.content_type {
@@ -86,6 +148,117 @@ pub struct CaptureContent {
     pub content: Content,
     pub base64: String,
     pub title: String,
+    /// The thumbnail was redacted because the content shows a blocked app
+    #[serde(default)]
+    pub redacted: bool,
+    /// Stream resolutions matching the display's geometry, empty for windows
+    /// and when the display's size isn't known
+    #[serde(default)]
+    pub presets: Vec<ResolutionPreset>,
+}
+
+/// A stream resolution suggested for a display.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResolutionPreset {
+    /// Short name of the preset, like "1080p"
+    pub label: String,
+    /// Oriented like the display, portrait displays get portrait presets
+    pub resolution: Extent,
+}
+
+/// How the thumbnails of content showing a blocked app are redacted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThumbnailRedaction {
+    /// The thumbnail is blurred beyond recognition
+    #[default]
+    Blur,
+    /// The thumbnail is replaced with a flat placeholder
+    Placeholder,
+}
+
+/// Apps whose windows are kept out of the content thumbnails.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ThumbnailPrivacy {
+    /// App names, bundle ids on macOS or executable names on Windows, matched
+    /// case insensitively
+    pub blocked_apps: Vec<String>,
+    pub redaction: ThumbnailRedaction,
+    /// Replaces the content's titles with generic labels, e.g. "Window 2",
+    /// for titles naming sensitive documents
+    pub generic_titles: bool,
+}
+
+/// Size of the cursors' badges on top of the display scale.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelSize {
+    Small,
+    #[default]
+    Medium,
+    Large,
+    /// Picked from the physical size of the display, e.g. larger on a TV
+    /// watched from across the room, medium when the size is unknown
+    Auto,
+}
+
+/// How the overlay draws the controllers' cursors.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct OverlayTheme {
+    /// Longest name shown in a cursor's badge in characters, longer names
+    /// keep their start and end around an ellipsis
+    pub max_name_glyphs: u32,
+    /// Draws the cursors on the mirrors of a mirrored shared display too,
+    /// for platforms that only show the overlay on one of them
+    pub mirror_overlays: bool,
+    pub label_size: LabelSize,
+    /// How far in milliseconds the controllers' cursors trail their received
+    /// positions, so they glide over dropped packets, 0 turns the smoothing off
+    pub cursor_smoothing_ms: u32,
+}
+
+impl Default for OverlayTheme {
+    fn default() -> Self {
+        Self {
+            max_name_glyphs: 20,
+            mirror_overlays: false,
+            label_size: LabelSize::default(),
+            cursor_smoothing_ms: 80,
+        }
+    }
+}
+
+/// Call controls a controller can trigger on the sharer's side.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum MediaControlAction {
+    /// Mute the sharer's microphone, or unmute it if it is muted
+    MuteMic,
+    ToggleCamera,
+}
+
+/// Why the shared display stopped showing the sharer's content.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ShareInterruption {
+    DisplaySleep,
+    ScreenLocked,
+    Screensaver,
+    /// The shared window is minimized or covered and delivers no frames
+    WindowNotVisible,
+}
+
+/// A system permission the core needs during a session.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Recording the screen, macOS asks the user for it
+    ScreenCapture,
+}
+
+/// Why the core rejected the room token of a screen share.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RoomTokenRejection {
+    Expired,
+    /// The token is malformed or lacks the grants needed for sharing
+    Invalid,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -98,6 +271,406 @@ pub struct ScreenShareMessage {
     pub content: Content,
     pub token: String,
     pub resolution: Extent,
+    /// The core doesn't create the keyboard and mouse injection for the session
+    #[serde(default)]
+    pub view_only: bool,
+    /// How the captured frames are scaled to `resolution`
+    #[serde(default)]
+    pub aspect_policy: AspectPolicy,
+    /// Target frames per second of the capture and the published video, the core's default when `None`
+    #[serde(default)]
+    pub framerate: Option<u32>,
+}
+
+/// How the captured frames are scaled to the requested stream resolution.
+///
+/// The requested resolution is turned to match the orientation of the
+/// captured frames, a portrait display isn't squeezed into a landscape
+/// stream.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AspectPolicy {
+    /// The longest side matches the resolution, the aspect ratio is kept
+    #[default]
+    Fit,
+    /// The frames are cropped around their center to the resolution's
+    /// aspect ratio, for ultra-wide displays
+    Fill,
+    /// The frames are stretched to the exact resolution
+    Stretch,
+    /// Like `Fit`, but frames smaller than the resolution aren't upscaled
+    Native,
+}
+
+/// Summary of a screen share session, kept for support bundles.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SessionSummary {
+    /// Seconds since the unix epoch
+    pub started_at: u64,
+    pub duration_secs: u64,
+    pub content: String,
+    /// Number of controllers that joined the session
+    pub controllers: u32,
+    pub capture_stalls: u32,
+    /// Why the session ended, `None` while it is active
+    pub end_reason: Option<String>,
+}
+
+/// A session of a previous core process that ended without being stopped,
+/// e.g. the core crashed or was killed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UncleanSession {
+    /// Seconds since the unix epoch
+    pub started_at: u64,
+    /// Seconds since the unix epoch of the last recorded transition
+    pub last_event_at: u64,
+    pub content: Content,
+    pub resolution: Extent,
+    pub view_only: bool,
+    pub aspect_policy: AspectPolicy,
+    /// The session's journal, one transition per line
+    pub journal: Vec<String>,
+}
+
+/// The core's state attached to support bundles.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiagnosticsMessage {
+    pub core_version: String,
+    /// The core's recent significant events, oldest first
+    pub breadcrumbs: Vec<String>,
+    /// The recent sessions, oldest first
+    pub sessions: Vec<SessionSummary>,
+}
+
+/// The label shown on a display while identifying the displays, `id` is the
+/// display's content id.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DisplayLabel {
+    pub id: u32,
+    pub label: String,
+}
+
+/// Who is admitted to a screen share, the default admits everyone.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AdmissionPolicy {
+    /// Maximum number of admitted participants, `None` for no limit
+    pub max_participants: Option<u32>,
+    /// New participants wait until the sharer admits them
+    pub waiting_room: bool,
+}
+
+/// Whose keystrokes reach the shared computer when several controllers are in control.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyboardArbitration {
+    /// Every controller's keystrokes are injected
+    Anyone,
+    /// Only the keystrokes of the controller that clicked last are injected
+    #[default]
+    LastClicked,
+}
+
+/// A keyboard layout the core knows the keys of, the sharer can force it
+/// when the keys are mapped through the detected layout wrong.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardLayoutKind {
+    Us,
+    /// AZERTY
+    French,
+    /// QWERTZ
+    German,
+    Greek,
+}
+
+/// Why a participant isn't admitted to the screen share yet.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WaitReason {
+    /// The share has `max_participants` participants
+    RoomFull,
+    /// The waiting room is on and the sharer hasn't admitted the participant
+    NeedsApproval,
+}
+
+/// A camera the sharer can publish next to the screen share.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CameraDevice {
+    /// Passed back in `Message::EnableCamera`
+    pub id: String,
+    pub name: String,
+}
+
+/// A microphone the sharer can publish from the core.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MicrophoneDevice {
+    /// Passed back in `Message::EnableMic`
+    pub id: String,
+    pub name: String,
+}
+
+/// The webrtc audio processing applied to the microphone published by the core.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct AudioProcessing {
+    pub echo_cancellation: bool,
+    pub noise_suppression: bool,
+    pub auto_gain_control: bool,
+}
+
+impl Default for AudioProcessing {
+    fn default() -> Self {
+        Self {
+            echo_cancellation: true,
+            noise_suppression: true,
+            auto_gain_control: true,
+        }
+    }
+}
+
+/// A participant in the waiting room of the screen share.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WaitingParticipant {
+    pub sid: String,
+    pub name: String,
+    pub identity: String,
+    pub reason: WaitReason,
+}
+
+/// A participant that raised its hand to get control of the screen share.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ControlRequest {
+    pub sid: String,
+    pub name: String,
+    pub identity: String,
+}
+
+/// Connection quality of a participant, as estimated by LiveKit from the
+/// packet loss, jitter and bitrate of its tracks.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionQualityLevel {
+    Excellent,
+    Good,
+    Poor,
+    /// The participant stopped sending and receiving media
+    Lost,
+}
+
+/// Connection quality of an admitted participant of the screen share.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ParticipantQuality {
+    pub sid: String,
+    pub name: String,
+    pub quality: ConnectionQualityLevel,
+}
+
+/// Where the core's shared memory region is, see `shm`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SharedMemoryInfo {
+    pub path: String,
+    pub slot_count: u32,
+    /// Largest payload in bytes
+    pub slot_size: u32,
+}
+
+/// What a bulk payload carries.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum BulkPayloadKind {
+    /// A JPEG frame of the live preview of the shared content, see `Message::SetSharePreview`
+    PreviewFrame,
+}
+
+/// A payload written to a slot of the shared memory region.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct BulkPayload {
+    pub kind: BulkPayloadKind,
+    pub slot: u32,
+    /// Length of the payload in bytes
+    pub len: u32,
+}
+
+/// Which input injection the active session created, reported from the
+/// core's state so the sharer can verify a view-only session.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct InputInjectionStatus {
+    /// Whether a session is active
+    pub sharing: bool,
+    pub view_only: bool,
+    /// Whether the keyboard injection exists
+    pub keyboard: bool,
+    /// Whether the mouse injection exists
+    pub mouse: bool,
+    /// The sharer's keyboard layout as the platform names it, `None` when it isn't known
+    #[serde(default)]
+    pub keyboard_layout: Option<String>,
+}
+
+/// Parameters of the video a share publishes, sent when the share started
+/// so the sharer can check that the requested resolution was honored.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StreamParameters {
+    /// Size of the published video, the shared content fitted into the requested resolution
+    pub resolution: Extent,
+    /// Max frames per second of the published video
+    pub fps: u32,
+    pub codec: String,
+    /// Max bitrate of the encoder in bits per second, `None` when the transport doesn't limit it
+    pub bitrate: Option<u64>,
+    /// Scale factor of the shared display, `None` when no display is shared
+    pub display_scale: Option<f64>,
+}
+
+/// Whether the shared content reaches the controllers.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum StreamHealth {
+    /// Frames are published
+    Healthy,
+    /// The capture stopped delivering frames, see `Message::CaptureStalled`
+    Stalled,
+    /// The shared display isn't showing the sharer's content
+    Interrupted(ShareInterruption),
+}
+
+/// An admitted participant of the active session.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SessionParticipant {
+    pub sid: String,
+    pub name: String,
+    /// The participant can take control of the sharer's mouse and keyboard
+    pub can_control: bool,
+    /// The participant controls the sharer's mouse right now
+    pub has_control: bool,
+    /// `None` until LiveKit estimated it
+    pub quality: Option<ConnectionQualityLevel>,
+    /// The sharer authorized the participant's clicks, keystrokes and scrolls
+    #[serde(default)]
+    pub input_authorized: bool,
+    /// The sharer hid the participant's cursor from the overlay
+    #[serde(default)]
+    pub cursor_hidden: bool,
+}
+
+/// Snapshot of the core's session, lets the tauri app rebuild its state
+/// after a webview reload.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SessionState {
+    /// Whether a session is active, the other fields are empty otherwise
+    pub sharing: bool,
+    pub view_only: bool,
+    /// The shared content, `None` for a test pattern share
+    pub content: Option<Content>,
+    /// Size of the published video, `None` until the first frame
+    pub resolution: Option<Extent>,
+    pub participants: Vec<SessionParticipant>,
+    /// Whether the session's room is joined and publishing the video
+    pub room_connected: bool,
+    pub stream_health: Option<StreamHealth>,
+}
+
+/// A remote input of a macro, in the normalized coordinates the controllers send.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum MacroInput {
+    MouseMove {
+        x: f64,
+        y: f64,
+    },
+    MouseClick {
+        x: f64,
+        y: f64,
+        button: u32,
+        clicks: u32,
+        down: bool,
+        shift: bool,
+        meta: bool,
+        ctrl: bool,
+        alt: bool,
+    },
+    Keystroke {
+        keys: Vec<String>,
+        shift: bool,
+        meta: bool,
+        ctrl: bool,
+        alt: bool,
+        down: bool,
+    },
+    Scroll {
+        x: f64,
+        y: f64,
+    },
+}
+
+/// An input of a macro and how long to wait before replaying it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MacroStep {
+    pub delay_ms: u64,
+    pub input: MacroInput,
+}
+
+/// A recorded or canned sequence of inputs the sharer can replay.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InputMacro {
+    pub id: String,
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+/// A check of the network pre-flight, see `Message::RunNetworkPreflight`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightCheckKind {
+    /// The LiveKit server URL is known and valid
+    ServerUrl,
+    /// The server's host name resolves
+    Dns,
+    /// The signaling WebSocket endpoint answers the upgrade request
+    Signaling,
+    /// The TURN server answers an allocation over UDP
+    TurnUdp,
+    /// The TURN server answers an allocation over TCP
+    TurnTcp,
+    /// The server's ICE over TCP port accepts connections
+    IceTcp,
+    /// A public STUN server answers over UDP
+    UdpEgress,
+    /// Rough download bandwidth
+    Bandwidth,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightStatus {
+    Passed,
+    /// The call can work but likely with a degraded quality
+    Warning,
+    Failed,
+    /// Not run because an earlier check failed
+    Skipped,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PreflightCheck {
+    pub kind: PreflightCheckKind,
+    pub status: PreflightStatus,
+    /// What the check observed
+    pub detail: String,
+    /// What the user can do about a warning or a failure
+    pub guidance: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// How the media of a call can reach the LiveKit server from this network.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum MediaPath {
+    Udp,
+    /// UDP is blocked, the media falls back to TCP with a higher latency
+    Tcp,
+    /// Only TLS on the signaling port is open, the media needs TURN over TLS
+    TlsOnly,
+    Blocked,
+}
+
+/// Results of the network pre-flight.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NetworkPreflightReport {
+    /// The LiveKit server URL that was checked, `None` when the core has none
+    pub server: Option<String>,
+    pub checks: Vec<PreflightCheck>,
+    pub media_path: MediaPath,
+    /// Rough download bandwidth in Mbit/s, `None` when it couldn't be measured
+    pub bandwidth_mbps: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -105,12 +678,307 @@ pub enum Message {
     GetAvailableContent,
     AvailableContent(AvailableContentMessage),
     StartScreenShare(ScreenShareMessage),
-    StartScreenShareResult(bool),
+    /* Sent by the core process, the published video's parameters or None when the share didn't start. */
+    StartScreenShareResult(Option<StreamParameters>),
+    /* Sent by the tauri app, rescales the shared content's video without leaving the room, answered with ChangeResolutionResult. */
+    ChangeResolution(Extent),
+    /* Sent by the core process, the parameters of the video at the new resolution or None when it wasn't changed. */
+    ChangeResolutionResult(Option<StreamParameters>),
+    /* Sent by the tauri app during a share, changes the target frames per second of the capture and the published video. */
+    SetFramerate(u32),
     StopScreenshare,
     Reset,
     Ping,
     ControllerCursorEnabled(bool),
     LivekitServerUrl(String),
+    /* Sent by the core process on startup and when a share failed without a room service, answered with LivekitServerUrl. */
+    NeedLivekitUrl,
+    /* Sent by the core process when a capture source was added or removed. */
+    AvailableContentChanged,
+    GetBreadcrumbs,
+    /* The core's recent significant events, oldest first. */
+    Breadcrumbs(Vec<String>),
+    /* Sent by the core process, the tauri app applies the action to the call. */
+    MediaControl(MediaControlAction),
+    /* Sent by the core process when the capture stopped delivering frames and was restarted. */
+    CaptureStalled,
+    /* Sent by the core process when the capture failed repeatedly and the session was ended. */
+    FatalCaptureError(String),
+    /* Sent by the core process when a permission was revoked during a session, the session was ended. */
+    PermissionRevoked(Permission),
+    GetDiagnostics,
+    Diagnostics(DiagnosticsMessage),
+    /* Sent by the core process when the shared display slept, locked or the screensaver started. */
+    ShareInterrupted(ShareInterruption),
+    /* Sent by the core process when the shared display shows the sharer's content again. */
+    ShareResumed,
+    /* Sent by the core process before a failed StartScreenShareResult when the token can't be used. */
+    RoomTokenRejected(RoomTokenRejection),
+    /* Sent by the core process before a failed StartScreenShareResult when a session is already active, the active session is kept. */
+    AlreadySharing,
+    /* Sent by the tauri app, the core briefly shows each label on its display. */
+    IdentifyDisplays(Vec<DisplayLabel>),
+    /* Sent by the tauri app, applies to the participants that join after it. */
+    SetAdmissionPolicy(AdmissionPolicy),
+    /* Sent by the tauri app, applies to the active session and the next ones. */
+    SetKeyboardArbitration(KeyboardArbitration),
+    /* Sent by the tauri app, key presses are only injected while one of the apps is in the foreground, an empty list allows every app. */
+    SetKeyboardAppAllowlist(Vec<String>),
+    /* Sent by the tauri app, the controllers' keys are mapped through the layout instead of the detected one, None follows the detected layout. */
+    SetKeyboardLayout(Option<KeyboardLayoutKind>),
+    /* Sent by the core process when the waiting room changed. */
+    WaitingParticipants(Vec<WaitingParticipant>),
+    /* Sent by the tauri app, admits the waiting participant with the sid. */
+    AdmitParticipant(String),
+    /* Sent by the tauri app, removes the participant with the sid from the session, it can rejoin. */
+    KickParticipant {
+        sid: String,
+    },
+    /* Sent by the tauri app, removes the identity's participants and ignores it until the session ends. */
+    BanIdentity {
+        identity: String,
+    },
+    /* Sent by the tauri app, allows or revokes the input of the participant's identity for the session. */
+    SetInputAuthorized {
+        sid: String,
+        authorized: bool,
+    },
+    /* Sent by the tauri app while sharing, hides or shows the participant's cursor in the sharer's overlay until the session ends, the participant keeps watching and its input is unaffected. */
+    SetCursorVisible {
+        sid: String,
+        visible: bool,
+    },
+    /* Sent by the tauri app before GetAvailableContent, redacts the thumbnails of blocked apps. */
+    SetThumbnailPrivacy(ThumbnailPrivacy),
+    /* Sent by the tauri app before sharing, pids or app names excluded from the capture on top of the app's own, applies to the streams started or restarted after it. */
+    SetCaptureExclusions(Vec<String>),
+    /* Sent by the tauri app, syncs the clipboard with the controllers until the session ends, off when a session starts. */
+    SetClipboardSharing(bool),
+    /* Sent by the tauri app before starting a share, applies to the cursors added after it. */
+    SetOverlayTheme(OverlayTheme),
+    /*
+     * Sent by the tauri app, reloads the overlay's markers and badge templates
+     * from the textures directory and redraws the cursors with the current theme.
+     */
+    ReloadAssets,
+    /* Sent by the core process when the connection quality of a participant changed. */
+    ParticipantQualities(Vec<ParticipantQuality>),
+    /* Sent by the tauri app, asks the core for its shared memory region. */
+    OpenSharedMemory,
+    SharedMemoryReady(SharedMemoryInfo),
+    /* Sent by the core process when the region can't be created, bulk data stays on the socket. */
+    SharedMemoryUnavailable(String),
+    /* Announces a payload written to the shared memory region, the receiver frees its slot. */
+    BulkPayload(BulkPayload),
+    /* Sent by the tauri app, starts or stops the live preview of the shared content, the frames only go through the shared memory region. */
+    SetSharePreview(bool),
+    /* Sent by either process, a part of a message too large for one frame, see `chunks`. */
+    Chunk {
+        id: u64,
+        index: u32,
+        total: u32,
+        #[serde(with = "chunks::base64_bytes")]
+        bytes: Vec<u8>,
+    },
+    /* Sent by the tauri app, the core records the inputs of the first controller that sends any. */
+    StartMacroRecording,
+    /* Sent by the tauri app with the macro's name, the core replies with MacroRecorded or MacroRecordingFailed. */
+    StopMacroRecording(String),
+    MacroRecorded(InputMacro),
+    MacroRecordingFailed(String),
+    /* Sent by the tauri app, adds a canned macro the sharer approved, replaces the macro with the same id. */
+    LoadMacro(InputMacro),
+    /* Sent by the tauri app, the core replays the macro while sharing. */
+    PlayMacro {
+        id: String,
+    },
+    /* Sent by the core process when the macro was replayed to the end. */
+    MacroFinished {
+        id: String,
+    },
+    /* Sent by the core process when loading or playing a macro failed or the playback was stopped. */
+    MacroError(String),
+    GetInputInjectionStatus,
+    InputInjectionStatus(InputInjectionStatus),
+    /* Sent by the tauri app, shares a generated moving test pattern instead of capturing the screen, answered with StartScreenShareResult. */
+    StartTestPatternShare {
+        token: String,
+        resolution: Extent,
+        fps: u32,
+    },
+    /* Sent by the tauri app, the core replies with SessionState. */
+    GetSessionState,
+    SessionState(SessionState),
+    /* Sent by the tauri app, the core checks how the LiveKit server is reachable and replies with NetworkPreflightResult. */
+    RunNetworkPreflight,
+    /* Sent by the core process when the pre-flight finished, it takes several seconds. */
+    NetworkPreflightResult(NetworkPreflightReport),
+    /* Sent by the tauri app, the core replies with Cameras. */
+    GetCameras,
+    Cameras(Vec<CameraDevice>),
+    /* Sent by the tauri app while sharing, publishes the camera as a second video track, None picks the default camera. */
+    EnableCamera {
+        device_id: Option<String>,
+    },
+    DisableCamera,
+    /* Sent by the core process when the camera couldn't be published or stopped delivering frames. */
+    CameraError(String),
+    /* Sent by the tauri app, the core replies with Microphones. */
+    GetMicrophones,
+    Microphones(Vec<MicrophoneDevice>),
+    /* Sent by the tauri app while sharing, publishes the microphone as an audio track, None picks the default microphone. */
+    EnableMic {
+        device_id: Option<String>,
+        #[serde(default)]
+        processing: AudioProcessing,
+    },
+    DisableMic,
+    /* Sent by the core process when the microphone couldn't be published or stopped delivering samples. */
+    MicError(String),
+    /* Sent by the tauri app, republishes the core's microphone with or without noise suppression. */
+    SetNoiseSuppression(bool),
+    /* Sent by the core process when it turned noise suppression off because the machine stayed busy. */
+    NoiseSuppressionOverloaded,
+    /* Sent by the tauri app, the core answers with SystemAudioSupport. */
+    GetSystemAudioSupport,
+    /* Sent by the core process, whether the audio the machine plays can be shared on this platform. */
+    SystemAudioSupport(bool),
+    /* Sent by the tauri app while sharing, publishes the audio the machine plays as a second audio track. */
+    EnableSystemAudio,
+    DisableSystemAudio,
+    /* Sent by the core process when the system audio couldn't be published or stopped delivering samples. */
+    SystemAudioError(String),
+    /* Sent by the core process when the raised hands changed, ordered by the time they were raised. */
+    ControlRequests(Vec<ControlRequest>),
+    /* Sent by the tauri app, gives control to the first raised hand and revokes the previous holder's. */
+    GrantNextControl,
+    /* Sent by the tauri app, answered with UncleanSession. */
+    GetUncleanSession,
+    /* Sent by the core process, the last session of a previous core process if it ended unexpectedly. */
+    UncleanSession(Option<UncleanSession>),
+    /* Sent by the tauri app once the user resumed or dismissed the unclean session. */
+    DismissUncleanSession,
+    /* Sent by the tauri app, the core shows the call on top of every window until it is answered or dismissed. */
+    IncomingCall {
+        caller: String,
+        room: String,
+    },
+    /* Sent by the tauri app when the call was answered in the webview or the caller gave up. */
+    DismissIncomingCall,
+    /* Sent by the core process when the sharer answered the call of `room` on the core's notification. */
+    IncomingCallAnswered {
+        room: String,
+        accepted: bool,
+    },
+    /* Sent by the core process when the overlay stopped drawing the controllers' cursors, the core tries to rebuild it. */
+    OverlayDegraded {
+        reason: String,
+    },
+    /* Sent by the core process when the rebuilt overlay draws the cursors again. */
+    OverlayRestored,
+    /* Sent by the tauri app while sharing, rebuilds the overlay and tries again after the core gave up. */
+    RestartOverlay,
+}
+
+impl Message {
+    /// Returns the name of the message without its payload, used for logging
+    /// messages that can carry tokens or large thumbnails.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Message::GetAvailableContent => "GetAvailableContent",
+            Message::AvailableContent(_) => "AvailableContent",
+            Message::StartScreenShare(_) => "StartScreenShare",
+            Message::StartScreenShareResult(_) => "StartScreenShareResult",
+            Message::ChangeResolution(_) => "ChangeResolution",
+            Message::ChangeResolutionResult(_) => "ChangeResolutionResult",
+            Message::SetFramerate(_) => "SetFramerate",
+            Message::StopScreenshare => "StopScreenshare",
+            Message::Reset => "Reset",
+            Message::Ping => "Ping",
+            Message::ControllerCursorEnabled(_) => "ControllerCursorEnabled",
+            Message::LivekitServerUrl(_) => "LivekitServerUrl",
+            Message::NeedLivekitUrl => "NeedLivekitUrl",
+            Message::AvailableContentChanged => "AvailableContentChanged",
+            Message::GetBreadcrumbs => "GetBreadcrumbs",
+            Message::Breadcrumbs(_) => "Breadcrumbs",
+            Message::MediaControl(_) => "MediaControl",
+            Message::CaptureStalled => "CaptureStalled",
+            Message::FatalCaptureError(_) => "FatalCaptureError",
+            Message::PermissionRevoked(_) => "PermissionRevoked",
+            Message::GetDiagnostics => "GetDiagnostics",
+            Message::Diagnostics(_) => "Diagnostics",
+            Message::ShareInterrupted(_) => "ShareInterrupted",
+            Message::ShareResumed => "ShareResumed",
+            Message::RoomTokenRejected(_) => "RoomTokenRejected",
+            Message::AlreadySharing => "AlreadySharing",
+            Message::IdentifyDisplays(_) => "IdentifyDisplays",
+            Message::SetAdmissionPolicy(_) => "SetAdmissionPolicy",
+            Message::SetKeyboardArbitration(_) => "SetKeyboardArbitration",
+            Message::SetKeyboardAppAllowlist(_) => "SetKeyboardAppAllowlist",
+            Message::SetKeyboardLayout(_) => "SetKeyboardLayout",
+            Message::WaitingParticipants(_) => "WaitingParticipants",
+            Message::AdmitParticipant(_) => "AdmitParticipant",
+            Message::KickParticipant { .. } => "KickParticipant",
+            Message::BanIdentity { .. } => "BanIdentity",
+            Message::SetInputAuthorized { .. } => "SetInputAuthorized",
+            Message::SetCursorVisible { .. } => "SetCursorVisible",
+            Message::SetThumbnailPrivacy(_) => "SetThumbnailPrivacy",
+            Message::SetCaptureExclusions(_) => "SetCaptureExclusions",
+            Message::SetClipboardSharing(_) => "SetClipboardSharing",
+            Message::SetOverlayTheme(_) => "SetOverlayTheme",
+            Message::ReloadAssets => "ReloadAssets",
+            Message::ParticipantQualities(_) => "ParticipantQualities",
+            Message::OpenSharedMemory => "OpenSharedMemory",
+            Message::SharedMemoryReady(_) => "SharedMemoryReady",
+            Message::SharedMemoryUnavailable(_) => "SharedMemoryUnavailable",
+            Message::BulkPayload(_) => "BulkPayload",
+            Message::SetSharePreview(_) => "SetSharePreview",
+            Message::Chunk { .. } => "Chunk",
+            Message::StartMacroRecording => "StartMacroRecording",
+            Message::StopMacroRecording(_) => "StopMacroRecording",
+            Message::MacroRecorded(_) => "MacroRecorded",
+            Message::MacroRecordingFailed(_) => "MacroRecordingFailed",
+            Message::LoadMacro(_) => "LoadMacro",
+            Message::PlayMacro { .. } => "PlayMacro",
+            Message::MacroFinished { .. } => "MacroFinished",
+            Message::MacroError(_) => "MacroError",
+            Message::GetInputInjectionStatus => "GetInputInjectionStatus",
+            Message::InputInjectionStatus(_) => "InputInjectionStatus",
+            Message::StartTestPatternShare { .. } => "StartTestPatternShare",
+            Message::GetSessionState => "GetSessionState",
+            Message::SessionState(_) => "SessionState",
+            Message::RunNetworkPreflight => "RunNetworkPreflight",
+            Message::NetworkPreflightResult(_) => "NetworkPreflightResult",
+            Message::GetCameras => "GetCameras",
+            Message::Cameras(_) => "Cameras",
+            Message::EnableCamera { .. } => "EnableCamera",
+            Message::DisableCamera => "DisableCamera",
+            Message::CameraError(_) => "CameraError",
+            Message::GetMicrophones => "GetMicrophones",
+            Message::Microphones(_) => "Microphones",
+            Message::EnableMic { .. } => "EnableMic",
+            Message::DisableMic => "DisableMic",
+            Message::MicError(_) => "MicError",
+            Message::SetNoiseSuppression(_) => "SetNoiseSuppression",
+            Message::NoiseSuppressionOverloaded => "NoiseSuppressionOverloaded",
+            Message::GetSystemAudioSupport => "GetSystemAudioSupport",
+            Message::SystemAudioSupport(_) => "SystemAudioSupport",
+            Message::EnableSystemAudio => "EnableSystemAudio",
+            Message::DisableSystemAudio => "DisableSystemAudio",
+            Message::SystemAudioError(_) => "SystemAudioError",
+            Message::ControlRequests(_) => "ControlRequests",
+            Message::GrantNextControl => "GrantNextControl",
+            Message::GetUncleanSession => "GetUncleanSession",
+            Message::UncleanSession(_) => "UncleanSession",
+            Message::DismissUncleanSession => "DismissUncleanSession",
+            Message::IncomingCall { .. } => "IncomingCall",
+            Message::DismissIncomingCall => "DismissIncomingCall",
+            Message::IncomingCallAnswered { .. } => "IncomingCallAnswered",
+            Message::OverlayDegraded { .. } => "OverlayDegraded",
+            Message::OverlayRestored => "OverlayRestored",
+            Message::RestartOverlay => "RestartOverlay",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -124,17 +992,22 @@ pub struct CursorSocket {
     stream: TcpStream,
     #[cfg(windows)]
     _listener: Option<TcpListener>,
+
+    /// The chunked messages being received, see `chunks`
+    reassembler: chunks::Reassembler,
 }
 
 impl CursorSocket {
     pub fn new(socket_path: &str) -> Result<Self, std::io::Error> {
         #[cfg(unix)]
         {
+            check_owner(Path::new(socket_path))?;
             let stream = UnixStream::connect(socket_path)?;
             stream.set_read_timeout(None)?;
             Ok(Self {
                 stream,
                 _listener: None,
+                reassembler: chunks::Reassembler::default(),
             })
         }
 
@@ -147,19 +1020,18 @@ impl CursorSocket {
             Ok(Self {
                 stream,
                 _listener: None,
+                reassembler: chunks::Reassembler::default(),
             })
         }
     }
 
     pub fn new_create(socket_path: &str) -> Result<Self, std::io::Error> {
         log::info!("Creating socket at {socket_path}");
+        remove_stale_socket(socket_path)?;
         #[cfg(unix)]
         {
-            if Path::new(socket_path).exists() {
-                fs::remove_file(socket_path)?;
-            }
-
             let listener = UnixListener::bind(socket_path)?;
+            write_pid_file(socket_path)?;
             log::info!("Wait for client");
             let (stream, _) = listener.accept()?;
             stream.set_read_timeout(None)?;
@@ -167,15 +1039,12 @@ impl CursorSocket {
             Ok(Self {
                 stream,
                 _listener: Some(listener),
+                reassembler: chunks::Reassembler::default(),
             })
         }
 
         #[cfg(windows)]
         {
-            if Path::new(socket_path).exists() {
-                fs::remove_file(socket_path)?;
-            }
-
             // Get initial port to try
             let mut port = socket_path_to_port(socket_path);
             let mut listener = None;
@@ -204,6 +1073,7 @@ impl CursorSocket {
 
             // Store just the port number in the file
             fs::write(socket_path, port.to_string())?;
+            write_pid_file(socket_path)?;
 
             log::info!("Listening on port {port}, waiting for client");
             let (stream, _) = listener.accept()?;
@@ -212,13 +1082,43 @@ impl CursorSocket {
             Ok(Self {
                 stream,
                 _listener: Some(listener),
+                reassembler: chunks::Reassembler::default(),
             })
         }
     }
 
+    /// Sends `message`, split in chunks when its JSON is larger than `chunks::CHUNK_SIZE`.
     pub fn send_message(&mut self, message: Message) -> Result<(), std::io::Error> {
-        let serialized_message = serde_json::to_string(&message)?;
-        let serialized_message = serialized_message.as_bytes();
+        let serialized_message = serde_json::to_vec(&message)?;
+        let is_chunk = matches!(message, Message::Chunk { .. });
+        if serialized_message.len() <= chunks::CHUNK_SIZE || is_chunk {
+            return self.write_frame(&serialized_message);
+        }
+        let mut chunked = chunks::ChunkedMessage::new(serialized_message);
+        while self.send_chunk(&mut chunked)? {}
+        Ok(())
+    }
+
+    /// Splits `message` in chunks sent with `send_chunk`.
+    ///
+    /// Lets the caller send other messages between the chunks, `send_message`
+    /// sends all of them at once.
+    pub fn chunked(&self, message: &Message) -> Result<chunks::ChunkedMessage, std::io::Error> {
+        Ok(chunks::ChunkedMessage::new(serde_json::to_vec(message)?))
+    }
+
+    /// Sends the next chunk of `chunked`, returns `true` while chunks are left.
+    pub fn send_chunk(
+        &mut self,
+        chunked: &mut chunks::ChunkedMessage,
+    ) -> Result<bool, std::io::Error> {
+        if let Some(chunk) = chunked.next() {
+            self.write_frame(&serde_json::to_vec(&chunk)?)?;
+        }
+        Ok(!chunked.is_done())
+    }
+
+    fn write_frame(&mut self, serialized_message: &[u8]) -> Result<(), std::io::Error> {
         let size = serialized_message.len();
         let mut message_bytes = size.to_le_bytes().to_vec();
         message_bytes.extend_from_slice(serialized_message);
@@ -226,19 +1126,47 @@ impl CursorSocket {
         Ok(())
     }
 
-    pub fn receive_message(&mut self) -> Result<Message, std::io::Error> {
+    fn read_frame(&mut self) -> Result<Message, std::io::Error> {
         let mut size_buffer = [0u8; std::mem::size_of::<usize>()];
         self.stream.read_exact(&mut size_buffer)?;
         let message_size = usize::from_le_bytes(size_buffer);
+        /* Larger messages come in chunks, a larger frame is a broken stream. */
+        if message_size > chunks::MAX_FRAME_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Frame of {message_size} bytes"),
+            ));
+        }
 
         let mut message_buffer = vec![0u8; message_size];
         self.stream.read_exact(&mut message_buffer)?;
-        let buffer_str =
-            String::from_utf8(message_buffer).expect("Failed to convert buffer to string");
-        let deserialized_message: Message = serde_json::from_str(&buffer_str)?;
+        let deserialized_message: Message = serde_json::from_slice(&message_buffer)?;
         Ok(deserialized_message)
     }
 
+    /// Reads frames until a whole message arrived, chunked messages are reassembled.
+    fn read_message(&mut self) -> Result<Message, std::io::Error> {
+        loop {
+            match self.read_frame()? {
+                Message::Chunk {
+                    id,
+                    index,
+                    total,
+                    bytes,
+                } => {
+                    if let Some(message) = self.reassembler.push(id, index, total, bytes)? {
+                        return Ok(message);
+                    }
+                }
+                message => return Ok(message),
+            }
+        }
+    }
+
+    pub fn receive_message(&mut self) -> Result<Message, std::io::Error> {
+        self.read_message()
+    }
+
     pub fn receive_message_with_timeout(
         &mut self,
         timeout: Duration,
@@ -246,18 +1174,7 @@ impl CursorSocket {
         let original_timeout = self.stream.read_timeout()?;
         self.stream.set_read_timeout(Some(timeout))?;
 
-        let result = (|| {
-            let mut size_buffer = [0u8; std::mem::size_of::<usize>()];
-            self.stream.read_exact(&mut size_buffer)?;
-            let message_size = usize::from_le_bytes(size_buffer);
-
-            let mut message_buffer = vec![0u8; message_size];
-            self.stream.read_exact(&mut message_buffer)?;
-            let buffer_str =
-                String::from_utf8(message_buffer).expect("Failed to convert buffer to string");
-            let deserialized_message: Message = serde_json::from_str(&buffer_str)?;
-            Ok(deserialized_message)
-        })();
+        let result = self.read_message();
 
         self.stream.set_read_timeout(original_timeout)?;
 
@@ -269,8 +1186,180 @@ impl CursorSocket {
         Ok(Self {
             stream: new_stream,
             _listener: None,
+            reassembler: chunks::Reassembler::default(),
         })
     }
+
+    /// Writes `data` to `region` and announces it over the socket.
+    ///
+    /// Fails with `WouldBlock` when the receiver hasn't freed any slot yet,
+    /// see `SharedRegion::write`.
+    pub fn send_bulk(
+        &mut self,
+        region: &mut shm::SharedRegion,
+        kind: BulkPayloadKind,
+        data: &[u8],
+    ) -> Result<(), std::io::Error> {
+        let payload = region.write(kind, data)?;
+        self.send_message(Message::BulkPayload(payload))
+    }
+
+    /// Shuts down both halves of the connection.
+    ///
+    /// This also affects the duplicates of the socket, a read blocked on any
+    /// of them returns immediately.
+    pub fn shutdown(&self) -> Result<(), std::io::Error> {
+        self.stream.shutdown(std::net::Shutdown::Both)
+    }
+}
+
+/// Returns the user's directory in the temp directory.
+///
+/// Each user gets their own directory, so the files of different users on a
+/// shared machine don't collide. The directory is created if needed and, on
+/// unix, it must be owned by the user and is only accessible by them.
+pub fn user_dir() -> Result<PathBuf, std::io::Error> {
+    let dir = std::env::temp_dir().join(format!("hopp-{}", user_id()));
+    fs::create_dir_all(&dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        check_owner(&dir)?;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// Returns the path of the socket `socket_name` in the user's directory, see `user_dir`.
+pub fn socket_path(socket_name: &str) -> Result<String, std::io::Error> {
+    Ok(user_dir()?.join(socket_name).to_string_lossy().into_owned())
+}
+
+/// Returns `true` when the socket file was left behind by a process that
+/// isn't running anymore.
+///
+/// Sockets created before the creator's pid was recorded are never
+/// considered stale.
+pub fn is_stale_socket(socket_path: &str) -> bool {
+    match read_pid_file(socket_path) {
+        Some(pid) => !process_alive(pid),
+        None => false,
+    }
+}
+
+/// Removes the socket file left behind by a previous run.
+///
+/// Whether the socket is still used is decided from the creator's pid alone,
+/// connecting to it would be taken by the running core's single accept and
+/// end its session. A socket without a pid file isn't stale, see
+/// `is_stale_socket`, and is left alone.
+///
+/// Fails with `AddrInUse` when the process that created the socket is still
+/// running or the socket has no pid file and, on unix, with `PermissionDenied`
+/// when the file belongs to another user.
+fn remove_stale_socket(socket_path: &str) -> Result<(), std::io::Error> {
+    let path = Path::new(socket_path);
+    if fs::symlink_metadata(path).is_err() {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    check_owner(path)?;
+
+    if !is_stale_socket(socket_path) {
+        let owner = match read_pid_file(socket_path) {
+            Some(pid) => format!("process {pid}"),
+            None => "a process that didn't record its pid".to_string(),
+        };
+        log::error!("remove_stale_socket: {socket_path} may be in use by {owner}");
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            format!("Socket may be in use by {owner}"),
+        ));
+    }
+
+    log::info!("remove_stale_socket: removing {socket_path}");
+    fs::remove_file(path)?;
+    let _ = fs::remove_file(pid_file_path(socket_path));
+    Ok(())
+}
+
+fn pid_file_path(socket_path: &str) -> PathBuf {
+    PathBuf::from(format!("{socket_path}.pid"))
+}
+
+/// Records the pid of the process that created the socket next to it.
+fn write_pid_file(socket_path: &str) -> Result<(), std::io::Error> {
+    fs::write(pid_file_path(socket_path), std::process::id().to_string())
+}
+
+fn read_pid_file(socket_path: &str) -> Option<u32> {
+    let content = fs::read_to_string(pid_file_path(socket_path)).ok()?;
+    content.trim().parse().ok()
+}
+
+/// Returns `true` while the process `pid` is running, whichever user it belongs to.
+#[cfg(unix)]
+pub fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    /* Signal 0 only checks that the process exists, EPERM means it belongs to another user. */
+    let res = unsafe { libc::kill(pid, 0) };
+    res == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Returns `true` while the process `pid` is running, whichever user it belongs to.
+#[cfg(windows)]
+pub fn process_alive(pid: u32) -> bool {
+    use std::os::windows::process::CommandExt;
+
+    /* Don't flash a console window. */
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let output = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/NH", "/FO", "CSV"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output();
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).contains(&format!("\"{pid}\"")),
+        /* Never remove the socket of a process that might be running. */
+        Err(_) => true,
+    }
+}
+
+#[cfg(unix)]
+fn user_id() -> String {
+    unsafe { libc::geteuid() }.to_string()
+}
+
+#[cfg(windows)]
+fn user_id() -> String {
+    std::env::var("USERNAME")
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+/// Fails with `PermissionDenied` when `path` isn't owned by the current user.
+#[cfg(unix)]
+fn check_owner(path: &Path) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.uid() != unsafe { libc::geteuid() } {
+        log::error!(
+            "check_owner: {} is owned by {}",
+            path.display(),
+            metadata.uid()
+        );
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("{} is owned by another user", path.display()),
+        ));
+    }
+    Ok(())
 }
 
 #[cfg(windows)]
@@ -299,3 +1388,72 @@ fn calculate_port_from_hash(socket_path: &str) -> u16 {
     // Use ports in range 49152-65535 (dynamic/private range)
     (hash % 16384 + 49152) as u16
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn temp_socket_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "hopp_socket_lib_{name}_{}.sock",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(pid_file_path(&path.to_string_lossy()));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_live_sockets_are_kept_without_connecting() {
+        let socket_path = temp_socket_path("live");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        write_pid_file(&socket_path).unwrap();
+
+        let err = remove_stale_socket(&socket_path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(Path::new(&socket_path).exists());
+        /* The running core's accept is left alone. */
+        let err = listener.accept().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+
+        fs::remove_file(&socket_path).unwrap();
+        fs::remove_file(pid_file_path(&socket_path)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stale_sockets_are_removed() {
+        let socket_path = temp_socket_path("stale");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        fs::write(pid_file_path(&socket_path), dead_pid.to_string()).unwrap();
+        assert!(is_stale_socket(&socket_path));
+
+        remove_stale_socket(&socket_path).unwrap();
+        assert!(!Path::new(&socket_path).exists());
+        assert!(!pid_file_path(&socket_path).exists());
+        drop(listener);
+
+        assert!(remove_stale_socket(&socket_path).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sockets_without_pid_file_are_kept() {
+        let socket_path = temp_socket_path("no_pid");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        assert!(!is_stale_socket(&socket_path));
+
+        let err = remove_stale_socket(&socket_path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(Path::new(&socket_path).exists());
+
+        drop(listener);
+        fs::remove_file(&socket_path).unwrap();
+    }
+}
@@ -0,0 +1,392 @@
+//! Shared memory channel for bulk binary payloads.
+//!
+//! JSON over the socket is fine for control messages but too slow for
+//! frames and thumbnails. The core creates a file backed region next to its
+//! socket, split in fixed size slots, and the tauri app maps the same file.
+//! A payload is copied into a free slot and announced with a small
+//! `Message::BulkPayload` over the socket. The receiver copies the payload
+//! out and frees the slot.
+//!
+//! There is no eventfd or named Event next to the region on purpose. The
+//! receiver already has a thread blocked on the socket, the announcement
+//! wakes it and tells it which slot to read, in order with the control
+//! messages. An extra notification would wake the same thread a second time,
+//! still need the announcement to find the slot, and the fd would have to be
+//! passed over the socket (SCM_RIGHTS) or the Event named per user on
+//! Windows. A writer that finds every slot taken drops the payload instead
+//! of waiting to be notified, see `SharedRegion::write`.
+//!
+//! A slot goes through `FREE -> WRITING -> READY -> FREE`, the transitions
+//! are atomic stores in the slot's header so both processes agree on who
+//! owns it.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use memmap2::MmapMut;
+
+use crate::{BulkPayload, BulkPayloadKind, SharedMemoryInfo};
+
+/// Identifies a hopp region, "HSHM" in little endian.
+const MAGIC: u32 = 0x4d48_5348;
+const VERSION: u32 = 1;
+/// Bytes reserved for the region header, keeps the slots cache line aligned.
+const REGION_HEADER_SIZE: usize = 64;
+/// Slot header: the state followed by the payload length.
+const SLOT_HEADER_SIZE: usize = 8;
+const SLOT_ALIGNMENT: usize = 64;
+
+const SLOT_FREE: u32 = 0;
+const SLOT_WRITING: u32 = 1;
+const SLOT_READY: u32 = 2;
+
+fn slot_stride(slot_size: u32) -> usize {
+    (SLOT_HEADER_SIZE + slot_size as usize).div_ceil(SLOT_ALIGNMENT) * SLOT_ALIGNMENT
+}
+
+fn region_size(slot_count: u32, slot_size: u32) -> usize {
+    REGION_HEADER_SIZE + slot_count as usize * slot_stride(slot_size)
+}
+
+/// A mapped shared memory region, see the module documentation.
+#[derive(Debug)]
+pub struct SharedRegion {
+    mmap: MmapMut,
+    info: SharedMemoryInfo,
+    /// The creator removes the file when the region is dropped
+    owner: bool,
+    /// Slot the next write starts looking from
+    next_slot: u32,
+}
+
+impl SharedRegion {
+    /// Creates the region `name` in the user's socket directory.
+    ///
+    /// A region left behind by a previous run is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - File name of the region
+    /// * `slot_count` - Number of payloads that can be in flight
+    /// * `slot_size` - Largest payload in bytes
+    pub fn create(name: &str, slot_count: u32, slot_size: u32) -> io::Result<Self> {
+        if slot_count == 0 || slot_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Shared memory needs at least one non empty slot",
+            ));
+        }
+        let path = crate::socket_path(name)?;
+        let _ = fs::remove_file(&path);
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(&path)?;
+        file.set_len(region_size(slot_count, slot_size) as u64)?;
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+
+        let header = [MAGIC, VERSION, slot_count, slot_size];
+        for (i, value) in header.iter().enumerate() {
+            mmap[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        mmap.flush()?;
+        log::info!("SharedRegion::create: {path} {slot_count}x{slot_size}");
+
+        Ok(Self {
+            mmap,
+            info: SharedMemoryInfo {
+                path,
+                slot_count,
+                slot_size,
+            },
+            owner: true,
+            next_slot: 0,
+        })
+    }
+
+    /// Maps a region created by the other process.
+    ///
+    /// Fails with `InvalidData` when the file isn't a region matching `info`.
+    pub fn open(info: &SharedMemoryInfo) -> io::Result<Self> {
+        #[cfg(unix)]
+        crate::check_owner(std::path::Path::new(&info.path))?;
+
+        let file: File = OpenOptions::new().read(true).write(true).open(&info.path)?;
+        let expected = region_size(info.slot_count, info.slot_size) as u64;
+        if file.metadata()?.len() != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Shared memory size doesn't match",
+            ));
+        }
+        let mmap = unsafe { MmapMut::map_mut(&file)? };
+
+        let header_value =
+            |i: usize| u32::from_le_bytes(mmap[i * 4..i * 4 + 4].try_into().unwrap());
+        let header = [
+            header_value(0),
+            header_value(1),
+            header_value(2),
+            header_value(3),
+        ];
+        if header != [MAGIC, VERSION, info.slot_count, info.slot_size] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected shared memory header {header:?}"),
+            ));
+        }
+
+        Ok(Self {
+            mmap,
+            info: info.clone(),
+            owner: false,
+            next_slot: 0,
+        })
+    }
+
+    /// Returns what the other process needs to open the region.
+    pub fn info(&self) -> &SharedMemoryInfo {
+        &self.info
+    }
+
+    fn slot_offset(&self, slot: u32) -> usize {
+        REGION_HEADER_SIZE + slot as usize * slot_stride(self.info.slot_size)
+    }
+
+    fn slot_state(&self, slot: u32) -> &AtomicU32 {
+        let offset = self.slot_offset(slot);
+        /*
+         * The map is page aligned and the slot offsets are multiples of
+         * SLOT_ALIGNMENT, so the state is aligned for an AtomicU32.
+         */
+        unsafe { &*(self.mmap.as_ptr().add(offset) as *const AtomicU32) }
+    }
+
+    /// Copies `data` to a free slot.
+    ///
+    /// # Returns
+    ///
+    /// The payload to announce to the other process. Fails with
+    /// `InvalidInput` when `data` is larger than a slot and with
+    /// `WouldBlock` when every slot is still waiting to be read, live data
+    /// is expected to drop the payload then.
+    pub fn write(&mut self, kind: BulkPayloadKind, data: &[u8]) -> io::Result<BulkPayload> {
+        if data.len() > self.info.slot_size as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Payload of {} bytes doesn't fit a {} bytes slot",
+                    data.len(),
+                    self.info.slot_size
+                ),
+            ));
+        }
+
+        let slot_count = self.info.slot_count;
+        let slot = (0..slot_count)
+            .map(|i| (self.next_slot + i) % slot_count)
+            .find(|&slot| {
+                self.slot_state(slot)
+                    .compare_exchange(
+                        SLOT_FREE,
+                        SLOT_WRITING,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            })
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::WouldBlock, "No free shared memory slot")
+            })?;
+        self.next_slot = (slot + 1) % slot_count;
+
+        let offset = self.slot_offset(slot);
+        let len = data.len() as u32;
+        self.mmap[offset + 4..offset + SLOT_HEADER_SIZE].copy_from_slice(&len.to_le_bytes());
+        let data_offset = offset + SLOT_HEADER_SIZE;
+        self.mmap[data_offset..data_offset + data.len()].copy_from_slice(data);
+        self.slot_state(slot).store(SLOT_READY, Ordering::Release);
+
+        Ok(BulkPayload { kind, slot, len })
+    }
+
+    /// Copies an announced payload out of its slot and frees the slot.
+    ///
+    /// Fails with `InvalidData` when the announcement doesn't match a
+    /// written slot.
+    pub fn read(&mut self, payload: &BulkPayload) -> io::Result<Vec<u8>> {
+        if payload.slot >= self.info.slot_count || payload.len > self.info.slot_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid payload {payload:?}"),
+            ));
+        }
+        if self.slot_state(payload.slot).load(Ordering::Acquire) != SLOT_READY {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Slot {} isn't ready", payload.slot),
+            ));
+        }
+
+        let offset = self.slot_offset(payload.slot);
+        let len = u32::from_le_bytes(
+            self.mmap[offset + 4..offset + SLOT_HEADER_SIZE]
+                .try_into()
+                .unwrap(),
+        );
+        let data_offset = offset + SLOT_HEADER_SIZE;
+        let data = self.mmap[data_offset..data_offset + len.min(payload.len) as usize].to_vec();
+        self.slot_state(payload.slot)
+            .store(SLOT_FREE, Ordering::Release);
+        Ok(data)
+    }
+}
+
+impl Drop for SharedRegion {
+    fn drop(&mut self) {
+        if self.owner {
+            if let Err(e) = fs::remove_file(&self.info.path) {
+                log::warn!(
+                    "SharedRegion::drop: failed to remove {}: {e:?}",
+                    self.info.path
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_name(name: &str) -> String {
+        format!("hopp_shm_test_{name}_{}", std::process::id())
+    }
+
+    #[test]
+    fn test_create_and_open() {
+        let region = SharedRegion::create(&test_name("open"), 2, 100).unwrap();
+        let info = region.info().clone();
+        assert_eq!((info.slot_count, info.slot_size), (2, 100));
+        assert_eq!(
+            fs::metadata(&info.path).unwrap().len(),
+            region_size(2, 100) as u64
+        );
+
+        let opened = SharedRegion::open(&info).unwrap();
+        assert_eq!(opened.info(), &info);
+
+        /* Only the creator removes the file. */
+        drop(opened);
+        assert!(std::path::Path::new(&info.path).exists());
+        drop(region);
+        assert!(!std::path::Path::new(&info.path).exists());
+    }
+
+    #[test]
+    fn test_open_rejects_mismatched_info() {
+        let region = SharedRegion::create(&test_name("mismatch"), 2, 100).unwrap();
+
+        let mut info = region.info().clone();
+        info.slot_count = 3;
+        let err = SharedRegion::open(&info).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        /* Same size, but the header doesn't describe these slots. */
+        let mut info = region.info().clone();
+        info.slot_count = 1;
+        info.slot_size = (slot_stride(100) * 2 - SLOT_HEADER_SIZE) as u32;
+        assert_eq!(region_size(1, info.slot_size), region_size(2, 100));
+        let err = SharedRegion::open(&info).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_write_then_read() {
+        let mut writer = SharedRegion::create(&test_name("roundtrip"), 2, 100).unwrap();
+        let mut reader = SharedRegion::open(writer.info()).unwrap();
+
+        let first = writer
+            .write(BulkPayloadKind::PreviewFrame, b"first")
+            .unwrap();
+        let second = writer
+            .write(BulkPayloadKind::PreviewFrame, &[7u8; 100])
+            .unwrap();
+        assert_ne!(first.slot, second.slot);
+        assert_eq!(first.len, 5);
+
+        assert_eq!(reader.read(&second).unwrap(), vec![7u8; 100]);
+        assert_eq!(reader.read(&first).unwrap(), b"first");
+
+        /* The read freed the slots for the writer. */
+        let third = writer
+            .write(BulkPayloadKind::PreviewFrame, b"third")
+            .unwrap();
+        assert_eq!(reader.read(&third).unwrap(), b"third");
+    }
+
+    #[test]
+    fn test_write_would_block_when_slots_are_taken() {
+        let mut writer = SharedRegion::create(&test_name("full"), 2, 16).unwrap();
+        let mut reader = SharedRegion::open(writer.info()).unwrap();
+
+        let first = writer.write(BulkPayloadKind::PreviewFrame, b"a").unwrap();
+        writer.write(BulkPayloadKind::PreviewFrame, b"b").unwrap();
+        let err = writer
+            .write(BulkPayloadKind::PreviewFrame, b"c")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        reader.read(&first).unwrap();
+        let payload = writer.write(BulkPayloadKind::PreviewFrame, b"c").unwrap();
+        assert_eq!(payload.slot, first.slot);
+    }
+
+    #[test]
+    fn test_write_rejects_oversized_payload() {
+        let mut writer = SharedRegion::create(&test_name("oversized"), 1, 16).unwrap();
+        let err = writer
+            .write(BulkPayloadKind::PreviewFrame, &[0u8; 17])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        /* The slot is still free. */
+        writer
+            .write(BulkPayloadKind::PreviewFrame, &[0u8; 16])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_read_rejects_unwritten_slots() {
+        let mut writer = SharedRegion::create(&test_name("unwritten"), 2, 16).unwrap();
+        let mut reader = SharedRegion::open(writer.info()).unwrap();
+
+        let unwritten = BulkPayload {
+            kind: BulkPayloadKind::PreviewFrame,
+            slot: 0,
+            len: 4,
+        };
+        let err = reader.read(&unwritten).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let out_of_range = BulkPayload {
+            slot: 2,
+            ..unwritten
+        };
+        let err = reader.read(&out_of_range).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        /* A payload is read once. */
+        let payload = writer
+            .write(BulkPayloadKind::PreviewFrame, b"once")
+            .unwrap();
+        reader.read(&payload).unwrap();
+        let err = reader.read(&payload).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,108 @@
+//! Typed messages between the services of the application.
+//!
+//! The capture, overlay, input and session services don't call each other.
+//! What one of them needs from another is posted on the `ServiceBus`, and the
+//! application routes the queued messages to their destination once the
+//! service returns, see `Application::route`. A service is tested alone by
+//! checking what it posted.
+
+use std::collections::VecDeque;
+
+use socket_lib::Message;
+
+use crate::utils::session_journal::JournalEntry;
+use crate::{MouseClickData, ScrollDelta};
+
+/// Mouse input headed for the overlay, which draws the cursors and injects it.
+#[derive(Debug, Clone)]
+pub enum PointerInput {
+    /// A controller's cursor moved, in the normalized coordinates it sent
+    Move {
+        x: f64,
+        y: f64,
+        sid: String,
+    },
+    Click(MouseClickData, String),
+    Scroll(ScrollDelta, String),
+    /// Inputs of a macro replayed with the sharer's cursor, see `input::macros`
+    ReplayMove {
+        x: f64,
+        y: f64,
+    },
+    ReplayClick(MouseClickData),
+    ReplayScroll(ScrollDelta),
+}
+
+#[derive(Debug)]
+pub enum BusMessage {
+    /// Sent to the tauri app over the socket
+    Tauri(Message),
+    /// Mouse input the input service accepted, see `PointerInput`
+    Pointer(PointerInput),
+    /// The controller that has control changed, `None` when the sharer has it back
+    InControl(Option<String>),
+    /// Text copied on the sharer's machine, published to the controllers
+    Clipboard(String),
+    /// The sids of the raised hands in their order, shown in the cursors' badges
+    ControlQueue(Vec<String>),
+    /// The participant was removed from the session, its cursor goes away
+    RevokeController(String),
+    /// The input of the identity was revoked, the sharer gets control back from its controllers
+    TakeBackControl(String),
+    /// Recorded in the active session's journal, see `utils::session_journal`
+    Journal(JournalEntry),
+    /// The degraded overlay is rebuilt on the shared display, see `graphics::overlay_recovery`
+    RebuildOverlay,
+}
+
+/// Queue of the messages the services posted, in the order they were posted.
+#[derive(Debug, Default)]
+pub struct ServiceBus {
+    messages: VecDeque<BusMessage>,
+}
+
+impl ServiceBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn post(&mut self, message: BusMessage) {
+        self.messages.push_back(message);
+    }
+
+    /// Sends `message` to the tauri app.
+    pub fn tauri(&mut self, message: Message) {
+        self.post(BusMessage::Tauri(message));
+    }
+
+    /// Removes the oldest message.
+    pub fn take(&mut self) -> Option<BusMessage> {
+        self.messages.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_are_taken_in_order() {
+        let mut bus = ServiceBus::new();
+        bus.post(BusMessage::InControl(Some("sid".to_string())));
+        bus.tauri(Message::OverlayRestored);
+        bus.post(BusMessage::RebuildOverlay);
+
+        assert!(matches!(bus.take(), Some(BusMessage::InControl(Some(sid))) if sid == "sid"));
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Tauri(Message::OverlayRestored))
+        ));
+        assert!(matches!(bus.take(), Some(BusMessage::RebuildOverlay)));
+        assert!(bus.take().is_none());
+        assert!(bus.is_empty());
+    }
+}
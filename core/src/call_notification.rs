@@ -0,0 +1,203 @@
+//! Notification of an incoming call.
+//!
+//! The webview can be hidden or throttled while the app sits in the tray, a
+//! toast in it is easy to miss. When the tauri app gets an incoming call it
+//! asks the core to show a card with the caller and a decline and an accept
+//! button in an always-on-top window at the top right corner of the primary
+//! display. Clicks on the buttons are hit-tested here and the answer is sent
+//! back to the tauri app, which accepts or rejects the call.
+
+use winit::dpi::{LogicalSize, PhysicalPosition};
+use winit::event_loop::ActiveEventLoop;
+use winit::monitor::MonitorHandle;
+use winit::window::WindowId;
+
+#[cfg(target_os = "macos")]
+use winit::platform::macos::WindowExtMacOS;
+
+#[cfg(target_os = "windows")]
+use winit::platform::windows::WindowExtWindows;
+
+use crate::graphics::graphics_context::cursor::Cursor;
+use crate::graphics::graphics_context::GraphicsContext;
+use crate::utils::geometry::Frame;
+use crate::utils::svg_renderer::{
+    render_call_notification_to_png, CALL_ACCEPT_BUTTON, CALL_CARD_EXTENT, CALL_DECLINE_BUTTON,
+};
+use crate::{get_window_attributes, ServerError};
+
+/// Distance in logical pixels of the card from the display's top right corner,
+/// it keeps the card below the macOS menubar.
+const CALL_CARD_MARGIN: f64 = 48.0;
+
+/// The cursor renderer draws textures at `scale / 2.5` of their size relative
+/// to the window, this draws the card at its size in physical pixels.
+const CALL_CARD_TEXTURE_SCALE: f64 = 2.5;
+
+/// How the sharer answered an incoming call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallAnswer {
+    Accept,
+    Decline,
+}
+
+/// Returns the button of the card at `x`, `y`, in logical pixels from the
+/// card's top left corner, `None` outside of the buttons.
+fn hit_test(x: f64, y: f64) -> Option<CallAnswer> {
+    let contains = |frame: Frame| {
+        x >= frame.origin_x
+            && x < frame.origin_x + frame.extent.width
+            && y >= frame.origin_y
+            && y < frame.origin_y + frame.extent.height
+    };
+    if contains(CALL_ACCEPT_BUTTON) {
+        Some(CallAnswer::Accept)
+    } else if contains(CALL_DECLINE_BUTTON) {
+        Some(CallAnswer::Decline)
+    } else {
+        None
+    }
+}
+
+/// The card of an incoming call, the window is closed when dropped.
+pub struct CallNotification<'a> {
+    gfx: GraphicsContext<'a>,
+    card: Cursor,
+    /// Identifies the call in the answer, passed by the tauri app
+    room: String,
+    /// Last position of the mouse in the window, in physical pixels
+    cursor_position: Option<PhysicalPosition<f64>>,
+}
+
+impl<'a> CallNotification<'a> {
+    /// Opens the card of an incoming call on `monitor`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_loop` - Event loop used to create the window
+    /// * `monitor` - The display the card is shown on
+    /// * `caller` - Name of the caller
+    /// * `room` - Identifies the call, see `CallNotification::room`
+    /// * `textures_path` - Path to texture resources, see `GraphicsContext::new`
+    /// * `render_scale` - Overlay render scale, see `GraphicsContext::new`
+    ///
+    /// # Returns
+    ///
+    /// The shown card, or an error if the window couldn't be created.
+    pub fn show(
+        event_loop: &ActiveEventLoop,
+        monitor: MonitorHandle,
+        caller: &str,
+        room: String,
+        textures_path: &str,
+        render_scale: Option<f64>,
+    ) -> Result<Self, ServerError> {
+        log::info!("CallNotification::show: monitor: {monitor:?} room: {room}");
+        let scale = monitor.scale_factor();
+        let monitor_position = monitor.position();
+        let monitor_size = monitor.size();
+        let position = PhysicalPosition::new(
+            monitor_position.x as f64 + monitor_size.width as f64
+                - (CALL_CARD_EXTENT.width + CALL_CARD_MARGIN) * scale,
+            monitor_position.y as f64 + CALL_CARD_MARGIN * scale,
+        );
+        let window = event_loop
+            .create_window(
+                get_window_attributes()
+                    .with_title("Incoming call")
+                    .with_inner_size(LogicalSize::new(
+                        CALL_CARD_EXTENT.width,
+                        CALL_CARD_EXTENT.height,
+                    ))
+                    .with_position(position),
+            )
+            .map_err(|_| ServerError::WindowCreationError)?;
+
+        #[cfg(target_os = "windows")]
+        {
+            window.set_skip_taskbar(true);
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            window.set_has_shadow(false);
+        }
+
+        window.set_visible(true);
+
+        let mut gfx = GraphicsContext::new(window, textures_path.to_string(), scale, render_scale)
+            .map_err(|error| {
+                log::error!("CallNotification::show: Error creating graphics context {error:?}");
+                ServerError::GfxCreationError(error.to_string())
+            })?;
+
+        let png = render_call_notification_to_png(caller, scale).map_err(|error| {
+            log::error!("CallNotification::show: Error rendering card {error:?}");
+            ServerError::GfxCreationError(error.to_string())
+        })?;
+        let mut card = gfx
+            .create_cursor(&png, CALL_CARD_TEXTURE_SCALE)
+            .map_err(|error| {
+                log::error!("CallNotification::show: Error creating card {error:?}");
+                ServerError::GfxCreationError(error.to_string())
+            })?;
+        card.set_center(0.5, 0.5);
+        gfx.window().request_redraw();
+
+        Ok(Self {
+            gfx,
+            card,
+            room,
+            cursor_position: None,
+        })
+    }
+
+    /// Returns the room of the call, the tauri app matches the answer with it.
+    pub fn room(&self) -> &str {
+        &self.room
+    }
+
+    /// Returns `true` if `window_id` is the card's window.
+    pub fn owns(&self, window_id: WindowId) -> bool {
+        self.gfx.window().id() == window_id
+    }
+
+    /// Draws the card, returns `false` if the window isn't the card's window.
+    pub fn draw(&self, window_id: WindowId) -> bool {
+        if !self.owns(window_id) {
+            return false;
+        }
+        self.gfx.draw_label(&self.card);
+        true
+    }
+
+    /// Records the mouse position in the card's window, in physical pixels.
+    pub fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.cursor_position = Some(position);
+    }
+
+    /// Returns the button under the mouse when the card was clicked.
+    pub fn clicked(&self) -> Option<CallAnswer> {
+        let position = self.cursor_position?;
+        let scale = self.gfx.window().scale_factor();
+        hit_test(position.x / scale, position.y / scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_test() {
+        assert_eq!(hit_test(20.0, 80.0), Some(CallAnswer::Decline));
+        assert_eq!(hit_test(340.0, 110.0), Some(CallAnswer::Accept));
+        /* The caller's name and the gap between the buttons aren't buttons. */
+        assert_eq!(hit_test(100.0, 40.0), None);
+        assert_eq!(hit_test(180.0, 90.0), None);
+        assert_eq!(
+            hit_test(CALL_CARD_EXTENT.width, CALL_CARD_EXTENT.height),
+            None
+        );
+    }
+}
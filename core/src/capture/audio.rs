@@ -0,0 +1,167 @@
+//! Capture of the audio the sharer's machine plays.
+//!
+//! The system audio is published next to the screen share, so the
+//! participants hear the video or the call the sharer is showing. It is
+//! captured from the default output device and published as is, without the
+//! voice processing of the microphone, see `publisher::publish_audio_track`.
+//!
+//! Only WASAPI records what an output device plays, on windows an input stream
+//! is built on the output device. macOS and linux need a virtual loopback
+//! device, the tauri app hides the system audio there, see
+//! `SystemAudioCapture::supported`, and enabling it fails with
+//! `SystemAudioError::Unsupported` without publishing a track.
+//!
+//! The stream's samples are pushed to the audio source like the
+//! microphone's, see `microphone::stream_samples`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+
+use cpal::traits::StreamTrait;
+use cpal::{Device, SupportedStreamConfig};
+
+use super::microphone::{build_stream, stream_samples};
+use crate::event_sender::EventSender;
+use crate::room::media::SharedAudioSink;
+use crate::UserEvent;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SystemAudioError {
+    #[error("The system audio can't be captured on this platform")]
+    Unsupported,
+    #[error("No output device found")]
+    NoOutputDevice,
+    #[error("Failed to open the output device: {0}")]
+    Open(String),
+    #[error("System audio stream failed: {0}")]
+    Stream(String),
+    #[error("Failed to publish system audio: {0}")]
+    Publish(String),
+    #[error("System audio thread stopped before the output device was opened")]
+    Stopped,
+}
+
+/// Returns the default output device and the config its loopback is captured with.
+#[cfg(target_os = "windows")]
+fn open_output_device() -> Result<(Device, SupportedStreamConfig), SystemAudioError> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or(SystemAudioError::NoOutputDevice)?;
+    let config = device
+        .default_output_config()
+        .map_err(|e| SystemAudioError::Open(e.to_string()))?;
+    Ok((device, config))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn open_output_device() -> Result<(Device, SupportedStreamConfig), SystemAudioError> {
+    Err(SystemAudioError::Unsupported)
+}
+
+/// The system audio being published, the capture stops when it is dropped.
+#[derive(Debug)]
+pub struct SystemAudioCapture {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SystemAudioCapture {
+    /// Returns whether the system audio can be captured on this platform.
+    pub fn supported() -> bool {
+        cfg!(target_os = "windows")
+    }
+
+    /// Opens the default output device and starts pushing what it plays to
+    /// the track `publish` creates.
+    ///
+    /// # Parameters
+    /// - `event_sender`: Gets `UserEvent::SystemAudioFailed` when the stream fails
+    /// - `publish`: Publishes a track of the output device's sample rate and
+    ///   channels and returns its source
+    ///
+    /// # Returns
+    /// The running capture, or the error that kept the system audio from being
+    /// captured or published.
+    pub fn start(
+        event_sender: Box<dyn EventSender>,
+        publish: impl FnOnce(u32, u32) -> Result<SharedAudioSink, SystemAudioError>,
+    ) -> Result<Self, SystemAudioError> {
+        log::info!("SystemAudioCapture::start");
+        let (format_tx, format_rx) = mpsc::channel();
+        let (source_tx, source_rx) = mpsc::channel::<SharedAudioSink>();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let (samples_tx, samples_rx) = mpsc::channel();
+            let opened = open_output_device().and_then(|(device, config)| {
+                let failed = move |e: String| {
+                    log::error!("SystemAudioCapture: stream error: {e}");
+                    let _ = event_sender.send(UserEvent::SystemAudioFailed(
+                        SystemAudioError::Stream(e).to_string(),
+                    ));
+                };
+                build_stream(&device, &config, samples_tx, failed)
+                    .map(|stream| (stream, config.sample_rate().0, u32::from(config.channels())))
+                    .map_err(|e| SystemAudioError::Open(e.to_string()))
+            });
+            let (stream, sample_rate, num_channels) = match opened {
+                Ok(opened) => opened,
+                Err(e) => {
+                    let _ = format_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = format_tx.send(Ok((sample_rate, num_channels)));
+            /* The sender is dropped without a source when publishing failed. */
+            let Ok(source) = source_rx.recv() else {
+                return;
+            };
+            if let Err(e) = stream.play() {
+                log::error!("SystemAudioCapture: Failed to play stream: {e:?}");
+                return;
+            }
+            stream_samples(
+                samples_rx,
+                &source,
+                sample_rate,
+                num_channels,
+                &thread_stop,
+                None,
+            );
+        });
+
+        let (sample_rate, num_channels) = match format_rx.recv() {
+            Ok(Ok(format)) => format,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(SystemAudioError::Stopped),
+        };
+        log::info!(
+            "SystemAudioCapture::start: output device opened at {sample_rate}Hz {num_channels} channels"
+        );
+        let source = publish(sample_rate, num_channels)?;
+        if source_tx.send(source).is_err() {
+            return Err(SystemAudioError::Stopped);
+        }
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for SystemAudioCapture {
+    fn drop(&mut self) {
+        log::info!("SystemAudioCapture::drop");
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("SystemAudioCapture::drop: System audio thread panicked");
+            }
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Capture of the sharer's webcam.
+//!
+//! The camera is published as a second video track next to the screen share,
+//! so the core owns all the media of the session and the webview never needs
+//! `getUserMedia` for video. The frames are read on a thread of their own,
+//! `Camera` handles of some backends can't move between threads, and pushed
+//! as I420 to the source of the camera track.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+
+use livekit::webrtc::native::yuv_helper;
+use nokhwa::pixel_format::RgbAFormat;
+use nokhwa::utils::{
+    ApiBackend, CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType,
+    Resolution,
+};
+use nokhwa::Camera;
+use socket_lib::CameraDevice;
+
+use crate::event_sender::EventSender;
+use crate::room::media::{I420Frame, SharedVideoSink, VideoFrameRef};
+use crate::UserEvent;
+
+/// The format asked from the camera, the closest one it supports is used.
+const CAMERA_WIDTH: u32 = 1280;
+const CAMERA_HEIGHT: u32 = 720;
+const CAMERA_FRAMERATE: u32 = 30;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CameraError {
+    #[error("Camera {0} not found")]
+    NotFound(String),
+    #[error("No camera found")]
+    NoCamera,
+    #[error("Failed to open camera: {0}")]
+    Open(String),
+    #[error("Failed to read camera frame: {0}")]
+    Frame(String),
+    #[error("Failed to publish camera: {0}")]
+    Publish(String),
+    #[error("Camera thread stopped before the camera was opened")]
+    Stopped,
+}
+
+/// Returns the cameras of the machine, an empty list when they can't be listed.
+pub fn list_cameras() -> Vec<CameraDevice> {
+    match nokhwa::query(ApiBackend::Auto) {
+        Ok(cameras) => cameras
+            .iter()
+            .map(|camera| CameraDevice {
+                id: camera.index().as_string(),
+                name: camera.human_name(),
+            })
+            .collect(),
+        Err(e) => {
+            log::error!("list_cameras: {e:?}");
+            vec![]
+        }
+    }
+}
+
+/// Returns the position of the camera to open in `devices`.
+///
+/// # Parameters
+/// - `devices`: The cameras of the machine
+/// - `device_id`: The id of the camera, `None` for the first one
+fn find_device(devices: &[CameraDevice], device_id: Option<&str>) -> Option<usize> {
+    match device_id {
+        Some(id) => devices.iter().position(|device| device.id == id),
+        None => (!devices.is_empty()).then_some(0),
+    }
+}
+
+/// Opens the camera `device_id` and starts its stream.
+fn open_camera(device_id: Option<&str>) -> Result<Camera, CameraError> {
+    /* The first use asks for the camera permission, opening fails until it is granted. */
+    #[cfg(target_os = "macos")]
+    if !nokhwa::nokhwa_check() {
+        nokhwa::nokhwa_initialize(|granted| {
+            log::info!("open_camera: camera access granted: {granted}");
+        });
+    }
+
+    let cameras = nokhwa::query(ApiBackend::Auto).map_err(|e| CameraError::Open(e.to_string()))?;
+    let devices: Vec<CameraDevice> = cameras
+        .iter()
+        .map(|camera| CameraDevice {
+            id: camera.index().as_string(),
+            name: camera.human_name(),
+        })
+        .collect();
+    let index: CameraIndex = match find_device(&devices, device_id) {
+        Some(position) => cameras[position].index().clone(),
+        None => {
+            return Err(match device_id {
+                Some(id) => CameraError::NotFound(id.to_string()),
+                None => CameraError::NoCamera,
+            })
+        }
+    };
+
+    let format =
+        RequestedFormat::new::<RgbAFormat>(RequestedFormatType::Closest(CameraFormat::new(
+            Resolution::new(CAMERA_WIDTH, CAMERA_HEIGHT),
+            FrameFormat::MJPEG,
+            CAMERA_FRAMERATE,
+        )));
+    let mut camera = Camera::new(index, format).map_err(|e| CameraError::Open(e.to_string()))?;
+    camera
+        .open_stream()
+        .map_err(|e| CameraError::Open(e.to_string()))?;
+    Ok(camera)
+}
+
+/// Pushes the camera frames to `source` until `stop` is set.
+fn stream_frames(
+    camera: &mut Camera,
+    source: &SharedVideoSink,
+    stop: &AtomicBool,
+) -> Result<(), CameraError> {
+    let resolution = camera.resolution();
+    let (width, height) = (resolution.width(), resolution.height());
+    let mut video_frame = I420Frame::new(width, height);
+
+    while !stop.load(Ordering::Relaxed) {
+        let frame = camera
+            .frame()
+            .map_err(|e| CameraError::Frame(e.to_string()))?;
+        let rgba = frame
+            .decode_image::<RgbAFormat>()
+            .map_err(|e| CameraError::Frame(e.to_string()))?;
+        if rgba.width() != width || rgba.height() != height {
+            log::warn!(
+                "stream_frames: Skipping {}x{} frame of a {width}x{height} camera",
+                rgba.width(),
+                rgba.height()
+            );
+            continue;
+        }
+
+        /* libyuv names the formats by their word order, its ABGR is RGBA in memory. */
+        let buffer = video_frame.buffer_mut();
+        let (stride_y, stride_u, stride_v) = buffer.strides();
+        let (data_y, data_u, data_v) = buffer.data_mut();
+        yuv_helper::abgr_to_i420(
+            rgba.as_raw(),
+            width * 4,
+            data_y,
+            stride_y,
+            data_u,
+            stride_u,
+            data_v,
+            stride_v,
+            width as i32,
+            height as i32,
+        );
+        source.push_frame(VideoFrameRef::I420(&video_frame));
+    }
+    Ok(())
+}
+
+/// A camera being published, the capture stops when it is dropped.
+#[derive(Debug)]
+pub struct CameraCapture {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CameraCapture {
+    /// Opens a camera and starts pushing its frames to the track `publish` creates.
+    ///
+    /// # Parameters
+    /// - `device_id`: The id of the camera, see `list_cameras`, `None` for the
+    ///   first camera
+    /// - `event_sender`: Gets `UserEvent::CameraFailed` when the camera stops
+    ///   delivering frames
+    /// - `publish`: Publishes a track of the camera's resolution and returns its source
+    ///
+    /// # Returns
+    /// The running capture, or the error that kept the camera from being
+    /// opened or published.
+    pub fn start(
+        device_id: Option<String>,
+        event_sender: Box<dyn EventSender>,
+        publish: impl FnOnce(u32, u32) -> Result<SharedVideoSink, CameraError>,
+    ) -> Result<Self, CameraError> {
+        log::info!("CameraCapture::start: {device_id:?}");
+        let (resolution_tx, resolution_rx) = mpsc::channel();
+        let (source_tx, source_rx) = mpsc::channel::<SharedVideoSink>();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut camera = match open_camera(device_id.as_deref()) {
+                Ok(camera) => camera,
+                Err(e) => {
+                    let _ = resolution_tx.send(Err(e));
+                    return;
+                }
+            };
+            let resolution = camera.resolution();
+            let _ = resolution_tx.send(Ok((resolution.width(), resolution.height())));
+            /* The sender is dropped without a source when publishing failed. */
+            if let Ok(source) = source_rx.recv() {
+                if let Err(e) = stream_frames(&mut camera, &source, &thread_stop) {
+                    log::error!("CameraCapture: {e}");
+                    let _ = event_sender.send(UserEvent::CameraFailed(e.to_string()));
+                }
+            }
+            if let Err(e) = camera.stop_stream() {
+                log::warn!("CameraCapture: Failed to stop stream: {e:?}");
+            }
+        });
+
+        let (width, height) = match resolution_rx.recv() {
+            Ok(Ok(resolution)) => resolution,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(CameraError::Stopped),
+        };
+        log::info!("CameraCapture::start: camera opened at {width}x{height}");
+        let source = publish(width, height)?;
+        if source_tx.send(source).is_err() {
+            return Err(CameraError::Stopped);
+        }
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for CameraCapture {
+    fn drop(&mut self) {
+        log::info!("CameraCapture::drop");
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("CameraCapture::drop: Camera thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_device() {
+        let devices = vec![
+            CameraDevice {
+                id: "0".to_string(),
+                name: "FaceTime HD Camera".to_string(),
+            },
+            CameraDevice {
+                id: "1".to_string(),
+                name: "Logitech BRIO".to_string(),
+            },
+        ];
+        assert_eq!(find_device(&devices, None), Some(0));
+        assert_eq!(find_device(&devices, Some("1")), Some(1));
+        assert_eq!(find_device(&devices, Some("2")), None);
+        assert_eq!(find_device(&[], None), None);
+    }
+}
@@ -0,0 +1,615 @@
+//! Owner of the screen capturer, its stream polling thread and the sharer's
+//! camera, microphone and system audio.
+//!
+//! The application doesn't hold the capturer's mutex, the service locks it
+//! for each of its calls. The capturer reports back through its
+//! `EventSender` like the room services, e.g. `UserEvent::CaptureStalled`,
+//! and the failures the tauri app is told about are posted on the
+//! `ServiceBus`, see `bus`.
+//!
+//! A panic while the capturer is locked poisons its mutex, the service then
+//! replaces the capturer and its polling thread with new ones, see `restart`.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use socket_lib::{AspectPolicy, AudioProcessing, CaptureContent, Content, ContentType, Message};
+use winit::monitor::MonitorHandle;
+
+use crate::bus::ServiceBus;
+use crate::event_sender::EventSender;
+use crate::room::media::SharedVideoSink;
+use crate::room::transport::SessionTransport;
+use crate::room_service::RoomService;
+use crate::utils::cancellation::CancellationToken;
+use crate::utils::geometry::{resolution_presets, Extent, Frame, StreamCrop};
+use crate::ServerError;
+
+use super::audio::{SystemAudioCapture, SystemAudioError};
+use super::camera::{CameraCapture, CameraError};
+use super::capturer::{
+    poll_stream, wait_stream_extent, Capturer, ColorMatrix, ColorSpace, FirstFrameWaiter,
+    MAX_CAPTURE_FRAMERATE,
+};
+use super::microphone::{MicrophoneCapture, MicrophoneError};
+
+/// Where the overlay of the active capture is created.
+#[derive(Debug, Clone)]
+pub struct OverlayTarget {
+    /// The monitor showing the shared content
+    pub monitor: MonitorHandle,
+    /// The part of the captured frames the stream shows
+    pub stream_crop: StreamCrop,
+    /// The shared window's frame in global physical pixels, `None` when a display is shared
+    pub shared_window: Option<Frame>,
+}
+
+/// Snapshot of the active stream, see `CaptureService::stream_status`.
+#[derive(Debug, Clone, Default)]
+pub struct StreamStatus {
+    /// `None` when idle or sharing a test pattern
+    pub content: Option<Content>,
+    /// `None` until the stream delivered a frame
+    pub extent: Option<Extent>,
+    pub paused: bool,
+    pub stalled: bool,
+    /// The matrix the frames are converted with, `None` when idle
+    pub color_matrix: Option<ColorMatrix>,
+}
+
+pub struct CaptureService {
+    /// Shared with the thread polling the active stream for failures
+    capturer: Arc<Mutex<Capturer>>,
+    poll_thread: Option<JoinHandle<()>>,
+    event_sender: Box<dyn EventSender>,
+    cancel: CancellationToken,
+    /// Frames per second the share requested, `None` for the default
+    framerate: Option<u32>,
+    /// The sharer's camera published next to the screen share, `None` while it is off
+    camera: Option<CameraCapture>,
+    /// The sharer's microphone published by the core, `None` while it is off
+    microphone: Option<MicrophoneCapture>,
+    /// The device and processing of the published microphone, it is republished with them
+    microphone_request: Option<(Option<String>, AudioProcessing)>,
+    /// The audio the sharer's machine plays, published next to the screen share, `None` while it is off
+    system_audio: Option<SystemAudioCapture>,
+}
+
+impl CaptureService {
+    /// Creates the capturer and starts polling its streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_sender` - Sender for the capturer's events to the application
+    /// * `cancel` - Stops the polling thread and the capture threads
+    pub fn new(event_sender: impl EventSender, cancel: CancellationToken) -> Self {
+        let event_sender: Box<dyn EventSender> = Box::new(event_sender);
+        let (capturer, poll_thread) = spawn_capturer(event_sender.clone(), cancel.clone());
+        Self {
+            capturer,
+            poll_thread: Some(poll_thread),
+            event_sender,
+            cancel,
+            framerate: None,
+            camera: None,
+            microphone: None,
+            microphone_request: None,
+            system_audio: None,
+        }
+    }
+
+    fn lock(&self, caller: &str) -> Option<MutexGuard<'_, Capturer>> {
+        match self.capturer.lock() {
+            Ok(capturer) => Some(capturer),
+            Err(e) => {
+                log::error!("{caller}: Error locking capturer: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Returns `false` once a panic poisoned the capturer's mutex.
+    pub fn is_valid(&self) -> bool {
+        self.capturer.lock().is_ok()
+    }
+
+    /// Replaces an invalid capturer and its polling thread with new ones.
+    ///
+    /// The previous polling thread isn't joined, it can't lock the poisoned
+    /// capturer and exits with the application's cancellation.
+    pub fn restart(&mut self) {
+        log::warn!("CaptureService::restart: replacing the screen capturer");
+        let (capturer, poll_thread) =
+            spawn_capturer(self.event_sender.clone(), self.cancel.clone());
+        self.capturer = capturer;
+        self.poll_thread = Some(poll_thread);
+    }
+
+    /// Lists the shareable content, displays come with their resolution presets.
+    pub fn available_content(&self, monitors: &[MonitorHandle]) -> Vec<CaptureContent> {
+        let Some(mut capturer) = self.lock("available_content") else {
+            return vec![];
+        };
+        let mut content = match capturer.get_available_content() {
+            Ok(content) => content,
+            Err(e) => {
+                log::error!("available_content: Error getting available content: {e:?}");
+                return vec![];
+            }
+        };
+        for item in content.iter_mut() {
+            if let ContentType::Display = item.content.content_type {
+                let size = Capturer::get_monitor_size(monitors, item.content.id);
+                item.presets = resolution_presets(size.width as u32, size.height as u32);
+            }
+        }
+        content
+    }
+
+    /// Prepares a stream for frames of `extent` while the sharer picks the content.
+    pub fn warm_up(&self, extent: Extent) {
+        if let Some(mut capturer) = self.lock("warm_up") {
+            capturer.warm_up(extent);
+        }
+    }
+
+    /// Starts capturing `content`, its frames are scaled to `resolution` as
+    /// `aspect_policy` says.
+    ///
+    /// The room's buffer source doesn't need to exist yet, the stream
+    /// creates its buffers lazily and `set_buffer_source` connects it later.
+    ///
+    /// # Returns
+    ///
+    /// The size of the stream's frames, known once the first one was captured.
+    pub fn start_capture(
+        &self,
+        content: Content,
+        resolution: Extent,
+        aspect_policy: AspectPolicy,
+    ) -> Result<Extent, ServerError> {
+        let mut capturer = self
+            .lock("start_capture")
+            .ok_or(ServerError::StreamCreationError)?;
+        if let Err(error) = capturer.start_capture(content, resolution, aspect_policy) {
+            log::error!("start_capture: error starting capture: {error:?}");
+            return Err(ServerError::StreamCreationError);
+        }
+        let waiter = capturer.first_frame_waiter();
+        drop(capturer);
+        stream_extent(waiter)
+    }
+
+    /// Starts generating test pattern frames of `resolution` at `fps`.
+    ///
+    /// # Returns
+    ///
+    /// The size of the stream's frames.
+    pub fn start_test_pattern(&self, resolution: Extent, fps: u32) -> Result<Extent, ServerError> {
+        let mut capturer = self
+            .lock("start_test_pattern")
+            .ok_or(ServerError::StreamCreationError)?;
+        capturer.start_test_pattern(resolution, fps);
+        let waiter = capturer.first_frame_waiter();
+        drop(capturer);
+        stream_extent(waiter)
+    }
+
+    /// Restarts the active capture at `resolution`.
+    ///
+    /// # Returns
+    ///
+    /// The size of the restarted stream's frames.
+    pub fn change_resolution(&self, resolution: Extent) -> Result<Extent, ServerError> {
+        let mut capturer = self
+            .lock("change_resolution")
+            .ok_or(ServerError::ActiveStreamNotFound)?;
+        if let Err(e) = capturer.change_resolution(resolution) {
+            log::error!("change_resolution: error changing the stream: {e:?}");
+            return Err(ServerError::ActiveStreamNotFound);
+        }
+        let waiter = capturer.first_frame_waiter();
+        drop(capturer);
+        stream_extent(waiter)
+    }
+
+    /// Connects the active stream to the video source of the room.
+    pub fn set_buffer_source(&self, buffer_source: SharedVideoSink) {
+        if let Some(mut capturer) = self.lock("set_buffer_source") {
+            capturer.set_buffer_source(buffer_source);
+        }
+    }
+
+    /// Stops the active capture, the media tracks and forgets the share's framerate.
+    ///
+    /// # Returns
+    ///
+    /// `false` when the capturer is invalid, see `restart`.
+    pub fn stop<T: SessionTransport>(&mut self, room_service: Option<&RoomService<T>>) -> bool {
+        let Some(mut capturer) = self.lock("CaptureService::stop") else {
+            return false;
+        };
+        capturer.stop_capture();
+        drop(capturer);
+        self.framerate = None;
+        self.disable_camera(room_service);
+        self.disable_microphone(room_service);
+        self.disable_system_audio(room_service);
+        true
+    }
+
+    /// Returns `true` while the capturer has a stream, captured or generated.
+    pub fn has_active_stream(&self) -> bool {
+        self.lock("has_active_stream")
+            .is_some_and(|capturer| capturer.has_active_stream())
+    }
+
+    /// Stops or resumes publishing the active stream's frames, the capture keeps running.
+    ///
+    /// # Arguments
+    ///
+    /// * `paused` - `true` while no participant watches the share
+    pub fn set_publishing_paused(&self, paused: bool) {
+        if let Some(capturer) = self.lock("set_publishing_paused") {
+            capturer.set_publishing_paused(paused);
+        }
+    }
+
+    /// Sets the target frames per second of the capture.
+    ///
+    /// # Arguments
+    ///
+    /// * `framerate` - Frames per second, clamped to 1..=MAX_CAPTURE_FRAMERATE,
+    ///   the defaults when `None`
+    ///
+    /// # Returns
+    ///
+    /// The clamped framerate, for the published video.
+    pub fn set_framerate(&mut self, framerate: Option<u32>) -> Option<u32> {
+        let framerate = framerate.map(|framerate| framerate.clamp(1, MAX_CAPTURE_FRAMERATE));
+        log::info!("set_framerate: {framerate:?}");
+        self.framerate = framerate;
+        if let Some(mut capturer) = self.lock("set_framerate") {
+            capturer.set_framerate(framerate);
+        }
+        framerate
+    }
+
+    /// Records the rate of a source that generates its own frames, e.g. a
+    /// test pattern, the capture's framerate is left to the defaults.
+    pub fn set_source_framerate(&mut self, fps: u32) {
+        self.set_framerate(None);
+        self.framerate = Some(fps);
+    }
+
+    /// Returns the frames per second the share requested, `None` for the default.
+    pub fn framerate(&self) -> Option<u32> {
+        self.framerate
+    }
+
+    /// Returns a snapshot of the active stream, the default without one.
+    pub fn stream_status(&self) -> StreamStatus {
+        let Some(capturer) = self.lock("stream_status") else {
+            return StreamStatus::default();
+        };
+        StreamStatus {
+            content: capturer.active_content(),
+            extent: capturer.current_stream_extent(),
+            paused: capturer.is_publishing_paused(),
+            stalled: capturer.is_stream_stalled(),
+            color_matrix: capturer.current_color_space().map(ColorSpace::matrix),
+        }
+    }
+
+    /// Returns the active stream's latest frame as a JPEG for the sharer's
+    /// preview, `None` before the first frame.
+    pub fn preview_frame(&self, target: Extent) -> Option<Vec<u8>> {
+        self.lock("preview_frame")?.preview_frame(target)
+    }
+
+    /// Returns the part of the captured frames the active stream shows.
+    pub fn stream_crop(&self) -> StreamCrop {
+        self.lock("stream_crop")
+            .map(|capturer| capturer.get_stream_crop())
+            .unwrap_or_default()
+    }
+
+    /// Returns where the overlay of the active capture goes, `None` without
+    /// captured content.
+    pub fn overlay_target(&self, monitors: &[MonitorHandle]) -> Option<OverlayTarget> {
+        let (content, lookup, waiter, shared_window) = {
+            let capturer = self.lock("overlay_target")?;
+            (
+                capturer.active_content()?,
+                capturer.monitor_lookup(),
+                capturer.first_frame_waiter(),
+                capturer.shared_window_frame(),
+            )
+        };
+        /* The lookup can wait for the first frame, the capturer isn't locked meanwhile. */
+        let monitor = lookup.selected_monitor(monitors, content.display_id());
+        Some(OverlayTarget {
+            monitor,
+            stream_crop: waiter
+                .map(|waiter| waiter.stream_crop())
+                .unwrap_or_default(),
+            shared_window,
+        })
+    }
+
+    /// Returns the monitor of the display with the content id `id`.
+    ///
+    /// On linux displays can only be mapped to monitors through an active
+    /// stream, so `None` is returned.
+    pub fn display_monitor(&self, monitors: &[MonitorHandle], id: u32) -> Option<MonitorHandle> {
+        #[cfg(target_os = "linux")]
+        {
+            let _ = monitors;
+            log::warn!("display_monitor: can't map display {id} to a monitor");
+            None
+        }
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        {
+            let lookup = self.lock("display_monitor")?.monitor_lookup();
+            Some(lookup.selected_monitor(monitors, id))
+        }
+    }
+
+    pub fn set_thumbnail_privacy(&self, privacy: socket_lib::ThumbnailPrivacy) {
+        if let Some(mut capturer) = self.lock("set_thumbnail_privacy") {
+            capturer.set_thumbnail_privacy(privacy);
+        }
+    }
+
+    pub fn set_capture_exclusions(&self, exclusions: Vec<String>) {
+        if let Some(mut capturer) = self.lock("set_capture_exclusions") {
+            capturer.set_capture_exclusions(exclusions);
+        }
+    }
+
+    /// Publishes the sharer's camera next to the screen share of the LiveKit room.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_id` - The camera, see `list_cameras`, `None` for the default one
+    /// * `room_service` - The room the camera is published to
+    /// * `bus` - Gets the error for the tauri app when the camera can't be published
+    pub fn enable_camera<T: SessionTransport>(
+        &mut self,
+        device_id: Option<String>,
+        room_service: Option<&RoomService<T>>,
+        bus: &mut ServiceBus,
+    ) {
+        log::info!("enable_camera: {device_id:?}");
+        sentry_utils::add_breadcrumb("state", "enable camera");
+        /* Switching cameras republishes the track. */
+        self.disable_camera(room_service);
+
+        let room_service = match room_service {
+            Some(room_service) if room_service.room_connected() => room_service,
+            _ => {
+                camera_failed(bus, "The camera can only be shared in a call".to_string());
+                return;
+            }
+        };
+        let res = CameraCapture::start(device_id, self.event_sender.clone(), |width, height| {
+            room_service
+                .publish_camera(width, height)
+                .map_err(|e| CameraError::Publish(e.to_string()))
+        });
+        match res {
+            Ok(camera) => self.camera = Some(camera),
+            Err(e) => camera_failed(bus, e.to_string()),
+        }
+    }
+
+    /// Stops the camera capture and unpublishes its track.
+    pub fn disable_camera<T: SessionTransport>(&mut self, room_service: Option<&RoomService<T>>) {
+        let Some(camera) = self.camera.take() else {
+            return;
+        };
+        log::info!("disable_camera");
+        drop(camera);
+        if let Some(room_service) = room_service {
+            room_service.unpublish_camera();
+        }
+    }
+
+    /// Publishes the sharer's microphone to the LiveKit room of the share.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_id` - The microphone, see `list_microphones`, `None` for the default one
+    /// * `processing` - The webrtc audio processing applied to the samples
+    /// * `room_service` - The room the microphone is published to
+    /// * `bus` - Gets the error for the tauri app when the microphone can't be published
+    pub fn enable_microphone<T: SessionTransport>(
+        &mut self,
+        device_id: Option<String>,
+        processing: AudioProcessing,
+        room_service: Option<&RoomService<T>>,
+        bus: &mut ServiceBus,
+    ) {
+        log::info!("enable_microphone: {device_id:?} {processing:?}");
+        sentry_utils::add_breadcrumb("state", "enable microphone");
+        /* Switching microphones or processing republishes the track. */
+        self.disable_microphone(room_service);
+
+        let room_service = match room_service {
+            Some(room_service) if room_service.room_connected() => room_service,
+            _ => {
+                microphone_failed(
+                    bus,
+                    "The microphone can only be shared in a call".to_string(),
+                );
+                return;
+            }
+        };
+        let res = MicrophoneCapture::start(
+            device_id.clone(),
+            self.event_sender.clone(),
+            processing.noise_suppression,
+            |sample_rate, num_channels| {
+                room_service
+                    .publish_microphone(processing, sample_rate, num_channels)
+                    .map_err(|e| MicrophoneError::Publish(e.to_string()))
+            },
+        );
+        match res {
+            Ok(microphone) => {
+                self.microphone = Some(microphone);
+                self.microphone_request = Some((device_id, processing));
+            }
+            Err(e) => microphone_failed(bus, e.to_string()),
+        }
+    }
+
+    /// Republishes the microphone with or without noise suppression.
+    ///
+    /// The audio processing of a track is fixed when it is published, a
+    /// microphone that isn't published gets the processing of `EnableMic`.
+    pub fn set_noise_suppression<T: SessionTransport>(
+        &mut self,
+        enabled: bool,
+        room_service: Option<&RoomService<T>>,
+        bus: &mut ServiceBus,
+    ) {
+        log::info!("set_noise_suppression: {enabled}");
+        let Some((device_id, processing)) = self.microphone_request.clone() else {
+            return;
+        };
+        if processing.noise_suppression == enabled {
+            return;
+        }
+        self.enable_microphone(
+            device_id,
+            AudioProcessing {
+                noise_suppression: enabled,
+                ..processing
+            },
+            room_service,
+            bus,
+        );
+    }
+
+    /// Stops the microphone capture and unpublishes its track.
+    pub fn disable_microphone<T: SessionTransport>(
+        &mut self,
+        room_service: Option<&RoomService<T>>,
+    ) {
+        let Some(microphone) = self.microphone.take() else {
+            return;
+        };
+        log::info!("disable_microphone");
+        self.microphone_request = None;
+        drop(microphone);
+        if let Some(room_service) = room_service {
+            room_service.unpublish_microphone();
+        }
+    }
+
+    /// Publishes the audio the sharer's machine plays to the LiveKit room of the share.
+    pub fn enable_system_audio<T: SessionTransport>(
+        &mut self,
+        room_service: Option<&RoomService<T>>,
+        bus: &mut ServiceBus,
+    ) {
+        log::info!("enable_system_audio");
+        sentry_utils::add_breadcrumb("state", "enable system audio");
+        self.disable_system_audio(room_service);
+        if !SystemAudioCapture::supported() {
+            system_audio_failed(bus, SystemAudioError::Unsupported.to_string());
+            return;
+        }
+
+        let room_service = match room_service {
+            Some(room_service) if room_service.room_connected() => room_service,
+            _ => {
+                system_audio_failed(
+                    bus,
+                    "The system audio can only be shared in a call".to_string(),
+                );
+                return;
+            }
+        };
+        let res =
+            SystemAudioCapture::start(self.event_sender.clone(), |sample_rate, num_channels| {
+                room_service
+                    .publish_audio_track(sample_rate, num_channels)
+                    .map_err(|e| SystemAudioError::Publish(e.to_string()))
+            });
+        match res {
+            Ok(system_audio) => self.system_audio = Some(system_audio),
+            Err(e) => system_audio_failed(bus, e.to_string()),
+        }
+    }
+
+    /// Stops the system audio capture and unpublishes its track.
+    pub fn disable_system_audio<T: SessionTransport>(
+        &mut self,
+        room_service: Option<&RoomService<T>>,
+    ) {
+        let Some(system_audio) = self.system_audio.take() else {
+            return;
+        };
+        log::info!("disable_system_audio");
+        drop(system_audio);
+        if let Some(room_service) = room_service {
+            room_service.unpublish_audio_track();
+        }
+    }
+
+    /// Stops the capture and waits for the polling thread, after the
+    /// application's cancellation.
+    pub fn shutdown(&mut self) {
+        if let Some(mut capturer) = self.lock("CaptureService::shutdown") {
+            capturer.stop_capture();
+        }
+        /* Released before joining, the polling thread locks it on its way out. */
+        if let Some(poll_thread) = self.poll_thread.take() {
+            if poll_thread.join().is_err() {
+                log::error!("CaptureService::shutdown: stream polling thread panicked");
+            }
+        }
+    }
+}
+
+/// Tells the tauri app the camera stopped or couldn't be published.
+pub fn camera_failed(bus: &mut ServiceBus, error: String) {
+    log::warn!("camera_failed: {error}");
+    bus.tauri(Message::CameraError(error));
+}
+
+/// Tells the tauri app the microphone stopped or couldn't be published.
+pub fn microphone_failed(bus: &mut ServiceBus, error: String) {
+    log::warn!("microphone_failed: {error}");
+    bus.tauri(Message::MicError(error));
+}
+
+/// Tells the tauri app the system audio stopped or couldn't be published.
+pub fn system_audio_failed(bus: &mut ServiceBus, error: String) {
+    log::warn!("system_audio_failed: {error}");
+    bus.tauri(Message::SystemAudioError(error));
+}
+
+/// Waits for the first frame of the stream `waiter` belongs to and returns
+/// its size, an error when it didn't deliver one.
+///
+/// The capturer's lock must be released first, see `FirstFrameWaiter`.
+fn stream_extent(waiter: Option<FirstFrameWaiter>) -> Result<Extent, ServerError> {
+    let extent = wait_stream_extent(waiter);
+    if extent.width == 0. || extent.height == 0. {
+        return Err(ServerError::StreamExtentError);
+    }
+    Ok(extent)
+}
+
+/// Creates a capturer and the thread polling its streams.
+fn spawn_capturer(
+    event_sender: Box<dyn EventSender>,
+    cancel: CancellationToken,
+) -> (Arc<Mutex<Capturer>>, JoinHandle<()>) {
+    let capturer = Arc::new(Mutex::new(Capturer::new(event_sender, cancel)));
+    let polled = capturer.clone();
+    (capturer, std::thread::spawn(move || poll_stream(polled)))
+}
@@ -1,15 +1,18 @@
 use base64::prelude::*;
 use image::{codecs::jpeg::JpegEncoder, ImageBuffer, Rgba};
-use livekit::webrtc::{
-    desktop_capturer::{CaptureResult, DesktopCapturer, DesktopFrame},
-    video_source::native::NativeVideoSource,
-};
+use livekit::webrtc::desktop_capturer::{CaptureResult, DesktopCapturer, DesktopFrame};
 
-use socket_lib::{CaptureContent, Content, ContentType};
-use winit::{event_loop::EventLoopProxy, monitor::MonitorHandle};
+use socket_lib::{
+    AspectPolicy, CaptureContent, Content, ContentType, ShareInterruption, ThumbnailPrivacy,
+    ThumbnailRedaction,
+};
+use winit::monitor::MonitorHandle;
 
 use crate::{
-    utils::geometry::{aspect_fit, Extent},
+    event_sender::EventSender,
+    room::media::SharedVideoSink,
+    utils::cancellation::CancellationToken,
+    utils::geometry::{aspect_fit, Extent, Frame, StreamCrop},
     UserEvent,
 };
 use std::sync::{mpsc, Arc, Mutex};
@@ -17,7 +20,39 @@ use std::vec;
 
 #[path = "stream.rs"]
 mod stream;
-use stream::{Stream, StreamRuntimeMessage};
+pub use stream::FirstFrameWaiter;
+pub use stream::MAX_CAPTURE_FRAMERATE;
+use stream::{Stream, StreamOptions, StreamRuntimeMessage};
+
+#[path = "color.rs"]
+mod color;
+pub use color::{ColorMatrix, ColorSpace, HdrTransfer};
+
+#[path = "source_watcher.rs"]
+mod source_watcher;
+use source_watcher::SourceWatcher;
+
+#[path = "interruption_watcher.rs"]
+mod interruption_watcher;
+use interruption_watcher::InterruptionWatcher;
+
+#[path = "window_watcher.rs"]
+mod window_watcher;
+use window_watcher::WindowWatcher;
+
+#[path = "permission.rs"]
+mod permission;
+use permission::{PermissionMonitor, PermissionState};
+
+#[path = "privacy.rs"]
+mod privacy;
+use privacy::{log_title, redact_rgb, replace_titles, ThumbnailFilter};
+
+#[path = "test_pattern.rs"]
+mod test_pattern;
+
+#[path = "triple_buffer.rs"]
+mod triple_buffer;
 
 // Constants for magic numbers
 const JPEG_QUALITY: u8 = 70;
@@ -25,16 +60,48 @@ const THUMBNAIL_WIDTH: f64 = 480.0;
 const THUMBNAIL_HEIGHT: f64 = 360.0;
 const SCREENSHOT_CAPTURE_SLEEP_MS: u64 = 33;
 const MAX_SCREENSHOT_RETRY_ATTEMPTS: u32 = 100;
-const MAX_STREAM_FAILURES_BEFORE_EXIT: u64 = 5;
-const POLL_STREAM_TIMEOUT_SECS: u64 = 100;
-const STREAM_FAILURE_EXIT_CODE: i32 = 2;
-const POLL_STREAM_DATA_SLEEP_MS: u64 = 100;
+const MAX_STREAM_FAILURES: u64 = 5;
+const STREAM_WATCHDOG_INTERVAL_SECS: u64 = 1;
+const FRAME_STALL_TIMEOUT_SECS: u64 = 5;
+/// How long a shared window delivers no frames before it is considered hidden,
+/// shorter than `FRAME_STALL_TIMEOUT_SECS` so a hidden window isn't restarted
+const WINDOW_HIDDEN_TIMEOUT_SECS: u64 = 2;
+/// How long a new stream is waited for to deliver its first frame
+const FIRST_FRAME_TIMEOUT_SECS: u64 = 15;
 
 #[cfg_attr(target_os = "windows", path = "windows.rs")]
 #[cfg_attr(target_os = "macos", path = "macos.rs")]
 #[cfg_attr(target_os = "linux", path = "linux.rs")]
 mod platform;
-pub use platform::ScreenshareFunctions;
+pub use platform::{ScreenshareFunctions, CAPTURE_BACKEND};
+
+/// Where a window is and who owns it, see `ScreenshareExt::get_window_info`.
+#[derive(Debug, Clone)]
+pub struct WindowInfo {
+    /// The window's frame in global physical pixels
+    pub frame: Frame,
+    /// The display showing most of the window
+    pub display_id: u32,
+    /// The names of the app owning the window, like `get_display_apps` lists them
+    pub apps: Vec<String>,
+}
+
+/// Returns the Sentry tags describing the capture of `content`.
+///
+/// # Parameters
+/// - `content`: The captured content, `None` when it isn't known
+pub(crate) fn capture_failure_tags(content: Option<Content>) -> Vec<(&'static str, String)> {
+    let mut tags = vec![("capture_backend", CAPTURE_BACKEND.to_string())];
+    if let Some(content) = content {
+        let content_type = match content.content_type {
+            ContentType::Display => "display",
+            ContentType::Window { .. } => "window",
+        };
+        tags.push(("content_type", content_type.to_string()));
+        tags.push(("content_id", content.id.to_string()));
+    }
+    tags
+}
 
 /// Errors that can occur during screen capturing operations.
 ///
@@ -56,6 +123,12 @@ pub enum CapturerError {
     /// Common causes include:
     #[error("Failed to capture frames")]
     FailedToCaptureFrames,
+
+    /// There is no active stream to change.
+    ///
+    /// Returned by `change_resolution` when nothing is being shared.
+    #[error("No active stream")]
+    NoActiveStream,
 }
 
 /// Platform-specific extensions for screen sharing and monitor management.
@@ -86,6 +159,53 @@ pub trait ScreenshareExt {
     /// The `MonitorHandle` for the specified monitor. If the monitor ID is not found,
     /// returns the first available monitor as a fallback.
     fn get_selected_monitor(monitors: &[MonitorHandle], input_id: u32) -> MonitorHandle;
+
+    /// Detects the color space of a specific display.
+    ///
+    /// # Parameters
+    /// - `input_id`: The identifier of the target display
+    ///
+    /// # Returns
+    /// The `ColorSpace` the display is currently outputting. Falls back to
+    /// `ColorSpace::Srgb` when the display can't be queried.
+    fn get_display_color_space(input_id: u32) -> ColorSpace;
+
+    /// Detects whether a specific display isn't showing the sharer's content.
+    ///
+    /// # Parameters
+    /// - `input_id`: The identifier of the target display
+    ///
+    /// # Returns
+    /// The reason the display is interrupted, `None` when it shows the
+    /// sharer's content or the state can't be queried.
+    fn get_share_interruption(input_id: u32) -> Option<ShareInterruption>;
+
+    /// Lists the apps with visible windows on a specific display.
+    ///
+    /// # Parameters
+    /// - `input_id`: The identifier of the target display
+    ///
+    /// # Returns
+    /// The names of the apps, an app can be listed more than once. Windows
+    /// hidden behind others are listed too, so a blocked app is never missed.
+    fn get_display_apps(input_id: u32) -> Vec<String>;
+
+    /// Looks up a window listed by the window capturer.
+    ///
+    /// # Parameters
+    /// - `window_id`: The identifier of the window in the capturer's source list
+    ///
+    /// # Returns
+    /// The window's frame, display and app, `None` when the window is gone,
+    /// minimized or the platform can't query it.
+    fn get_window_info(window_id: u32) -> Option<WindowInfo>;
+
+    /// Checks the permission to record the screen.
+    ///
+    /// # Returns
+    /// `false` when the user denied or revoked the permission, platforms
+    /// without the permission always return `true`.
+    fn screen_capture_permitted() -> bool;
 }
 
 fn raw_image_to_jpeg(raw_image: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
@@ -100,14 +220,23 @@ fn raw_image_to_jpeg(raw_image: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
     }
 }
 
+/// Encodes tightly packed RGB pixels as a base64 JPEG data URL.
+fn rgb_to_thumbnail(raw_image: Vec<u8>, width: u32, height: u32) -> String {
+    let buffer = raw_image_to_jpeg(raw_image, width, height);
+    let base64 = BASE64_STANDARD.encode(&buffer);
+    format!("data:image/{};base64,{}", "jpeg", base64)
+}
+
 fn screenshot_capture_callback(
     target_extent: Extent,
-    display_id: u32,
+    source: Content,
     display_title: String,
+    redaction: Option<ThumbnailRedaction>,
     content: Arc<Mutex<Vec<CaptureContent>>>,
 ) -> impl Fn(CaptureResult, DesktopFrame) {
     log::debug!(
-        "screenshot_capture_callback: display_id: {display_id}, display_title: {display_title}"
+        "screenshot_capture_callback: source: {source}, display_title: {}",
+        log_title(&display_title)
     );
     move |result: CaptureResult, frame: DesktopFrame| {
         match result {
@@ -117,26 +246,26 @@ fn screenshot_capture_callback(
             }
             CaptureResult::ErrorPermanent => {
                 log::info!(
-                    "Capture frame, permanent error for display: {display_id}, title: {display_title}"
+                    "Capture frame, permanent error for {source}, title: {}",
+                    log_title(&display_title)
                 );
                 let mut content = content.lock().unwrap();
                 content.push(CaptureContent {
-                    content: Content {
-                        content_type: ContentType::Display,
-                        id: display_id,
-                    },
+                    content: source,
                     base64: "".to_string(),
                     title: display_title.clone(),
+                    redacted: false,
+                    presets: vec![],
                 });
                 return;
             }
             _ => {}
         }
-        /* Skip processing if there is content for this display */
+        /* Skip processing if there is content for this source */
         {
             let content = content.lock().unwrap();
             for c in content.iter() {
-                if c.content.id == display_id {
+                if c.content == source {
                     return;
                 }
             }
@@ -185,21 +314,23 @@ fn screenshot_capture_callback(
             .pixels()
             .flat_map(|p| [p[2], p[1], p[0]])
             .collect();
-        let buffer = raw_image_to_jpeg(raw_image, width, height);
-        let base64 = BASE64_STANDARD.encode(&buffer);
-        let base64 = format!("data:image/{};base64,{}", "jpeg", base64);
+        let raw_image = match redaction {
+            Some(redaction) => redact_rgb(raw_image, width, height, redaction),
+            None => raw_image,
+        };
+        let base64 = rgb_to_thumbnail(raw_image, width, height);
 
         let mut content = content.lock().unwrap();
         content.push(CaptureContent {
-            content: Content {
-                content_type: ContentType::Display,
-                id: display_id,
-            },
+            content: source,
             base64,
             title: display_title.clone(),
+            redacted: redaction.is_some(),
+            presets: vec![],
         });
         log::info!(
-            "screenshot_capture_callback: Added display: {display_id}, title: {display_title}"
+            "screenshot_capture_callback: Added {source}, title: {}",
+            log_title(&display_title)
         );
     }
 }
@@ -231,19 +362,58 @@ pub struct Capturer {
     /// is currently in progress.
     active_stream: Option<Stream>,
 
-    /// Event loop proxy for triggering UI updates and application events.
+    /// A stream created ahead of time by `warm_up`, used by the next `start_capture`.
+    warm_stream: Option<Stream>,
+
+    /// The content captured by the active stream.
+    ///
+    /// Used for reusing the active stream's frames when generating the
+    /// thumbnail of the shared display.
+    active_content: Option<Content>,
+
+    /// Sender for triggering UI updates and application events.
     ///
     /// Used to communicate capture state changes back to the main application,
     /// particularly for updating the UI when users stop screen sharing through
     /// system controls. This ensures proper cleanup of tracks and room connections.
-    event_loop_proxy: EventLoopProxy<UserEvent>,
+    event_sender: Box<dyn EventSender>,
+
+    /// Notifies the application when displays are added or removed.
+    _source_watcher: SourceWatcher,
+
+    /// Notifies the application when the shared display sleeps, locks or
+    /// the screensaver starts, exists only while capturing.
+    interruption_watcher: Option<InterruptionWatcher>,
+
+    /// Notifies the application when the shared window moves, exists only
+    /// while capturing a window.
+    window_watcher: Option<WindowWatcher>,
+
+    /// Whether the shared window stopped delivering frames, see `check_window_visibility`.
+    window_hidden: bool,
+
+    /// Redacts the thumbnails of displays showing apps the user blocked.
+    thumbnail_filter: ThumbnailFilter,
+
+    /// The applications excluded from the capture, shared with the streams.
+    capture_exclusions: Arc<Mutex<Vec<String>>>,
+
+    /// Target frames per second of the session's capture, `None` for the default.
+    framerate: Option<u32>,
+
+    /// Detects the screen recording permission being revoked while capturing.
+    permission: PermissionMonitor,
+
+    /// Cancellation of the application, stops `poll_stream` and the capture threads.
+    cancel: CancellationToken,
 }
 
 impl Capturer {
     /// Creates a new capturer instance.
     ///
     /// # Parameters
-    /// - `event_loop_proxy`: Proxy for sending events back to the main application event loop
+    /// - `event_sender`: Sender for events back to the main application event loop
+    /// - `cancel`: Cancellation of the application
     ///
     /// # Returns
     /// A new `Capturer` instance ready to discover and capture screen sources.
@@ -251,14 +421,68 @@ impl Capturer {
     /// # Notes
     /// The capturer is created in an idle state with no active streams.
     /// Use `get_available_content()` to discover sources and `start_capture()` to begin capturing.
-    pub fn new(event_loop_proxy: EventLoopProxy<UserEvent>) -> Self {
+    /// While the capturer exists the available displays and windows are
+    /// watched and `UserEvent::AvailableContentChanged` is sent when they change.
+    pub fn new(event_sender: impl EventSender, cancel: CancellationToken) -> Self {
         let (tx, rx) = mpsc::channel();
+        let event_sender: Box<dyn EventSender> = Box::new(event_sender);
         Capturer {
             rx: Arc::new(Mutex::new(rx)),
             tx,
             active_stream: None,
-            event_loop_proxy,
+            warm_stream: None,
+            active_content: None,
+            _source_watcher: SourceWatcher::new(event_sender.clone()),
+            interruption_watcher: None,
+            window_watcher: None,
+            window_hidden: false,
+            event_sender,
+            thumbnail_filter: ThumbnailFilter::default(),
+            capture_exclusions: Arc::default(),
+            framerate: None,
+            permission: PermissionMonitor::new(ScreenshareFunctions::screen_capture_permitted),
+            cancel,
+        }
+    }
+
+    /// Sets the apps whose windows are kept out of the thumbnails.
+    ///
+    /// # Parameters
+    /// - `privacy`: The blocked apps and how their thumbnails are redacted
+    pub fn set_thumbnail_privacy(&mut self, privacy: ThumbnailPrivacy) {
+        log::info!("set_thumbnail_privacy: {privacy:?}");
+        self.thumbnail_filter = ThumbnailFilter::new(privacy);
+    }
+
+    /// Sets the applications excluded from the capture.
+    ///
+    /// # Parameters
+    /// - `exclusions`: Pids, or parts of process names matched case insensitively,
+    ///   the app's own processes are always excluded
+    ///
+    /// # Notes
+    /// The exclusions apply to the streams started or restarted after it, a
+    /// warm stream created with other exclusions is dropped.
+    pub fn set_capture_exclusions(&mut self, exclusions: Vec<String>) {
+        let mut current = self.capture_exclusions.lock().unwrap();
+        if *current == exclusions {
+            return;
+        }
+        log::info!("set_capture_exclusions: {exclusions:?}");
+        *current = exclusions;
+        drop(current);
+        self.warm_stream = None;
+    }
+
+    /// Returns how the thumbnail of a display is redacted, `None` if it
+    /// doesn't show a blocked app.
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    fn display_redaction(&self, display_id: u32) -> Option<ThumbnailRedaction> {
+        if self.thumbnail_filter.is_empty() {
+            return None;
         }
+        let apps = ScreenshareFunctions::get_display_apps(display_id);
+        self.thumbnail_filter.redaction_for(&apps)
     }
 
     /// Discovers and captures thumbnails of all available screen sources.
@@ -270,6 +494,12 @@ impl Capturer {
     /// # Behavior
     /// - Creates temporary capturers for each available display/window
     /// - Captures a single frame from each source at THUMBNAIL_WIDTH x THUMBNAIL_HEIGHT resolution
+    /// - Uses the active stream's latest frame for the display being shared, starting
+    ///   a second capturer on it can glitch the live stream on some platforms
+    /// - Redacts the thumbnails of displays with windows of blocked apps, and of
+    ///   the blocked apps' windows, before encoding them, see `set_thumbnail_privacy`
+    /// - Lists the windows with the display showing most of them, minimized
+    ///   windows and windows without a frame in time are left out
     /// - Converts frames to base64-encoded JPEG thumbnails for display in UI
     /// - Times out after MAX_SCREENSHOT_RETRY_ATTEMPTS if sources don't respond
     ///
@@ -289,17 +519,46 @@ impl Capturer {
             let displays = first_capturer.get_source_list();
             log::info!("get_available_content: displays: {}", displays.len());
 
+            /* A missing window list shouldn't keep the user from sharing a display. */
+            let windows = match DesktopCapturer::new(|_, _| {}, true) {
+                Some(window_capturer) => window_capturer.get_source_list(),
+                None => {
+                    log::error!("get_available_content: Failed to create window capturer");
+                    vec![]
+                }
+            };
+            log::info!("get_available_content: windows: {}", windows.len());
+
             let mut capturers = vec![];
             let result = Arc::new(Mutex::new(vec![]));
             let target_dims = Extent {
                 width: THUMBNAIL_WIDTH,
                 height: THUMBNAIL_HEIGHT,
             };
+            let shared_display = self.shared_display_id();
             for display in displays.iter() {
+                let redaction = self.display_redaction(display.id() as u32);
+                if Some(display.id() as u32) == shared_display {
+                    if let Some(content) =
+                        self.active_stream_thumbnail(display.title(), target_dims, redaction)
+                    {
+                        result.lock().unwrap().push(content);
+                        continue;
+                    }
+                    log::warn!(
+                        "get_available_content: no frame in active stream for display: {}",
+                        display.id()
+                    );
+                }
+
                 let callback = screenshot_capture_callback(
                     target_dims,
-                    display.id() as u32,
+                    Content {
+                        content_type: ContentType::Display,
+                        id: display.id() as u32,
+                    },
                     display.title(),
+                    redaction,
                     result.clone(),
                 );
                 let capturer = DesktopCapturer::new(callback, false);
@@ -315,6 +574,35 @@ impl Capturer {
                 capturers.push(capturer);
             }
 
+            let mut listed_windows = 0;
+            for window in windows.iter() {
+                let window_id = window.id() as u32;
+                /* Minimized windows have no frame to follow, they aren't offered. */
+                let Some(info) = ScreenshareFunctions::get_window_info(window_id) else {
+                    log::debug!("get_available_content: skipping window {window_id}");
+                    continue;
+                };
+                let callback = screenshot_capture_callback(
+                    target_dims,
+                    Content {
+                        content_type: ContentType::Window {
+                            display_id: info.display_id,
+                        },
+                        id: window_id,
+                    },
+                    window.title(),
+                    self.thumbnail_filter.redaction_for(&info.apps),
+                    result.clone(),
+                );
+                let Some(mut capturer) = DesktopCapturer::new(callback, true) else {
+                    log::error!("Failed to create DesktopCapturer for window: {window_id}");
+                    continue;
+                };
+                capturer.start_capture(window.clone());
+                capturers.push(capturer);
+                listed_windows += 1;
+            }
+
             let mut times = 0;
             loop {
                 for capturer in capturers.iter_mut() {
@@ -322,7 +610,7 @@ impl Capturer {
                 }
 
                 let res = result.lock().unwrap();
-                if res.len() == displays.len() {
+                if res.len() == displays.len() + listed_windows {
                     break;
                 }
 
@@ -335,12 +623,21 @@ impl Capturer {
                 }
             }
 
-            if times > MAX_SCREENSHOT_RETRY_ATTEMPTS {
+            /* A window that never delivered a frame is left out, a display can't be. */
+            let res = result.lock().unwrap();
+            let captured_displays = res
+                .iter()
+                .filter(|c| c.content.content_type == ContentType::Display)
+                .count();
+            if times > MAX_SCREENSHOT_RETRY_ATTEMPTS && captured_displays < displays.len() {
                 return Err(CapturerError::FailedToCaptureFrames);
             }
 
-            let res = result.lock().unwrap();
-            Ok((*res).clone())
+            let mut content = (*res).clone();
+            if self.thumbnail_filter.generic_titles() {
+                replace_titles(&mut content);
+            }
+            Ok(content)
         }
         /*
          * On linux desktop capture is using the system picker so we can't get
@@ -359,14 +656,20 @@ impl Capturer {
                 return Ok(vec![]);
             }
             let display = &sources[0];
-            Ok(vec![CaptureContent {
+            let mut content = vec![CaptureContent {
                 content: Content {
                     content_type: ContentType::Display,
                     id: display.id() as u32,
                 },
                 base64: "".to_string(),
                 title: display.title().clone(),
-            }])
+                redacted: false,
+                presets: vec![],
+            }];
+            if self.thumbnail_filter.generic_titles() {
+                replace_titles(&mut content);
+            }
+            Ok(content)
         }
     }
 
@@ -375,6 +678,7 @@ impl Capturer {
     /// # Parameters
     /// - `content`: The content source to capture (display or window with display_id)
     /// - `stream_resolution`: The resolution of the stream buffer
+    /// - `aspect_policy`: How the captured frames are scaled to the resolution
     ///
     /// # Returns
     /// - `Ok(())`: Successfully started the capture stream
@@ -383,8 +687,12 @@ impl Capturer {
     /// # Behavior
     /// - Stops any existing active stream
     /// - Selects the appropriate monitor based on the content's display_id
-    /// - Creates a new capture stream configured for the target resolution
+    /// - Detects the color space of that display so frames are converted correctly
+    /// - Creates a new capture stream configured for the target resolution, a
+    ///   window gets a stream of its own bounds
     /// - Starts the capture loop and frame processing pipeline
+    /// - Watches the display for sleep, lock and screensaver interruptions
+    /// - Watches a shared window's frame, see `shared_window_frame`
     ///
     /// # Notes
     /// Only one stream can be active at a time. Starting a new capture automatically
@@ -394,6 +702,7 @@ impl Capturer {
         &mut self,
         content: Content,
         stream_resolution: Extent,
+        aspect_policy: AspectPolicy,
     ) -> Result<(), CapturerError> {
         log::info!("start_capture: content {content:?}");
         if self.active_stream.is_some() {
@@ -402,14 +711,159 @@ impl Capturer {
             self.active_stream = None;
         }
 
-        let scale = 1.0;
-        let mut stream = Stream::new(stream_resolution, scale, self.tx.clone())?;
+        let display_id = content.display_id();
+        let color_space = ScreenshareFunctions::get_display_color_space(display_id);
+        log::info!("start_capture: display {display_id} color space {color_space:?}");
 
+        let window = matches!(content.content_type, ContentType::Window { .. });
+        /* The warm stream captures displays, it is kept for the next display share. */
+        let warm_stream = if window {
+            None
+        } else {
+            self.warm_stream.take()
+        };
+        let mut stream = match warm_stream {
+            Some(mut stream) => {
+                log::info!("start_capture: using the warmed up stream");
+                stream.configure(stream_resolution, aspect_policy, color_space);
+                stream
+            }
+            None => match Stream::new(
+                StreamOptions {
+                    resolution: stream_resolution,
+                    aspect_policy,
+                    color_space,
+                    window,
+                },
+                self.capture_exclusions.clone(),
+                self.tx.clone(),
+                self.cancel.clone(),
+            ) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let mut tags = capture_failure_tags(Some(content));
+                    tags.push(("error", format!("{e:?}")));
+                    sentry_utils::capture_failure("Capture start failed", &tags);
+                    return Err(e);
+                }
+            },
+        };
+
+        stream.set_framerate(self.framerate);
         stream.start_capture(content.id);
         self.active_stream = Some(stream);
+        self.active_content = Some(content);
+        self.permission.reset();
+        self.interruption_watcher = Some(InterruptionWatcher::new(
+            self.event_sender.clone(),
+            move || ScreenshareFunctions::get_share_interruption(display_id),
+        ));
+        self.window_hidden = false;
+        self.window_watcher = window.then(|| {
+            let window_id = content.id;
+            WindowWatcher::new(self.event_sender.clone(), move || {
+                ScreenshareFunctions::get_window_info(window_id).map(|info| info.frame)
+            })
+        });
+        Ok(())
+    }
+
+    /// Starts a stream publishing a generated test pattern instead of a capture.
+    ///
+    /// # Parameters
+    /// - `resolution`: The size of the pattern frames
+    /// - `fps`: Frames generated per second
+    ///
+    /// # Behavior
+    /// - Stops any existing active stream
+    /// - Pushes color bars with the elapsed time and a frame counter through the
+    ///   same conversion, scaling and buffer source as a captured display
+    ///
+    /// # Notes
+    /// Nothing is captured, so this works on machines without capture permissions.
+    /// There is no shared content, so the pattern has no interruption watcher.
+    pub fn start_test_pattern(&mut self, resolution: Extent, fps: u32) {
+        log::info!("start_test_pattern: resolution {resolution:?} fps {fps}");
+        if self.active_stream.is_some() {
+            log::warn!("start_test_pattern: active stream, stopping it");
+            self.active_stream.as_mut().unwrap().stop_capture();
+            self.active_stream = None;
+        }
+
+        let mut stream =
+            Stream::test_pattern(resolution, fps, self.tx.clone(), self.cancel.clone());
+        stream.start_capture(0);
+        self.active_stream = Some(stream);
+        self.active_content = None;
+        self.interruption_watcher = None;
+        self.window_watcher = None;
+        self.window_hidden = false;
+    }
+
+    /// Switches the active stream to another resolution without ending the share.
+    ///
+    /// # Parameters
+    /// - `stream_resolution`: The new resolution of the stream buffer
+    ///
+    /// # Returns
+    /// - `Ok(())`: The capture was restarted at the new resolution
+    /// - `Err(CapturerError::NoActiveStream)`: Nothing is being shared
+    ///
+    /// # Behavior
+    /// - Stops the capture thread, the desktop capturer and the buffer source are kept
+    /// - Clears the stream frames, `get_stream_extent` waits for a frame of the
+    ///   new resolution
+    /// - Starts capturing the same source again
+    pub fn change_resolution(&mut self, stream_resolution: Extent) -> Result<(), CapturerError> {
+        log::info!("change_resolution: {stream_resolution:?}");
+        let Some(stream) = self.active_stream.as_mut() else {
+            log::warn!("change_resolution: no active stream");
+            return Err(CapturerError::NoActiveStream);
+        };
+        stream.stop_capture();
+        stream.set_resolution(stream_resolution);
+        stream.start_capture(stream.source_id());
         Ok(())
     }
 
+    /// Prepares a stream ahead of the next `start_capture`.
+    ///
+    /// # Parameters
+    /// - `frame_extent`: The expected size of the captured frames in pixels
+    ///
+    /// # Behavior
+    /// - Creates the desktop capturer and looks up the excluded applications,
+    ///   the slow part of starting a stream
+    /// - Allocates the capture buffer for `frame_extent`
+    /// - Does nothing while capturing or when a stream is already warm
+    ///
+    /// # Notes
+    /// Called while the user is in the content picker, so the share produces
+    /// its first frames without the stream's initialization delay.
+    pub fn warm_up(&mut self, frame_extent: Extent) {
+        if self.active_stream.is_some() || self.warm_stream.is_some() {
+            return;
+        }
+        log::info!("warm_up: frame extent {frame_extent:?}");
+        match Stream::new(
+            StreamOptions {
+                resolution: frame_extent,
+                aspect_policy: AspectPolicy::Fit,
+                color_space: ColorSpace::default(),
+                window: false,
+            },
+            self.capture_exclusions.clone(),
+            self.tx.clone(),
+            self.cancel.clone(),
+        ) {
+            Ok(mut stream) => {
+                stream.preallocate(frame_extent);
+                self.warm_stream = Some(stream);
+            }
+            Err(e) => log::warn!("warm_up: Failed to create stream: {e:?}"),
+        }
+    }
+
     /// Stops the currently active capture stream.
     ///
     /// # Behavior
@@ -430,20 +884,75 @@ impl Capturer {
         }
         self.active_stream.as_mut().unwrap().stop_capture();
         self.active_stream = None;
+        self.active_content = None;
+        self.interruption_watcher = None;
+        self.window_watcher = None;
+        self.window_hidden = false;
+    }
+
+    /// Returns the id of the display being shared, `None` when sharing a window or idle.
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    fn shared_display_id(&self) -> Option<u32> {
+        if self.active_stream.is_none() {
+            return None;
+        }
+        match self.active_content {
+            Some(Content {
+                content_type: ContentType::Display,
+                id,
+            }) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Creates the thumbnail of the shared display from the active stream's latest frame.
+    ///
+    /// # Parameters
+    /// - `title`: The title of the display
+    /// - `target`: The size the thumbnail fits in
+    /// - `redaction`: How the thumbnail is redacted, `None` to keep it as is
+    ///
+    /// # Returns
+    /// - `Some(CaptureContent)`: The shared display with its thumbnail
+    /// - `None`: There is no active stream or it hasn't captured a frame yet
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    fn active_stream_thumbnail(
+        &self,
+        title: String,
+        target: Extent,
+        redaction: Option<ThumbnailRedaction>,
+    ) -> Option<CaptureContent> {
+        let id = self.shared_display_id()?;
+        let (raw_image, width, height) = self.active_stream.as_ref()?.latest_frame_rgb(target)?;
+        let raw_image = match redaction {
+            Some(redaction) => redact_rgb(raw_image, width, height, redaction),
+            None => raw_image,
+        };
+        Some(CaptureContent {
+            content: Content {
+                content_type: ContentType::Display,
+                id,
+            },
+            base64: rgb_to_thumbnail(raw_image, width, height),
+            title,
+            redacted: redaction.is_some(),
+            presets: vec![],
+        })
     }
 
     /// Restarts the current stream to recover from permanent errors.
     ///
     /// # Behavior
     /// - Stops the current stream if running
-    /// - Checks failure count and exits process if too many consecutive failures
+    /// - Checks failure count and gives up if too many consecutive failures
     /// - Creates a new stream instance sharing the same buffers and configuration
     /// - Restarts capture on the same source ID
     /// - Preserves failure tracking across restart
     ///
     /// # Error Handling
-    /// If the failure count exceeds MAX_STREAM_FAILURES_BEFORE_EXIT, the process
-    /// will exit with STREAM_FAILURE_EXIT_CODE to trigger application restart.
+    /// If the failure count exceeds MAX_STREAM_FAILURES, or the stream can't be
+    /// recreated, the stream is dropped and `UserEvent::FatalCaptureError` is sent,
+    /// the event loop tears down the session and notifies the tauri app.
     /// This prevents infinite restart loops when the capture system is fundamentally broken.
     ///
     /// # Notes
@@ -455,23 +964,30 @@ impl Capturer {
             Some(mut stream) => {
                 stream.stop_capture();
 
-                // If something fails here we are killing the process in
-                // order to trigger the health check in the tauri app.
-                // The health check will instruct the user to restart.
-                // We should do this via a message in the future.
+                // If something fails here we give up on the stream, the
+                // event loop ends the session and the tauri app decides
+                // how to recover.
                 let failures_count = stream.get_failures_count();
-                if failures_count > MAX_STREAM_FAILURES_BEFORE_EXIT {
-                    log::error!("restart_stream: Too many failures, killing the process");
+                sentry_utils::add_breadcrumb(
+                    "stream",
+                    format!("restarting stream, failures: {failures_count}"),
+                );
+                if failures_count > MAX_STREAM_FAILURES {
+                    log::error!("restart_stream: Too many failures, stopping the stream");
+                    self.report_stream_failure("TooManyFailures");
                     sentry_utils::upload_logs_event("Stream failed".to_string());
-                    std::process::exit(STREAM_FAILURE_EXIT_CODE);
+                    self.report_fatal_error("Too many capture failures");
+                    return;
                 }
 
                 let mut new_stream = match stream.copy() {
                     Ok(new_stream) => new_stream,
                     Err(_) => {
                         log::error!("restart_stream: Failed to copy stream");
+                        self.report_stream_failure("StreamCopyFailed");
                         sentry_utils::upload_logs_event("Stream copy failed".to_string());
-                        std::process::exit(STREAM_FAILURE_EXIT_CODE);
+                        self.report_fatal_error("Failed to recreate the capture stream");
+                        return;
                     }
                 };
                 new_stream.start_capture(new_stream.source_id());
@@ -483,6 +999,47 @@ impl Capturer {
         };
     }
 
+    /// Sends a structured Sentry event for a stream that couldn't be restarted.
+    fn report_stream_failure(&self, error: &str) {
+        let mut tags = capture_failure_tags(self.active_content);
+        tags.push(("error", error.to_string()));
+        sentry_utils::capture_failure("Stream failed", &tags);
+    }
+
+    /// Notifies the event loop that the capture can't be recovered.
+    fn report_fatal_error(&self, reason: &str) {
+        sentry_utils::add_breadcrumb("stream", format!("fatal capture error: {reason}"));
+        let res = self
+            .event_sender
+            .send(UserEvent::FatalCaptureError(reason.to_string()));
+        if let Err(e) = res {
+            log::error!("report_fatal_error: error sending fatal capture error: {e:?}");
+        }
+    }
+
+    /// Probes the screen recording permission of the active capture.
+    ///
+    /// # Returns
+    /// - `true`: The permission was revoked, restarting the stream can't help.
+    ///   `UserEvent::PermissionRevoked` is sent once, the event loop ends the session
+    /// - `false`: The permission is granted, or nothing is captured
+    pub fn permission_revoked(&mut self) -> bool {
+        /* The test pattern doesn't capture anything. */
+        if self.active_content.is_none() {
+            return false;
+        }
+        if let Some(permission) = self.permission.check() {
+            sentry_utils::add_breadcrumb("stream", format!("{permission:?} permission revoked"));
+            let res = self
+                .event_sender
+                .send(UserEvent::PermissionRevoked(permission));
+            if let Err(e) = res {
+                log::error!("permission_revoked: error sending permission revoked: {e:?}");
+            }
+        }
+        self.permission.state() == PermissionState::Revoked
+    }
+
     /// Checks if there is currently an active capture stream.
     ///
     /// # Returns
@@ -492,22 +1049,58 @@ impl Capturer {
         self.active_stream.is_some()
     }
 
-    /// Signals the runtime stream monitoring thread to terminate.
+    /// Checks if the active stream stopped delivering frames without reporting an error.
     ///
-    /// # Behavior
-    /// Sends a `Stop` message to the polling thread that monitors for stream
-    /// failures and runtime events. This is used during application shutdown
-    /// to ensure all capture-related threads terminate cleanly.
+    /// # Returns
+    /// - `true`: The stream is publishing but no frame arrived for `FRAME_STALL_TIMEOUT_SECS`
+    /// - `false`: Frames are flowing, or there is no stream being published
+    pub fn is_stream_stalled(&self) -> bool {
+        match &self.active_stream {
+            Some(stream) => {
+                stream.is_stalled(std::time::Duration::from_secs(FRAME_STALL_TIMEOUT_SECS))
+            }
+            None => false,
+        }
+    }
+
+    /// Detects a shared window that stopped delivering frames.
     ///
-    /// # Notes
-    /// This method should be called before dropping the Capturer instance to
-    /// prevent the polling thread from running indefinitely. The method is
-    /// non-blocking and returns immediately after sending the stop signal.
-    pub fn stop_runtime_stream_handler(&self) {
-        let res = self.tx.send(StreamRuntimeMessage::Stop);
-        if let Err(e) = res {
-            log::error!("stop_runtime_stream_handler: error sending Stop message: {e}");
+    /// Some platforms deliver no frames of a minimized or fully covered
+    /// window while the capture stays healthy. After `WINDOW_HIDDEN_TIMEOUT_SECS`
+    /// without frames the window is considered hidden, the application is sent
+    /// `UserEvent::ShareInterrupted` with `ShareInterruption::WindowNotVisible`
+    /// and `UserEvent::ShareResumed` once the frames are back.
+    ///
+    /// # Returns
+    /// - `true`: The window is hidden, a placeholder frame was published in its place
+    /// - `false`: Frames are flowing, or no window is being published
+    fn check_window_visibility(&mut self) -> bool {
+        let Some(stream) = self
+            .active_stream
+            .as_ref()
+            .filter(|stream| stream.is_window())
+        else {
+            return false;
+        };
+        let hidden = stream.is_stalled(std::time::Duration::from_secs(WINDOW_HIDDEN_TIMEOUT_SECS));
+        if hidden != self.window_hidden {
+            log::info!("check_window_visibility: window hidden: {hidden}");
+            sentry_utils::add_breadcrumb("stream", format!("window hidden: {hidden}"));
+            let event = if hidden {
+                UserEvent::ShareInterrupted(ShareInterruption::WindowNotVisible)
+            } else {
+                UserEvent::ShareResumed
+            };
+            if let Err(e) = self.event_sender.send(event) {
+                log::error!("check_window_visibility: error sending window visibility: {e:?}");
+            }
+            self.window_hidden = hidden;
         }
+        /* Republished every check, viewers joining later get it too. */
+        if hidden {
+            stream.publish_placeholder();
+        }
+        hidden
     }
 
     /// Gets the size of a specific monitor by ID.
@@ -527,65 +1120,131 @@ impl Capturer {
         ScreenshareFunctions::get_monitor_size(monitors, input_id)
     }
 
-    pub fn get_selected_monitor(&self, monitors: &[MonitorHandle], input_id: u32) -> MonitorHandle {
+    /// Returns what finding the monitor of a display needs, see `MonitorLookup`.
+    pub fn monitor_lookup(&self) -> MonitorLookup {
         #[cfg(any(target_os = "windows", target_os = "macos"))]
         {
-            ScreenshareFunctions::get_selected_monitor(monitors, input_id)
+            MonitorLookup {}
         }
         #[cfg(target_os = "linux")]
         {
-            if self.active_stream.is_none() {
-                log::warn!("get_selected_monitor: no active stream");
-                return monitors[0].clone();
-            }
-            let capturer = self.active_stream.as_ref().unwrap().capturer();
-            let capturer = capturer.lock().unwrap();
-            for _ in 0..150 {
-                let rect = capturer.get_source_rect();
-                if rect.top != 0 || rect.left != 0 || rect.width != 0 || rect.height != 0 {
-                    for monitor in monitors {
-                        let position = monitor.position();
-                        let size = monitor.size();
-                        if position.x == rect.left
-                            && position.y == rect.top
-                            && size.width == (rect.width as u32)
-                            && size.height == (rect.height as u32)
-                        {
-                            return monitor.clone();
-                        }
-                    }
-                }
-                std::thread::sleep(std::time::Duration::from_millis(POLL_STREAM_DATA_SLEEP_MS));
-            }
-            log::error!("get_selected_monitor: capturer hasn't started");
-            return monitors[0].clone();
+            let stream = self.active_stream.as_ref().and_then(|stream| {
+                stream
+                    .capturer()
+                    .map(|capturer| (stream.first_frame_waiter(), capturer))
+            });
+            MonitorLookup { stream }
         }
     }
 
-    pub fn get_stream_extent(&self) -> Extent {
-        if self.active_stream.is_none() {
-            log::error!("get_stream_extent: no active stream");
-            return Extent {
-                width: 0.,
-                height: 0.,
-            };
+    /// Returns the color space of the active stream, `None` without one.
+    pub fn current_color_space(&self) -> Option<ColorSpace> {
+        self.active_stream
+            .as_ref()
+            .map(|stream| stream.color_space())
+    }
+
+    /// Returns the content of the active capture, `None` when idle or sharing a test pattern.
+    pub fn active_content(&self) -> Option<Content> {
+        self.active_content
+    }
+
+    /// Returns the frame of the shared window in global physical pixels.
+    ///
+    /// # Returns
+    /// - `Some(Frame)`: A window is shared and its frame could be queried
+    /// - `None`: A display is shared, the capturer is idle or the window is gone
+    ///
+    /// # Notes
+    /// The frame is only the initial one, `UserEvent::SharedWindowMoved` is
+    /// sent when the window moves or is resized.
+    pub fn shared_window_frame(&self) -> Option<Frame> {
+        match self.active_content? {
+            Content {
+                content_type: ContentType::Window { .. },
+                id,
+            } => ScreenshareFunctions::get_window_info(id).map(|info| info.frame),
+            _ => None,
         }
-        let stream = self.active_stream.as_ref().unwrap();
-        for i in 0..150 {
-            let extent = stream.get_stream_extent();
-            if extent.width > 0. && extent.height > 0. {
-                log::info!("get_stream_extent: got extent in try {i}");
-                return extent;
-            }
-            std::thread::sleep(std::time::Duration::from_millis(POLL_STREAM_DATA_SLEEP_MS));
+    }
+
+    /// Encodes the active stream's latest frame for the sharer's live preview.
+    ///
+    /// # Parameters
+    /// - `target`: The size the preview fits in
+    ///
+    /// # Returns
+    /// - `Some(jpeg)`: The JPEG encoded frame
+    /// - `None`: There is no active stream or it hasn't captured a frame yet
+    pub fn preview_frame(&self, target: Extent) -> Option<Vec<u8>> {
+        let (raw_image, width, height) = self.active_stream.as_ref()?.latest_frame_rgb(target)?;
+        let jpeg = raw_image_to_jpeg(raw_image, width, height);
+        (!jpeg.is_empty()).then_some(jpeg)
+    }
+
+    /// Returns the size of the published video without waiting for the first frame.
+    ///
+    /// # Returns
+    /// - `Some(Extent)`: The active stream's resolution
+    /// - `None`: There is no active stream or it hasn't delivered a frame yet
+    pub fn current_stream_extent(&self) -> Option<Extent> {
+        let extent = self.active_stream.as_ref()?.get_stream_extent();
+        (extent.width > 0. && extent.height > 0.).then_some(extent)
+    }
+
+    /// Returns the part of the captured frames the active stream shows.
+    ///
+    /// # Returns
+    /// The whole frames when there is no active stream, or it hasn't
+    /// delivered a frame yet, see `get_stream_extent`.
+    pub fn get_stream_crop(&self) -> StreamCrop {
+        self.active_stream
+            .as_ref()
+            .map(|stream| stream.get_stream_crop())
+            .unwrap_or_default()
+    }
+
+    /// Returns a handle waiting for the active stream's first frame, `None`
+    /// without an active stream.
+    pub fn first_frame_waiter(&self) -> Option<FirstFrameWaiter> {
+        self.active_stream
+            .as_ref()
+            .map(|stream| stream.first_frame_waiter())
+    }
+
+    /// Sets the target frames per second of the capture.
+    ///
+    /// # Parameters
+    /// - `framerate`: Frames per second, `None` for the default
+    ///
+    /// # Notes
+    /// Applies to the active stream right away and to the streams started after it.
+    pub fn set_framerate(&mut self, framerate: Option<u32>) {
+        log::info!("set_framerate: {framerate:?}");
+        self.framerate = framerate;
+        if let Some(stream) = &self.active_stream {
+            stream.set_framerate(framerate);
         }
-        Extent {
-            width: 0.,
-            height: 0.,
+    }
+
+    /// Stops or resumes publishing the active stream's frames, the capture keeps running.
+    ///
+    /// # Parameters
+    /// - `paused`: `true` while no participant watches the share
+    pub fn set_publishing_paused(&self, paused: bool) {
+        if let Some(stream) = &self.active_stream {
+            stream.set_publishing_paused(paused);
         }
     }
 
-    pub fn set_buffer_source(&mut self, buffer_source: NativeVideoSource) {
+    /// Returns whether the active stream's frames aren't published, `false` without a stream.
+    pub fn is_publishing_paused(&self) -> bool {
+        self.active_stream
+            .as_ref()
+            .is_some_and(|stream| stream.is_publishing_paused())
+    }
+
+    pub fn set_buffer_source(&mut self, buffer_source: SharedVideoSink) {
         if self.active_stream.is_none() {
             log::error!("set_buffer_source: no active stream");
             return;
@@ -597,16 +1256,118 @@ impl Capturer {
     }
 }
 
+/// Waits for the first frame of a stream and returns the size of its frames.
+///
+/// # Parameters
+/// - `waiter`: The stream's waiter, see `Capturer::first_frame_waiter`
+///
+/// # Returns
+/// The size of the frames, zero when there is no stream or no frame arrived
+/// within `FIRST_FRAME_TIMEOUT_SECS`.
+///
+/// # Notes
+/// The capturer's lock must not be held while waiting.
+pub fn wait_stream_extent(waiter: Option<FirstFrameWaiter>) -> Extent {
+    let Some(waiter) = waiter else {
+        log::error!("wait_stream_extent: no active stream");
+        return Extent {
+            width: 0.,
+            height: 0.,
+        };
+    };
+    let started = std::time::Instant::now();
+    if !waiter.wait(std::time::Duration::from_secs(FIRST_FRAME_TIMEOUT_SECS)) {
+        log::error!("wait_stream_extent: no frame after {FIRST_FRAME_TIMEOUT_SECS}s");
+        return Extent {
+            width: 0.,
+            height: 0.,
+        };
+    }
+    log::info!(
+        "wait_stream_extent: got extent after {:?}",
+        started.elapsed()
+    );
+    waiter.stream_extent()
+}
+
+/// What finding the monitor of a display needs from the capturer.
+///
+/// On linux the monitor is found from the source rect of the capture, known
+/// once its first frame was captured. The lookup is taken out of the
+/// capturer, so the frame is waited for without holding its lock.
+pub struct MonitorLookup {
+    #[cfg(target_os = "linux")]
+    stream: Option<(FirstFrameWaiter, Arc<Mutex<DesktopCapturer>>)>,
+}
+
+impl MonitorLookup {
+    /// Returns the monitor of the display with the content id `input_id`,
+    /// the first monitor when it isn't found.
+    pub fn selected_monitor(&self, monitors: &[MonitorHandle], input_id: u32) -> MonitorHandle {
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        {
+            ScreenshareFunctions::get_selected_monitor(monitors, input_id)
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let _ = input_id;
+            let Some((waiter, capturer)) = &self.stream else {
+                log::warn!("selected_monitor: no captured stream");
+                return monitors[0].clone();
+            };
+            /* The source rect is known once the first frame was captured. */
+            let timeout = std::time::Duration::from_secs(FIRST_FRAME_TIMEOUT_SECS);
+            if !waiter.wait(timeout) {
+                log::error!("selected_monitor: capturer hasn't started");
+                return monitors[0].clone();
+            }
+            let rect = capturer.lock().unwrap().get_source_rect();
+            for monitor in monitors {
+                let position = monitor.position();
+                let size = monitor.size();
+                if position.x == rect.left
+                    && position.y == rect.top
+                    && size.width == (rect.width as u32)
+                    && size.height == (rect.height as u32)
+                {
+                    return monitor.clone();
+                }
+            }
+            log::error!(
+                "selected_monitor: no monitor at {}x{} {}x{}",
+                rect.left,
+                rect.top,
+                rect.width,
+                rect.height
+            );
+            monitors[0].clone()
+        }
+    }
+}
+
 /*
  * This function is spawned in a separate thread and
  * is used for checking whether the stream failed, if it
  * failed it restarts it.
  *
- * This thread is owned by the Application struct.
+ * It also acts as a watchdog for captures that stop
+ * delivering frames without reporting an error, those
+ * are restarted too and the user is notified. A shared
+ * window without frames is reported as hidden instead,
+ * see `Capturer::check_window_visibility`.
+ *
+ * A capture whose screen recording permission was revoked
+ * isn't restarted, see `Capturer::permission_revoked`.
+ *
+ * This thread is owned by the CaptureService, it exits
+ * within a watchdog interval of the application's cancellation.
  */
 pub fn poll_stream(capturer: Arc<Mutex<Capturer>> /* mut socket: CursorSocket */) {
-    let rx = { capturer.lock().unwrap().rx.clone() };
-    loop {
+    let (rx, cancel) = {
+        let capturer = capturer.lock().unwrap();
+        (capturer.rx.clone(), capturer.cancel.clone())
+    };
+    while !cancel.is_cancelled() {
         log::debug!("poll_stream: waiting for message");
         let rx_lock = rx.lock();
         if rx_lock.is_err() {
@@ -614,25 +1375,87 @@ pub fn poll_stream(capturer: Arc<Mutex<Capturer>> /* mut socket: CursorSocket */
             break;
         }
         let rx_lock = rx_lock.unwrap();
-        match rx_lock.recv_timeout(std::time::Duration::from_secs(POLL_STREAM_TIMEOUT_SECS)) {
+        match rx_lock.recv_timeout(std::time::Duration::from_secs(
+            STREAM_WATCHDOG_INTERVAL_SECS,
+        )) {
             Ok(StreamRuntimeMessage::Failed) => {
                 log::info!("poll_stream: stream failed");
                 let mut capturer = capturer.lock().unwrap();
+                if capturer.permission_revoked() {
+                    continue;
+                }
                 capturer.restart_stream();
             }
             Ok(StreamRuntimeMessage::UserStoppedCapture) => {
                 log::info!("poll_stream: user stopped capture");
-                let capturer = capturer.lock().unwrap();
-                let _ = capturer
-                    .event_loop_proxy
-                    .send_event(UserEvent::StopScreenShare);
+                let mut capturer = capturer.lock().unwrap();
+                /* Revoking the permission stops the capture the same way. */
+                if capturer.permission_revoked() {
+                    continue;
+                }
+                sentry_utils::add_breadcrumb("stream", "user stopped capture");
+                let _ = capturer.event_sender.send_stop_screen_share();
             }
-            Ok(StreamRuntimeMessage::Stop) => {
-                log::info!("poll_stream: stop message");
-                break;
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let mut capturer = capturer.lock().unwrap();
+                /* The frames can stop without an error when the permission is revoked. */
+                if capturer.permission_revoked() {
+                    continue;
+                }
+                /* Restarting the capture doesn't bring back the frames of a hidden window. */
+                if capturer.check_window_visibility() || !capturer.is_stream_stalled() {
+                    continue;
+                }
+
+                log::warn!(
+                    "poll_stream: no frames for {FRAME_STALL_TIMEOUT_SECS}s, restarting stream"
+                );
+                sentry_utils::add_breadcrumb("stream", "stream stalled");
+                if let Err(e) = capturer.event_sender.send(UserEvent::CaptureStalled) {
+                    log::error!("poll_stream: error sending capture stalled: {e:?}");
+                }
+                if let Some(stream) = capturer.active_stream.as_ref() {
+                    stream.record_failure();
+                }
+                capturer.restart_stream();
             }
             Err(_) => {}
             _ => {}
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use crate::utils::cancellation::SHUTDOWN_TIMEOUT;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_cancellation_stops_poll_stream() {
+        let cancel = CancellationToken::new();
+        let capturer = Arc::new(Mutex::new(Capturer::new(
+            EventCollector::new(),
+            cancel.clone(),
+        )));
+        capturer
+            .lock()
+            .unwrap()
+            .start_test_pattern(Extent::new(64., 36.), 30);
+        let handle = {
+            let capturer = capturer.clone();
+            std::thread::spawn(move || poll_stream(capturer))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+
+        let cancelled_at = Instant::now();
+        cancel.cancel();
+        while !handle.is_finished() {
+            assert!(cancelled_at.elapsed() < SHUTDOWN_TIMEOUT);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        handle.join().unwrap();
+    }
+}
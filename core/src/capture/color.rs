@@ -0,0 +1,473 @@
+//! Color-space aware ARGB to NV12 conversion for the stream pipeline.
+//!
+//! The desktop capturer hands us 8-bit BGRA frames encoded in the color space
+//! of the display they were captured from. Wide-gamut (Display P3) displays
+//! produce frames that look washed out when they are fed straight into the
+//! YUV conversion, because the receivers decode them with sRGB primaries.
+//!
+//! sRGB frames are converted by libyuv, as they always were, with its BT.601
+//! matrix. Frames of the other displays are converted by a scalar path to
+//! limited-range BT.709:
+//! - Wide-gamut frames have their gamut mapped to BT.709 primaries.
+//! - HDR frames are PQ or HLG encoded with BT.2020 primaries. They are
+//!   decoded to linear light, mapped to BT.709 primaries and tone mapped to
+//!   SDR with the BT.2390 EETF, so the highlights roll off instead of clipping.
+//!
+//! The matrix of each stream is announced to the viewers with the share
+//! configuration, see `ColorSpace::matrix`.
+
+use livekit::webrtc::prelude::NV12Buffer;
+use livekit::webrtc::{native::yuv_helper, prelude::VideoBuffer};
+use serde::{Deserialize, Serialize};
+
+/// Color space of the display a capture source lives on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Standard dynamic range with sRGB/BT.709 primaries.
+    #[default]
+    Srgb,
+    /// Standard dynamic range with wide-gamut (Display P3) primaries.
+    WideGamut,
+    /// High dynamic range output with BT.2020 primaries.
+    Hdr(HdrTransfer),
+}
+
+/// Transfer function of an HDR display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrTransfer {
+    /// SMPTE ST 2084, absolute luminance up to 10000 nits
+    Pq,
+    /// Hybrid log-gamma, scene referred
+    Hlg,
+}
+
+/// The YUV matrix a stream's frames are converted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMatrix {
+    Bt601,
+    Bt709,
+}
+
+impl ColorSpace {
+    /// Returns the limited-range matrix `ColorConverter` uses for frames of this color space.
+    pub fn matrix(self) -> ColorMatrix {
+        match self {
+            ColorSpace::Srgb => ColorMatrix::Bt601,
+            ColorSpace::WideGamut | ColorSpace::Hdr(_) => ColorMatrix::Bt709,
+        }
+    }
+}
+
+// Size of the lookup tables indexed by quantized linear light.
+const ENCODE_LUT_SIZE: usize = 4096;
+
+// Luminance of SDR white in HDR content (BT.2408), it becomes the stream's white.
+const SDR_WHITE_NITS: f32 = 203.0;
+// Peak luminance HDR content is assumed to be mastered for, it is tone mapped to SDR white.
+const HDR_PEAK_NITS: f32 = 1000.0;
+// System gamma of HLG's OOTF for a display of `HDR_PEAK_NITS`.
+const HLG_SYSTEM_GAMMA: f32 = 1.2;
+
+// SMPTE ST 2084 constants.
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+// BT.2100 HLG constants.
+const HLG_A: f32 = 0.178_832_77;
+const HLG_B: f32 = 0.284_668_92;
+const HLG_C: f32 = 0.559_910_7;
+
+// Display P3 -> BT.709 primaries (both D65) in linear light.
+const P3_TO_BT709: [[f32; 3]; 3] = [
+    [1.224_940_2, -0.224_940_4, 0.0],
+    [-0.042_056_955, 1.042_057, 0.0],
+    [-0.019_637_555, -0.078_636_04, 1.098_273_6],
+];
+
+// BT.2020 -> BT.709 primaries (both D65) in linear light.
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.660_491, -0.587_641_1, -0.072_849_86],
+    [-0.124_550_47, 1.132_899_9, -0.008_349_42],
+    [-0.018_150_76, -0.100_578_9, 1.118_729_6],
+];
+
+// Luminance of linear BT.2020 RGB.
+const BT2020_LUMA: [f32; 3] = [0.2627, 0.6780, 0.0593];
+
+/// Converts the sRGB transfer function value `v` (0..1) to linear light.
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts linear light `v` (0..1) to the sRGB transfer function.
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts the PQ value `v` (0..1) to luminance in nits.
+fn pq_to_nits(v: f32) -> f32 {
+    let p = v.powf(1.0 / PQ_M2);
+    let num = (p - PQ_C1).max(0.0);
+    10000.0 * (num / (PQ_C2 - PQ_C3 * p)).powf(1.0 / PQ_M1)
+}
+
+/// Converts luminance in nits to the PQ value (0..1).
+fn nits_to_pq(nits: f32) -> f32 {
+    let y = (nits / 10000.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+/// Converts the HLG value `v` (0..1) to scene linear light (0..1).
+fn hlg_to_scene_linear(v: f32) -> f32 {
+    if v <= 0.5 {
+        v * v / 3.0
+    } else {
+        (((v - HLG_C) / HLG_A).exp() + HLG_B) / 12.0
+    }
+}
+
+/// Maps luminance in nits to SDR with the BT.2390 EETF.
+///
+/// Luminance below the knee is kept, above it the curve rolls off so that
+/// `HDR_PEAK_NITS` lands on `SDR_WHITE_NITS`. The curve works on PQ values,
+/// the roll-off follows the perceived brightness.
+fn tone_map_nits(nits: f32) -> f32 {
+    let source_peak = nits_to_pq(HDR_PEAK_NITS);
+    let e1 = nits_to_pq(nits.min(HDR_PEAK_NITS)) / source_peak;
+    let max_lum = nits_to_pq(SDR_WHITE_NITS) / source_peak;
+    let knee = 1.5 * max_lum - 0.5;
+    let e2 = if e1 < knee {
+        e1
+    } else {
+        /* Hermite spline from the knee to `max_lum`. */
+        let t = (e1 - knee) / (1.0 - knee);
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * knee
+            + (t3 - 2.0 * t2 + t) * (1.0 - knee)
+            + (-2.0 * t3 + 3.0 * t2) * max_lum
+    };
+    pq_to_nits(e2 * source_peak)
+}
+
+/// Index of `v` (0..`max`) in a table of `ENCODE_LUT_SIZE` entries.
+#[inline(always)]
+fn lut_index(v: f32, max: f32) -> usize {
+    ((v / max).clamp(0.0, 1.0) * (ENCODE_LUT_SIZE - 1) as f32) as usize
+}
+
+/* The limited-range BT.709 coefficients, scaled by 256. */
+
+#[inline(always)]
+fn rgb_to_y(r: i32, g: i32, b: i32) -> u8 {
+    (((47 * r + 157 * g + 16 * b + 128) >> 8) + 16) as u8
+}
+
+#[inline(always)]
+fn rgb_to_u(r: i32, g: i32, b: i32) -> u8 {
+    (((-26 * r - 86 * g + 112 * b + 128) >> 8) + 128) as u8
+}
+
+#[inline(always)]
+fn rgb_to_v(r: i32, g: i32, b: i32) -> u8 {
+    (((112 * r - 102 * g - 10 * b + 128) >> 8) + 128) as u8
+}
+
+/// Converts BGRA frames to limited-range NV12 for a given source color space.
+///
+/// The transfer and tone mapping tables are built once per stream, so the
+/// per-frame cost of a wide-gamut or HDR frame is a few table lookups per
+/// pixel on top of the matrices.
+pub struct ColorConverter {
+    /// Color space of the source frames.
+    color_space: ColorSpace,
+    /// Source primaries -> BT.709 primaries in linear light.
+    primaries: [[f32; 3]; 3],
+    /// Transfer decoding table, linear light with SDR white at 1.0, HLG's
+    /// is scene light before the OOTF.
+    linear_lut: [f32; 256],
+    /// HLG's OOTF gain indexed by quantized scene luminance, empty otherwise.
+    ootf_lut: Vec<f32>,
+    /// Gain of the tone curve indexed by the quantized brightest channel, up
+    /// to `HDR_PEAK_NITS`, empty for SDR frames.
+    tone_lut: Vec<f32>,
+    /// Gamma encoding table indexed by quantized linear light.
+    encode_lut: Vec<u8>,
+}
+
+impl ColorConverter {
+    /// Creates a converter for frames captured in `color_space`.
+    pub fn new(color_space: ColorSpace) -> Self {
+        let decode: fn(f32) -> f32 = match color_space {
+            ColorSpace::Srgb | ColorSpace::WideGamut => srgb_to_linear,
+            ColorSpace::Hdr(HdrTransfer::Pq) => |v| pq_to_nits(v) / SDR_WHITE_NITS,
+            ColorSpace::Hdr(HdrTransfer::Hlg) => hlg_to_scene_linear,
+        };
+        let mut linear_lut = [0.0; 256];
+        for (i, v) in linear_lut.iter_mut().enumerate() {
+            *v = decode(i as f32 / 255.0);
+        }
+
+        let table = |f: &dyn Fn(f32) -> f32| -> Vec<f32> {
+            (0..ENCODE_LUT_SIZE)
+                .map(|i| f(i as f32 / (ENCODE_LUT_SIZE - 1) as f32))
+                .collect()
+        };
+        let ootf_lut = match color_space {
+            ColorSpace::Hdr(HdrTransfer::Hlg) => {
+                table(&|luma| HDR_PEAK_NITS * luma.powf(HLG_SYSTEM_GAMMA - 1.0) / SDR_WHITE_NITS)
+            }
+            _ => vec![],
+        };
+        let tone_lut = match color_space {
+            ColorSpace::Hdr(_) => table(&|v| {
+                let nits = v * HDR_PEAK_NITS;
+                if nits > 0.0 {
+                    tone_map_nits(nits) / nits
+                } else {
+                    1.0
+                }
+            }),
+            _ => vec![],
+        };
+        let encode_lut = (0..ENCODE_LUT_SIZE)
+            .map(|i| {
+                let linear = i as f32 / (ENCODE_LUT_SIZE - 1) as f32;
+                (linear_to_srgb(linear) * 255.0).round() as u8
+            })
+            .collect();
+
+        ColorConverter {
+            color_space,
+            primaries: match color_space {
+                ColorSpace::Hdr(_) => BT2020_TO_BT709,
+                _ => P3_TO_BT709,
+            },
+            linear_lut,
+            ootf_lut,
+            tone_lut,
+            encode_lut,
+        }
+    }
+
+    /// Maps a single BGRA pixel to gamma encoded BT.709 RGB.
+    #[inline(always)]
+    fn map_pixel(&self, pixel: &[u8]) -> (i32, i32, i32) {
+        let (b, g, r) = (pixel[0], pixel[1], pixel[2]);
+        let mut lin = [
+            self.linear_lut[r as usize],
+            self.linear_lut[g as usize],
+            self.linear_lut[b as usize],
+        ];
+        if !self.ootf_lut.is_empty() {
+            let luma: f32 = lin.iter().zip(BT2020_LUMA).map(|(v, k)| v * k).sum();
+            let gain = self.ootf_lut[lut_index(luma, 1.0)];
+            lin = lin.map(|v| v * gain);
+        }
+
+        let mut rgb = [0f32; 3];
+        for (channel, row) in rgb.iter_mut().zip(self.primaries.iter()) {
+            *channel = (row[0] * lin[0] + row[1] * lin[1] + row[2] * lin[2]).max(0.0);
+        }
+        if !self.tone_lut.is_empty() {
+            /* The brightest channel picks the gain, the hue is kept. */
+            let brightest = rgb[0].max(rgb[1]).max(rgb[2]);
+            let gain = self.tone_lut[lut_index(brightest, HDR_PEAK_NITS / SDR_WHITE_NITS)];
+            rgb = rgb.map(|v| v * gain);
+        }
+        let encode = |v: f32| self.encode_lut[lut_index(v, 1.0)] as i32;
+        (encode(rgb[0]), encode(rgb[1]), encode(rgb[2]))
+    }
+
+    /// Converts a BGRA frame to NV12.
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - Source BGRA pixels
+    /// * `src_stride` - Bytes per source row
+    /// * `dst` - Destination buffer, the frame has its size
+    pub fn argb_to_nv12(&self, src: &[u8], src_stride: u32, dst: &mut NV12Buffer) {
+        let (width, height) = (dst.width() as i32, dst.height() as i32);
+        let (stride_y, stride_uv) = dst.strides();
+        let (dst_y, dst_uv) = dst.data_mut();
+        match self.color_space {
+            ColorSpace::Srgb => yuv_helper::argb_to_nv12(
+                src, src_stride, dst_y, stride_y, dst_uv, stride_uv, width, height,
+            ),
+            ColorSpace::WideGamut | ColorSpace::Hdr(_) => self.mapped_to_nv12(src, src_stride, dst),
+        }
+    }
+
+    /// The scalar conversion of the frames that need their colors mapped, see `argb_to_nv12`.
+    fn mapped_to_nv12(&self, src: &[u8], src_stride: u32, dst: &mut NV12Buffer) {
+        let width = dst.width() as usize;
+        let height = dst.height() as usize;
+        let (stride_y, stride_uv) = dst.strides();
+        let (dst_y, dst_uv) = dst.data_mut();
+        let src_stride = src_stride as usize;
+        let stride_y = stride_y as usize;
+        let stride_uv = stride_uv as usize;
+
+        for row in (0..height).step_by(2) {
+            let rows = if row + 1 < height { 2 } else { 1 };
+            for col in (0..width).step_by(2) {
+                let cols = if col + 1 < width { 2 } else { 1 };
+                let (mut sum_r, mut sum_g, mut sum_b) = (0, 0, 0);
+                for dy in 0..rows {
+                    for dx in 0..cols {
+                        let offset = (row + dy) * src_stride + (col + dx) * 4;
+                        let (r, g, b) = self.map_pixel(&src[offset..offset + 4]);
+                        dst_y[(row + dy) * stride_y + col + dx] = rgb_to_y(r, g, b);
+                        sum_r += r;
+                        sum_g += g;
+                        sum_b += b;
+                    }
+                }
+                let count = (rows * cols) as i32;
+                let (r, g, b) = (sum_r / count, sum_g / count, sum_b / count);
+                let uv_offset = (row / 2) * stride_uv + col;
+                dst_uv[uv_offset] = rgb_to_u(r, g, b);
+                dst_uv[uv_offset + 1] = rgb_to_v(r, g, b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert_pixel(converter: &ColorConverter, bgra: [u8; 4]) -> (u8, u8, u8) {
+        let src: Vec<u8> = bgra.iter().cycle().take(16).copied().collect();
+        let mut dst = NV12Buffer::new(2, 2);
+        converter.mapped_to_nv12(&src, 8, &mut dst);
+        let (y, uv) = dst.data();
+        (y[0], uv[0], uv[1])
+    }
+
+    fn gray(value: u8) -> [u8; 4] {
+        [value, value, value, 255]
+    }
+
+    #[test]
+    fn test_matrix_is_bt709() {
+        /* Black, white and pure red in limited-range BT.709. */
+        assert_eq!(
+            (rgb_to_y(0, 0, 0), rgb_to_u(0, 0, 0), rgb_to_v(0, 0, 0)),
+            (16, 128, 128)
+        );
+        assert_eq!(
+            (
+                rgb_to_y(255, 255, 255),
+                rgb_to_u(255, 255, 255),
+                rgb_to_v(255, 255, 255)
+            ),
+            (235, 128, 128)
+        );
+        assert_eq!(
+            (
+                rgb_to_y(255, 0, 0),
+                rgb_to_u(255, 0, 0),
+                rgb_to_v(255, 0, 0)
+            ),
+            (63, 102, 240)
+        );
+        assert_eq!(ColorSpace::Srgb.matrix(), ColorMatrix::Bt601);
+        assert_eq!(ColorSpace::WideGamut.matrix(), ColorMatrix::Bt709);
+        assert_eq!(
+            ColorSpace::Hdr(HdrTransfer::Pq).matrix(),
+            ColorMatrix::Bt709
+        );
+    }
+
+    #[test]
+    fn test_wide_gamut_keeps_neutrals() {
+        let converter = ColorConverter::new(ColorSpace::WideGamut);
+        for value in [0u8, 64, 128, 255] {
+            let (y, u, v) = convert_pixel(&converter, gray(value));
+            let expected = rgb_to_y(value as i32, value as i32, value as i32);
+            assert!((y as i32 - expected as i32).abs() <= 1);
+            assert!((u as i32 - 128).abs() <= 1);
+            assert!((v as i32 - 128).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_wide_gamut_red_is_clipped_to_srgb_red() {
+        /* P3 red is outside sRGB, it maps to the most saturated sRGB red. */
+        let converter = ColorConverter::new(ColorSpace::WideGamut);
+        assert_eq!(convert_pixel(&converter, [0, 0, 255, 255]), (63, 102, 240));
+    }
+
+    #[test]
+    fn test_tone_curve() {
+        /* Below the knee the luminance is kept, the peak lands on SDR white. */
+        for nits in [1.0, 10.0, 50.0] {
+            assert!((tone_map_nits(nits) - nits).abs() < 0.01 * nits);
+        }
+        assert!((tone_map_nits(HDR_PEAK_NITS) - SDR_WHITE_NITS).abs() < 0.5);
+        assert!((tone_map_nits(4000.0) - SDR_WHITE_NITS).abs() < 0.5);
+
+        let mut last = 0.0;
+        for nits in (1..=100).map(|i| i as f32 * 10.0) {
+            let mapped = tone_map_nits(nits);
+            assert!(mapped >= last && mapped <= SDR_WHITE_NITS + 0.5);
+            last = mapped;
+        }
+    }
+
+    #[test]
+    fn test_pq_frames_are_tone_mapped() {
+        let converter = ColorConverter::new(ColorSpace::Hdr(HdrTransfer::Pq));
+        let code = |nits: f32| (nits_to_pq(nits) * 255.0).round() as u8;
+
+        assert_eq!(convert_pixel(&converter, gray(0)), (16, 128, 128));
+        /* The mastering peak and everything above it become SDR white. */
+        for nits in [HDR_PEAK_NITS, 4000.0] {
+            let (y, u, v) = convert_pixel(&converter, gray(code(nits)));
+            assert!(y >= 234, "{nits} nits: {y}");
+            assert!((u as i32 - 128).abs() <= 1 && (v as i32 - 128).abs() <= 1);
+        }
+        /* A dim gray keeps its brightness relative to SDR white. */
+        let value = pq_to_nits(code(20.0) as f32 / 255.0) / SDR_WHITE_NITS;
+        let expected = (linear_to_srgb(value) * 255.0).round() as i32;
+        let (y, _, _) = convert_pixel(&converter, gray(code(20.0)));
+        assert!((y as i32 - rgb_to_y(expected, expected, expected) as i32).abs() <= 1);
+
+        /* Brighter codes never get darker. */
+        let mut last = 0;
+        for value in 0..=255u8 {
+            let (y, _, _) = convert_pixel(&converter, gray(value));
+            assert!(y >= last);
+            last = y;
+        }
+    }
+
+    #[test]
+    fn test_hlg_frames_are_tone_mapped() {
+        let converter = ColorConverter::new(ColorSpace::Hdr(HdrTransfer::Hlg));
+        assert_eq!(convert_pixel(&converter, gray(0)), (16, 128, 128));
+        /* HLG's white is the display's peak, it becomes SDR white. */
+        let (y, u, v) = convert_pixel(&converter, gray(255));
+        assert!(y >= 234);
+        assert!((u as i32 - 128).abs() <= 1 && (v as i32 - 128).abs() <= 1);
+
+        let mut last = 0;
+        for value in 0..=255u8 {
+            let (y, _, _) = convert_pixel(&converter, gray(value));
+            assert!(y >= last);
+            last = y;
+        }
+    }
+}
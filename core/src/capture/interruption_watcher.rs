@@ -0,0 +1,144 @@
+//! Detection of the shared display being interrupted.
+//!
+//! When the shared display sleeps, gets locked or the screensaver starts the
+//! capture keeps delivering a frozen or black frame. The watcher thread checks
+//! the display's state periodically while a display is shared and notifies
+//! the application when it is interrupted or resumed, so the UIs can show why.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use socket_lib::ShareInterruption;
+
+use crate::event_sender::EventSender;
+use crate::UserEvent;
+
+const INTERRUPTION_WATCH_INTERVAL_SECS: u64 = 1;
+
+/// Background thread that sends `UserEvent::ShareInterrupted` and
+/// `UserEvent::ShareResumed` when the shared display's state changes.
+///
+/// The thread is stopped when the watcher is dropped.
+pub struct InterruptionWatcher {
+    stop_tx: mpsc::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl InterruptionWatcher {
+    /// Starts watching the display.
+    ///
+    /// # Parameters
+    /// - `event_sender`: Where the interruption events are sent
+    /// - `detect`: Returns the display's current interruption, `None` when it isn't interrupted
+    pub fn new(
+        event_sender: Box<dyn EventSender>,
+        detect: impl Fn() -> Option<ShareInterruption> + Send + 'static,
+    ) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            watch_interruptions(
+                event_sender,
+                detect,
+                stop_rx,
+                Duration::from_secs(INTERRUPTION_WATCH_INTERVAL_SECS),
+            )
+        });
+        Self {
+            stop_tx,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for InterruptionWatcher {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("InterruptionWatcher::drop: watcher thread panicked");
+            }
+        }
+    }
+}
+
+fn watch_interruptions(
+    event_sender: Box<dyn EventSender>,
+    detect: impl Fn() -> Option<ShareInterruption>,
+    stop_rx: mpsc::Receiver<()>,
+    interval: Duration,
+) {
+    let mut previous = None;
+    loop {
+        let current = detect();
+        if current != previous {
+            log::info!("watch_interruptions: {previous:?} -> {current:?}");
+            sentry_utils::add_breadcrumb(
+                "capture",
+                format!("share interruption changed: {current:?}"),
+            );
+            let event = match current {
+                Some(reason) => UserEvent::ShareInterrupted(reason),
+                None => UserEvent::ShareResumed,
+            };
+            if let Err(e) = event_sender.send(event) {
+                log::error!("watch_interruptions: error sending interruption: {e:?}");
+                break;
+            }
+            previous = current;
+        }
+
+        match stop_rx.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_watch_interruptions_reports_changes() {
+        let states = Arc::new(Mutex::new(vec![
+            None,
+            Some(ShareInterruption::ScreenLocked),
+            Some(ShareInterruption::ScreenLocked),
+            Some(ShareInterruption::DisplaySleep),
+            None,
+        ]));
+        let collector = EventCollector::new();
+        let (stop_tx, stop_rx) = mpsc::channel();
+
+        let detect_states = states.clone();
+        let detect = move || {
+            let mut states = detect_states.lock().unwrap();
+            if states.len() == 1 {
+                let _ = stop_tx.send(());
+                return states[0];
+            }
+            states.remove(0)
+        };
+        watch_interruptions(
+            Box::new(collector.clone()),
+            detect,
+            stop_rx,
+            Duration::from_millis(1),
+        );
+
+        let events = collector.take();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[0],
+            UserEvent::ShareInterrupted(ShareInterruption::ScreenLocked)
+        ));
+        assert!(matches!(
+            events[1],
+            UserEvent::ShareInterrupted(ShareInterruption::DisplaySleep)
+        ));
+        assert!(matches!(events[2], UserEvent::ShareResumed));
+    }
+}
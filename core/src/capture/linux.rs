@@ -1,13 +1,18 @@
-use crate::{capture::capturer::ScreenshareExt, utils::geometry::Extent};
+use socket_lib::ShareInterruption;
+
+use crate::{
+    capture::capturer::{ColorSpace, ScreenshareExt, WindowInfo},
+    utils::geometry::Extent,
+};
+
+/// Backend the desktop capturer uses on this platform, reported with capture failures.
+pub const CAPTURE_BACKEND: &str = "PipeWire";
 
 pub struct ScreenshareFunctions {}
 
 impl ScreenshareExt for ScreenshareFunctions {
     fn get_monitor_size(monitors: &[winit::monitor::MonitorHandle], input_id: u32) -> Extent {
-        Extent {
-            width: 0.,
-            height: 0.,
-        }
+        Extent::default()
     }
 
     fn get_selected_monitor(
@@ -16,6 +21,29 @@ impl ScreenshareExt for ScreenshareFunctions {
     ) -> winit::monitor::MonitorHandle {
         monitors[0].clone()
     }
+
+    fn get_display_color_space(_input_id: u32) -> ColorSpace {
+        ColorSpace::Srgb
+    }
+
+    fn get_share_interruption(_input_id: u32) -> Option<ShareInterruption> {
+        None
+    }
+
+    /// Thumbnails aren't captured on linux, the system picker shows the content.
+    fn get_display_apps(_input_id: u32) -> Vec<String> {
+        vec![]
+    }
+
+    /// Windows are picked in the system picker, their frame isn't known.
+    fn get_window_info(_window_id: u32) -> Option<WindowInfo> {
+        None
+    }
+
+    /// The portal asks for every share, there is no permission to revoke.
+    fn screen_capture_permitted() -> bool {
+        true
+    }
 }
 
 impl Default for ScreenshareFunctions {
@@ -1,7 +1,135 @@
 #[cfg(target_os = "macos")]
 use winit::platform::macos::MonitorHandleExtMacOS;
 
-use crate::{capture::capturer::ScreenshareExt, utils::geometry::Extent};
+use std::ffi::c_void;
+
+use core_foundation::{
+    base::{CFType, TCFType},
+    boolean::CFBoolean,
+    dictionary::CFDictionary,
+    dictionary::CFDictionaryRef,
+    number::CFNumber,
+    string::CFString,
+    string::CFStringRef,
+};
+use core_graphics::{
+    display::CGDisplay,
+    geometry::CGRect,
+    window::{
+        copy_window_info, kCGNullWindowID, kCGWindowBounds, kCGWindowIsOnscreen, kCGWindowLayer,
+        kCGWindowListExcludeDesktopElements, kCGWindowListOptionIncludingWindow,
+        kCGWindowListOptionOnScreenOnly, kCGWindowOwnerName, kCGWindowOwnerPID,
+    },
+};
+use socket_lib::ShareInterruption;
+
+use crate::{
+    capture::capturer::{ColorSpace, HdrTransfer, ScreenshareExt, WindowInfo},
+    utils::geometry::{Extent, Frame},
+};
+
+type CGColorSpaceRef = *const c_void;
+
+const SCREENSAVER_BUNDLE_ID: &str = "com.apple.ScreenSaver.Engine";
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGDisplayCopyColorSpace(display: u32) -> CGColorSpaceRef;
+    fn CGColorSpaceCopyName(space: CGColorSpaceRef) -> CFStringRef;
+    fn CGColorSpaceUsesExtendedRange(space: CGColorSpaceRef) -> bool;
+    fn CGColorSpaceIsWideGamutRGB(space: CGColorSpaceRef) -> bool;
+    fn CGColorSpaceRelease(space: CGColorSpaceRef);
+    fn CGDisplayIsAsleep(display: u32) -> u32;
+    fn CGSessionCopyCurrentDictionary() -> CFDictionaryRef;
+    fn CGPreflightScreenCaptureAccess() -> bool;
+}
+
+/// Returns `true` when the login window is shown over the user's session.
+fn screen_locked() -> bool {
+    let session = unsafe { CGSessionCopyCurrentDictionary() };
+    if session.is_null() {
+        return false;
+    }
+    let session: CFDictionary<CFString, core_foundation::base::CFType> =
+        unsafe { CFDictionary::wrap_under_create_rule(session) };
+    session
+        .find(CFString::from_static_string("CGSSessionScreenIsLocked"))
+        .and_then(|locked| locked.downcast::<CFBoolean>())
+        .map(bool::from)
+        .unwrap_or(false)
+}
+
+#[allow(unused_unsafe)]
+fn screensaver_running() -> bool {
+    use objc2_app_kit::NSWorkspace;
+
+    let applications = unsafe { NSWorkspace::sharedWorkspace().runningApplications() };
+    applications.iter().any(|application| {
+        unsafe { application.bundleIdentifier() }
+            .is_some_and(|bundle_id| bundle_id.to_string() == SCREENSAVER_BUNDLE_ID)
+    })
+}
+
+/// Returns `true` when the rectangles overlap.
+fn rects_intersect(a: &CGRect, b: &CGRect) -> bool {
+    a.origin.x < b.origin.x + b.size.width
+        && b.origin.x < a.origin.x + a.size.width
+        && a.origin.y < b.origin.y + b.size.height
+        && b.origin.y < a.origin.y + a.size.height
+}
+
+/// Returns the area the rectangles share, 0 when they don't overlap.
+fn intersection_area(a: &CGRect, b: &CGRect) -> f64 {
+    let width =
+        (a.origin.x + a.size.width).min(b.origin.x + b.size.width) - a.origin.x.max(b.origin.x);
+    let height =
+        (a.origin.y + a.size.height).min(b.origin.y + b.size.height) - a.origin.y.max(b.origin.y);
+    width.max(0.) * height.max(0.)
+}
+
+fn window_key(key: CFStringRef) -> CFString {
+    unsafe { CFString::wrap_under_get_rule(key) }
+}
+
+/// Returns the bounds, in points, of a window of the window list.
+fn window_bounds(window: &CFDictionary<CFString, CFType>) -> Option<CGRect> {
+    window
+        .find(window_key(unsafe { kCGWindowBounds }))
+        .and_then(|bounds| bounds.downcast::<CFDictionary>())
+        .and_then(|bounds| CGRect::from_dict_representation(&bounds))
+}
+
+/// Returns the app name and the bundle id of the app owning a window of the window list.
+fn window_apps(window: &CFDictionary<CFString, CFType>) -> Vec<String> {
+    let mut apps = vec![];
+    if let Some(name) = window
+        .find(window_key(unsafe { kCGWindowOwnerName }))
+        .and_then(|name| name.downcast::<CFString>())
+    {
+        apps.push(name.to_string());
+    }
+    if let Some(bundle_id) = window
+        .find(window_key(unsafe { kCGWindowOwnerPID }))
+        .and_then(|pid| pid.downcast::<CFNumber>())
+        .and_then(|pid| pid.to_i32())
+        .and_then(bundle_id)
+    {
+        apps.push(bundle_id);
+    }
+    apps
+}
+
+/// Returns the bundle id of the running app with the pid.
+fn bundle_id(pid: i32) -> Option<String> {
+    use objc2_app_kit::NSRunningApplication;
+
+    let application =
+        unsafe { NSRunningApplication::runningApplicationWithProcessIdentifier(pid) }?;
+    unsafe { application.bundleIdentifier() }.map(|bundle_id| bundle_id.to_string())
+}
+
+/// Backend the desktop capturer uses on this platform, reported with capture failures.
+pub const CAPTURE_BACKEND: &str = "ScreenCaptureKit";
 
 pub struct ScreenshareFunctions {}
 
@@ -9,18 +137,11 @@ impl ScreenshareExt for ScreenshareFunctions {
     fn get_monitor_size(monitors: &[winit::monitor::MonitorHandle], input_id: u32) -> Extent {
         for monitor in monitors {
             if monitor.native_id() == input_id {
-                let monitor_size = monitor.size();
-                return Extent {
-                    width: monitor_size.width as f64,
-                    height: monitor_size.height as f64,
-                };
+                return monitor.size().into();
             }
         }
 
-        Extent {
-            width: 0.,
-            height: 0.,
-        }
+        Extent::default()
     }
 
     fn get_selected_monitor(
@@ -35,6 +156,144 @@ impl ScreenshareExt for ScreenshareFunctions {
         }
         selected_monitor
     }
+
+    fn get_display_color_space(input_id: u32) -> ColorSpace {
+        unsafe {
+            let space = CGDisplayCopyColorSpace(input_id);
+            if space.is_null() {
+                log::warn!("get_display_color_space: no color space for display {input_id}");
+                return ColorSpace::Srgb;
+            }
+
+            let name_ref = CGColorSpaceCopyName(space);
+            let name = if name_ref.is_null() {
+                String::new()
+            } else {
+                CFString::wrap_under_create_rule(name_ref).to_string()
+            };
+            let extended = CGColorSpaceUsesExtendedRange(space);
+            let wide_gamut = CGColorSpaceIsWideGamutRGB(space);
+            CGColorSpaceRelease(space);
+            log::debug!(
+                "get_display_color_space: {input_id} name: {name} extended: {extended} wide: {wide_gamut}"
+            );
+
+            /* PQ and HLG color spaces are only reported while the display is in HDR mode,
+             * extended range alone is EDR, its frames are SDR. */
+            if name.contains("PQ") {
+                ColorSpace::Hdr(HdrTransfer::Pq)
+            } else if name.contains("HLG") {
+                ColorSpace::Hdr(HdrTransfer::Hlg)
+            } else if extended || wide_gamut || name.contains("P3") {
+                ColorSpace::WideGamut
+            } else {
+                ColorSpace::Srgb
+            }
+        }
+    }
+
+    fn get_share_interruption(input_id: u32) -> Option<ShareInterruption> {
+        /* The lock screen is shown over the screensaver, so it is checked first. */
+        if screen_locked() {
+            Some(ShareInterruption::ScreenLocked)
+        } else if screensaver_running() {
+            Some(ShareInterruption::Screensaver)
+        } else if unsafe { CGDisplayIsAsleep(input_id) } != 0 {
+            Some(ShareInterruption::DisplaySleep)
+        } else {
+            None
+        }
+    }
+
+    /// Both the app name and the bundle id of each app are listed.
+    fn get_display_apps(input_id: u32) -> Vec<String> {
+        let display_bounds = CGDisplay::new(input_id).bounds();
+        let options = kCGWindowListOptionOnScreenOnly | kCGWindowListExcludeDesktopElements;
+        let windows = match copy_window_info(options, kCGNullWindowID) {
+            Some(windows) => windows,
+            None => {
+                log::warn!("get_display_apps: failed to copy window list");
+                return vec![];
+            }
+        };
+
+        let mut apps = vec![];
+        for window in windows.iter() {
+            let window: CFDictionary<CFString, CFType> =
+                unsafe { CFDictionary::wrap_under_get_rule(*window as CFDictionaryRef) };
+
+            /* The menu bar, the dock and other system windows are above the normal layer. */
+            let layer = window
+                .find(window_key(unsafe { kCGWindowLayer }))
+                .and_then(|layer| layer.downcast::<CFNumber>())
+                .and_then(|layer| layer.to_i64());
+            if layer != Some(0) {
+                continue;
+            }
+
+            let on_display = window_bounds(&window)
+                .is_some_and(|bounds| rects_intersect(&bounds, &display_bounds));
+            if !on_display {
+                continue;
+            }
+
+            apps.extend(window_apps(&window));
+        }
+        apps
+    }
+
+    /// The window's bounds are in points, they are converted to pixels with
+    /// the scale of the display showing most of it, the way winit positions
+    /// the monitors.
+    fn get_window_info(window_id: u32) -> Option<WindowInfo> {
+        let windows = copy_window_info(kCGWindowListOptionIncludingWindow, window_id)?;
+        let window = windows.iter().next()?;
+        let window: CFDictionary<CFString, CFType> =
+            unsafe { CFDictionary::wrap_under_get_rule(*window as CFDictionaryRef) };
+
+        /* Minimized windows and windows of hidden apps are off screen. */
+        let on_screen = window
+            .find(window_key(unsafe { kCGWindowIsOnscreen }))
+            .and_then(|on_screen| on_screen.downcast::<CFBoolean>())
+            .is_some_and(bool::from);
+        if !on_screen {
+            return None;
+        }
+        let bounds = window_bounds(&window)?;
+
+        let displays = match CGDisplay::active_displays() {
+            Ok(displays) => displays,
+            Err(e) => {
+                log::warn!("get_window_info: failed to list displays: {e:?}");
+                return None;
+            }
+        };
+        let (display_id, display_bounds) = displays
+            .into_iter()
+            .map(|id| (id, CGDisplay::new(id).bounds()))
+            .filter(|(_, display_bounds)| intersection_area(&bounds, display_bounds) > 0.)
+            .max_by(|(_, a), (_, b)| {
+                intersection_area(&bounds, a).total_cmp(&intersection_area(&bounds, b))
+            })?;
+        let scale = CGDisplay::new(display_id).pixels_wide() as f64 / display_bounds.size.width;
+
+        Some(WindowInfo {
+            frame: Frame::new(
+                display_bounds.origin.x * scale
+                    + (bounds.origin.x - display_bounds.origin.x) * scale,
+                display_bounds.origin.y * scale
+                    + (bounds.origin.y - display_bounds.origin.y) * scale,
+                Extent::new(bounds.size.width * scale, bounds.size.height * scale),
+            ),
+            display_id,
+            apps: window_apps(&window),
+        })
+    }
+
+    /// Doesn't show the permission prompt, unlike `CGRequestScreenCaptureAccess`.
+    fn screen_capture_permitted() -> bool {
+        unsafe { CGPreflightScreenCaptureAccess() }
+    }
 }
 
 impl Default for ScreenshareFunctions {
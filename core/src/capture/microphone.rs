@@ -0,0 +1,397 @@
+//! Capture of the sharer's microphone.
+//!
+//! The microphone is published by the core like the screen share and the
+//! camera, so the webview doesn't depend on the browser's audio constraints.
+//! The samples go through the webrtc audio processing module of the audio
+//! source, which does the echo cancellation, noise suppression and gain
+//! control the sharer picked, see `AudioProcessing`.
+//!
+//! The input stream lives on a thread of its own, streams of some hosts
+//! can't move between threads. Its samples are converted to 16-bit and pushed
+//! to the audio source in the 10ms frames webrtc processes.
+//!
+//! Noise suppression is the most expensive stage, on a machine that is busy
+//! for a while the capture reports `UserEvent::MicrophoneOverloaded` and the
+//! application republishes the microphone without it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, SupportedStreamConfig};
+use socket_lib::MicrophoneDevice;
+use sysinfo::System;
+
+use crate::event_sender::EventSender;
+use crate::room::media::{AudioSamples, SharedAudioSink};
+use crate::UserEvent;
+
+/// Frames of this length are pushed to the audio source, webrtc processes 10ms at a time.
+const FRAME_DURATION_MS: u32 = 10;
+/// How often the capture thread checks if it was stopped while no samples arrive.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the CPU usage is sampled while noise suppression is on.
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+/// Global CPU usage, in percent, above which the machine counts as busy.
+const CPU_OVERLOAD_THRESHOLD: f32 = 90.0;
+/// Consecutive busy samples before noise suppression is turned off.
+const CPU_OVERLOAD_SAMPLES: u32 = 5;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MicrophoneError {
+    #[error("Microphone {0} not found")]
+    NotFound(String),
+    #[error("No microphone found")]
+    NoMicrophone,
+    #[error("Failed to open microphone: {0}")]
+    Open(String),
+    #[error("Unsupported sample format {0}")]
+    UnsupportedFormat(String),
+    #[error("Microphone stream failed: {0}")]
+    Stream(String),
+    #[error("Failed to publish microphone: {0}")]
+    Publish(String),
+    #[error("Microphone thread stopped before the microphone was opened")]
+    Stopped,
+}
+
+/// Returns the microphones of the machine, an empty list when they can't be listed.
+pub fn list_microphones() -> Vec<MicrophoneDevice> {
+    let devices = match cpal::default_host().input_devices() {
+        Ok(devices) => devices,
+        Err(e) => {
+            log::error!("list_microphones: {e:?}");
+            return vec![];
+        }
+    };
+    devices
+        .filter_map(|device| device.name().ok())
+        .map(|name| MicrophoneDevice {
+            id: name.clone(),
+            name,
+        })
+        .collect()
+}
+
+/// Splits the interleaved samples of the microphone into frames of `FRAME_DURATION_MS`.
+#[derive(Debug)]
+struct FrameChunker {
+    samples: Vec<i16>,
+    /// Interleaved samples of a frame
+    frame_len: usize,
+}
+
+impl FrameChunker {
+    fn new(sample_rate: u32, num_channels: u32) -> Self {
+        let samples_per_channel = sample_rate * FRAME_DURATION_MS / 1000;
+        Self {
+            samples: vec![],
+            frame_len: (samples_per_channel * num_channels) as usize,
+        }
+    }
+
+    fn push(&mut self, samples: &[i16]) {
+        self.samples.extend_from_slice(samples);
+    }
+
+    /// Returns the oldest complete frame, `None` until enough samples were pushed.
+    fn next_frame(&mut self) -> Option<Vec<i16>> {
+        if self.frame_len == 0 || self.samples.len() < self.frame_len {
+            return None;
+        }
+        Some(self.samples.drain(..self.frame_len).collect())
+    }
+}
+
+/// Decides when the machine is too busy for noise suppression.
+///
+/// A single busy sample is a spike, only a machine that stays busy for
+/// `CPU_OVERLOAD_SAMPLES` samples is overloaded.
+#[derive(Debug, Default)]
+struct CpuGuard {
+    busy_samples: u32,
+}
+
+impl CpuGuard {
+    /// Records a sample of the global CPU usage in percent.
+    ///
+    /// # Returns
+    /// `true` once the machine was busy for long enough.
+    fn record(&mut self, usage: f32) -> bool {
+        if usage >= CPU_OVERLOAD_THRESHOLD {
+            self.busy_samples += 1;
+        } else {
+            self.busy_samples = 0;
+        }
+        self.busy_samples >= CPU_OVERLOAD_SAMPLES
+    }
+}
+
+/// Returns the microphone `device_id` and its default input config.
+fn open_microphone(
+    device_id: Option<&str>,
+) -> Result<(Device, SupportedStreamConfig), MicrophoneError> {
+    let host = cpal::default_host();
+    let device = match device_id {
+        Some(id) => host
+            .input_devices()
+            .map_err(|e| MicrophoneError::Open(e.to_string()))?
+            .find(|device| device.name().is_ok_and(|name| name == id))
+            .ok_or_else(|| MicrophoneError::NotFound(id.to_string()))?,
+        None => host
+            .default_input_device()
+            .ok_or(MicrophoneError::NoMicrophone)?,
+    };
+    let config = device
+        .default_input_config()
+        .map_err(|e| MicrophoneError::Open(e.to_string()))?;
+    Ok((device, config))
+}
+
+/// Builds the input stream of `device`, its samples are sent to `samples_tx` as 16-bit.
+///
+/// `failed` gets the error of a stream that stopped delivering samples.
+pub(super) fn build_stream(
+    device: &Device,
+    config: &SupportedStreamConfig,
+    samples_tx: mpsc::Sender<Vec<i16>>,
+    failed: impl Fn(String) + Send + 'static,
+) -> Result<Stream, MicrophoneError> {
+    match config.sample_format() {
+        SampleFormat::I16 => build_typed_stream::<i16>(device, config, samples_tx, failed),
+        SampleFormat::U16 => build_typed_stream::<u16>(device, config, samples_tx, failed),
+        SampleFormat::F32 => build_typed_stream::<f32>(device, config, samples_tx, failed),
+        format => Err(MicrophoneError::UnsupportedFormat(format.to_string())),
+    }
+}
+
+fn build_typed_stream<T>(
+    device: &Device,
+    config: &SupportedStreamConfig,
+    samples_tx: mpsc::Sender<Vec<i16>>,
+    failed: impl Fn(String) + Send + 'static,
+) -> Result<Stream, MicrophoneError>
+where
+    T: SizedSample,
+    i16: FromSample<T>,
+{
+    device
+        .build_input_stream(
+            &config.config(),
+            move |data: &[T], _| {
+                let samples = data
+                    .iter()
+                    .map(|sample| sample.to_sample::<i16>())
+                    .collect();
+                let _ = samples_tx.send(samples);
+            },
+            move |e| failed(e.to_string()),
+            None,
+        )
+        .map_err(|e| MicrophoneError::Open(e.to_string()))
+}
+
+/// Pushes the captured samples to `source` in 10ms frames until `stop` is set.
+///
+/// With `overload_sender` the CPU usage is sampled, it gets
+/// `UserEvent::MicrophoneOverloaded` once when the machine stays busy.
+pub(super) fn stream_samples(
+    samples_rx: mpsc::Receiver<Vec<i16>>,
+    source: &SharedAudioSink,
+    sample_rate: u32,
+    num_channels: u32,
+    stop: &AtomicBool,
+    mut overload_sender: Option<Box<dyn EventSender>>,
+) {
+    /* The audio source is fed asynchronously, this thread is its only task. */
+    let runtime = match tokio::runtime::Builder::new_current_thread().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            log::error!("stream_samples: Failed to create runtime: {e:?}");
+            return;
+        }
+    };
+    let mut chunker = FrameChunker::new(sample_rate, num_channels);
+    let samples_per_channel = sample_rate * FRAME_DURATION_MS / 1000;
+    let mut system = System::new();
+    let mut cpu_guard = CpuGuard::default();
+    let mut cpu_sampled_at = Instant::now();
+    if overload_sender.is_some() {
+        /* The usage is computed between two refreshes. */
+        system.refresh_cpu_usage();
+    }
+    while !stop.load(Ordering::Relaxed) {
+        if overload_sender.is_some() && cpu_sampled_at.elapsed() >= CPU_SAMPLE_INTERVAL {
+            cpu_sampled_at = Instant::now();
+            system.refresh_cpu_usage();
+            if cpu_guard.record(system.global_cpu_usage()) {
+                log::warn!("stream_samples: CPU overloaded, noise suppression is too expensive");
+                if let Some(sender) = overload_sender.take() {
+                    let _ = sender.send(UserEvent::MicrophoneOverloaded);
+                }
+            }
+        }
+        match samples_rx.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok(samples) => chunker.push(&samples),
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        while let Some(frame) = chunker.next_frame() {
+            let samples = AudioSamples {
+                data: &frame,
+                sample_rate,
+                num_channels,
+                samples_per_channel,
+            };
+            if let Err(e) = runtime.block_on(source.push_samples(samples)) {
+                log::warn!("stream_samples: Failed to push frame: {e:?}");
+            }
+        }
+    }
+}
+
+/// A microphone being published, the capture stops when it is dropped.
+#[derive(Debug)]
+pub struct MicrophoneCapture {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MicrophoneCapture {
+    /// Opens a microphone and starts pushing its samples to the track `publish` creates.
+    ///
+    /// # Parameters
+    /// - `device_id`: The id of the microphone, see `list_microphones`, `None`
+    ///   for the default microphone
+    /// - `event_sender`: Gets `UserEvent::MicrophoneFailed` when the stream fails
+    /// - `guard_cpu`: Whether `UserEvent::MicrophoneOverloaded` is sent when
+    ///   the machine stays busy, set while noise suppression is on
+    /// - `publish`: Publishes a track of the microphone's sample rate and
+    ///   channels and returns its source
+    ///
+    /// # Returns
+    /// The running capture, or the error that kept the microphone from being
+    /// opened or published.
+    pub fn start(
+        device_id: Option<String>,
+        event_sender: Box<dyn EventSender>,
+        guard_cpu: bool,
+        publish: impl FnOnce(u32, u32) -> Result<SharedAudioSink, MicrophoneError>,
+    ) -> Result<Self, MicrophoneError> {
+        log::info!("MicrophoneCapture::start: {device_id:?}");
+        let (format_tx, format_rx) = mpsc::channel();
+        let (source_tx, source_rx) = mpsc::channel::<SharedAudioSink>();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_stop = stop.clone();
+        let overload_sender = guard_cpu.then(|| event_sender.boxed_clone());
+        let thread = std::thread::spawn(move || {
+            let (samples_tx, samples_rx) = mpsc::channel();
+            let opened = open_microphone(device_id.as_deref()).and_then(|(device, config)| {
+                let failed = move |e: String| {
+                    log::error!("MicrophoneCapture: stream error: {e}");
+                    let _ = event_sender.send(UserEvent::MicrophoneFailed(
+                        MicrophoneError::Stream(e).to_string(),
+                    ));
+                };
+                build_stream(&device, &config, samples_tx, failed)
+                    .map(|stream| (stream, config.sample_rate().0, u32::from(config.channels())))
+            });
+            let (stream, sample_rate, num_channels) = match opened {
+                Ok(opened) => opened,
+                Err(e) => {
+                    let _ = format_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = format_tx.send(Ok((sample_rate, num_channels)));
+            /* The sender is dropped without a source when publishing failed. */
+            let Ok(source) = source_rx.recv() else {
+                return;
+            };
+            if let Err(e) = stream.play() {
+                log::error!("MicrophoneCapture: Failed to play stream: {e:?}");
+                return;
+            }
+            stream_samples(
+                samples_rx,
+                &source,
+                sample_rate,
+                num_channels,
+                &thread_stop,
+                overload_sender,
+            );
+        });
+
+        let (sample_rate, num_channels) = match format_rx.recv() {
+            Ok(Ok(format)) => format,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(MicrophoneError::Stopped),
+        };
+        log::info!("MicrophoneCapture::start: microphone opened at {sample_rate}Hz {num_channels} channels");
+        let source = publish(sample_rate, num_channels)?;
+        if source_tx.send(source).is_err() {
+            return Err(MicrophoneError::Stopped);
+        }
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for MicrophoneCapture {
+    fn drop(&mut self) {
+        log::info!("MicrophoneCapture::drop");
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("MicrophoneCapture::drop: Microphone thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_are_10ms_of_interleaved_samples() {
+        let mut chunker = FrameChunker::new(48_000, 2);
+        assert_eq!(chunker.next_frame(), None);
+
+        /* 480 samples of both channels make a frame. */
+        chunker.push(&vec![1; 700]);
+        assert_eq!(chunker.next_frame(), None);
+        chunker.push(&vec![2; 500]);
+        let frame = chunker.next_frame().unwrap();
+        assert_eq!(frame.len(), 960);
+        assert_eq!(frame[699], 1);
+        assert_eq!(frame[700], 2);
+        assert_eq!(chunker.next_frame(), None);
+
+        /* The rest stays for the next frame. */
+        chunker.push(&vec![3; 720]);
+        let frame = chunker.next_frame().unwrap();
+        assert_eq!(&frame[..240], &[2; 240][..]);
+        assert_eq!(chunker.next_frame(), None);
+    }
+
+    #[test]
+    fn test_cpu_guard_ignores_spikes() {
+        let mut guard = CpuGuard::default();
+        for _ in 0..CPU_OVERLOAD_SAMPLES - 1 {
+            assert!(!guard.record(99.0));
+        }
+        /* An idle sample restarts the count. */
+        assert!(!guard.record(40.0));
+        for _ in 0..CPU_OVERLOAD_SAMPLES - 1 {
+            assert!(!guard.record(CPU_OVERLOAD_THRESHOLD));
+        }
+        assert!(guard.record(95.0));
+    }
+}
@@ -0,0 +1,102 @@
+//! Detection of the screen recording permission being revoked mid-share.
+//!
+//! On macOS the user can revoke the screen recording permission in System
+//! Settings while sharing. The capture doesn't report it, ScreenCaptureKit
+//! either stops delivering frames, fails permanently or reports the user
+//! stopped the capture, and restarting the stream can't bring the frames
+//! back. The capturer probes the permission when the capture fails in one of
+//! these ways, and on every watchdog tick while capturing, so the session is
+//! ended once instead of being restarted until it gives up.
+
+use socket_lib::Permission;
+
+/// What the last probe found about the screen recording permission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionState {
+    Granted,
+    /// Reported to the application, stays until a probe finds it granted again
+    Revoked,
+}
+
+/// Tracks the screen recording permission of the active capture.
+#[derive(Debug)]
+pub struct PermissionMonitor {
+    state: PermissionState,
+    /// Returns `true` while the permission is granted
+    probe: fn() -> bool,
+}
+
+impl PermissionMonitor {
+    /// Creates the monitor, the permission is assumed granted until probed.
+    ///
+    /// # Parameters
+    /// - `probe`: Checks the permission, see `ScreenshareExt::screen_capture_permitted`
+    pub fn new(probe: fn() -> bool) -> Self {
+        Self {
+            state: PermissionState::Granted,
+            probe,
+        }
+    }
+
+    pub fn state(&self) -> PermissionState {
+        self.state
+    }
+
+    /// Probes the permission.
+    ///
+    /// # Returns
+    /// `Some(Permission::ScreenCapture)` when the permission was revoked since
+    /// the last probe, a revocation is only returned once.
+    pub fn check(&mut self) -> Option<Permission> {
+        let granted = (self.probe)();
+        match (self.state, granted) {
+            (PermissionState::Granted, false) => {
+                log::warn!("PermissionMonitor::check: screen recording permission revoked");
+                self.state = PermissionState::Revoked;
+                Some(Permission::ScreenCapture)
+            }
+            (PermissionState::Revoked, true) => {
+                log::info!("PermissionMonitor::check: screen recording permission granted");
+                self.state = PermissionState::Granted;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Assumes the permission is granted again, a new capture probes it afresh.
+    pub fn reset(&mut self) {
+        self.state = PermissionState::Granted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static GRANTED: AtomicBool = AtomicBool::new(true);
+
+    fn probe() -> bool {
+        GRANTED.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn test_revocation_is_reported_once() {
+        let mut monitor = PermissionMonitor::new(probe);
+        assert_eq!(monitor.check(), None);
+
+        GRANTED.store(false, Ordering::Relaxed);
+        assert_eq!(monitor.check(), Some(Permission::ScreenCapture));
+        assert_eq!(monitor.state(), PermissionState::Revoked);
+        assert_eq!(monitor.check(), None);
+
+        /* A new capture reports it again if it is still revoked. */
+        monitor.reset();
+        assert_eq!(monitor.check(), Some(Permission::ScreenCapture));
+
+        GRANTED.store(true, Ordering::Relaxed);
+        assert_eq!(monitor.check(), None);
+        assert_eq!(monitor.state(), PermissionState::Granted);
+    }
+}
@@ -0,0 +1,88 @@
+//! Live preview of the shared content for the sharer.
+//!
+//! While the tauri app shows the preview, the ticker thread wakes the event
+//! loop at the preview's frame rate. The application encodes the active
+//! stream's latest frame and sends it through the shared memory region, see
+//! `socket_lib::shm`. The preview doesn't capture on its own, it is only as
+//! fresh as the stream.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::event_sender::EventSender;
+use crate::utils::geometry::Extent;
+use crate::UserEvent;
+
+/// Interval between two preview frames, 5 fps is plenty for a thumbnail.
+const PREVIEW_INTERVAL_MS: u64 = 200;
+
+/// The size the preview frames fit in.
+pub const PREVIEW_EXTENT: Extent = Extent {
+    width: 480.0,
+    height: 270.0,
+};
+
+/// Background thread that sends `UserEvent::SharePreviewTick` until it is dropped.
+pub struct PreviewTicker {
+    stop_tx: mpsc::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PreviewTicker {
+    pub fn new(event_sender: Box<dyn EventSender>) -> Self {
+        Self::with_interval(event_sender, Duration::from_millis(PREVIEW_INTERVAL_MS))
+    }
+
+    fn with_interval(event_sender: Box<dyn EventSender>, interval: Duration) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || tick(event_sender, stop_rx, interval));
+        Self {
+            stop_tx,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for PreviewTicker {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("PreviewTicker::drop: ticker thread panicked");
+            }
+        }
+    }
+}
+
+fn tick(event_sender: Box<dyn EventSender>, stop_rx: mpsc::Receiver<()>, interval: Duration) {
+    while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+        if let Err(e) = event_sender.send(UserEvent::SharePreviewTick) {
+            log::error!("tick: error sending share preview tick: {e:?}");
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+
+    #[test]
+    fn test_ticks_until_dropped() {
+        let collector = EventCollector::new();
+        let ticker =
+            PreviewTicker::with_interval(Box::new(collector.clone()), Duration::from_millis(5));
+        std::thread::sleep(Duration::from_millis(100));
+        drop(ticker);
+
+        let events = collector.take();
+        assert!(!events.is_empty());
+        assert!(events
+            .iter()
+            .all(|event| matches!(event, UserEvent::SharePreviewTick)));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(collector.take().is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+//! Redaction of the thumbnails of content showing blocked apps.
+//!
+//! The user can block apps, like password managers, whose windows shouldn't
+//! be previewed. Thumbnails of displays with a window of a blocked app are
+//! redacted before they are encoded, so the sensitive pixels never reach the
+//! content picker or the socket.
+//!
+//! The titles of windows and displays can name sensitive documents too. They
+//! are only logged as hashes, see `log_title`, and the user can replace them
+//! with generic labels in the available content.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use image::{imageops, RgbImage};
+use socket_lib::{CaptureContent, ContentType, ThumbnailPrivacy, ThumbnailRedaction};
+
+/// How much a thumbnail is downscaled when it is blurred.
+const BLUR_DOWNSCALE: u32 = 24;
+/// Color of the placeholder thumbnails.
+const PLACEHOLDER_COLOR: [u8; 3] = [51, 65, 85];
+
+/// Normalizes an app name for matching, executable extensions are dropped.
+fn normalize_app(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    match name.strip_suffix(".exe") {
+        Some(stem) => stem.to_string(),
+        None => name,
+    }
+}
+
+/// Decides which thumbnails are redacted from the apps shown in the content.
+#[derive(Debug, Default)]
+pub struct ThumbnailFilter {
+    /// Normalized names of the blocked apps
+    blocked_apps: Vec<String>,
+    redaction: ThumbnailRedaction,
+    generic_titles: bool,
+}
+
+impl ThumbnailFilter {
+    pub fn new(privacy: ThumbnailPrivacy) -> Self {
+        let mut blocked_apps: Vec<String> = privacy
+            .blocked_apps
+            .iter()
+            .map(|app| normalize_app(app))
+            .filter(|app| !app.is_empty())
+            .collect();
+        blocked_apps.sort();
+        blocked_apps.dedup();
+        Self {
+            blocked_apps,
+            redaction: privacy.redaction,
+            generic_titles: privacy.generic_titles,
+        }
+    }
+
+    /// Returns how the thumbnail of content showing `apps` is redacted.
+    ///
+    /// # Parameters
+    /// - `apps`: Names of the apps with windows in the content
+    ///
+    /// # Returns
+    /// - `Some(ThumbnailRedaction)`: One of the apps is blocked
+    /// - `None`: The thumbnail can be shown as is
+    pub fn redaction_for(&self, apps: &[String]) -> Option<ThumbnailRedaction> {
+        let blocked = apps
+            .iter()
+            .find(|app| self.blocked_apps.contains(&normalize_app(app)))?;
+        log::info!("ThumbnailFilter::redaction_for: {blocked} is blocked");
+        Some(self.redaction)
+    }
+
+    /// Returns `true` when no app is blocked.
+    pub fn is_empty(&self) -> bool {
+        self.blocked_apps.is_empty()
+    }
+
+    /// Returns `true` when the content's titles are replaced with generic labels.
+    pub fn generic_titles(&self) -> bool {
+        self.generic_titles
+    }
+}
+
+/// Returns how a title is logged, a short hash that tells the titles apart
+/// without showing them, and its length.
+///
+/// # Parameters
+/// - `title`: The title of a window or display
+pub fn log_title(title: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    title.hash(&mut hasher);
+    format!(
+        "title#{:08x} ({} chars)",
+        hasher.finish() as u32,
+        title.chars().count()
+    )
+}
+
+/// Replaces the titles of the content with generic labels.
+///
+/// Displays and windows are numbered separately in the order of their ids,
+/// so a content keeps its label while the sources don't change.
+///
+/// # Parameters
+/// - `content`: The available content
+pub fn replace_titles(content: &mut [CaptureContent]) {
+    let mut ids: Vec<(bool, u32)> = content
+        .iter()
+        .map(|c| (c.content.content_type == ContentType::Display, c.content.id))
+        .collect();
+    ids.sort();
+    ids.dedup();
+    for c in content.iter_mut() {
+        let is_display = c.content.content_type == ContentType::Display;
+        let number = ids
+            .iter()
+            .filter(|(display, _)| *display == is_display)
+            .position(|(_, id)| *id == c.content.id)
+            .unwrap_or_default()
+            + 1;
+        let kind = if is_display { "Display" } else { "Window" };
+        c.title = format!("{kind} {number}");
+    }
+}
+
+/// Redacts a thumbnail of tightly packed RGB pixels.
+///
+/// # Parameters
+/// - `raw_image`: The thumbnail pixels
+/// - `width`, `height`: The thumbnail size
+/// - `redaction`: How the thumbnail is redacted
+///
+/// # Returns
+/// The redacted pixels, with the same size as the thumbnail.
+pub fn redact_rgb(
+    raw_image: Vec<u8>,
+    width: u32,
+    height: u32,
+    redaction: ThumbnailRedaction,
+) -> Vec<u8> {
+    let pixels = (width * height) as usize;
+    match redaction {
+        ThumbnailRedaction::Placeholder => PLACEHOLDER_COLOR.repeat(pixels),
+        ThumbnailRedaction::Blur => {
+            let image = match RgbImage::from_raw(width, height, raw_image) {
+                Some(image) => image,
+                None => {
+                    log::error!("redact_rgb: thumbnail doesn't match {width}x{height}");
+                    return PLACEHOLDER_COLOR.repeat(pixels);
+                }
+            };
+            /* Scaling down and back up blurs far cheaper than a gaussian blur. */
+            let small = imageops::resize(
+                &image,
+                (width / BLUR_DOWNSCALE).max(1),
+                (height / BLUR_DOWNSCALE).max(1),
+                imageops::FilterType::Triangle,
+            );
+            imageops::resize(&small, width, height, imageops::FilterType::Triangle).into_raw()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(apps: &[&str], redaction: ThumbnailRedaction) -> ThumbnailFilter {
+        ThumbnailFilter::new(ThumbnailPrivacy {
+            blocked_apps: apps.iter().map(|app| app.to_string()).collect(),
+            redaction,
+            generic_titles: false,
+        })
+    }
+
+    #[test]
+    fn test_redaction_for_blocked_apps() {
+        let filter = filter(
+            &["1Password", " KeePassXC.exe ", ""],
+            ThumbnailRedaction::Blur,
+        );
+        assert!(!filter.is_empty());
+        assert_eq!(
+            filter.redaction_for(&["Finder".to_string(), "1password".to_string()]),
+            Some(ThumbnailRedaction::Blur)
+        );
+        assert_eq!(
+            filter.redaction_for(&["KeePassXC".to_string()]),
+            Some(ThumbnailRedaction::Blur)
+        );
+        assert_eq!(filter.redaction_for(&["Finder".to_string()]), None);
+        assert_eq!(filter.redaction_for(&[]), None);
+    }
+
+    #[test]
+    fn test_default_filter_blocks_nothing() {
+        let filter = ThumbnailFilter::default();
+        assert!(filter.is_empty());
+        assert_eq!(filter.redaction_for(&["1Password".to_string()]), None);
+    }
+
+    #[test]
+    fn test_redact_rgb() {
+        let (width, height) = (48, 24);
+        /* Black and white columns, readable detail that the blur removes. */
+        let raw_image: Vec<u8> = (0..width * height)
+            .flat_map(|i| if i % 2 == 0 { [0; 3] } else { [255; 3] })
+            .collect();
+
+        let placeholder = redact_rgb(
+            raw_image.clone(),
+            width,
+            height,
+            ThumbnailRedaction::Placeholder,
+        );
+        assert_eq!(placeholder.len(), raw_image.len());
+        assert!(placeholder
+            .chunks(3)
+            .all(|pixel| pixel == PLACEHOLDER_COLOR));
+
+        let blurred = redact_rgb(raw_image.clone(), width, height, ThumbnailRedaction::Blur);
+        assert_eq!(blurred.len(), raw_image.len());
+        assert!(blurred.iter().all(|&value| (64..192).contains(&value)));
+    }
+
+    fn content(content_type: ContentType, id: u32, title: &str) -> CaptureContent {
+        CaptureContent {
+            content: socket_lib::Content { content_type, id },
+            base64: String::new(),
+            title: title.to_string(),
+            redacted: false,
+            presets: vec![],
+        }
+    }
+
+    #[test]
+    fn test_log_title_hides_the_title() {
+        let logged = log_title("Q3 layoffs.xlsx - Excel");
+        assert!(!logged.contains("layoffs"));
+        assert!(logged.ends_with("(23 chars)"));
+        assert_eq!(logged, log_title("Q3 layoffs.xlsx - Excel"));
+        assert_ne!(logged, log_title("Q4 layoffs.xlsx - Excel"));
+    }
+
+    #[test]
+    fn test_replace_titles() {
+        let window = ContentType::Window { display_id: 1 };
+        let mut available = vec![
+            content(ContentType::Display, 2, "DELL U2720Q"),
+            content(window, 40, "Q3 layoffs.xlsx - Excel"),
+            content(ContentType::Display, 1, "Built-in Retina Display"),
+            content(window, 7, "Inbox - Mail"),
+        ];
+        replace_titles(&mut available);
+        let titles: Vec<&str> = available.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Display 2", "Window 2", "Display 1", "Window 1"]
+        );
+    }
+}
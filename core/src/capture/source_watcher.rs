@@ -0,0 +1,202 @@
+//! Detection of capture sources being added or removed.
+//!
+//! The watcher thread lists the available displays and windows periodically
+//! and notifies the application when the list changes, e.g. when a monitor
+//! is plugged in or a window is opened, so the content picker can be
+//! refreshed. The platforms don't notify about both with one API, the
+//! watcher reuses the capturers' enumeration instead.
+//!
+//! On linux the content picker has a single entry that opens the system
+//! picker, which lists the sources itself, so there is nothing to watch.
+
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+use std::fmt;
+
+use super::privacy::log_title;
+use crate::event_sender::EventSender;
+use crate::UserEvent;
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+const SOURCE_WATCH_INTERVAL_SECS: u64 = 2;
+
+/// Identifies a capture source, used for detecting changes in the source list.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceId {
+    pub id: u64,
+    /// The display's title, empty for windows
+    pub title: String,
+    pub window: bool,
+}
+
+/* The source lists are logged when they change, the titles only as hashes. */
+impl fmt::Debug for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceId")
+            .field("id", &self.id)
+            .field("title", &log_title(&self.title))
+            .field("window", &self.window)
+            .finish()
+    }
+}
+
+/// Returns `true` if the two source lists don't contain the same sources.
+///
+/// The order of the sources is ignored.
+pub fn sources_changed(previous: &[SourceId], current: &[SourceId]) -> bool {
+    let mut previous = previous.to_vec();
+    let mut current = current.to_vec();
+    previous.sort();
+    current.sort();
+    previous != current
+}
+
+/// Lists the available displays and windows without capturing them.
+///
+/// The capturers are created once, each listing enumerates the sources again.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+struct SourceLister {
+    displays: livekit::webrtc::desktop_capturer::DesktopCapturer,
+    /// `None` when the window capturer couldn't be created, the displays are still watched
+    windows: Option<livekit::webrtc::desktop_capturer::DesktopCapturer>,
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+impl SourceLister {
+    fn new() -> Option<Self> {
+        use livekit::webrtc::desktop_capturer::DesktopCapturer;
+
+        let displays = DesktopCapturer::new(|_, _| {}, false)?;
+        let windows = DesktopCapturer::new(|_, _| {}, true);
+        if windows.is_none() {
+            log::error!("SourceLister::new: failed to create the window capturer");
+        }
+        Some(Self { displays, windows })
+    }
+
+    fn list(&self) -> Vec<SourceId> {
+        let displays = self
+            .displays
+            .get_source_list()
+            .into_iter()
+            .map(|source| SourceId {
+                id: source.id(),
+                title: source.title(),
+                window: false,
+            });
+        /* Window titles follow their content, e.g. a browser's tab, only windows coming and going count. */
+        let windows = self
+            .windows
+            .iter()
+            .flat_map(|capturer| capturer.get_source_list())
+            .map(|source| SourceId {
+                id: source.id(),
+                title: String::new(),
+                window: true,
+            });
+        displays.chain(windows).collect()
+    }
+}
+
+/// Background thread that sends `UserEvent::AvailableContentChanged` when
+/// the capture sources change.
+///
+/// The thread is stopped when the watcher is dropped.
+pub struct SourceWatcher {
+    stop_tx: mpsc::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SourceWatcher {
+    pub fn new(event_sender: Box<dyn EventSender>) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || watch_sources(event_sender, stop_rx));
+        Self {
+            stop_tx,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for SourceWatcher {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("SourceWatcher::drop: watcher thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn watch_sources(_event_sender: Box<dyn EventSender>, _stop_rx: mpsc::Receiver<()>) {
+    log::info!("watch_sources: the system picker lists the sources");
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+fn watch_sources(event_sender: Box<dyn EventSender>, stop_rx: mpsc::Receiver<()>) {
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Duration;
+
+    let Some(lister) = SourceLister::new() else {
+        log::error!("watch_sources: failed to create the display capturer");
+        return;
+    };
+    let mut previous = lister.list();
+
+    while let Err(RecvTimeoutError::Timeout) =
+        stop_rx.recv_timeout(Duration::from_secs(SOURCE_WATCH_INTERVAL_SECS))
+    {
+        let current = lister.list();
+        if !sources_changed(&previous, &current) {
+            continue;
+        }
+
+        log::info!("watch_sources: sources changed {previous:?} -> {current:?}");
+        if let Err(e) = event_sender.send(UserEvent::AvailableContentChanged) {
+            log::error!("watch_sources: error sending available content changed: {e:?}");
+            break;
+        }
+        previous = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(id: u64, title: &str) -> SourceId {
+        SourceId {
+            id,
+            title: title.to_string(),
+            window: false,
+        }
+    }
+
+    fn window(id: u64) -> SourceId {
+        SourceId {
+            id,
+            title: String::new(),
+            window: true,
+        }
+    }
+
+    #[test]
+    fn test_sources_changed() {
+        let previous = vec![display(1, "Built-in"), display(2, "External"), window(7)];
+        let reordered = vec![window(7), display(2, "External"), display(1, "Built-in")];
+        assert!(!sources_changed(&previous, &reordered));
+        assert!(sources_changed(&previous, &previous[..2]));
+        assert!(sources_changed(
+            &previous,
+            &[display(1, "Built-in"), display(3, "External"), window(7)]
+        ));
+        /* Window and display ids are separate, a window with a display's id is another source. */
+        assert!(sources_changed(
+            &previous,
+            &[display(1, "Built-in"), window(2), window(7)]
+        ));
+    }
+}
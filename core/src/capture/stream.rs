@@ -1,19 +1,34 @@
-use crate::utils::geometry::{aspect_fit, Extent, Frame};
+use crate::room::media::{Nv12Frame, SharedVideoSink, VideoFrameRef};
+use crate::utils::geometry::{aspect_fit, stream_layout, Extent, StreamCrop};
 use livekit::webrtc::{
     desktop_capturer::{CaptureResult, DesktopCapturer, DesktopFrame},
-    native::yuv_helper,
-    prelude::{NV12Buffer, VideoBuffer, VideoFrame, VideoRotation},
-    video_source::native::NativeVideoSource,
+    prelude::{NV12Buffer, VideoBuffer},
 };
+use socket_lib::AspectPolicy;
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 use sysinfo::System;
 
-use super::CapturerError;
+use crate::utils::cancellation::CancellationToken;
 
+use super::{
+    color::{ColorConverter, ColorSpace},
+    test_pattern::TestPattern,
+    triple_buffer::TripleBuffer,
+    CapturerError,
+};
+
+/// Interval of the capture timer when the share doesn't request a framerate
 const FRAME_CAPTURE_INTERVAL_MS: u64 = 16;
+/// Highest framerate a share can request, the capture timer can't go faster
+pub const MAX_CAPTURE_FRAMERATE: u32 = 60;
+const TEST_PATTERN_MAX_FPS: u32 = 60;
 
 /// Messages used for inter-thread communication in the stream capture system.
 ///
@@ -27,13 +42,6 @@ pub enum StreamRuntimeMessage {
     /// The main thread will attempt to restart the stream when receiving this message.
     Failed,
 
-    /// Requests that the stream polling thread should terminate.
-    ///
-    /// This message is sent to gracefully shut down the stream monitoring thread
-    /// that checks for runtime messages. Used during application shutdown or
-    /// when stopping the capture system entirely.
-    Stop,
-
     /// Requests that the frame capture thread should stop capturing.
     ///
     /// This message is sent to the worker thread that continuously captures frames
@@ -49,10 +57,29 @@ pub enum StreamRuntimeMessage {
     UserStoppedCapture,
 }
 
+/// Settings of a stream that can be changed until its capture starts.
+///
+/// A stream can be created ahead of time, see `Capturer::warm_up`, and
+/// configured for the selected content when the share starts.
+#[derive(Debug, Clone, Copy)]
+struct StreamSettings {
+    /// The resolution the captured frames are scaled to.
+    resolution: Extent,
+
+    /// How the captured frames are scaled to the resolution.
+    aspect_policy: AspectPolicy,
+
+    /// Color space of the display being captured, used for the NV12 conversion.
+    color_space: ColorSpace,
+}
+
 /// Buffer for holding video frame data in the streaming pipeline.
 struct StreamBuffer {
     /// The video frame containing NV12-formatted pixel data.
-    video_frame: VideoFrame<NV12Buffer>,
+    video_frame: Nv12Frame,
+
+    /// The part of the captured frames the video frame shows.
+    crop: StreamCrop,
 }
 
 impl StreamBuffer {
@@ -66,26 +93,66 @@ impl StreamBuffer {
     /// A new `StreamBuffer` instance with an initialized NV12 buffer of the given
     /// dimensions.
     pub fn new(width: u32, height: u32) -> Self {
-        let video_frame = VideoFrame {
-            rotation: VideoRotation::VideoRotation0,
-            buffer: NV12Buffer::new(width, height),
-            timestamp_us: 0,
-        };
-        StreamBuffer { video_frame }
+        StreamBuffer {
+            video_frame: Nv12Frame::new(width, height),
+            crop: StreamCrop::default(),
+        }
+    }
+}
+
+/// Returns `true` if no frame was published for longer than `timeout`.
+///
+/// `last_frame_at` is `None` when the stream isn't publishing, such a
+/// stream is never considered stalled.
+fn frame_delivery_stalled(last_frame_at: Option<Instant>, now: Instant, timeout: Duration) -> bool {
+    match last_frame_at {
+        Some(last_frame_at) => now.saturating_duration_since(last_frame_at) > timeout,
+        None => false,
     }
 }
 
+/// Luma of the frame published in place of a hidden window, a dark gray
+const PLACEHOLDER_LUMA: u8 = 0x20;
+/// Chroma of the placeholder frame, neutral
+const PLACEHOLDER_CHROMA: u8 = 0x80;
+
+/// Part of the names of the processes that are always excluded, the overlay
+/// window would otherwise show up in the stream.
+const ALWAYS_EXCLUDED: &str = "hopp";
+
+/// Returns `true` if the process is excluded from the capture.
+///
+/// # Parameters
+/// - `pid`, `name`: The process
+/// - `exclusions`: Pids, or parts of process names matched case insensitively,
+///   see `Capturer::set_capture_exclusions`
+fn process_excluded(pid: u64, name: &str, exclusions: &[String]) -> bool {
+    if name.contains(ALWAYS_EXCLUDED) {
+        return true;
+    }
+    let name = name.to_lowercase();
+    exclusions.iter().any(|exclusion| {
+        let exclusion = exclusion.trim();
+        match exclusion.parse::<u64>() {
+            Ok(excluded_pid) => excluded_pid == pid,
+            Err(_) => !exclusion.is_empty() && name.contains(&exclusion.to_lowercase()),
+        }
+    })
+}
+
 /*
  * This function is used to get the pids of the applications that we want to exclude
- * from the capturing.
+ * from the capturing. The pids are looked up every time a desktop capturer is
+ * created, the excluded apps may have been restarted since the last one.
  */
-fn get_excluded_application_pids() -> Vec<u64> {
+fn get_excluded_application_pids(exclusions: &[String]) -> Vec<u64> {
     let system = System::new_all();
     let mut pids = vec![];
     for (pid, process) in system.processes() {
         if let Some(name) = process.name().to_str() {
-            if name.contains("hopp") {
-                pids.push(pid.as_u32() as u64);
+            let pid = pid.as_u32() as u64;
+            if process_excluded(pid, name, exclusions) {
+                pids.push(pid);
             }
         }
     }
@@ -93,15 +160,256 @@ fn get_excluded_application_pids() -> Vec<u64> {
     pids
 }
 
+/// The buffer source the frames are pushed to.
+///
+/// The producer keeps a clone of it and only locks `source` when
+/// `generation` changed, i.e. a new buffer source was set.
+#[derive(Default)]
+struct SharedSource {
+    source: Mutex<Option<SharedVideoSink>>,
+    /// Bumped every time `source` is set
+    generation: AtomicU64,
+}
+
+impl SharedSource {
+    fn set(&self, source: SharedVideoSink) {
+        *self.source.lock().unwrap() = Some(source);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    fn is_some(&self) -> bool {
+        self.source.lock().unwrap().is_some()
+    }
+
+    /// Updates the producer's clone if the source was set since it was taken.
+    fn refresh(&self, cached: &mut (u64, Option<SharedVideoSink>)) {
+        let generation = self.generation.load(Ordering::Acquire);
+        if cached.0 != generation {
+            *cached = (generation, self.source.lock().unwrap().clone());
+        }
+    }
+}
+
+/// Wakes the readers waiting for the first frame of a stream.
+///
+/// The producer only checks `published` on the following frames, the mutex
+/// is taken once per configuration of the stream.
+#[derive(Default)]
+struct FirstFrame {
+    published: AtomicBool,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl FirstFrame {
+    fn notify(&self) {
+        if self.published.load(Ordering::Acquire) {
+            return;
+        }
+        let _guard = self.lock.lock().unwrap();
+        self.published.store(true, Ordering::Release);
+        self.condvar.notify_all();
+    }
+
+    fn reset(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.published.store(false, Ordering::Release);
+    }
+
+    /// Returns `true` once the first frame was published, `false` if it
+    /// wasn't within `timeout`.
+    fn wait(&self, timeout: Duration) -> bool {
+        let guard = self.lock.lock().unwrap();
+        let _guard = self
+            .condvar
+            .wait_timeout_while(guard, timeout, |_| !self.published.load(Ordering::Acquire))
+            .unwrap();
+        self.published.load(Ordering::Acquire)
+    }
+}
+
+/// Waits for the first frame of a stream without borrowing the stream.
+///
+/// Taken out of the capturer, the frame is waited for without holding the
+/// capturer's lock, which the other capture commands need meanwhile.
+#[derive(Clone)]
+pub struct FirstFrameWaiter {
+    first_frame: Arc<FirstFrame>,
+    frames: Arc<TripleBuffer<StreamBuffer>>,
+}
+
+impl FirstFrameWaiter {
+    /// Waits for the first frame after the stream was created or configured.
+    ///
+    /// # Parameters
+    /// - `timeout`: How long to wait for it
+    ///
+    /// # Returns
+    /// `true` once the frame is published, `stream_extent` has its size
+    /// then, `false` if it didn't arrive within `timeout`.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        self.first_frame.wait(timeout)
+    }
+
+    /// See `Stream::get_stream_extent`.
+    pub fn stream_extent(&self) -> Extent {
+        self.frames.read(|stream_buffer| Extent {
+            width: stream_buffer.video_frame.width() as f64,
+            height: stream_buffer.video_frame.height() as f64,
+        })
+    }
+
+    /// See `Stream::get_stream_crop`.
+    pub fn stream_crop(&self) -> StreamCrop {
+        self.frames.read(|stream_buffer| stream_buffer.crop)
+    }
+}
+
+/// What the producer of a stream's frames, the capture callback or the
+/// test pattern, keeps between frames.
+///
+/// Only the producer locks it while capturing, `Stream::preallocate` does
+/// before the capture starts.
+struct ProducerState {
+    /// Buffer holding the captured frame converted to NV12, before scaling,
+    /// frames streamed at their size are converted straight into the stream buffer
+    capture_buffer: NV12Buffer,
+    /// The converter for the color space it was created for
+    color_converter: (ColorSpace, ColorConverter),
+    /// The buffer source and the `SharedSource::generation` it was taken at
+    buffer_source: (u64, Option<SharedVideoSink>),
+    /// Size of the published frames, for logging layout changes
+    streamed: (u32, u32),
+}
+
+impl ProducerState {
+    fn new(color_space: ColorSpace) -> Self {
+        Self {
+            capture_buffer: NV12Buffer::new(0, 0),
+            color_converter: (color_space, ColorConverter::new(color_space)),
+            buffer_source: (u64::MAX, None),
+            streamed: (0, 0),
+        }
+    }
+}
+
+/// Converts frames to NV12, scales them to the stream resolution and pushes
+/// them to the buffer source, whether they were captured or generated.
+///
+/// The scaled frames are published to a triple buffer, the readers of the
+/// stream never hold a lock the producer waits for. Its slots are the pool
+/// of stream buffers, the frame handed to the buffer source is the slot
+/// itself and no frame is copied between the buffers:
+/// - A frame streamed at its size is converted straight into the slot.
+/// - A scaled frame is converted into the capture buffer, libwebrtc's
+///   scaling allocates the scaled buffer and it replaces the slot's.
+struct FrameProcessor {
+    buffer_source: Arc<SharedSource>,
+    settings: Arc<Mutex<StreamSettings>>,
+    producer: Arc<Mutex<ProducerState>>,
+    frames: Arc<TripleBuffer<StreamBuffer>>,
+    first_frame: Arc<FirstFrame>,
+    last_frame_at: Arc<Mutex<Option<Instant>>>,
+    publishing_paused: Arc<AtomicBool>,
+}
+
+impl FrameProcessor {
+    /// Pushes a BGRA frame through the pipeline.
+    ///
+    /// # Parameters
+    /// - `frame_data`: The BGRA pixels of the frame
+    /// - `frame_stride`: Bytes per row of `frame_data`
+    /// - `frame_width` / `frame_height`: The frame dimensions in pixels
+    fn process(&self, frame_data: &[u8], frame_stride: u32, frame_width: i32, frame_height: i32) {
+        if frame_width == 0 || frame_height == 0 {
+            log::warn!("Capture frame frame dims zero {frame_width}x{frame_height}");
+            return;
+        }
+        log::trace!(
+            "capture_callback: Frame: {frame_width}x{frame_height}, stride: {frame_stride}",
+        );
+
+        let settings = *self.settings.lock().unwrap();
+        let mut producer = self.producer.lock().unwrap();
+        let producer = &mut *producer;
+        if producer.color_converter.0 != settings.color_space {
+            producer.color_converter = (
+                settings.color_space,
+                ColorConverter::new(settings.color_space),
+            );
+        }
+
+        let layout = stream_layout(
+            settings.aspect_policy,
+            frame_width as u32,
+            frame_height as u32,
+            settings.resolution.width as u32,
+            settings.resolution.height as u32,
+        );
+        let crop = layout.crop;
+
+        /* The crop starts at its top left pixel, the rows keep the frame's stride. */
+        let crop_offset = (crop.y * frame_stride + crop.x * 4) as usize;
+        let cropped_data = &frame_data[crop_offset..];
+        let color_converter = &producer.color_converter.1;
+
+        let mut framebuffer_resized = false;
+        let scaled_buffer = if crop.width != layout.width || crop.height != layout.height {
+            let framebuffer = &mut producer.capture_buffer;
+            if framebuffer.width() != crop.width || framebuffer.height() != crop.height {
+                *framebuffer = NV12Buffer::new(crop.width, crop.height);
+                framebuffer_resized = true;
+            }
+            color_converter.argb_to_nv12(cropped_data, frame_stride, framebuffer);
+            Some(framebuffer.scale(layout.width as i32, layout.height as i32))
+        } else {
+            None
+        };
+        if framebuffer_resized || producer.streamed != (layout.width, layout.height) {
+            log::info!(
+                "process: {frame_width}x{frame_height} streamed as {}x{} {:?} crop {crop:?}",
+                layout.width,
+                layout.height,
+                settings.aspect_policy
+            );
+            producer.streamed = (layout.width, layout.height);
+        }
+
+        self.buffer_source.refresh(&mut producer.buffer_source);
+        let buffer_source = producer.buffer_source.1.as_ref();
+        let stream_crop = crop.normalized(frame_width as u32, frame_height as u32);
+        let publishing_paused = self.publishing_paused.load(Ordering::Relaxed);
+        self.frames.publish(|stream_buffer| {
+            let buffer = stream_buffer.video_frame.buffer_mut();
+            match scaled_buffer {
+                Some(scaled_buffer) => *buffer = scaled_buffer,
+                None => {
+                    /* The slots are empty after the stream was (re)configured. */
+                    if buffer.width() != layout.width || buffer.height() != layout.height {
+                        *buffer = NV12Buffer::new(layout.width, layout.height);
+                    }
+                    color_converter.argb_to_nv12(cropped_data, frame_stride, buffer);
+                }
+            }
+            stream_buffer.crop = stream_crop;
+
+            /* Without viewers the frame is kept for the thumbnails, nothing is encoded. */
+            if let Some(buffer_source) = buffer_source.filter(|_| !publishing_paused) {
+                buffer_source.push_frame(VideoFrameRef::Nv12(&stream_buffer.video_frame));
+            }
+        });
+        self.first_frame.notify();
+        if buffer_source.is_some() {
+            *self.last_frame_at.lock().unwrap() = Some(Instant::now());
+        }
+    }
+}
+
 fn create_capture_callback(
-    buffer_source: Arc<Mutex<Option<NativeVideoSource>>>,
-    resolution: Extent,
-    stream_buffer: Arc<Mutex<StreamBuffer>>,
-    desktop_frame: Arc<Mutex<Frame>>,
+    processor: FrameProcessor,
     tx: mpsc::Sender<StreamRuntimeMessage>,
     failures_count: Arc<Mutex<u64>>,
 ) -> impl Fn(CaptureResult, DesktopFrame) {
-    let capture_buffer = Arc::new(Mutex::new(NV12Buffer::new(0, 0)));
     move |result: CaptureResult, frame: DesktopFrame| {
         match result {
             CaptureResult::ErrorTemporary => {
@@ -131,102 +439,27 @@ fn create_capture_callback(
                 *failures_count = 0;
             }
         }
-        let frame_height = frame.height();
-        let frame_width = frame.width();
-        let frame_stride = frame.stride();
-        if frame_width == 0 || frame_height == 0 {
-            log::warn!("Capture frame frame dims zero {frame_width}x{frame_height}");
-            return;
-        }
-        let frame_top = frame.top();
-        let frame_left = frame.left();
-        let frame_data = frame.data();
-        log::trace!(
-            "capture_callback: Frame: {frame_width}x{frame_height}, stride: {frame_stride}",
-        );
-
-        {
-            let mut frame = desktop_frame.lock().unwrap();
-            if (frame_top != (frame.origin_y as i32))
-                || (frame_left != (frame.origin_x as i32))
-                || (frame_width != (frame.extent.width as i32))
-                || (frame_height != (frame.extent.height as i32))
-            {
-                frame.extent.width = frame_width as f64;
-                frame.extent.height = frame_height as f64;
-                frame.origin_x = frame_left as f64;
-                frame.origin_y = frame_top as f64;
-            }
-        }
-
-        // Copy DesktopFrame to framebuffer
-        let mut framebuffer = capture_buffer.lock().unwrap();
-        let framebuffer_width = framebuffer.width();
-        let framebuffer_height = framebuffer.height();
-        if (framebuffer_width != (frame_width as u32))
-            || (framebuffer_height != (frame_height as u32))
-        {
-            *framebuffer = NV12Buffer::new(frame_width as u32, frame_height as u32);
-            let (stream_width, stream_height) = aspect_fit(
-                frame_width as u32,
-                frame_height as u32,
-                resolution.width as u32,
-                resolution.height as u32,
-            );
-            let mut stream_buffer = stream_buffer.lock().unwrap();
-            *stream_buffer = StreamBuffer::new(stream_width, stream_height);
-        }
-
-        let (stride_y, stride_uv) = framebuffer.strides();
-        let (data_y, data_uv) = framebuffer.data_mut();
-        yuv_helper::argb_to_nv12(
-            frame_data,
-            frame_stride,
-            data_y,
-            stride_y,
-            data_uv,
-            stride_uv,
-            frame_width,
-            frame_height,
-        );
-
-        // Scale framebuffer to stream resolution
-        let mut stream_buffer = stream_buffer.lock().unwrap();
-        let stream_width = stream_buffer.video_frame.buffer.width();
-        let stream_height = stream_buffer.video_frame.buffer.height();
-        let mut scaled_buffer = framebuffer.scale(stream_width as i32, stream_height as i32);
-        drop(framebuffer);
-
-        // Copy scaled buffer to stream buffer
-        let (data_y, data_uv) = scaled_buffer.data_mut();
-        let (dst_y, dst_uv) = stream_buffer.video_frame.buffer.data_mut();
-        dst_y.copy_from_slice(data_y);
-        dst_uv.copy_from_slice(data_uv);
-
-        let buffer_source = buffer_source.lock().unwrap();
-        if buffer_source.is_some() {
-            buffer_source
-                .as_ref()
-                .unwrap()
-                .capture_frame(&stream_buffer.video_frame);
-        }
+        processor.process(frame.data(), frame.stride(), frame.width(), frame.height());
     }
 }
 
+/// Calls `capture_frame` every capture interval until the stream is stopped
+/// or the application is cancelled.
 fn run_capture_frame(
     rx: mpsc::Receiver<StreamRuntimeMessage>,
-    capturer: Arc<Mutex<DesktopCapturer>>,
+    capture_interval_ms: Arc<AtomicU64>,
+    cancel: CancellationToken,
+    mut capture_frame: impl FnMut(),
 ) {
-    loop {
-        match rx.recv_timeout(std::time::Duration::from_millis(FRAME_CAPTURE_INTERVAL_MS)) {
+    while !cancel.is_cancelled() {
+        /* Read on every frame, a framerate change applies without restarting the capture. */
+        let interval = Duration::from_millis(capture_interval_ms.load(Ordering::Relaxed));
+        match rx.recv_timeout(interval) {
             Ok(StreamRuntimeMessage::StopCapture) => {
                 break;
             }
             Err(e) => match e {
-                mpsc::RecvTimeoutError::Timeout => {
-                    let mut capturer = capturer.lock().unwrap();
-                    capturer.capture_frame();
-                }
+                mpsc::RecvTimeoutError::Timeout => capture_frame(),
                 mpsc::RecvTimeoutError::Disconnected => {
                     log::error!("run_capture_frame: Disconnected");
                     break;
@@ -237,6 +470,84 @@ fn run_capture_frame(
     }
 }
 
+/// Returns the interval of the capture timer for a requested framerate.
+///
+/// The framerate is clamped to 1..=MAX_CAPTURE_FRAMERATE, `None` keeps
+/// FRAME_CAPTURE_INTERVAL_MS.
+fn capture_interval_ms(framerate: Option<u32>) -> u64 {
+    match framerate {
+        Some(framerate) => 1000 / framerate.clamp(1, MAX_CAPTURE_FRAMERATE) as u64,
+        None => FRAME_CAPTURE_INTERVAL_MS,
+    }
+}
+
+/// Rounds `resolution` down to the even dimensions the NV12 conversion of a
+/// test pattern needs.
+fn even_extent(resolution: Extent) -> Extent {
+    Extent::new(
+        ((resolution.width as u32) & !1).max(2) as f64,
+        ((resolution.height as u32) & !1).max(2) as f64,
+    )
+}
+
+/// Pushes test pattern frames of `resolution` through `processor` at `fps`
+/// until the stream is stopped.
+fn run_test_pattern(
+    rx: mpsc::Receiver<StreamRuntimeMessage>,
+    processor: FrameProcessor,
+    resolution: Extent,
+    fps: u32,
+    cancel: CancellationToken,
+) {
+    let interval = Duration::from_secs(1) / fps;
+    let mut pattern = TestPattern::new(resolution.width as u32, resolution.height as u32);
+    let start = Instant::now();
+    let mut frame = 0;
+    while !cancel.is_cancelled() {
+        match rx.recv_timeout(interval) {
+            Ok(StreamRuntimeMessage::StopCapture) => {
+                break;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let (stride, width, height) = (pattern.stride(), pattern.width(), pattern.height());
+                let pixels = pattern.render(frame, start.elapsed());
+                processor.process(pixels, stride, width as i32, height as i32);
+                frame += 1;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                log::error!("run_test_pattern: Disconnected");
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// What a stream created by `Stream::new` captures and how its frames are scaled.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamOptions {
+    /// The resolution of the stream buffer
+    pub resolution: Extent,
+    /// How the captured frames are scaled to the resolution
+    pub aspect_policy: AspectPolicy,
+    /// Color space of the display being captured
+    pub color_space: ColorSpace,
+    /// Whether the stream captures a window, its ID is then passed to `start_capture`
+    pub window: bool,
+}
+
+/// Where the frames of a stream come from.
+enum StreamSource {
+    /// The platform's desktop capturer.
+    ///
+    /// Wrapped in Arc<Mutex<>> to allow safe sharing between the main thread and
+    /// the capture worker thread that continuously captures frames.
+    Desktop(Arc<Mutex<DesktopCapturer>>),
+
+    /// A generated test pattern, nothing is captured.
+    TestPattern { fps: u32 },
+}
+
 /// Manages a single screen capture stream and its associated resources.
 ///
 /// This struct encapsulates all the components needed to capture screen content
@@ -244,11 +555,9 @@ fn run_capture_frame(
 /// suitable for WebRTC transmission. It handles the capture thread lifecycle,
 /// frame buffering, format conversion, and error recovery.
 pub struct Stream {
-    /// The platform-specific desktop capturer that interfaces with the OS screen capture APIs.
-    ///
-    /// Wrapped in Arc<Mutex<>> to allow safe sharing between the main thread and
-    /// the capture worker thread that continuously captures frames.
-    capturer: Arc<Mutex<DesktopCapturer>>,
+    /// The platform-specific desktop capturer that interfaces with the OS screen
+    /// capture APIs, or the test pattern replacing it.
+    source: StreamSource,
 
     /// Handle to the background thread that performs continuous frame capturing.
     ///
@@ -268,92 +577,279 @@ pub struct Stream {
     /// or complete capture system shutdown.
     permanent_error_tx: mpsc::Sender<StreamRuntimeMessage>,
 
-    /// Buffer containing the final video frame data for streaming.
+    /// The latest video frames for streaming.
     ///
-    /// This buffer holds frames that have been processed, scaled, and converted
-    /// to the target stream resolution for WebRTC transmission.
-    stream_buffer: Arc<Mutex<StreamBuffer>>,
+    /// They have been processed, scaled, and converted to the target stream
+    /// resolution for WebRTC transmission. Published by the producer to a
+    /// triple buffer, the extent polling and the thumbnails read the latest.
+    frames: Arc<TripleBuffer<StreamBuffer>>,
+
+    /// Signaled when `frames` gets its first frame after a (re)configuration.
+    first_frame: Arc<FirstFrame>,
 
     /// Buffer source for the stream.
-    buffer_source: Arc<Mutex<Option<NativeVideoSource>>>,
+    buffer_source: Arc<SharedSource>,
 
-    /// Metadata about the current capture frame dimensions and position.
+    /// The capture buffer and color converter of the producer.
     ///
-    /// Tracks the actual captured area size and position, which may change
-    /// if the source window is resized or moved.
-    frame: Arc<Mutex<Frame>>,
+    /// The capture buffer is reallocated when the captured frame's size
+    /// changes, it can be preallocated with `preallocate` to skip this on
+    /// the first frame. Only scaled frames go through it.
+    producer: Arc<Mutex<ProducerState>>,
 
-    /// The resolution of the stream buffer.
-    stream_resolution: Extent,
+    /// The resolution and color space, shared with the capture callback.
+    settings: Arc<Mutex<StreamSettings>>,
 
     /// Identifier of the capture source (display or window ID).
     source_id: u32,
 
+    /// Whether the desktop capturer captures a window instead of a display.
+    ///
+    /// A window capturer lists windows as its sources and delivers frames
+    /// of the window's bounds only.
+    window: bool,
+
     /// Counter tracking consecutive stream failures for health monitoring.
     ///
     /// Incremented on capture failures and reset on successful captures.
-    /// When this reaches MAX_STREAM_FAILURES_BEFORE_EXIT, the process exits
-    /// to trigger application restart.
+    /// When this reaches MAX_STREAM_FAILURES, the capturer gives up on the
+    /// stream and the session is ended.
     failures_count: Arc<Mutex<u64>>,
+
+    /// When the last frame was pushed to the buffer source.
+    ///
+    /// `None` while the stream isn't publishing, used by the watchdog in
+    /// `poll_stream` for detecting captures that stopped without an error.
+    last_frame_at: Arc<Mutex<Option<Instant>>>,
+
+    /// Whether the captured frames are kept from the buffer source.
+    ///
+    /// Set while no participant watches the share, the capture keeps
+    /// running so publishing resumes with the next frame.
+    publishing_paused: Arc<AtomicBool>,
+
+    /// The applications excluded from the capture, shared with the capturer.
+    ///
+    /// Read again when the desktop capturer is recreated in `copy`, so a
+    /// restarted stream picks up the latest exclusions.
+    exclusions: Arc<Mutex<Vec<String>>>,
+
+    /// Interval of the capture timer in milliseconds, see `set_framerate`.
+    ///
+    /// Shared with the capture thread and kept by `copy`, so a restarted
+    /// stream captures at the same rate.
+    capture_interval_ms: Arc<AtomicU64>,
+
+    /// Cancellation of the application, stops the capture thread.
+    cancel: CancellationToken,
 }
 
 impl Stream {
     /// Creates a new stream instance for capturing from a screen source.
     ///
     /// # Parameters
-    /// - `stream_resolution`: The resolution of the stream buffer
-    /// - `_scale`: Display scale factor (currently unused but reserved for future scaling)
+    /// - `options`: What the stream captures and how its frames are scaled
+    /// - `exclusions`: The applications excluded from the capture
     /// - `tx`: Channel sender for communicating runtime messages back to the main capturer
+    /// - `cancel`: Cancellation of the application
     ///
     /// # Returns
     /// - `Ok(Stream)`: Successfully created stream ready for capture
     /// - `Err(CapturerError::DesktopCapturerCreationError)`: Failed to initialize the underlying capture system
     pub fn new(
-        stream_resolution: Extent,
-        _scale: f64,
+        options: StreamOptions,
+        exclusions: Arc<Mutex<Vec<String>>>,
         tx: mpsc::Sender<StreamRuntimeMessage>,
+        cancel: CancellationToken,
     ) -> Result<Self, CapturerError> {
-        let buffer_source = Arc::new(Mutex::new(None));
-        let stream_buffer = Arc::new(Mutex::new(StreamBuffer::new(0, 0)));
-        let frame = Arc::new(Mutex::new(Frame {
-            origin_x: 0.,
-            origin_y: 0.,
-            extent: Extent {
-                width: 0.,
-                height: 0.,
-            },
+        let StreamOptions {
+            resolution,
+            aspect_policy,
+            color_space,
+            window,
+        } = options;
+        let buffer_source = Arc::new(SharedSource::default());
+        let settings = Arc::new(Mutex::new(StreamSettings {
+            resolution,
+            aspect_policy,
+            color_space,
         }));
+        let producer = Arc::new(Mutex::new(ProducerState::new(color_space)));
+        let frames = Arc::new(TripleBuffer::new(|| StreamBuffer::new(0, 0)));
+        let first_frame = Arc::new(FirstFrame::default());
         let failures_count = Arc::new(Mutex::new(0));
+        let last_frame_at = Arc::new(Mutex::new(None));
+        let publishing_paused = Arc::new(AtomicBool::new(false));
 
-        let callback = create_capture_callback(
-            buffer_source.clone(),
-            stream_resolution,
-            stream_buffer.clone(),
-            frame.clone(),
-            tx.clone(),
-            failures_count.clone(),
-        );
-        let capturer = DesktopCapturer::new(callback, false);
+        let processor = FrameProcessor {
+            buffer_source: buffer_source.clone(),
+            settings: settings.clone(),
+            producer: producer.clone(),
+            frames: frames.clone(),
+            first_frame: first_frame.clone(),
+            last_frame_at: last_frame_at.clone(),
+            publishing_paused: publishing_paused.clone(),
+        };
+        let callback = create_capture_callback(processor, tx.clone(), failures_count.clone());
+        let capturer = DesktopCapturer::new(callback, window);
         if capturer.is_none() {
             return Err(CapturerError::DesktopCapturerCreationError);
         }
         let capturer = capturer.unwrap();
-        let apps_to_exclude = get_excluded_application_pids();
+        let apps_to_exclude = get_excluded_application_pids(&exclusions.lock().unwrap());
         capturer.set_excluded_applications(apps_to_exclude);
         Ok(Stream {
-            capturer: Arc::new(Mutex::new(capturer)),
+            source: StreamSource::Desktop(Arc::new(Mutex::new(capturer))),
             capture_frame_handle: None,
             tx: None,
             permanent_error_tx: tx,
-            stream_buffer,
+            frames,
+            first_frame,
             buffer_source,
-            frame,
-            stream_resolution,
+            producer,
+            settings,
             source_id: 0,
+            window,
             failures_count,
+            last_frame_at,
+            publishing_paused,
+            exclusions,
+            capture_interval_ms: Arc::new(AtomicU64::new(FRAME_CAPTURE_INTERVAL_MS)),
+            cancel,
         })
     }
 
+    /// Creates a stream publishing a generated test pattern instead of a capture.
+    ///
+    /// # Parameters
+    /// - `resolution`: The size of the pattern frames, rounded down to even
+    ///   dimensions for the NV12 conversion
+    /// - `fps`: Frames generated per second, clamped to 1..=TEST_PATTERN_MAX_FPS
+    /// - `tx`: Channel sender for communicating runtime messages back to the main capturer
+    /// - `cancel`: Cancellation of the application
+    ///
+    /// # Notes
+    /// No desktop capturer is created, so this works without capture permissions.
+    pub fn test_pattern(
+        resolution: Extent,
+        fps: u32,
+        tx: mpsc::Sender<StreamRuntimeMessage>,
+        cancel: CancellationToken,
+    ) -> Self {
+        let resolution = even_extent(resolution);
+        Stream {
+            source: StreamSource::TestPattern {
+                fps: fps.clamp(1, TEST_PATTERN_MAX_FPS),
+            },
+            capture_frame_handle: None,
+            tx: None,
+            permanent_error_tx: tx,
+            frames: Arc::new(TripleBuffer::new(|| StreamBuffer::new(0, 0))),
+            first_frame: Arc::new(FirstFrame::default()),
+            buffer_source: Arc::new(SharedSource::default()),
+            producer: Arc::new(Mutex::new(ProducerState::new(ColorSpace::Srgb))),
+            settings: Arc::new(Mutex::new(StreamSettings {
+                resolution,
+                aspect_policy: AspectPolicy::Fit,
+                color_space: ColorSpace::Srgb,
+            })),
+            source_id: 0,
+            window: false,
+            failures_count: Arc::new(Mutex::new(0)),
+            last_frame_at: Arc::new(Mutex::new(None)),
+            publishing_paused: Arc::new(AtomicBool::new(false)),
+            exclusions: Arc::default(),
+            capture_interval_ms: Arc::new(AtomicU64::new(FRAME_CAPTURE_INTERVAL_MS)),
+            cancel,
+        }
+    }
+
+    /// Returns a processor pushing frames through this stream's buffers.
+    fn frame_processor(&self) -> FrameProcessor {
+        FrameProcessor {
+            buffer_source: self.buffer_source.clone(),
+            settings: self.settings.clone(),
+            producer: self.producer.clone(),
+            frames: self.frames.clone(),
+            first_frame: self.first_frame.clone(),
+            last_frame_at: self.last_frame_at.clone(),
+            publishing_paused: self.publishing_paused.clone(),
+        }
+    }
+
+    /// Changes the resolution and color space of a stream that isn't capturing.
+    ///
+    /// # Parameters
+    /// - `stream_resolution`: The resolution of the stream buffer
+    /// - `aspect_policy`: How the captured frames are scaled to the resolution
+    /// - `color_space`: Color space of the display that will be captured
+    ///
+    /// # Notes
+    /// The stream frames are cleared, they are sized again from the first
+    /// captured frame so `get_stream_extent` reflects the new settings.
+    pub fn configure(
+        &mut self,
+        stream_resolution: Extent,
+        aspect_policy: AspectPolicy,
+        color_space: ColorSpace,
+    ) {
+        if self.tx.is_some() {
+            log::warn!("Stream::configure: Stream is running, ignoring");
+            return;
+        }
+        *self.settings.lock().unwrap() = StreamSettings {
+            resolution: stream_resolution,
+            aspect_policy,
+            color_space,
+        };
+        self.frames.reset(|| StreamBuffer::new(0, 0));
+        self.first_frame.reset();
+    }
+
+    /// Changes the resolution of a stream that isn't capturing, keeping its
+    /// aspect policy and color space.
+    ///
+    /// # Parameters
+    /// - `stream_resolution`: The new resolution of the stream buffer, a test
+    ///   pattern rounds it down to even dimensions
+    ///
+    /// # Notes
+    /// Used for switching the resolution mid-session, the capture is restarted
+    /// with `start_capture` and the buffer source is kept.
+    pub fn set_resolution(&mut self, stream_resolution: Extent) {
+        let settings = *self.settings.lock().unwrap();
+        let stream_resolution = match self.source {
+            StreamSource::TestPattern { .. } => even_extent(stream_resolution),
+            StreamSource::Desktop(_) => stream_resolution,
+        };
+        log::info!(
+            "Stream::set_resolution: {:?} -> {stream_resolution:?}",
+            settings.resolution
+        );
+        self.configure(
+            stream_resolution,
+            settings.aspect_policy,
+            settings.color_space,
+        );
+    }
+
+    /// Allocates the capture buffer for frames of `frame_extent`.
+    ///
+    /// # Parameters
+    /// - `frame_extent`: The expected size of the captured frames in pixels
+    ///
+    /// # Notes
+    /// Used when warming up a stream, if the first frame has a different
+    /// size the buffer is reallocated as usual.
+    pub fn preallocate(&mut self, frame_extent: Extent) {
+        let (width, height) = (frame_extent.width as u32, frame_extent.height as u32);
+        if width == 0 || height == 0 {
+            return;
+        }
+        log::info!("Stream::preallocate: {width}x{height}");
+        self.producer.lock().unwrap().capture_buffer = NV12Buffer::new(width, height);
+    }
+
     /// Starts capturing frames from the specified source.
     ///
     /// # Parameters
@@ -363,32 +859,56 @@ impl Stream {
     /// - Finds the capture source matching the provided ID from available sources
     /// - Falls back to the first available source if the specified ID is not found
     /// - Spawns a background worker thread that continuously captures frames
-    /// - Begins the frame capture loop at FRAME_CAPTURE_INTERVAL_MS intervals
+    /// - Begins the frame capture loop at the interval of the framerate, see `set_framerate`
+    /// - A test pattern stream ignores the ID and generates frames at its fps instead
     ///
     /// # Notes
     /// This method should only be called when the stream is not already capturing.
     /// The capture thread will run until `stop_capture()` is called.
     pub fn start_capture(&mut self, id: u32) {
         log::info!("stream::start_capture: Starting capture for id: {id}");
-        let mut capturer = self.capturer.lock().unwrap();
-        let sources = capturer.get_source_list();
-        let mut source = sources[0].clone();
-        for s in sources {
-            if s.id() == (id as u64) {
-                source = s;
-                break;
-            }
-        }
-        if source.id() != (id as u64) {
-            log::warn!("start_capture: Source not found, capturing first source");
-        }
         self.source_id = id;
-        capturer.start_capture(source);
         let (tx, rx) = mpsc::channel();
-        let capturer_clone = self.capturer.clone();
-        self.capture_frame_handle = Some(std::thread::spawn(move || {
-            run_capture_frame(rx, capturer_clone);
-        }));
+        let handle = match &self.source {
+            StreamSource::Desktop(capturer) => {
+                let mut capturer_guard = capturer.lock().unwrap();
+                let sources = capturer_guard.get_source_list();
+                let mut source = sources[0].clone();
+                for s in sources {
+                    if s.id() == (id as u64) {
+                        source = s;
+                        break;
+                    }
+                }
+                if source.id() != (id as u64) {
+                    log::warn!("start_capture: Source not found, capturing first source");
+                }
+                capturer_guard.start_capture(source);
+                drop(capturer_guard);
+                let capturer_clone = capturer.clone();
+                let capture_interval_ms = self.capture_interval_ms.clone();
+                let cancel = self.cancel.clone();
+                std::thread::spawn(move || {
+                    run_capture_frame(rx, capture_interval_ms, cancel, move || {
+                        capturer_clone.lock().unwrap().capture_frame();
+                    });
+                })
+            }
+            StreamSource::TestPattern { fps } => {
+                let fps = *fps;
+                let processor = self.frame_processor();
+                let resolution = self.settings.lock().unwrap().resolution;
+                let cancel = self.cancel.clone();
+                std::thread::spawn(move || {
+                    run_test_pattern(rx, processor, resolution, fps, cancel);
+                })
+            }
+        };
+        /* Give the new capture time to deliver its first frame before the watchdog checks it. */
+        if self.buffer_source.is_some() {
+            *self.last_frame_at.lock().unwrap() = Some(Instant::now());
+        }
+        self.capture_frame_handle = Some(handle);
         self.tx = Some(tx);
     }
 
@@ -414,6 +934,7 @@ impl Stream {
             .unwrap()
             .send(StreamRuntimeMessage::StopCapture);
         self.tx.take();
+        *self.last_frame_at.lock().unwrap() = None;
         let handle = self.capture_frame_handle.take();
         if let Some(handle) = handle {
             let res = handle.join();
@@ -432,7 +953,7 @@ impl Stream {
     /// # Behavior
     /// - Stops the current stream if it's running
     /// - Creates a new desktop capturer with the same configuration
-    /// - Shares the same buffers (stream_buffer, capture_buffer, frame) for memory efficiency
+    /// - Shares the same buffers (frames, producer state) for memory efficiency
     /// - Preserves the source_id and failure count from the original stream
     /// - Sets up the same error reporting channel
     ///
@@ -446,34 +967,45 @@ impl Stream {
             self.stop_capture();
         }
 
-        let callback = create_capture_callback(
-            self.buffer_source.clone(),
-            self.stream_resolution,
-            self.stream_buffer.clone(),
-            self.frame.clone(),
-            self.permanent_error_tx.clone(),
-            self.failures_count.clone(),
-        );
-        let capturer = DesktopCapturer::new(callback, false);
-        if capturer.is_none() {
-            log::error!("Stream::copy: Failed to create DesktopCapturer");
-            return Err(());
-        }
-        let capturer = capturer.unwrap();
-        let apps_to_exclude = get_excluded_application_pids();
-        capturer.set_excluded_applications(apps_to_exclude);
+        let source = match &self.source {
+            StreamSource::Desktop(_) => {
+                let callback = create_capture_callback(
+                    self.frame_processor(),
+                    self.permanent_error_tx.clone(),
+                    self.failures_count.clone(),
+                );
+                let capturer = DesktopCapturer::new(callback, self.window);
+                if capturer.is_none() {
+                    log::error!("Stream::copy: Failed to create DesktopCapturer");
+                    return Err(());
+                }
+                let capturer = capturer.unwrap();
+                let apps_to_exclude =
+                    get_excluded_application_pids(&self.exclusions.lock().unwrap());
+                capturer.set_excluded_applications(apps_to_exclude);
+                StreamSource::Desktop(Arc::new(Mutex::new(capturer)))
+            }
+            StreamSource::TestPattern { fps } => StreamSource::TestPattern { fps: *fps },
+        };
 
         let new_stream = Stream {
-            capturer: Arc::new(Mutex::new(capturer)),
+            source,
             capture_frame_handle: None,
             tx: None,
             permanent_error_tx: self.permanent_error_tx.clone(),
-            stream_buffer: self.stream_buffer.clone(),
+            frames: self.frames.clone(),
+            first_frame: self.first_frame.clone(),
             buffer_source: self.buffer_source.clone(),
-            frame: self.frame.clone(),
-            stream_resolution: self.stream_resolution,
+            producer: self.producer.clone(),
+            settings: self.settings.clone(),
             source_id: self.source_id,
+            window: self.window,
             failures_count: self.failures_count.clone(),
+            last_frame_at: self.last_frame_at.clone(),
+            publishing_paused: self.publishing_paused.clone(),
+            exclusions: self.exclusions.clone(),
+            capture_interval_ms: self.capture_interval_ms.clone(),
+            cancel: self.cancel.clone(),
         };
 
         Ok(new_stream)
@@ -487,13 +1019,84 @@ impl Stream {
     ///
     /// # Use Cases
     /// Used for health monitoring and determining when the stream should be
-    /// restarted or when the process should exit due to persistent failures.
-    /// When this count reaches MAX_STREAM_FAILURES_BEFORE_EXIT, the application
-    /// will terminate to trigger a restart.
+    /// restarted or when it should be abandoned due to persistent failures.
+    /// When this count reaches MAX_STREAM_FAILURES, the session is ended and
+    /// the tauri app is notified with `FatalCaptureError`.
     pub fn get_failures_count(&self) -> u64 {
         *self.failures_count.lock().unwrap()
     }
 
+    /// Counts a failure that wasn't reported by the capturer, e.g. a stall.
+    pub fn record_failure(&self) {
+        *self.failures_count.lock().unwrap() += 1;
+    }
+
+    /// Returns `true` if the stream is publishing but no frame arrived for `timeout`.
+    pub fn is_stalled(&self, timeout: Duration) -> bool {
+        frame_delivery_stalled(*self.last_frame_at.lock().unwrap(), Instant::now(), timeout)
+    }
+
+    /// Returns `true` if the stream captures a window.
+    pub fn is_window(&self) -> bool {
+        self.window
+    }
+
+    /// Publishes a uniform frame in place of the captured content.
+    ///
+    /// # Notes
+    /// Used while a shared window delivers no frames, the viewers would keep
+    /// seeing its last frame otherwise. The frame has the stream's size and
+    /// doesn't count as a captured frame for `is_stalled`.
+    pub fn publish_placeholder(&self) {
+        if self.publishing_paused.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(buffer_source) = self.buffer_source.source.lock().unwrap().clone() else {
+            return;
+        };
+        let extent = self.get_stream_extent();
+        if extent.width == 0. || extent.height == 0. {
+            return;
+        }
+
+        let mut frame = Nv12Frame::new(extent.width as u32, extent.height as u32);
+        let (data_y, data_uv) = frame.buffer_mut().data_mut();
+        data_y.fill(PLACEHOLDER_LUMA);
+        data_uv.fill(PLACEHOLDER_CHROMA);
+        buffer_source.push_frame(VideoFrameRef::Nv12(&frame));
+    }
+
+    /// Stops or resumes pushing the captured frames to the buffer source.
+    ///
+    /// # Parameters
+    /// - `paused`: `true` while nobody watches the share
+    pub fn set_publishing_paused(&self, paused: bool) {
+        if self.publishing_paused.swap(paused, Ordering::Relaxed) != paused {
+            log::info!("set_publishing_paused: {paused}");
+        }
+    }
+
+    /// Returns whether the captured frames aren't pushed to the buffer source.
+    pub fn is_publishing_paused(&self) -> bool {
+        self.publishing_paused.load(Ordering::Relaxed)
+    }
+
+    /// Changes how often the desktop capturer is asked for a frame.
+    ///
+    /// # Parameters
+    /// - `framerate`: Target frames per second, clamped to 1..=MAX_CAPTURE_FRAMERATE,
+    ///   `None` for the default interval
+    ///
+    /// # Notes
+    /// Applies to a running capture from its next frame. A test pattern
+    /// keeps the fps it was created with.
+    pub fn set_framerate(&self, framerate: Option<u32>) {
+        let interval = capture_interval_ms(framerate);
+        if self.capture_interval_ms.swap(interval, Ordering::Relaxed) != interval {
+            log::info!("Stream::set_framerate: {framerate:?}, capturing every {interval}ms");
+        }
+    }
+
     /// Returns the identifier of the capture source.
     ///
     /// # Returns
@@ -508,21 +1111,218 @@ impl Stream {
         self.source_id
     }
 
-    pub fn get_stream_extent(&self) -> Extent {
-        let stream_buffer = self.stream_buffer.lock().unwrap();
-        Extent {
-            width: stream_buffer.video_frame.buffer.width() as f64,
-            height: stream_buffer.video_frame.buffer.height() as f64,
+    /// Returns a handle waiting for the stream's first frame, see `FirstFrameWaiter`.
+    pub fn first_frame_waiter(&self) -> FirstFrameWaiter {
+        FirstFrameWaiter {
+            first_frame: self.first_frame.clone(),
+            frames: self.frames.clone(),
         }
     }
 
-    pub fn set_buffer_source(&mut self, buffer_source: NativeVideoSource) {
-        let mut b_source = self.buffer_source.lock().unwrap();
-        *b_source = Some(buffer_source);
+    /// Returns the color space the stream's frames are converted from.
+    pub fn color_space(&self) -> ColorSpace {
+        self.settings.lock().unwrap().color_space
+    }
+
+    pub fn get_stream_extent(&self) -> Extent {
+        self.first_frame_waiter().stream_extent()
+    }
+
+    /// Returns the part of the captured frames the stream shows, the whole
+    /// frames until the first one was captured.
+    pub fn get_stream_crop(&self) -> StreamCrop {
+        self.first_frame_waiter().stream_crop()
     }
 
+    /// Returns the latest streamed frame as RGB pixels, fitted in `target`.
+    ///
+    /// # Parameters
+    /// - `target`: The maximum dimensions of the returned image
+    ///
+    /// # Returns
+    /// - `Some((pixels, width, height))`: Tightly packed RGB pixels of the frame
+    /// - `None`: No frame has been captured yet
+    ///
+    /// # Notes
+    /// Used for generating the thumbnail of the shared display without
+    /// starting a second capturer on it, and for the sharer's live preview.
+    pub fn latest_frame_rgb(&self, target: Extent) -> Option<(Vec<u8>, u32, u32)> {
+        let (scaled_buffer, width, height) = self.frames.read(|stream_buffer| {
+            let buffer = stream_buffer.video_frame.buffer_mut();
+            if buffer.width() == 0 || buffer.height() == 0 {
+                return None;
+            }
+
+            let (width, height) = aspect_fit(
+                buffer.width(),
+                buffer.height(),
+                target.width as u32,
+                target.height as u32,
+            );
+            Some((buffer.scale(width as i32, height as i32), width, height))
+        })?;
+
+        /* libyuv's ABGR is R, G, B, A in memory. */
+        let mut rgba = vec![0u8; (width * height * 4) as usize];
+        scaled_buffer.to_argb(
+            livekit::webrtc::prelude::VideoFormatType::ABGR,
+            &mut rgba,
+            width * 4,
+            width as i32,
+            height as i32,
+        );
+        let rgb = rgba
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect();
+        Some((rgb, width, height))
+    }
+
+    pub fn set_buffer_source(&mut self, buffer_source: SharedVideoSink) {
+        self.buffer_source.set(buffer_source);
+        *self.last_frame_at.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Returns the desktop capturer, `None` for a test pattern stream.
     #[cfg(target_os = "linux")]
-    pub fn capturer(&self) -> Arc<Mutex<DesktopCapturer>> {
-        self.capturer.clone()
+    pub fn capturer(&self) -> Option<Arc<Mutex<DesktopCapturer>>> {
+        match &self.source {
+            StreamSource::Desktop(capturer) => Some(capturer.clone()),
+            StreamSource::TestPattern { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::cancellation::SHUTDOWN_TIMEOUT;
+
+    #[test]
+    fn test_process_excluded() {
+        let exclusions = vec!["zoom.us".to_string(), " 4242 ".to_string(), "".to_string()];
+        assert!(process_excluded(1, "hopp", &[]));
+        assert!(process_excluded(1, "Zoom.us", &exclusions));
+        assert!(process_excluded(4242, "Finder", &exclusions));
+        assert!(!process_excluded(1, "Finder", &exclusions));
+        assert!(!process_excluded(42, "Finder", &exclusions));
+    }
+
+    #[test]
+    fn test_first_frame_wakes_waiters() {
+        let first_frame = Arc::new(FirstFrame::default());
+        assert!(!first_frame.wait(Duration::from_millis(10)));
+
+        let waiter = {
+            let first_frame = first_frame.clone();
+            std::thread::spawn(move || first_frame.wait(Duration::from_secs(10)))
+        };
+        first_frame.notify();
+        assert!(waiter.join().unwrap());
+        assert!(first_frame.wait(Duration::ZERO));
+
+        first_frame.reset();
+        assert!(!first_frame.wait(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_frame_delivery_stalled() {
+        let timeout = Duration::from_secs(5);
+        let now = Instant::now();
+        let start = now - Duration::from_secs(10);
+        assert!(!frame_delivery_stalled(None, now, timeout));
+        assert!(!frame_delivery_stalled(Some(now), now, timeout));
+        assert!(!frame_delivery_stalled(
+            Some(start + Duration::from_secs(6)),
+            now,
+            timeout
+        ));
+        assert!(frame_delivery_stalled(Some(start), now, timeout));
+    }
+
+    #[test]
+    fn test_capture_interval() {
+        assert_eq!(capture_interval_ms(None), FRAME_CAPTURE_INTERVAL_MS);
+        assert_eq!(capture_interval_ms(Some(30)), 33);
+        assert_eq!(capture_interval_ms(Some(5)), 200);
+        assert_eq!(capture_interval_ms(Some(240)), 16);
+        assert_eq!(capture_interval_ms(Some(0)), 1000);
+    }
+
+    #[test]
+    fn test_even_extent() {
+        assert_eq!(
+            even_extent(Extent::new(1281., 721.)),
+            Extent::new(1280., 720.)
+        );
+        assert_eq!(
+            even_extent(Extent::new(1280., 720.)),
+            Extent::new(1280., 720.)
+        );
+        assert_eq!(even_extent(Extent::new(0., 1.)), Extent::new(2., 2.));
+    }
+
+    /// Waits for `handle`'s thread to exit, at most `SHUTDOWN_TIMEOUT`.
+    fn joined_in_time(handle: JoinHandle<()>) -> bool {
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while !handle.is_finished() {
+            if Instant::now() > deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        handle.join().is_ok()
+    }
+
+    #[test]
+    fn test_cancellation_stops_run_capture_frame() {
+        let cancel = CancellationToken::new();
+        /* The sender is kept, only the cancellation stops the thread. */
+        let (_tx, rx) = mpsc::channel();
+        let captured = Arc::new(AtomicU64::new(0));
+        let handle = {
+            let captured = captured.clone();
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                run_capture_frame(rx, Arc::new(AtomicU64::new(5)), cancel, move || {
+                    captured.fetch_add(1, Ordering::Relaxed);
+                })
+            })
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(captured.load(Ordering::Relaxed) > 0);
+
+        cancel.cancel();
+        assert!(joined_in_time(handle));
+    }
+
+    #[test]
+    fn test_first_frame_waiter() {
+        let (tx, _rx) = mpsc::channel();
+        let mut stream =
+            Stream::test_pattern(Extent::new(64., 36.), 30, tx, CancellationToken::new());
+        let waiter = stream.first_frame_waiter();
+        assert!(!waiter.wait(Duration::from_millis(10)));
+
+        /* The waiter doesn't borrow the stream, it is waited on while the stream is used. */
+        stream.start_capture(0);
+        assert!(waiter.wait(SHUTDOWN_TIMEOUT));
+        assert_eq!(waiter.stream_extent(), Extent::new(64., 36.));
+        assert_eq!(stream.get_stream_extent(), waiter.stream_extent());
+        stream.stop_capture();
+    }
+
+    #[test]
+    fn test_cancellation_stops_the_test_pattern() {
+        let cancel = CancellationToken::new();
+        let (tx, _rx) = mpsc::channel();
+        let mut stream = Stream::test_pattern(Extent::new(64., 36.), 30, tx, cancel.clone());
+        stream.start_capture(0);
+        let handle = stream.capture_frame_handle.take().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+
+        cancel.cancel();
+        assert!(joined_in_time(handle));
     }
 }
@@ -0,0 +1,231 @@
+//! Generated frames for sharing without capturing the screen.
+//!
+//! A test pattern share publishes these frames through the same conversion,
+//! scaling and buffer source as a captured display, so QA, demos and CI runs
+//! can check the publishing path on machines without capture permissions.
+//!
+//! The pattern is the 75% color bars with a block sweeping across the bottom,
+//! so the encoder always has motion to work on, and the elapsed time and the
+//! frame counter drawn on the top left, so receivers can tell frozen and
+//! dropped frames apart.
+
+use std::time::Duration;
+
+/// 75% color bars as RGB, left to right.
+const COLOR_BARS: [[u8; 3]; 7] = [
+    [191, 191, 191],
+    [191, 191, 0],
+    [0, 191, 191],
+    [0, 191, 0],
+    [191, 0, 191],
+    [191, 0, 0],
+    [0, 0, 191],
+];
+const BACKGROUND: [u8; 3] = [16, 16, 16];
+const FOREGROUND: [u8; 3] = [235, 235, 235];
+
+/// Frames the sweeping block takes to cross the frame.
+const SWEEP_FRAMES: u64 = 120;
+
+/// Glyphs of the 3x5 font, one row per byte with the leftmost pixel in bit 2.
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+const COLON: [u8; 5] = [0b000, 0b010, 0b000, 0b010, 0b000];
+const DOT: [u8; 5] = [0b000, 0b000, 0b000, 0b000, 0b010];
+
+fn glyph(c: char) -> Option<[u8; 5]> {
+    match c {
+        '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+        ':' => Some(COLON),
+        '.' => Some(DOT),
+        ' ' => Some([0; 5]),
+        _ => None,
+    }
+}
+
+/// Formats `elapsed` as `MM:SS.mmm`, minutes wrap after an hour.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let millis = elapsed.as_millis();
+    format!(
+        "{:02}:{:02}.{:03}",
+        (millis / 60_000) % 60,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+/// Renders test pattern frames of a fixed size.
+pub struct TestPattern {
+    width: u32,
+    height: u32,
+    /// BGRA pixels of the last rendered frame, like the desktop capturer's frames
+    pixels: Vec<u8>,
+}
+
+impl TestPattern {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Bytes per row of the rendered frames.
+    pub fn stride(&self) -> u32 {
+        self.width * 4
+    }
+
+    /// Renders frame number `frame`, `elapsed` after the pattern started.
+    ///
+    /// # Returns
+    ///
+    /// The BGRA pixels of the frame.
+    pub fn render(&mut self, frame: u64, elapsed: Duration) -> &[u8] {
+        let bars_height = self.height * 3 / 4;
+        for x in 0..self.width {
+            let bar = (x as usize * COLOR_BARS.len()) / self.width.max(1) as usize;
+            self.fill_rect(x, 0, 1, bars_height, COLOR_BARS[bar]);
+        }
+        self.fill_rect(
+            0,
+            bars_height,
+            self.width,
+            self.height - bars_height,
+            BACKGROUND,
+        );
+
+        let block_width = (self.width / 16).max(1);
+        let travel = self.width.saturating_sub(block_width) as u64;
+        let block_x = (frame % SWEEP_FRAMES) * travel / SWEEP_FRAMES;
+        self.fill_rect(
+            block_x as u32,
+            bars_height,
+            block_width,
+            self.height - bars_height,
+            FOREGROUND,
+        );
+
+        let scale = (self.height / 90).max(1);
+        let line_height = (GLYPH_HEIGHT + 2) * scale;
+        self.draw_text(&format_elapsed(elapsed), scale, scale, scale);
+        self.draw_text(&frame.to_string(), scale, scale + line_height, scale);
+
+        &self.pixels
+    }
+
+    /// Draws `text` on a dark box with its top left corner at `x`, `y`,
+    /// every font pixel is `scale` pixels wide.
+    fn draw_text(&mut self, text: &str, x: u32, y: u32, scale: u32) {
+        let advance = (GLYPH_WIDTH + 1) * scale;
+        let glyphs: Vec<[u8; 5]> = text.chars().filter_map(glyph).collect();
+        self.fill_rect(
+            x.saturating_sub(scale),
+            y.saturating_sub(scale),
+            glyphs.len() as u32 * advance + scale,
+            (GLYPH_HEIGHT + 2) * scale,
+            BACKGROUND,
+        );
+        for (i, rows) in glyphs.iter().enumerate() {
+            let glyph_x = x + i as u32 * advance;
+            for (row, bits) in rows.iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (0b100 >> col) == 0 {
+                        continue;
+                    }
+                    self.fill_rect(
+                        glyph_x + col * scale,
+                        y + row as u32 * scale,
+                        scale,
+                        scale,
+                        FOREGROUND,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Fills a rectangle with `rgb`, the parts outside of the frame are skipped.
+    fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, rgb: [u8; 3]) {
+        let right = (x + width).min(self.width);
+        let bottom = (y + height).min(self.height);
+        for row in y.min(bottom)..bottom {
+            for col in x.min(right)..right {
+                let offset = ((row * self.width + col) * 4) as usize;
+                self.pixels[offset..offset + 4].copy_from_slice(&[rgb[2], rgb[1], rgb[0], 255]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(pattern: &TestPattern, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * pattern.width + x) * 4) as usize;
+        pattern.pixels[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_color_bars() {
+        let mut pattern = TestPattern::new(700, 400);
+        pattern.render(0, Duration::ZERO);
+        for (i, [r, g, b]) in COLOR_BARS.iter().enumerate() {
+            let x = i as u32 * 100 + 50;
+            assert_eq!(pixel(&pattern, x, 200), [*b, *g, *r, 255], "bar {i}");
+        }
+    }
+
+    #[test]
+    fn test_frames_differ() {
+        let mut pattern = TestPattern::new(320, 180);
+        let first = pattern.render(0, Duration::ZERO).to_vec();
+        let second = pattern.render(1, Duration::from_millis(33)).to_vec();
+        assert_eq!(first.len(), (320 * 180 * 4) as usize);
+        assert_ne!(first, second);
+
+        /* The block sweeps across the bottom of the frame. */
+        let y = 170;
+        let block_x = |pattern: &TestPattern| {
+            (0..pattern.width).find(|&x| pixel(pattern, x, y)[..3] == [235, 235, 235])
+        };
+        pattern.render(0, Duration::ZERO);
+        let start = block_x(&pattern).unwrap();
+        pattern.render(SWEEP_FRAMES / 2, Duration::ZERO);
+        assert!(block_x(&pattern).unwrap() > start);
+    }
+
+    #[test]
+    fn test_tiny_frame() {
+        let mut pattern = TestPattern::new(2, 2);
+        assert_eq!(pattern.render(12345, Duration::from_secs(3600)).len(), 16);
+    }
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(Duration::ZERO), "00:00.000");
+        assert_eq!(format_elapsed(Duration::from_millis(61_042)), "01:01.042");
+        assert_eq!(format_elapsed(Duration::from_secs(3600)), "00:00.000");
+    }
+}
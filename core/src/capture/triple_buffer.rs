@@ -0,0 +1,142 @@
+//! Triple buffer handing the streamed frames from the capture callback to
+//! the readers of the stream.
+//!
+//! The buffer has three slots, one written by the producer, one read by the
+//! consumers and one holding the latest published frame. Publishing and
+//! reading swap their slot with the latest one through an atomic, so the
+//! capture callback never waits for a consumer scaling a thumbnail or polling
+//! the stream extent, and the consumers never wait for a frame conversion.
+//!
+//! Each side has a mutex for its own slot, it is only contended by the same
+//! side, e.g. a restarted capturer replacing the previous one.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+/// Bits of `TripleBuffer::latest` holding the index of a slot
+const INDEX_MASK: u8 = 0b11;
+/// Set in `TripleBuffer::latest` until the consumers read the published slot
+const FRESH: u8 = 0b100;
+
+pub struct TripleBuffer<T> {
+    slots: [UnsafeCell<T>; 3],
+    /// Index of the last published slot, with `FRESH` if it wasn't read yet
+    latest: AtomicU8,
+    /// Index of the slot the producer writes to
+    write: Mutex<u8>,
+    /// Index of the slot the consumers read from
+    read: Mutex<u8>,
+}
+
+/*
+ * Each slot is owned by one of the producer, the consumers or `latest` at a
+ * time. The producer and the consumers only touch their slot while holding
+ * their mutex, and exchange it with `latest` atomically.
+ */
+unsafe impl<T: Send> Send for TripleBuffer<T> {}
+unsafe impl<T: Send> Sync for TripleBuffer<T> {}
+
+impl<T> TripleBuffer<T> {
+    /// Creates a buffer with its slots filled by `init`.
+    pub fn new(init: impl Fn() -> T) -> Self {
+        Self {
+            slots: [
+                UnsafeCell::new(init()),
+                UnsafeCell::new(init()),
+                UnsafeCell::new(init()),
+            ],
+            latest: AtomicU8::new(1),
+            write: Mutex::new(0),
+            read: Mutex::new(2),
+        }
+    }
+
+    /// Fills the producer's slot with `write` and publishes it as the latest.
+    ///
+    /// The slot holds a frame published earlier, or the initial value,
+    /// `write` has to overwrite what it uses from it.
+    pub fn publish<R>(&self, write: impl FnOnce(&mut T) -> R) -> R {
+        let mut index = self.write.lock().unwrap();
+        /* Safety: the producer's slot is only touched with its mutex held. */
+        let slot = unsafe { &mut *self.slots[*index as usize].get() };
+        let result = write(slot);
+        let previous = self.latest.swap(*index | FRESH, Ordering::AcqRel);
+        *index = previous & INDEX_MASK;
+        result
+    }
+
+    /// Runs `read` on the latest published slot.
+    ///
+    /// Without a newer publication the slot read last time is read again.
+    pub fn read<R>(&self, read: impl FnOnce(&mut T) -> R) -> R {
+        let mut index = self.read.lock().unwrap();
+        if self.latest.load(Ordering::Acquire) & FRESH != 0 {
+            let previous = self.latest.swap(*index, Ordering::AcqRel);
+            *index = previous & INDEX_MASK;
+        }
+        /* Safety: the consumers' slot is only touched with their mutex held. */
+        read(unsafe { &mut *self.slots[*index as usize].get() })
+    }
+
+    /// Refills every slot with `init`, e.g. when the stream is reconfigured.
+    ///
+    /// Waits for the producer and the consumers to be done with their slots.
+    pub fn reset(&self, init: impl Fn() -> T) {
+        let _write = self.write.lock().unwrap();
+        let _read = self.read.lock().unwrap();
+        /* Safety: with both mutexes held nobody swaps `latest`, every slot is ours. */
+        for slot in &self.slots {
+            unsafe { *slot.get() = init() };
+        }
+        self.latest.fetch_and(INDEX_MASK, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_reads_latest_publication() {
+        let buffer = TripleBuffer::new(|| 0);
+        assert_eq!(buffer.read(|value| *value), 0);
+
+        buffer.publish(|value| *value = 1);
+        buffer.publish(|value| *value = 2);
+        assert_eq!(buffer.read(|value| *value), 2);
+        /* Nothing new, the same slot is read again. */
+        assert_eq!(buffer.read(|value| *value), 2);
+
+        buffer.publish(|value| *value = 3);
+        assert_eq!(buffer.read(|value| *value), 3);
+
+        buffer.reset(|| 0);
+        assert_eq!(buffer.read(|value| *value), 0);
+        buffer.publish(|value| *value = 4);
+        assert_eq!(buffer.read(|value| *value), 4);
+    }
+
+    #[test]
+    fn test_concurrent_frames_arent_torn() {
+        let buffer = Arc::new(TripleBuffer::new(|| vec![0u32; 64]));
+        let producer = {
+            let buffer = buffer.clone();
+            std::thread::spawn(move || {
+                for frame in 1..=2000 {
+                    buffer.publish(|slot| slot.fill(frame));
+                }
+            })
+        };
+
+        let mut last = 0;
+        while !producer.is_finished() || last != 2000 {
+            let (first, torn) = buffer.read(|slot| (slot[0], slot.iter().any(|v| *v != slot[0])));
+            assert!(!torn);
+            assert!(first >= last);
+            last = first;
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+//! Tracking of the shared window's frame.
+//!
+//! When a window is shared the overlay covers its whole display, but the
+//! controllers' positions are fractions of the window. The watcher thread
+//! polls the window's frame while it is shared and notifies the application
+//! when it moves or is resized, so the cursors and the simulated events
+//! follow the window.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::event_sender::EventSender;
+use crate::utils::geometry::Frame;
+use crate::UserEvent;
+
+const WINDOW_WATCH_INTERVAL_MS: u64 = 100;
+
+/// Background thread that sends `UserEvent::SharedWindowMoved` when the
+/// shared window's frame changes.
+///
+/// The thread is stopped when the watcher is dropped.
+pub struct WindowWatcher {
+    stop_tx: mpsc::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl WindowWatcher {
+    /// Starts watching the window.
+    ///
+    /// # Parameters
+    /// - `event_sender`: Where the frame changes are sent
+    /// - `frame`: Returns the window's frame in global physical pixels, `None`
+    ///   while it can't be queried, e.g. it is minimized or was closed
+    pub fn new(
+        event_sender: Box<dyn EventSender>,
+        frame: impl Fn() -> Option<Frame> + Send + 'static,
+    ) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            watch_window(
+                event_sender,
+                frame,
+                stop_rx,
+                Duration::from_millis(WINDOW_WATCH_INTERVAL_MS),
+            )
+        });
+        Self {
+            stop_tx,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for WindowWatcher {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("WindowWatcher::drop: watcher thread panicked");
+            }
+        }
+    }
+}
+
+fn watch_window(
+    event_sender: Box<dyn EventSender>,
+    frame: impl Fn() -> Option<Frame>,
+    stop_rx: mpsc::Receiver<()>,
+    interval: Duration,
+) {
+    let mut previous = None;
+    loop {
+        /* The last known frame is kept while the window can't be queried. */
+        if let Some(current) = frame() {
+            if Some(current) != previous {
+                log::debug!("watch_window: {previous:?} -> {current:?}");
+                if let Err(e) = event_sender.send(UserEvent::SharedWindowMoved(current)) {
+                    log::error!("watch_window: error sending window frame: {e:?}");
+                    break;
+                }
+                previous = Some(current);
+            }
+        }
+
+        match stop_rx.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use crate::utils::geometry::Extent;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_watch_window_reports_moves() {
+        let moved = Frame::new(200.0, 100.0, Extent::new(800.0, 600.0));
+        let resized = Frame::new(200.0, 100.0, Extent::new(1024.0, 768.0));
+        let frames = Arc::new(Mutex::new(vec![
+            Some(moved),
+            Some(moved),
+            None,
+            Some(resized),
+            Some(resized),
+        ]));
+        let collector = EventCollector::new();
+        let (stop_tx, stop_rx) = mpsc::channel();
+
+        let watched_frames = frames.clone();
+        let frame = move || {
+            let mut frames = watched_frames.lock().unwrap();
+            if frames.len() == 1 {
+                let _ = stop_tx.send(());
+                return frames[0];
+            }
+            frames.remove(0)
+        };
+        watch_window(
+            Box::new(collector.clone()),
+            frame,
+            stop_rx,
+            Duration::from_millis(1),
+        );
+
+        let events = collector.take();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], UserEvent::SharedWindowMoved(frame) if frame == moved));
+        assert!(matches!(events[1], UserEvent::SharedWindowMoved(frame) if frame == resized));
+    }
+}
@@ -1,9 +1,33 @@
+use socket_lib::ShareInterruption;
 use winit::platform::windows::MonitorHandleExtWindows;
 
-use crate::{capture::capturer::ScreenshareExt, utils::geometry::Extent};
+use crate::{
+    capture::capturer::{ColorSpace, HdrTransfer, ScreenshareExt, WindowInfo},
+    utils::geometry::{Extent, Frame},
+};
 
-use windows::core::PCWSTR;
-use windows::Win32::Graphics::Gdi::{EnumDisplayDevicesW, DISPLAY_DEVICEW};
+use windows::core::{Interface, PCWSTR, PWSTR};
+use windows::Win32::Foundation::{CloseHandle, BOOL, FALSE, HWND, LPARAM, RECT, TRUE};
+use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_EXTENDED_FRAME_BOUNDS};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020;
+use windows::Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIFactory1, IDXGIOutput6};
+use windows::Win32::Graphics::Gdi::{
+    EnumDisplayDevicesW, GetMonitorInfoW, MonitorFromWindow, DISPLAY_DEVICEW, MONITORINFO,
+    MONITORINFOEXW, MONITOR_DEFAULTTONEAREST,
+};
+use windows::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    EnumWindows, GetWindowThreadProcessId, IsIconic, IsWindowVisible, SystemParametersInfoW,
+    SPI_GETSCREENSAVERRUNNING, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+};
+
+// sRGB red primary x is 0.64, anything noticeably wider is treated as wide gamut.
+const WIDE_GAMUT_RED_PRIMARY_X: f32 = 0.66;
+
+/// Backend the desktop capturer uses on this platform, reported with capture failures.
+pub const CAPTURE_BACKEND: &str = "DXGI";
 
 pub struct ScreenshareFunctions {}
 
@@ -13,18 +37,11 @@ impl ScreenshareExt for ScreenshareFunctions {
         log::debug!("get_monitor_size input name: {input_monitor_name:?}");
         for monitor in monitors {
             if monitor.native_id() == input_monitor_name {
-                let monitor_size = monitor.size();
-                return Extent {
-                    width: monitor_size.width as f64,
-                    height: monitor_size.height as f64,
-                };
+                return monitor.size().into();
             }
         }
 
-        Extent {
-            width: 0.,
-            height: 0.,
-        }
+        Extent::default()
     }
 
     fn get_selected_monitor(
@@ -41,6 +58,228 @@ impl ScreenshareExt for ScreenshareFunctions {
         }
         selected_monitor
     }
+
+    fn get_display_color_space(input_id: u32) -> ColorSpace {
+        let input_monitor_name = get_display_index(input_id);
+        unsafe {
+            let factory: IDXGIFactory1 = match CreateDXGIFactory1() {
+                Ok(factory) => factory,
+                Err(e) => {
+                    log::warn!("get_display_color_space: failed to create dxgi factory {e:?}");
+                    return ColorSpace::Srgb;
+                }
+            };
+
+            let mut adapter_index = 0;
+            while let Ok(adapter) = factory.EnumAdapters1(adapter_index) {
+                let mut output_index = 0;
+                while let Ok(output) = adapter.EnumOutputs(output_index) {
+                    output_index += 1;
+                    let desc = match output.cast::<IDXGIOutput6>() {
+                        Ok(output) => match output.GetDesc1() {
+                            Ok(desc) => desc,
+                            Err(_) => continue,
+                        },
+                        Err(_) => continue,
+                    };
+                    let name = String::from_utf16_lossy(
+                        desc.DeviceName[..].split(|&x| x == 0).next().unwrap_or(&[]),
+                    );
+                    if name != input_monitor_name {
+                        continue;
+                    }
+
+                    log::debug!(
+                        "get_display_color_space: {name} color space: {:?} red primary: {:?}",
+                        desc.ColorSpace,
+                        desc.RedPrimary
+                    );
+                    if desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020 {
+                        return ColorSpace::Hdr(HdrTransfer::Pq);
+                    }
+                    if desc.RedPrimary[0] > WIDE_GAMUT_RED_PRIMARY_X {
+                        return ColorSpace::WideGamut;
+                    }
+                    return ColorSpace::Srgb;
+                }
+                adapter_index += 1;
+            }
+        }
+        log::warn!("get_display_color_space: output not found for {input_monitor_name:?}");
+        ColorSpace::Srgb
+    }
+
+    /// Display sleep isn't detected, it can only be observed through
+    /// power notifications to a window.
+    fn get_share_interruption(_input_id: u32) -> Option<ShareInterruption> {
+        if screen_locked() {
+            Some(ShareInterruption::ScreenLocked)
+        } else if screensaver_running() {
+            Some(ShareInterruption::Screensaver)
+        } else {
+            None
+        }
+    }
+
+    /// Windows spanning several displays are listed on the display with
+    /// most of their area.
+    fn get_display_apps(input_id: u32) -> Vec<String> {
+        let display_name = get_display_index(input_id);
+        let mut windows: Vec<HWND> = vec![];
+        let res = unsafe {
+            EnumWindows(
+                Some(collect_window),
+                LPARAM(&mut windows as *mut Vec<HWND> as isize),
+            )
+        };
+        if let Err(e) = res {
+            log::warn!("get_display_apps: failed to enumerate windows: {e:?}");
+            return vec![];
+        }
+
+        windows
+            .into_iter()
+            .filter(|&hwnd| unsafe { IsWindowVisible(hwnd).as_bool() && !IsIconic(hwnd).as_bool() })
+            .filter(|&hwnd| window_display_name(hwnd) == display_name)
+            .filter_map(window_app)
+            .collect()
+    }
+
+    /// The process is DPI aware, the window's bounds are already in physical
+    /// pixels. The invisible resize borders aren't part of the frame.
+    fn get_window_info(window_id: u32) -> Option<WindowInfo> {
+        let hwnd = HWND(window_id as isize as *mut std::ffi::c_void);
+        if unsafe { !IsWindowVisible(hwnd).as_bool() || IsIconic(hwnd).as_bool() } {
+            return None;
+        }
+
+        let mut bounds = RECT::default();
+        let res = unsafe {
+            DwmGetWindowAttribute(
+                hwnd,
+                DWMWA_EXTENDED_FRAME_BOUNDS,
+                &mut bounds as *mut RECT as *mut std::ffi::c_void,
+                std::mem::size_of::<RECT>() as u32,
+            )
+        };
+        if let Err(e) = res {
+            log::warn!("get_window_info: failed to get bounds of {window_id}: {e:?}");
+            return None;
+        }
+
+        /* The capturer's display ids are the indices of the display devices. */
+        let display_name = window_display_name(hwnd);
+        let display_id = (0..)
+            .map(|i| (i, get_display_index(i)))
+            .take_while(|(_, name)| !name.is_empty())
+            .find(|(_, name)| *name == display_name)
+            .map(|(i, _)| i)?;
+
+        Some(WindowInfo {
+            frame: Frame::new(
+                bounds.left as f64,
+                bounds.top as f64,
+                Extent::new(
+                    (bounds.right - bounds.left) as f64,
+                    (bounds.bottom - bounds.top) as f64,
+                ),
+            ),
+            display_id,
+            apps: window_app(hwnd).into_iter().collect(),
+        })
+    }
+
+    fn screen_capture_permitted() -> bool {
+        true
+    }
+}
+
+unsafe extern "system" fn collect_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let windows = &mut *(lparam.0 as *mut Vec<HWND>);
+    windows.push(hwnd);
+    TRUE
+}
+
+/// Returns the device name of the display showing most of the window.
+fn window_display_name(hwnd: HWND) -> String {
+    unsafe {
+        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+        let mut info = MONITORINFOEXW::default();
+        info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+        if !GetMonitorInfoW(
+            monitor,
+            &mut info as *mut MONITORINFOEXW as *mut MONITORINFO,
+        )
+        .as_bool()
+        {
+            return String::new();
+        }
+        String::from_utf16_lossy(info.szDevice[..].split(|&x| x == 0).next().unwrap_or(&[]))
+    }
+}
+
+/// Returns the executable name, without extension, of the app owning the window.
+fn window_app(hwnd: HWND) -> Option<String> {
+    let mut pid = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+    if pid == 0 {
+        return None;
+    }
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) }.ok()?;
+    let mut buffer = [0u16; 1024];
+    let mut len = buffer.len() as u32;
+    let res = unsafe {
+        QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut len,
+        )
+    };
+    if let Err(e) = unsafe { CloseHandle(process) } {
+        log::warn!("window_app: failed to close process handle: {e:?}");
+    }
+    res.ok()?;
+    let path = String::from_utf16_lossy(&buffer[..len as usize]);
+    std::path::Path::new(&path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+}
+
+/// Returns `true` when the secure desktop of the lock screen is the input desktop.
+///
+/// The user's session can't open the input desktop while it is locked.
+fn screen_locked() -> bool {
+    use windows::Win32::System::StationsAndDesktops::{
+        CloseDesktop, OpenInputDesktop, DESKTOP_CONTROL_FLAGS, DESKTOP_SWITCHDESKTOP,
+    };
+
+    match unsafe { OpenInputDesktop(DESKTOP_CONTROL_FLAGS(0), FALSE, DESKTOP_SWITCHDESKTOP) } {
+        Ok(desktop) => {
+            if let Err(e) = unsafe { CloseDesktop(desktop) } {
+                log::warn!("screen_locked: failed to close input desktop: {e:?}");
+            }
+            false
+        }
+        Err(_) => true,
+    }
+}
+
+fn screensaver_running() -> bool {
+    let mut running = windows::Win32::Foundation::BOOL(0);
+    let res = unsafe {
+        SystemParametersInfoW(
+            SPI_GETSCREENSAVERRUNNING,
+            0,
+            Some(&mut running as *mut _ as *mut std::ffi::c_void),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    };
+    if let Err(e) = res {
+        log::warn!("screensaver_running: failed to get screensaver state: {e:?}");
+        return false;
+    }
+    running.as_bool()
 }
 
 // TODO: Change name to this.
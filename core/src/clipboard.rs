@@ -0,0 +1,226 @@
+//! Clipboard sync between the sharer and the controllers.
+//!
+//! While the sharer has clipboard sharing on, text copied on the sharer's
+//! machine is published to the controllers, and a controller sends its
+//! clipboard text when its window gets the focus back, so what it copied in
+//! another app is on the sharer's clipboard before it pastes, see
+//! `ClientEvent::ClipboardUpdate`. Only text is synced for now.
+//!
+//! The platforms don't notify about clipboard changes in a common way, the
+//! watcher thread polls the clipboard. A text written for a controller is
+//! remembered as the last seen one, so it isn't published back to the room.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::event_sender::EventSender;
+use crate::UserEvent;
+
+const CLIPBOARD_WATCH_INTERVAL_MS: u64 = 500;
+/// Larger texts aren't synced, they would take many data packets
+pub const MAX_CLIPBOARD_TEXT_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClipboardError {
+    #[error("Clipboard unavailable: {0}")]
+    Unavailable(String),
+    #[error("Clipboard text is {0} bytes, the limit is {MAX_CLIPBOARD_TEXT_BYTES}")]
+    TooLarge(usize),
+    #[error("Failed to write the clipboard: {0}")]
+    Write(String),
+}
+
+/// Returns the text on the system clipboard, `None` when it has no text.
+fn read_text() -> Option<String> {
+    arboard::Clipboard::new().ok()?.get_text().ok()
+}
+
+/// Returns an error when `text` is too large to be synced.
+fn check_text(text: &str) -> Result<(), ClipboardError> {
+    if text.len() > MAX_CLIPBOARD_TEXT_BYTES {
+        return Err(ClipboardError::TooLarge(text.len()));
+    }
+    Ok(())
+}
+
+/// Background thread that sends `UserEvent::ClipboardChanged` when text is
+/// copied on the sharer's machine, and the writer of the controllers' texts.
+///
+/// The thread is stopped when the sync is dropped.
+pub struct ClipboardSync {
+    stop_tx: mpsc::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+    /// The text last seen on or written to the clipboard, shared with the thread
+    last_text: Arc<Mutex<Option<String>>>,
+    /* Kept open, on linux the written text is gone when its owner is dropped. */
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl ClipboardSync {
+    /// Starts watching the system clipboard.
+    ///
+    /// # Parameters
+    /// - `event_sender`: Where the copied texts are sent
+    pub fn start(event_sender: Box<dyn EventSender>) -> Self {
+        log::info!("ClipboardSync::start");
+        let clipboard = match arboard::Clipboard::new() {
+            Ok(clipboard) => Some(clipboard),
+            Err(e) => {
+                log::error!("ClipboardSync::start: clipboard unavailable: {e:?}");
+                None
+            }
+        };
+        let last_text = Arc::new(Mutex::new(None));
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let thread_last_text = last_text.clone();
+        let handle = std::thread::spawn(move || {
+            watch_clipboard(
+                event_sender,
+                read_text,
+                thread_last_text,
+                stop_rx,
+                Duration::from_millis(CLIPBOARD_WATCH_INTERVAL_MS),
+            )
+        });
+        Self {
+            stop_tx,
+            handle: Some(handle),
+            last_text,
+            clipboard,
+        }
+    }
+
+    /// Writes the text a controller copied to the sharer's clipboard.
+    ///
+    /// # Parameters
+    /// - `text`: The controller's clipboard text
+    ///
+    /// # Returns
+    /// An error when the text is too large or the clipboard can't be written.
+    pub fn apply_remote(&mut self, text: String) -> Result<(), ClipboardError> {
+        check_text(&text)?;
+        let clipboard = self
+            .clipboard
+            .as_mut()
+            .ok_or_else(|| ClipboardError::Unavailable("not opened".to_string()))?;
+        /* Remembered first, the watcher could read it before set_text returns. */
+        *self.last_text.lock().unwrap() = Some(text.clone());
+        clipboard
+            .set_text(text)
+            .map_err(|e| ClipboardError::Write(e.to_string()))
+    }
+}
+
+impl Drop for ClipboardSync {
+    fn drop(&mut self) {
+        log::info!("ClipboardSync::drop");
+        let _ = self.stop_tx.send(());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("ClipboardSync::drop: watcher thread panicked");
+            }
+        }
+    }
+}
+
+fn watch_clipboard(
+    event_sender: Box<dyn EventSender>,
+    read: impl Fn() -> Option<String>,
+    last_text: Arc<Mutex<Option<String>>>,
+    stop_rx: mpsc::Receiver<()>,
+    interval: Duration,
+) {
+    /* What was copied before the sync started isn't shared. */
+    {
+        let mut last_text = last_text.lock().unwrap();
+        if last_text.is_none() {
+            *last_text = read();
+        }
+    }
+    while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+        /* The last seen text is kept while the clipboard has no text. */
+        let Some(text) = read() else {
+            continue;
+        };
+        {
+            let mut last_text = last_text.lock().unwrap();
+            if last_text.as_deref() == Some(text.as_str()) {
+                continue;
+            }
+            *last_text = Some(text.clone());
+        }
+        if let Err(e) = check_text(&text) {
+            log::warn!("watch_clipboard: not syncing copied text: {e}");
+            continue;
+        }
+        log::debug!("watch_clipboard: text copied, {} bytes", text.len());
+        if let Err(e) = event_sender.send(UserEvent::ClipboardChanged(text)) {
+            log::error!("watch_clipboard: error sending clipboard text: {e:?}");
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+
+    #[test]
+    fn test_watch_clipboard_sends_copied_text() {
+        let large = "a".repeat(MAX_CLIPBOARD_TEXT_BYTES + 1);
+        let reads = Arc::new(Mutex::new(vec![
+            Some("before".to_string()),
+            Some("before".to_string()),
+            None,
+            Some("copied".to_string()),
+            Some("copied".to_string()),
+            Some("pasted".to_string()),
+            Some(large),
+            Some("copied again".to_string()),
+        ]));
+        let last_text = Arc::new(Mutex::new(None));
+        let collector = EventCollector::new();
+        let (stop_tx, stop_rx) = mpsc::channel();
+
+        let watched_reads = reads.clone();
+        let written = last_text.clone();
+        let read = move || {
+            let mut reads = watched_reads.lock().unwrap();
+            let text = reads.remove(0);
+            /* Written for a controller, see `ClipboardSync::apply_remote`. */
+            if text.as_deref() == Some("pasted") {
+                *written.lock().unwrap() = text.clone();
+            }
+            if reads.is_empty() {
+                let _ = stop_tx.send(());
+            }
+            text
+        };
+        watch_clipboard(
+            Box::new(collector.clone()),
+            read,
+            last_text.clone(),
+            stop_rx,
+            Duration::from_millis(1),
+        );
+
+        let events = collector.take();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], UserEvent::ClipboardChanged(text) if text == "copied"));
+        assert!(matches!(&events[1], UserEvent::ClipboardChanged(text) if text == "copied again"));
+        assert_eq!(last_text.lock().unwrap().as_deref(), Some("copied again"));
+    }
+
+    #[test]
+    fn test_check_text() {
+        assert!(check_text("text").is_ok());
+        assert!(check_text(&"a".repeat(MAX_CLIPBOARD_TEXT_BYTES)).is_ok());
+        assert!(matches!(
+            check_text(&"a".repeat(MAX_CLIPBOARD_TEXT_BYTES + 1)),
+            Err(ClipboardError::TooLarge(_))
+        ));
+    }
+}
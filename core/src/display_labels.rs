@@ -0,0 +1,160 @@
+//! Overlays that identify the displays.
+//!
+//! When the sharer has several similar displays the thumbnails in the content
+//! picker aren't enough to tell them apart. While identifying, every display
+//! gets a short-lived, click-through overlay window with its label from the
+//! picker drawn in the middle.
+
+use std::time::{Duration, Instant};
+
+use winit::dpi::LogicalPosition;
+use winit::event_loop::ActiveEventLoop;
+use winit::monitor::MonitorHandle;
+use winit::window::WindowId;
+
+#[cfg(target_os = "macos")]
+use winit::platform::macos::WindowExtMacOS;
+
+#[cfg(target_os = "windows")]
+use winit::platform::windows::WindowExtWindows;
+
+use crate::graphics::graphics_context::cursor::Cursor;
+use crate::graphics::graphics_context::GraphicsContext;
+use crate::utils::svg_renderer::render_display_label_to_png;
+use crate::{get_window_attributes, set_fullscreen, ServerError};
+
+/// How long the labels stay on the displays
+pub const DISPLAY_LABEL_DURATION: Duration = Duration::from_secs(3);
+
+/// The cursor renderer draws textures at `scale / 2.5` of their size relative
+/// to the window, this draws the labels at their size in logical pixels.
+const DISPLAY_LABEL_SCALE: f64 = 2.5;
+
+/// The overlay window of a display and the label drawn on it.
+struct LabelWindow<'a> {
+    gfx: GraphicsContext<'a>,
+    label: Cursor,
+}
+
+/// The labels shown on the displays, the windows are closed when dropped.
+pub struct DisplayLabels<'a> {
+    windows: Vec<LabelWindow<'a>>,
+    shown_at: Instant,
+}
+
+impl<'a> DisplayLabels<'a> {
+    /// Opens an overlay window with a label on each display.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_loop` - Event loop used to create the windows
+    /// * `labels` - The displays and their labels
+    /// * `textures_path` - Path to texture resources, see `GraphicsContext::new`
+    /// * `render_scale` - Overlay render scale, see `GraphicsContext::new`
+    ///
+    /// # Returns
+    ///
+    /// The shown labels, or an error if any of the windows couldn't be created.
+    pub fn show(
+        event_loop: &ActiveEventLoop,
+        labels: Vec<(MonitorHandle, String)>,
+        textures_path: &str,
+        render_scale: Option<f64>,
+    ) -> Result<Self, ServerError> {
+        let mut windows = Vec::with_capacity(labels.len());
+        for (monitor, label) in labels {
+            windows.push(Self::create_label_window(
+                event_loop,
+                monitor,
+                &label,
+                textures_path,
+                render_scale,
+            )?);
+        }
+
+        for window in &windows {
+            window.gfx.window().request_redraw();
+        }
+
+        Ok(Self {
+            windows,
+            shown_at: Instant::now(),
+        })
+    }
+
+    fn create_label_window(
+        event_loop: &ActiveEventLoop,
+        monitor: MonitorHandle,
+        label: &str,
+        textures_path: &str,
+        render_scale: Option<f64>,
+    ) -> Result<LabelWindow<'a>, ServerError> {
+        log::info!("create_label_window: monitor: {monitor:?} label: {label}");
+        let window = event_loop
+            .create_window(get_window_attributes().with_title("Display label"))
+            .map_err(|_| ServerError::WindowCreationError)?;
+        window
+            .set_cursor_hittest(false)
+            .map_err(|_| ServerError::CursorHittestError)?;
+
+        #[cfg(target_os = "windows")]
+        {
+            window.set_skip_taskbar(true);
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            window.set_has_shadow(false);
+        }
+
+        window.set_visible(true);
+        let monitor_position = monitor.position();
+        window.set_outer_position(LogicalPosition::new(monitor_position.x, monitor_position.y));
+        if let Err(error) = set_fullscreen(&window, monitor.clone()) {
+            log::error!("create_label_window: Error setting fullscreen {error:?}");
+            return Err(ServerError::FullscreenError);
+        }
+
+        let scale = monitor.scale_factor();
+        let mut gfx = GraphicsContext::new(window, textures_path.to_string(), scale, render_scale)
+            .map_err(|error| {
+                log::error!("create_label_window: Error creating graphics context {error:?}");
+                ServerError::GfxCreationError(error.to_string())
+            })?;
+
+        let png = render_display_label_to_png(label).map_err(|error| {
+            log::error!("create_label_window: Error rendering label {error:?}");
+            ServerError::GfxCreationError(error.to_string())
+        })?;
+        let mut label = gfx
+            .create_cursor(&png, scale * DISPLAY_LABEL_SCALE)
+            .map_err(|error| {
+                log::error!("create_label_window: Error creating label {error:?}");
+                ServerError::GfxCreationError(error.to_string())
+            })?;
+        label.set_center(0.5, 0.5);
+
+        Ok(LabelWindow { gfx, label })
+    }
+
+    /// Draws the label of the window, returns `false` if the window isn't
+    /// one of the label windows.
+    pub fn draw(&self, window_id: WindowId) -> bool {
+        match self
+            .windows
+            .iter()
+            .find(|window| window.gfx.window().id() == window_id)
+        {
+            Some(window) => {
+                window.gfx.draw_label(&window.label);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` when the labels have been shown long enough.
+    pub fn expired(&self) -> bool {
+        self.shown_at.elapsed() >= DISPLAY_LABEL_DURATION
+    }
+}
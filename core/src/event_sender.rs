@@ -0,0 +1,181 @@
+//! Typed event delivery to the application's event loop.
+//!
+//! Subsystems (capturer, room service, cursor controller) don't talk to the
+//! winit `EventLoopProxy` directly, they get an `EventSender`. The proxy
+//! implements it for the real application and `EventCollector` implements it
+//! for tests, so the subsystems can be exercised without an event loop.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use socket_lib::{ConnectionQualityLevel, WaitReason};
+use winit::event_loop::{EventLoopClosed, EventLoopProxy};
+
+use crate::room::capabilities::PeerCapabilities;
+use crate::{ParticipantData, UserEvent};
+
+/// The event loop has exited, the undelivered event is returned.
+#[derive(Debug, thiserror::Error)]
+#[error("Event loop closed, failed to deliver {0:?}")]
+pub struct EventSendError(pub UserEvent);
+
+/// Sends `UserEvent`s to the application.
+///
+/// Only `send` and `boxed_clone` need to be implemented, the typed helpers
+/// build the matching `UserEvent`.
+pub trait EventSender: Debug + Send + 'static {
+    /// Delivers an event to the application.
+    fn send(&self, event: UserEvent) -> Result<(), EventSendError>;
+
+    /// Returns a boxed copy of the sender that delivers to the same destination.
+    fn boxed_clone(&self) -> Box<dyn EventSender>;
+
+    /// Reports the sharer's cursor position as a percentage of the shared display.
+    fn send_sharer_position(&self, x: f64, y: f64) -> Result<(), EventSendError> {
+        self.send(UserEvent::SharerPosition(x, y))
+    }
+
+    /// Reports a participant that joined the session.
+    fn send_participant_connected(
+        &self,
+        participant: ParticipantData,
+    ) -> Result<(), EventSendError> {
+        self.send(UserEvent::ParticipantConnected(participant))
+    }
+
+    /// Reports a participant that joined the session but isn't admitted yet.
+    fn send_participant_waiting(
+        &self,
+        participant: ParticipantData,
+        reason: WaitReason,
+    ) -> Result<(), EventSendError> {
+        self.send(UserEvent::ParticipantWaiting(participant, reason))
+    }
+
+    /// Reports a change of the connection quality of a participant.
+    fn send_participant_quality(
+        &self,
+        participant: ParticipantData,
+        quality: ConnectionQualityLevel,
+    ) -> Result<(), EventSendError> {
+        self.send(UserEvent::ParticipantQuality(participant, quality))
+    }
+
+    /// Reports the capabilities the participant `sid` announced.
+    fn send_participant_capabilities(
+        &self,
+        sid: String,
+        capabilities: PeerCapabilities,
+    ) -> Result<(), EventSendError> {
+        self.send(UserEvent::ParticipantCapabilities(sid, capabilities))
+    }
+
+    /// Reports a participant that left the session.
+    fn send_participant_disconnected(
+        &self,
+        participant: ParticipantData,
+    ) -> Result<(), EventSendError> {
+        self.send(UserEvent::ParticipantDisconnected(participant))
+    }
+
+    /// Asks the application to stop the screen share.
+    fn send_stop_screen_share(&self) -> Result<(), EventSendError> {
+        self.send(UserEvent::StopScreenShare)
+    }
+
+    /// Reports that a controller started sharing their screen.
+    fn send_controller_takes_screen_share(&self) -> Result<(), EventSendError> {
+        self.send(UserEvent::ControllerTakesScreenShare)
+    }
+
+    /// Asks for the overlay to be redrawn.
+    fn send_redraw_request(&self) -> Result<(), EventSendError> {
+        self.send(UserEvent::RequestRedraw)
+    }
+}
+
+impl Clone for Box<dyn EventSender> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+impl EventSender for Box<dyn EventSender> {
+    fn send(&self, event: UserEvent) -> Result<(), EventSendError> {
+        (**self).send(event)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn EventSender> {
+        (**self).boxed_clone()
+    }
+}
+
+impl EventSender for EventLoopProxy<UserEvent> {
+    fn send(&self, event: UserEvent) -> Result<(), EventSendError> {
+        self.send_event(event)
+            .map_err(|EventLoopClosed(event)| EventSendError(event))
+    }
+
+    fn boxed_clone(&self) -> Box<dyn EventSender> {
+        Box::new(self.clone())
+    }
+}
+
+/// `EventSender` that stores the events instead of delivering them.
+///
+/// Clones share the same storage, so a collector can be handed to a
+/// subsystem and inspected afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct EventCollector {
+    events: Arc<Mutex<Vec<UserEvent>>>,
+}
+
+impl EventCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the events sent so far, emptying the collector.
+    pub fn take(&self) -> Vec<UserEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
+
+impl EventSender for EventCollector {
+    fn send(&self, event: UserEvent) -> Result<(), EventSendError> {
+        self.events.lock().unwrap().push(event);
+        Ok(())
+    }
+
+    fn boxed_clone(&self) -> Box<dyn EventSender> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collector_receives_typed_events() {
+        let collector = EventCollector::new();
+        let sender: Box<dyn EventSender> = Box::new(collector.clone());
+
+        sender.send_sharer_position(0.5, 0.25).unwrap();
+        sender.clone().send_stop_screen_share().unwrap();
+        sender
+            .send_participant_connected(ParticipantData {
+                name: "Name".to_string(),
+                sid: "sid".to_string(),
+                identity: "user".to_string(),
+            })
+            .unwrap();
+
+        let events = collector.take();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[0], UserEvent::SharerPosition(x, y) if x == 0.5 && y == 0.25));
+        assert!(matches!(events[1], UserEvent::StopScreenShare));
+        assert!(matches!(&events[2], UserEvent::ParticipantConnected(data) if data.sid == "sid"));
+        assert!(collector.take().is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+//! Overlay assets loaded from the textures directory.
+//!
+//! The markers are PNGs in the textures directory and the cursor badges are
+//! drawn from built-in SVG templates, which a `cursor.svg` or `pointer.svg` in
+//! the directory replaces. The assets are read when the overlay is created and
+//! again on `UserEvent::ReloadAssets`, which the tauri app sends on request and
+//! the `AssetWatcher` sends when a file in the directory changes, so designers
+//! can iterate on the cursor art without restarting the session.
+
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use crate::event_sender::EventSender;
+use crate::UserEvent;
+
+const ASSET_WATCH_INTERVAL_MS: u64 = 500;
+
+/// File replacing the badge template of the arrow cursor
+pub const CURSOR_TEMPLATE_FILE: &str = "cursor.svg";
+/// File replacing the badge template of the pointing hand cursor
+pub const POINTER_TEMPLATE_FILE: &str = "pointer.svg";
+
+/// Badge templates read from the textures directory, see
+/// `svg_renderer::render_badge_template_to_png` for their placeholders.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BadgeTemplates {
+    cursor: Option<String>,
+    pointer: Option<String>,
+}
+
+impl BadgeTemplates {
+    /// Reads the templates in `texture_path`, a missing or unreadable file
+    /// leaves the built-in template in place.
+    pub fn load(texture_path: &str) -> Self {
+        let read = |file: &str| {
+            let path = Path::new(texture_path).join(file);
+            match std::fs::read_to_string(&path) {
+                Ok(template) => {
+                    log::info!("BadgeTemplates::load: using {}", path.display());
+                    Some(template)
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    log::error!("BadgeTemplates::load: failed to read {path:?}: {e:?}");
+                    None
+                }
+            }
+        };
+        Self {
+            cursor: read(CURSOR_TEMPLATE_FILE),
+            pointer: read(POINTER_TEMPLATE_FILE),
+        }
+    }
+
+    /// Returns the template of the pointing hand or the arrow cursor, `None`
+    /// when the built-in one is used.
+    pub fn get(&self, pointer: bool) -> Option<&str> {
+        if pointer {
+            self.pointer.as_deref()
+        } else {
+            self.cursor.as_deref()
+        }
+    }
+}
+
+/// Name, modification time and size of every asset in a directory.
+pub type AssetsFingerprint = Vec<(String, Option<SystemTime>, u64)>;
+
+fn is_asset(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.ends_with(".png") || name.ends_with(".svg")
+}
+
+/// Returns the fingerprint of the PNGs and SVGs in `dir`, sorted by name.
+///
+/// An unreadable directory has an empty fingerprint.
+pub fn fingerprint(dir: &Path) -> AssetsFingerprint {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut fingerprint: AssetsFingerprint = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !is_asset(&name) {
+                return None;
+            }
+            let metadata = entry.metadata().ok()?;
+            Some((name, metadata.modified().ok(), metadata.len()))
+        })
+        .collect();
+    fingerprint.sort();
+    fingerprint
+}
+
+/// Background thread that sends `UserEvent::ReloadAssets` when the assets in
+/// the textures directory change.
+///
+/// The thread is stopped when the watcher is dropped.
+pub struct AssetWatcher {
+    stop_tx: mpsc::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AssetWatcher {
+    /// Starts watching the directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_sender` - Where the reload events are sent
+    /// * `texture_path` - The textures directory
+    pub fn new(event_sender: Box<dyn EventSender>, texture_path: String) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            watch_assets(
+                event_sender,
+                || fingerprint(Path::new(&texture_path)),
+                stop_rx,
+                Duration::from_millis(ASSET_WATCH_INTERVAL_MS),
+            )
+        });
+        Self {
+            stop_tx,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for AssetWatcher {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("AssetWatcher::drop: watcher thread panicked");
+            }
+        }
+    }
+}
+
+fn watch_assets(
+    event_sender: Box<dyn EventSender>,
+    fingerprint: impl Fn() -> AssetsFingerprint,
+    stop_rx: mpsc::Receiver<()>,
+    interval: Duration,
+) {
+    let mut previous = fingerprint();
+    while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+        let current = fingerprint();
+        if current == previous {
+            continue;
+        }
+        log::info!("watch_assets: assets changed");
+        if let Err(e) = event_sender.send(UserEvent::ReloadAssets) {
+            log::error!("watch_assets: error sending reload: {e:?}");
+            break;
+        }
+        previous = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    fn assets_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hopp-assets-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = assets_dir("fingerprint");
+        std::fs::write(dir.join("marker_top_left.png"), b"png").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"notes").unwrap();
+        let first = fingerprint(&dir);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].0, "marker_top_left.png");
+
+        /* Only the assets count. */
+        std::fs::write(dir.join("notes.txt"), b"more notes").unwrap();
+        assert_eq!(fingerprint(&dir), first);
+
+        std::fs::write(dir.join("marker_top_left.png"), b"bigger png").unwrap();
+        assert_ne!(fingerprint(&dir), first);
+        std::fs::write(dir.join(CURSOR_TEMPLATE_FILE), b"<svg/>").unwrap();
+        assert_eq!(fingerprint(&dir).len(), 2);
+
+        assert!(fingerprint(&dir.join("missing")).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_badge_templates() {
+        let dir = assets_dir("templates");
+        let path = dir.to_string_lossy().into_owned();
+        assert_eq!(BadgeTemplates::load(&path), BadgeTemplates::default());
+
+        std::fs::write(dir.join(POINTER_TEMPLATE_FILE), "<svg>{name}</svg>").unwrap();
+        let templates = BadgeTemplates::load(&path);
+        assert_eq!(templates.get(true), Some("<svg>{name}</svg>"));
+        assert_eq!(templates.get(false), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_watch_assets_reports_changes() {
+        let fingerprints = Arc::new(Mutex::new(vec![
+            vec![("a.png".to_string(), None, 1)],
+            vec![("a.png".to_string(), None, 1)],
+            vec![("a.png".to_string(), None, 2)],
+            vec![("a.png".to_string(), None, 2)],
+            vec![],
+        ]));
+        let collector = EventCollector::new();
+        let (stop_tx, stop_rx) = mpsc::channel();
+
+        let next_fingerprint = move || {
+            let mut fingerprints = fingerprints.lock().unwrap();
+            if fingerprints.len() == 1 {
+                let _ = stop_tx.send(());
+                return fingerprints[0].clone();
+            }
+            fingerprints.remove(0)
+        };
+        watch_assets(
+            Box::new(collector.clone()),
+            next_fingerprint,
+            stop_rx,
+            Duration::from_millis(1),
+        );
+
+        let events = collector.take();
+        assert_eq!(events.len(), 2);
+        assert!(events
+            .iter()
+            .all(|event| matches!(event, UserEvent::ReloadAssets)));
+    }
+}
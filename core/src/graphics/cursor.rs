@@ -3,7 +3,10 @@
 //! This module provides a GPU-accelerated cursor rendering system using wgpu.
 //! It supports multiple cursors with individual textures, transforms, and positions.
 //! The system uses a shared transform buffer with dynamic offsets for efficient
-//! rendering of multiple cursors.
+//! rendering of multiple cursors. A dropped cursor gives its slot in the buffer
+//! back, so participants coming and going in a long session don't run out of them.
+
+use std::sync::{Arc, Mutex};
 
 use crate::utils::geometry::Extent;
 use wgpu::util::DeviceExt;
@@ -16,6 +19,10 @@ const MAX_CURSORS: u32 = 100;
 const BASE_OFFSET_X: f32 = 0.001;
 /// Base vertical offset for cursor positioning (as a fraction of screen space)
 const BASE_OFFSET_Y: f32 = 0.002;
+/// Position hidden cursors are moved to, outside of the overlay
+pub const HIDDEN_CURSOR_POSITION: f64 = -100.;
+/// Largest side of a cursor quad in clip space, half of the overlay
+const MAX_CURSOR_CLIP_EXTENT: f64 = 1.0;
 
 /// A 4x4 transformation matrix for GPU vertex transformations.
 ///
@@ -146,6 +153,10 @@ pub struct Cursor {
     transform_offset: wgpu::DynamicOffset,
     /// Position and transformation data
     position: Point,
+    /// Size of the cursor quad as a fraction of the window
+    extent: Extent,
+    /// Free slots of the renderer that created this cursor, the slot is returned on drop
+    free_slots: Arc<Mutex<Vec<wgpu::DynamicOffset>>>,
 }
 
 impl Cursor {
@@ -158,6 +169,32 @@ impl Cursor {
         self.position.set_position(x as f32, y as f32);
     }
 
+    /// Moves the cursor so that its center is at the given position.
+    ///
+    /// # Arguments
+    /// * `x` - X coordinate of the center (0.0 to 1.0, representing screen space)
+    /// * `y` - Y coordinate of the center (0.0 to 1.0, representing screen space)
+    pub fn set_center(&mut self, x: f64, y: f64) {
+        /* set_position shifts the quad by the base offsets, undo it. */
+        let x = x - self.extent.width / 2. + self.position.offset_x as f64;
+        let y = y - self.extent.height / 2. + self.position.offset_y as f64;
+        self.position.set_position(x as f32, y as f32);
+    }
+
+    /// Returns the GPU memory of the cursor's texture in bytes.
+    pub fn texture_bytes(&self) -> u64 {
+        texture_bytes(
+            self.texture.extent.width as u32,
+            self.texture.extent.height as u32,
+        )
+    }
+
+    /// Returns `true` when the cursor was moved out of the overlay to hide it.
+    pub fn is_hidden(&self) -> bool {
+        self.position.x <= HIDDEN_CURSOR_POSITION as f32
+            && self.position.y <= HIDDEN_CURSOR_POSITION as f32
+    }
+
     /// Returns the current transformation matrix for this cursor.
     ///
     /// This matrix can be used to position the cursor in 3D space or
@@ -174,8 +211,20 @@ impl Cursor {
     /// This method uploads the cursor's transformation matrix to the GPU
     /// at the appropriate offset in the shared buffer.
     pub fn update_transform_buffer(&self, gfx: &GraphicsContext) {
-        gfx.queue.write_buffer(
-            &gfx.cursor_renderer.transforms_buffer,
+        self.write_transform(&gfx.queue, &gfx.cursor_renderer);
+    }
+
+    /// Uploads this cursor's transformation matrix to the renderer's shared buffer.
+    ///
+    /// # Arguments
+    /// * `queue` - wgpu queue of the device the renderer was created on
+    /// * `renderer` - Renderer that created this cursor
+    ///
+    /// Unlike `update_transform_buffer` it doesn't need a window, the
+    /// offscreen tests render with it.
+    pub fn write_transform(&self, queue: &wgpu::Queue, renderer: &CursorsRenderer) {
+        queue.write_buffer(
+            &renderer.transforms_buffer,
             self.transform_offset as wgpu::BufferAddress,
             bytemuck::cast_slice(&[self.position.get_transform_matrix()]),
         );
@@ -190,18 +239,39 @@ impl Cursor {
     /// This method sets up the necessary bind groups, buffers, and draw call
     /// to render the cursor to the current render target.
     pub fn draw(&self, render_pass: &mut wgpu::RenderPass, gfx: &GraphicsContext) {
+        self.draw_with(render_pass, &gfx.cursor_renderer);
+    }
+
+    /// Renders this cursor with the shared resources of `renderer`.
+    ///
+    /// # Arguments
+    /// * `render_pass` - Active wgpu render pass using the renderer's pipeline
+    /// * `renderer` - Renderer that created this cursor
+    pub fn draw_with(&self, render_pass: &mut wgpu::RenderPass, renderer: &CursorsRenderer) {
         render_pass.set_bind_group(0, &self.texture.bind_group, &[]);
-        render_pass.set_bind_group(
-            1,
-            &gfx.cursor_renderer.transforms_bind_group,
-            &[self.transform_offset],
-        );
+        render_pass.set_bind_group(1, &renderer.transforms_bind_group, &[self.transform_offset]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         render_pass.draw_indexed(0..6, 0, 0..1);
     }
 }
 
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        self.free_slots.lock().unwrap().push(self.transform_offset);
+    }
+}
+
+/// Returns the GPU memory of a cursor texture of the given size, see `create_texture`.
+///
+/// # Arguments
+/// * `width` - Width of the image in pixels
+/// * `height` - Height of the image in pixels
+pub fn texture_bytes(width: u32, height: u32) -> u64 {
+    /* One RGBA8 level, without mipmaps. */
+    width as u64 * height as u64 * 4
+}
+
 /// Main cursor rendering system that manages multiple cursors.
 ///
 /// This renderer creates and manages the GPU resources needed for cursor rendering,
@@ -227,8 +297,10 @@ pub struct CursorsRenderer {
     pub transforms_buffer_entry_offset: wgpu::BufferAddress,
     /// Bind group for accessing the transform buffer
     pub transforms_bind_group: wgpu::BindGroup,
-    /// Number of cursors that have been created
+    /// Number of slots of the transform buffer handed out, the released ones included
     pub cursors_created: u32,
+    /// Offsets of the slots released by dropped cursors, reused before new ones
+    free_slots: Arc<Mutex<Vec<wgpu::DynamicOffset>>>,
 }
 
 impl CursorsRenderer {
@@ -396,6 +468,7 @@ impl CursorsRenderer {
             transforms_buffer_entry_offset: aligned_buffer_size,
             transforms_bind_group: transform_bind_group,
             cursors_created: 0,
+            free_slots: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -414,11 +487,12 @@ impl CursorsRenderer {
     ///
     /// # Errors
     /// Returns `OverlayError::TextureCreationError` if:
-    /// - The maximum number of cursors has been reached
+    /// - The maximum number of cursors is alive
     /// - Texture creation fails
     ///
     /// The cursor is automatically positioned at (0,0) and its transform matrix
-    /// is uploaded to the GPU.
+    /// is uploaded to the GPU. It takes the slot of a dropped cursor when there
+    /// is one.
     pub fn create_cursor(
         &mut self,
         image_data: &[u8],
@@ -427,7 +501,8 @@ impl CursorsRenderer {
         queue: &wgpu::Queue,
         window_size: Extent,
     ) -> Result<Cursor, OverlayError> {
-        if self.cursors_created >= MAX_CURSORS {
+        let free_slots = self.free_slots.lock().unwrap().len() as u32;
+        if self.cursors_created >= MAX_CURSORS && free_slots == 0 {
             log::error!("create_cursor: maximum number of cursors reached");
             return Err(OverlayError::TextureCreationError);
         }
@@ -436,13 +511,19 @@ impl CursorsRenderer {
         let texture = create_texture(device, queue, image_data, &self.texture_bind_group_layout)?;
 
         // Create vertex and index buffers for cursor geometry
-        let (vertex_buffer, index_buffer) =
+        let (vertex_buffer, index_buffer, clip_extent) =
             Self::create_cursor_vertex_buffer(device, &texture, scale, window_size);
 
-        // Calculate offset into shared transform buffer
-        let transform_offset =
-            (self.cursors_created as wgpu::BufferAddress) * self.transforms_buffer_entry_offset;
-        self.cursors_created += 1;
+        // Calculate offset into shared transform buffer, reusing a released slot first
+        let transform_offset = match self.free_slots.lock().unwrap().pop() {
+            Some(offset) => offset as wgpu::BufferAddress,
+            None => {
+                let offset = (self.cursors_created as wgpu::BufferAddress)
+                    * self.transforms_buffer_entry_offset;
+                self.cursors_created += 1;
+                offset
+            }
+        };
 
         // Initialize cursor position with base offsets
         let point = Point::new(
@@ -465,9 +546,59 @@ impl CursorsRenderer {
             index_buffer,
             transform_offset: transform_offset as wgpu::DynamicOffset,
             position: point,
+            /* Clip space spans two units in each direction. */
+            extent: Extent {
+                width: clip_extent.width / 2.,
+                height: clip_extent.height / 2.,
+            },
+            free_slots: self.free_slots.clone(),
         })
     }
 
+    /// Returns the number of cursors alive, the slots handed out and not released.
+    pub fn cursors_alive(&self) -> u32 {
+        self.cursors_created - self.free_slots.lock().unwrap().len() as u32
+    }
+
+    /// Replaces the image of an existing cursor.
+    ///
+    /// The cursor keeps its slot in the shared transform buffer and its
+    /// position, so reloading the cursor art doesn't use up cursor slots.
+    ///
+    /// # Arguments
+    /// * `cursor` - The cursor to update
+    /// * `image_data` - Loaded image data
+    /// * `scale` - Display scale
+    /// * `device` - wgpu device for creating GPU resources
+    /// * `queue` - wgpu queue for uploading data to GPU
+    /// * `window_size` - Size of the rendering window for proper scaling
+    ///
+    /// # Errors
+    /// Returns `OverlayError::TextureCreationError` if the texture creation
+    /// fails, the cursor is left unchanged then.
+    pub fn replace_cursor_image(
+        &self,
+        cursor: &mut Cursor,
+        image_data: &[u8],
+        scale: f64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        window_size: Extent,
+    ) -> Result<(), OverlayError> {
+        let texture = create_texture(device, queue, image_data, &self.texture_bind_group_layout)?;
+        let (vertex_buffer, index_buffer, clip_extent) =
+            Self::create_cursor_vertex_buffer(device, &texture, scale, window_size);
+
+        cursor.texture = texture;
+        cursor.vertex_buffer = vertex_buffer;
+        cursor.index_buffer = index_buffer;
+        cursor.extent = Extent {
+            width: clip_extent.width / 2.,
+            height: clip_extent.height / 2.,
+        };
+        Ok(())
+    }
+
     /// Creates vertex and index buffers for a cursor quad.
     ///
     /// # Arguments
@@ -477,7 +608,8 @@ impl CursorsRenderer {
     /// * `window_size` - Window dimensions for proper aspect ratio
     ///
     /// # Returns
-    /// A tuple containing (vertex_buffer, index_buffer) for the cursor quad.
+    /// A tuple containing (vertex_buffer, index_buffer, clip_extent) for the cursor quad,
+    /// `clip_extent` is the size of the quad in clip space.
     ///
     /// This method creates a quad that maintains the original texture aspect ratio
     /// while scaling appropriately for the target window size. The quad is positioned
@@ -488,7 +620,7 @@ impl CursorsRenderer {
         texture: &Texture,
         scale: f64,
         window_size: Extent,
-    ) -> (wgpu::Buffer, wgpu::Buffer) {
+    ) -> (wgpu::Buffer, wgpu::Buffer, Extent) {
         /*
          * Here we want to make the cursor size in the shader to always
          * be relative to the monitor extents. Also we want to keep the
@@ -496,11 +628,18 @@ impl CursorsRenderer {
          */
 
         // Calculate cursor size in clip space, maintaining aspect ratio
-        let clip_extent = Extent {
+        let mut clip_extent = Extent {
             width: (texture.extent.width / window_size.width) * 2.0 * scale / 2.5,
             height: (texture.extent.height / window_size.height) * 2.0 * scale / 2.5,
         };
 
+        /* Badges with a large label size still leave most of a small overlay visible. */
+        let fit = (MAX_CURSOR_CLIP_EXTENT / clip_extent.width)
+            .min(MAX_CURSOR_CLIP_EXTENT / clip_extent.height)
+            .min(1.0);
+        clip_extent.width *= fit;
+        clip_extent.height *= fit;
+
         // Create quad vertices with texture coordinates
         let vertices = vec![
             Vertex {
@@ -539,6 +678,180 @@ impl CursorsRenderer {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        (vertex_buffer, index_buffer)
+        (vertex_buffer, index_buffer, clip_extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::offscreen::{opaque_bounds, solid_png, OffscreenTarget};
+    use super::*;
+    use crate::utils::svg_renderer::render_user_badge_to_png;
+    use socket_lib::OverlayTheme;
+
+    const TARGET_WIDTH: u32 = 400;
+    const TARGET_HEIGHT: u32 = 200;
+    /* At this display scale a cursor is drawn with the size of its image. */
+    const NATIVE_SCALE: f64 = 2.5;
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    /* `None` only on machines without even a software adapter, the test is skipped. */
+    fn target() -> Option<OffscreenTarget> {
+        OffscreenTarget::new(TARGET_WIDTH, TARGET_HEIGHT)
+    }
+
+    /* The linear sampler can spill or drop a pixel at the edges. */
+    fn assert_near(actual: f64, expected: f64, what: &str) {
+        assert!(
+            (actual - expected).abs() <= 1.,
+            "{what}: expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn test_cursor_position() {
+        let Some(mut target) = target() else {
+            return;
+        };
+        let mut cursor = target
+            .create_cursor(&solid_png(20, 10, RED), NATIVE_SCALE)
+            .unwrap();
+
+        cursor.set_center(0.5, 0.5);
+        let bounds = opaque_bounds(&target.render(&[&cursor])).unwrap();
+        let (x, y) = bounds.center();
+        assert_near(x, 200., "center x");
+        assert_near(y, 100., "center y");
+
+        /* set_position places the top left corner, shifted by the base offsets. */
+        cursor.set_position(0.25, 0.5);
+        let bounds = opaque_bounds(&target.render(&[&cursor])).unwrap();
+        let offset_x = BASE_OFFSET_X as f64 * NATIVE_SCALE * TARGET_WIDTH as f64;
+        let offset_y = BASE_OFFSET_Y as f64 * NATIVE_SCALE * TARGET_HEIGHT as f64;
+        assert_near(bounds.left as f64, 100. - offset_x, "left");
+        assert_near(bounds.top as f64, 100. - offset_y, "top");
+    }
+
+    #[test]
+    fn test_cursor_scale() {
+        let Some(mut target) = target() else {
+            return;
+        };
+        let mut native = target
+            .create_cursor(&solid_png(20, 10, RED), NATIVE_SCALE)
+            .unwrap();
+        native.set_center(0.5, 0.5);
+        let bounds = opaque_bounds(&target.render(&[&native])).unwrap();
+        assert_near(bounds.width() as f64, 20., "native width");
+        assert_near(bounds.height() as f64, 10., "native height");
+
+        let mut doubled = target
+            .create_cursor(&solid_png(20, 10, RED), NATIVE_SCALE * 2.)
+            .unwrap();
+        doubled.set_center(0.5, 0.5);
+        let bounds = opaque_bounds(&target.render(&[&doubled])).unwrap();
+        assert_near(bounds.width() as f64, 40., "doubled width");
+        assert_near(bounds.height() as f64, 20., "doubled height");
+    }
+
+    #[test]
+    fn test_hidden_cursor_is_not_drawn() {
+        let Some(mut target) = target() else {
+            return;
+        };
+        let mut cursor = target
+            .create_cursor(&solid_png(20, 10, RED), NATIVE_SCALE)
+            .unwrap();
+        cursor.set_position(HIDDEN_CURSOR_POSITION, HIDDEN_CURSOR_POSITION);
+        assert!(cursor.is_hidden());
+        assert_eq!(opaque_bounds(&target.render(&[&cursor])), None);
+    }
+
+    #[test]
+    fn test_cursors_use_their_own_transforms() {
+        let Some(mut target) = target() else {
+            return;
+        };
+        let mut left = target
+            .create_cursor(&solid_png(10, 10, RED), NATIVE_SCALE)
+            .unwrap();
+        let mut right = target
+            .create_cursor(&solid_png(10, 10, [0, 0, 255, 255]), NATIVE_SCALE)
+            .unwrap();
+        left.set_center(0.25, 0.5);
+        right.set_center(0.75, 0.5);
+        let frame = target.render(&[&left, &right]);
+
+        let pixel = frame.get_pixel(100, 100);
+        assert!(pixel[0] > 200 && pixel[2] < 50, "left pixel {pixel:?}");
+        let pixel = frame.get_pixel(300, 100);
+        assert!(pixel[2] > 200 && pixel[0] < 50, "right pixel {pixel:?}");
+        assert_eq!(frame.get_pixel(200, 100)[3], 0);
+    }
+
+    #[test]
+    fn test_dropped_cursors_release_their_slots() {
+        let Some(mut target) = target() else {
+            return;
+        };
+        let png = solid_png(4, 4, RED);
+        let first = target.create_cursor(&png, NATIVE_SCALE).unwrap();
+        let second = target.create_cursor(&png, NATIVE_SCALE).unwrap();
+        assert_eq!(first.texture_bytes(), 64);
+        let offset = first.transform_offset;
+        drop(first);
+        let third = target.create_cursor(&png, NATIVE_SCALE).unwrap();
+        assert_eq!(third.transform_offset, offset);
+        assert_ne!(third.transform_offset, second.transform_offset);
+
+        /* Participants coming and going never run out of slots. */
+        for _ in 0..MAX_CURSORS * 2 {
+            target.create_cursor(&png, NATIVE_SCALE).unwrap();
+        }
+        let alive: Vec<_> = (2..MAX_CURSORS)
+            .map(|_| target.create_cursor(&png, NATIVE_SCALE).unwrap())
+            .collect();
+        assert!(target.create_cursor(&png, NATIVE_SCALE).is_err());
+        drop(alive);
+        assert!(target.create_cursor(&png, NATIVE_SCALE).is_ok());
+    }
+
+    #[test]
+    fn test_cursor_fits_overlay() {
+        let Some(mut target) = target() else {
+            return;
+        };
+        let mut wide = target
+            .create_cursor(&solid_png(400, 50, RED), NATIVE_SCALE)
+            .unwrap();
+        wide.set_center(0.5, 0.5);
+        let bounds = opaque_bounds(&target.render(&[&wide])).unwrap();
+        assert_near(bounds.width() as f64, 200., "clamped width");
+        assert_near(bounds.height() as f64, 25., "clamped height");
+    }
+
+    #[test]
+    fn test_label_layout() {
+        /* The badges are rendered at three times their view box, 600x180. */
+        let (width, height) = (800, 400);
+        let Some(mut target) = OffscreenTarget::new(width, height) else {
+            return;
+        };
+        let badge =
+            render_user_badge_to_png("#7CCF00", "Alice", false, &OverlayTheme::default(), 1.0)
+                .unwrap();
+        let badge_size = image::load_from_memory(&badge).unwrap();
+        let mut label = target.create_cursor(&badge, NATIVE_SCALE).unwrap();
+        label.set_center(0.5, 0.5);
+        let bounds = opaque_bounds(&target.render(&[&label])).unwrap();
+
+        /* The badge has transparent margins, its drawn part stays inside the quad. */
+        let quad_left = (width as f64 - badge_size.width() as f64) / 2.;
+        let quad_top = (height as f64 - badge_size.height() as f64) / 2.;
+        assert!(bounds.left as f64 >= quad_left - 1.);
+        assert!(bounds.top as f64 >= quad_top - 1.);
+        assert!(bounds.right as f64 <= quad_left + badge_size.width() as f64);
+        assert!(bounds.bottom as f64 <= quad_top + badge_size.height() as f64);
+        assert!(bounds.width() > 10);
     }
 }
@@ -3,8 +3,12 @@ use raw_window_handle::RawWindowHandle;
 use std::{os::raw::c_void, sync::Arc};
 use windows::core::*;
 use windows::Win32::{
-    Foundation::{HMODULE, HWND},
+    Foundation::{COLORREF, HMODULE, HWND},
     Graphics::{Direct2D::*, Direct3D::*, Direct3D11::*, DirectComposition::*, Dxgi::*},
+    UI::WindowsAndMessaging::{
+        GetWindowLongW, SetLayeredWindowAttributes, SetWindowLongW, GWL_EXSTYLE, LWA_COLORKEY,
+        WS_EX_LAYERED,
+    },
 };
 use winit::{raw_window_handle::HasWindowHandle, window::Window};
 
@@ -157,3 +161,21 @@ impl DirectComposition {
         Ok(())
     }
 }
+
+/*
+ * Fallback for when DirectComposition or transparent surfaces aren't available,
+ * makes the window layered and lets the pixels of the `key` color through.
+ */
+pub fn apply_color_key(window: &Window, key: [u8; 3]) -> std::result::Result<(), String> {
+    let hwnd = match window.window_handle().map(|handle| handle.as_raw()) {
+        Ok(RawWindowHandle::Win32(handle)) => HWND(handle.hwnd.get() as *mut c_void),
+        _ => return Err("failed to get raw win32 window handle".to_string()),
+    };
+    let color = COLORREF(key[0] as u32 | (key[1] as u32) << 8 | (key[2] as u32) << 16);
+    unsafe {
+        let win_style = GetWindowLongW(hwnd, GWL_EXSTYLE);
+        SetWindowLongW(hwnd, GWL_EXSTYLE, win_style | (WS_EX_LAYERED.0 as i32));
+        SetLayeredWindowAttributes(hwnd, color, 0, LWA_COLORKEY)
+            .map_err(|e| format!("failed to set the window color key: {e:?}"))
+    }
+}
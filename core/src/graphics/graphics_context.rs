@@ -4,16 +4,18 @@
 //! such as cursors and markers on top of shared screen content. It uses wgpu for
 //! hardware-accelerated rendering with proper alpha blending and transparent window support.
 
+use crate::graphics::assets::BadgeTemplates;
 use crate::input::mouse::CursorController;
 use crate::utils::geometry::Extent;
 use image::GenericImageView;
 use log::error;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
+use winit::monitor::MonitorHandle;
 use winit::window::Window;
 
 #[cfg(target_os = "windows")]
-use super::direct_composition::DirectComposition;
+use super::direct_composition::{apply_color_key, DirectComposition};
 
 #[path = "marker.rs"]
 mod marker;
@@ -23,6 +25,41 @@ use marker::MarkerRenderer;
 pub mod cursor;
 use cursor::{Cursor, CursorsRenderer};
 
+#[cfg(test)]
+#[path = "offscreen.rs"]
+pub(crate) mod offscreen;
+
+/// Overlays with more physical pixels than a 4K display are rendered at a lower resolution
+const HIGH_RESOLUTION_PIXELS: f64 = 3840. * 2160.;
+/// Render scale picked for overlays above `HIGH_RESOLUTION_PIXELS`
+const HIGH_RESOLUTION_RENDER_SCALE: f64 = 0.5;
+const MIN_RENDER_SCALE: f64 = 0.25;
+const MAX_RENDER_SCALE: f64 = 1.0;
+
+/// Surface formats the overlay can be transparent with, they have a full alpha channel.
+const TRANSPARENT_FORMATS: [wgpu::TextureFormat; 5] = [
+    wgpu::TextureFormat::Bgra8UnormSrgb,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Bgra8Unorm,
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+/// Color the overlay is cleared with when its surface is opaque, the window
+/// shows the pixels of this color as transparent.
+pub const COLOR_KEY: [u8; 3] = [255, 0, 255];
+
+/// Whether the windows of this platform can be made transparent with a color key.
+const COLOR_KEY_SUPPORTED: bool = cfg!(target_os = "windows");
+
+/// Environment variable picking the adapter the overlay is rendered on by
+/// a case-insensitive part of its name, e.g. `HOPP_OVERLAY_ADAPTER=nvidia`
+pub const ADAPTER_OVERRIDE_ENV: &str = "HOPP_OVERLAY_ADAPTER";
+
+/// Frames in a row the surface can be lost or outdated before `draw` gives
+/// up on reconfiguring it, half a second at 60fps
+const MAX_FAILED_FRAMES: u32 = 30;
+
 /// Errors that can occur during overlay graphics operations.
 #[derive(Error, Debug)]
 pub enum OverlayError {
@@ -45,6 +82,381 @@ pub enum OverlayError {
     /// Failed to create or load a texture resource.
     #[error("Failed to create or load texture resource")]
     TextureCreationError,
+
+    /// Every step of the surface negotiation failed.
+    #[error("Failed to negotiate the overlay surface: {}", describe_failures(.0))]
+    SurfaceNegotiationError(Vec<NegotiationFailure>),
+
+    /// The GPU device was lost, e.g. after a driver reset.
+    #[error("Overlay graphics device lost: {0}")]
+    DeviceLost(String),
+
+    /// Failed to acquire or present the frames of the overlay window.
+    #[error("Failed to present overlay frame: {0}")]
+    PresentError(String),
+}
+
+/// A step of the surface negotiation done by `GraphicsContext::new`, the
+/// steps are tried in order until one succeeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegotiationStep {
+    /// Transparent surface on the hardware adapter
+    Transparent,
+    /// Opaque surface cleared with `COLOR_KEY` on the hardware adapter
+    ColorKey,
+    /// Transparent, then color keyed, surface on the software adapter
+    Software,
+}
+
+const NEGOTIATION_STEPS: [NegotiationStep; 3] = [
+    NegotiationStep::Transparent,
+    NegotiationStep::ColorKey,
+    NegotiationStep::Software,
+];
+
+impl NegotiationStep {
+    /// Returns the surface modes tried by the step, in order.
+    fn modes(self) -> &'static [SurfaceMode] {
+        match self {
+            NegotiationStep::Transparent => &[SurfaceMode::Transparent],
+            NegotiationStep::ColorKey => &[SurfaceMode::ColorKey],
+            NegotiationStep::Software => &[SurfaceMode::Transparent, SurfaceMode::ColorKey],
+        }
+    }
+
+    fn software(self) -> bool {
+        self == NegotiationStep::Software
+    }
+
+    /// Returns whether `mode` is the last surface the negotiation can try,
+    /// it then settles for what the surface offers instead of failing.
+    fn last_resort(self, mode: SurfaceMode) -> bool {
+        if Some(&self) != NEGOTIATION_STEPS.last() {
+            return false;
+        }
+        match mode {
+            SurfaceMode::Transparent => !COLOR_KEY_SUPPORTED,
+            SurfaceMode::ColorKey => true,
+        }
+    }
+}
+
+impl std::fmt::Display for NegotiationStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            NegotiationStep::Transparent => "transparent surface",
+            NegotiationStep::ColorKey => "color keyed surface",
+            NegotiationStep::Software => "software adapter",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Why a step of the surface negotiation failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiationFailure {
+    pub step: NegotiationStep,
+    pub reason: String,
+}
+
+fn describe_failures(failures: &[NegotiationFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| format!("{}: {}", failure.step, failure.reason))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// How the overlay surface is composed with the desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceMode {
+    /// The compositor blends the surface using its alpha channel
+    Transparent,
+    /// The surface is opaque, the window makes the `COLOR_KEY` pixels transparent
+    ColorKey,
+}
+
+/// The surface configuration picked by the negotiation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurfaceChoice {
+    pub mode: SurfaceMode,
+    pub format: wgpu::TextureFormat,
+    pub alpha_mode: wgpu::CompositeAlphaMode,
+}
+
+/// Returns the alpha mode that makes the surface transparent.
+fn transparent_alpha_mode(
+    alpha_modes: &[wgpu::CompositeAlphaMode],
+) -> Option<wgpu::CompositeAlphaMode> {
+    let premultiplied = alpha_modes.contains(&wgpu::CompositeAlphaMode::PreMultiplied);
+    /*
+     * This is a workaround for windows, where we observed
+     * crashes with post multiplied alpha.
+     */
+    let post_multiplied = !cfg!(target_os = "windows")
+        && alpha_modes.contains(&wgpu::CompositeAlphaMode::PostMultiplied);
+    if premultiplied {
+        Some(wgpu::CompositeAlphaMode::PreMultiplied)
+    } else if post_multiplied {
+        Some(wgpu::CompositeAlphaMode::PostMultiplied)
+    } else {
+        None
+    }
+}
+
+/// Returns `wanted`, or the first of `offered` when it is the last resort.
+///
+/// # Arguments
+///
+/// * `wanted` - The value `mode` needs, `None` when the surface doesn't offer it
+/// * `offered` - What the surface offers, in its order of preference
+/// * `last_resort` - Whether failing would leave the overlay without a surface
+/// * `missing` - Why the surface can't be used without `wanted`
+fn wanted_or_first<T: Copy + std::fmt::Debug>(
+    wanted: Option<T>,
+    offered: &[T],
+    last_resort: bool,
+    missing: String,
+) -> Result<T, String> {
+    match (wanted, offered.first()) {
+        (Some(wanted), _) => Ok(wanted),
+        (None, Some(first)) if last_resort => {
+            log::warn!("choose_surface: {missing}, falling back to {first:?}");
+            Ok(*first)
+        }
+        (None, _) => Err(missing),
+    }
+}
+
+/// Picks the surface format and alpha mode for `mode` from the capabilities
+/// of a surface.
+///
+/// # Arguments
+///
+/// * `mode` - How the surface should be composed with the desktop
+/// * `formats` - The formats supported by the surface, in its order of preference
+/// * `alpha_modes` - The alpha modes supported by the surface
+/// * `last_resort` - Whether this is the last surface the negotiation tries,
+///   the first format and alpha mode are then used when none fit `mode`
+///
+/// # Returns
+///
+/// The surface configuration, or why the surface can't be used in `mode`.
+pub fn choose_surface(
+    mode: SurfaceMode,
+    formats: &[wgpu::TextureFormat],
+    alpha_modes: &[wgpu::CompositeAlphaMode],
+    last_resort: bool,
+) -> Result<SurfaceChoice, String> {
+    match mode {
+        SurfaceMode::Transparent => {
+            let alpha_mode = wanted_or_first(
+                transparent_alpha_mode(alpha_modes),
+                alpha_modes,
+                last_resort,
+                format!("no premultiplied or postmultiplied alpha mode in {alpha_modes:?}"),
+            )?;
+            let format = wanted_or_first(
+                formats
+                    .iter()
+                    .find(|format| TRANSPARENT_FORMATS.contains(*format))
+                    .copied(),
+                formats,
+                last_resort,
+                format!("no format with an alpha channel in {formats:?}"),
+            )?;
+            Ok(SurfaceChoice {
+                mode,
+                format,
+                alpha_mode,
+            })
+        }
+        SurfaceMode::ColorKey => {
+            if !COLOR_KEY_SUPPORTED {
+                return Err("color keyed windows aren't supported on this platform".to_string());
+            }
+            let opaque = alpha_modes
+                .contains(&wgpu::CompositeAlphaMode::Opaque)
+                .then_some(wgpu::CompositeAlphaMode::Opaque);
+            let alpha_mode = wanted_or_first(
+                opaque,
+                alpha_modes,
+                last_resort,
+                format!("no opaque alpha mode in {alpha_modes:?}"),
+            )?;
+            let format = formats
+                .first()
+                .copied()
+                .ok_or_else(|| "the surface has no formats".to_string())?;
+            Ok(SurfaceChoice {
+                mode,
+                format,
+                alpha_mode,
+            })
+        }
+    }
+}
+
+/// Returns the color the frames are cleared with in `mode`.
+fn clear_color(mode: SurfaceMode) -> wgpu::Color {
+    match mode {
+        SurfaceMode::Transparent => wgpu::Color::TRANSPARENT,
+        SurfaceMode::ColorKey => wgpu::Color {
+            r: COLOR_KEY[0] as f64 / 255.,
+            g: COLOR_KEY[1] as f64 / 255.,
+            b: COLOR_KEY[2] as f64 / 255.,
+            a: 1.0,
+        },
+    }
+}
+
+/// PCI ids of a graphics adapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdapterId {
+    pub vendor: u32,
+    pub device: u32,
+}
+
+/// An adapter the overlay's surface can be rendered on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterCandidate {
+    pub name: String,
+    pub id: AdapterId,
+    /// The adapter renders on the CPU
+    pub software: bool,
+}
+
+impl From<&wgpu::AdapterInfo> for AdapterCandidate {
+    fn from(info: &wgpu::AdapterInfo) -> Self {
+        Self {
+            name: info.name.clone(),
+            id: AdapterId {
+                vendor: info.vendor,
+                device: info.device,
+            },
+            software: info.device_type == wgpu::DeviceType::Cpu,
+        }
+    }
+}
+
+/// Picks the hardware adapter the overlay is rendered on.
+///
+/// On laptops with hybrid graphics the high performance adapter often isn't
+/// the one driving the shared display, presenting on it copies every frame
+/// across the GPUs and the overlay stutters. The adapter driving the display
+/// is preferred, unless another one is picked with `ADAPTER_OVERRIDE_ENV`.
+///
+/// # Arguments
+///
+/// * `candidates` - The adapters compatible with the overlay's surface
+/// * `display_adapter` - The adapter driving the overlay's display, when it is known
+/// * `override_name` - The value of `ADAPTER_OVERRIDE_ENV`
+///
+/// # Returns
+///
+/// The index of the adapter in `candidates`, `None` leaves the choice to wgpu.
+pub fn select_adapter(
+    candidates: &[AdapterCandidate],
+    display_adapter: Option<AdapterId>,
+    override_name: Option<&str>,
+) -> Option<usize> {
+    let hardware = || {
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| !candidate.software)
+    };
+
+    if let Some(name) = override_name.map(str::trim).filter(|name| !name.is_empty()) {
+        let name = name.to_lowercase();
+        match hardware().find(|(_, candidate)| candidate.name.to_lowercase().contains(&name)) {
+            Some((index, _)) => return Some(index),
+            None => {
+                log::warn!("select_adapter: no adapter matches {ADAPTER_OVERRIDE_ENV}={name:?}")
+            }
+        }
+    }
+
+    let display_adapter = display_adapter?;
+    hardware()
+        .find(|(_, candidate)| candidate.id == display_adapter)
+        .map(|(index, _)| index)
+}
+
+/// Returns the adapter driving `monitor`, from the DXGI outputs of the adapters.
+#[cfg(target_os = "windows")]
+fn display_adapter(monitor: &MonitorHandle) -> Option<AdapterId> {
+    use windows::Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIFactory1};
+    use winit::platform::windows::MonitorHandleExtWindows;
+
+    let monitor_name = monitor.native_id();
+    unsafe {
+        let factory: IDXGIFactory1 = match CreateDXGIFactory1() {
+            Ok(factory) => factory,
+            Err(e) => {
+                log::warn!("display_adapter: failed to create dxgi factory {e:?}");
+                return None;
+            }
+        };
+
+        let mut adapter_index = 0;
+        while let Ok(adapter) = factory.EnumAdapters1(adapter_index) {
+            adapter_index += 1;
+            let mut output_index = 0;
+            while let Ok(output) = adapter.EnumOutputs(output_index) {
+                output_index += 1;
+                let Ok(desc) = output.GetDesc() else {
+                    continue;
+                };
+                let name = String::from_utf16_lossy(
+                    desc.DeviceName[..].split(|&x| x == 0).next().unwrap_or(&[]),
+                );
+                if name != monitor_name {
+                    continue;
+                }
+                let desc = adapter.GetDesc1().ok()?;
+                return Some(AdapterId {
+                    vendor: desc.VendorId,
+                    device: desc.DeviceId,
+                });
+            }
+        }
+    }
+    log::warn!("display_adapter: no adapter drives {monitor_name:?}");
+    None
+}
+
+/// Returns the adapter driving `monitor`, the other platforms pick it themselves.
+#[cfg(not(target_os = "windows"))]
+fn display_adapter(_monitor: &MonitorHandle) -> Option<AdapterId> {
+    None
+}
+
+/// The surface of a window and, on Windows, what composes it with the desktop.
+struct WindowSurface<'a> {
+    surface: wgpu::Surface<'a>,
+    #[cfg(target_os = "windows")]
+    direct_composition: Option<DirectComposition>,
+}
+
+/// GPU resources of a surface that passed a negotiation step.
+struct NegotiatedSurface<'a> {
+    surface: wgpu::Surface<'a>,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    choice: SurfaceChoice,
+    #[cfg(target_os = "windows")]
+    direct_composition: Option<DirectComposition>,
+}
+
+/// A window on a mirror of the overlay's display, it shows the same frames.
+#[derive(Debug)]
+struct MirrorSurface<'a> {
+    surface: wgpu::Surface<'a>,
+    window: Arc<Window>,
+    #[cfg(target_os = "windows")]
+    _direct_composition: Option<DirectComposition>,
 }
 
 /// Type alias for Results in overlay graphics operations.
@@ -86,6 +498,34 @@ struct Vertex {
     texture_coords: [f32; 2],
 }
 
+/// Returns the resolution the overlay is rendered at, relative to its window size.
+///
+/// The compositor upscales the surface to the window. The overlay only renders
+/// a few small cursors, so on very large displays rendering at a lower resolution
+/// cuts its GPU memory and fill cost.
+///
+/// # Arguments
+///
+/// * `window_size` - The overlay window's size in physical pixels
+/// * `configured` - The render scale set by the user, when `None` it is picked from the window size
+///
+/// # Returns
+///
+/// The render scale clamped between `MIN_RENDER_SCALE` and `MAX_RENDER_SCALE`.
+pub fn render_scale(window_size: Extent, configured: Option<f64>) -> f64 {
+    let scale = configured.unwrap_or(
+        if window_size.width * window_size.height > HIGH_RESOLUTION_PIXELS {
+            HIGH_RESOLUTION_RENDER_SCALE
+        } else {
+            MAX_RENDER_SCALE
+        },
+    );
+    if scale.is_nan() {
+        return MAX_RENDER_SCALE;
+    }
+    scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE)
+}
+
 /// Core graphics context for overlay rendering operations.
 ///
 /// `GraphicsContext` encapsulates all the necessary GPU resources and state required
@@ -113,6 +553,12 @@ struct Vertex {
 pub struct GraphicsContext<'a> {
     /// wgpu surface for rendering to the window
     surface: wgpu::Surface<'a>,
+    /// Configuration of `surface`, the mirrors are configured the same
+    surface_config: wgpu::SurfaceConfiguration,
+    /// Instance and adapter the surface was negotiated on, the mirrors'
+    /// surfaces are created on them
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
     /// GPU logical device for creating resources and submitting commands
     device: wgpu::Device,
     /// Command queue for submitting GPU operations
@@ -122,12 +568,35 @@ pub struct GraphicsContext<'a> {
     /// Renderer for cursor graphics with multi-cursor support
     cursor_renderer: CursorsRenderer,
 
-    /// Windows-specific DirectComposition integration for transparent overlays
+    /// Windows-specific DirectComposition integration for transparent overlays,
+    /// `None` when the surface is color keyed
     #[cfg(target_os = "windows")]
-    _direct_composition: DirectComposition,
+    _direct_composition: Option<DirectComposition>,
+
+    /// How the surface is composed with the desktop
+    surface_mode: SurfaceMode,
 
     /// Renderer for corner markers indicating overlay boundaries
     marker_renderer: MarkerRenderer,
+
+    /// Directory the markers and badge templates are loaded from, kept for `reload_assets`
+    texture_path: String,
+    /// Display scale, picks the marker images
+    scale: f64,
+    /// Badge templates replacing the built-in ones
+    badge_templates: BadgeTemplates,
+
+    /// Whether the last presented frame had no cursors, while idle
+    /// nothing is presented until a cursor becomes visible
+    idle: bool,
+
+    /// Windows on the mirrors of the overlay's display, see `add_mirror`
+    mirrors: Vec<MirrorSurface<'a>>,
+
+    /// Why the device was lost, set by the device's lost callback
+    device_lost: Arc<Mutex<Option<String>>>,
+    /// Frames in a row that couldn't be presented to the overlay window
+    failed_frames: u32,
 }
 
 impl<'a> GraphicsContext<'a> {
@@ -142,120 +611,112 @@ impl<'a> GraphicsContext<'a> {
     /// * `window` - The overlay window to render to
     /// * `texture_path` - Base directory path for loading texture resources
     /// * `scale` - Display scale
+    /// * `render_scale` - Overlay render scale, see `render_scale`
     ///
     /// # Returns
     ///
     /// Returns a `Result` containing the initialized `GraphicsContext` on success,
     /// or an `OverlayError` if any initialization step fails.
     ///
+    /// # Surface Negotiation
+    ///
+    /// Transparent surfaces fail on some virtual machines and older GPUs, the
+    /// surface is negotiated by trying these steps in order:
+    /// 1. A transparent surface with a premultiplied alpha format on the hardware adapter
+    /// 2. An opaque surface on the hardware adapter, cleared with `COLOR_KEY`
+    ///    which the window shows as transparent
+    /// 3. The same two surfaces on the software adapter
+    ///
     /// # Errors
     ///
     /// This method can return several types of errors:
-    /// - `OverlayError::SurfaceCreationError` - Failed to create rendering surface
-    /// - `OverlayError::AdapterRequestError` - No suitable GPU adapter found
-    /// - `OverlayError::DeviceRequestError` - Failed to create logical GPU device
+    /// - `OverlayError::SurfaceNegotiationError` - Every negotiation step failed, with why each did
+    /// - `OverlayError::SurfaceCreationError` - Failed to commit the DirectComposition surface
     /// - `OverlayError::TextureCreationError` - Failed to initialize marker textures
     ///
     /// # Platform-Specific Behavior
     ///
-    /// - **Windows**: Initializes DirectComposition for transparent overlay rendering
-    pub fn new(window: Window, texture_path: String, scale: f64) -> OverlayResult<Self> {
-        log::info!("GraphicsContext::new: path: {texture_path:?} scale: {scale:?}");
+    /// - **Windows**: Initializes DirectComposition for transparent overlay rendering,
+    ///   color keyed surfaces use a layered window instead
+    /// - **macOS**: Color keyed windows aren't supported, only transparent surfaces are tried
+    pub fn new(
+        window: Window,
+        texture_path: String,
+        scale: f64,
+        render_scale: Option<f64>,
+    ) -> OverlayResult<Self> {
         let size = window.inner_size();
+        let render_scale = self::render_scale(size.into(), render_scale);
+        log::info!(
+            "GraphicsContext::new: path: {texture_path:?} scale: {scale:?} render_scale: {render_scale:?}"
+        );
         let window_arc = Arc::new(window);
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
             backends: wgpu::Backends::PRIMARY,
             ..Default::default()
         });
 
-        #[cfg(target_os = "windows")]
-        let direct_composition =
-            DirectComposition::new(window_arc.clone()).ok_or(OverlayError::SurfaceCreationError)?;
-
-        let surface = {
-            #[cfg(target_os = "windows")]
-            {
-                direct_composition.create_surface(&instance)?
-            }
-            #[cfg(target_os = "macos")]
-            {
-                instance.create_surface(window_arc.clone()).map_err(|e| {
-                    log::error!("GraphicsContext::new: {e:?}");
-                    OverlayError::SurfaceCreationError
-                })?
+        let mut failures = Vec::new();
+        let mut negotiated = None;
+        for step in NEGOTIATION_STEPS {
+            match Self::negotiate_step(&instance, &window_arc, step) {
+                Ok(surface) => {
+                    log::info!(
+                        "GraphicsContext::new: {step} negotiated: {:?}",
+                        surface.choice
+                    );
+                    negotiated = Some(surface);
+                    break;
+                }
+                Err(reason) => {
+                    log::warn!("GraphicsContext::new: {step} failed: {reason}");
+                    failures.push(NegotiationFailure { step, reason });
+                }
             }
-            // Add other OS targets here if needed
-            #[cfg(not(any(target_os = "windows", target_os = "macos")))]
-            {
-                // Default or error for unsupported OS
-                instance.create_surface(window_arc.clone()).map_err(|e| {
-                    log::error!("GraphicsContext::new: {:?}", e);
-                    OverlayError::SurfaceCreationError
-                })?
-            }
-        };
-
-        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        }));
-        if let Err(e) = adapter {
-            log::error!("GraphicsContext::new request_adapter: {e:?}");
-            return Err(OverlayError::AdapterRequestError);
         }
-        let adapter = adapter.unwrap();
-
-        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
-            required_features: wgpu::Features::empty(),
-            required_limits: wgpu::Limits::default(),
-            label: None,
-            memory_hints: wgpu::MemoryHints::default(),
-            trace: wgpu::Trace::default(),
-        }))
-        .map_err(|_| OverlayError::DeviceRequestError)?;
-
-        let surface_capabilities = surface.get_capabilities(&adapter);
-
-        let alpha_modes = surface_capabilities.alpha_modes;
-        let surface_formats = surface_capabilities.formats;
+        let NegotiatedSurface {
+            surface,
+            adapter,
+            device,
+            queue,
+            choice,
+            #[cfg(target_os = "windows")]
+            direct_composition,
+        } = negotiated.ok_or(OverlayError::SurfaceNegotiationError(failures))?;
 
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_formats[0],
-            width: size.width,
-            height: size.height,
+            format: choice.format,
+            /* The geometry is computed from the window size, only the surface is scaled. */
+            width: ((size.width as f64 * render_scale).round() as u32).max(1),
+            height: ((size.height as f64 * render_scale).round() as u32).max(1),
             present_mode: wgpu::PresentMode::AutoVsync, // This is using fifo or fifo_relaxed
-            alpha_mode: alpha_modes
-                .iter()
-                .find(|mode| {
-                    /*
-                     * This is a workaround for windows, where we observed
-                     * crashes with post multiplied alpha.
-                     */
-                    #[allow(unused_variables)]
-                    let post_multiplied = mode == &&wgpu::CompositeAlphaMode::PostMultiplied;
-                    #[cfg(target_os = "windows")]
-                    let post_multiplied = false;
-                    (mode != &&wgpu::CompositeAlphaMode::Opaque)
-                        && ((mode == &&wgpu::CompositeAlphaMode::PreMultiplied) || post_multiplied)
-                })
-                .copied()
-                .unwrap_or(alpha_modes[0]),
+            alpha_mode: choice.alpha_mode,
             view_formats: vec![],
             desired_maximum_frame_latency: 0,
         };
         surface.configure(&device, &surface_config);
 
-        #[cfg(target_os = "windows")]
-        direct_composition.commit()?;
+        let device_lost = Arc::new(Mutex::new(None));
+        device.set_device_lost_callback({
+            let device_lost = device_lost.clone();
+            move |reason, message| {
+                /* Dropping the context destroys the device. */
+                if matches!(reason, wgpu::DeviceLostReason::Destroyed) {
+                    return;
+                }
+                log::error!("GraphicsContext: device lost: {reason:?} {message}");
+                *device_lost.lock().unwrap() = Some(format!("{reason:?}: {message}"));
+            }
+        });
 
         /*
          * Workaround for resetting the default white background
          * on transparent windows on windows.
          */
         #[cfg(target_os = "windows")]
-        {
+        if let Some(direct_composition) = &direct_composition {
+            direct_composition.commit()?;
             window_arc.set_minimized(true);
             std::thread::sleep(std::time::Duration::from_millis(100));
             window_arc.set_minimized(false);
@@ -277,16 +738,320 @@ impl<'a> GraphicsContext<'a> {
 
         Ok(Self {
             surface,
+            surface_config,
+            instance,
+            adapter,
             device,
             queue,
             window: window_arc,
             cursor_renderer,
             #[cfg(target_os = "windows")]
             _direct_composition: direct_composition,
+            surface_mode: choice.mode,
             marker_renderer,
+            badge_templates: BadgeTemplates::load(&texture_path),
+            texture_path,
+            scale,
+            idle: false,
+            mirrors: Vec::new(),
+            device_lost,
+            failed_frames: 0,
         })
     }
 
+    /// Reloads the overlay assets from the textures directory.
+    ///
+    /// The marker textures are recreated and the badge templates are read
+    /// again, the cursors are re-rendered by `CursorController::reload_cursors`.
+    /// The next frame is drawn even when the overlay is idle.
+    ///
+    /// # Errors
+    ///
+    /// `OverlayError::TextureCreationError` when a marker image can't be
+    /// loaded, the previous markers are kept then.
+    pub fn reload_assets(&mut self) -> OverlayResult<()> {
+        log::info!("reload_assets: path: {:?}", self.texture_path);
+        self.badge_templates = BadgeTemplates::load(&self.texture_path);
+        self.idle = false;
+
+        let size = self.window.inner_size();
+        self.marker_renderer = MarkerRenderer::new(
+            &self.device,
+            &self.queue,
+            self.surface_config.format,
+            &self.texture_path,
+            Extent {
+                width: size.width as f64,
+                height: size.height as f64,
+            },
+            self.scale,
+        )?;
+        Ok(())
+    }
+
+    /// Returns the badge templates read from the textures directory.
+    pub fn badge_templates(&self) -> &BadgeTemplates {
+        &self.badge_templates
+    }
+
+    /// Adds a window on a mirror of the overlay's display.
+    ///
+    /// Every frame is presented to the mirrors too, they share the overlay's
+    /// device and cursors, so presenters see the remote cursors on a
+    /// mirrored projector even when the platform doesn't mirror the overlay.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Window covering the mirror, the same size as the overlay window
+    ///
+    /// # Errors
+    ///
+    /// `OverlayError::SurfaceCreationError` when the window has another size
+    /// or its surface can't be configured like the overlay's.
+    pub fn add_mirror(&mut self, window: Window) -> OverlayResult<()> {
+        let window = Arc::new(window);
+        if window.inner_size() != self.window.inner_size() {
+            log::error!(
+                "GraphicsContext::add_mirror: mirror size {:?} doesn't match {:?}",
+                window.inner_size(),
+                self.window.inner_size()
+            );
+            return Err(OverlayError::SurfaceCreationError);
+        }
+
+        let WindowSurface {
+            surface,
+            #[cfg(target_os = "windows")]
+            direct_composition,
+        } = Self::create_window_surface(&self.instance, &window, self.surface_mode).map_err(
+            |e| {
+                log::error!("GraphicsContext::add_mirror: {e}");
+                OverlayError::SurfaceCreationError
+            },
+        )?;
+        let capabilities = surface.get_capabilities(&self.adapter);
+        if !capabilities.formats.contains(&self.surface_config.format)
+            || !capabilities
+                .alpha_modes
+                .contains(&self.surface_config.alpha_mode)
+        {
+            log::error!(
+                "GraphicsContext::add_mirror: mirror doesn't support {:?} {:?}",
+                self.surface_config.format,
+                self.surface_config.alpha_mode
+            );
+            return Err(OverlayError::SurfaceCreationError);
+        }
+        surface.configure(&self.device, &self.surface_config);
+
+        #[cfg(target_os = "windows")]
+        {
+            match &direct_composition {
+                Some(direct_composition) => {
+                    direct_composition.commit()?;
+                    /* Same workaround for the white background as in `new`. */
+                    window.set_minimized(true);
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    window.set_minimized(false);
+                }
+                None => apply_color_key(&window, COLOR_KEY).map_err(|e| {
+                    log::error!("GraphicsContext::add_mirror: {e}");
+                    OverlayError::SurfaceCreationError
+                })?,
+            }
+        }
+
+        log::info!("GraphicsContext::add_mirror: {:?}", window.id());
+        self.mirrors.push(MirrorSurface {
+            surface,
+            window,
+            #[cfg(target_os = "windows")]
+            _direct_composition: direct_composition,
+        });
+        Ok(())
+    }
+
+    /// Runs one step of the surface negotiation.
+    ///
+    /// # Arguments
+    ///
+    /// * `instance` - The wgpu instance
+    /// * `window` - The overlay window
+    /// * `step` - The step to run
+    ///
+    /// # Returns
+    ///
+    /// The configured GPU resources, or which part of the step failed.
+    fn negotiate_step(
+        instance: &wgpu::Instance,
+        window: &Arc<Window>,
+        step: NegotiationStep,
+    ) -> Result<NegotiatedSurface<'a>, String> {
+        let mut reasons = Vec::new();
+        for &mode in step.modes() {
+            match Self::negotiate_surface(instance, window, mode, step) {
+                Ok(surface) => return Ok(surface),
+                Err(reason) => reasons.push(reason),
+            }
+        }
+        Err(reasons.join(", "))
+    }
+
+    /// Creates the surface of `window` for `mode`, on Windows transparent
+    /// surfaces are composed with DirectComposition.
+    fn create_window_surface(
+        instance: &wgpu::Instance,
+        window: &Arc<Window>,
+        mode: SurfaceMode,
+    ) -> Result<WindowSurface<'a>, String> {
+        if mode == SurfaceMode::ColorKey && !COLOR_KEY_SUPPORTED {
+            return Err("color keyed windows aren't supported on this platform".to_string());
+        }
+
+        #[cfg(target_os = "windows")]
+        let direct_composition = match mode {
+            SurfaceMode::Transparent => Some(
+                DirectComposition::new(window.clone())
+                    .ok_or_else(|| "failed to set up DirectComposition".to_string())?,
+            ),
+            SurfaceMode::ColorKey => None,
+        };
+
+        let surface = {
+            #[cfg(target_os = "windows")]
+            {
+                match &direct_composition {
+                    Some(direct_composition) => direct_composition
+                        .create_surface(instance)
+                        .map_err(|e| format!("failed to create composition surface: {e}"))?,
+                    None => instance
+                        .create_surface(window.clone())
+                        .map_err(|e| format!("failed to create surface: {e}"))?,
+                }
+            }
+            #[cfg(not(target_os = "windows"))]
+            {
+                instance
+                    .create_surface(window.clone())
+                    .map_err(|e| format!("failed to create surface: {e}"))?
+            }
+        };
+
+        Ok(WindowSurface {
+            surface,
+            #[cfg(target_os = "windows")]
+            direct_composition,
+        })
+    }
+
+    fn negotiate_surface(
+        instance: &wgpu::Instance,
+        window: &Arc<Window>,
+        mode: SurfaceMode,
+        step: NegotiationStep,
+    ) -> Result<NegotiatedSurface<'a>, String> {
+        let software = step.software();
+        let WindowSurface {
+            surface,
+            #[cfg(target_os = "windows")]
+            direct_composition,
+        } = Self::create_window_surface(instance, window, mode)?;
+
+        let adapter_kind = if software { "software" } else { "hardware" };
+        let preferred = if software {
+            None
+        } else {
+            Self::preferred_adapter(instance, window, &surface)
+        };
+        let adapter = match preferred {
+            Some(adapter) => adapter,
+            None => pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: software,
+            }))
+            .map_err(|e| format!("no {adapter_kind} adapter: {e}"))?,
+        };
+        let info = adapter.get_info();
+        log::info!(
+            "GraphicsContext::negotiate_surface: {mode:?} on {} ({:?}, {:?})",
+            info.name,
+            info.backend,
+            info.device_type
+        );
+
+        let capabilities = surface.get_capabilities(&adapter);
+        let choice = choose_surface(
+            mode,
+            &capabilities.formats,
+            &capabilities.alpha_modes,
+            step.last_resort(mode),
+        )
+        .map_err(|e| format!("{} on {}", e, info.name))?;
+
+        /* Software adapters don't always reach the default limits. */
+        let required_limits = if software {
+            wgpu::Limits::downlevel_defaults()
+        } else {
+            wgpu::Limits::default()
+        };
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_features: wgpu::Features::empty(),
+            required_limits,
+            label: None,
+            memory_hints: wgpu::MemoryHints::default(),
+            trace: wgpu::Trace::default(),
+        }))
+        .map_err(|e| format!("failed to request device on {}: {e}", info.name))?;
+
+        #[cfg(target_os = "windows")]
+        if mode == SurfaceMode::ColorKey {
+            apply_color_key(window, COLOR_KEY)?;
+        }
+
+        Ok(NegotiatedSurface {
+            surface,
+            adapter,
+            device,
+            queue,
+            choice,
+            #[cfg(target_os = "windows")]
+            direct_composition,
+        })
+    }
+
+    /// Returns the hardware adapter picked by `select_adapter` for the
+    /// surface of `window`, `None` leaves the choice to wgpu.
+    fn preferred_adapter(
+        instance: &wgpu::Instance,
+        window: &Arc<Window>,
+        surface: &wgpu::Surface,
+    ) -> Option<wgpu::Adapter> {
+        let adapters = instance
+            .enumerate_adapters(wgpu::Backends::PRIMARY)
+            .into_iter()
+            .filter(|adapter| adapter.is_surface_supported(surface))
+            .collect::<Vec<_>>();
+        let candidates = adapters
+            .iter()
+            .map(|adapter| AdapterCandidate::from(&adapter.get_info()))
+            .collect::<Vec<_>>();
+        let display_adapter = window
+            .current_monitor()
+            .and_then(|monitor| display_adapter(&monitor));
+        let override_name = std::env::var(ADAPTER_OVERRIDE_ENV).ok();
+        log::info!(
+            "GraphicsContext::preferred_adapter: candidates: {candidates:?} display adapter: {display_adapter:?} override: {override_name:?}"
+        );
+
+        let index = select_adapter(&candidates, display_adapter, override_name.as_deref())?;
+        log::info!(
+            "GraphicsContext::preferred_adapter: chose {}",
+            candidates[index].name
+        );
+        adapters.into_iter().nth(index)
+    }
+
     /// Creates a new cursor with the specified image and scale factor.
     ///
     /// This method loads a cursor image from disk and creates all necessary GPU
@@ -321,6 +1086,33 @@ impl<'a> GraphicsContext<'a> {
         )
     }
 
+    /// Replaces the image of a cursor created by `create_cursor`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - The cursor to update, it keeps its position
+    /// * `image_data` - Loaded image data
+    /// * `display_scale` - Display scale
+    pub fn replace_cursor_image(
+        &mut self,
+        cursor: &mut Cursor,
+        image_data: &[u8],
+        display_scale: f64,
+    ) -> std::result::Result<(), OverlayError> {
+        let window_size = self.window.inner_size();
+        self.cursor_renderer.replace_cursor_image(
+            cursor,
+            image_data,
+            display_scale,
+            &self.device,
+            &self.queue,
+            Extent {
+                width: window_size.width as f64,
+                height: window_size.height as f64,
+            },
+        )
+    }
+
     /// Renders the current frame with all overlay elements.
     ///
     /// This method performs a complete render pass for the overlay, drawing all
@@ -334,23 +1126,112 @@ impl<'a> GraphicsContext<'a> {
     ///
     /// The draw operation follows this sequence:
     /// 1. Acquire the current frame buffer from the surface
-    /// 2. Clear the frame buffer with transparent black (0,0,0,0)
+    /// 2. Clear the frame buffer with transparent black (0,0,0,0), or with
+    ///    `COLOR_KEY` when the surface is color keyed
     /// 3. Set up the cursor rendering pipeline
     /// 4. Render all active cursors via the cursor controller
     /// 5. Render corner markers for overlay boundaries
     /// 6. Submit commands to GPU and present the frame
     ///
+    /// # Idle Mode
+    ///
+    /// When no cursor is visible one last frame is presented, which leaves only
+    /// the markers on screen, and then drawing is skipped until a cursor becomes
+    /// visible again. This keeps the GPU idle during passive viewing sessions.
+    ///
     /// # Error Handling
     ///
-    /// If frame acquisition fails (e.g., surface lost), the method logs the error
-    /// and returns early without crashing. This provides resilience against
-    /// temporary graphics driver issues or window state changes.
-    pub fn draw(&self, cursor_controller: &CursorController) {
-        let output = match self.surface.get_current_texture() {
+    /// A frame that times out is skipped. A lost or outdated surface is
+    /// reconfigured and the frame is skipped, this provides resilience against
+    /// window state changes. The context is unusable when the device was lost,
+    /// the surface ran out of memory or kept failing for `MAX_FAILED_FRAMES`,
+    /// and it has to be recreated.
+    ///
+    /// # Returns
+    ///
+    /// - `OverlayError::DeviceLost` - The GPU device was lost
+    /// - `OverlayError::PresentError` - The frames can't be presented anymore
+    pub fn draw(&mut self, cursor_controller: &CursorController) -> OverlayResult<()> {
+        if let Some(reason) = self.device_lost.lock().unwrap().clone() {
+            return Err(OverlayError::DeviceLost(reason));
+        }
+        let has_visible_cursors = cursor_controller.has_visible_cursors();
+        if self.idle && !has_visible_cursors {
+            return Ok(());
+        }
+
+        let res = self.present(|render_pass, gfx| {
+            cursor_controller.draw(render_pass, gfx);
+            gfx.marker_renderer.draw(render_pass);
+        });
+        match res {
+            Ok(()) => self.failed_frames = 0,
+            Err(wgpu::SurfaceError::Timeout) => return Ok(()),
+            Err(e @ (wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
+                self.failed_frames += 1;
+                if self.failed_frames >= MAX_FAILED_FRAMES {
+                    return Err(OverlayError::PresentError(format!(
+                        "{e} for {} frames",
+                        self.failed_frames
+                    )));
+                }
+                self.surface.configure(&self.device, &self.surface_config);
+                return Ok(());
+            }
+            Err(e) => return Err(OverlayError::PresentError(e.to_string())),
+        }
+
+        if self.idle == has_visible_cursors {
+            log::debug!("GraphicsContext::draw: idle: {}", !has_visible_cursors);
+            self.idle = !has_visible_cursors;
+        }
+        Ok(())
+    }
+
+    /// Renders a frame with only the given label, used by the overlays that
+    /// identify the displays.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - Label created with `create_cursor` on this context
+    pub fn draw_label(&self, label: &Cursor) {
+        label.update_transform_buffer(self);
+        /* Logged by present_to, the label is drawn again on the next redraw. */
+        let _ = self.present(|render_pass, gfx| label.draw(render_pass, gfx));
+    }
+
+    /// Clears the frame buffer, records the draw calls of `draw` and presents
+    /// the frame, to the overlay window and then to every mirror.
+    ///
+    /// # Returns
+    ///
+    /// The error acquiring the frame of the overlay window, nothing was
+    /// presented to it then. The mirrors' errors are only logged.
+    fn present(
+        &self,
+        draw: impl Fn(&mut wgpu::RenderPass, &Self),
+    ) -> Result<(), wgpu::SurfaceError> {
+        let presented = self.present_to(&self.surface, &self.window, &draw);
+        for mirror in &self.mirrors {
+            let _ = self.present_to(&mirror.surface, &mirror.window, &draw);
+        }
+        presented
+    }
+
+    fn present_to(
+        &self,
+        surface: &wgpu::Surface,
+        window: &Window,
+        draw: &impl Fn(&mut wgpu::RenderPass, &Self),
+    ) -> Result<(), wgpu::SurfaceError> {
+        let output = match surface.get_current_texture() {
             Ok(output) => output,
             Err(e) => {
-                log::error!("GraphicsContext::draw: failed to get current texture: {e:?}");
-                return;
+                log::error!(
+                    "GraphicsContext::present_to: failed to get current texture of {:?}: {e:?}",
+                    window.id()
+                );
+                return Err(e);
             }
         };
         let view = output
@@ -367,12 +1248,7 @@ impl<'a> GraphicsContext<'a> {
                 view: &view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.0,
-                        g: 0.0,
-                        b: 0.0,
-                        a: 0.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(clear_color(self.surface_mode)),
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -382,17 +1258,17 @@ impl<'a> GraphicsContext<'a> {
         });
         render_pass.set_pipeline(&self.cursor_renderer.render_pipeline);
 
-        cursor_controller.draw(&mut render_pass, self);
-
-        self.marker_renderer.draw(&mut render_pass);
+        draw(&mut render_pass, self);
 
         drop(render_pass);
 
         self.queue.submit(std::iter::once(encoder.finish()));
 
-        self.window.pre_present_notify();
+        window.pre_present_notify();
 
         output.present();
+
+        Ok(())
     }
 
     /// Returns a reference to the underlying overlay window.
@@ -506,3 +1382,150 @@ fn create_texture(
         bind_group: diffuse_bind_group,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_scale() {
+        let retina = Extent::new(2880., 1800.);
+        let five_k = Extent::new(5120., 2880.);
+        assert_eq!(render_scale(retina, None), 1.0);
+        assert_eq!(render_scale(five_k, None), HIGH_RESOLUTION_RENDER_SCALE);
+        assert_eq!(render_scale(retina, Some(0.75)), 0.75);
+        assert_eq!(render_scale(five_k, Some(2.0)), MAX_RENDER_SCALE);
+        assert_eq!(render_scale(five_k, Some(0.)), MIN_RENDER_SCALE);
+        assert_eq!(render_scale(five_k, Some(f64::NAN)), MAX_RENDER_SCALE);
+    }
+
+    #[test]
+    fn test_choose_transparent_surface() {
+        use wgpu::{CompositeAlphaMode, TextureFormat};
+
+        let formats = [TextureFormat::Rgb10a2Unorm, TextureFormat::Bgra8Unorm];
+        let alpha_modes = [
+            CompositeAlphaMode::Opaque,
+            CompositeAlphaMode::PreMultiplied,
+        ];
+        let choice =
+            choose_surface(SurfaceMode::Transparent, &formats, &alpha_modes, false).unwrap();
+        assert_eq!(choice.format, TextureFormat::Bgra8Unorm);
+        assert_eq!(choice.alpha_mode, CompositeAlphaMode::PreMultiplied);
+
+        /* Surfaces that are only opaque are left to the color key step. */
+        let opaque = [CompositeAlphaMode::Opaque, CompositeAlphaMode::Inherit];
+        let error = choose_surface(SurfaceMode::Transparent, &formats, &opaque, false).unwrap_err();
+        assert!(error.contains("no premultiplied or postmultiplied alpha mode"));
+
+        let error = choose_surface(
+            SurfaceMode::Transparent,
+            &[TextureFormat::Rgb10a2Unorm],
+            &alpha_modes,
+            false,
+        )
+        .unwrap_err();
+        assert!(error.contains("alpha channel"));
+
+        /* Without another surface to try, the first ones are used. */
+        let choice = choose_surface(
+            SurfaceMode::Transparent,
+            &[TextureFormat::Rgb10a2Unorm],
+            &opaque,
+            true,
+        )
+        .unwrap();
+        assert_eq!(choice.format, TextureFormat::Rgb10a2Unorm);
+        assert_eq!(choice.alpha_mode, CompositeAlphaMode::Opaque);
+        assert!(choose_surface(SurfaceMode::Transparent, &formats, &[], true).is_err());
+    }
+
+    #[test]
+    fn test_last_resort_surface() {
+        for step in [NegotiationStep::Transparent, NegotiationStep::ColorKey] {
+            assert!(!step.last_resort(SurfaceMode::Transparent));
+            assert!(!step.last_resort(SurfaceMode::ColorKey));
+        }
+        let step = NegotiationStep::Software;
+        assert_eq!(
+            step.last_resort(SurfaceMode::Transparent),
+            !COLOR_KEY_SUPPORTED
+        );
+        assert!(step.last_resort(SurfaceMode::ColorKey));
+    }
+
+    #[test]
+    fn test_choose_color_keyed_surface() {
+        use wgpu::{CompositeAlphaMode, TextureFormat};
+
+        let result = choose_surface(
+            SurfaceMode::ColorKey,
+            &[TextureFormat::Rgb10a2Unorm],
+            &[CompositeAlphaMode::Opaque],
+            false,
+        );
+        if COLOR_KEY_SUPPORTED {
+            let choice = result.unwrap();
+            assert_eq!(choice.format, TextureFormat::Rgb10a2Unorm);
+            assert_eq!(choice.alpha_mode, CompositeAlphaMode::Opaque);
+            assert_eq!(clear_color(choice.mode).a, 1.0);
+        } else {
+            assert!(result.is_err());
+        }
+    }
+
+    fn candidate(name: &str, vendor: u32, software: bool) -> AdapterCandidate {
+        AdapterCandidate {
+            name: name.to_string(),
+            id: AdapterId { vendor, device: 1 },
+            software,
+        }
+    }
+
+    #[test]
+    fn test_select_adapter() {
+        let candidates = [
+            candidate("NVIDIA GeForce RTX 4060 Laptop GPU", 0x10de, false),
+            candidate("Intel(R) UHD Graphics", 0x8086, false),
+            candidate("Microsoft Basic Render Driver", 0x1414, true),
+        ];
+        let intel = Some(AdapterId {
+            vendor: 0x8086,
+            device: 1,
+        });
+
+        /* The integrated GPU drives the laptop's display. */
+        assert_eq!(select_adapter(&candidates, intel, None), Some(1));
+        assert_eq!(select_adapter(&candidates, intel, Some("nvidia")), Some(0));
+        assert_eq!(select_adapter(&candidates, intel, Some("  ")), Some(1));
+        assert_eq!(select_adapter(&candidates, intel, Some("amd")), Some(1));
+        /* The override never picks the software adapter. */
+        assert_eq!(select_adapter(&candidates, None, Some("basic")), None);
+
+        let unknown = Some(AdapterId {
+            vendor: 0x1002,
+            device: 1,
+        });
+        assert_eq!(select_adapter(&candidates, unknown, None), None);
+        assert_eq!(select_adapter(&candidates, None, None), None);
+    }
+
+    #[test]
+    fn test_negotiation_error_describes_steps() {
+        let error = OverlayError::SurfaceNegotiationError(vec![
+            NegotiationFailure {
+                step: NegotiationStep::Transparent,
+                reason: "no premultiplied alpha mode".to_string(),
+            },
+            NegotiationFailure {
+                step: NegotiationStep::Software,
+                reason: "no software adapter".to_string(),
+            },
+        ]);
+        assert_eq!(
+            error.to_string(),
+            "Failed to negotiate the overlay surface: transparent surface: no premultiplied \
+             alpha mode; software adapter: no software adapter"
+        );
+    }
+}
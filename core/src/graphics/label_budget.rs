@@ -0,0 +1,210 @@
+//! GPU memory budget of the controllers' name labels.
+//!
+//! Every controller gets two badge textures, the arrow and the pointing hand,
+//! and most of each is the box with the name. With large label sizes on high
+//! density displays a few controllers take a lot of GPU memory, so the labeled
+//! badges are kept within `LABEL_MEMORY_BUDGET`. When the badges of a new
+//! controller don't fit, the labels of the least recently active controllers
+//! are evicted and they are drawn with label-less cursors, see
+//! `render_user_cursor_to_png`. A controller whose badges don't fit even then
+//! gets label-less cursors itself.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use image::ImageReader;
+
+use super::graphics_context::cursor::texture_bytes;
+
+/// GPU memory the labeled badges can take, in bytes
+pub const LABEL_MEMORY_BUDGET: u64 = 32 * 1024 * 1024;
+
+/// Whether a controller's badges got their labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelAdmission {
+    /// The badges fit, the controllers in `evicted` lost their labels to make room
+    Labeled { evicted: Vec<String> },
+    /// The badges don't fit, the controller gets label-less cursors
+    LabelLess,
+}
+
+#[derive(Debug)]
+struct LabelEntry {
+    bytes: u64,
+    /// Value of the budget's clock when the controller was last active
+    last_used: u64,
+}
+
+/// Tracks the GPU memory of the labeled badges per controller.
+#[derive(Debug)]
+pub struct LabelBudget {
+    budget: u64,
+    labels: HashMap<String, LabelEntry>,
+    /// Counts the uses, orders the controllers by activity
+    clock: u64,
+}
+
+impl Default for LabelBudget {
+    fn default() -> Self {
+        Self::new(LABEL_MEMORY_BUDGET)
+    }
+}
+
+impl LabelBudget {
+    /// Creates an empty budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - GPU memory the labeled badges can take, in bytes
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            labels: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Returns the GPU memory taken by the labeled badges.
+    pub fn used(&self) -> u64 {
+        self.labels.values().map(|entry| entry.bytes).sum()
+    }
+
+    /// Returns whether the controller's badges have their labels.
+    pub fn is_labeled(&self, sid: &str) -> bool {
+        self.labels.contains_key(sid)
+    }
+
+    /// Makes room for the labeled badges of a controller.
+    ///
+    /// The least recently active controllers are evicted until the badges
+    /// fit, nobody is evicted when evicting everyone else isn't enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The controller, its previous badges are replaced
+    /// * `bytes` - GPU memory of the controller's labeled badges
+    pub fn admit(&mut self, sid: &str, bytes: u64) -> LabelAdmission {
+        self.labels.remove(sid);
+        if bytes > self.budget {
+            log::warn!("LabelBudget::admit: badges of {sid} exceed the budget: {bytes}");
+            return LabelAdmission::LabelLess;
+        }
+
+        let mut used = self.used();
+        let mut by_activity: Vec<(String, u64, u64)> = self
+            .labels
+            .iter()
+            .map(|(sid, entry)| (sid.clone(), entry.last_used, entry.bytes))
+            .collect();
+        by_activity.sort_by_key(|(_, last_used, _)| *last_used);
+
+        let mut evicted = vec![];
+        for (evicted_sid, _, evicted_bytes) in by_activity {
+            if used + bytes <= self.budget {
+                break;
+            }
+            used -= evicted_bytes;
+            evicted.push(evicted_sid);
+        }
+        for evicted_sid in &evicted {
+            self.labels.remove(evicted_sid);
+        }
+        if !evicted.is_empty() {
+            log::info!("LabelBudget::admit: {sid} evicted the labels of {evicted:?}");
+        }
+
+        self.clock += 1;
+        self.labels.insert(
+            sid.to_string(),
+            LabelEntry {
+                bytes,
+                last_used: self.clock,
+            },
+        );
+        LabelAdmission::Labeled { evicted }
+    }
+
+    /// Records activity of a controller, its label is evicted last.
+    pub fn touch(&mut self, sid: &str) {
+        if let Some(entry) = self.labels.get_mut(sid) {
+            self.clock += 1;
+            entry.last_used = self.clock;
+        }
+    }
+
+    /// Releases the labeled badges of a controller that left.
+    pub fn release(&mut self, sid: &str) {
+        self.labels.remove(sid);
+    }
+}
+
+/// Returns the GPU memory of the texture of a PNG image, it's read from the
+/// header without decoding the image.
+pub fn png_texture_bytes(png: &[u8]) -> Option<u64> {
+    let (width, height) = ImageReader::new(Cursor::new(png))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+    Some(texture_bytes(width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_recently_active_labels_are_evicted() {
+        let mut budget = LabelBudget::new(100);
+        for sid in ["a", "b", "c"] {
+            assert_eq!(
+                budget.admit(sid, 30),
+                LabelAdmission::Labeled { evicted: vec![] }
+            );
+        }
+        assert_eq!(budget.used(), 90);
+
+        budget.touch("a");
+        assert_eq!(
+            budget.admit("d", 40),
+            LabelAdmission::Labeled {
+                evicted: vec!["b".to_string()]
+            }
+        );
+        assert!(!budget.is_labeled("b"));
+        assert!(budget.is_labeled("a"));
+        assert_eq!(budget.used(), 100);
+
+        /* Redrawn badges replace the previous ones. */
+        assert_eq!(
+            budget.admit("d", 20),
+            LabelAdmission::Labeled { evicted: vec![] }
+        );
+        assert_eq!(budget.used(), 80);
+    }
+
+    #[test]
+    fn test_oversized_badges_are_label_less() {
+        let mut budget = LabelBudget::new(100);
+        budget.admit("a", 60);
+        assert_eq!(budget.admit("b", 120), LabelAdmission::LabelLess);
+        assert!(budget.is_labeled("a"));
+
+        budget.release("a");
+        assert_eq!(budget.used(), 0);
+        assert_eq!(
+            budget.admit("b", 100),
+            LabelAdmission::Labeled { evicted: vec![] }
+        );
+    }
+
+    #[test]
+    fn test_png_texture_bytes() {
+        let mut png = vec![];
+        image::RgbaImage::new(20, 10)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        assert_eq!(png_texture_bytes(&png), Some(800));
+        assert_eq!(png_texture_bytes(b"not a png"), None);
+    }
+}
@@ -0,0 +1,262 @@
+//! Headless rendering target for the overlay tests.
+//!
+//! The overlay normally renders to a window surface, which isn't available
+//! in CI. `OffscreenTarget` renders the cursors with the same pipeline to a
+//! texture on a device without a surface and reads the pixels back, so the
+//! positioning, scaling and visibility of the cursors can be asserted on.
+//!
+//! Adapters sample the textures slightly differently, the tests assert on
+//! the bounds of the drawn pixels instead of comparing against golden files.
+
+use image::RgbaImage;
+
+use super::cursor::{Cursor, CursorsRenderer};
+use super::OverlayError;
+use crate::utils::geometry::Extent;
+
+/// Format of the offscreen texture, the same as the cursor textures.
+const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Pixels with a lower alpha are treated as transparent by `opaque_bounds`.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Renders cursors to a texture and reads the result back.
+pub struct OffscreenTarget {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    texture: wgpu::Texture,
+    width: u32,
+    height: u32,
+    pub renderer: CursorsRenderer,
+}
+
+impl OffscreenTarget {
+    /// Creates a target of `width` x `height` pixels.
+    ///
+    /// The software adapter (lavapipe, WARP) is preferred, it is what CI
+    /// machines have and renders the same everywhere. A hardware adapter is
+    /// only used without one.
+    ///
+    /// # Returns
+    ///
+    /// `None` when the machine has no adapter at all, the rendering tests
+    /// are skipped then.
+    pub fn new(width: u32, height: u32) -> Option<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let request = |force_fallback_adapter| {
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::LowPower,
+                compatible_surface: None,
+                force_fallback_adapter,
+            }))
+        };
+        let adapter = match request(true).or_else(|_| request(false)) {
+            Ok(adapter) => adapter,
+            Err(e) => {
+                eprintln!("OffscreenTarget::new: no adapter, skipping: {e}");
+                return None;
+            }
+        };
+        log::info!("OffscreenTarget::new: {:?}", adapter.get_info());
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+            label: Some("offscreen device"),
+            memory_hints: wgpu::MemoryHints::default(),
+            trace: wgpu::Trace::default(),
+        }))
+        .ok()?;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TARGET_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let renderer = CursorsRenderer::create(&device, TARGET_FORMAT);
+
+        Some(Self {
+            device,
+            queue,
+            texture,
+            width,
+            height,
+            renderer,
+        })
+    }
+
+    /// Returns the size of the target in pixels.
+    pub fn extent(&self) -> Extent {
+        Extent::new(self.width as f64, self.height as f64)
+    }
+
+    /// Creates a cursor from PNG data, like `GraphicsContext::create_cursor`.
+    pub fn create_cursor(&mut self, image_data: &[u8], scale: f64) -> Result<Cursor, OverlayError> {
+        let extent = self.extent();
+        self.renderer
+            .create_cursor(image_data, scale, &self.device, &self.queue, extent)
+    }
+
+    /// Clears the target, draws `cursors` in order and reads the pixels back.
+    pub fn render(&self, cursors: &[&Cursor]) -> RgbaImage {
+        for cursor in cursors {
+            cursor.write_transform(&self.queue, &self.renderer);
+        }
+
+        let view = self
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("offscreen encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("offscreen render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&self.renderer.render_pipeline);
+            for cursor in cursors {
+                cursor.draw_with(&mut render_pass, &self.renderer);
+            }
+        }
+
+        /* Buffer rows have to be aligned when copying from a texture. */
+        let unpadded_row = 4 * self.width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = unpadded_row.div_ceil(align) * align;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen readback"),
+            size: (padded_row * self.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            self.texture.size(),
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device
+            .poll(wgpu::PollType::Wait)
+            .expect("OffscreenTarget::render: poll failed");
+        receiver
+            .recv()
+            .expect("OffscreenTarget::render: map callback dropped")
+            .expect("OffscreenTarget::render: failed to map buffer");
+
+        let mapped = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((unpadded_row * self.height) as usize);
+        for row in mapped.chunks(padded_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_row as usize]);
+        }
+        drop(mapped);
+        buffer.unmap();
+
+        RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("OffscreenTarget::render: pixel buffer doesn't match the target")
+    }
+}
+
+/// Bounds of the drawn pixels of a frame, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelBounds {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl PixelBounds {
+    pub fn width(&self) -> u32 {
+        self.right - self.left + 1
+    }
+
+    pub fn height(&self) -> u32 {
+        self.bottom - self.top + 1
+    }
+
+    /// Returns the center of the bounds in pixels.
+    pub fn center(&self) -> (f64, f64) {
+        (
+            (self.left + self.right + 1) as f64 / 2.,
+            (self.top + self.bottom + 1) as f64 / 2.,
+        )
+    }
+}
+
+/// Returns the bounds of the mostly opaque pixels, `None` for an empty frame.
+pub fn opaque_bounds(image: &RgbaImage) -> Option<PixelBounds> {
+    let mut bounds: Option<PixelBounds> = None;
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] < ALPHA_THRESHOLD {
+            continue;
+        }
+        bounds = Some(match bounds {
+            None => PixelBounds {
+                left: x,
+                top: y,
+                right: x,
+                bottom: y,
+            },
+            Some(b) => PixelBounds {
+                left: b.left.min(x),
+                top: b.top.min(y),
+                right: b.right.max(x),
+                bottom: b.bottom.max(y),
+            },
+        });
+    }
+    bounds
+}
+
+/// Encodes a solid `width` x `height` image as PNG, a cursor with known bounds.
+pub fn solid_png(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+    let image = RgbaImage::from_pixel(width, height, image::Rgba(color));
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .expect("solid_png: failed to encode");
+    png
+}
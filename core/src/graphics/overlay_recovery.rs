@@ -0,0 +1,171 @@
+//! Recovery of an overlay that stopped drawing.
+//!
+//! The overlay can fail in the middle of a session, e.g. the GPU device is
+//! lost after a driver update or the cursor textures can't be created
+//! anymore, and the controllers' cursors vanish while the share goes on. The
+//! sharer is told with `Message::OverlayDegraded` and the overlay is rebuilt
+//! a few times, each attempt waiting longer than the one before. When the
+//! attempts run out the overlay stays degraded until the sharer restarts it
+//! from the UI, `Message::RestartOverlay`, which starts the attempts over.
+//!
+//! The overlay is only restored once a rebuilt one drew, the failed overlay
+//! can keep drawing while it misses the cursors it couldn't create.
+
+use std::time::{Duration, Instant};
+
+/// Rebuilds attempted before waiting for the sharer to restart the overlay
+const MAX_REBUILD_ATTEMPTS: u32 = 3;
+/// Delay before the second attempt, doubled for every next one, the first is immediate
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// What to do about a failed overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryAction {
+    /// Rebuild the overlay now
+    Rebuild,
+    /// The last attempt is recent, the next failure tries again
+    Wait,
+    /// The attempts ran out
+    GiveUp,
+}
+
+/// Tracks the failures of the overlay and the rebuilds attempted since it last drew.
+#[derive(Debug, Default)]
+pub struct OverlayRecovery {
+    /// Why the overlay first failed, `None` while it draws
+    degraded: Option<String>,
+    attempts: u32,
+    last_attempt: Option<Instant>,
+    /// Whether the overlay was rebuilt and didn't fail since
+    rebuilt: bool,
+}
+
+impl OverlayRecovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a failure of the overlay.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the overlay failed
+    ///
+    /// # Returns
+    ///
+    /// `true` if the overlay was drawing until now, the sharer is told once per degradation.
+    pub fn failed(&mut self, reason: &str) -> bool {
+        self.rebuilt = false;
+        if self.degraded.is_some() {
+            return false;
+        }
+        log::warn!("OverlayRecovery::failed: {reason}");
+        self.degraded = Some(reason.to_string());
+        true
+    }
+
+    /// Returns whether to rebuild the failed overlay now, counting the attempt.
+    pub fn next_action(&mut self, now: Instant) -> RecoveryAction {
+        if self.attempts >= MAX_REBUILD_ATTEMPTS {
+            return RecoveryAction::GiveUp;
+        }
+        if let Some(last_attempt) = self.last_attempt {
+            let delay = FIRST_RETRY_DELAY * 2u32.pow(self.attempts.saturating_sub(1));
+            if now.duration_since(last_attempt) < delay {
+                return RecoveryAction::Wait;
+            }
+        }
+        self.attempts += 1;
+        self.last_attempt = Some(now);
+        RecoveryAction::Rebuild
+    }
+
+    /// Records that the overlay was rebuilt with all its cursors.
+    pub fn rebuilt(&mut self) {
+        self.rebuilt = true;
+    }
+
+    /// Records a drawn frame.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the frame is the first one of the rebuilt overlay and the
+    /// overlay was degraded until now.
+    pub fn drawn(&mut self) -> bool {
+        if !self.rebuilt {
+            return false;
+        }
+        self.rebuilt = false;
+        self.attempts = 0;
+        self.last_attempt = None;
+        if self.degraded.take().is_none() {
+            return false;
+        }
+        log::info!("OverlayRecovery::drawn: the rebuilt overlay drew");
+        true
+    }
+
+    /// Returns why the overlay failed, `None` while it draws.
+    pub fn degraded(&self) -> Option<&str> {
+        self.degraded.as_deref()
+    }
+
+    /// Starts the attempts over, for a restart requested by the sharer.
+    pub fn restart(&mut self) {
+        self.attempts = 0;
+        self.last_attempt = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebuilds_back_off_and_give_up() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut recovery = OverlayRecovery::new();
+
+        assert!(recovery.failed("device lost"));
+        assert!(!recovery.failed("device lost"));
+        assert_eq!(recovery.degraded(), Some("device lost"));
+
+        assert_eq!(recovery.next_action(ms(0)), RecoveryAction::Rebuild);
+        assert_eq!(recovery.next_action(ms(500)), RecoveryAction::Wait);
+        assert_eq!(recovery.next_action(ms(1000)), RecoveryAction::Rebuild);
+        assert_eq!(recovery.next_action(ms(2500)), RecoveryAction::Wait);
+        assert_eq!(recovery.next_action(ms(3000)), RecoveryAction::Rebuild);
+        assert_eq!(recovery.next_action(ms(60000)), RecoveryAction::GiveUp);
+
+        recovery.restart();
+        assert_eq!(recovery.next_action(ms(60000)), RecoveryAction::Rebuild);
+    }
+
+    #[test]
+    fn test_drawn_frame_ends_the_degradation() {
+        let start = Instant::now();
+        let mut recovery = OverlayRecovery::new();
+        assert!(!recovery.drawn());
+
+        recovery.failed("texture limit");
+        recovery.next_action(start);
+        /* The failed overlay still draws, without the missing cursor. */
+        assert!(!recovery.drawn());
+        assert_eq!(recovery.degraded(), Some("texture limit"));
+
+        recovery.rebuilt();
+        assert!(recovery.drawn());
+        assert_eq!(recovery.degraded(), None);
+        assert!(!recovery.drawn());
+
+        /* A later degradation gets its attempts again, starting right away. */
+        assert!(recovery.failed("device lost"));
+        assert_eq!(recovery.next_action(start), RecoveryAction::Rebuild);
+        recovery.rebuilt();
+        /* The rebuilt overlay failed before drawing. */
+        assert!(!recovery.failed("device lost"));
+        assert!(!recovery.drawn());
+        assert_eq!(recovery.degraded(), Some("device lost"));
+    }
+}
@@ -0,0 +1,944 @@
+//! Owner of the windows the core draws on the sharer's displays.
+//!
+//! The overlay shows the controllers' cursors on the shared display and
+//! injects their mouse input, it is created for a session and rebuilt when
+//! it fails, see `graphics::overlay_recovery`. The display labels of the
+//! picker and the card of an incoming call are drawn by the service too.
+//!
+//! The mouse input reaches the service as `PointerInput` after the input
+//! service accepted it, what the application has to do after the service
+//! returns is posted on the `ServiceBus`, see `bus`.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use log::debug;
+use socket_lib::{Message, OverlayTheme, SessionParticipant};
+use winit::dpi::{LogicalPosition, PhysicalPosition};
+use winit::event_loop::ActiveEventLoop;
+use winit::monitor::MonitorHandle;
+use winit::window::{Window, WindowId};
+
+#[cfg(target_os = "macos")]
+use winit::platform::macos::WindowExtMacOS;
+
+#[cfg(target_os = "windows")]
+use winit::platform::windows::WindowExtWindows;
+
+use crate::bus::{BusMessage, PointerInput, ServiceBus};
+use crate::call_notification::{CallAnswer, CallNotification};
+use crate::capture::capture_service::OverlayTarget;
+use crate::display_labels::{DisplayLabels, DISPLAY_LABEL_DURATION};
+use crate::event_sender::EventSender;
+use crate::input::cursor_shape::{CursorShape, CursorShapeWatcher};
+use crate::input::mouse::{
+    system_cursor_shape, ControllerPositions, CursorController, CursorControllerError,
+};
+use crate::overlay_window::{DisplayInfo, OverlayWindow};
+use crate::utils::display_mirrors::{monitor_mirrors, MonitorMirrors};
+use crate::utils::display_size;
+use crate::utils::geometry::{Frame, StreamCrop};
+use crate::{get_window_attributes, set_fullscreen, ServerError, UserEvent};
+
+use super::graphics_context::GraphicsContext;
+use super::overlay_recovery::{OverlayRecovery, RecoveryAction};
+
+/// The overlay of the active session.
+///
+/// # Fields
+///
+/// * `gfx` - Graphics context for rendering cursors and visual feedback
+/// * `overlay_window` - Coordinate transformations of the overlay, follows the shared window
+/// * `cursor_controller` - Handles mouse movement, clicks, and cursor visualization
+///
+/// # Lifetime
+///
+/// The lifetime parameter `'a` ensures that the graphics context and cursor controller
+/// don't outlive the underlying window resources they depend on.
+struct Overlay<'a> {
+    gfx: GraphicsContext<'a>,
+    overlay_window: Arc<OverlayWindow>,
+    cursor_controller: CursorController,
+    _cursor_shape_watcher: CursorShapeWatcher,
+}
+
+pub struct OverlayService<'a> {
+    /// `None` while there is no session or on linux, where the overlay isn't supported
+    overlay: Option<Overlay<'a>>,
+    textures_path: String,
+    /// Overlay render scale set by the user, picked from the display size when `None`
+    render_scale: Option<f64>,
+    /// How the overlay draws the cursors, set by the tauri app
+    overlay_theme: OverlayTheme,
+    /// Whether the overlay injects the controllers' mouse input, `false` in view-only sessions
+    inject_input: bool,
+    /// Last controller cursor positions, restored when the overlay is recreated
+    controller_positions: ControllerPositions,
+    /// Failures and rebuilds of the overlay, see `graphics::overlay_recovery`
+    recovery: OverlayRecovery,
+    /// Participants whose cursors couldn't be created, added by the next rebuild of the overlay
+    pending_controllers: Vec<(String, String)>,
+    /// Participants whose cursors the sharer hid, they stay hidden when their cursors are added again
+    hidden_cursors: HashSet<String>,
+    /// Labels shown on the displays while the picker identifies them
+    display_labels: Option<DisplayLabels<'a>>,
+    /// Card of an incoming call, shown until it is answered or dismissed
+    call_notification: Option<CallNotification<'a>>,
+    event_sender: Box<dyn EventSender>,
+}
+
+impl<'a> OverlayService<'a> {
+    /// # Arguments
+    ///
+    /// * `textures_path` - Path to texture resources for cursor and UI rendering
+    /// * `render_scale` - Overlay render scale, picked from the display size when `None`
+    /// * `event_sender` - Sender for the redraw requests and cursor shapes of the overlay
+    pub fn new(
+        textures_path: String,
+        render_scale: Option<f64>,
+        event_sender: Box<dyn EventSender>,
+    ) -> Self {
+        Self {
+            overlay: None,
+            textures_path,
+            render_scale,
+            overlay_theme: OverlayTheme::default(),
+            inject_input: false,
+            controller_positions: ControllerPositions::new(),
+            recovery: OverlayRecovery::new(),
+            pending_controllers: Vec::new(),
+            hidden_cursors: HashSet::new(),
+            display_labels: None,
+            call_notification: None,
+            event_sender,
+        }
+    }
+
+    /// Returns `true` while the session has an overlay.
+    pub fn is_active(&self) -> bool {
+        self.overlay.is_some()
+    }
+
+    /// Creates the overlay on the shared monitor, or on the canonical monitor
+    /// when the shared one is mirrored, see `utils::display_mirrors`.
+    ///
+    /// When a window is shared `target.shared_window` is its frame in global
+    /// physical pixels, the overlay still covers the display showing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - Where the captured content is shown
+    /// * `monitors` - The available monitors
+    /// * `inject_input` - Whether the controllers' mouse input is injected, `false` in view-only sessions
+    /// * `event_loop` - Creates the overlay's windows
+    pub fn create(
+        &mut self,
+        target: OverlayTarget,
+        monitors: &[MonitorHandle],
+        inject_input: bool,
+        event_loop: &ActiveEventLoop,
+    ) -> Result<(), ServerError> {
+        self.inject_input = inject_input;
+        self.overlay = Some(self.create_overlay(target, monitors, event_loop)?);
+
+        #[cfg(target_os = "linux")]
+        {
+            /* We can't support the overlay surface on linux yet. */
+            self.overlay = None;
+        }
+
+        Ok(())
+    }
+
+    fn create_overlay(
+        &self,
+        target: OverlayTarget,
+        monitors: &[MonitorHandle],
+        event_loop: &ActiveEventLoop,
+    ) -> Result<Overlay<'a>, ServerError> {
+        log::info!("create_overlay: selected_monitor: {:?} ", target.monitor);
+        let MonitorMirrors {
+            canonical: selected_monitor,
+            mirrors,
+        } = monitor_mirrors(monitors, target.monitor, event_loop.primary_monitor());
+        if !mirrors.is_empty() {
+            log::info!(
+                "create_overlay: {selected_monitor:?} is mirrored to {mirrors:?} mirror overlays: {}",
+                self.overlay_theme.mirror_overlays
+            );
+            sentry_utils::add_breadcrumb("state", "shared display is mirrored");
+        }
+
+        let window = create_overlay_surface_window(event_loop, &selected_monitor)?;
+        let monitor_position = selected_monitor.position();
+
+        let window_position = match window.outer_position() {
+            Ok(position) => position,
+            Err(error) => {
+                log::error!(
+                    "create_overlay: Error getting window position {error:?} using monitor's"
+                );
+                selected_monitor.position()
+            }
+        };
+
+        let window_size = window.inner_size();
+
+        let mut graphics_context = match GraphicsContext::new(
+            window,
+            self.textures_path.clone(),
+            selected_monitor.scale_factor(),
+            self.render_scale,
+        ) {
+            Ok(context) => context,
+            Err(error) => {
+                log::error!("create_overlay: Error creating graphics context {error:?}");
+                return Err(ServerError::GfxCreationError(error.to_string()));
+            }
+        };
+
+        let scaled = {
+            #[cfg(target_os = "macos")]
+            {
+                true
+            }
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
+            {
+                false
+            }
+        };
+        let overlay_window = Arc::new(
+            OverlayWindow::new(
+                Frame::default(),
+                window_size.into(),
+                window_position,
+                DisplayInfo {
+                    display_extent: selected_monitor.size().into(),
+                    display_position: monitor_position,
+                    display_scale: selected_monitor.scale_factor(),
+                    physical_size: display_size::physical_size(&selected_monitor),
+                },
+                scaled,
+            )
+            .with_stream_crop(target.stream_crop),
+        );
+        if let Some(frame) = target.shared_window {
+            overlay_window.set_shared_window_frame(frame);
+        }
+
+        log::info!("create_overlay: overlay_window created {overlay_window}");
+
+        if self.overlay_theme.mirror_overlays {
+            for mirror in &mirrors {
+                /* A mirror without the cursors is better than no session. */
+                let res = create_overlay_surface_window(event_loop, mirror).and_then(|window| {
+                    graphics_context
+                        .add_mirror(window)
+                        .map_err(|error| ServerError::GfxCreationError(error.to_string()))
+                });
+                if let Err(error) = res {
+                    log::warn!("create_overlay: no overlay on mirror {mirror:?}: {error:?}");
+                }
+            }
+        }
+
+        let cursor_controller = match CursorController::new(
+            &mut graphics_context,
+            overlay_window.clone(),
+            self.event_sender.clone(),
+            self.controller_positions.clone(),
+            self.inject_input,
+            self.overlay_theme,
+        ) {
+            Ok(cursor_controller) => cursor_controller,
+            Err(error) => {
+                log::error!("create_overlay: Error creating cursor controller {error:?}");
+                return Err(ServerError::CursorControllerCreationError);
+            }
+        };
+
+        Ok(Overlay {
+            gfx: graphics_context,
+            overlay_window,
+            cursor_controller,
+            _cursor_shape_watcher: CursorShapeWatcher::new(
+                self.event_sender.clone(),
+                system_cursor_shape,
+            ),
+        })
+    }
+
+    /// Removes the overlay at the end of a session.
+    pub fn destroy(&mut self) {
+        log::info!("OverlayService::destroy");
+        self.overlay = None;
+        self.recovery = OverlayRecovery::new();
+        self.pending_controllers.clear();
+        self.hidden_cursors.clear();
+    }
+
+    /// Forgets the controllers' cursor positions, the next session starts them over.
+    pub fn clear_positions(&self) {
+        self.controller_positions.clear();
+    }
+
+    /// Handles a failure of the overlay, the sharer is told once per
+    /// degradation and the overlay is rebuilt, see `graphics::overlay_recovery`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the overlay failed
+    /// * `bus` - Gets the degradation for the tauri app and the rebuild
+    pub fn failed(&mut self, reason: String, bus: &mut ServiceBus) {
+        if self.recovery.failed(&reason) {
+            sentry_utils::add_breadcrumb("state", format!("overlay degraded: {reason}"));
+            bus.tauri(Message::OverlayDegraded { reason });
+        }
+        self.recover(bus);
+    }
+
+    /// Records a frame drawn by the overlay, the sharer is told when the
+    /// rebuilt overlay drew.
+    fn drawn(&mut self, bus: &mut ServiceBus) {
+        if self.recovery.drawn() {
+            sentry_utils::add_breadcrumb("state", "overlay restored");
+            bus.tauri(Message::OverlayRestored);
+        } else if self.recovery.degraded().is_some() {
+            /* The last rebuild failed, the old overlay draws without the missing cursors. */
+            self.recover(bus);
+        }
+    }
+
+    /// Asks for a rebuild of the degraded overlay when the recovery allows an attempt now.
+    fn recover(&mut self, bus: &mut ServiceBus) {
+        match self.recovery.next_action(std::time::Instant::now()) {
+            RecoveryAction::Rebuild => bus.post(BusMessage::RebuildOverlay),
+            RecoveryAction::Wait => {}
+            RecoveryAction::GiveUp => {
+                debug!("recover: waiting for the sharer to restart the overlay");
+            }
+        }
+    }
+
+    /// Starts the rebuild attempts over, for a restart requested by the sharer.
+    pub fn restart(&mut self, bus: &mut ServiceBus) {
+        if self.overlay.is_none() {
+            log::warn!("OverlayService::restart: no overlay");
+            return;
+        }
+        self.recovery.restart();
+        self.recover(bus);
+    }
+
+    /// Replaces the overlay with a new one on the shared display, the
+    /// controllers' cursors are added to it again.
+    ///
+    /// The positions of the cursors are kept, see `ControllerPositions`, and
+    /// so is the sharer's choice to disable the controllers. The old overlay
+    /// is kept when the new one can't be created.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - Where the captured content is shown, `None` without captured content
+    /// * `monitors` - The available monitors
+    /// * `event_loop` - Creates the windows of the rebuilt overlay
+    pub fn rebuild(
+        &mut self,
+        target: Option<OverlayTarget>,
+        monitors: &[MonitorHandle],
+        event_loop: &ActiveEventLoop,
+    ) {
+        let Some(old) = self.overlay.take() else {
+            log::warn!("rebuild: no overlay");
+            return;
+        };
+        log::info!("rebuild");
+        sentry_utils::add_breadcrumb("state", "rebuild overlay");
+
+        let Some(target) = target else {
+            log::warn!("rebuild: no active content");
+            self.overlay = Some(old);
+            return;
+        };
+        let mut overlay = match self.create_overlay(target, monitors, event_loop) {
+            Ok(overlay) => overlay,
+            Err(e) => {
+                log::error!("rebuild: Error creating overlay window: {e:?}");
+                self.overlay = Some(old);
+                return;
+            }
+        };
+
+        let controllers = old.cursor_controller.controller_states();
+        let controllers_enabled = controllers.iter().all(|controller| controller.can_control);
+        let mut participants = controllers
+            .into_iter()
+            .map(|controller| (controller.sid, controller.name))
+            .collect::<Vec<_>>();
+        participants.append(&mut self.pending_controllers);
+        let mut missing = 0;
+        for (sid, name) in participants {
+            if let Err(e) = overlay.cursor_controller.add_controller(
+                &mut overlay.gfx,
+                sid.clone(),
+                name.clone(),
+            ) {
+                log::error!("rebuild: Error adding controller {sid}: {e:?}");
+                self.pending_controllers.push((sid, name));
+                missing += 1;
+            }
+        }
+        if self.inject_input && !controllers_enabled {
+            overlay.cursor_controller.set_controllers_enabled(false);
+        }
+        for sid in &self.hidden_cursors {
+            overlay.cursor_controller.set_cursor_visible(sid, false);
+        }
+        overlay.gfx.window().request_redraw();
+        self.overlay = Some(overlay);
+
+        if missing == 0 {
+            self.recovery.rebuilt();
+        } else {
+            log::warn!("rebuild: {missing} cursors are still missing");
+        }
+    }
+
+    /// Moves the cursors and injects the mouse input the input service accepted.
+    ///
+    /// After a click or a scroll the controller that has control is posted,
+    /// its cursor moves need its input token from then on, see `room::input_auth`.
+    pub fn pointer_input(&mut self, input: PointerInput, bus: &mut ServiceBus) {
+        let Some(overlay) = self.overlay.as_mut() else {
+            log::warn!("pointer_input: no overlay");
+            return;
+        };
+        let cursor_controller = &mut overlay.cursor_controller;
+        match input {
+            PointerInput::Move { x, y, sid } => {
+                cursor_controller.cursor_move_controller(x, y, sid.as_str());
+                return;
+            }
+            PointerInput::Click(data, sid) => {
+                cursor_controller.mouse_click_controller(data, sid.as_str())
+            }
+            PointerInput::Scroll(delta, sid) => {
+                cursor_controller.scroll_controller(delta, sid.as_str())
+            }
+            PointerInput::ReplayMove { x, y } => {
+                cursor_controller.replay_cursor_move(x, y);
+                return;
+            }
+            PointerInput::ReplayClick(data) => cursor_controller.replay_click(data),
+            PointerInput::ReplayScroll(delta) => cursor_controller.replay_scroll(delta),
+        }
+        let in_control = cursor_controller
+            .controller_states()
+            .into_iter()
+            .find(|controller| controller.has_control)
+            .map(|controller| controller.sid);
+        bus.post(BusMessage::InControl(in_control));
+    }
+
+    /// Adds the cursor of a participant that joined.
+    ///
+    /// A cursor the overlay can't create textures for anymore, e.g. when the
+    /// cursor slots ran out, is added by the rebuild of the failed overlay.
+    pub fn add_controller(&mut self, sid: String, name: String, bus: &mut ServiceBus) {
+        let Some(overlay) = self.overlay.as_mut() else {
+            log::warn!("add_controller: no overlay");
+            return;
+        };
+        match overlay
+            .cursor_controller
+            .add_controller(&mut overlay.gfx, sid.clone(), name.clone())
+        {
+            Ok(()) => {
+                if self.hidden_cursors.contains(&sid) {
+                    overlay.cursor_controller.set_cursor_visible(&sid, false);
+                }
+            }
+            Err(e) => {
+                log::error!("add_controller: Error adding controller: {e:?}");
+                if matches!(
+                    e,
+                    CursorControllerError::ControllerCursorCreationFailed
+                        | CursorControllerError::ControllerPointerCursorCreationFailed
+                        | CursorControllerError::SvgRenderError
+                ) {
+                    self.pending_controllers.push((sid, name));
+                    self.failed(e.to_string(), bus);
+                }
+            }
+        }
+    }
+
+    /// Removes the cursor of a participant that left.
+    pub fn remove_controller(&mut self, sid: &str) {
+        self.pending_controllers
+            .retain(|(pending, _)| pending.as_str() != sid);
+        if let Some(overlay) = self.overlay.as_mut() {
+            overlay.cursor_controller.remove_controller(sid);
+        }
+    }
+
+    /// Removes the cursor of a participant the sharer kicked out.
+    pub fn revoke_controller(&mut self, sid: &str) {
+        if let Some(overlay) = self.overlay.as_mut() {
+            overlay.cursor_controller.revoke_controller(sid);
+        }
+    }
+
+    /// Gives control back to the sharer from the controllers `owned` matches.
+    ///
+    /// # Returns
+    ///
+    /// `true` if one of them had control.
+    pub fn take_back_control(&mut self, owned: impl Fn(&str) -> bool) -> bool {
+        let Some(overlay) = self.overlay.as_mut() else {
+            return false;
+        };
+        let cursor_controller = &mut overlay.cursor_controller;
+        let mut taken_back = false;
+        for controller in cursor_controller.controller_states() {
+            if owned(&controller.sid) && cursor_controller.take_back_control(&controller.sid) {
+                taken_back = true;
+            }
+        }
+        overlay.gfx.window().request_redraw();
+        taken_back
+    }
+
+    /// Returns the controllers with a cursor in the overlay, none without an overlay.
+    pub fn controller_states(&self) -> Vec<SessionParticipant> {
+        match &self.overlay {
+            Some(overlay) => overlay.cursor_controller.controller_states(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the name the cursor of the participant `sid` shows.
+    pub fn controller_name(&self, sid: &str) -> Option<String> {
+        self.controller_states()
+            .into_iter()
+            .find(|controller| controller.sid == sid)
+            .map(|controller| controller.name)
+    }
+
+    /// Returns whether the overlay injects the controllers' mouse input,
+    /// `None` without an overlay.
+    pub fn injects_input(&self) -> Option<bool> {
+        self.overlay
+            .as_ref()
+            .map(|overlay| overlay.cursor_controller.injects_input())
+    }
+
+    /// Lets the controllers take control or gives it back to the sharer.
+    ///
+    /// # Returns
+    ///
+    /// `false` without an overlay.
+    pub fn set_controllers_enabled(&mut self, enabled: bool) -> bool {
+        let Some(overlay) = self.overlay.as_mut() else {
+            return false;
+        };
+        overlay.cursor_controller.set_controllers_enabled(enabled);
+        true
+    }
+
+    /// Shows or hides the cursor of the controller `sid` while it is outside the shared content.
+    pub fn set_controller_visible(&mut self, visible: bool, sid: &str) {
+        let Some(overlay) = self.overlay.as_mut() else {
+            log::warn!("set_controller_visible: no overlay");
+            return;
+        };
+        overlay
+            .cursor_controller
+            .set_controller_visible(visible, sid);
+    }
+
+    /// Hides or shows the cursor of the participant `sid` in the overlay until
+    /// the session ends, the participant keeps watching and its input is
+    /// handled as before.
+    pub fn set_cursor_visible(&mut self, sid: String, visible: bool) {
+        let Some(overlay) = self.overlay.as_mut() else {
+            log::warn!("set_cursor_visible: no overlay");
+            return;
+        };
+        sentry_utils::add_breadcrumb("room", format!("cursor visible: {sid} {visible}"));
+        if !overlay.cursor_controller.set_cursor_visible(&sid, visible) {
+            log::warn!("set_cursor_visible: {sid} has no cursor yet");
+        }
+        overlay.gfx.window().request_redraw();
+        if visible {
+            self.hidden_cursors.remove(&sid);
+        } else {
+            self.hidden_cursors.insert(sid);
+        }
+    }
+
+    /// Shows the order of the raised hands in the cursors' badges.
+    pub fn set_control_queue(&mut self, sids: &[String]) {
+        let Some(overlay) = self.overlay.as_mut() else {
+            return;
+        };
+        if let Err(e) = overlay
+            .cursor_controller
+            .set_control_queue(&mut overlay.gfx, sids)
+        {
+            log::error!("set_control_queue: failed to update the badges: {e:?}");
+        }
+        overlay.gfx.window().request_redraw();
+    }
+
+    /// Shows whether the controller `sid` holds its push-to-control modifier.
+    pub fn set_controller_held(&mut self, held: bool, sid: &str) {
+        if let Some(overlay) = self.overlay.as_mut() {
+            overlay.cursor_controller.set_controller_held(held, sid);
+            overlay.gfx.window().request_redraw();
+        }
+    }
+
+    /// Draws the sharer's badge with the shape of the sharer's system cursor.
+    pub fn set_sharer_cursor_shape(&mut self, shape: CursorShape) {
+        let Some(overlay) = self.overlay.as_mut() else {
+            return;
+        };
+        if let Err(e) = overlay
+            .cursor_controller
+            .set_sharer_cursor_shape(&mut overlay.gfx, shape)
+        {
+            log::error!("set_sharer_cursor_shape: failed to update the sharer's badge: {e:?}");
+        }
+        overlay.gfx.window().request_redraw();
+    }
+
+    /// Reloads the overlay assets and redraws the cursors, see `graphics::assets`.
+    ///
+    /// Without an overlay there is nothing to reload, the next overlay loads
+    /// the assets when it is created.
+    pub fn reload_assets(&mut self) {
+        let Some(overlay) = self.overlay.as_mut() else {
+            log::info!("reload_assets: no overlay");
+            return;
+        };
+        sentry_utils::add_breadcrumb("state", "reload overlay assets");
+        if let Err(e) = overlay.gfx.reload_assets() {
+            log::error!("reload_assets: failed to reload the markers: {e:?}");
+        }
+        if let Err(e) = overlay.cursor_controller.reload_cursors(&mut overlay.gfx) {
+            log::error!("reload_assets: failed to reload the cursors: {e:?}");
+        }
+        overlay.gfx.window().request_redraw();
+    }
+
+    /// Sets how the overlay draws the cursors, the badges are redrawn right
+    /// away when their size changed.
+    pub fn set_theme(&mut self, theme: OverlayTheme) {
+        log::info!("set_theme: {theme:?}");
+        let label_size_changed = theme.label_size != self.overlay_theme.label_size;
+        self.overlay_theme = theme;
+        let Some(overlay) = self.overlay.as_mut() else {
+            return;
+        };
+        overlay.cursor_controller.set_overlay_theme(theme);
+        if label_size_changed {
+            if let Err(e) = overlay.cursor_controller.reload_cursors(&mut overlay.gfx) {
+                log::error!("set_theme: failed to resize the cursors: {e:?}");
+            }
+            overlay.gfx.window().request_redraw();
+        }
+    }
+
+    /// Follows the shared window, `frame` is in global physical pixels.
+    pub fn set_shared_window_frame(&mut self, frame: Frame) {
+        if let Some(overlay) = self.overlay.as_mut() {
+            overlay.overlay_window.set_shared_window_frame(frame);
+            overlay.gfx.window().request_redraw();
+        }
+    }
+
+    /// Sets the part of the captured frames the stream shows, after its resolution changed.
+    ///
+    /// # Returns
+    ///
+    /// The scale factor of the shared display, `None` without an overlay.
+    pub fn set_stream_crop(&mut self, stream_crop: StreamCrop) -> Option<f64> {
+        let overlay = self.overlay.as_ref()?;
+        overlay.overlay_window.set_stream_crop(stream_crop);
+        Some(overlay.overlay_window.get_display_scale())
+    }
+
+    pub fn request_redraw(&self) {
+        match &self.overlay {
+            Some(overlay) => overlay.gfx.window().request_redraw(),
+            None => log::warn!("request_redraw: no overlay"),
+        }
+    }
+
+    /// Shows each label on its display for `DISPLAY_LABEL_DURATION`.
+    ///
+    /// # Arguments
+    ///
+    /// * `labels` - The displays' monitors and their labels in the picker
+    /// * `event_loop` - Event loop used to create the label windows
+    pub fn identify_displays(
+        &mut self,
+        labels: Vec<(MonitorHandle, String)>,
+        event_loop: &ActiveEventLoop,
+    ) {
+        /* Close the previous labels before opening new windows on the same displays. */
+        self.display_labels = None;
+        if labels.is_empty() {
+            log::warn!("identify_displays: no displays to identify");
+            return;
+        }
+
+        match DisplayLabels::show(event_loop, labels, &self.textures_path, self.render_scale) {
+            Ok(display_labels) => self.display_labels = Some(display_labels),
+            Err(e) => {
+                log::error!("identify_displays: Error showing labels: {e:?}");
+                return;
+            }
+        }
+
+        let event_sender = self.event_sender.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(DISPLAY_LABEL_DURATION);
+            if let Err(e) = event_sender.send(UserEvent::HideDisplayLabels) {
+                log::error!("identify_displays: Error sending hide labels event: {e:?}");
+            }
+        });
+    }
+
+    /// Hides the display labels once their time is up.
+    pub fn hide_display_labels(&mut self) {
+        /* A newer request keeps its labels until its own timer fires. */
+        if self
+            .display_labels
+            .as_ref()
+            .is_some_and(|labels| labels.expired())
+        {
+            log::info!("hide_display_labels");
+            self.display_labels = None;
+        }
+    }
+
+    /// Shows the card of an incoming call on the primary display, it replaces
+    /// the card of a previous call.
+    ///
+    /// # Arguments
+    ///
+    /// * `caller` - Name of the caller
+    /// * `room` - Identifies the call, sent back with the answer
+    /// * `event_loop` - Event loop used to create the card's window
+    pub fn show_incoming_call(
+        &mut self,
+        caller: String,
+        room: String,
+        event_loop: &ActiveEventLoop,
+    ) {
+        log::info!("show_incoming_call: room: {room}");
+        sentry_utils::add_breadcrumb("state", "incoming call");
+        self.call_notification = None;
+
+        let Some(monitor) = event_loop
+            .primary_monitor()
+            .or_else(|| event_loop.available_monitors().next())
+        else {
+            log::error!("show_incoming_call: no monitors");
+            return;
+        };
+        match CallNotification::show(
+            event_loop,
+            monitor,
+            &caller,
+            room,
+            &self.textures_path,
+            self.render_scale,
+        ) {
+            Ok(call_notification) => self.call_notification = Some(call_notification),
+            Err(e) => log::error!("show_incoming_call: Error showing notification: {e:?}"),
+        }
+    }
+
+    pub fn dismiss_incoming_call(&mut self) {
+        log::info!("dismiss_incoming_call");
+        self.call_notification = None;
+    }
+
+    /// Closes the card of the incoming call and lets the tauri app know how
+    /// the sharer answered it.
+    fn answer_incoming_call(&mut self, answer: CallAnswer, bus: &mut ServiceBus) {
+        let Some(call_notification) = self.call_notification.take() else {
+            return;
+        };
+        log::info!("answer_incoming_call: {answer:?}");
+        bus.tauri(Message::IncomingCallAnswered {
+            room: call_notification.room().to_string(),
+            accepted: answer == CallAnswer::Accept,
+        });
+    }
+
+    fn owns_call(&self, window_id: WindowId) -> bool {
+        self.call_notification
+            .as_ref()
+            .is_some_and(|call_notification| call_notification.owns(window_id))
+    }
+
+    /// Handles the close request of a window.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the window was the card of a call, closing it with the
+    /// keyboard declines the call.
+    pub fn close_requested(&mut self, window_id: WindowId, bus: &mut ServiceBus) -> bool {
+        if !self.owns_call(window_id) {
+            return false;
+        }
+        self.answer_incoming_call(CallAnswer::Decline, bus);
+        true
+    }
+
+    pub fn cursor_moved(&mut self, window_id: WindowId, position: PhysicalPosition<f64>) {
+        if let Some(call_notification) = self
+            .call_notification
+            .as_mut()
+            .filter(|call_notification| call_notification.owns(window_id))
+        {
+            call_notification.cursor_moved(position);
+        }
+    }
+
+    /// Answers the call when one of the card's buttons was clicked.
+    pub fn clicked(&mut self, window_id: WindowId, bus: &mut ServiceBus) {
+        let answer = self
+            .call_notification
+            .as_ref()
+            .filter(|call_notification| call_notification.owns(window_id))
+            .and_then(|call_notification| call_notification.clicked());
+        if let Some(answer) = answer {
+            self.answer_incoming_call(answer, bus);
+        }
+    }
+
+    /// Draws the window `window_id`, the overlay's failures are handled by
+    /// its recovery.
+    pub fn redraw(&mut self, window_id: WindowId, bus: &mut ServiceBus) {
+        if self
+            .call_notification
+            .as_ref()
+            .is_some_and(|call_notification| call_notification.draw(window_id))
+        {
+            return;
+        }
+        if self
+            .display_labels
+            .as_ref()
+            .is_some_and(|labels| labels.draw(window_id))
+        {
+            return;
+        }
+
+        // render the cursor
+        // The vertices should be in counter clockwise order because of the front face culling
+        let Some(overlay) = self.overlay.as_mut() else {
+            log::warn!("redraw: no overlay");
+            return;
+        };
+        match overlay.gfx.draw(&overlay.cursor_controller) {
+            Ok(()) => self.drawn(bus),
+            Err(e) => {
+                log::error!("redraw: Error drawing the overlay: {e:?}");
+                self.failed(e.to_string(), bus);
+            }
+        }
+    }
+}
+
+/// Creates a click-through window covering `monitor` for an overlay.
+fn create_overlay_surface_window(
+    event_loop: &ActiveEventLoop,
+    monitor: &MonitorHandle,
+) -> Result<Window, ServerError> {
+    let attributes = get_window_attributes();
+    let window = match event_loop.create_window(attributes) {
+        Ok(window) => window,
+        Err(_error) => {
+            return Err(ServerError::WindowCreationError);
+        }
+    };
+
+    #[cfg(target_os = "linux")]
+    {
+        /* This is needed for getting the system picker for screen sharing. */
+        let _ = window.request_inner_size(monitor.size().clone());
+    }
+
+    let res = window.set_cursor_hittest(false);
+    if let Err(_error) = res {
+        return Err(ServerError::CursorHittestError);
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        window.set_skip_taskbar(true);
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        window.set_has_shadow(false);
+    }
+
+    window.set_visible(true);
+    let monitor_position = monitor.position();
+    window.set_outer_position(LogicalPosition::new(monitor_position.x, monitor_position.y));
+
+    let res = set_fullscreen(&window, monitor.clone());
+    if let Err(error) = res {
+        log::error!("create_overlay_surface_window: Error setting fullscreen {error:?}");
+        return Err(ServerError::FullscreenError);
+    }
+
+    Ok(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+
+    fn overlay_service() -> OverlayService<'static> {
+        OverlayService::new(String::new(), None, Box::new(EventCollector::new()))
+    }
+
+    #[test]
+    fn test_failure_is_reported_once_and_asks_for_a_rebuild() {
+        let mut overlay_service = overlay_service();
+        let mut bus = ServiceBus::new();
+
+        overlay_service.failed("no cursor slots".to_string(), &mut bus);
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Tauri(Message::OverlayDegraded { reason })) if reason == "no cursor slots"
+        ));
+        assert!(matches!(bus.take(), Some(BusMessage::RebuildOverlay)));
+        assert!(bus.is_empty());
+
+        /* The sharer already knows, the next attempt waits for its delay. */
+        overlay_service.failed("no cursor slots".to_string(), &mut bus);
+        assert!(bus.is_empty());
+    }
+
+    #[test]
+    fn test_restart_without_overlay_does_nothing() {
+        let mut overlay_service = overlay_service();
+        let mut bus = ServiceBus::new();
+
+        overlay_service.restart(&mut bus);
+        assert!(bus.is_empty());
+        assert!(!overlay_service.is_active());
+        assert!(overlay_service.controller_states().is_empty());
+        assert_eq!(overlay_service.injects_input(), None);
+    }
+}
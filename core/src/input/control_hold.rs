@@ -0,0 +1,100 @@
+//! Push-to-control holds of the controllers.
+//!
+//! A controller can switch to push-to-control in its UI, from then on it holds
+//! a modifier while it means to control and sends `ClientEvent::ControlHold`
+//! when it presses and releases it. Its clicks, keystrokes and scrolls are only
+//! injected while the hold is active, a stray click while pointing at
+//! something during a pair session doesn't take control from the sharer.
+//! Controllers that never sent a hold aren't affected.
+
+use std::collections::HashMap;
+
+/// The holds of the controllers in push-to-control mode.
+#[derive(Debug, Default)]
+pub struct ControlHolds {
+    /// Whether each controller in push-to-control mode holds its modifier
+    holds: HashMap<String, bool>,
+}
+
+impl ControlHolds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a hold of the controller `sid`, which puts it in push-to-control mode.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the hold changed, repeated events of a held key are ignored.
+    pub fn set(&mut self, sid: &str, active: bool) -> bool {
+        let previous = self.holds.insert(sid.to_string(), active);
+        previous != Some(active)
+    }
+
+    /// Returns whether the input of the controller `sid` is injected.
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The controller that sent the input
+    /// * `release` - Whether the input releases a key or a button
+    ///
+    /// # Returns
+    ///
+    /// `true` for controllers outside of push-to-control and for held ones.
+    /// Releases always pass, otherwise a button or key pressed before the
+    /// hold ended would stay pressed.
+    pub fn accepts(&self, sid: &str, release: bool) -> bool {
+        release || self.holds.get(sid).copied().unwrap_or(true)
+    }
+
+    /// Returns the hold of the controller `sid`, `None` outside of push-to-control.
+    pub fn hold(&self, sid: &str) -> Option<bool> {
+        self.holds.get(sid).copied()
+    }
+
+    /// Forgets the hold of a controller that left the session.
+    pub fn participant_left(&mut self, sid: &str) {
+        self.holds.remove(sid);
+    }
+
+    /// Forgets every hold when the session ended.
+    pub fn reset(&mut self) {
+        self.holds.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_controllers_without_holds_pass() {
+        let holds = ControlHolds::new();
+        assert!(holds.accepts("a", false));
+        assert_eq!(holds.hold("a"), None);
+    }
+
+    #[test]
+    fn test_input_only_passes_while_held() {
+        let mut holds = ControlHolds::new();
+        assert!(holds.set("a", false));
+        assert!(!holds.accepts("a", false));
+        /* Releases aren't held back. */
+        assert!(holds.accepts("a", true));
+        /* Other controllers aren't affected. */
+        assert!(holds.accepts("b", false));
+
+        assert!(holds.set("a", true));
+        assert!(!holds.set("a", true));
+        assert!(holds.accepts("a", false));
+
+        assert!(holds.set("a", false));
+        assert!(!holds.accepts("a", false));
+
+        holds.participant_left("a");
+        assert!(holds.accepts("a", false));
+        holds.set("b", false);
+        holds.reset();
+        assert_eq!(holds.hold("b"), None);
+    }
+}
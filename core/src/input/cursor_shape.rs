@@ -0,0 +1,169 @@
+//! Shape of the sharer's system cursor.
+//!
+//! The frames are captured without the cursor and the controllers draw their
+//! own, so without this they always see an arrow, even when the sharer's
+//! cursor is an I-beam over text or a resize handle on a window edge. The
+//! watcher thread reads the shape of the system cursor while the overlay is
+//! up and notifies the application when it changes, which publishes it to the
+//! room and draws the sharer's badge with it.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event_sender::EventSender;
+use crate::UserEvent;
+
+const CURSOR_SHAPE_WATCH_INTERVAL_MS: u64 = 100;
+
+/// The system cursors the sharer's cursor is mapped to.
+///
+/// Application specific cursors the platform can't name are reported as
+/// `Arrow`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Arrow,
+    IBeam,
+    Hand,
+    Crosshair,
+    /// The left and right edges of a window
+    ResizeHorizontal,
+    /// The top and bottom edges of a window
+    ResizeVertical,
+    /// The top left and bottom right corners of a window
+    ResizeNwse,
+    /// The top right and bottom left corners of a window
+    ResizeNesw,
+    Move,
+    Wait,
+    NotAllowed,
+}
+
+/// Background thread that sends `UserEvent::SharerCursorShape` when the
+/// shape of the sharer's cursor changes.
+///
+/// The thread is stopped when the watcher is dropped.
+pub struct CursorShapeWatcher {
+    stop_tx: mpsc::Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl CursorShapeWatcher {
+    /// Starts watching the cursor.
+    ///
+    /// # Parameters
+    /// - `event_sender`: Where the shape changes are sent
+    /// - `detect`: Returns the current shape, `None` when the platform can't tell
+    pub fn new(
+        event_sender: Box<dyn EventSender>,
+        detect: impl Fn() -> Option<CursorShape> + Send + 'static,
+    ) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            watch_cursor_shape(
+                event_sender,
+                detect,
+                stop_rx,
+                Duration::from_millis(CURSOR_SHAPE_WATCH_INTERVAL_MS),
+            )
+        });
+        Self {
+            stop_tx,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for CursorShapeWatcher {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("CursorShapeWatcher::drop: watcher thread panicked");
+            }
+        }
+    }
+}
+
+fn watch_cursor_shape(
+    event_sender: Box<dyn EventSender>,
+    detect: impl Fn() -> Option<CursorShape>,
+    stop_rx: mpsc::Receiver<()>,
+    interval: Duration,
+) {
+    let mut previous = None;
+    loop {
+        /* A shape the platform can't tell keeps the last one. */
+        if let Some(current) = detect() {
+            if Some(current) != previous {
+                log::debug!("watch_cursor_shape: {previous:?} -> {current:?}");
+                if let Err(e) = event_sender.send(UserEvent::SharerCursorShape(current)) {
+                    log::error!("watch_cursor_shape: error sending shape: {e:?}");
+                    break;
+                }
+                previous = Some(current);
+            }
+        }
+
+        match stop_rx.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_watch_cursor_shape_reports_changes() {
+        let shapes = Arc::new(Mutex::new(vec![
+            Some(CursorShape::Arrow),
+            Some(CursorShape::Arrow),
+            None,
+            Some(CursorShape::Arrow),
+            Some(CursorShape::IBeam),
+            Some(CursorShape::ResizeHorizontal),
+            Some(CursorShape::ResizeHorizontal),
+        ]));
+        let collector = EventCollector::new();
+        let (stop_tx, stop_rx) = mpsc::channel();
+
+        let detect = move || {
+            let mut shapes = shapes.lock().unwrap();
+            if shapes.len() == 1 {
+                let _ = stop_tx.send(());
+                return shapes[0];
+            }
+            shapes.remove(0)
+        };
+        watch_cursor_shape(
+            Box::new(collector.clone()),
+            detect,
+            stop_rx,
+            Duration::from_millis(1),
+        );
+
+        let shapes: Vec<CursorShape> = collector
+            .take()
+            .into_iter()
+            .map(|event| match event {
+                UserEvent::SharerCursorShape(shape) => shape,
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect();
+        assert_eq!(
+            shapes,
+            vec![
+                CursorShape::Arrow,
+                CursorShape::IBeam,
+                CursorShape::ResizeHorizontal
+            ]
+        );
+    }
+}
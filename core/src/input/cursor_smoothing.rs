@@ -0,0 +1,164 @@
+//! Smoothing of the controllers' cursors in the overlay.
+//!
+//! The controllers' positions arrive over an unreliable data channel, a
+//! dropped packet makes the cursor stand still and then jump. Each cursor
+//! buffers the positions with their arrival time, and is drawn where it was
+//! a latency budget ago, interpolated between the positions around that time.
+//! The overlay redraws at 60fps, so the cursor glides over the gaps instead
+//! of jumping, at the cost of trailing the controller by the budget.
+//!
+//! Only the drawn cursor is smoothed, the simulated input of a controller in
+//! control uses the positions as they arrive.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::utils::geometry::NormalizedPoint;
+
+/// Positions buffered per cursor, the oldest are dropped past it
+const MAX_BUFFERED_POSITIONS: usize = 64;
+
+/// Interpolates the positions of a cursor for drawing.
+#[derive(Debug)]
+pub struct CursorInterpolator {
+    /// How far behind the received positions the cursor is drawn, zero draws the last one
+    latency_budget: Duration,
+    /// Received positions with their arrival time, oldest first
+    positions: VecDeque<(Instant, NormalizedPoint)>,
+}
+
+impl CursorInterpolator {
+    /// Creates the interpolator of a cursor without positions.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency_budget` - How far behind the received positions the cursor is drawn
+    pub fn new(latency_budget: Duration) -> Self {
+        Self {
+            latency_budget,
+            positions: VecDeque::new(),
+        }
+    }
+
+    /// Changes how far behind the received positions the cursor is drawn.
+    pub fn set_latency_budget(&mut self, latency_budget: Duration) {
+        self.latency_budget = latency_budget;
+    }
+
+    /// Buffers a position of the cursor.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - When the position arrived
+    /// * `point` - The position in the overlay window
+    pub fn push(&mut self, at: Instant, point: NormalizedPoint) {
+        if let (Some(&(last_at, last_point)), Some(start)) =
+            (self.positions.back(), at.checked_sub(self.latency_budget))
+        {
+            /*
+             * The cursor rests at the last position after a pause, it moves
+             * from there over the budget instead of jumping most of the way.
+             */
+            if last_at < start {
+                self.positions.clear();
+                self.positions.push_back((start, last_point));
+            }
+        }
+        if self.positions.len() >= MAX_BUFFERED_POSITIONS {
+            self.positions.pop_front();
+        }
+        self.positions.push_back((at, point));
+    }
+
+    /// Returns where the cursor is drawn at `now`, `None` before the first position.
+    ///
+    /// The positions that were passed are dropped.
+    pub fn position(&mut self, now: Instant) -> Option<NormalizedPoint> {
+        let Some(render_at) = now.checked_sub(self.latency_budget) else {
+            return self.positions.back().map(|&(_, point)| point);
+        };
+        while self.positions.len() > 1 && self.positions[1].0 <= render_at {
+            self.positions.pop_front();
+        }
+
+        let &(from_at, from) = self.positions.front()?;
+        let Some(&(to_at, to)) = self.positions.get(1) else {
+            return Some(from);
+        };
+        if render_at <= from_at {
+            return Some(from);
+        }
+        let progress = (render_at - from_at).as_secs_f64() / (to_at - from_at).as_secs_f64();
+        Some(NormalizedPoint::new(
+            from.x + (to.x - from.x) * progress,
+            from.y + (to.y - from.y) * progress,
+        ))
+    }
+
+    /// Forgets the positions, the next one is drawn right away.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(point: Option<NormalizedPoint>, x: f64, y: f64) {
+        let point = point.unwrap();
+        assert!(
+            (point.x - x).abs() < 1e-9 && (point.y - y).abs() < 1e-9,
+            "{point:?} != ({x}, {y})"
+        );
+    }
+
+    #[test]
+    fn test_positions_are_interpolated_behind_the_budget() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut interpolator = CursorInterpolator::new(Duration::from_millis(100));
+        assert_eq!(interpolator.position(start), None);
+
+        interpolator.push(ms(0), NormalizedPoint::new(0.0, 0.0));
+        interpolator.push(ms(20), NormalizedPoint::new(0.2, 0.4));
+        /* Two packets were dropped, the cursor glides over the gap. */
+        interpolator.push(ms(80), NormalizedPoint::new(0.8, 0.4));
+
+        assert_near(interpolator.position(ms(50)), 0.0, 0.0);
+        assert_near(interpolator.position(ms(110)), 0.1, 0.2);
+        assert_near(interpolator.position(ms(150)), 0.5, 0.4);
+        assert_near(interpolator.position(ms(400)), 0.8, 0.4);
+        assert_eq!(interpolator.positions.len(), 1);
+    }
+
+    #[test]
+    fn test_cursor_moves_from_its_rest_position_after_a_pause() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut interpolator = CursorInterpolator::new(Duration::from_millis(100));
+        interpolator.push(ms(0), NormalizedPoint::new(0.0, 0.0));
+        assert_near(interpolator.position(ms(1000)), 0.0, 0.0);
+
+        interpolator.push(ms(2000), NormalizedPoint::new(1.0, 0.5));
+        assert_near(interpolator.position(ms(2000)), 0.0, 0.0);
+        assert_near(interpolator.position(ms(2050)), 0.5, 0.25);
+        assert_near(interpolator.position(ms(2100)), 1.0, 0.5);
+    }
+
+    #[test]
+    fn test_zero_budget_draws_the_last_position() {
+        let start = Instant::now();
+        let mut interpolator = CursorInterpolator::new(Duration::ZERO);
+        interpolator.push(start, NormalizedPoint::new(0.1, 0.1));
+        interpolator.push(
+            start + Duration::from_millis(10),
+            NormalizedPoint::new(0.3, 0.3),
+        );
+        assert_near(
+            interpolator.position(start + Duration::from_millis(10)),
+            0.3,
+            0.3,
+        );
+    }
+}
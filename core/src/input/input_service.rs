@@ -0,0 +1,646 @@
+//! Gate for the controllers' input during a session.
+//!
+//! The service decides which clicks, keystrokes and scrolls of the
+//! controllers reach the sharer's machine: view-only sessions, push-to-control
+//! holds, the keyboard arbitration and the app allowlist are checked here.
+//! Keystrokes are injected by the service itself, the accepted mouse input
+//! is posted on the `ServiceBus` for the overlay, which draws the cursors and
+//! injects it, see `graphics::overlay_service`.
+//!
+//! Macro recording and playback and the clipboard sync follow the same
+//! input, so they live here too.
+
+use socket_lib::{
+    InputInjectionStatus, InputMacro, KeyboardArbitration, KeyboardLayoutKind, MacroInput, Message,
+};
+
+use crate::bus::{BusMessage, PointerInput, ServiceBus};
+use crate::clipboard::ClipboardSync;
+use crate::event_sender::EventSender;
+use crate::{KeystrokeData, MouseClickData, ScrollDelta};
+
+use super::control_hold::ControlHolds;
+use super::keyboard::{foreground_app, KeyboardController, KeyboardLayout};
+use super::keyboard_app_filter::KeyboardAppFilter;
+use super::keyboard_arbitration::KeyboardArbiter;
+use super::macros::{
+    click_input, keystroke_input, scroll_input, MacroError, MacroLibrary, MacroPlayback,
+    MacroRecorder, ReplayInput,
+};
+
+pub struct InputService {
+    /// The session's overlay is drawing the controllers' cursors, their input
+    /// is dropped until then, see `attach_overlay`
+    attached: bool,
+    /// The active session never creates the keyboard and mouse injection
+    view_only: bool,
+    /// Injects the controllers' keystrokes, `None` in view-only sessions
+    keyboard_controller: Option<KeyboardController<KeyboardLayout>>,
+    /// Whose keystrokes are injected when several controllers are in control
+    keyboard_arbiter: KeyboardArbiter,
+    /// Whose input waits for a push-to-control hold, see `input::control_hold`
+    control_holds: ControlHolds,
+    /// The apps the controllers' keystrokes are injected in, set by the tauri app
+    keyboard_app_filter: KeyboardAppFilter,
+    /// Layout the keystrokes are mapped through instead of the detected one, set by the tauri app
+    forced_keyboard_layout: Option<KeyboardLayoutKind>,
+    /// Recording of the controllers' inputs, started by the sharer
+    macro_recorder: Option<MacroRecorder>,
+    macros: MacroLibrary,
+    macro_playback: Option<MacroPlayback>,
+    /// Syncs the clipboard with the controllers, `None` while the sharer has it off
+    clipboard_sync: Option<ClipboardSync>,
+    event_sender: Box<dyn EventSender>,
+}
+
+impl InputService {
+    /// # Arguments
+    ///
+    /// * `event_sender` - Sender for the macro playback's and the clipboard's events
+    pub fn new(event_sender: Box<dyn EventSender>) -> Self {
+        Self {
+            attached: false,
+            view_only: false,
+            keyboard_controller: None,
+            keyboard_arbiter: KeyboardArbiter::new(),
+            control_holds: ControlHolds::new(),
+            keyboard_app_filter: KeyboardAppFilter::new(),
+            forced_keyboard_layout: None,
+            macro_recorder: None,
+            macros: MacroLibrary::new(),
+            macro_playback: None,
+            clipboard_sync: None,
+            event_sender,
+        }
+    }
+
+    /// Records the mode of a session that is starting.
+    pub fn start_session(&mut self, view_only: bool) {
+        self.view_only = view_only;
+        if view_only {
+            log::info!("InputService::start_session: view-only session");
+        }
+    }
+
+    /// Starts handling the controllers' input once the session's overlay exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyboard_controller` - Injects the keystrokes, `None` in view-only sessions
+    pub fn attach_overlay(
+        &mut self,
+        mut keyboard_controller: Option<KeyboardController<KeyboardLayout>>,
+    ) {
+        if let Some(keyboard_controller) = keyboard_controller.as_mut() {
+            keyboard_controller.set_forced_layout(self.forced_keyboard_layout);
+        }
+        self.keyboard_controller = keyboard_controller;
+        self.attached = true;
+    }
+
+    /// Drops the injection, the macro recording and playback and the
+    /// clipboard sync of the session that ended.
+    pub fn end_session(&mut self, bus: &mut ServiceBus) {
+        self.stop_macro_playback(bus);
+        self.macro_recorder = None;
+        self.clipboard_sync = None;
+        self.keyboard_controller = None;
+        self.attached = false;
+        self.view_only = false;
+        self.keyboard_arbiter.reset();
+    }
+
+    /// Forgets the push-to-control holds, after the state was reset.
+    pub fn reset(&mut self) {
+        self.control_holds.reset();
+    }
+
+    /// Returns whether the session never injects input.
+    pub fn view_only(&self) -> bool {
+        self.view_only
+    }
+
+    /// Records `input` if the sharer is recording a macro.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input a controller sent
+    /// * `sid` - The controller that sent it, `None` for keystrokes
+    fn record_macro_input(&mut self, input: MacroInput, sid: Option<&str>) {
+        if let Some(recorder) = self.macro_recorder.as_mut() {
+            recorder.record(input, sid, std::time::Instant::now());
+        }
+    }
+
+    /// Moves the cursor of the controller `sid`, also in view-only sessions.
+    pub fn cursor_position(&mut self, x: f32, y: f32, sid: String, bus: &mut ServiceBus) {
+        if !self.attached {
+            log::warn!("cursor_position: no overlay");
+            return;
+        }
+        let (x, y) = (x as f64, y as f64);
+        self.record_macro_input(MacroInput::MouseMove { x, y }, Some(sid.as_str()));
+        bus.post(BusMessage::Pointer(PointerInput::Move { x, y, sid }));
+    }
+
+    /// Passes a click of the controller `sid` to the overlay, a press gives
+    /// it the keyboard, see `KeyboardArbiter`.
+    pub fn mouse_click(&mut self, data: MouseClickData, sid: String, bus: &mut ServiceBus) {
+        if !self.attached {
+            log::warn!("mouse_click: no overlay");
+            return;
+        }
+        if self.view_only {
+            log::debug!("mouse_click: view-only session, ignoring mouse click");
+            return;
+        }
+        if !self.control_holds.accepts(sid.as_str(), !data.down) {
+            log::debug!("mouse_click: {sid} doesn't hold push-to-control, ignoring mouse click");
+            return;
+        }
+        self.record_macro_input(click_input(&data), Some(sid.as_str()));
+        if data.down {
+            self.keyboard_arbiter.clicked(sid.as_str());
+        }
+        bus.post(BusMessage::Pointer(PointerInput::Click(data, sid)));
+    }
+
+    /// Injects a keystroke of the controller `sid` when it may type in the
+    /// foreground app.
+    pub fn keystroke(&mut self, data: KeystrokeData, sid: String) {
+        if !self.attached {
+            log::warn!("keystroke: no overlay");
+            return;
+        }
+        if self.view_only {
+            log::debug!("keystroke: view-only session, ignoring keystroke");
+            return;
+        }
+        if !self.control_holds.accepts(sid.as_str(), !data.down) {
+            log::debug!("keystroke: {sid} doesn't hold push-to-control, ignoring keystroke");
+            return;
+        }
+        if !self.keyboard_arbiter.accepts(sid.as_str(), data.down) {
+            log::debug!(
+                "keystroke: ignoring keystroke of {sid}, {:?} has the keyboard",
+                self.keyboard_arbiter.owner()
+            );
+            return;
+        }
+        if !self.keyboard_app_filter.is_empty() {
+            let foreground = foreground_app();
+            if !self.keyboard_app_filter.accepts(&foreground, data.down) {
+                log::info!("keystroke: ignoring keystroke of {sid}, {foreground:?} isn't allowed");
+                return;
+            }
+        }
+        self.record_macro_input(keystroke_input(&data), None);
+        if let Some(keyboard_controller) = self.keyboard_controller.as_mut() {
+            keyboard_controller.simulate_keystrokes(data);
+        }
+    }
+
+    /// Passes a scroll of the controller `sid` to the overlay.
+    pub fn scroll(&mut self, delta: ScrollDelta, sid: String, bus: &mut ServiceBus) {
+        if !self.attached {
+            log::warn!("scroll: no overlay");
+            return;
+        }
+        if self.view_only {
+            log::debug!("scroll: view-only session, ignoring scroll");
+            return;
+        }
+        if !self.control_holds.accepts(sid.as_str(), false) {
+            log::debug!("scroll: {sid} doesn't hold push-to-control, ignoring scroll");
+            return;
+        }
+        self.record_macro_input(scroll_input(&delta), Some(sid.as_str()));
+        bus.post(BusMessage::Pointer(PointerInput::Scroll(delta, sid)));
+    }
+
+    /// Enables or disables the keystrokes with the controllers' cursors.
+    pub fn set_controllers_enabled(&mut self, enabled: bool) {
+        if let Some(keyboard_controller) = self.keyboard_controller.as_mut() {
+            keyboard_controller.set_enabled(enabled);
+        }
+    }
+
+    pub fn set_keyboard_arbitration(&mut self, policy: KeyboardArbitration) {
+        self.keyboard_arbiter.set_policy(policy);
+    }
+
+    pub fn set_keyboard_app_allowlist(&mut self, apps: Vec<String>) {
+        self.keyboard_app_filter.set_allowlist(apps);
+    }
+
+    pub fn set_keyboard_layout(&mut self, layout: Option<KeyboardLayoutKind>) {
+        self.forced_keyboard_layout = layout;
+        if let Some(keyboard_controller) = self.keyboard_controller.as_mut() {
+            keyboard_controller.set_forced_layout(layout);
+        }
+    }
+
+    /// Records a push-to-control hold of the controller `sid`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the hold changed.
+    pub fn control_hold(&mut self, active: bool, sid: &str) -> bool {
+        self.control_holds.set(sid, active)
+    }
+
+    /// Forgets the keyboard and the hold of a controller that left.
+    pub fn participant_left(&mut self, sid: &str) {
+        self.keyboard_arbiter.participant_left(sid);
+        self.control_holds.participant_left(sid);
+    }
+
+    /// Returns the injection of the active session.
+    ///
+    /// # Arguments
+    ///
+    /// * `sharing` - Whether a session is active
+    /// * `mouse` - Whether the overlay injects the mouse input, `None` without an overlay
+    pub fn injection_status(&self, sharing: bool, mouse: Option<bool>) -> InputInjectionStatus {
+        match mouse {
+            Some(mouse) => InputInjectionStatus {
+                sharing: true,
+                view_only: self.view_only,
+                keyboard: self.keyboard_controller.is_some(),
+                mouse,
+                keyboard_layout: self
+                    .keyboard_controller
+                    .as_ref()
+                    .and_then(|keyboard_controller| keyboard_controller.layout_name()),
+            },
+            None => InputInjectionStatus {
+                sharing,
+                view_only: sharing && self.view_only,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn macro_error(bus: &mut ServiceBus, error: MacroError) {
+        log::warn!("macro_error: {error}");
+        bus.tauri(Message::MacroError(error.to_string()));
+    }
+
+    pub fn start_macro_recording(&mut self) {
+        log::info!("start_macro_recording");
+        sentry_utils::add_breadcrumb("state", "start macro recording");
+        self.macro_recorder = Some(MacroRecorder::new());
+    }
+
+    /// Finishes the macro recording and replies with the recorded macro.
+    pub fn stop_macro_recording(&mut self, name: String, bus: &mut ServiceBus) {
+        let recorded = match self.macro_recorder.take() {
+            Some(recorder) => {
+                let id = self.macros.next_id();
+                recorder
+                    .finish(id, name)
+                    .and_then(|recorded| self.macros.insert(recorded.clone()).map(|_| recorded))
+            }
+            None => Err(MacroError::NotRecording),
+        };
+        match recorded {
+            Ok(recorded) => bus.tauri(Message::MacroRecorded(recorded)),
+            Err(e) => {
+                log::warn!("stop_macro_recording: {e}");
+                bus.tauri(Message::MacroRecordingFailed(e.to_string()));
+            }
+        }
+    }
+
+    /// Adds a macro the sharer approved to the library.
+    pub fn load_macro(&mut self, input_macro: InputMacro, bus: &mut ServiceBus) {
+        if let Err(e) = self.macros.insert(input_macro) {
+            Self::macro_error(bus, e);
+        }
+    }
+
+    /// Starts replaying the macro `id` on the shared content.
+    pub fn play_macro(&mut self, id: String, bus: &mut ServiceBus) {
+        log::info!("play_macro: {id}");
+        sentry_utils::add_breadcrumb("state", format!("play macro: {id}"));
+        if !self.attached {
+            Self::macro_error(bus, MacroError::NotSharing);
+            return;
+        }
+        if self.view_only {
+            Self::macro_error(bus, MacroError::ViewOnly);
+            return;
+        }
+        if self.macro_playback.is_some() {
+            Self::macro_error(bus, MacroError::AlreadyPlaying);
+            return;
+        }
+        let input_macro = match self.macros.get(&id) {
+            Ok(input_macro) => input_macro.clone(),
+            Err(e) => {
+                Self::macro_error(bus, e);
+                return;
+            }
+        };
+        self.macro_playback = Some(MacroPlayback::start(input_macro, self.event_sender.clone()));
+    }
+
+    /// Simulates an input of the playing macro, the mouse input goes to the overlay.
+    pub fn replay_macro_input(&mut self, playback: u64, input: MacroInput, bus: &mut ServiceBus) {
+        if !self
+            .macro_playback
+            .as_ref()
+            .is_some_and(|macro_playback| macro_playback.is(playback))
+        {
+            return;
+        }
+        if !self.attached {
+            log::warn!("replay_macro_input: no overlay");
+            return;
+        }
+        match ReplayInput::from_input(input) {
+            Some(ReplayInput::CursorMove(x, y)) => {
+                bus.post(BusMessage::Pointer(PointerInput::ReplayMove { x, y }))
+            }
+            Some(ReplayInput::MouseClick(data)) => {
+                bus.post(BusMessage::Pointer(PointerInput::ReplayClick(data)))
+            }
+            Some(ReplayInput::Keystroke(data)) => {
+                if let Some(keyboard_controller) = self.keyboard_controller.as_mut() {
+                    keyboard_controller.simulate_keystrokes(data);
+                }
+            }
+            Some(ReplayInput::Scroll(delta)) => {
+                bus.post(BusMessage::Pointer(PointerInput::ReplayScroll(delta)))
+            }
+            None => log::warn!("replay_macro_input: Keystroke without valid keys"),
+        }
+    }
+
+    /// Tells the tauri app the playback `playback` replayed every step.
+    pub fn macro_playback_finished(&mut self, playback: u64, bus: &mut ServiceBus) {
+        if !self
+            .macro_playback
+            .as_ref()
+            .is_some_and(|macro_playback| macro_playback.is(playback))
+        {
+            return;
+        }
+        if let Some(macro_playback) = self.macro_playback.take() {
+            let id = macro_playback.id().to_string();
+            log::info!("macro_playback_finished: {id}");
+            bus.tauri(Message::MacroFinished { id });
+        }
+    }
+
+    /// Stops the playing macro, the tauri app is told it didn't finish.
+    fn stop_macro_playback(&mut self, bus: &mut ServiceBus) {
+        if let Some(macro_playback) = self.macro_playback.take() {
+            let reason = format!("Playback of {} was stopped", macro_playback.id());
+            drop(macro_playback);
+            log::info!("stop_macro_playback: {reason}");
+            bus.tauri(Message::MacroError(reason));
+        }
+    }
+
+    /// Starts or stops syncing the clipboard with the controllers, see `clipboard`.
+    pub fn set_clipboard_sharing(&mut self, enabled: bool) {
+        if !enabled {
+            self.clipboard_sync = None;
+            return;
+        }
+        if self.clipboard_sync.is_none() {
+            self.clipboard_sync = Some(ClipboardSync::start(self.event_sender.clone()));
+        }
+    }
+
+    /// Posts the text copied on the sharer's machine for the controllers,
+    /// while the clipboard is shared.
+    pub fn clipboard_changed(&mut self, text: String, bus: &mut ServiceBus) {
+        if self.clipboard_sync.is_some() {
+            bus.post(BusMessage::Clipboard(text));
+        }
+    }
+
+    /// Writes the clipboard text of the controller `sid` to the sharer's clipboard.
+    pub fn clipboard_update(&mut self, text: String, sid: &str) {
+        let Some(clipboard_sync) = self.clipboard_sync.as_mut() else {
+            log::debug!("clipboard_update: Clipboard sharing is off, dropping update of {sid}");
+            return;
+        };
+        if self.view_only {
+            log::warn!("clipboard_update: Dropping clipboard update of {sid} in view-only session");
+            return;
+        }
+        if let Err(e) = clipboard_sync.apply_remote(text) {
+            log::error!("clipboard_update: Failed to apply clipboard update of {sid}: {e:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+
+    fn input_service(view_only: bool) -> InputService {
+        let mut input_service = InputService::new(Box::new(EventCollector::new()));
+        input_service.start_session(view_only);
+        input_service.attach_overlay(None);
+        input_service
+    }
+
+    fn click(down: bool) -> MouseClickData {
+        MouseClickData {
+            x: 0.5,
+            y: 0.5,
+            button: 0,
+            clicks: 1.,
+            down,
+            shift: false,
+            alt: false,
+            ctrl: false,
+            meta: false,
+        }
+    }
+
+    fn keystroke(down: bool) -> KeystrokeData {
+        KeystrokeData {
+            keys: vec![crate::input::keys::Key::Character("a".to_string())],
+            meta: false,
+            shift: false,
+            ctrl: false,
+            alt: false,
+            down,
+        }
+    }
+
+    /// Returns the steps of the recorded macro, see `stop_macro_recording`.
+    fn recorded_steps(input_service: &mut InputService) -> Vec<MacroInput> {
+        let mut bus = ServiceBus::new();
+        input_service.stop_macro_recording("test".to_string(), &mut bus);
+        match bus.take() {
+            Some(BusMessage::Tauri(Message::MacroRecorded(recorded))) => {
+                recorded.steps.into_iter().map(|step| step.input).collect()
+            }
+            other => panic!("expected a recorded macro, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_mouse_input_goes_to_the_overlay() {
+        let mut input_service = input_service(false);
+        let mut bus = ServiceBus::new();
+
+        input_service.cursor_position(0.25, 0.75, "a".to_string(), &mut bus);
+        input_service.mouse_click(click(true), "a".to_string(), &mut bus);
+        input_service.scroll(ScrollDelta { x: 0., y: 3. }, "a".to_string(), &mut bus);
+
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Pointer(PointerInput::Move { x, y, sid })) if x == 0.25 && y == 0.75 && sid == "a"
+        ));
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Pointer(PointerInput::Click(data, sid))) if data.down && sid == "a"
+        ));
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Pointer(PointerInput::Scroll(delta, sid))) if delta.y == 3. && sid == "a"
+        ));
+        assert!(bus.is_empty());
+    }
+
+    #[test]
+    fn test_input_is_dropped_without_an_overlay() {
+        let mut input_service = InputService::new(Box::new(EventCollector::new()));
+        input_service.start_session(false);
+        let mut bus = ServiceBus::new();
+
+        input_service.cursor_position(0.5, 0.5, "a".to_string(), &mut bus);
+        input_service.mouse_click(click(true), "a".to_string(), &mut bus);
+        assert!(bus.is_empty());
+
+        input_service.play_macro("missing".to_string(), &mut bus);
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Tauri(Message::MacroError(error))) if error == MacroError::NotSharing.to_string()
+        ));
+    }
+
+    #[test]
+    fn test_view_only_session_only_moves_the_cursors() {
+        let mut input_service = input_service(true);
+        let mut bus = ServiceBus::new();
+        input_service.start_macro_recording();
+
+        input_service.cursor_position(0.5, 0.5, "a".to_string(), &mut bus);
+        input_service.mouse_click(click(true), "a".to_string(), &mut bus);
+        input_service.scroll(ScrollDelta { x: 0., y: 1. }, "a".to_string(), &mut bus);
+        input_service.keystroke(keystroke(true), "a".to_string());
+
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Pointer(PointerInput::Move { .. }))
+        ));
+        assert!(bus.is_empty());
+        assert_eq!(
+            recorded_steps(&mut input_service),
+            vec![MacroInput::MouseMove { x: 0.5, y: 0.5 }]
+        );
+
+        input_service.play_macro("missing".to_string(), &mut bus);
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Tauri(Message::MacroError(error))) if error == MacroError::ViewOnly.to_string()
+        ));
+    }
+
+    #[test]
+    fn test_push_to_control_holds_back_presses() {
+        let mut input_service = input_service(false);
+        let mut bus = ServiceBus::new();
+        assert!(input_service.control_hold(false, "a"));
+
+        input_service.mouse_click(click(true), "a".to_string(), &mut bus);
+        input_service.scroll(ScrollDelta { x: 0., y: 1. }, "a".to_string(), &mut bus);
+        assert!(bus.is_empty());
+        /* The release of a button pressed before the hold ended passes. */
+        input_service.mouse_click(click(false), "a".to_string(), &mut bus);
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Pointer(PointerInput::Click(data, _))) if !data.down
+        ));
+
+        assert!(input_service.control_hold(true, "a"));
+        input_service.mouse_click(click(true), "a".to_string(), &mut bus);
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Pointer(PointerInput::Click(data, _))) if data.down
+        ));
+    }
+
+    #[test]
+    fn test_keyboard_follows_the_last_click() {
+        let mut input_service = input_service(false);
+        let mut bus = ServiceBus::new();
+        input_service.start_macro_recording();
+
+        input_service.mouse_click(click(true), "a".to_string(), &mut bus);
+        /* "a" clicked last, the presses of "b" are dropped. */
+        input_service.keystroke(keystroke(true), "b".to_string());
+        input_service.keystroke(keystroke(true), "a".to_string());
+
+        assert_eq!(
+            recorded_steps(&mut input_service),
+            vec![click_input(&click(true)), keystroke_input(&keystroke(true))]
+        );
+
+        /* The arbiter forgets the owner that left. */
+        input_service.participant_left("a");
+        input_service.start_macro_recording();
+        input_service.keystroke(keystroke(true), "b".to_string());
+        assert_eq!(
+            recorded_steps(&mut input_service),
+            vec![keystroke_input(&keystroke(true))]
+        );
+    }
+
+    #[test]
+    fn test_injection_status() {
+        let input_service = input_service(true);
+        let status = input_service.injection_status(true, Some(false));
+        assert!(status.sharing && status.view_only);
+        assert!(!status.keyboard && !status.mouse);
+
+        let status = input_service.injection_status(false, None);
+        assert!(!status.sharing && !status.view_only);
+    }
+
+    #[test]
+    fn test_end_session_stops_handling_input() {
+        let mut input_service = input_service(false);
+        let mut bus = ServiceBus::new();
+        input_service.start_macro_recording();
+
+        input_service.end_session(&mut bus);
+        assert!(bus.is_empty());
+        assert!(!input_service.view_only());
+        input_service.cursor_position(0.5, 0.5, "a".to_string(), &mut bus);
+        assert!(bus.is_empty());
+        input_service.stop_macro_recording("test".to_string(), &mut bus);
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Tauri(Message::MacroRecordingFailed(_)))
+        ));
+    }
+
+    #[test]
+    fn test_clipboard_changes_need_the_sharing_on() {
+        let mut input_service = input_service(false);
+        let mut bus = ServiceBus::new();
+
+        input_service.clipboard_changed("copied".to_string(), &mut bus);
+        assert!(bus.is_empty());
+    }
+}
@@ -1,5 +1,14 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
+use socket_lib::KeyboardLayoutKind;
+
+use super::keyboard_layouts::{layout_kind, StaticLayout};
+use super::keys::Key;
 use crate::KeystrokeData;
 
 #[cfg(target_os = "macos")]
@@ -14,8 +23,99 @@ mod platform;
 #[path = "keyboard_linux.rs"]
 mod platform;
 
+pub use platform::foreground_app;
+use platform::key_repeat_settings;
+pub use platform::us_keycode;
 pub use platform::{KeyboardEvent, KeyboardLayout};
 
+/// The sharer's OS key repeat settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRepeatSettings {
+    /// How long a key needs to be held before it starts repeating
+    pub delay: Duration,
+    /// Time between the repeated key presses
+    pub interval: Duration,
+}
+
+/// A held key that is being repeated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RepeatedKey {
+    keycode: u16,
+    modifier: u32,
+    /// The string that overrides the event's character, see `simulate_keystrokes`
+    utf: Option<String>,
+}
+
+enum KeyRepeatCommands {
+    /// A key was pressed, it starts repeating after the delay
+    Press(RepeatedKey),
+    /// A key was released, it stops repeating if it is the held one
+    Release(u16),
+    /// Stop repeating the held key
+    Stop,
+    Terminate,
+}
+
+/// Creates and sends a single platform keyboard event.
+fn send_key_event(keycode: u16, modifier: u32, down: bool, utf: Option<&str>) {
+    let event = KeyboardEvent::new(keycode, modifier, down);
+    if event.is_none() {
+        log::error!("send_key_event: couldn't create keyboard event");
+        return;
+    }
+    let mut event = event.unwrap();
+    if let Some(utf) = utf {
+        event.override_utf(utf);
+    }
+    event.send();
+}
+
+/// This thread synthesizes the key repeats of the held key.
+///
+/// The controllers send a single key down for a held key, like the OS
+/// does for a physical keyboard the held key is pressed again every
+/// `interval` once it has been held for `delay`.
+fn key_repeat_thread(
+    settings: KeyRepeatSettings,
+    receiver: Receiver<KeyRepeatCommands>,
+    send: impl Fn(&RepeatedKey),
+) {
+    let mut held: Option<(RepeatedKey, Instant)> = None;
+    loop {
+        let command = match &held {
+            Some((_, next_repeat)) => {
+                let timeout = next_repeat.saturating_duration_since(Instant::now());
+                receiver.recv_timeout(timeout)
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match command {
+            Ok(KeyRepeatCommands::Press(key)) => {
+                held = Some((key, Instant::now() + settings.delay));
+            }
+            Ok(KeyRepeatCommands::Release(keycode)) => {
+                if matches!(&held, Some((key, _)) if key.keycode == keycode) {
+                    held = None;
+                }
+            }
+            Ok(KeyRepeatCommands::Stop) => {
+                held = None;
+            }
+            Ok(KeyRepeatCommands::Terminate) => break,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some((key, next_repeat)) = held.as_mut() {
+                    send(key);
+                    *next_repeat += settings.interval;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                log::error!("key_repeat_thread: command channel disconnected");
+                break;
+            }
+        }
+    }
+}
+
 // See https://github.com/phracker/MacOSX-SDKs/blob/master/MacOSX10.6.sdk/System/Library/Frameworks/Carbon.framework/Versions/A/Frameworks/HIToolbox.framework/Versions/A/Headers/Events.h
 pub enum KeyModifier {
     Cmd = 1 << 8,
@@ -194,6 +294,10 @@ pub trait KeyboardLayoutTrait {
     /// A HashMap mapping standardized key names to platform-specific keycodes.
     /// The keys are static string references for efficiency.
     fn get_independent_codes(&self) -> HashMap<&'static str, u16>;
+
+    /// Returns the platform's name of the layout, the input source id on
+    /// macOS and the layout id on Windows, `None` when it isn't known.
+    fn name(&self) -> Option<String>;
 }
 
 /// A comprehensive key mapping table that translates key strings and modifier combinations
@@ -344,10 +448,18 @@ impl KeyMap {
 pub struct KeyboardController<T: KeyboardLayoutTrait> {
     /// Internal key mapping table for efficient keycode lookups.
     map: KeyMap,
+    /// The US layout's keys, the shortcuts of keys the layout doesn't have are pressed with them.
+    shortcut_map: KeyMap,
     /// Platform-specific keyboard layout handler.
     layout: T,
+    /// Layout the keys are mapped through instead of the detected one, set by the sharer
+    forced_layout: Option<KeyboardLayoutKind>,
     /// Whether keyboard simulation is currently enabled.
     enabled: bool,
+    /// Sender for the key repeat thread
+    repeat_sender: Sender<KeyRepeatCommands>,
+    /// Thread that repeats the held key
+    repeat_handle: Option<JoinHandle<()>>,
 }
 
 impl<T: KeyboardLayoutTrait> KeyboardController<T> {
@@ -363,10 +475,31 @@ impl<T: KeyboardLayoutTrait> KeyboardController<T> {
     pub fn new() -> KeyboardController<KeyboardLayout> {
         let layout = KeyboardLayout::new();
         let map = KeyMap::new(&layout);
+        let shortcut_map = KeyMap::new(&StaticLayout::new(
+            KeyboardLayoutKind::Us,
+            us_keycode,
+            layout.get_independent_codes(),
+        ));
+        log::info!(
+            "KeyboardController::new: layout: {:?}",
+            layout.name().map(|name| (layout_kind(&name), name))
+        );
+        let settings = key_repeat_settings();
+        log::info!("KeyboardController::new: key repeat settings: {settings:?}");
+        let (repeat_sender, receiver) = mpsc::channel();
+        let repeat_handle = std::thread::spawn(move || {
+            key_repeat_thread(settings, receiver, |key| {
+                send_key_event(key.keycode, key.modifier, true, key.utf.as_deref())
+            })
+        });
         KeyboardController {
             map,
+            shortcut_map,
             layout,
+            forced_layout: None,
             enabled: true,
+            repeat_sender,
+            repeat_handle: Some(repeat_handle),
         }
     }
 
@@ -379,8 +512,45 @@ impl<T: KeyboardLayoutTrait> KeyboardController<T> {
     /// # Arguments
     ///
     /// * `enabled` - `true` to enable simulation, `false` to disable
+    ///
+    /// Disabling stops repeating the held key.
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        if !enabled {
+            self.send_repeat_command(KeyRepeatCommands::Stop);
+        }
+    }
+
+    /// Maps the keys through a known layout instead of the detected one.
+    ///
+    /// # Arguments
+    ///
+    /// * `forced_layout` - The layout, `None` follows the detected layout again
+    pub fn set_forced_layout(&mut self, forced_layout: Option<KeyboardLayoutKind>) {
+        if self.forced_layout == forced_layout {
+            return;
+        }
+        log::info!("set_forced_layout: {forced_layout:?}");
+        self.forced_layout = forced_layout;
+        self.map = match forced_layout {
+            Some(kind) => KeyMap::new(&StaticLayout::new(
+                kind,
+                us_keycode,
+                self.layout.get_independent_codes(),
+            )),
+            None => KeyMap::new(&self.layout),
+        };
+    }
+
+    /// Returns the platform's name of the sharer's active layout, see `KeyboardLayoutTrait::name`.
+    pub fn layout_name(&self) -> Option<String> {
+        self.layout.name()
+    }
+
+    fn send_repeat_command(&self, command: KeyRepeatCommands) {
+        if let Err(e) = self.repeat_sender.send(command) {
+            log::error!("send_repeat_command: error sending command: {e:?}");
+        }
     }
 
     /// Simulates a keystroke from high-level keystroke data.
@@ -393,11 +563,13 @@ impl<T: KeyboardLayoutTrait> KeyboardController<T> {
     ///
     /// 1. Check if simulation is enabled (early return if disabled)
     /// 2. Convert boolean modifiers to platform modifier bitmask
-    /// 3. Detect and handle layout changes (rebuild key map if needed)
-    /// 4. Look up keycode for the key + modifier combination
-    /// 5. Create platform-specific keyboard event
-    /// 6. Override UTF string for layout-independent character input
-    /// 7. Send the event to the system
+    /// 3. Detect and handle layout changes (rebuild key map if needed, unless a layout is forced)
+    /// 4. For each key, pressed in order and released in reverse order:
+    ///    1. Look up keycode for the key + modifier combination, shortcuts fall back to the US layout
+    ///    2. Create platform-specific keyboard event
+    ///    3. Override UTF string for layout-independent character input
+    ///    4. Send the event to the system
+    ///    5. Start or stop repeating the key
     ///
     /// # UTF Override Logic
     ///
@@ -408,7 +580,7 @@ impl<T: KeyboardLayoutTrait> KeyboardController<T> {
     ///
     /// UTF override is skipped when:
     /// - Meta or Ctrl modifiers are active (typically non-character shortcuts)
-    /// - Key is layout-independent (Enter, Tab, arrows, etc.)
+    /// - Key is a named key (Enter, Tab, arrows, etc.)
     /// - Keystroke is a key release event (`down = false`)
     ///
     /// # Arguments
@@ -437,30 +609,52 @@ impl<T: KeyboardLayoutTrait> KeyboardController<T> {
             modifier = extend_modifier(modifier, KeyModifier::Ctrl);
         }
 
+        /* A forced layout is kept when the sharer switches layouts. */
         let layout_changed = self.layout.has_changed();
-        if layout_changed {
-            log::info!("simulate_keystrokes: layout changed updating map");
+        if layout_changed && self.forced_layout.is_none() {
+            log::info!(
+                "simulate_keystrokes: layout changed to {:?} updating map",
+                self.layout.name()
+            );
             self.map = KeyMap::new(&self.layout);
         }
 
-        let keycode = match self.map.get_code(&keystroke_data.key, modifier) {
+        if keystroke_data.down {
+            for key in &keystroke_data.keys {
+                self.simulate_key(key, modifier, &keystroke_data);
+            }
+        } else {
+            for key in keystroke_data.keys.iter().rev() {
+                self.simulate_key(key, modifier, &keystroke_data);
+            }
+        }
+    }
+
+    /// Sends the event of one of the keys of `keystroke_data`.
+    fn simulate_key(&mut self, key: &Key, modifier: u32, keystroke_data: &KeystrokeData) {
+        /*
+         * The layouts without latin letters don't have the keys of the
+         * shortcuts, they are pressed at the US layout's positions. The US
+         * layout only knows the keys with and without Shift.
+         */
+        let shortcut = keystroke_data.meta || keystroke_data.ctrl;
+        let keycode = self.map.get_code(key.as_str(), modifier).or_else(|| {
+            let shift = modifier & KeyModifier::Shift as u32;
+            shortcut
+                .then(|| self.shortcut_map.get_code(key.as_str(), shift))
+                .flatten()
+        });
+        let keycode = match keycode {
             Some(keycode) => keycode,
             None => {
                 log::warn!(
-                    "simulate_keystrokes: failed to get keycode for key: {}",
-                    keystroke_data.key
+                    "simulate_key: failed to get keycode for key: {}",
+                    key.as_str()
                 );
                 0
             }
         };
 
-        let event = KeyboardEvent::new(keycode, modifier, keystroke_data.down);
-        if event.is_none() {
-            log::error!("simulate_keystrokes: couldn't create keyboard event");
-            return;
-        }
-        let mut event = event.unwrap();
-
         /*
          * We only overwrite the utf string for non layout independent keys
          * in order to handle where the case where the sharer is using different
@@ -468,28 +662,37 @@ impl<T: KeyboardLayoutTrait> KeyboardController<T> {
          *
          * When cmd or ctrl are pressed no visible characters are inserted.
          */
-        if (keystroke_data.key != "Enter")
-            && (keystroke_data.key != "Tab")
-            && (keystroke_data.key != "Backspace")
-            && (keystroke_data.key != "Escape")
-            && (keystroke_data.key != "Delete")
-            && (keystroke_data.key != "ArrowLeft")
-            && (keystroke_data.key != "ArrowRight")
-            && (keystroke_data.key != "ArrowUp")
-            && (keystroke_data.key != "ArrowDown")
-            && (keystroke_data.key != "PageUp")
-            && (keystroke_data.key != "PageDown")
-            && (keystroke_data.key != "Control")
-            && (keystroke_data.key != "Shift")
-            && (!keystroke_data.key.is_empty())
+        let override_utf = key.is_character()
             && !keystroke_data.meta
             && !keystroke_data.ctrl
-            && keystroke_data.down
-        {
-            event.override_utf(&keystroke_data.key);
+            && keystroke_data.down;
+        let utf = override_utf.then(|| key.as_str().to_string());
+
+        send_key_event(keycode, modifier, keystroke_data.down, utf.as_deref());
+
+        if !keystroke_data.down {
+            self.send_repeat_command(KeyRepeatCommands::Release(keycode));
+        } else if !key.repeats() {
+            /* Pressing a modifier stops the repeat, like on a physical keyboard. */
+            self.send_repeat_command(KeyRepeatCommands::Stop);
+        } else {
+            self.send_repeat_command(KeyRepeatCommands::Press(RepeatedKey {
+                keycode,
+                modifier,
+                utf,
+            }));
         }
+    }
+}
 
-        event.send();
+impl<T: KeyboardLayoutTrait> Drop for KeyboardController<T> {
+    fn drop(&mut self) {
+        self.send_repeat_command(KeyRepeatCommands::Terminate);
+        if let Some(handle) = self.repeat_handle.take() {
+            if handle.join().is_err() {
+                log::error!("KeyboardController::drop: key repeat thread panicked");
+            }
+        }
     }
 }
 
@@ -502,4 +705,50 @@ mod keyboard_tests {
         env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug")).init();
         let _ = KeyboardController::<KeyboardLayout>::new();
     }
+
+    #[test]
+    fn test_key_repeat_thread() {
+        use std::sync::{Arc, Mutex};
+
+        let settings = KeyRepeatSettings {
+            delay: Duration::from_millis(50),
+            interval: Duration::from_millis(10),
+        };
+        let repeats = Arc::new(Mutex::new(vec![]));
+        let (sender, receiver) = mpsc::channel();
+        let thread_repeats = repeats.clone();
+        let handle = std::thread::spawn(move || {
+            key_repeat_thread(settings, receiver, |key| {
+                thread_repeats.lock().unwrap().push(key.keycode)
+            })
+        });
+        let key = |keycode| RepeatedKey {
+            keycode,
+            modifier: 0,
+            utf: None,
+        };
+
+        /* Released before the delay, nothing is repeated. */
+        sender.send(KeyRepeatCommands::Press(key(1))).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        sender.send(KeyRepeatCommands::Release(1)).unwrap();
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(repeats.lock().unwrap().is_empty());
+
+        /* Releasing another key doesn't stop the held one. */
+        sender.send(KeyRepeatCommands::Press(key(2))).unwrap();
+        sender.send(KeyRepeatCommands::Release(3)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        sender.send(KeyRepeatCommands::Stop).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let count = repeats.lock().unwrap().len();
+        assert!(count >= 2);
+        assert!(repeats.lock().unwrap().iter().all(|keycode| *keycode == 2));
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(repeats.lock().unwrap().len(), count);
+
+        sender.send(KeyRepeatCommands::Terminate).unwrap();
+        handle.join().unwrap();
+    }
 }
@@ -0,0 +1,108 @@
+//! Foreground app filter of the controllers' keystrokes.
+//!
+//! While pair programming the sharer only wants the controllers typing in the
+//! app they work in, a notification or a focus change shouldn't send their
+//! keystrokes to a chat or an email. With an allowlist set, key presses are
+//! only injected while one of the allowed apps is in the foreground.
+
+/// Normalizes an app name for matching, executable extensions are dropped.
+fn normalize_app(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    match name.strip_suffix(".exe") {
+        Some(stem) => stem.to_string(),
+        None => name,
+    }
+}
+
+/// Decides whether keystrokes are injected in the foreground app.
+#[derive(Debug, Default)]
+pub struct KeyboardAppFilter {
+    /// Normalized names of the allowed apps, empty when the filter is off
+    allowlist: Vec<String>,
+}
+
+impl KeyboardAppFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the allowed apps, an empty list turns the filter off.
+    ///
+    /// # Arguments
+    ///
+    /// * `apps` - App names, bundle ids on macOS or executable names on
+    ///   Windows, matched case insensitively
+    pub fn set_allowlist(&mut self, apps: Vec<String>) {
+        log::info!("KeyboardAppFilter::set_allowlist: {apps:?}");
+        let mut allowlist: Vec<String> = apps
+            .iter()
+            .map(|app| normalize_app(app))
+            .filter(|app| !app.is_empty())
+            .collect();
+        allowlist.sort();
+        allowlist.dedup();
+        self.allowlist = allowlist;
+    }
+
+    /// Returns `true` when keystrokes aren't filtered.
+    pub fn is_empty(&self) -> bool {
+        self.allowlist.is_empty()
+    }
+
+    /// Returns whether a keystroke is injected with `foreground` in front.
+    ///
+    /// # Arguments
+    ///
+    /// * `foreground` - Names of the foreground app, see `foreground_app`
+    /// * `down` - Whether the keystroke presses the keys
+    ///
+    /// # Returns
+    ///
+    /// `true` for every keystroke when the filter is off. Key presses are
+    /// dropped when the foreground app can't be found, the filter fails
+    /// closed. Releases always pass, otherwise the keys held when the focus
+    /// moved would stay pressed.
+    pub fn accepts(&self, foreground: &[String], down: bool) -> bool {
+        if self.allowlist.is_empty() || !down {
+            return true;
+        }
+        foreground
+            .iter()
+            .any(|app| self.allowlist.contains(&normalize_app(app)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apps(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_empty_allowlist_accepts_everything() {
+        let filter = KeyboardAppFilter::new();
+        assert!(filter.is_empty());
+        assert!(filter.accepts(&apps(&["Slack"]), true));
+        assert!(filter.accepts(&[], true));
+    }
+
+    #[test]
+    fn test_only_allowed_apps_get_key_presses() {
+        let mut filter = KeyboardAppFilter::new();
+        filter.set_allowlist(apps(&[" Code.exe ", "com.jetbrains.intellij", ""]));
+        assert!(!filter.is_empty());
+
+        assert!(filter.accepts(&apps(&["code"]), true));
+        assert!(filter.accepts(&apps(&["IntelliJ IDEA", "com.JetBrains.IntelliJ"]), true));
+        assert!(!filter.accepts(&apps(&["Slack", "com.tinyspeck.slackmacgap"]), true));
+        /* The foreground app is unknown. */
+        assert!(!filter.accepts(&[], true));
+        /* Releases of the keys held when the focus moved pass. */
+        assert!(filter.accepts(&apps(&["Slack"]), false));
+
+        filter.set_allowlist(vec![]);
+        assert!(filter.accepts(&apps(&["Slack"]), true));
+    }
+}
@@ -0,0 +1,127 @@
+//! Arbitration of the controllers' keystrokes.
+//!
+//! With several controllers in control the keystrokes of one of them can land
+//! while another one is in the middle of a drag or typing in a field it just
+//! clicked. The arbiter remembers who clicked last and, depending on the
+//! `KeyboardArbitration` policy, only lets that controller's keystrokes
+//! through.
+
+use socket_lib::KeyboardArbitration;
+
+/// Decides whose keystrokes are injected.
+#[derive(Debug, Default)]
+pub struct KeyboardArbiter {
+    policy: KeyboardArbitration,
+    /// The controller that clicked last
+    owner: Option<String>,
+}
+
+impl KeyboardArbiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn policy(&self) -> KeyboardArbitration {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: KeyboardArbitration) {
+        log::info!("KeyboardArbiter::set_policy: {policy:?}");
+        self.policy = policy;
+    }
+
+    /// Returns the controller that clicked last.
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    /// Records a mouse press of the controller `sid`, which takes the keyboard.
+    pub fn clicked(&mut self, sid: &str) {
+        if self.owner.as_deref() != Some(sid) {
+            log::debug!("KeyboardArbiter::clicked: {sid} takes the keyboard");
+            self.owner = Some(sid.to_string());
+        }
+    }
+
+    /// Returns whether a keystroke of the controller `sid` is injected.
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The controller that sent the keystroke
+    /// * `down` - Whether the keystroke presses the keys
+    ///
+    /// # Returns
+    ///
+    /// `true` for every keystroke without an owner, before anyone clicked
+    /// all controllers can type. Releases always pass, otherwise the keys a
+    /// controller held while another one clicked would stay pressed.
+    pub fn accepts(&self, sid: &str, down: bool) -> bool {
+        match (self.policy, self.owner.as_deref()) {
+            (KeyboardArbitration::Anyone, _) => true,
+            (KeyboardArbitration::LastClicked, None) => true,
+            (KeyboardArbitration::LastClicked, Some(owner)) => !down || owner == sid,
+        }
+    }
+
+    /// Forgets the owner when it left the session.
+    pub fn participant_left(&mut self, sid: &str) {
+        if self.owner.as_deref() == Some(sid) {
+            self.owner = None;
+        }
+    }
+
+    /// Forgets the owner when the session ended, the policy is kept.
+    pub fn reset(&mut self) {
+        self.owner = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_clicked_owns_the_keyboard() {
+        let mut arbiter = KeyboardArbiter::new();
+        assert_eq!(arbiter.policy(), KeyboardArbitration::LastClicked);
+        /* Nobody clicked yet. */
+        assert!(arbiter.accepts("a", true));
+        assert!(arbiter.accepts("b", true));
+
+        arbiter.clicked("a");
+        assert!(arbiter.accepts("a", true));
+        assert!(!arbiter.accepts("b", true));
+        /* Releases of the keys held before losing the keyboard pass. */
+        assert!(arbiter.accepts("b", false));
+
+        arbiter.clicked("b");
+        assert_eq!(arbiter.owner(), Some("b"));
+        assert!(!arbiter.accepts("a", true));
+        assert!(arbiter.accepts("b", true));
+    }
+
+    #[test]
+    fn test_anyone_policy() {
+        let mut arbiter = KeyboardArbiter::new();
+        arbiter.set_policy(KeyboardArbitration::Anyone);
+        arbiter.clicked("a");
+        assert!(arbiter.accepts("a", true));
+        assert!(arbiter.accepts("b", true));
+    }
+
+    #[test]
+    fn test_owner_leaving() {
+        let mut arbiter = KeyboardArbiter::new();
+        arbiter.clicked("a");
+        arbiter.participant_left("b");
+        assert_eq!(arbiter.owner(), Some("a"));
+        arbiter.participant_left("a");
+        assert_eq!(arbiter.owner(), None);
+        assert!(arbiter.accepts("b", true));
+
+        arbiter.clicked("b");
+        arbiter.reset();
+        assert_eq!(arbiter.owner(), None);
+        assert_eq!(arbiter.policy(), KeyboardArbitration::LastClicked);
+    }
+}
@@ -0,0 +1,238 @@
+//! Keyboard layouts the core knows the keys of.
+//!
+//! The keyboard simulation maps the controllers' key strings to keycodes
+//! through the sharer's active layout, see `KeyMap`. When the platform
+//! reports the layout wrong, e.g. an input method without layout data, the
+//! sharer can force one of these layouts instead.
+//!
+//! A layout is described by the characters of its keys at the positions of
+//! the US layout's keys, the platforms give the keycodes of the positions.
+//! The US layout also maps the shortcuts of the layouts without latin
+//! letters, e.g. Ctrl+C on a Greek layout presses the key of the US "c".
+
+use std::collections::HashMap;
+
+use socket_lib::KeyboardLayoutKind;
+
+use super::keyboard::{KeyModifier, KeyboardLayoutTrait};
+
+/// The rows of the US layout's character keys, a layout's rows have the same lengths.
+const US_POSITIONS: [&str; 4] = [
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+
+/// The characters of a layout's keys without and with Shift.
+struct LayoutRows {
+    normal: [&'static str; 4],
+    shifted: [&'static str; 4],
+}
+
+const US: LayoutRows = LayoutRows {
+    normal: US_POSITIONS,
+    shifted: [
+        "~!@#$%^&*()_+",
+        "QWERTYUIOP{}|",
+        "ASDFGHJKL:\"",
+        "ZXCVBNM<>?",
+    ],
+};
+
+const FRENCH: LayoutRows = LayoutRows {
+    normal: [
+        "²&é\"'(-è_çà)=",
+        "azertyuiop^$*",
+        "qsdfghjklmù",
+        "wxcvbn,;:!",
+    ],
+    shifted: [
+        "²1234567890°+",
+        "AZERTYUIOP¨£µ",
+        "QSDFGHJKLM%",
+        "WXCVBN?./§",
+    ],
+};
+
+const GERMAN: LayoutRows = LayoutRows {
+    normal: [
+        "^1234567890ß´",
+        "qwertzuiopü+#",
+        "asdfghjklöä",
+        "yxcvbnm,.-",
+    ],
+    shifted: [
+        "°!\"§$%&/()=?`",
+        "QWERTZUIOPÜ*'",
+        "ASDFGHJKLÖÄ",
+        "YXCVBNM;:_",
+    ],
+};
+
+const GREEK: LayoutRows = LayoutRows {
+    normal: [
+        "`1234567890-=",
+        ";ςερτυθιοπ[]\\",
+        "ασδφγηξκλ΄'",
+        "ζχψωβνμ,./",
+    ],
+    shifted: [
+        "~!@#$%^&*()_+",
+        ":΅ΕΡΤΥΘΙΟΠ{}|",
+        "ΑΣΔΦΓΗΞΚΛ¨\"",
+        "ΖΧΨΩΒΝΜ<>?",
+    ],
+};
+
+fn layout_rows(kind: KeyboardLayoutKind) -> &'static LayoutRows {
+    match kind {
+        KeyboardLayoutKind::Us => &US,
+        KeyboardLayoutKind::French => &FRENCH,
+        KeyboardLayoutKind::German => &GERMAN,
+        KeyboardLayoutKind::Greek => &GREEK,
+    }
+}
+
+/// Returns the known layout of the platform's layout name, see `KeyboardLayoutTrait::name`.
+///
+/// The names are the input source ids on macOS and the layout ids on Windows.
+pub fn layout_kind(name: &str) -> Option<KeyboardLayoutKind> {
+    let kind = match name.trim_start_matches("com.apple.keylayout.") {
+        "US" | "ABC" | "00000409" => KeyboardLayoutKind::Us,
+        "French" | "French-PC" | "French-numerical" | "0000040C" => KeyboardLayoutKind::French,
+        "German" | "00000407" => KeyboardLayoutKind::German,
+        "Greek" | "GreekPolytonic" | "00000408" => KeyboardLayoutKind::Greek,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// A known layout, translates the keys through its rows instead of asking the platform.
+pub struct StaticLayout {
+    /// The characters of the keycodes, without and with Shift
+    keys: HashMap<u16, (char, char)>,
+    independent_codes: HashMap<&'static str, u16>,
+}
+
+impl StaticLayout {
+    /// Creates the layout.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The layout
+    /// * `position_keycode` - Returns the platform's keycode of a US layout key, see `us_keycode`
+    /// * `independent_codes` - The platform's layout independent keys
+    pub fn new(
+        kind: KeyboardLayoutKind,
+        position_keycode: impl Fn(char) -> Option<u16>,
+        independent_codes: HashMap<&'static str, u16>,
+    ) -> Self {
+        let rows = layout_rows(kind);
+        let mut keys = HashMap::new();
+        for (i, positions) in US_POSITIONS.iter().enumerate() {
+            let characters = rows.normal[i].chars().zip(rows.shifted[i].chars());
+            for (position, characters) in positions.chars().zip(characters) {
+                if let Some(keycode) = position_keycode(position) {
+                    keys.insert(keycode, characters);
+                }
+            }
+        }
+        Self {
+            keys,
+            independent_codes,
+        }
+    }
+}
+
+impl KeyboardLayoutTrait for StaticLayout {
+    fn key_translate(&self, keycode: u16, modifier: u32) -> Option<String> {
+        let (normal, shifted) = self.keys.get(&keycode)?;
+        match modifier {
+            0 => Some(normal.to_string()),
+            m if m == KeyModifier::Shift as u32 => Some(shifted.to_string()),
+            _ => None,
+        }
+    }
+
+    fn has_changed(&mut self) -> bool {
+        false
+    }
+
+    fn get_independent_codes(&self) -> HashMap<&'static str, u16> {
+        self.independent_codes.clone()
+    }
+
+    fn name(&self) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Numbers the US positions in reading order.
+    fn position_keycode(position: char) -> Option<u16> {
+        US_POSITIONS
+            .concat()
+            .chars()
+            .position(|c| c == position)
+            .map(|i| i as u16)
+    }
+
+    #[test]
+    fn test_rows_match_the_us_positions() {
+        for kind in [
+            KeyboardLayoutKind::Us,
+            KeyboardLayoutKind::French,
+            KeyboardLayoutKind::German,
+            KeyboardLayoutKind::Greek,
+        ] {
+            let rows = layout_rows(kind);
+            for (i, positions) in US_POSITIONS.iter().enumerate() {
+                let len = positions.chars().count();
+                assert_eq!(rows.normal[i].chars().count(), len, "{kind:?} row {i}");
+                assert_eq!(rows.shifted[i].chars().count(), len, "{kind:?} row {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_keys_are_translated_at_the_us_positions() {
+        let shift = KeyModifier::Shift as u32;
+        let french =
+            StaticLayout::new(KeyboardLayoutKind::French, position_keycode, HashMap::new());
+        let q = position_keycode('q').unwrap();
+        assert_eq!(french.key_translate(q, 0).as_deref(), Some("a"));
+        assert_eq!(french.key_translate(q, shift).as_deref(), Some("A"));
+        let one = position_keycode('1').unwrap();
+        assert_eq!(french.key_translate(one, 0).as_deref(), Some("&"));
+        assert_eq!(french.key_translate(one, shift).as_deref(), Some("1"));
+        assert_eq!(french.key_translate(q, KeyModifier::Option as u32), None);
+
+        let german =
+            StaticLayout::new(KeyboardLayoutKind::German, position_keycode, HashMap::new());
+        let y = position_keycode('y').unwrap();
+        assert_eq!(german.key_translate(y, 0).as_deref(), Some("z"));
+
+        let greek = StaticLayout::new(KeyboardLayoutKind::Greek, position_keycode, HashMap::new());
+        let c = position_keycode('c').unwrap();
+        assert_eq!(greek.key_translate(c, 0).as_deref(), Some("ψ"));
+        assert_eq!(greek.key_translate(c, shift).as_deref(), Some("Ψ"));
+    }
+
+    #[test]
+    fn test_layout_kind() {
+        assert_eq!(
+            layout_kind("com.apple.keylayout.French"),
+            Some(KeyboardLayoutKind::French)
+        );
+        assert_eq!(layout_kind("00000408"), Some(KeyboardLayoutKind::Greek));
+        assert_eq!(
+            layout_kind("com.apple.keylayout.ABC"),
+            Some(KeyboardLayoutKind::Us)
+        );
+        assert_eq!(layout_kind("com.apple.keylayout.Dvorak"), None);
+    }
+}
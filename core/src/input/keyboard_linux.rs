@@ -1,7 +1,7 @@
 #![allow(non_snake_case)]
 use std::collections::HashMap;
 
-use super::{KeyboardEventTrait, KeyboardLayoutTrait};
+use super::{KeyRepeatSettings, KeyboardEventTrait, KeyboardLayoutTrait};
 
 pub struct KeyboardLayout {}
 
@@ -23,6 +23,15 @@ impl KeyboardLayoutTrait for KeyboardLayout {
     fn get_independent_codes(&self) -> HashMap<&'static str, u16> {
         HashMap::new()
     }
+
+    fn name(&self) -> Option<String> {
+        None
+    }
+}
+
+/// The keyboard isn't simulated on linux.
+pub fn us_keycode(_position: char) -> Option<u16> {
+    None
 }
 
 pub struct KeyboardEvent {}
@@ -38,3 +47,15 @@ impl KeyboardEventTrait for KeyboardEvent {
 
     fn send(&self) {}
 }
+
+pub fn key_repeat_settings() -> KeyRepeatSettings {
+    KeyRepeatSettings {
+        delay: std::time::Duration::from_millis(500),
+        interval: std::time::Duration::from_millis(33),
+    }
+}
+
+/// The foreground app isn't known on linux.
+pub fn foreground_app() -> Vec<String> {
+    vec![]
+}
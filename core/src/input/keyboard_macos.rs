@@ -1,13 +1,15 @@
 #![allow(non_snake_case)]
 use std::os::raw::c_void;
 
-use super::{get_modifiers, KeyModifier, KeyboardEventTrait, KeyboardLayoutTrait};
+use super::{
+    get_modifiers, KeyModifier, KeyRepeatSettings, KeyboardEventTrait, KeyboardLayoutTrait,
+};
 
 use core_foundation::{
-    base::{OSStatus, TCFType},
+    base::{CFRelease, CFTypeRef, OSStatus, TCFType},
     data::{CFData, CFDataGetBytePtr, CFDataRef},
     dictionary::CFDictionaryRef,
-    string::CFStringRef,
+    string::{CFString, CFStringRef},
 };
 use core_graphics::event::{CGEvent, CGEventFlags, CGEventTapLocation};
 use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
@@ -41,6 +43,8 @@ pub type CFNotificationCallback = Option<
 extern "C" {
     #[allow(non_upper_case_globals)]
     static kTISPropertyUnicodeKeyLayoutData: CFStringRef;
+    #[allow(non_upper_case_globals)]
+    static kTISPropertyInputSourceID: CFStringRef;
     //static kTISNotifySelectcallbackedKeyboardInputSourceChanged: CFStringRef;
     pub static kTISNotifySelectedKeyboardInputSourceChanged: CFStringRef;
 
@@ -83,6 +87,8 @@ extern "C" {
 
 pub struct KeyboardLayout {
     data: CFData,
+    /// Input source id of the layout, e.g. "com.apple.keylayout.French"
+    name: Option<String>,
     changed: Box<bool>,
 }
 
@@ -102,6 +108,20 @@ fn get_layout_data() -> CFData {
     }
 }
 
+/// Returns the input source id of the current keyboard input source.
+fn get_layout_name() -> Option<String> {
+    unsafe {
+        let source = TISCopyCurrentKeyboardInputSource();
+        if source.is_null() {
+            return None;
+        }
+        let id = TISGetInputSourceProperty(source, kTISPropertyInputSourceID) as CFStringRef;
+        let name = (!id.is_null()).then(|| CFString::wrap_under_get_rule(id).to_string());
+        CFRelease(source as CFTypeRef);
+        name
+    }
+}
+
 impl Default for KeyboardLayout {
     fn default() -> Self {
         Self::new()
@@ -111,6 +131,7 @@ impl Default for KeyboardLayout {
 impl KeyboardLayout {
     pub fn new() -> Self {
         let data = get_layout_data();
+        let name = get_layout_name();
         let changed = Box::new(false);
 
         unsafe {
@@ -124,7 +145,11 @@ impl KeyboardLayout {
                 4,
             );
         }
-        Self { data, changed }
+        Self {
+            data,
+            name,
+            changed,
+        }
     }
 }
 
@@ -165,6 +190,7 @@ impl KeyboardLayoutTrait for KeyboardLayout {
         let changed = *self.changed;
         if changed {
             self.data = get_layout_data();
+            self.name = get_layout_name();
             *self.changed = false;
         }
         changed
@@ -190,6 +216,67 @@ impl KeyboardLayoutTrait for KeyboardLayout {
         independent_codes.insert("CapsLock", 0x39);
         independent_codes
     }
+
+    fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+}
+
+/// Returns the keycode of a US layout key, named by its character without Shift.
+///
+/// See the kVK_ANSI_ constants in HIToolbox/Events.h.
+pub fn us_keycode(position: char) -> Option<u16> {
+    let keycode = match position {
+        'a' => 0x00,
+        's' => 0x01,
+        'd' => 0x02,
+        'f' => 0x03,
+        'h' => 0x04,
+        'g' => 0x05,
+        'z' => 0x06,
+        'x' => 0x07,
+        'c' => 0x08,
+        'v' => 0x09,
+        'b' => 0x0B,
+        'q' => 0x0C,
+        'w' => 0x0D,
+        'e' => 0x0E,
+        'r' => 0x0F,
+        'y' => 0x10,
+        't' => 0x11,
+        '1' => 0x12,
+        '2' => 0x13,
+        '3' => 0x14,
+        '4' => 0x15,
+        '6' => 0x16,
+        '5' => 0x17,
+        '=' => 0x18,
+        '9' => 0x19,
+        '7' => 0x1A,
+        '-' => 0x1B,
+        '8' => 0x1C,
+        '0' => 0x1D,
+        ']' => 0x1E,
+        'o' => 0x1F,
+        'u' => 0x20,
+        '[' => 0x21,
+        'i' => 0x22,
+        'p' => 0x23,
+        'l' => 0x25,
+        'j' => 0x26,
+        '\'' => 0x27,
+        'k' => 0x28,
+        ';' => 0x29,
+        '\\' => 0x2A,
+        ',' => 0x2B,
+        '/' => 0x2C,
+        'n' => 0x2D,
+        'm' => 0x2E,
+        '.' => 0x2F,
+        '`' => 0x32,
+        _ => return None,
+    };
+    Some(keycode)
 }
 
 extern "C" fn observer(
@@ -268,3 +355,54 @@ impl KeyboardEventTrait for KeyboardEvent {
         self.event.post(CGEventTapLocation::AnnotatedSession);
     }
 }
+
+/// Reads a number from the global preferences.
+fn global_preferences_number(key: &str) -> Option<f64> {
+    use core_foundation::{base::CFType, number::CFNumber, string::CFString};
+    use core_foundation_sys::preferences::{
+        kCFPreferencesAnyApplication, CFPreferencesCopyAppValue,
+    };
+
+    let key = CFString::new(key);
+    let value = unsafe {
+        CFPreferencesCopyAppValue(key.as_concrete_TypeRef(), kCFPreferencesAnyApplication)
+    };
+    if value.is_null() {
+        return None;
+    }
+    let value = unsafe { CFType::wrap_under_create_rule(value) };
+    value.downcast::<CFNumber>()?.to_f64()
+}
+
+/// Returns the key repeat settings from the Keyboard preferences.
+///
+/// Both values are stored in units of 15ms, when they haven't been changed
+/// they are missing and the system defaults are used.
+pub fn key_repeat_settings() -> KeyRepeatSettings {
+    const UNIT_MS: f64 = 15.0;
+    let delay = global_preferences_number("InitialKeyRepeat").unwrap_or(25.0);
+    let interval = global_preferences_number("KeyRepeat").unwrap_or(6.0);
+    KeyRepeatSettings {
+        delay: std::time::Duration::from_millis((delay * UNIT_MS) as u64),
+        interval: std::time::Duration::from_millis((interval * UNIT_MS).max(1.0) as u64),
+    }
+}
+
+/// Returns the name and the bundle id of the frontmost app.
+#[allow(unused_unsafe)]
+pub fn foreground_app() -> Vec<String> {
+    use objc2_app_kit::NSWorkspace;
+
+    let Some(application) = (unsafe { NSWorkspace::sharedWorkspace().frontmostApplication() })
+    else {
+        return vec![];
+    };
+    let mut names = vec![];
+    if let Some(name) = unsafe { application.localizedName() } {
+        names.push(name.to_string());
+    }
+    if let Some(bundle_id) = unsafe { application.bundleIdentifier() } {
+        names.push(bundle_id.to_string());
+    }
+    names
+}
@@ -1,12 +1,15 @@
-use super::{get_modifiers, KeyModifier, KeyboardEventTrait, KeyboardLayoutTrait};
+use super::{
+    get_modifiers, KeyModifier, KeyRepeatSettings, KeyboardEventTrait, KeyboardLayoutTrait,
+};
 
 use windows::Win32::UI::{
     Input::KeyboardAndMouse::{
         GetKeyboardLayout, GetKeyboardState, MapVirtualKeyExW, SendInput, ToUnicode, HKL, INPUT,
         INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP, KEYEVENTF_UNICODE,
         MAPVK_VK_TO_VSC, VIRTUAL_KEY, VK_BACK, VK_CAPITAL, VK_CONTROL, VK_DELETE, VK_DOWN,
-        VK_ESCAPE, VK_LEFT, VK_LWIN, VK_MENU, VK_NEXT, VK_PRIOR, VK_RCONTROL, VK_RETURN, VK_RIGHT,
-        VK_RMENU, VK_RSHIFT, VK_SHIFT, VK_TAB, VK_UP,
+        VK_ESCAPE, VK_LEFT, VK_LWIN, VK_MENU, VK_NEXT, VK_OEM_1, VK_OEM_2, VK_OEM_3, VK_OEM_4,
+        VK_OEM_5, VK_OEM_6, VK_OEM_7, VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PERIOD, VK_OEM_PLUS,
+        VK_PRIOR, VK_RCONTROL, VK_RETURN, VK_RIGHT, VK_RMENU, VK_RSHIFT, VK_SHIFT, VK_TAB, VK_UP,
     },
     WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId},
 };
@@ -118,6 +121,32 @@ impl KeyboardLayoutTrait for KeyboardLayout {
         map.insert("CapsLock", VK_CAPITAL.0);
         map
     }
+
+    /// The language of the layout, e.g. "0000040C" for French.
+    fn name(&self) -> Option<String> {
+        Some(format!("{:08X}", self.layout.0 as usize & 0xFFFF))
+    }
+}
+
+/// Returns the virtual key of a US layout key, named by its character without Shift.
+pub fn us_keycode(position: char) -> Option<u16> {
+    let keycode = match position {
+        'a'..='z' => position.to_ascii_uppercase() as u16,
+        '0'..='9' => position as u16,
+        ';' => VK_OEM_1.0,
+        '=' => VK_OEM_PLUS.0,
+        ',' => VK_OEM_COMMA.0,
+        '-' => VK_OEM_MINUS.0,
+        '.' => VK_OEM_PERIOD.0,
+        '/' => VK_OEM_2.0,
+        '`' => VK_OEM_3.0,
+        '[' => VK_OEM_4.0,
+        '\\' => VK_OEM_5.0,
+        ']' => VK_OEM_6.0,
+        '\'' => VK_OEM_7.0,
+        _ => return None,
+    };
+    Some(keycode)
 }
 
 fn keyboard_input(keycode: u16, unicode: u16, flags: KEYBD_EVENT_FLAGS) -> INPUT {
@@ -185,3 +214,85 @@ impl KeyboardEventTrait for KeyboardEvent {
         send_input(&inputs);
     }
 }
+
+/// Returns the key repeat settings from the Keyboard control panel.
+///
+/// The delay goes from 0 (250ms) to 3 (1s), the speed goes from 0
+/// (~2.5 repeats per second) to 31 (~30 repeats per second).
+pub fn key_repeat_settings() -> KeyRepeatSettings {
+    use std::time::Duration;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETKEYBOARDDELAY, SPI_GETKEYBOARDSPEED,
+        SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+    };
+
+    let mut delay = 1u32;
+    let mut speed = 31u32;
+    unsafe {
+        if let Err(e) = SystemParametersInfoW(
+            SPI_GETKEYBOARDDELAY,
+            0,
+            Some(&mut delay as *mut u32 as *mut std::ffi::c_void),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        ) {
+            log::error!("key_repeat_settings: failed to get keyboard delay: {e:?}");
+        }
+        if let Err(e) = SystemParametersInfoW(
+            SPI_GETKEYBOARDSPEED,
+            0,
+            Some(&mut speed as *mut u32 as *mut std::ffi::c_void),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        ) {
+            log::error!("key_repeat_settings: failed to get keyboard speed: {e:?}");
+        }
+    }
+    let repeats_per_second = 2.5 + speed.min(31) as f64 * 27.5 / 31.0;
+    KeyRepeatSettings {
+        delay: Duration::from_millis(250 * (delay.min(3) as u64 + 1)),
+        interval: Duration::from_secs_f64(1.0 / repeats_per_second),
+    }
+}
+
+/// Returns the executable name, without extension, of the foreground window's app.
+pub fn foreground_app() -> Vec<String> {
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::{CloseHandle, FALSE};
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    let mut pid = 0;
+    unsafe { GetWindowThreadProcessId(GetForegroundWindow(), Some(&mut pid)) };
+    if pid == 0 {
+        return vec![];
+    }
+    let process = match unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) } {
+        Ok(process) => process,
+        Err(e) => {
+            log::warn!("foreground_app: failed to open process {pid}: {e:?}");
+            return vec![];
+        }
+    };
+    let mut buffer = [0u16; 1024];
+    let mut len = buffer.len() as u32;
+    let res = unsafe {
+        QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut len,
+        )
+    };
+    if let Err(e) = unsafe { CloseHandle(process) } {
+        log::warn!("foreground_app: failed to close process handle: {e:?}");
+    }
+    if res.is_err() {
+        return vec![];
+    }
+    let path = String::from_utf16_lossy(&buffer[..len as usize]);
+    std::path::Path::new(&path)
+        .file_stem()
+        .map(|stem| vec![stem.to_string_lossy().into_owned()])
+        .unwrap_or_default()
+}
@@ -0,0 +1,271 @@
+//! Parsing of the key strings sent by controllers.
+//!
+//! Controllers send the `key` values of the browser's keyboard events, a
+//! named key like "ArrowLeft" or the characters the key produces, which can
+//! be any Unicode text. The strings come from untrusted clients, so they are
+//! validated before they reach the keyboard simulation.
+
+use thiserror::Error;
+
+/// Named keys that can be simulated on every platform.
+pub const NAMED_KEYS: [&str; 16] = [
+    "Alt",
+    "ArrowDown",
+    "ArrowLeft",
+    "ArrowRight",
+    "ArrowUp",
+    "Backspace",
+    "CapsLock",
+    "Control",
+    "Delete",
+    "Enter",
+    "Escape",
+    "Meta",
+    "PageDown",
+    "PageUp",
+    "Shift",
+    "Tab",
+];
+
+/// Named keys that don't auto-repeat when held.
+const NON_REPEATING_KEYS: [&str; 5] = ["Control", "Shift", "Meta", "Alt", "CapsLock"];
+
+/// Maximum number of characters of a character key, combining sequences and
+/// emoji can take a few code points.
+const MAX_KEY_CHARS: usize = 8;
+
+/// Maximum length of the browser's key names.
+const MAX_NAMED_KEY_CHARS: usize = 32;
+
+/// Maximum number of keys in one keystroke.
+pub const MAX_KEYS_PER_KEYSTROKE: usize = 8;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum KeyParseError {
+    #[error("Empty key")]
+    Empty,
+    #[error("Key is too long: {0} characters")]
+    TooLong(usize),
+    #[error("Key contains control characters")]
+    ControlCharacter,
+    #[error("Unsupported named key: {0}")]
+    UnsupportedNamedKey(String),
+    #[error("Too many keys: {0}")]
+    TooManyKeys(usize),
+}
+
+/// A key pressed by a controller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// A layout independent key, one of `NAMED_KEYS`
+    Named(&'static str),
+    /// The characters produced by the key, typed as they are on the sharer's layout
+    Character(String),
+}
+
+impl Key {
+    /// Parses a browser key string.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The `key` value of the controller's keyboard event
+    ///
+    /// # Returns
+    ///
+    /// The parsed key, or why the string isn't a key that can be simulated.
+    pub fn parse(key: &str) -> Result<Self, KeyParseError> {
+        if key.is_empty() {
+            return Err(KeyParseError::Empty);
+        }
+        if let Some(named) = NAMED_KEYS.iter().find(|named| **named == key) {
+            return Ok(Key::Named(named));
+        }
+
+        let chars = key.chars().count();
+        /* Browsers name the keys without a character in PascalCase, e.g. "F5" or "Home". */
+        let starts_uppercase = key.chars().next().is_some_and(|c| c.is_ascii_uppercase());
+        if (2..=MAX_NAMED_KEY_CHARS).contains(&chars)
+            && starts_uppercase
+            && key.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(KeyParseError::UnsupportedNamedKey(key.to_string()));
+        }
+        if chars > MAX_KEY_CHARS {
+            return Err(KeyParseError::TooLong(chars));
+        }
+        if key.chars().any(char::is_control) {
+            return Err(KeyParseError::ControlCharacter);
+        }
+
+        Ok(Key::Character(key.to_string()))
+    }
+
+    /// Returns the key's string, as used in the key maps.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Key::Named(name) => name,
+            Key::Character(characters) => characters,
+        }
+    }
+
+    /// Returns `true` for keys that insert characters.
+    pub fn is_character(&self) -> bool {
+        matches!(self, Key::Character(_))
+    }
+
+    /// Returns `true` for keys that auto-repeat when held.
+    pub fn repeats(&self) -> bool {
+        !NON_REPEATING_KEYS.contains(&self.as_str())
+    }
+}
+
+/// Parses the keys of a keystroke.
+///
+/// Keys that can't be simulated are logged and skipped so the rest of the
+/// keystroke still works, duplicates are dropped.
+///
+/// # Arguments
+///
+/// * `keys` - The key strings of the keystroke, in the order they were pressed
+///
+/// # Returns
+///
+/// The keys that can be simulated, or `KeyParseError::TooManyKeys` when the
+/// keystroke has more keys than a keyboard can press at once.
+pub fn parse_keys(keys: &[String]) -> Result<Vec<Key>, KeyParseError> {
+    if keys.len() > MAX_KEYS_PER_KEYSTROKE {
+        return Err(KeyParseError::TooManyKeys(keys.len()));
+    }
+
+    let mut parsed = Vec::with_capacity(keys.len());
+    for key in keys {
+        match Key::parse(key) {
+            Ok(key) if !parsed.contains(&key) => parsed.push(key),
+            Ok(_) => {}
+            Err(e) => log::warn!("parse_keys: skipping {key:?}: {e}"),
+        }
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Small xorshift generator so the fuzz tests are reproducible. */
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn string(&mut self) -> String {
+            const SAMPLES: [&str; 12] = [
+                "a", "Z", "é", "\u{301}", "😀", "\u{200d}", "\0", "\n", "F", "1", "Arrow", " ",
+            ];
+            let len = self.below(12);
+            (0..len)
+                .map(|_| match self.below(3) {
+                    0 => SAMPLES[self.below(SAMPLES.len())].to_string(),
+                    1 => NAMED_KEYS[self.below(NAMED_KEYS.len())].to_string(),
+                    _ => char::from_u32(self.next() as u32 % 0x11_0000)
+                        .unwrap_or('?')
+                        .to_string(),
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(Key::parse("ArrowLeft"), Ok(Key::Named("ArrowLeft")));
+        assert_eq!(Key::parse("Backspace"), Ok(Key::Named("Backspace")));
+        assert_eq!(Key::parse("a"), Ok(Key::Character("a".to_string())));
+        assert_eq!(Key::parse("A"), Ok(Key::Character("A".to_string())));
+        assert_eq!(Key::parse(" "), Ok(Key::Character(" ".to_string())));
+        assert_eq!(Key::parse("ß"), Ok(Key::Character("ß".to_string())));
+        assert_eq!(Key::parse("ж"), Ok(Key::Character("ж".to_string())));
+        assert_eq!(
+            Key::parse("e\u{301}"),
+            Ok(Key::Character("e\u{301}".to_string()))
+        );
+        assert_eq!(Key::parse("👍🏽"), Ok(Key::Character("👍🏽".to_string())));
+
+        assert_eq!(Key::parse(""), Err(KeyParseError::Empty));
+        assert_eq!(Key::parse("\u{8}"), Err(KeyParseError::ControlCharacter));
+        assert_eq!(Key::parse("a\nb"), Err(KeyParseError::ControlCharacter));
+        assert_eq!(
+            Key::parse("F5"),
+            Err(KeyParseError::UnsupportedNamedKey("F5".to_string()))
+        );
+        assert_eq!(
+            Key::parse("Unidentified"),
+            Err(KeyParseError::UnsupportedNamedKey(
+                "Unidentified".to_string()
+            ))
+        );
+        assert_eq!(
+            Key::parse(&"a".repeat(100)),
+            Err(KeyParseError::TooLong(100))
+        );
+    }
+
+    #[test]
+    fn test_key_properties() {
+        assert!(Key::Character("a".to_string()).is_character());
+        assert!(!Key::Named("Enter").is_character());
+        assert!(Key::Named("ArrowUp").repeats());
+        assert!(!Key::Named("Shift").repeats());
+        assert_eq!(Key::Named("Tab").as_str(), "Tab");
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let keys = ["Shift", "a", "F13", "", "a"].map(String::from);
+        assert_eq!(
+            parse_keys(&keys),
+            Ok(vec![Key::Named("Shift"), Key::Character("a".to_string())])
+        );
+
+        let keys = vec!["a".to_string(); MAX_KEYS_PER_KEYSTROKE + 1];
+        assert_eq!(
+            parse_keys(&keys),
+            Err(KeyParseError::TooManyKeys(MAX_KEYS_PER_KEYSTROKE + 1))
+        );
+    }
+
+    #[test]
+    fn test_parse_keys_fuzz() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let keys: Vec<String> = (0..rng.below(MAX_KEYS_PER_KEYSTROKE + 3))
+                .map(|_| rng.string())
+                .collect();
+            let Ok(parsed) = parse_keys(&keys) else {
+                assert!(keys.len() > MAX_KEYS_PER_KEYSTROKE);
+                continue;
+            };
+            assert!(parsed.len() <= keys.len());
+            for (i, key) in parsed.iter().enumerate() {
+                assert!(keys.iter().any(|k| k == key.as_str()));
+                assert!(!parsed[i + 1..].contains(key));
+                match key {
+                    Key::Named(name) => assert!(NAMED_KEYS.contains(name)),
+                    Key::Character(characters) => {
+                        assert!(!characters.is_empty());
+                        assert!(characters.chars().count() <= MAX_KEY_CHARS);
+                        assert!(!characters.chars().any(char::is_control));
+                    }
+                }
+            }
+        }
+    }
+}
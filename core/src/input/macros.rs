@@ -0,0 +1,567 @@
+//! Recording and playback of remote input macros.
+//!
+//! Support workflows often repeat the same setup steps on many machines. The
+//! sharer can record the inputs a controller sends, or load a canned
+//! sequence they approved, and replay it on demand with `Message::PlayMacro`.
+//! Macros are kept in the format of the controllers' inputs, so the ones
+//! loaded from outside the core are validated like remote input.
+//!
+//! Playback runs on its own thread, which waits between the steps and sends
+//! every input to the event loop where it is simulated as the sharer's input.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use socket_lib::{InputMacro, MacroInput, MacroStep};
+use thiserror::Error;
+
+use super::keys::{parse_keys, KeyParseError};
+use crate::event_sender::EventSender;
+use crate::{KeystrokeData, MouseClickData, ScrollDelta, UserEvent};
+
+/// Longest wait between two steps, longer pauses are shortened while recording.
+pub const MAX_STEP_DELAY: Duration = Duration::from_secs(5);
+
+/// Maximum number of steps of a macro.
+pub const MAX_MACRO_STEPS: usize = 10_000;
+
+/// Maximum number of macros kept by the core.
+const MAX_MACROS: usize = 64;
+
+/// Number of the next playback, tags the events of a playback so the ones
+/// still queued after it was stopped are ignored.
+static NEXT_PLAYBACK: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MacroError {
+    #[error("Not recording a macro")]
+    NotRecording,
+    #[error("The macro has no steps")]
+    Empty,
+    #[error("The macro has too many steps: {0}")]
+    TooManySteps(usize),
+    #[error("Step {0} waits too long")]
+    DelayTooLong(usize),
+    #[error("Step {0} is outside the shared content")]
+    OutOfBounds(usize),
+    #[error("Step {0} has invalid keys: {1}")]
+    InvalidKeys(usize, KeyParseError),
+    #[error("Unknown macro: {0}")]
+    UnknownMacro(String),
+    #[error("Too many macros")]
+    TooManyMacros,
+    #[error("A macro is already playing")]
+    AlreadyPlaying,
+    #[error("Macros can only be played while sharing")]
+    NotSharing,
+    #[error("Macros can't be played in view-only sessions")]
+    ViewOnly,
+}
+
+/// Returns the macro input of a click sent by a controller.
+pub fn click_input(click: &MouseClickData) -> MacroInput {
+    MacroInput::MouseClick {
+        x: click.x as f64,
+        y: click.y as f64,
+        button: click.button,
+        clicks: click.clicks as u32,
+        down: click.down,
+        shift: click.shift,
+        meta: click.meta,
+        ctrl: click.ctrl,
+        alt: click.alt,
+    }
+}
+
+/// Returns the macro input of a keystroke sent by a controller.
+pub fn keystroke_input(keystroke: &KeystrokeData) -> MacroInput {
+    MacroInput::Keystroke {
+        keys: keystroke
+            .keys
+            .iter()
+            .map(|key| key.as_str().to_string())
+            .collect(),
+        shift: keystroke.shift,
+        meta: keystroke.meta,
+        ctrl: keystroke.ctrl,
+        alt: keystroke.alt,
+        down: keystroke.down,
+    }
+}
+
+/// Returns the macro input of a scroll sent by a controller.
+pub fn scroll_input(delta: &ScrollDelta) -> MacroInput {
+    MacroInput::Scroll {
+        x: delta.x,
+        y: delta.y,
+    }
+}
+
+/// An input of a macro, converted to what the input controllers simulate.
+#[derive(Debug)]
+pub enum ReplayInput {
+    CursorMove(f64, f64),
+    MouseClick(MouseClickData),
+    Keystroke(KeystrokeData),
+    Scroll(ScrollDelta),
+}
+
+impl ReplayInput {
+    /// Converts a macro input.
+    ///
+    /// # Returns
+    ///
+    /// `None` when a keystroke has no keys that can be simulated.
+    pub fn from_input(input: MacroInput) -> Option<Self> {
+        match input {
+            MacroInput::MouseMove { x, y } => Some(Self::CursorMove(x, y)),
+            MacroInput::MouseClick {
+                x,
+                y,
+                button,
+                clicks,
+                down,
+                shift,
+                meta,
+                ctrl,
+                alt,
+            } => Some(Self::MouseClick(MouseClickData {
+                x: x as f32,
+                y: y as f32,
+                button,
+                clicks: clicks as f32,
+                down,
+                shift,
+                alt,
+                ctrl,
+                meta,
+            })),
+            MacroInput::Keystroke {
+                keys,
+                shift,
+                meta,
+                ctrl,
+                alt,
+                down,
+            } => {
+                let keys = parse_keys(&keys).ok()?;
+                if keys.is_empty() {
+                    return None;
+                }
+                Some(Self::Keystroke(KeystrokeData {
+                    keys,
+                    meta,
+                    shift,
+                    ctrl,
+                    alt,
+                    down,
+                }))
+            }
+            MacroInput::Scroll { x, y } => Some(Self::Scroll(ScrollDelta { x, y })),
+        }
+    }
+}
+
+fn in_bounds(x: f64, y: f64) -> bool {
+    (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y)
+}
+
+/// Checks a macro before it's kept, canned macros come from outside the core.
+///
+/// # Returns
+///
+/// The first problem found, the macro can be replayed as a whole otherwise.
+pub fn validate_macro(input_macro: &InputMacro) -> Result<(), MacroError> {
+    if input_macro.steps.is_empty() {
+        return Err(MacroError::Empty);
+    }
+    if input_macro.steps.len() > MAX_MACRO_STEPS {
+        return Err(MacroError::TooManySteps(input_macro.steps.len()));
+    }
+
+    for (i, step) in input_macro.steps.iter().enumerate() {
+        if step.delay_ms > MAX_STEP_DELAY.as_millis() as u64 {
+            return Err(MacroError::DelayTooLong(i));
+        }
+        match &step.input {
+            MacroInput::MouseMove { x, y } | MacroInput::MouseClick { x, y, .. } => {
+                if !in_bounds(*x, *y) {
+                    return Err(MacroError::OutOfBounds(i));
+                }
+            }
+            MacroInput::Scroll { x, y } => {
+                if !x.is_finite() || !y.is_finite() {
+                    return Err(MacroError::OutOfBounds(i));
+                }
+            }
+            MacroInput::Keystroke { keys, .. } => match parse_keys(keys) {
+                Ok(parsed) if parsed.is_empty() => {
+                    return Err(MacroError::InvalidKeys(i, KeyParseError::Empty));
+                }
+                Ok(_) => {}
+                Err(e) => return Err(MacroError::InvalidKeys(i, e)),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Records the inputs of a controller.
+#[derive(Debug, Default)]
+pub struct MacroRecorder {
+    /// The recorded controller, the first one that sends an input
+    sid: Option<String>,
+    last_input: Option<Instant>,
+    steps: Vec<MacroStep>,
+}
+
+impl MacroRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an input.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input the controller sent
+    /// * `sid` - The controller that sent it, `None` for keystrokes which
+    ///   aren't attributed to a controller
+    /// * `now` - When the input was received
+    pub fn record(&mut self, input: MacroInput, sid: Option<&str>, now: Instant) {
+        match (&self.sid, sid) {
+            (Some(recorded), Some(sid)) if recorded != sid => return,
+            (None, Some(sid)) => self.sid = Some(sid.to_string()),
+            _ => {}
+        }
+        if self.steps.len() >= MAX_MACRO_STEPS {
+            log::warn!("MacroRecorder::record: step limit reached, dropping input");
+            return;
+        }
+
+        let delay = self
+            .last_input
+            .map_or(Duration::ZERO, |last| now.duration_since(last))
+            .min(MAX_STEP_DELAY);
+        self.last_input = Some(now);
+        self.steps.push(MacroStep {
+            delay_ms: delay.as_millis() as u64,
+            input,
+        });
+    }
+
+    /// Finishes the recording.
+    ///
+    /// # Returns
+    ///
+    /// The recorded macro, or `MacroError::Empty` if nothing was recorded.
+    pub fn finish(self, id: String, name: String) -> Result<InputMacro, MacroError> {
+        if self.steps.is_empty() {
+            return Err(MacroError::Empty);
+        }
+        Ok(InputMacro {
+            id,
+            name,
+            steps: self.steps,
+        })
+    }
+}
+
+/// The macros the sharer recorded or loaded, by id.
+#[derive(Debug, Default)]
+pub struct MacroLibrary {
+    macros: HashMap<String, InputMacro>,
+    recorded: u64,
+}
+
+impl MacroLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an id for a new recording.
+    pub fn next_id(&mut self) -> String {
+        self.recorded += 1;
+        format!("recorded-{}", self.recorded)
+    }
+
+    /// Validates and keeps a macro, replacing the one with the same id.
+    pub fn insert(&mut self, input_macro: InputMacro) -> Result<(), MacroError> {
+        validate_macro(&input_macro)?;
+        if !self.macros.contains_key(&input_macro.id) && self.macros.len() >= MAX_MACROS {
+            return Err(MacroError::TooManyMacros);
+        }
+        log::info!(
+            "MacroLibrary::insert: {} {:?} with {} steps",
+            input_macro.id,
+            input_macro.name,
+            input_macro.steps.len()
+        );
+        self.macros.insert(input_macro.id.clone(), input_macro);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Result<&InputMacro, MacroError> {
+        self.macros
+            .get(id)
+            .ok_or_else(|| MacroError::UnknownMacro(id.to_string()))
+    }
+}
+
+/// A macro being replayed, dropping it stops the playback.
+#[derive(Debug)]
+pub struct MacroPlayback {
+    id: String,
+    playback: u64,
+    stop_sender: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MacroPlayback {
+    /// Starts replaying `input_macro`.
+    ///
+    /// Every step is sent as `UserEvent::ReplayMacroInput` after its delay,
+    /// followed by `UserEvent::MacroPlaybackFinished` when all were sent.
+    pub fn start(input_macro: InputMacro, event_sender: Box<dyn EventSender>) -> Self {
+        let playback = NEXT_PLAYBACK.fetch_add(1, Ordering::Relaxed);
+        let (stop_sender, stop_receiver) = mpsc::channel();
+        let id = input_macro.id.clone();
+        log::info!(
+            "MacroPlayback::start: {id} with {} steps",
+            input_macro.steps.len()
+        );
+
+        let handle = std::thread::spawn(move || {
+            for step in input_macro.steps {
+                match stop_receiver.recv_timeout(Duration::from_millis(step.delay_ms)) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                if let Err(e) = event_sender.send(UserEvent::ReplayMacroInput(playback, step.input))
+                {
+                    log::error!("MacroPlayback: Error sending input: {e:?}");
+                    return;
+                }
+            }
+            if let Err(e) = event_sender.send(UserEvent::MacroPlaybackFinished(playback)) {
+                log::error!("MacroPlayback: Error sending finished event: {e:?}");
+            }
+        });
+
+        Self {
+            id,
+            playback,
+            stop_sender: Some(stop_sender),
+            handle: Some(handle),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns whether the events of `playback` belong to this playback.
+    pub fn is(&self, playback: u64) -> bool {
+        self.playback == playback
+    }
+}
+
+impl Drop for MacroPlayback {
+    fn drop(&mut self) {
+        /* Dropping the sender wakes up the thread from its wait. */
+        self.stop_sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(delay_ms: u64, input: MacroInput) -> MacroStep {
+        MacroStep { delay_ms, input }
+    }
+
+    fn canned(steps: Vec<MacroStep>) -> InputMacro {
+        InputMacro {
+            id: "canned".to_string(),
+            name: "Open settings".to_string(),
+            steps,
+        }
+    }
+
+    fn keystroke(keys: &[&str]) -> MacroInput {
+        MacroInput::Keystroke {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            shift: false,
+            meta: false,
+            ctrl: false,
+            alt: false,
+            down: true,
+        }
+    }
+
+    #[test]
+    fn test_recorder_delays_and_controller() {
+        let start = Instant::now();
+        let mut recorder = MacroRecorder::new();
+        recorder.record(MacroInput::MouseMove { x: 0.1, y: 0.2 }, Some("a"), start);
+        /* Only the first controller is recorded. */
+        recorder.record(
+            MacroInput::MouseMove { x: 0.9, y: 0.9 },
+            Some("b"),
+            start + Duration::from_millis(10),
+        );
+        recorder.record(keystroke(&["a"]), None, start + Duration::from_millis(250));
+        recorder.record(
+            MacroInput::Scroll { x: 0., y: 3. },
+            Some("a"),
+            start + Duration::from_secs(60),
+        );
+
+        let recorded = recorder
+            .finish("recorded-1".to_string(), "Setup".to_string())
+            .unwrap();
+        let delays: Vec<u64> = recorded.steps.iter().map(|step| step.delay_ms).collect();
+        assert_eq!(
+            delays,
+            [0, 250, MAX_STEP_DELAY.as_millis() as u64],
+            "pauses are shortened to MAX_STEP_DELAY"
+        );
+        assert_eq!(
+            recorded.steps[0].input,
+            MacroInput::MouseMove { x: 0.1, y: 0.2 }
+        );
+        assert!(validate_macro(&recorded).is_ok());
+    }
+
+    #[test]
+    fn test_empty_recording() {
+        assert_eq!(
+            MacroRecorder::new().finish("recorded-1".to_string(), "Empty".to_string()),
+            Err(MacroError::Empty)
+        );
+    }
+
+    #[test]
+    fn test_validate_macro() {
+        assert_eq!(validate_macro(&canned(vec![])), Err(MacroError::Empty));
+        assert_eq!(
+            validate_macro(&canned(vec![
+                step(0, MacroInput::MouseMove { x: 0.5, y: 0.5 }),
+                step(0, MacroInput::MouseMove { x: 1.5, y: 0.5 }),
+            ])),
+            Err(MacroError::OutOfBounds(1))
+        );
+        assert_eq!(
+            validate_macro(&canned(vec![step(
+                0,
+                MacroInput::Scroll { x: f64::NAN, y: 0. }
+            )])),
+            Err(MacroError::OutOfBounds(0))
+        );
+        assert_eq!(
+            validate_macro(&canned(vec![step(
+                MAX_STEP_DELAY.as_millis() as u64 + 1,
+                keystroke(&["a"])
+            )])),
+            Err(MacroError::DelayTooLong(0))
+        );
+        assert_eq!(
+            validate_macro(&canned(vec![step(0, keystroke(&["F13"]))])),
+            Err(MacroError::InvalidKeys(0, KeyParseError::Empty))
+        );
+        assert_eq!(
+            validate_macro(&canned(vec![step(0, keystroke(&["a"; 9]))])),
+            Err(MacroError::InvalidKeys(0, KeyParseError::TooManyKeys(9)))
+        );
+        assert_eq!(
+            validate_macro(&canned(vec![
+                step(
+                    0,
+                    MacroInput::MouseMove { x: 0.5, y: 0.5 }
+                );
+                MAX_MACRO_STEPS + 1
+            ])),
+            Err(MacroError::TooManySteps(MAX_MACRO_STEPS + 1))
+        );
+    }
+
+    #[test]
+    fn test_library() {
+        let mut library = MacroLibrary::new();
+        assert_eq!(library.next_id(), "recorded-1");
+        assert_eq!(library.next_id(), "recorded-2");
+
+        assert_eq!(
+            library.get("canned").unwrap_err(),
+            MacroError::UnknownMacro("canned".to_string())
+        );
+        assert_eq!(library.insert(canned(vec![])), Err(MacroError::Empty));
+
+        let first = canned(vec![step(0, keystroke(&["Enter"]))]);
+        library.insert(first.clone()).unwrap();
+        assert_eq!(library.get("canned"), Ok(&first));
+
+        let second = canned(vec![step(0, keystroke(&["Tab"]))]);
+        library.insert(second.clone()).unwrap();
+        assert_eq!(library.get("canned"), Ok(&second));
+    }
+
+    #[test]
+    fn test_library_limit() {
+        let mut library = MacroLibrary::new();
+        for i in 0..MAX_MACROS {
+            let mut input_macro = canned(vec![step(0, keystroke(&["a"]))]);
+            input_macro.id = format!("canned-{i}");
+            library.insert(input_macro).unwrap();
+        }
+        assert_eq!(
+            library.insert(canned(vec![step(0, keystroke(&["a"]))])),
+            Err(MacroError::TooManyMacros)
+        );
+        /* Replacing a macro doesn't need room for another one. */
+        let mut replaced = canned(vec![step(0, keystroke(&["b"]))]);
+        replaced.id = "canned-0".to_string();
+        assert!(library.insert(replaced).is_ok());
+    }
+
+    #[test]
+    fn test_replay_input() {
+        let click = MacroInput::MouseClick {
+            x: 0.25,
+            y: 0.75,
+            button: 0,
+            clicks: 2,
+            down: true,
+            shift: true,
+            meta: false,
+            ctrl: false,
+            alt: false,
+        };
+        let Some(ReplayInput::MouseClick(data)) = ReplayInput::from_input(click.clone()) else {
+            panic!("expected a click");
+        };
+        assert_eq!(click_input(&data), click);
+
+        let Some(ReplayInput::Keystroke(data)) =
+            ReplayInput::from_input(keystroke(&["Shift", "a"]))
+        else {
+            panic!("expected a keystroke");
+        };
+        assert_eq!(keystroke_input(&data), keystroke(&["Shift", "a"]));
+        assert!(ReplayInput::from_input(keystroke(&["F13"])).is_none());
+
+        let Some(ReplayInput::Scroll(delta)) =
+            ReplayInput::from_input(MacroInput::Scroll { x: 1., y: -2. })
+        else {
+            panic!("expected a scroll");
+        };
+        assert_eq!(scroll_input(&delta), MacroInput::Scroll { x: 1., y: -2. });
+    }
+}
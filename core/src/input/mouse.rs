@@ -1,21 +1,36 @@
 use std::{
+    collections::HashMap,
     sync::{
         mpsc::{Receiver, RecvTimeoutError, Sender},
         Arc, Mutex,
     },
     thread::JoinHandle,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    graphics::graphics_context::{cursor::Cursor, GraphicsContext},
+    event_sender::EventSender,
+    graphics::{
+        graphics_context::{
+            cursor::{Cursor, HIDDEN_CURSOR_POSITION},
+            GraphicsContext, OverlayError,
+        },
+        label_budget::{png_texture_bytes, LabelAdmission, LabelBudget},
+    },
+    input::{cursor_shape::CursorShape, cursor_smoothing::CursorInterpolator},
     overlay_window::OverlayWindow,
-    utils::{geometry::Position, svg_renderer::render_user_badge_to_png},
-    MouseClickData, ScrollDelta, UserEvent,
+    utils::{
+        geometry::{NormalizedPoint, Position},
+        svg_renderer::{
+            render_badge_template_to_png, render_user_badge_to_png, render_user_cursor_to_png,
+            SvgRenderError,
+        },
+    },
+    MouseClickData, ScrollDelta,
 };
 
 use log::{debug, error};
-use winit::event_loop::EventLoopProxy;
+use socket_lib::{OverlayTheme, SessionParticipant};
 
 /// Platform-specific mouse input implementations and cursor control system.
 ///
@@ -65,7 +80,8 @@ mod platform;
 #[path = "mouse_linux.rs"]
 mod platform;
 
-pub use platform::{CursorSimulator, MouseObserver};
+use platform::{accessibility_zoom, cursor_scale};
+pub use platform::{system_cursor_shape, CursorSimulator, MouseObserver};
 
 /// Custom identifier used to mark simulated mouse events to prevent feedback loops.
 ///
@@ -92,6 +108,9 @@ pub const CUSTOM_MOUSE_EVENT: i64 = 1234;
 /// Maximum number of simultaneous remote controllers supported by the system.
 const MAX_CURSORS: u32 = 10;
 
+/// Name in the sharer's own badge
+const SHARER_BADGE_NAME: &str = "Me ";
+
 // Hand-picked colors for the tailwind colors page:
 // https://tailwindcss.com/docs/colors
 pub static SVG_BADGE_COLORS: [&str; 7] = [
@@ -194,22 +213,104 @@ pub trait CursorSimulatorFunctions {
     fn simulate_scroll(&mut self, delta: ScrollDelta);
 }
 
+/// Where the visible part of a zoomed display is, as reported by the platform.
+#[derive(Debug, Clone, Copy)]
+pub enum ZoomViewport {
+    /// The viewport's top left corner in global coordinates.
+    TopLeft(Position),
+    /// The viewport follows the pointer, which is at the given global coordinates.
+    ///
+    /// The pointer keeps the same relative position in the viewport as in
+    /// the display, e.g. at the display's center it is at the viewport's center.
+    FollowsPointer(Position),
+}
+
+/// OS level display zoom (macOS Zoom, Windows Magnifier).
+#[derive(Debug, Clone, Copy)]
+pub struct AccessibilityZoom {
+    /// Magnification factor, values above 1.0 mean zoomed in
+    pub factor: f64,
+    pub viewport: ZoomViewport,
+}
+
+/// Maps between the zoomed viewport and the display it is part of.
+///
+/// When the display is zoomed the captured frames, and therefore the
+/// controllers' positions, cover only the viewport, `1 / factor` of the
+/// display's width and height starting at `origin`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomTransform {
+    pub factor: f64,
+    /// The viewport's top left corner in the display
+    pub origin: NormalizedPoint,
+}
+
+impl ZoomTransform {
+    /// Creates the transform of the viewport that keeps `pointer` at its relative position.
+    pub fn following_pointer(factor: f64, pointer: NormalizedPoint) -> Self {
+        let visible = 1.0 / factor;
+        Self {
+            factor,
+            origin: NormalizedPoint::new(pointer.x * (1.0 - visible), pointer.y * (1.0 - visible)),
+        }
+    }
+
+    /// Converts a position in the viewport to a position in the display.
+    pub fn to_display(&self, point: NormalizedPoint) -> NormalizedPoint {
+        NormalizedPoint::new(
+            self.origin.x + point.x / self.factor,
+            self.origin.y + point.y / self.factor,
+        )
+    }
+
+    /// Converts a position in the display to a position in the viewport.
+    pub fn to_viewport(&self, point: NormalizedPoint) -> NormalizedPoint {
+        NormalizedPoint::new(
+            (point.x - self.origin.x) * self.factor,
+            (point.y - self.origin.y) * self.factor,
+        )
+    }
+}
+
+/// A cursor without any events for this long is hidden.
+const CURSOR_HIDE_TIMEOUT: Duration = Duration::from_secs(5);
+
 enum CursorWrapperCommands {
     Hide,
-    Show(Position),
+    Show(NormalizedPoint),
     Terminate,
 }
 
+/// The position of a cursor `CursorWrapper` shows and hides, implemented by
+/// the overlay's `Cursor`.
+trait CursorPosition: Send + 'static {
+    fn set_position(&mut self, x: f64, y: f64);
+    fn is_hidden(&self) -> bool;
+}
+
+impl CursorPosition for Cursor {
+    fn set_position(&mut self, x: f64, y: f64) {
+        Cursor::set_position(self, x, y);
+    }
+
+    fn is_hidden(&self) -> bool {
+        Cursor::is_hidden(self)
+    }
+}
+
 /// This thread is used for updating the virtual cursor's position,
-/// when there isn't any events for 5 seconds, we hide the cursor.
-fn cursor_wrapper_thread(cursor: Arc<Mutex<Cursor>>, receiver: Receiver<CursorWrapperCommands>) {
-    let timeout = Duration::from_secs(5);
+/// when there isn't any events for `hide_timeout`, we hide the cursor.
+fn cursor_wrapper_thread<C: CursorPosition>(
+    cursor: Arc<Mutex<C>>,
+    receiver: Receiver<CursorWrapperCommands>,
+    hide_timeout: Duration,
+) {
     loop {
-        match receiver.recv_timeout(timeout) {
+        match receiver.recv_timeout(hide_timeout) {
             Ok(command) => match command {
                 CursorWrapperCommands::Hide => {
                     let mut cursor = cursor.lock().unwrap();
-                    cursor.set_position(-100., -100.);
+                    cursor.set_position(HIDDEN_CURSOR_POSITION, HIDDEN_CURSOR_POSITION);
                 }
                 CursorWrapperCommands::Show(position) => {
                     let mut cursor = cursor.lock().unwrap();
@@ -222,7 +323,7 @@ fn cursor_wrapper_thread(cursor: Arc<Mutex<Cursor>>, receiver: Receiver<CursorWr
             Err(e) => match e {
                 RecvTimeoutError::Timeout => {
                     let mut cursor = cursor.lock().unwrap();
-                    cursor.set_position(-100., -100.);
+                    cursor.set_position(HIDDEN_CURSOR_POSITION, HIDDEN_CURSOR_POSITION);
                 }
                 _ => {
                     log::error!("cursor_wrapper_thread: error receiving command: {e:?}");
@@ -233,33 +334,125 @@ fn cursor_wrapper_thread(cursor: Arc<Mutex<Cursor>>, receiver: Receiver<CursorWr
     }
 }
 
-struct CursorWrapper {
-    cursor: Arc<Mutex<Cursor>>,
+/// Renders a cursor badge with the template from the textures directory,
+/// the built-in template is used when there is none or it fails to render.
+fn render_badge(
+    gfx: &GraphicsContext,
+    color: &str,
+    name: &str,
+    pointer: bool,
+    theme: &OverlayTheme,
+    label_scale: f64,
+) -> Result<Vec<u8>, SvgRenderError> {
+    if let Some(template) = gfx.badge_templates().get(pointer) {
+        match render_badge_template_to_png(template, color, name, theme, label_scale) {
+            Ok(png) => return Ok(png),
+            Err(e) => log::error!("render_badge: template failed, using the built-in one: {e:?}"),
+        }
+    }
+    render_user_badge_to_png(color, name, pointer, theme, label_scale)
+}
+
+/// Renders a badge of a controller, only the cursor when its label was evicted.
+fn render_controller_badge(
+    gfx: &GraphicsContext,
+    controller: &ControllerCursor,
+    pointer: bool,
+    theme: &OverlayTheme,
+    label_scale: f64,
+) -> Result<Vec<u8>, SvgRenderError> {
+    if !controller.labeled {
+        return render_user_cursor_to_png(controller.color, pointer, label_scale);
+    }
+    render_badge(
+        gfx,
+        controller.color,
+        &controller.badge_name(),
+        pointer,
+        theme,
+        label_scale,
+    )
+}
+
+/// Renders both badges of a controller again and uploads them to its cursors.
+fn redraw_controller_badges(
+    gfx: &mut GraphicsContext,
+    controller: &ControllerCursor,
+    theme: &OverlayTheme,
+    label_scale: f64,
+    scale_factor: f64,
+) -> Result<(), CursorControllerError> {
+    let mut result = Ok(());
+    for (cursor, pointer, error) in [
+        (
+            &controller.control_cursor,
+            false,
+            CursorControllerError::ControllerCursorCreationFailed,
+        ),
+        (
+            &controller.pointer_cursor,
+            true,
+            CursorControllerError::ControllerPointerCursorCreationFailed,
+        ),
+    ] {
+        let badge = match render_controller_badge(gfx, controller, pointer, theme, label_scale) {
+            Ok(badge) => badge,
+            Err(e) => {
+                log::error!(
+                    "redraw_controller_badges: failed to render badge of {}: {e:?}",
+                    controller.sid
+                );
+                result = Err(CursorControllerError::SvgRenderError);
+                continue;
+            }
+        };
+        if let Err(e) = cursor.replace_image(gfx, &badge, scale_factor) {
+            log::error!(
+                "redraw_controller_badges: failed to replace cursor of {}: {e:?}",
+                controller.sid
+            );
+            result = Err(error);
+        }
+    }
+    result
+}
+
+struct CursorWrapper<C: CursorPosition = Cursor> {
+    cursor: Arc<Mutex<C>>,
     /// Cursor's position in global coordinates, this is used when simulating events
     global_position: Position,
-    /// Cursor's position in local coordinates, this is used for rendering
-    local_position: Position,
+    /// Cursor's position in the overlay window, this is used for rendering
+    local_position: NormalizedPoint,
     /// Handle for the thread that updates the cursor's position
     hide_handle: Option<JoinHandle<()>>,
     command_sender: Sender<CursorWrapperCommands>,
 }
 
-impl CursorWrapper {
-    fn new(cursor: Cursor) -> Self {
+impl<C: CursorPosition> CursorWrapper<C> {
+    fn new(cursor: C) -> Self {
+        Self::with_hide_timeout(cursor, CURSOR_HIDE_TIMEOUT)
+    }
+
+    fn with_hide_timeout(cursor: C, hide_timeout: Duration) -> Self {
         let cursor = Arc::new(Mutex::new(cursor));
         let (tx, rx) = std::sync::mpsc::channel();
         Self {
             cursor: cursor.clone(),
             global_position: Position::default(),
-            local_position: Position::default(),
+            local_position: NormalizedPoint::default(),
             hide_handle: Some(std::thread::spawn(move || {
-                cursor_wrapper_thread(cursor, rx)
+                cursor_wrapper_thread(cursor, rx, hide_timeout)
             })),
             command_sender: tx,
         }
     }
 
-    fn set_position(&mut self, global_position: Position, local_position: Position, show: bool) {
+    fn set_position(
+        &mut self,
+        global_position: Position,
+        local_position: NormalizedPoint,
+        show: bool,
+    ) {
         log::debug!(
             "set_position: global_position: {global_position:?} local_position: {local_position:?}"
         );
@@ -290,14 +483,48 @@ impl CursorWrapper {
         }
     }
 
+    fn is_visible(&self) -> bool {
+        !self.cursor.lock().unwrap().is_hidden()
+    }
+}
+
+impl CursorWrapper {
     fn draw(&self, render_pass: &mut wgpu::RenderPass, gfx: &GraphicsContext) {
         let cursor = self.cursor.lock().unwrap();
         cursor.update_transform_buffer(gfx);
         cursor.draw(render_pass, gfx);
     }
+
+    /// Draws the cursor at `position` instead of its last position, a hidden cursor stays hidden.
+    fn draw_at(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        gfx: &GraphicsContext,
+        position: Option<NormalizedPoint>,
+    ) {
+        let mut cursor = self.cursor.lock().unwrap();
+        if let Some(position) = position {
+            if !cursor.is_hidden() {
+                cursor.set_position(position.x, position.y);
+            }
+        }
+        cursor.update_transform_buffer(gfx);
+        cursor.draw(render_pass, gfx);
+    }
+
+    /// Replaces the cursor's image, its position and visibility are kept.
+    fn replace_image(
+        &self,
+        gfx: &mut GraphicsContext,
+        image_data: &[u8],
+        scale: f64,
+    ) -> Result<(), OverlayError> {
+        let mut cursor = self.cursor.lock().unwrap();
+        gfx.replace_cursor_image(&mut cursor, image_data, scale)
+    }
 }
 
-impl Drop for CursorWrapper {
+impl<C: CursorPosition> Drop for CursorWrapper<C> {
     fn drop(&mut self) {
         if let Some(handle) = self.hide_handle.take() {
             let res = self.command_sender.send(CursorWrapperCommands::Terminate);
@@ -310,64 +537,106 @@ impl Drop for CursorWrapper {
     }
 }
 
-struct ControllerCursor {
+struct ControllerCursor<C: CursorPosition = Cursor> {
     /// Cursor that is shown when the controller is allowed to take control
-    control_cursor: CursorWrapper,
+    control_cursor: CursorWrapper<C>,
     /// Cursor that is shown when the controller is not allowed to take control
-    pointer_cursor: CursorWrapper,
+    pointer_cursor: CursorWrapper<C>,
     /*
      * This is used to record when the controller
      * clicked down. Then for each mouse move we
      * send LeftMouseDragged instead of MouseMoved.
      */
     clicked: bool,
+    /// The button of the last single click, released when control is revoked
+    clicked_button: u32,
     enabled: bool,
+    /// Whether the controller holds its push-to-control modifier, `None` outside of push-to-control
+    held: Option<bool>,
     has_control: bool,
     visible_name: String,
+    /// Background of the badges, kept for redrawing them
+    color: &'static str,
     sid: String,
+    /// 1-based place of the controller in the raised hands, shown in its badges
+    queue_position: Option<usize>,
+    /// Smooths the drawn cursor between the received positions
+    interpolator: CursorInterpolator,
+    /// The sharer hid the cursor, see `CursorController::set_cursor_visible`
+    hidden_by_sharer: bool,
+    /// Whether the badges show the name, see `LabelBudget`
+    labeled: bool,
 }
 
-impl ControllerCursor {
+impl<C: CursorPosition> ControllerCursor<C> {
     fn new(
-        control_cursor: CursorWrapper,
-        pointer_cursor: CursorWrapper,
+        control_cursor: CursorWrapper<C>,
+        pointer_cursor: CursorWrapper<C>,
         sid: String,
         visible_name: String,
+        color: &'static str,
+        smoothing_latency: Duration,
     ) -> Self {
         Self {
             control_cursor,
             pointer_cursor,
             clicked: false,
+            clicked_button: 0,
             enabled: true,
+            held: None,
             has_control: false,
             visible_name,
+            color,
             sid,
+            queue_position: None,
+            interpolator: CursorInterpolator::new(smoothing_latency),
+            hidden_by_sharer: false,
+            labeled: true,
         }
     }
 
-    fn set_position(&mut self, global_position: Position, local_position: Position) {
+    /// Returns the name shown in the badges, prefixed with the place in the raised hands.
+    fn badge_name(&self) -> String {
+        match self.queue_position {
+            Some(position) => format!("#{position} {}", self.visible_name),
+            None => self.visible_name.clone(),
+        }
+    }
+
+    fn set_position(&mut self, global_position: Position, local_position: NormalizedPoint) {
         log::debug!(
-            "controller_cursor: set_position: global_position: {:?} local_position: {:?} has_control: {} enabled: {}",
+            "controller_cursor: set_position: global_position: {:?} local_position: {:?} has_control: {} enabled: {} held: {:?}",
             global_position,
             local_position,
             self.has_control,
-            self.enabled
+            self.enabled,
+            self.held
         );
+        self.interpolator.push(Instant::now(), local_position);
+        let shows_control_cursor = self.shows_control_cursor();
         self.control_cursor.set_position(
             global_position,
             local_position,
-            !self.has_control && self.enabled,
+            !self.has_control && shows_control_cursor,
         );
         self.pointer_cursor.set_position(
             global_position,
             local_position,
-            !self.has_control && !self.enabled,
+            !self.has_control && !shows_control_cursor,
         );
     }
 
+    /// Returns whether the control cursor is shown instead of the pointer one.
+    ///
+    /// A controller in push-to-control mode that released its modifier is
+    /// shown with the pointer cursor, its input isn't injected.
+    fn shows_control_cursor(&self) -> bool {
+        self.enabled && self.held != Some(false)
+    }
+
     fn show(&mut self) {
         self.has_control = false;
-        if self.enabled {
+        if self.shows_control_cursor() {
             self.control_cursor.show();
         } else {
             self.pointer_cursor.show();
@@ -375,7 +644,7 @@ impl ControllerCursor {
     }
 
     fn hide(&mut self) {
-        if self.enabled {
+        if self.shows_control_cursor() {
             self.has_control = true;
             self.control_cursor.hide();
         } else {
@@ -389,8 +658,17 @@ impl ControllerCursor {
 
     fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        self.update_cursors();
+    }
 
-        if enabled {
+    fn set_held(&mut self, held: bool) {
+        self.held = Some(held);
+        self.update_cursors();
+    }
+
+    /// Swaps the control and pointer cursors after `enabled` or `held` changed.
+    fn update_cursors(&mut self) {
+        if self.shows_control_cursor() {
             self.control_cursor.show();
             self.pointer_cursor.hide();
         } else {
@@ -403,23 +681,24 @@ impl ControllerCursor {
         self.clicked
     }
 
-    fn set_clicked(&mut self, clicked: bool) {
+    fn set_clicked(&mut self, clicked: bool, button: u32) {
         self.clicked = clicked;
+        self.clicked_button = button;
     }
 
     fn global_position(&self) -> Position {
         self.control_cursor.global_position
     }
 
-    fn draw(&self, render_pass: &mut wgpu::RenderPass, gfx: &GraphicsContext) {
-        if self.has_control {
-            return;
+    fn is_visible(&self) -> bool {
+        if self.has_control || self.hidden_by_sharer {
+            return false;
         }
 
-        if self.enabled {
-            self.control_cursor.draw(render_pass, gfx);
+        if self.shows_control_cursor() {
+            self.control_cursor.is_visible()
         } else {
-            self.pointer_cursor.draw(render_pass, gfx);
+            self.pointer_cursor.is_visible()
         }
     }
 
@@ -428,29 +707,60 @@ impl ControllerCursor {
     }
 }
 
+impl ControllerCursor {
+    /// Draws the shown cursor at its smoothed position.
+    fn draw(&mut self, render_pass: &mut wgpu::RenderPass, gfx: &GraphicsContext) {
+        if self.has_control || self.hidden_by_sharer {
+            return;
+        }
+
+        /* A cursor that was hidden for inactivity reappears where it moved to. */
+        if !self.is_visible() {
+            self.interpolator.clear();
+        }
+        let position = self.interpolator.position(Instant::now());
+        if self.shows_control_cursor() {
+            self.control_cursor.draw_at(render_pass, gfx, position);
+        } else {
+            self.pointer_cursor.draw_at(render_pass, gfx, position);
+        }
+    }
+}
+
 pub struct SharerCursor {
     cursor: CursorWrapper,
     has_control: bool,
-    event_loop_proxy: EventLoopProxy<UserEvent>,
+    event_sender: Box<dyn EventSender>,
     overlay_window: Arc<OverlayWindow>,
     /// We are using this to take control back when the sharer clicks/scrolls
     controllers_cursors: Arc<Mutex<Vec<ControllerCursor>>>,
-    cursor_simulator: Arc<Mutex<CursorSimulator>>,
+    /// `None` in view-only sessions, see `CursorController::new`
+    cursor_simulator: Option<Arc<Mutex<CursorSimulator>>>,
     last_event_position: Position,
 }
 
+/// Runs `simulate` with the cursor simulator, does nothing in view-only sessions.
+fn with_simulator(
+    cursor_simulator: &Option<Arc<Mutex<CursorSimulator>>>,
+    simulate: impl FnOnce(&mut CursorSimulator),
+) {
+    if let Some(cursor_simulator) = cursor_simulator {
+        simulate(&mut cursor_simulator.lock().unwrap());
+    }
+}
+
 impl SharerCursor {
     fn new(
         cursor: CursorWrapper,
-        event_loop_proxy: EventLoopProxy<UserEvent>,
+        event_sender: Box<dyn EventSender>,
         overlay_window: Arc<OverlayWindow>,
-        cursor_simulator: Arc<Mutex<CursorSimulator>>,
+        cursor_simulator: Option<Arc<Mutex<CursorSimulator>>>,
         controllers_cursors: Arc<Mutex<Vec<ControllerCursor>>>,
     ) -> Self {
         Self {
             cursor,
             has_control: true,
-            event_loop_proxy,
+            event_sender,
             overlay_window,
             controllers_cursors,
             cursor_simulator,
@@ -463,18 +773,24 @@ impl SharerCursor {
 
         let local_position = self
             .overlay_window
-            .local_percentage_from_global(global_position.x, global_position.y);
-        let display_percentage = self
+            .local_percentage_from_global(global_position);
+        let mut display_percentage = self
+            .overlay_window
+            .global_percentage_from_global(global_position);
+        if let Some(zoom) = zoom_transform(&self.overlay_window) {
+            display_percentage = zoom.to_viewport(display_percentage);
+        }
+        display_percentage = self
             .overlay_window
-            .global_percentage_from_global(global_position.x, global_position.y);
+            .stream_crop()
+            .to_stream(display_percentage);
 
         self.cursor
             .set_position(global_position, local_position, !self.has_control);
 
-        let res = self.event_loop_proxy.send_event(UserEvent::SharerPosition(
-            display_percentage.x,
-            display_percentage.y,
-        ));
+        let res = self
+            .event_sender
+            .send_sharer_position(display_percentage.x, display_percentage.y);
         if let Err(e) = res {
             error!("sharer_cursor: set_position: error sending sharer position: {e:?}");
         }
@@ -501,19 +817,20 @@ impl SharerCursor {
          * the system cursor to the position of the click, because the system cursor
          * was were the controlling controller was.
          */
-        let mut cursor_simulator = self.cursor_simulator.lock().unwrap();
         let global_position = self.global_position();
-        cursor_simulator.simulate_cursor_movement(global_position, false);
-        cursor_simulator.simulate_click(MouseClickData {
-            x: global_position.x as f32,
-            y: global_position.y as f32,
-            button: 0,
-            clicks: 1.,
-            down: true,
-            shift: false,
-            alt: false,
-            ctrl: false,
-            meta: false,
+        with_simulator(&self.cursor_simulator, |cursor_simulator| {
+            cursor_simulator.simulate_cursor_movement(global_position, false);
+            cursor_simulator.simulate_click(MouseClickData {
+                x: global_position.x as f32,
+                y: global_position.y as f32,
+                button: 0,
+                clicks: 1.,
+                down: true,
+                shift: false,
+                alt: false,
+                ctrl: false,
+                meta: false,
+            });
         });
     }
 
@@ -524,16 +841,15 @@ impl SharerCursor {
             return;
         }
 
-        {
-            /*
-             * This is the same as the click, we need to move the system cursor to the
-             * position of the scroll, because the system cursor was were the controlling
-             * controller was.
-             */
-            let mut cursor_simulator = self.cursor_simulator.lock().unwrap();
-            let global_position = self.global_position();
+        /*
+         * This is the same as the click, we need to move the system cursor to the
+         * position of the scroll, because the system cursor was were the controlling
+         * controller was.
+         */
+        let global_position = self.global_position();
+        with_simulator(&self.cursor_simulator, |cursor_simulator| {
             cursor_simulator.simulate_cursor_movement(global_position, false);
-        }
+        });
 
         self.has_control = true;
         self.cursor.hide();
@@ -549,6 +865,13 @@ impl SharerCursor {
         self.has_control
     }
 
+    /// Gives control back to the sharer without simulating input, the system
+    /// cursor stays where the controller left it.
+    fn take_control(&mut self) {
+        self.has_control = true;
+        self.cursor.hide();
+    }
+
     fn global_position(&self) -> Position {
         self.cursor.global_position
     }
@@ -559,6 +882,10 @@ impl SharerCursor {
         }
     }
 
+    fn is_visible(&self) -> bool {
+        !self.has_control && self.cursor.is_visible()
+    }
+
     fn show(&mut self) {
         self.has_control = false;
         self.cursor.show();
@@ -583,10 +910,7 @@ enum RedrawThreadCommands {
  * Instead of sending a redraw request after each mouse event, control
  * the redraws to happen in 60fps.
  */
-fn redraw_thread(
-    event_loop_proxy: EventLoopProxy<UserEvent>,
-    receiver: Receiver<RedrawThreadCommands>,
-) {
+fn redraw_thread(event_sender: impl EventSender, receiver: Receiver<RedrawThreadCommands>) {
     loop {
         match receiver.recv_timeout(std::time::Duration::from_millis(16)) {
             Ok(command) => match command {
@@ -594,7 +918,7 @@ fn redraw_thread(
             },
             Err(e) => match e {
                 RecvTimeoutError::Timeout => {
-                    if let Err(e) = event_loop_proxy.send_event(UserEvent::RequestRedraw) {
+                    if let Err(e) = event_sender.send_redraw_request() {
                         log::error!("redraw_thread: error sending redraw event: {e:?}");
                     }
                 }
@@ -607,6 +931,58 @@ fn redraw_thread(
     }
 }
 
+/// Returns the current display zoom, `None` when the display isn't zoomed.
+fn zoom_transform(overlay_window: &OverlayWindow) -> Option<ZoomTransform> {
+    let zoom = accessibility_zoom()?;
+    if zoom.factor <= 1.0 {
+        return None;
+    }
+    let transform = match zoom.viewport {
+        ZoomViewport::TopLeft(position) => ZoomTransform {
+            factor: zoom.factor,
+            origin: overlay_window.global_percentage_from_global(position),
+        },
+        ZoomViewport::FollowsPointer(position) => ZoomTransform::following_pointer(
+            zoom.factor,
+            overlay_window.global_percentage_from_global(position),
+        ),
+    };
+    debug!("zoom_transform: {transform:?}");
+    Some(transform)
+}
+
+/// The last position of each controller's cursor, by the controller's SID.
+///
+/// The overlay window and its `CursorController` are recreated when the
+/// stream or the session restarts, without this the controllers' cursors
+/// would appear at the top left corner until they move again. Clones share
+/// the same positions.
+#[derive(Debug, Default, Clone)]
+pub struct ControllerPositions {
+    /* Positions as sent by the controllers, before unzooming. */
+    positions: Arc<Mutex<HashMap<String, NormalizedPoint>>>,
+}
+
+impl ControllerPositions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, sid: &str, point: NormalizedPoint) {
+        let mut positions = self.positions.lock().unwrap();
+        positions.insert(sid.to_string(), point);
+    }
+
+    fn get(&self, sid: &str) -> Option<NormalizedPoint> {
+        self.positions.lock().unwrap().get(sid).copied()
+    }
+
+    /// Forgets all positions, used when the call ends.
+    pub fn clear(&self) {
+        self.positions.lock().unwrap().clear();
+    }
+}
+
 /// Main cursor controller that manages both local and remote cursor interactions.
 ///
 /// It manages the visual representation of multiple remote controller cursors and
@@ -636,8 +1012,8 @@ pub struct CursorController {
     sharer_cursor: Arc<Mutex<SharerCursor>>,
     /// Cursors for the remote controllers
     controllers_cursors: Arc<Mutex<Vec<ControllerCursor>>>,
-    /// Object that is used to simulate mouse events
-    cursor_simulator: Arc<Mutex<CursorSimulator>>,
+    /// Object that is used to simulate mouse events, `None` in view-only sessions
+    cursor_simulator: Option<Arc<Mutex<CursorSimulator>>>,
     /// Object that is used to translate coordinates between local and global
     overlay_window: Arc<OverlayWindow>,
     /// Platform-specific mouse event observer.
@@ -649,6 +1025,20 @@ pub struct CursorController {
     redraw_thread: Option<JoinHandle<()>>,
     /// Sender for the redraw thread
     redraw_thread_sender: Sender<RedrawThreadCommands>,
+    /// Last known controller positions, they outlive the controller
+    last_positions: ControllerPositions,
+    /// Whether a replayed macro holds a mouse button down, moves are drags then
+    replay_clicked: bool,
+    /// Limits the names in the badges of the controllers added next
+    overlay_theme: OverlayTheme,
+    /// Scale of the badges on top of the display scale, from the theme's label size
+    label_scale: f64,
+    /// Whether the sharer's badge has the pointing hand, see `set_sharer_cursor_shape`
+    sharer_pointer: bool,
+    /// How far the drawn controllers' cursors trail their positions, from the theme
+    smoothing_latency: Duration,
+    /// GPU memory of the controllers' labeled badges
+    label_budget: LabelBudget,
 }
 
 impl CursorController {
@@ -667,7 +1057,12 @@ impl CursorController {
     ///
     /// * `gfx` - Graphics context for creating cursor textures and render resources
     /// * `overlay_window` - Shared overlay window for coordinate transformations
-    /// * `event_loop_proxy` - Event loop proxy for sending cursor position updates
+    /// * `event_sender` - Sender for the cursor position updates and redraw requests
+    /// * `last_positions` - Controller positions kept from previous controllers, the
+    ///   cursors of controllers added later are restored to them
+    /// * `inject_input` - Whether the controllers' mouse input is simulated, view-only
+    ///   sessions don't create the cursor simulator at all and only draw the cursors
+    /// * `overlay_theme` - How the badges of the cursors are drawn
     ///
     /// # Returns
     ///
@@ -676,22 +1071,44 @@ impl CursorController {
     pub fn new(
         gfx: &mut GraphicsContext,
         overlay_window: Arc<OverlayWindow>,
-        event_loop_proxy: EventLoopProxy<UserEvent>,
+        event_sender: impl EventSender,
+        last_positions: ControllerPositions,
+        inject_input: bool,
+        overlay_theme: OverlayTheme,
     ) -> Result<Self, CursorControllerError> {
         let scale_factor = overlay_window.get_display_scale();
+        /*
+         * A bigger cursor keeps its hotspot, so it doesn't need compensation,
+         * it's logged for making sense of misplaced click reports.
+         */
+        log::info!(
+            "CursorController::new: cursor scale: {} zoom: {:?}",
+            cursor_scale(),
+            accessibility_zoom()
+        );
+        let label_scale = overlay_window.label_scale(overlay_theme.label_size);
+        log::info!("CursorController::new: label scale: {label_scale}");
         let color = SVG_BADGE_COLORS[0];
-        let svg_badge = render_user_badge_to_png(color, "Me ", false)
-            .map_err(|_| CursorControllerError::SvgRenderError)?;
+        let svg_badge = render_badge(
+            gfx,
+            color,
+            SHARER_BADGE_NAME,
+            false,
+            &overlay_theme,
+            label_scale,
+        )
+        .map_err(|_| CursorControllerError::SvgRenderError)?;
         let sharer_cursor = match gfx.create_cursor(&svg_badge, scale_factor) {
             Ok(cursor) => cursor,
             Err(_) => return Err(CursorControllerError::SharerCursorCreationFailed),
         };
 
-        let cursor_simulator = Arc::new(Mutex::new(CursorSimulator::new()));
+        /* View-only sessions never create the platform's input injection. */
+        let cursor_simulator = inject_input.then(|| Arc::new(Mutex::new(CursorSimulator::new())));
         let controllers_cursors = Arc::new(Mutex::new(vec![]));
         let sharer_cursor = Arc::new(Mutex::new(SharerCursor::new(
             CursorWrapper::new(sharer_cursor),
-            event_loop_proxy.clone(),
+            event_sender.boxed_clone(),
             overlay_window.clone(),
             cursor_simulator.clone(),
             controllers_cursors.clone(),
@@ -712,12 +1129,180 @@ impl CursorController {
             overlay_window,
             _mouse_observer: mouse_observer,
             redraw_thread: Some(std::thread::spawn(move || {
-                redraw_thread(event_loop_proxy.clone(), receiver);
+                redraw_thread(event_sender, receiver);
             })),
             redraw_thread_sender: sender,
+            last_positions,
+            replay_clicked: false,
+            overlay_theme,
+            label_scale,
+            sharer_pointer: false,
+            smoothing_latency: Duration::from_millis(overlay_theme.cursor_smoothing_ms.into()),
+            label_budget: LabelBudget::default(),
         })
     }
 
+    /// Sets the theme of the badges, the existing cursors keep theirs until
+    /// `reload_cursors`.
+    pub fn set_overlay_theme(&mut self, overlay_theme: OverlayTheme) {
+        self.overlay_theme = overlay_theme;
+        self.label_scale = self.overlay_window.label_scale(overlay_theme.label_size);
+        /* Unlike the badges, the smoothing applies to the existing cursors right away. */
+        self.smoothing_latency = Duration::from_millis(overlay_theme.cursor_smoothing_ms.into());
+        let mut controllers_cursors = self.controllers_cursors.lock().unwrap();
+        for controller in controllers_cursors.iter_mut() {
+            controller
+                .interpolator
+                .set_latency_budget(self.smoothing_latency);
+        }
+    }
+
+    /// Matches the sharer's badge to the shape of the sharer's system cursor.
+    ///
+    /// The badge has the pointing hand while the system cursor is a hand and
+    /// the arrow otherwise, the other shapes have no badge of their own.
+    ///
+    /// # Parameters
+    ///
+    /// * `gfx` - Graphics context the cursors were created with
+    /// * `shape` - The shape of the sharer's system cursor
+    pub fn set_sharer_cursor_shape(
+        &mut self,
+        gfx: &mut GraphicsContext,
+        shape: CursorShape,
+    ) -> Result<(), CursorControllerError> {
+        let pointer = shape == CursorShape::Hand;
+        if pointer == self.sharer_pointer {
+            return Ok(());
+        }
+
+        let badge = render_badge(
+            gfx,
+            SVG_BADGE_COLORS[0],
+            SHARER_BADGE_NAME,
+            pointer,
+            &self.overlay_theme,
+            self.label_scale,
+        )
+        .map_err(|_| CursorControllerError::SvgRenderError)?;
+        let scale_factor = self.overlay_window.get_display_scale();
+        let sharer_cursor = self.sharer_cursor.lock().unwrap();
+        if let Err(e) = sharer_cursor
+            .cursor
+            .replace_image(gfx, &badge, scale_factor)
+        {
+            log::error!("set_sharer_cursor_shape: failed to replace sharer cursor: {e:?}");
+            return Err(CursorControllerError::SharerCursorCreationFailed);
+        }
+        self.sharer_pointer = pointer;
+        Ok(())
+    }
+
+    /// Redraws the badges of every cursor.
+    ///
+    /// The badges are rendered again with the templates loaded by
+    /// `GraphicsContext::reload_assets` and the current overlay theme, the
+    /// cursors keep their positions, visibility and colors.
+    ///
+    /// # Parameters
+    ///
+    /// * `gfx` - Graphics context the cursors were created with
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Every badge was redrawn
+    /// * `Err(CursorControllerError)` - A badge failed to render or upload, the
+    ///   remaining cursors are still redrawn and the failed one keeps its badge
+    pub fn reload_cursors(
+        &mut self,
+        gfx: &mut GraphicsContext,
+    ) -> Result<(), CursorControllerError> {
+        let scale_factor = self.overlay_window.get_display_scale();
+        let mut result = Ok(());
+
+        let sharer_badge = render_badge(
+            gfx,
+            SVG_BADGE_COLORS[0],
+            SHARER_BADGE_NAME,
+            self.sharer_pointer,
+            &self.overlay_theme,
+            self.label_scale,
+        );
+        match sharer_badge {
+            Ok(badge) => {
+                let sharer_cursor = self.sharer_cursor.lock().unwrap();
+                if let Err(e) = sharer_cursor
+                    .cursor
+                    .replace_image(gfx, &badge, scale_factor)
+                {
+                    log::error!("reload_cursors: failed to replace sharer cursor: {e:?}");
+                    result = Err(CursorControllerError::SharerCursorCreationFailed);
+                }
+            }
+            Err(e) => {
+                log::error!("reload_cursors: failed to render sharer badge: {e:?}");
+                result = Err(CursorControllerError::SvgRenderError);
+            }
+        }
+
+        let controllers_cursors = self.controllers_cursors.lock().unwrap();
+        for controller in controllers_cursors.iter() {
+            if let Err(e) = redraw_controller_badges(
+                gfx,
+                controller,
+                &self.overlay_theme,
+                self.label_scale,
+                scale_factor,
+            ) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Shows the place of each controller in the raised hands in its badges.
+    ///
+    /// # Parameters
+    ///
+    /// * `gfx` - Graphics context the cursors were created with
+    /// * `sids` - Sids of the controllers that raised their hand, in order
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The badges that changed were redrawn
+    /// * `Err(CursorControllerError)` - A badge failed to render or upload, the
+    ///   remaining cursors are still redrawn and the failed one keeps its badge
+    pub fn set_control_queue(
+        &mut self,
+        gfx: &mut GraphicsContext,
+        sids: &[String],
+    ) -> Result<(), CursorControllerError> {
+        let scale_factor = self.overlay_window.get_display_scale();
+        let mut result = Ok(());
+
+        let mut controllers_cursors = self.controllers_cursors.lock().unwrap();
+        for controller in controllers_cursors.iter_mut() {
+            let queue_position = sids
+                .iter()
+                .position(|sid| *sid == controller.sid)
+                .map(|index| index + 1);
+            if controller.queue_position == queue_position {
+                continue;
+            }
+            controller.queue_position = queue_position;
+            if let Err(e) = redraw_controller_badges(
+                gfx,
+                controller,
+                &self.overlay_theme,
+                self.label_scale,
+                scale_factor,
+            ) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
     /// Adds a new remote controller to the cursor management system.
     ///
     /// This function creates visual cursor representations for a new remote controller
@@ -744,6 +1329,14 @@ impl CursorController {
     ///
     /// If multiple controllers have the same name, the system automatically generates
     /// unique visible names (e.g., "John" → "John", "John S", "John Smith", "John Smith2").
+    /// The badge then shows the visible name sanitized and shortened according to the
+    /// overlay theme, the UIs keep showing the full name for the same sid.
+    ///
+    /// # Memory Budget
+    ///
+    /// The labeled badges are kept within the GPU memory of `LabelBudget`, the
+    /// least recently active controllers lose their labels to make room for the
+    /// new one, and when that isn't enough the new controller gets label-less cursors.
     pub fn add_controller(
         &mut self,
         gfx: &mut GraphicsContext,
@@ -774,29 +1367,109 @@ impl CursorController {
             .collect();
         let visible_name = generate_unique_visible_name(&name, &used_names);
         let scale_factor = self.overlay_window.get_display_scale();
-        let svg_badge = render_user_badge_to_png(color, &visible_name, false)
-            .map_err(|_| CursorControllerError::SvgRenderError)?;
+        let mut svg_badge = render_badge(
+            gfx,
+            color,
+            &visible_name,
+            false,
+            &self.overlay_theme,
+            self.label_scale,
+        )
+        .map_err(|_| CursorControllerError::SvgRenderError)?;
+        let mut svg_badge_pointer = render_badge(
+            gfx,
+            color,
+            &visible_name,
+            true,
+            &self.overlay_theme,
+            self.label_scale,
+        )
+        .map_err(|_| CursorControllerError::SvgRenderError)?;
+
+        let badges_bytes = png_texture_bytes(&svg_badge).unwrap_or_default()
+            + png_texture_bytes(&svg_badge_pointer).unwrap_or_default();
+        let labeled = match self.label_budget.admit(&sid, badges_bytes) {
+            LabelAdmission::Labeled { evicted } => {
+                for controller in controllers_cursors.iter_mut() {
+                    if !evicted.contains(&controller.sid) {
+                        continue;
+                    }
+                    controller.labeled = false;
+                    if let Err(e) = redraw_controller_badges(
+                        gfx,
+                        controller,
+                        &self.overlay_theme,
+                        self.label_scale,
+                        scale_factor,
+                    ) {
+                        log::error!(
+                            "add_controller: failed to evict the label of {}: {e:?}",
+                            controller.sid
+                        );
+                    }
+                }
+                true
+            }
+            LabelAdmission::LabelLess => {
+                log::warn!("add_controller: {sid} gets label-less cursors");
+                svg_badge = render_user_cursor_to_png(color, false, self.label_scale)
+                    .map_err(|_| CursorControllerError::SvgRenderError)?;
+                svg_badge_pointer = render_user_cursor_to_png(color, true, self.label_scale)
+                    .map_err(|_| CursorControllerError::SvgRenderError)?;
+                false
+            }
+        };
 
         let controller_cursor = match gfx.create_cursor(&svg_badge, scale_factor) {
             Ok(cursor) => cursor,
-            Err(_) => return Err(CursorControllerError::ControllerCursorCreationFailed),
+            Err(_) => {
+                self.label_budget.release(&sid);
+                return Err(CursorControllerError::ControllerCursorCreationFailed);
+            }
         };
-        let svg_badge_pointer = render_user_badge_to_png(color, &visible_name, true)
-            .map_err(|_| CursorControllerError::SvgRenderError)?;
         let controller_pointer_cursor = match gfx.create_cursor(&svg_badge_pointer, scale_factor) {
             Ok(cursor) => cursor,
-            Err(_) => return Err(CursorControllerError::ControllerPointerCursorCreationFailed),
+            Err(_) => {
+                self.label_budget.release(&sid);
+                return Err(CursorControllerError::ControllerPointerCursorCreationFailed);
+            }
         };
 
-        controllers_cursors.push(ControllerCursor::new(
+        let mut controller = ControllerCursor::new(
             CursorWrapper::new(controller_cursor),
             CursorWrapper::new(controller_pointer_cursor),
             sid,
             visible_name,
-        ));
+            color,
+            self.smoothing_latency,
+        );
+        controller.labeled = labeled;
+        if let Some(point) = self.last_positions.get(&controller.sid) {
+            log::debug!("add_controller: restoring position {point:?}");
+            let point = self.unzoom(point);
+            controller.set_position(
+                self.overlay_window.translate_to_global(point),
+                self.overlay_window.translate_location(point),
+            );
+        }
+        controllers_cursors.push(controller);
         Ok(())
     }
 
+    /// Maps a controller's position in the streamed frame to the display.
+    ///
+    /// A cropped stream shows only part of the captured frame, and when the
+    /// sharer's display is zoomed the frames show only the zoomed viewport,
+    /// without this the simulated events would land outside of what the
+    /// controller is pointing at.
+    fn unzoom(&self, point: NormalizedPoint) -> NormalizedPoint {
+        let point = self.overlay_window.stream_crop().to_frame(point);
+        match zoom_transform(&self.overlay_window) {
+            Some(zoom) => zoom.to_display(point),
+            None => point,
+        }
+    }
+
     /// Removes a remote controller from the cursor management system.
     ///
     /// This function removes the specified controller from the active controller list
@@ -811,11 +1484,100 @@ impl CursorController {
     ///
     /// * If the controller exists, it is immediately removed from the list
     /// * If the controller doesn't exist, the operation is silently ignored
-    /// * Visual cursor resources are automatically cleaned up
+    /// * Visual cursor resources are automatically cleaned up, their slots and
+    ///   memory budget are reused by the controllers added next
     /// * Control state is preserved until next input event
     pub fn remove_controller(&mut self, sid: &str) {
         let mut controllers_cursors = self.controllers_cursors.lock().unwrap();
         controllers_cursors.retain(|controller| controller.sid != sid);
+        self.label_budget.release(sid);
+    }
+
+    /// Removes a remote controller the sharer kicked out of the session.
+    ///
+    /// Unlike `remove_controller`, control goes back to the sharer right away
+    /// when the removed controller has it, see `take_back_control`.
+    ///
+    /// # Parameters
+    ///
+    /// * `sid` - Session ID of the controller to remove
+    pub fn revoke_controller(&mut self, sid: &str) {
+        self.take_back_control(sid);
+        self.controllers_cursors
+            .lock()
+            .unwrap()
+            .retain(|controller| controller.sid != sid);
+        self.label_budget.release(sid);
+    }
+
+    /// Gives control back to the sharer when the controller `sid` has it, the
+    /// controller keeps its cursor.
+    ///
+    /// A button the controller holds down is released first, so a revoked
+    /// controller doesn't leave a drag behind.
+    ///
+    /// # Parameters
+    ///
+    /// * `sid` - Session ID of the controller whose input was revoked
+    ///
+    /// # Returns
+    ///
+    /// `true` if the controller had control.
+    pub fn take_back_control(&mut self, sid: &str) -> bool {
+        let released = {
+            let mut controllers_cursors = self.controllers_cursors.lock().unwrap();
+            let Some(controller) = controllers_cursors
+                .iter_mut()
+                .find(|controller| controller.sid == sid && controller.has_control())
+            else {
+                return false;
+            };
+            let released = controller.clicked().then(|| {
+                let global_position = controller.global_position();
+                (global_position, controller.clicked_button)
+            });
+            controller.set_clicked(false, 0);
+            controller.show();
+            released
+        };
+        log::info!("take_back_control: {sid} had control, giving it back to the sharer");
+        if let Some((global_position, button)) = released {
+            with_simulator(&self.cursor_simulator, |cursor_simulator| {
+                cursor_simulator.simulate_click(MouseClickData {
+                    x: global_position.x as f32,
+                    y: global_position.y as f32,
+                    button,
+                    clicks: 1.,
+                    down: false,
+                    shift: false,
+                    alt: false,
+                    ctrl: false,
+                    meta: false,
+                });
+            });
+        }
+        self.sharer_cursor.lock().unwrap().take_control();
+        true
+    }
+
+    /// Returns the permissions of the remote controllers, in the order they were added.
+    ///
+    /// The names are the ones shown in the badges. The connection quality and
+    /// the input authorization aren't known here and are left unset.
+    pub fn controller_states(&self) -> Vec<SessionParticipant> {
+        let controllers_cursors = self.controllers_cursors.lock().unwrap();
+        controllers_cursors
+            .iter()
+            .map(|controller| SessionParticipant {
+                sid: controller.sid.clone(),
+                name: controller.visible_name.clone(),
+                can_control: controller.enabled() && self.injects_input(),
+                has_control: controller.has_control(),
+                quality: None,
+                input_authorized: false,
+                cursor_hidden: controller.hidden_by_sharer,
+            })
+            .collect()
     }
 
     /// Handles controller cursor movement from remote input.
@@ -831,6 +1593,8 @@ impl CursorController {
     /// * `sid` - Session ID identifying which controller is moving
     pub fn cursor_move_controller(&mut self, x: f64, y: f64, sid: &str) {
         debug!("cursor_move_controller: x: {x} y: {y}");
+        self.last_positions.record(sid, NormalizedPoint::new(x, y));
+        self.label_budget.touch(sid);
 
         let mut controllers_cursors = self.controllers_cursors.lock().unwrap();
         for controller in controllers_cursors.iter_mut() {
@@ -838,13 +1602,16 @@ impl CursorController {
                 continue;
             }
 
-            let local_position = self.overlay_window.translate_location(x, y);
-            let global_position = self.overlay_window.translate_to_global(x, y);
+            let point = self.unzoom(NormalizedPoint::new(x, y));
+            let local_position = self.overlay_window.translate_location(point);
+            let global_position = self.overlay_window.translate_to_global(point);
 
             controller.set_position(global_position, local_position);
             if controller.has_control() {
-                let mut cursor_simulator = self.cursor_simulator.lock().unwrap();
-                cursor_simulator.simulate_cursor_movement(global_position, controller.clicked());
+                with_simulator(&self.cursor_simulator, |cursor_simulator| {
+                    cursor_simulator
+                        .simulate_cursor_movement(global_position, controller.clicked());
+                });
             }
             break;
         }
@@ -875,9 +1642,11 @@ impl CursorController {
                 break;
             }
 
-            let global_position = self
-                .overlay_window
-                .translate_to_global(click_data.x as f64, click_data.y as f64);
+            let point = self.unzoom(NormalizedPoint::new(
+                click_data.x as f64,
+                click_data.y as f64,
+            ));
+            let global_position = self.overlay_window.translate_to_global(point);
             click_data.x = global_position.x as f32;
             click_data.y = global_position.y as f32;
 
@@ -889,13 +1658,14 @@ impl CursorController {
             }
 
             if click_data.clicks <= 1. {
-                controller.set_clicked(click_data.down);
+                controller.set_clicked(click_data.down, click_data.button);
             }
 
-            let mut cursor_simulator = self.cursor_simulator.lock().unwrap();
-            /* Take the cursor to the controller's position. */
-            cursor_simulator.simulate_cursor_movement(global_position, false);
-            cursor_simulator.simulate_click(click_data);
+            with_simulator(&self.cursor_simulator, |cursor_simulator| {
+                /* Take the cursor to the controller's position. */
+                cursor_simulator.simulate_cursor_movement(global_position, false);
+                cursor_simulator.simulate_click(click_data);
+            });
 
             break;
         }
@@ -946,9 +1716,10 @@ impl CursorController {
                 controller.hide();
             }
 
-            let mut cursor_simulator = self.cursor_simulator.lock().unwrap();
-            cursor_simulator.simulate_cursor_movement(controller.global_position(), false);
-            cursor_simulator.simulate_scroll(delta);
+            with_simulator(&self.cursor_simulator, |cursor_simulator| {
+                cursor_simulator.simulate_cursor_movement(controller.global_position(), false);
+                cursor_simulator.simulate_scroll(delta);
+            });
 
             break;
         }
@@ -969,6 +1740,66 @@ impl CursorController {
         }
     }
 
+    /// Moves the system cursor for a replayed macro.
+    ///
+    /// Macros are replayed as the sharer's input, the controllers' cursors
+    /// don't move.
+    ///
+    /// # Parameters
+    ///
+    /// * `x` - Local window coordinates as percentage (0.0-1.0 range) for horizontal position
+    /// * `y` - Local window coordinates as percentage (0.0-1.0 range) for vertical position
+    pub fn replay_cursor_move(&mut self, x: f64, y: f64) {
+        debug!("replay_cursor_move: x: {x} y: {y}");
+        let point = self.unzoom(NormalizedPoint::new(x, y));
+        let global_position = self.overlay_window.translate_to_global(point);
+        let replay_clicked = self.replay_clicked;
+        with_simulator(&self.cursor_simulator, |cursor_simulator| {
+            cursor_simulator.simulate_cursor_movement(global_position, replay_clicked);
+        });
+    }
+
+    /// Simulates a click of a replayed macro, see `replay_cursor_move`.
+    ///
+    /// # Parameters
+    ///
+    /// * `click_data` - The click with its position in local window coordinates
+    pub fn replay_click(&mut self, mut click_data: MouseClickData) {
+        debug!("replay_click: {click_data:?}");
+        let point = self.unzoom(NormalizedPoint::new(
+            click_data.x as f64,
+            click_data.y as f64,
+        ));
+        let global_position = self.overlay_window.translate_to_global(point);
+        click_data.x = global_position.x as f32;
+        click_data.y = global_position.y as f32;
+        if click_data.clicks <= 1. {
+            self.replay_clicked = click_data.down;
+        }
+
+        with_simulator(&self.cursor_simulator, |cursor_simulator| {
+            cursor_simulator.simulate_cursor_movement(global_position, false);
+            cursor_simulator.simulate_click(click_data);
+        });
+    }
+
+    /// Simulates a scroll of a replayed macro at the system cursor's position.
+    ///
+    /// # Parameters
+    ///
+    /// * `delta` - Scroll wheel movement
+    pub fn replay_scroll(&mut self, delta: ScrollDelta) {
+        debug!("replay_scroll: {delta:?}");
+        with_simulator(&self.cursor_simulator, |cursor_simulator| {
+            cursor_simulator.simulate_scroll(delta);
+        });
+    }
+
+    /// Returns whether the cursor simulator was created, `false` in view-only sessions.
+    pub fn injects_input(&self) -> bool {
+        self.cursor_simulator.is_some()
+    }
+
     /// Enables or disables input processing for all controllers.
     ///
     /// This function controls whether remote controllers can interact with the
@@ -1007,6 +1838,65 @@ impl CursorController {
         }
     }
 
+    /// Hides or shows a controller's cursor in the overlay, triggered by the sharer.
+    ///
+    /// Unlike `set_controller_visible` the cursor isn't drawn at all while
+    /// hidden, the controller's input is handled as before.
+    ///
+    /// # Parameters
+    ///
+    /// * `sid` - Session ID identifying which controller to modify
+    /// * `visible` - Whether the cursor is drawn
+    ///
+    /// # Returns
+    ///
+    /// `false` if there is no controller with the sid.
+    pub fn set_cursor_visible(&mut self, sid: &str, visible: bool) -> bool {
+        log::info!("set_cursor_visible: {sid} {visible}");
+        let mut controllers_cursors = self.controllers_cursors.lock().unwrap();
+        let Some(controller) = controllers_cursors
+            .iter_mut()
+            .find(|controller| controller.sid == sid)
+        else {
+            return false;
+        };
+        controller.hidden_by_sharer = !visible;
+        true
+    }
+
+    /// Records whether a controller in push-to-control mode holds its modifier.
+    ///
+    /// The controller is drawn with its pointer cursor while it doesn't hold
+    /// it. Releasing the modifier while in control gives control back to the
+    /// sharer, like `revoke_controller`.
+    ///
+    /// # Parameters
+    ///
+    /// * `held` - Whether the controller holds its modifier
+    /// * `sid` - Session ID identifying which controller to modify
+    pub fn set_controller_held(&mut self, held: bool, sid: &str) {
+        log::info!("set_controller_held: {sid} {held}");
+        let had_control = {
+            let mut controllers_cursors = self.controllers_cursors.lock().unwrap();
+            let Some(controller) = controllers_cursors
+                .iter_mut()
+                .find(|controller| controller.sid == sid)
+            else {
+                return;
+            };
+            let had_control = !held && controller.has_control();
+            controller.set_held(held);
+            if had_control {
+                controller.show();
+            }
+            had_control
+        };
+        if had_control {
+            log::info!("set_controller_held: {sid} released control, giving it back to the sharer");
+            self.sharer_cursor.lock().unwrap().take_control();
+        }
+    }
+
     /// Renders all appropriate cursors to the overlay during the graphics draw cycle.
     ///
     /// This function is called during each frame rendering to draw the current cursor
@@ -1028,6 +1918,20 @@ impl CursorController {
             controller.draw(render_pass, gfx);
         }
     }
+
+    /// Returns `true` if any of the cursors would be drawn in the overlay.
+    ///
+    /// Cursors are hidden when they have been inactive or another
+    /// participant has control.
+    pub fn has_visible_cursors(&self) -> bool {
+        if self.sharer_cursor.lock().unwrap().is_visible() {
+            return true;
+        }
+        let controllers_cursors = self.controllers_cursors.lock().unwrap();
+        controllers_cursors
+            .iter()
+            .any(|controller| controller.is_visible())
+    }
 }
 
 impl Drop for CursorController {
@@ -1076,3 +1980,242 @@ fn generate_unique_visible_name(name: &str, used_names: &[String]) -> String {
 
     unreachable!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use crate::UserEvent;
+
+    const HIDE_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Stands in for a `Cursor`, the wrapper only moves it.
+    #[derive(Default)]
+    struct FakeCursor {
+        x: f64,
+        y: f64,
+    }
+
+    impl CursorPosition for FakeCursor {
+        fn set_position(&mut self, x: f64, y: f64) {
+            self.x = x;
+            self.y = y;
+        }
+
+        fn is_hidden(&self) -> bool {
+            self.x <= HIDDEN_CURSOR_POSITION && self.y <= HIDDEN_CURSOR_POSITION
+        }
+    }
+
+    fn wrapper(hide_timeout: Duration) -> CursorWrapper<FakeCursor> {
+        CursorWrapper::with_hide_timeout(FakeCursor::default(), hide_timeout)
+    }
+
+    /* The wrapper's thread applies the commands, the tests wait for it. */
+    fn wait_until(what: &str, condition: impl Fn() -> bool) {
+        let started = Instant::now();
+        while !condition() {
+            assert!(started.elapsed() < Duration::from_secs(2), "{what}");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /* The cursors start hidden, a controller only shows up once it moves. */
+    fn controller() -> ControllerCursor<FakeCursor> {
+        let mut control_cursor = wrapper(CURSOR_HIDE_TIMEOUT);
+        let mut pointer_cursor = wrapper(CURSOR_HIDE_TIMEOUT);
+        control_cursor.hide();
+        pointer_cursor.hide();
+        wait_until("cursors hidden", || {
+            !control_cursor.is_visible() && !pointer_cursor.is_visible()
+        });
+        ControllerCursor::new(
+            control_cursor,
+            pointer_cursor,
+            "sid".to_string(),
+            "Alice".to_string(),
+            SVG_BADGE_COLORS[1],
+            Duration::ZERO,
+        )
+    }
+
+    /// Returns which of the controller's cursors are shown, the control and the pointer one.
+    fn shown(controller: &ControllerCursor<FakeCursor>) -> (bool, bool) {
+        (
+            controller.control_cursor.is_visible(),
+            controller.pointer_cursor.is_visible(),
+        )
+    }
+
+    fn move_controller(controller: &mut ControllerCursor<FakeCursor>) {
+        controller.set_position(
+            Position { x: 100., y: 100. },
+            NormalizedPoint::new(0.5, 0.5),
+        );
+    }
+
+    #[test]
+    fn test_redraw_thread_requests_redraws_until_stopped() {
+        let collector = EventCollector::new();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let events = collector.clone();
+        let handle = std::thread::spawn(move || redraw_thread(events, receiver));
+
+        std::thread::sleep(Duration::from_millis(100));
+        sender.send(RedrawThreadCommands::Stop).unwrap();
+        handle.join().unwrap();
+
+        let events = collector.take();
+        assert!(!events.is_empty());
+        assert!(events
+            .iter()
+            .all(|event| matches!(event, UserEvent::RequestRedraw)));
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(collector.take().is_empty());
+    }
+
+    #[test]
+    fn test_controller_positions() {
+        let positions = ControllerPositions::new();
+        let shared = positions.clone();
+        shared.record("a", NormalizedPoint::new(0.25, 0.5));
+        shared.record("a", NormalizedPoint::new(0.75, 0.5));
+        assert_eq!(positions.get("a"), Some(NormalizedPoint::new(0.75, 0.5)));
+        assert_eq!(positions.get("b"), None);
+
+        positions.clear();
+        assert_eq!(shared.get("a"), None);
+    }
+
+    #[test]
+    fn test_zoom_transform() {
+        let zoom = ZoomTransform {
+            factor: 2.0,
+            origin: NormalizedPoint::new(0.25, 0.5),
+        };
+        let display = zoom.to_display(NormalizedPoint::new(0.5, 0.5));
+        assert_eq!(display, NormalizedPoint::new(0.5, 0.75));
+        assert_eq!(zoom.to_viewport(display), NormalizedPoint::new(0.5, 0.5));
+
+        /* The pointer stays at its relative position in the viewport. */
+        let pointer = NormalizedPoint::new(0.2, 0.8);
+        let zoom = ZoomTransform::following_pointer(4.0, pointer);
+        let viewport = zoom.to_viewport(pointer);
+        assert!((viewport.x - pointer.x).abs() < 1e-9);
+        assert!((viewport.y - pointer.y).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cursor_fades_after_inactivity() {
+        let mut cursor = wrapper(HIDE_TIMEOUT);
+
+        cursor.set_position(Position::default(), NormalizedPoint::new(0.5, 0.5), true);
+        wait_until("shown after moving", || cursor.is_visible());
+
+        /* Without events the cursor is hidden, the next one shows it where it was. */
+        wait_until("hidden after the timeout", || !cursor.is_visible());
+        cursor.show();
+        wait_until("shown again", || cursor.is_visible());
+        {
+            let fake = cursor.cursor.lock().unwrap();
+            assert_eq!((fake.x, fake.y), (0.5, 0.5));
+        }
+
+        /* Events keep it visible past the timeout. */
+        let started = Instant::now();
+        while started.elapsed() < HIDE_TIMEOUT * 3 {
+            cursor.set_position(Position::default(), NormalizedPoint::new(0.5, 0.5), true);
+            std::thread::sleep(HIDE_TIMEOUT / 4);
+            assert!(cursor.is_visible());
+        }
+
+        /* Moves that don't show the cursor don't keep a hidden one alive. */
+        cursor.hide();
+        wait_until("hidden", || !cursor.is_visible());
+        cursor.set_position(Position::default(), NormalizedPoint::new(0.25, 0.25), false);
+        std::thread::sleep(HIDE_TIMEOUT / 2);
+        assert!(!cursor.is_visible());
+    }
+
+    #[test]
+    fn test_controller_cursor_follows_enabled() {
+        let mut controller = controller();
+
+        move_controller(&mut controller);
+        wait_until("control cursor shown", || controller.is_visible());
+        assert_eq!(shown(&controller), (true, false));
+
+        /* A controller that isn't allowed to control is drawn with the pointer cursor. */
+        controller.set_enabled(false);
+        assert!(!controller.enabled());
+        wait_until("pointer cursor shown", || {
+            shown(&controller) == (false, true)
+        });
+        controller.set_enabled(true);
+        wait_until("control cursor shown again", || {
+            shown(&controller) == (true, false)
+        });
+
+        /* Push-to-control shows the pointer cursor while the modifier is released. */
+        controller.set_held(false);
+        wait_until("released modifier", || shown(&controller) == (false, true));
+        controller.set_held(true);
+        wait_until("held modifier", || shown(&controller) == (true, false));
+    }
+
+    #[test]
+    fn test_controller_cursor_hidden_while_in_control() {
+        let mut controller = controller();
+        move_controller(&mut controller);
+        wait_until("control cursor shown", || controller.is_visible());
+
+        /* The controller moves the sharer's system cursor, its own is hidden. */
+        controller.hide();
+        assert!(controller.has_control());
+        assert!(!controller.is_visible());
+        wait_until("nothing shown", || shown(&controller) == (false, false));
+
+        /* Its moves don't show it while it is in control. */
+        move_controller(&mut controller);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(shown(&controller), (false, false));
+
+        controller.show();
+        assert!(!controller.has_control());
+        wait_until("shown when control is taken back", || {
+            controller.is_visible()
+        });
+
+        /* A cursor the sharer hid stays hidden while it moves. */
+        controller.hidden_by_sharer = true;
+        move_controller(&mut controller);
+        assert!(!controller.is_visible());
+    }
+
+    #[test]
+    fn test_controller_cursor_state() {
+        let mut controller = controller();
+        assert_eq!(controller.badge_name(), "Alice");
+        controller.queue_position = Some(2);
+        assert_eq!(controller.badge_name(), "#2 Alice");
+
+        /* Moves after a click down are drags, the button is released on revoke. */
+        assert!(!controller.clicked());
+        controller.set_clicked(true, 1);
+        assert!(controller.clicked());
+        assert_eq!(controller.clicked_button, 1);
+
+        move_controller(&mut controller);
+        let position = controller.global_position();
+        assert_eq!((position.x, position.y), (100., 100.));
+
+        /* A released modifier hides the control cursor, the pointer takes no control. */
+        controller.set_held(false);
+        controller.hide();
+        assert!(!controller.has_control());
+        wait_until("pointer hidden", || shown(&controller) == (false, false));
+        controller.show();
+        wait_until("pointer shown", || shown(&controller) == (false, true));
+    }
+}
@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
-use super::CursorSimulatorFunctions;
+use super::{AccessibilityZoom, CursorSimulatorFunctions};
+use crate::input::cursor_shape::CursorShape;
 use crate::{input::mouse::SharerCursor, utils::geometry::Position, MouseClickData, ScrollDelta};
 
 use crate::overlay_window::OverlayWindow;
@@ -37,3 +38,15 @@ impl CursorSimulatorFunctions for CursorSimulator {
         log::error!("default_observer.rs: simulate_scroll");
     }
 }
+
+pub fn accessibility_zoom() -> Option<AccessibilityZoom> {
+    None
+}
+
+pub fn cursor_scale() -> f64 {
+    1.0
+}
+
+pub fn system_cursor_shape() -> Option<CursorShape> {
+    None
+}
@@ -3,7 +3,11 @@ use std::{
     thread::JoinHandle,
 };
 
-use crate::{input::mouse::SharerCursor, utils::geometry::Position, MouseClickData, ScrollDelta};
+use crate::{
+    input::{cursor_shape::CursorShape, mouse::SharerCursor},
+    utils::geometry::Position,
+    MouseClickData, ScrollDelta,
+};
 
 use core_foundation::{
     base::TCFType,
@@ -22,9 +26,15 @@ use core_graphics::{
     event_source::{CGEventSource, CGEventSourceStateID},
 };
 
-use super::{CursorSimulatorFunctions, CUSTOM_MOUSE_EVENT};
+use super::{AccessibilityZoom, CursorSimulatorFunctions, ZoomViewport, CUSTOM_MOUSE_EVENT};
 
 const EVENT_TAP_DURATION_MS: u64 = 250;
+const UNIVERSAL_ACCESS_DOMAIN: &str = "com.apple.universalaccess";
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn UAZoomEnabled() -> u8;
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum MouseObserverError {
@@ -391,3 +401,90 @@ impl CursorSimulatorFunctions for CursorSimulator {
         event.post(CGEventTapLocation::HID);
     }
 }
+
+/// Reads a number from the Accessibility preferences.
+fn universal_access_number(key: &str) -> Option<f64> {
+    use core_foundation::{base::CFType, number::CFNumber, string::CFString};
+    use core_foundation_sys::preferences::CFPreferencesCopyAppValue;
+
+    let key = CFString::new(key);
+    let domain = CFString::new(UNIVERSAL_ACCESS_DOMAIN);
+    let value = unsafe {
+        CFPreferencesCopyAppValue(key.as_concrete_TypeRef(), domain.as_concrete_TypeRef())
+    };
+    if value.is_null() {
+        return None;
+    }
+    let value = unsafe { CFType::wrap_under_create_rule(value) };
+    value.downcast::<CFNumber>()?.to_f64()
+}
+
+/// Returns the Zoom accessibility feature state, `None` when it is off.
+///
+/// The zoomed viewport isn't exposed by the OS, Zoom follows the pointer
+/// by default so the viewport is derived from the pointer's location.
+pub fn accessibility_zoom() -> Option<AccessibilityZoom> {
+    if unsafe { UAZoomEnabled() } == 0 {
+        return None;
+    }
+    let factor = universal_access_number("closeViewZoomFactor")?;
+    let event_source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState).ok()?;
+    let pointer = CGEvent::new(event_source).ok()?.location();
+    Some(AccessibilityZoom {
+        factor,
+        viewport: ZoomViewport::FollowsPointer(Position {
+            x: pointer.x,
+            y: pointer.y,
+        }),
+    })
+}
+
+/// Returns the pointer size multiplier set in the Accessibility settings.
+pub fn cursor_scale() -> f64 {
+    universal_access_number("mouseDriverCursorSize").unwrap_or(1.0)
+}
+
+/// Returns the shape of the system cursor.
+///
+/// `NSCursor::currentSystemCursor` returns a new object every time, so the
+/// cursors are told apart by their hot spot and image size, which are
+/// distinct for the standard cursors we can name.
+#[allow(unused_unsafe)]
+pub fn system_cursor_shape() -> Option<CursorShape> {
+    use objc2::rc::Retained;
+    use objc2_app_kit::NSCursor;
+
+    let key = |cursor: &Retained<NSCursor>| {
+        let hot_spot = unsafe { cursor.hotSpot() };
+        let size = unsafe { cursor.image().size() };
+        (hot_spot.x, hot_spot.y, size.width, size.height)
+    };
+
+    let current = unsafe { NSCursor::currentSystemCursor() }?;
+    let current = key(&current);
+    let shapes = unsafe {
+        [
+            (NSCursor::arrowCursor(), CursorShape::Arrow),
+            (NSCursor::IBeamCursor(), CursorShape::IBeam),
+            (NSCursor::pointingHandCursor(), CursorShape::Hand),
+            (NSCursor::crosshairCursor(), CursorShape::Crosshair),
+            (
+                NSCursor::resizeLeftRightCursor(),
+                CursorShape::ResizeHorizontal,
+            ),
+            (NSCursor::resizeUpDownCursor(), CursorShape::ResizeVertical),
+            (NSCursor::openHandCursor(), CursorShape::Move),
+            (NSCursor::closedHandCursor(), CursorShape::Move),
+            (
+                NSCursor::operationNotAllowedCursor(),
+                CursorShape::NotAllowed,
+            ),
+        ]
+    };
+    let shape = shapes
+        .iter()
+        .find(|(cursor, _)| key(cursor) == current)
+        .map(|(_, shape)| *shape)
+        .unwrap_or(CursorShape::Arrow);
+    Some(shape)
+}
@@ -6,7 +6,9 @@ use std::{
     thread::JoinHandle,
 };
 
-use crate::{utils::geometry::Position, MouseClickData, ScrollDelta};
+use crate::{
+    input::cursor_shape::CursorShape, utils::geometry::Position, MouseClickData, ScrollDelta,
+};
 
 use windows::Win32::{
     Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WAIT_TIMEOUT, WPARAM},
@@ -27,7 +29,9 @@ use windows::Win32::{
     },
 };
 
-use super::{CursorSimulatorFunctions, SharerCursor, CUSTOM_MOUSE_EVENT};
+use super::{
+    AccessibilityZoom, CursorSimulatorFunctions, SharerCursor, ZoomViewport, CUSTOM_MOUSE_EVENT,
+};
 
 // This is safe to do because the callback is not accessed after the hook is set up. It
 // could fail only during destruction if a mouse event is received at the same time.
@@ -487,3 +491,98 @@ impl CursorSimulatorFunctions for CursorSimulator {
         }
     }
 }
+
+/// Returns the full screen Magnifier state, `None` when it isn't magnifying.
+pub fn accessibility_zoom() -> Option<AccessibilityZoom> {
+    use windows::Win32::UI::Magnification::{MagGetFullscreenTransform, MagInitialize};
+
+    static MAG_INITIALIZED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    if !*MAG_INITIALIZED.get_or_init(|| unsafe { MagInitialize() }.as_bool()) {
+        return None;
+    }
+
+    let mut level = 1.0f32;
+    let mut x_offset = 0;
+    let mut y_offset = 0;
+    let res = unsafe { MagGetFullscreenTransform(&mut level, &mut x_offset, &mut y_offset) };
+    if !res.as_bool() || level <= 1.0 {
+        return None;
+    }
+    Some(AccessibilityZoom {
+        factor: level as f64,
+        viewport: ZoomViewport::TopLeft(Position {
+            x: x_offset as f64,
+            y: y_offset as f64,
+        }),
+    })
+}
+
+/// Returns the pointer size multiplier set in the Accessibility settings.
+///
+/// The size goes from 1 to 15, the pointer is 32 pixels at 1 and grows by
+/// 16 pixels with each step.
+pub fn cursor_scale() -> f64 {
+    use windows::core::w;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+
+    let mut size = 0u32;
+    let mut data_size = std::mem::size_of::<u32>() as u32;
+    let res = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Accessibility"),
+            w!("CursorSize"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut size as *mut u32 as *mut std::ffi::c_void),
+            Some(&mut data_size),
+        )
+    };
+    if res.is_err() || size == 0 {
+        return 1.0;
+    }
+    (32.0 + (size - 1) as f64 * 16.0) / 32.0
+}
+
+/// Returns the shape of the system cursor, `None` when it is hidden.
+///
+/// The standard cursors are shared handles, so the current cursor is
+/// compared with the handles of the ones we can name.
+pub fn system_cursor_shape() -> Option<CursorShape> {
+    use windows::Win32::UI::WindowsAndMessaging::{
+        GetCursorInfo, LoadCursorW, CURSORINFO, CURSOR_SHOWING, IDC_APPSTARTING, IDC_ARROW,
+        IDC_CROSS, IDC_HAND, IDC_IBEAM, IDC_NO, IDC_SIZEALL, IDC_SIZENESW, IDC_SIZENS,
+        IDC_SIZENWSE, IDC_SIZEWE, IDC_WAIT,
+    };
+
+    let mut info = CURSORINFO {
+        cbSize: std::mem::size_of::<CURSORINFO>() as u32,
+        ..Default::default()
+    };
+    if unsafe { GetCursorInfo(&mut info) }.is_err() || info.flags != CURSOR_SHOWING {
+        return None;
+    }
+
+    let shapes = [
+        (IDC_ARROW, CursorShape::Arrow),
+        (IDC_IBEAM, CursorShape::IBeam),
+        (IDC_HAND, CursorShape::Hand),
+        (IDC_CROSS, CursorShape::Crosshair),
+        (IDC_SIZEWE, CursorShape::ResizeHorizontal),
+        (IDC_SIZENS, CursorShape::ResizeVertical),
+        (IDC_SIZENWSE, CursorShape::ResizeNwse),
+        (IDC_SIZENESW, CursorShape::ResizeNesw),
+        (IDC_SIZEALL, CursorShape::Move),
+        (IDC_WAIT, CursorShape::Wait),
+        (IDC_APPSTARTING, CursorShape::Wait),
+        (IDC_NO, CursorShape::NotAllowed),
+    ];
+    let shape = shapes
+        .into_iter()
+        .find(|(id, _)| {
+            unsafe { LoadCursorW(None, *id) }.is_ok_and(|cursor| cursor == info.hCursor)
+        })
+        .map(|(_, shape)| shape)
+        .unwrap_or(CursorShape::Arrow);
+    Some(shape)
+}
@@ -1,76 +1,161 @@
+pub(crate) mod bus;
+pub mod event_sender;
 pub mod room_service;
 
+pub mod room {
+    pub mod adaptive_bitrate;
+    pub mod admission;
+    pub mod capabilities;
+    pub mod client_event;
+    pub mod clock_sync;
+    pub mod compression;
+    pub mod connection;
+    pub mod control_queue;
+    pub mod data_events;
+    pub mod data_handlers;
+    pub mod input_auth;
+    pub mod media;
+    pub mod preflight;
+    pub mod publisher;
+    pub mod quality;
+    pub mod session_service;
+    pub mod sharer_location;
+    pub mod token;
+    pub mod transport;
+}
+
 pub mod input {
+    pub mod control_hold;
+    pub mod cursor_shape;
+    pub mod cursor_smoothing;
+    pub mod input_service;
     pub mod keyboard;
+    pub mod keyboard_app_filter;
+    pub mod keyboard_arbitration;
+    pub mod keyboard_layouts;
+    pub mod keys;
+    pub mod macros;
     pub mod mouse;
 }
 
 pub mod capture {
+    pub mod audio;
+    pub mod camera;
+    pub mod capture_service;
     pub mod capturer;
+    pub mod microphone;
+    pub mod preview;
 }
 
 pub mod graphics {
+    pub mod assets;
     pub mod graphics_context;
+    pub mod label_budget;
+    pub mod overlay_recovery;
+    pub mod overlay_service;
 
     #[cfg(target_os = "windows")]
     pub mod direct_composition;
 }
 
 pub mod utils {
+    pub mod cancellation;
+    pub mod display_mirrors;
+    pub mod display_size;
     pub mod geometry;
+    pub mod health;
+    pub mod livekit_url;
+    pub mod session_dirs;
+    pub mod session_history;
+    pub mod session_journal;
     pub mod svg_renderer;
 }
 
+pub(crate) mod call_notification;
+pub(crate) mod clipboard;
+pub(crate) mod display_labels;
 pub(crate) mod overlay_window;
+pub(crate) mod socket_thread;
 
-use capture::capturer::{poll_stream, Capturer};
-use graphics::graphics_context::GraphicsContext;
+use bus::{BusMessage, ServiceBus};
+use capture::audio::SystemAudioCapture;
+use capture::camera::list_cameras;
+use capture::capture_service::{
+    camera_failed, microphone_failed, system_audio_failed, CaptureService,
+};
+use capture::capturer::{capture_failure_tags, ColorMatrix};
+use capture::microphone::list_microphones;
+use capture::preview::{PreviewTicker, PREVIEW_EXTENT};
+use event_sender::EventSender;
+use graphics::assets::AssetWatcher;
+use graphics::overlay_service::OverlayService;
+use input::cursor_shape::CursorShape;
+use input::input_service::InputService;
 use input::keyboard::{KeyboardController, KeyboardLayout};
-use input::mouse::CursorController;
-use log::{debug, error};
-use overlay_window::OverlayWindow;
+use log::debug;
+use room::capabilities::PeerCapabilities;
+use room::client_event::ShareConfigData;
+use room::clock_sync::ClockSyncRequest;
+use room::preflight;
+use room::publisher::{VideoEncodingInfo, DEFAULT_SCREEN_SHARE_FRAMERATE};
+use room::session_service::SessionService;
+use room::transport::{LiveKitTransport, SessionTransport};
 use room_service::RoomService;
+use socket_lib::shm::SharedRegion;
 use socket_lib::{
-    AvailableContentMessage, CaptureContent, CursorSocket, Message, ScreenShareMessage,
+    AdmissionPolicy, AudioProcessing, AvailableContentMessage, BulkPayloadKind,
+    ConnectionQualityLevel, Content, CursorSocket, DiagnosticsMessage, DisplayLabel, InputMacro,
+    KeyboardArbitration, KeyboardLayoutKind, MacroInput, MediaControlAction, Message,
+    NetworkPreflightReport, OverlayTheme, Permission, RoomTokenRejection, ScreenShareMessage,
+    SessionState, ShareInterruption, StreamHealth, StreamParameters, ThumbnailPrivacy,
+    UncleanSession, WaitReason,
 };
+use socket_thread::socket_receive_thread;
 use std::fmt;
-use std::sync::{Arc, Mutex};
-use std::thread::JoinHandle;
 use thiserror::Error;
+use utils::cancellation::CancellationToken;
 use utils::geometry::{Extent, Frame};
+use utils::livekit_url;
+use utils::session_dirs::{self, SessionDirs};
+use utils::session_history::SessionHistory;
+use utils::session_journal::{self, JournalEntry, SessionJournal};
 use winit::application::ApplicationHandler;
-use winit::dpi::{LogicalPosition, LogicalSize};
+use winit::dpi::LogicalSize;
 use winit::error::EventLoopError;
-use winit::event::WindowEvent;
-use winit::event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy};
+use winit::event::{ElementState, MouseButton, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, EventLoop};
 use winit::monitor::MonitorHandle;
 
 #[cfg(target_os = "macos")]
-use winit::platform::macos::{EventLoopBuilderExtMacOS, WindowExtMacOS};
-
-#[cfg(target_os = "windows")]
-use winit::platform::windows::WindowExtWindows;
+use winit::platform::macos::EventLoopBuilderExtMacOS;
 
 use winit::window::{WindowAttributes, WindowLevel};
 
-use crate::overlay_window::DisplayInfo;
-
 // Constants for magic numbers
 /// Initial size for the overlay window (width and height in logical pixels)
 const OVERLAY_WINDOW_INITIAL_SIZE: f64 = 1.0;
 
-/// Timeout in seconds for socket message reception
-const SOCKET_MESSAGE_TIMEOUT_SECONDS: u64 = 30;
-
-/// Process exit code for errors
-const PROCESS_EXIT_CODE_ERROR: i32 = 1;
+/// File name of the shared memory region, next to the socket
+const SHARED_MEMORY_NAME: &str = "core-shm";
+/// Payloads that can wait in the shared memory region for the tauri app
+const SHARED_MEMORY_SLOTS: u32 = 4;
+/// Largest bulk payload, fits a 1080p RGBA frame
+const SHARED_MEMORY_SLOT_SIZE: u32 = 8 * 1024 * 1024;
+/// Socket the tauri app connects to when neither `--socket-name` nor `CORE_SOCKET_NAME` is set
+const DEFAULT_SOCKET_NAME: &str = "core-socket";
 
 #[derive(Error, Debug)]
 pub enum ServerError {
     #[error("Room service not found")]
     RoomServiceNotFound,
+    #[error("A screen share session is already active")]
+    AlreadySharing,
     #[error("Failed to create room")]
     RoomCreationError,
+    #[error("Room token expired")]
+    RoomTokenExpired,
+    #[error("Invalid room token")]
+    InvalidRoomToken,
     #[error("Display not found")]
     DisplayNotFound,
     #[error("Window not found")]
@@ -85,14 +170,16 @@ pub enum ServerError {
     StreamCreationError,
     #[error("Failed to get stream extent")]
     StreamExtentError,
+    #[error("Failed to republish video")]
+    VideoRepublishError,
     #[error("Failed to create window")]
     WindowCreationError,
     #[error("Failed to get window position")]
     WindowPositionError,
     #[error("Failed to set cursor hittest")]
     CursorHittestError,
-    #[error("Failed to create graphics context")]
-    GfxCreationError,
+    #[error("Failed to create graphics context: {0}")]
+    GfxCreationError(String),
     #[error("Failed to create cursor controller")]
     CursorControllerCreationError,
 }
@@ -110,28 +197,6 @@ pub fn get_window_attributes() -> WindowAttributes {
         .with_content_protected(true)
 }
 
-/// Encapsulates the active remote control session components.
-///
-/// This struct manages all the components needed for an active remote control session,
-/// including graphics rendering, input simulation, and window management. It's created
-/// when a screen sharing session begins and destroyed when it ends.
-///
-/// # Fields
-///
-/// * `gfx` - Graphics context for rendering cursors and visual feedback
-/// * `cursor_controller` - Handles mouse movement, clicks, and cursor visualization
-/// * `keyboard_controller` - Manages keyboard input simulation
-///
-/// # Lifetime
-///
-/// The lifetime parameter `'a` ensures that the graphics context and cursor controller
-/// don't outlive the underlying window resources they depend on.
-struct RemoteControl<'a> {
-    gfx: GraphicsContext<'a>,
-    cursor_controller: CursorController,
-    keyboard_controller: KeyboardController<KeyboardLayout>,
-}
-
 /// The main application struct that manages the entire remote desktop control session.
 ///
 /// This struct coordinates all aspects of the remote desktop system, including screen capture,
@@ -144,17 +209,19 @@ struct RemoteControl<'a> {
 /// - Screen capture runs in a separate thread
 /// - Socket communication handles messages the main tauri app
 /// - Event loop processes commands received from the socket and the livekit room and system events
-/// - Remote control components are created/destroyed based on session state
+/// - Each event is handed to the service it concerns, what the services need
+///   from each other is posted on the `ServiceBus` and routed after the event, see `bus`
 ///
 /// # Fields
 ///
-/// * `remote_control` - Optional active remote control session (None when not sharing)
-/// * `textures_path` - Path to texture resources for cursor and UI rendering
-/// * `screen_capturer` - Thread-safe screen capture system wrapped in Arc<Mutex>
-/// * `_screen_capturer_events` - Handle to the screen capture event polling thread
+/// * `capture_service` - The screen capturer, the thread polling its streams and the sharer's media
+/// * `overlay_service` - The overlay of the session, the display labels and the call card
+/// * `input_service` - Gates and injects the controllers' input, records and replays macros
+/// * `session_service` - The room of the session, its participants and what is published to them
+/// * `bus` - Messages the services posted, delivered by `route`
 /// * `socket` - Local socket for communication with the main tauri app
-/// * `room_service` - object for interacting with the livekit room and its async thread
-/// * `event_loop_proxy` - Proxy for sending events to the main event loop
+/// * `cancel` - Cancellation shared with the socket, capture and room threads, cancelled on drop
+/// * `event_sender` - Sends events to the main event loop
 ///
 /// # Lifecycle
 ///
@@ -176,17 +243,29 @@ struct RemoteControl<'a> {
 ///
 /// Operations return `Result<(), ServerError>` for proper error propagation.
 /// Critical errors may trigger session reset or application termination.
-pub struct Application<'a> {
-    remote_control: Option<RemoteControl<'a>>,
-    textures_path: String,
-    // The arc is needed because we move the object to the
-    // thread that checks if the stream has failed.
-    //screen_capturer: Arc<Mutex<ScreenCapturer>>,
-    screen_capturer: Arc<Mutex<Capturer>>,
-    _screen_capturer_events: Option<JoinHandle<()>>,
+pub struct Application<'a, T: SessionTransport = LiveKitTransport> {
+    capture_service: CaptureService,
+    overlay_service: OverlayService<'a>,
+    input_service: InputService,
+    session_service: SessionService<T>,
+    bus: ServiceBus,
     socket: CursorSocket,
-    room_service: Option<RoomService>,
-    event_loop_proxy: EventLoopProxy<UserEvent>,
+    cancel: CancellationToken,
+    session_history: SessionHistory,
+    /// On-disk journal of the active session, see `utils::session_journal`
+    session_journal: SessionJournal,
+    /// Scratch space of the active session, see `utils::session_dirs`
+    session_dirs: SessionDirs,
+    /// The last session of the previous core process if it ended unexpectedly,
+    /// kept until the tauri app dismisses it
+    unclean_session: Option<UncleanSession>,
+    /// Region for the bulk payloads sent to the tauri app, created when it asks for it
+    shared_memory: Option<SharedRegion>,
+    /// Wakes the event loop for the next frame of the sharer's preview, `None` while it is off
+    share_preview: Option<PreviewTicker>,
+    /// Reloads the overlay assets when the textures change, see `RenderLoopRunArgs::watch_assets`
+    _asset_watcher: Option<AssetWatcher>,
+    event_sender: Box<dyn EventSender>,
 }
 
 #[derive(Error, Debug)]
@@ -200,14 +279,15 @@ impl<'a> Application<'a> {
     ///
     /// This initializes all the core components needed for remote desktop control:
     /// - Screen capturer for capturing display content
-    /// - Room service for interacting with the livekit room and its async thread
+    /// - Session service for the livekit rooms and their participants
     /// - Event handling infrastructure
     ///
     /// # Arguments
     ///
     /// * `input` - Configuration including texture paths and LiveKit server URL
     /// * `socket` - Established socket connection for client communication
-    /// * `event_loop_proxy` - Proxy for sending events to the main event loop
+    /// * `cancel` - Cancelled when the application is dropped, stops the socket, capture and room threads
+    /// * `event_sender` - Sends events to the main event loop, its proxy
     ///
     /// # Returns
     ///
@@ -222,31 +302,213 @@ impl<'a> Application<'a> {
     pub fn new(
         input: RenderLoopRunArgs,
         socket: CursorSocket,
-        event_loop_proxy: EventLoopProxy<UserEvent>,
+        cancel: CancellationToken,
+        event_sender: impl EventSender,
     ) -> Result<Self, ApplicationError> {
-        let screencapturer = Arc::new(Mutex::new(Capturer::new(event_loop_proxy.clone())));
+        Self::with_transport(LiveKitTransport::new, input, socket, cancel, event_sender)
+    }
+}
 
-        Ok(Self {
-            remote_control: None,
-            textures_path: input.textures_path,
-            screen_capturer: screencapturer.clone(),
-            _screen_capturer_events: Some(std::thread::spawn(move || poll_stream(screencapturer))),
+impl<'a, T: SessionTransport> Application<'a, T> {
+    /// Creates an Application whose sessions are carried by another transport,
+    /// see `room::transport`.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_transport` - Creates the transport for the server URL the tauri app sends
+    /// * `input`, `socket`, `cancel`, `event_sender` - See `new`
+    pub fn with_transport(
+        new_transport: fn(String) -> T,
+        input: RenderLoopRunArgs,
+        socket: CursorSocket,
+        cancel: CancellationToken,
+        event_sender: impl EventSender,
+    ) -> Result<Self, ApplicationError> {
+        let event_sender: Box<dyn EventSender> = Box::new(event_sender);
+        let capture_service = CaptureService::new(event_sender.clone(), cancel.clone());
+        let asset_watcher = input
+            .watch_assets
+            .then(|| AssetWatcher::new(event_sender.clone(), input.textures_path.clone()));
+        /* Covers a core restarted while the tauri app kept running. */
+        if let Some(path) = livekit_url::cache_path() {
+            livekit_url::revalidate_cached(event_sender.clone(), path);
+        }
+        let instance_name = input.instance_name();
+        let journal_path = session_journal::journal_path(instance_name.as_deref());
+        let unclean_session = journal_path.as_deref().and_then(session_journal::recover);
+        if let Some(session) = &unclean_session {
+            log::warn!(
+                "Application::new: the last session ended unexpectedly: {:?}",
+                session.content
+            );
+            sentry_utils::upload_journal_event(
+                "Session ended unexpectedly".to_string(),
+                session.journal.join("\n"),
+            );
+        }
+
+        let mut application = Self {
+            capture_service,
+            overlay_service: OverlayService::new(
+                input.textures_path,
+                input.render_scale,
+                event_sender.clone(),
+            ),
+            input_service: InputService::new(event_sender.clone()),
+            session_service: SessionService::with_transport(
+                new_transport,
+                event_sender.clone(),
+                cancel.clone(),
+                input.min_client_version,
+            ),
+            bus: ServiceBus::new(),
             socket,
-            room_service: None,
-            event_loop_proxy,
-        })
+            cancel,
+            session_history: SessionHistory::new(),
+            session_journal: SessionJournal::new(journal_path),
+            session_dirs: SessionDirs::new(session_dirs::default_root(instance_name.as_deref())),
+            unclean_session,
+            shared_memory: None,
+            share_preview: None,
+            _asset_watcher: asset_watcher,
+            event_sender,
+        };
+        application.request_livekit_url();
+        Ok(application)
     }
 
-    fn get_available_content(&mut self) -> Vec<CaptureContent> {
-        let mut screen_capturer = self.screen_capturer.lock().unwrap();
-        let res = screen_capturer.get_available_content();
+    /// Delivers the messages the services posted on the bus, see `bus`.
+    ///
+    /// Runs after every event, the messages posted while routing are
+    /// delivered in the same pass.
+    fn route(&mut self, event_loop: &ActiveEventLoop) {
+        while let Some(message) = self.bus.take() {
+            match message {
+                BusMessage::RebuildOverlay => {
+                    let monitors: Vec<MonitorHandle> = event_loop.available_monitors().collect();
+                    let target = self.capture_service.overlay_target(&monitors);
+                    self.overlay_service.rebuild(target, &monitors, event_loop);
+                }
+                message => self.deliver(message),
+            }
+        }
+    }
 
-        if let Err(e) = res {
-            log::error!("get_available_content: Error getting available content: {e:?}");
-            return vec![];
+    /// Delivers a message that doesn't need the event loop, see `route`.
+    fn deliver(&mut self, message: BusMessage) {
+        match message {
+            BusMessage::Tauri(message) => {
+                if let Err(e) = self.socket.send_message(message) {
+                    log::error!("deliver: Error sending message: {e:?}");
+                }
+            }
+            BusMessage::Pointer(input) => {
+                self.overlay_service.pointer_input(input, &mut self.bus);
+            }
+            BusMessage::InControl(sid) => {
+                self.session_service.set_in_control(sid);
+            }
+            BusMessage::Clipboard(text) => {
+                self.session_service
+                    .with_room("deliver: clipboard", |room_service| {
+                        room_service.publish_clipboard(text)
+                    });
+            }
+            BusMessage::ControlQueue(sids) => {
+                self.overlay_service.set_control_queue(&sids);
+            }
+            BusMessage::RevokeController(sid) => {
+                self.overlay_service.revoke_controller(&sid);
+            }
+            BusMessage::TakeBackControl(identity) => {
+                let session_service = &self.session_service;
+                if self.overlay_service.take_back_control(|sid| {
+                    session_service.identity(sid).as_deref() == Some(identity.as_str())
+                }) {
+                    session_service.set_in_control(None);
+                }
+            }
+            BusMessage::Journal(entry) => {
+                self.session_journal.record(entry);
+            }
+            BusMessage::RebuildOverlay => {
+                log::warn!("deliver: the overlay is rebuilt with the event loop, see route");
+            }
         }
+    }
 
-        res.unwrap()
+    /// Sends the shareable content to the tauri app.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_loop` - Lists the monitors, the stream is prepared for the primary one
+    fn send_available_content(&mut self, event_loop: &ActiveEventLoop) {
+        log::info!("send_available_content");
+        let monitors: Vec<MonitorHandle> = event_loop.available_monitors().collect();
+        let content = self.capture_service.available_content(&monitors);
+        if content.is_empty() {
+            log::error!("send_available_content: No available content");
+            sentry_utils::upload_logs_event("No available content".to_string());
+        }
+        self.bus
+            .tauri(Message::AvailableContent(AvailableContentMessage {
+                content,
+            }));
+
+        /* Prepare the stream while the user is in the picker. */
+        let monitor = event_loop
+            .primary_monitor()
+            .or_else(|| event_loop.available_monitors().next());
+        if let Some(monitor) = monitor {
+            self.capture_service.warm_up(monitor.size().into());
+        }
+    }
+
+    /// Starts the screen share the tauri app asked for and replies with its parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Configuration including content selection and resolution
+    /// * `event_loop` - Active event loop for window creation
+    fn start_screenshare(&mut self, data: ScreenShareMessage, event_loop: &ActiveEventLoop) {
+        log::info!("start_screenshare: {data:?}");
+        let monitors = event_loop
+            .available_monitors()
+            .collect::<Vec<MonitorHandle>>();
+        let content = data.content;
+        let started = JournalEntry::Started {
+            content,
+            resolution: data.resolution,
+            view_only: data.view_only,
+            aspect_policy: data.aspect_policy,
+        };
+        let res = self.screenshare(data, monitors, event_loop);
+        sentry_utils::add_breadcrumb("state", format!("screen share started: {res:?}"));
+        let already_sharing = matches!(res, Err(ServerError::AlreadySharing));
+        if already_sharing {
+            self.reject_duplicate_share();
+        }
+        let rejection = match res {
+            Err(ServerError::RoomTokenExpired) => Some(RoomTokenRejection::Expired),
+            Err(ServerError::InvalidRoomToken) => Some(RoomTokenRejection::Invalid),
+            _ => None,
+        };
+        if let Some(rejection) = rejection {
+            /* Lets the UI ask for a new token instead of showing a generic failure. */
+            self.bus.tauri(Message::RoomTokenRejected(rejection));
+        }
+        let parameters = res.ok();
+        if parameters.is_some() {
+            self.session_history.session_started(content);
+            self.session_journal.record(started);
+            self.session_dirs.start_session();
+            utils::health::record_session();
+        }
+        /* The active session is fine, there is nothing to upload. */
+        if parameters.is_none() && !already_sharing {
+            sentry_utils::upload_logs_event("Screen share failed".to_string());
+        }
+        self.bus.tauri(Message::StartScreenShareResult(parameters));
     }
 
     /// Initiates a screen sharing session with the specified configuration.
@@ -265,7 +527,8 @@ impl<'a> Application<'a> {
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on successful setup, or `Err(ServerError)` if any step fails.
+    /// Returns the parameters of the published video on successful setup, or
+    /// `Err(ServerError)` if any step fails.
     ///
     /// # Side Effects
     ///
@@ -279,212 +542,498 @@ impl<'a> Application<'a> {
         screenshare_input: ScreenShareMessage,
         monitors: Vec<MonitorHandle>,
         event_loop: &ActiveEventLoop,
-    ) -> Result<(), ServerError> {
-        let mut screen_capturer = self.screen_capturer.lock().unwrap();
-        /*
-         * In order to not rely on the buffer source to exist before starting the room
-         * we start the stream first and we lazy initialize the stream buffer and the
-         * capture buffer.
-         *
-         * Then using the stream extent we can create the room and create the buffer source,
-         * which we set in the Stream.
-         */
-        let res = screen_capturer.start_capture(
-            screenshare_input.content,
-            Extent {
-                width: screenshare_input.resolution.width,
-                height: screenshare_input.resolution.height,
-            },
-        );
-        if let Err(error) = res {
-            log::error!("screenshare: error starting capture: {error:?}");
-            return Err(ServerError::StreamCreationError);
+    ) -> Result<StreamParameters, ServerError> {
+        if self.session_active() {
+            log::warn!("screenshare: rejected, a session is already active");
+            return Err(ServerError::AlreadySharing);
         }
-
-        let extent = screen_capturer.get_stream_extent();
-        if extent.width == 0. || extent.height == 0. {
-            return Err(ServerError::StreamExtentError);
+        let content = screenshare_input.content;
+        let display_count = monitors.len();
+        let res = if !self.session_service.is_connected() {
+            self.report_missing_livekit_url();
+            Err(ServerError::RoomServiceNotFound)
+        } else {
+            self.set_framerate(screenshare_input.framerate);
+            self.start_session(screenshare_input, monitors, event_loop)
+        };
+        if let Err(error) = &res {
+            report_share_failure("Screen share failed", error, content, display_count);
         }
+        res
+    }
 
-        if self.room_service.is_none() {
+    /// Shares a generated test pattern through the LiveKit room instead of the screen.
+    ///
+    /// The frames go through the same stream and room service as a captured
+    /// display, so the publishing path can be checked on machines without
+    /// capture permissions. There is nothing to control, the session is view-only
+    /// and has no overlay.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - LiveKit room token
+    /// * `resolution` - Size of the pattern frames
+    /// * `fps` - Frames generated per second
+    ///
+    /// # Returns
+    ///
+    /// The parameters of the published video, the pattern's fps capped by the encoder's.
+    fn test_pattern_share(
+        &mut self,
+        token: String,
+        resolution: socket_lib::Extent,
+        fps: u32,
+    ) -> Result<StreamParameters, ServerError> {
+        if self.session_active() {
+            log::warn!("test_pattern_share: rejected, a session is already active");
+            return Err(ServerError::AlreadySharing);
+        }
+        if !self.session_service.is_connected() {
+            self.report_missing_livekit_url();
             return Err(ServerError::RoomServiceNotFound);
         }
+        self.input_service.start_session(true);
+        self.set_framerate(None);
+        /* The pattern is generated at its own rate, not the capture's. */
+        self.capture_service.set_source_framerate(fps);
+        sentry_utils::add_breadcrumb("state", "test pattern session");
+
+        let extent = self
+            .capture_service
+            .start_test_pattern(resolution.into(), fps)?;
+        let encoding = self
+            .session_service
+            .create_room(&self.capture_service, token, extent)?;
+
+        self.session_service
+            .with_room("test_pattern_share", RoomService::iterate_participants);
+        self.publish_share_config(false);
+        Ok(stream_parameters(extent, encoding, Some(fps), None))
+    }
 
-        let room_service = self.room_service.as_mut().unwrap();
-        let res = room_service.create_room(
-            screenshare_input.token,
-            extent.width as u32,
-            extent.height as u32,
-            self.event_loop_proxy.clone(),
-        );
-        if let Err(error) = res {
-            log::error!("screenshare: error creating room: {error:?}");
-            return Err(ServerError::RoomCreationError);
+    /// Switches the active session's video to another resolution, the room stays joined.
+    ///
+    /// The capture is restarted at `resolution` and the video track is
+    /// published again for its size, so the encoder's bitrate follows it.
+    ///
+    /// # Arguments
+    ///
+    /// * `resolution` - The new resolution the captured frames are scaled to
+    ///
+    /// # Returns
+    ///
+    /// The parameters of the video at the new resolution, or
+    /// `Err(ServerError::VideoRepublishError)` when the previous track was
+    /// unpublished and the new one couldn't be published.
+    fn change_resolution(
+        &mut self,
+        resolution: socket_lib::Extent,
+    ) -> Result<StreamParameters, ServerError> {
+        if !self.session_active() {
+            log::warn!("change_resolution: no active session");
+            return Err(ServerError::ActiveStreamNotFound);
         }
-        log::info!("screenshare: room created");
+        let extent = self.capture_service.change_resolution(resolution.into())?;
+        let encoding = self
+            .session_service
+            .republish_video(&self.capture_service, extent)?;
+
+        let display_scale = self
+            .overlay_service
+            .set_stream_crop(self.capture_service.stream_crop());
+        log::info!("change_resolution: streaming at {extent:?}");
+        self.publish_share_config(false);
+        Ok(stream_parameters(extent, encoding, None, display_scale))
+    }
 
-        let buffer_source = room_service.get_buffer_source();
-        screen_capturer.set_buffer_source(buffer_source);
+    /// Sets the target frames per second of the capture and the published video.
+    ///
+    /// # Arguments
+    ///
+    /// * `framerate` - Frames per second, clamped to 1..=MAX_CAPTURE_FRAMERATE,
+    ///   the defaults when `None`
+    fn set_framerate(&mut self, framerate: Option<u32>) {
+        let framerate = self.capture_service.set_framerate(framerate);
+        self.session_service
+            .with_room("set_framerate", |room_service| {
+                room_service.set_video_framerate(framerate)
+            });
+    }
+
+    /// Returns `true` while a screen share session is running.
+    ///
+    /// A session is active from the moment its capture started until
+    /// `stop_screenshare`, starting another one in between would restart the
+    /// capture under the existing room. Switching content is done by stopping
+    /// the session first.
+    fn session_active(&self) -> bool {
+        self.overlay_service.is_active() || self.capture_service.has_active_stream()
+    }
 
-        let monitor = screen_capturer.get_selected_monitor(&monitors, screenshare_input.content.id);
-        drop(screen_capturer);
+    /// Lets the tauri app know that a share request was rejected because a
+    /// session is already active.
+    fn reject_duplicate_share(&mut self) {
+        sentry_utils::add_breadcrumb("state", "share rejected: already sharing");
+        self.bus.tauri(Message::AlreadySharing);
+    }
 
-        let res = self.create_overlay_window(monitor, event_loop);
+    /// Starts capturing the share's content and publishes it through the active session's room service.
+    ///
+    /// A `view_only` share never creates the keyboard and mouse injection.
+    /// The captured frames are scaled to its `resolution` as its `aspect_policy` says.
+    ///
+    /// # Returns
+    ///
+    /// The parameters of the published video.
+    fn start_session(
+        &mut self,
+        share: ScreenShareMessage,
+        monitors: Vec<MonitorHandle>,
+        event_loop: &ActiveEventLoop,
+    ) -> Result<StreamParameters, ServerError> {
+        let ScreenShareMessage {
+            content,
+            token,
+            resolution,
+            view_only,
+            aspect_policy,
+            ..
+        } = share;
+        self.input_service.start_session(view_only);
+        if view_only {
+            sentry_utils::add_breadcrumb("state", "view-only session");
+        }
+        /*
+         * In order to not rely on the buffer source to exist before starting the room
+         * we start the stream first and we lazy initialize the stream buffer and the
+         * capture buffer.
+         *
+         * Then using the stream extent we can create the room and create the buffer source,
+         * which we set in the Stream.
+         */
+        let extent =
+            self.capture_service
+                .start_capture(content, resolution.into(), aspect_policy)?;
+        let encoding = self
+            .session_service
+            .create_room(&self.capture_service, token, extent)?;
+
+        let Some(target) = self.capture_service.overlay_target(&monitors) else {
+            log::error!("start_session: the capture has no content");
+            self.stop_screenshare();
+            return Err(ServerError::ActiveStreamNotFound);
+        };
+        let display_scale = target.monitor.scale_factor();
+
+        let res = self
+            .overlay_service
+            .create(target, &monitors, !view_only, event_loop);
         if let Err(e) = res {
             self.stop_screenshare();
-            log::error!("screenshare: error creating overlay window: {e:?}");
+            log::error!("start_session: error creating overlay window: {e:?}");
             return Err(e);
         }
+        if self.overlay_service.is_active() {
+            self.input_service
+                .attach_overlay((!view_only).then(KeyboardController::<KeyboardLayout>::new));
+        }
 
         /* We want to add the participants that already exist in the cursor controller list. */
-        self.room_service.as_ref().unwrap().iterate_participants();
+        self.session_service
+            .with_room("start_session", RoomService::iterate_participants);
+        self.publish_share_config(false);
+
+        Ok(stream_parameters(
+            extent,
+            encoding,
+            None,
+            Some(display_scale),
+        ))
+    }
 
-        Ok(())
+    /// Stops or resumes publishing the shared content's frames, the capture keeps running.
+    ///
+    /// # Arguments
+    ///
+    /// * `paused` - `true` while no participant watches the share
+    fn set_publishing_paused(&mut self, paused: bool) {
+        self.capture_service.set_publishing_paused(paused);
+        self.publish_share_config(false);
     }
 
-    fn stop_screenshare(&mut self) {
-        log::info!("stop_screenshare");
-        let screen_capturer = self.screen_capturer.lock();
-        if let Err(e) = screen_capturer {
-            log::error!("stop_screenshare: Error locking screen capturer: {e:?}");
+    /// Returns the configuration of the active share, `None` when there is no
+    /// active stream or it hasn't delivered a frame yet.
+    fn share_config(&self) -> Option<ShareConfigData> {
+        let status = self.capture_service.stream_status();
+        let extent = status.extent?;
+        Some(ShareConfigData {
+            width: extent.width as u32,
+            height: extent.height as u32,
+            framerate: self
+                .capture_service
+                .framerate()
+                .unwrap_or(DEFAULT_SCREEN_SHARE_FRAMERATE),
+            content_type: status.content.map(|content| content.content_type),
+            paused: status.paused,
+            view_only: self.input_service.view_only(),
+            color_matrix: status.color_matrix.unwrap_or(ColorMatrix::Bt601),
+        })
+    }
+
+    /// Publishes the configuration of the active share to the room, see
+    /// `SessionService::publish_share_config`.
+    ///
+    /// # Arguments
+    ///
+    /// * `force` - Publish even when the configuration didn't change since
+    ///   it was last published, for the participants joining later
+    fn publish_share_config(&mut self, force: bool) {
+        let Some(config) = self.share_config() else {
+            log::warn!("publish_share_config: no active stream");
             return;
-        }
-        let mut screen_capturer = screen_capturer.unwrap();
-        screen_capturer.stop_capture();
-        if let Some(room_service) = self.room_service.as_mut() {
-            room_service.destroy_room();
-        }
-        drop(screen_capturer);
-        self.destroy_overlay_window();
+        };
+        self.session_service.publish_share_config(config, force);
     }
 
-    fn create_overlay_window(
-        &mut self,
-        selected_monitor: MonitorHandle,
-        event_loop: &ActiveEventLoop,
-    ) -> Result<(), ServerError> {
-        log::info!("create_overlay_window: selected_monitor: {selected_monitor:?} ",);
-        let attributes = get_window_attributes();
-        let window = match event_loop.create_window(attributes) {
-            Ok(window) => window,
-            Err(_error) => {
-                return Err(ServerError::WindowCreationError);
-            }
+    /// Asks the tauri app for the LiveKit server URL, see `utils::livekit_url`.
+    fn request_livekit_url(&mut self) {
+        self.bus.tauri(Message::NeedLivekitUrl);
+    }
+
+    /// Reports a share that can't start because the LiveKit server URL never
+    /// arrived and asks the tauri app for it again.
+    fn report_missing_livekit_url(&mut self) {
+        log::error!("report_missing_livekit_url: no LiveKit server URL, can't create the room");
+        sentry_utils::add_breadcrumb("state", "share without a livekit server url");
+        self.request_livekit_url();
+    }
+
+    /// Publishes the shape of the sharer's system cursor and draws the
+    /// sharer's badge with it, see `input::cursor_shape`.
+    fn set_sharer_cursor_shape(&mut self, shape: CursorShape) {
+        debug!("set_sharer_cursor_shape: {shape:?}");
+        self.session_service.set_sharer_cursor_shape(shape);
+        self.overlay_service.set_sharer_cursor_shape(shape);
+    }
+
+    /// Sends a snapshot of the session to the tauri app, see `SessionState`.
+    fn send_session_state(&mut self) {
+        let state = self.session_state();
+        log::info!(
+            "send_session_state: sharing: {} participants: {}",
+            state.sharing,
+            state.participants.len()
+        );
+        self.bus.tauri(Message::SessionState(state));
+    }
+
+    fn session_state(&self) -> SessionState {
+        if !self.session_active() {
+            return SessionState::default();
+        }
+
+        let status = self.capture_service.stream_status();
+        let stream_health = match self.session_service.share_interruption() {
+            Some(reason) => StreamHealth::Interrupted(reason),
+            None if status.stalled => StreamHealth::Stalled,
+            None => StreamHealth::Healthy,
         };
 
-        #[cfg(target_os = "linux")]
-        {
-            /* This is needed for getting the system picker for screen sharing. */
-            let _ = window.request_inner_size(selected_monitor.size().clone());
+        let mut participants = self.overlay_service.controller_states();
+        self.session_service
+            .update_participant_states(&mut participants);
+
+        SessionState {
+            sharing: true,
+            view_only: self.input_service.view_only(),
+            content: status.content,
+            resolution: status.extent.map(socket_lib::Extent::from),
+            participants,
+            room_connected: self.session_service.room_connected(),
+            stream_health: Some(stream_health),
         }
+    }
 
-        let res = window.set_cursor_hittest(false);
-        if let Err(_error) = res {
-            return Err(ServerError::CursorHittestError);
+    /// Creates the shared memory region for bulk payloads and tells the
+    /// tauri app where it is.
+    ///
+    /// The region is created once, a tauri app asking again gets the same one.
+    fn open_shared_memory(&mut self) {
+        if self.shared_memory.is_none() {
+            match SharedRegion::create(
+                SHARED_MEMORY_NAME,
+                SHARED_MEMORY_SLOTS,
+                SHARED_MEMORY_SLOT_SIZE,
+            ) {
+                Ok(region) => self.shared_memory = Some(region),
+                Err(e) => {
+                    log::error!("open_shared_memory: Failed to create region: {e:?}");
+                    self.bus
+                        .tauri(Message::SharedMemoryUnavailable(e.to_string()));
+                    return;
+                }
+            }
+        }
+        if let Some(region) = &self.shared_memory {
+            self.bus
+                .tauri(Message::SharedMemoryReady(region.info().clone()));
         }
+    }
 
-        #[cfg(target_os = "windows")]
-        {
-            window.set_skip_taskbar(true);
+    /// Starts or stops the sharer's live preview, see `capture::preview`.
+    ///
+    /// The preview needs the shared memory region, the tauri app opens it first.
+    fn set_share_preview(&mut self, enabled: bool) {
+        if !enabled {
+            self.share_preview = None;
+            return;
+        }
+        if self.shared_memory.is_none() {
+            log::warn!("set_share_preview: no shared memory region");
+            return;
+        }
+        if self.share_preview.is_none() {
+            self.share_preview = Some(PreviewTicker::new(self.event_sender.clone()));
         }
+    }
 
-        #[cfg(target_os = "macos")]
+    /// Sends the active stream's latest frame to the tauri app.
+    ///
+    /// The frame is dropped while the tauri app hasn't read the previous
+    /// ones, the next tick sends a fresher one.
+    fn send_share_preview(&mut self) {
+        let Some(region) = self.shared_memory.as_mut() else {
+            return;
+        };
+        let Some(jpeg) = self.capture_service.preview_frame(PREVIEW_EXTENT) else {
+            return;
+        };
+        match self
+            .socket
+            .send_bulk(region, BulkPayloadKind::PreviewFrame, &jpeg)
         {
-            window.set_has_shadow(false);
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                debug!("send_share_preview: no free slot, dropping the frame");
+            }
+            Err(e) => log::error!("send_share_preview: Error sending frame: {e:?}"),
         }
+    }
 
-        window.set_visible(true);
-        let monitor_position = selected_monitor.position();
-        window.set_outer_position(LogicalPosition::new(monitor_position.x, monitor_position.y));
-
-        let res = set_fullscreen(&window, selected_monitor.clone());
-        if let Err(error) = res {
-            log::error!("create_overlay_window: Error setting fullscreen {error:?}");
-            return Err(ServerError::FullscreenError);
+    /// Starts or stops syncing the clipboard with the controllers, see `clipboard`.
+    ///
+    /// Only applies to the active session, every session starts with it off.
+    fn set_clipboard_sharing(&mut self, enabled: bool) {
+        log::info!("set_clipboard_sharing: {enabled}");
+        if enabled && !self.session_active() {
+            log::warn!("set_clipboard_sharing: no active session");
+            return;
         }
+        self.input_service.set_clipboard_sharing(enabled);
+    }
 
-        let window_position = match window.outer_position() {
-            Ok(position) => position,
-            Err(error) => {
-                log::error!("create_overlay_window: Error getting window position {error:?} using monitor's");
-                selected_monitor.position()
-            }
-        };
-
-        let window_size = window.inner_size();
+    /// Replies with the input injection the active session created.
+    ///
+    /// The status is read from the session's controllers, not from the
+    /// requested mode, so the sharer can verify a view-only session.
+    fn send_input_injection_status(&mut self) {
+        let status = self
+            .input_service
+            .injection_status(self.session_active(), self.overlay_service.injects_input());
+        log::info!("send_input_injection_status: {status:?}");
+        self.bus.tauri(Message::InputInjectionStatus(status));
+    }
 
-        let mut graphics_context = match GraphicsContext::new(
-            window,
-            self.textures_path.clone(),
-            selected_monitor.scale_factor(),
-        ) {
-            Ok(context) => context,
-            Err(error) => {
-                log::error!("create_overlay_window: Error creating graphics context {error:?}");
-                return Err(ServerError::GfxCreationError);
-            }
-        };
+    /// Sends the session's state to the participant that joined and adds its cursor.
+    fn participant_connected(&mut self, participant: ParticipantData) {
+        log::info!("participant_connected: {participant:?}");
+        sentry_utils::add_breadcrumb(
+            "room",
+            format!("participant connected: {}", participant.sid),
+        );
+        self.session_history.controller_joined();
+        self.session_journal
+            .record(JournalEntry::ParticipantJoined {
+                sid: participant.sid.clone(),
+            });
+        self.set_publishing_paused(false);
+        self.session_service
+            .participant_connected(&participant, self.input_service.view_only());
+        self.publish_share_config(true);
+        self.overlay_service
+            .add_controller(participant.sid, participant.name, &mut self.bus);
+    }
 
-        /* Hardcode window frame to zero as we only support displays for now.*/
-        let window_frame = Frame::default();
-        let scaled = {
-            #[cfg(target_os = "macos")]
-            {
-                true
-            }
-            #[cfg(any(target_os = "windows", target_os = "linux"))]
-            {
-                false
-            }
-        };
-        let overlay_window = Arc::new(OverlayWindow::new(
-            window_frame,
-            Extent {
-                width: window_size.width as f64,
-                height: window_size.height as f64,
-            },
-            window_position,
-            DisplayInfo {
-                display_extent: Extent {
-                    width: selected_monitor.size().width as f64,
-                    height: selected_monitor.size().height as f64,
-                },
-                display_position: monitor_position,
-                display_scale: selected_monitor.scale_factor(),
-            },
-            scaled,
-        ));
-
-        log::info!("create_overlay_window: overlay_window created {overlay_window}");
-
-        let cursor_controller = CursorController::new(
-            &mut graphics_context,
-            overlay_window.clone(),
-            self.event_loop_proxy.clone(),
+    /// Removes the cursor of the participant that left, the frames aren't
+    /// published while nobody watches.
+    fn participant_disconnected(&mut self, participant: ParticipantData) {
+        log::info!("participant_disconnected: {participant:?}");
+        sentry_utils::add_breadcrumb(
+            "room",
+            format!("participant disconnected: {}", participant.sid),
         );
-        if let Err(error) = cursor_controller {
-            log::error!("create_overlay_window: Error creating cursor controller {error:?}");
-            return Err(ServerError::CursorControllerCreationError);
+        self.session_journal.record(JournalEntry::ParticipantLeft {
+            sid: participant.sid.clone(),
+        });
+        self.session_service
+            .participant_disconnected(&participant.sid, &mut self.bus);
+        self.input_service.participant_left(&participant.sid);
+        /* Nobody watches until someone rejoins, the frames aren't encoded meanwhile. */
+        if self.session_service.admitted_count() == 0 {
+            self.set_publishing_paused(true);
         }
+        self.overlay_service.remove_controller(&participant.sid);
+    }
 
-        self.remote_control = Some(RemoteControl {
-            gfx: graphics_context,
-            cursor_controller: cursor_controller.unwrap(),
-            keyboard_controller: KeyboardController::<KeyboardLayout>::new(),
-        });
+    /// Shows each label on its display for `DISPLAY_LABEL_DURATION`.
+    ///
+    /// # Arguments
+    ///
+    /// * `labels` - The displays' content ids and their labels in the picker
+    /// * `event_loop` - Event loop used to create the label windows
+    fn identify_displays(&mut self, labels: Vec<DisplayLabel>, event_loop: &ActiveEventLoop) {
+        log::info!("identify_displays: {labels:?}");
+        let monitors = event_loop
+            .available_monitors()
+            .collect::<Vec<MonitorHandle>>();
+        if monitors.is_empty() {
+            log::error!("identify_displays: no monitors");
+            self.overlay_service.identify_displays(vec![], event_loop);
+            return;
+        }
+        let labels = labels
+            .into_iter()
+            .filter_map(|display| {
+                let monitor = self
+                    .capture_service
+                    .display_monitor(&monitors, display.id)?;
+                Some((monitor, display.label))
+            })
+            .collect::<Vec<_>>();
+        self.overlay_service.identify_displays(labels, event_loop);
+    }
 
-        #[cfg(target_os = "linux")]
+    fn stop_screenshare(&mut self) {
+        log::info!("stop_screenshare");
+        sentry_utils::add_breadcrumb("state", "stop screen share");
+        if !self
+            .capture_service
+            .stop(self.session_service.room_service())
         {
-            /* We can't support the overlay surface on linux yet. */
-            self.remote_control = None;
+            return;
         }
-
-        Ok(())
+        self.input_service.end_session(&mut self.bus);
+        self.overlay_service.destroy();
+        self.session_service.end_session();
+        self.session_dirs.end_session();
     }
 
-    fn destroy_overlay_window(&mut self) {
-        log::info!("destroy_overlay_window");
-        self.remote_control = None;
+    /// Records the end of the active session in the history and the journal.
+    fn session_ended(&mut self, reason: &str) {
+        self.session_history.session_ended(reason);
+        self.session_journal.record(JournalEntry::Ended {
+            reason: reason.to_string(),
+        });
     }
 
     /// Resets the application state after a session ends or encounters an error.
@@ -516,204 +1065,205 @@ impl<'a> Application<'a> {
     /// - May create new threads for screen capture polling
     /// - Resets all session-specific state to initial values
     fn reset_state(&mut self) {
-        let capturer_valid = {
-            let screen_capturer = self.screen_capturer.lock();
-            screen_capturer.is_ok()
-        };
-        if capturer_valid {
+        sentry_utils::add_breadcrumb("state", "reset state");
+        self.session_ended("reset");
+        self.overlay_service.clear_positions();
+        self.session_service.reset();
+        self.input_service.reset();
+        if self.capture_service.is_valid() {
             self.stop_screenshare();
         } else {
             log::warn!("reset_state: Screen capturer is not valid");
-            self.destroy_overlay_window();
-            if let Some(room_service) = self.room_service.as_mut() {
-                room_service.destroy_room();
-            }
-
-            /* Restart the screen capturer. */
-            self.screen_capturer =
-                Arc::new(Mutex::new(Capturer::new(self.event_loop_proxy.clone())));
-            let screen_capturer_clone = self.screen_capturer.clone();
-
-            /*
-             * The previous screen capturer is invalid so we can stop the polling thread,
-             * this should be unlikely to happen to happen.
-             * Therefore we can have thread running but not doing anything.
-             */
-            self._screen_capturer_events = Some(std::thread::spawn(move || {
-                poll_stream(screen_capturer_clone)
-            }));
+            self.input_service.end_session(&mut self.bus);
+            self.overlay_service.destroy();
+            self.session_service.end_session();
+            self.capture_service.restart();
         }
 
         // Upload logs to sentry when ending call.
         sentry_utils::upload_logs_event("Ending call".to_string());
     }
-}
-
-impl Drop for Application<'_> {
-    fn drop(&mut self) {
-        let screen_capturer = self.screen_capturer.lock();
-        if let Err(e) = screen_capturer {
-            log::error!("Error locking screen capturer: {e:?}");
-            return;
-        }
-        let mut screen_capturer = screen_capturer.unwrap();
-        screen_capturer.stop_capture();
-        screen_capturer.stop_runtime_stream_handler();
-        if let Some(screen_capturer_events) = self._screen_capturer_events.take() {
-            screen_capturer_events.join().unwrap();
-        }
-    }
-}
 
-#[derive(Debug, Clone)]
-pub struct ScrollDelta {
-    pub x: f64,
-    pub y: f64,
-}
-
-impl<'a> ApplicationHandler<UserEvent> for Application<'a> {
-    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+    /// Hands `event` to the service it concerns, see `ApplicationHandler::user_event`.
+    ///
+    /// The events that need the event loop are handled by `user_event`.
+    fn handle_user_event(&mut self, event: UserEvent) {
         match event {
             UserEvent::CursorPosition(x, y, sid) => {
                 debug!("user_event: cursor position: {x} {y} {sid}");
-                if self.remote_control.is_none() {
-                    log::warn!("user_event: remote control is none cursor position");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                remote_control.cursor_controller.cursor_move_controller(
-                    x as f64,
-                    y as f64,
-                    sid.as_str(),
-                );
+                self.input_service.cursor_position(x, y, sid, &mut self.bus);
             }
             UserEvent::MouseClick(data, sid) => {
                 debug!("user_event: mouse click: {data:?} {sid}");
-                if self.remote_control.is_none() {
-                    log::warn!("user_event: remote control is none mouse click");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                remote_control
-                    .cursor_controller
-                    .mouse_click_controller(data, sid.as_str());
+                self.input_service.mouse_click(data, sid, &mut self.bus);
             }
             UserEvent::ControllerCursorEnabled(enabled) => {
                 debug!("user_event: cursor enabled: {enabled:?}");
-                if self.remote_control.is_none() {
+                sentry_utils::add_breadcrumb("state", format!("remote control enabled: {enabled}"));
+                if !self.overlay_service.set_controllers_enabled(enabled) {
                     log::warn!("user_event: remote control is none cursor enabled ");
                     return;
                 }
-                if self.room_service.is_none() {
-                    log::warn!("user_event: room service is none cursor enabled");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                let cursor_controller = &mut remote_control.cursor_controller;
-                cursor_controller.set_controllers_enabled(enabled);
-                let keyboard_controller = &mut remote_control.keyboard_controller;
-                keyboard_controller.set_enabled(enabled);
-                self.room_service
-                    .as_ref()
-                    .unwrap()
-                    .publish_controller_cursor_enabled(enabled);
+                self.input_service.set_controllers_enabled(enabled);
+                /* Controllers of a view-only session can't take control either way. */
+                let enabled = enabled && !self.input_service.view_only();
+                self.session_service
+                    .with_room("user_event: cursor enabled", |room_service| {
+                        room_service.publish_controller_cursor_enabled(enabled)
+                    });
             }
             UserEvent::ControllerCursorVisible(visible, sid) => {
                 debug!("user_event: cursor visible: {visible:?} {sid}");
-                if self.remote_control.is_none() {
-                    log::warn!("user_event: remote control is none cursor visible");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                let cursor_controller = &mut remote_control.cursor_controller;
-                cursor_controller.set_controller_visible(visible, sid.as_str());
-            }
-            UserEvent::Keystroke(keystroke_data) => {
-                debug!("user_event: keystroke: {keystroke_data:?}");
-                if self.remote_control.is_none() {
-                    log::warn!("user_event: remote control is none keystroke");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                let keyboard_controller = &mut remote_control.keyboard_controller;
-                keyboard_controller.simulate_keystrokes(keystroke_data);
+                self.overlay_service
+                    .set_controller_visible(visible, sid.as_str());
+            }
+            UserEvent::Keystroke(keystroke_data, sid) => {
+                debug!("user_event: keystroke: {keystroke_data:?} {sid}");
+                self.input_service.keystroke(keystroke_data, sid);
             }
             UserEvent::Scroll(delta, sid) => {
                 debug!("user_event: scroll: {delta:?} {sid}");
-                if self.remote_control.is_none() {
-                    log::warn!("user_event: remote control is none scroll");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                let cursor_controller = &mut remote_control.cursor_controller;
-                cursor_controller.scroll_controller(delta, sid.as_str());
+                self.input_service.scroll(delta, sid, &mut self.bus);
             }
-            UserEvent::Terminate => {
-                log::info!("user_event: Client disconnected, terminating.");
-                event_loop.exit();
+            UserEvent::ScreenShare(data) => {
+                /* Only a duplicate share gets here, see `user_event`. */
+                log::warn!("user_event: Screen share while sharing: {data:?}");
+                self.reject_duplicate_share();
+                self.bus.tauri(Message::StartScreenShareResult(None));
             }
-            UserEvent::GetAvailableContent => {
-                log::info!("user_event: Get available content");
-                let content = self.get_available_content();
-                if content.is_empty() {
-                    log::error!("user_event: No available content");
-                    sentry_utils::upload_logs_event("No available content".to_string());
-                }
-                let res =
-                    self.socket
-                        .send_message(Message::AvailableContent(AvailableContentMessage {
-                            content,
-                        }));
-                if res.is_err() {
-                    log::error!(
-                        "user_event: Error sending available content: {:?}",
-                        res.err()
-                    );
+            event @ (UserEvent::Terminate
+            | UserEvent::GetAvailableContent
+            | UserEvent::IdentifyDisplays(_)
+            | UserEvent::IncomingCall { .. }) => {
+                log::warn!("user_event: {event:?} needs the event loop");
+            }
+            UserEvent::GetBreadcrumbs => {
+                log::info!("user_event: Get breadcrumbs");
+                self.bus
+                    .tauri(Message::Breadcrumbs(sentry_utils::breadcrumb_lines()));
+            }
+            UserEvent::GetDiagnostics => {
+                log::info!("user_event: Get diagnostics");
+                self.bus.tauri(Message::Diagnostics(DiagnosticsMessage {
+                    core_version: env!("CARGO_PKG_VERSION").to_string(),
+                    breadcrumbs: sentry_utils::breadcrumb_lines(),
+                    sessions: self.session_history.summaries(),
+                }));
+            }
+            UserEvent::AvailableContentChanged => {
+                log::info!("user_event: Available content changed");
+                self.bus.tauri(Message::AvailableContentChanged);
+            }
+            UserEvent::FatalCaptureError(reason) => {
+                log::error!("user_event: Fatal capture error: {reason}");
+                self.stop_screenshare();
+                self.session_journal.record(JournalEntry::Error {
+                    message: reason.clone(),
+                });
+                self.session_history
+                    .session_ended(&format!("fatal capture error: {reason}"));
+                self.session_journal.record(JournalEntry::Ended {
+                    reason: "fatal capture error".to_string(),
+                });
+                self.bus.tauri(Message::FatalCaptureError(reason));
+            }
+            UserEvent::PermissionRevoked(permission) => {
+                log::error!("user_event: {permission:?} permission revoked");
+                self.stop_screenshare();
+                self.session_ended(&format!("{permission:?} permission revoked"));
+                self.bus.tauri(Message::PermissionRevoked(permission));
+            }
+            UserEvent::CaptureStalled => {
+                log::info!("user_event: Capture stalled");
+                self.session_history.capture_stalled();
+                self.session_journal.record(JournalEntry::Error {
+                    message: "capture stalled".to_string(),
+                });
+                self.bus.tauri(Message::CaptureStalled);
+            }
+            UserEvent::ShareInterrupted(reason) => {
+                log::info!("user_event: Share interrupted: {reason:?}");
+                self.session_service
+                    .publish_share_interruption(Some(reason), &mut self.bus);
+            }
+            UserEvent::ShareResumed => {
+                log::info!("user_event: Share resumed");
+                self.session_service
+                    .publish_share_interruption(None, &mut self.bus);
+            }
+            UserEvent::SharedWindowMoved(frame) => {
+                log::debug!("user_event: Shared window moved: {frame}");
+                self.overlay_service.set_shared_window_frame(frame);
+            }
+            UserEvent::TestPatternShare {
+                token,
+                resolution,
+                fps,
+            } => {
+                log::info!("user_event: Test pattern share: {resolution:?} {fps} fps");
+                let res = self.test_pattern_share(token, resolution, fps);
+                sentry_utils::add_breadcrumb(
+                    "state",
+                    format!("test pattern share started: {res:?}"),
+                );
+                if matches!(res, Err(ServerError::AlreadySharing)) {
+                    self.reject_duplicate_share();
+                } else if res.is_err() {
+                    /* Leaves nothing running from the failed attempt. */
+                    self.stop_screenshare();
+                } else {
+                    self.session_dirs.start_session();
                 }
+                self.bus.tauri(Message::StartScreenShareResult(res.ok()));
             }
-            UserEvent::ScreenShare(data) => {
-                log::info!("user_event: Screen share: {data:?}");
-                let monitors = event_loop
-                    .available_monitors()
-                    .collect::<Vec<MonitorHandle>>();
-                let res = self.screenshare(data, monitors, event_loop);
-                let res = res.is_ok();
-                if !res {
-                    sentry_utils::upload_logs_event("Screen share failed".to_string());
+            UserEvent::ChangeResolution(resolution) => {
+                log::info!("user_event: Change resolution: {resolution:?}");
+                let res = self.change_resolution(resolution);
+                sentry_utils::add_breadcrumb("state", format!("resolution changed: {res:?}"));
+                if matches!(res, Err(ServerError::VideoRepublishError)) {
+                    /* The controllers lost the video, the session can't go on without it. */
+                    let reason = "Failed to republish the video".to_string();
+                    if let Err(e) = self.event_sender.send(UserEvent::FatalCaptureError(reason)) {
+                        log::error!("user_event: Error sending fatal capture error: {e:?}");
+                    }
                 }
-                let res = self
-                    .socket
-                    .send_message(Message::StartScreenShareResult(res));
-                if res.is_err() {
-                    error!(
-                        "user_event: Error sending start screen share result: {:?}",
-                        res.err()
-                    );
+                self.bus.tauri(Message::ChangeResolutionResult(res.ok()));
+            }
+            UserEvent::SetFramerate(framerate) => {
+                if !self.session_active() {
+                    log::warn!("user_event: set framerate without an active session");
+                    return;
                 }
+                self.set_framerate(Some(framerate));
+                self.publish_share_config(false);
+            }
+            UserEvent::DismissIncomingCall => {
+                self.overlay_service.dismiss_incoming_call();
+            }
+            UserEvent::RestartOverlay => {
+                log::info!("user_event: Restart overlay");
+                self.overlay_service.restart(&mut self.bus);
+            }
+            UserEvent::HideDisplayLabels => {
+                self.overlay_service.hide_display_labels();
             }
             UserEvent::StopScreenShare => {
                 self.stop_screenshare();
+                self.session_ended("stopped by the sharer");
             }
             UserEvent::RequestRedraw => {
                 log::trace!("user_event: Requesting redraw");
-                if self.remote_control.is_none() {
-                    log::warn!("user_event: remote control is none request redraw");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                let gfx = &mut remote_control.gfx;
-                gfx.window().request_redraw();
+                self.overlay_service.request_redraw();
             }
             UserEvent::SharerPosition(x, y) => {
                 debug!("user_event: sharer position: {x} {y}");
-                if self.room_service.is_none() {
-                    log::warn!("user_event: room service is none sharer position");
-                    return;
-                }
-                self.room_service
-                    .as_ref()
-                    .unwrap()
-                    .publish_sharer_location(x, y, true);
+                self.session_service
+                    .with_room("user_event: sharer position", |room_service| {
+                        room_service.publish_sharer_location(x, y, true)
+                    });
+            }
+            UserEvent::SharerCursorShape(shape) => {
+                self.set_sharer_cursor_shape(shape);
             }
             UserEvent::ResetState => {
                 debug!("user_event: Resetting state");
@@ -721,93 +1271,433 @@ impl<'a> ApplicationHandler<UserEvent> for Application<'a> {
             }
             UserEvent::Tick(time) => {
                 debug!("user_event: Tick");
-                if self.room_service.is_none() {
-                    log::warn!("user_event: room service is none tick");
-                    return;
-                }
-                self.room_service.as_ref().unwrap().tick_response(time);
+                self.session_service
+                    .with_room("user_event: tick", |room_service| {
+                        room_service.tick_response(time)
+                    });
+            }
+            UserEvent::ClockSync(request) => {
+                debug!("user_event: Clock sync: {request:?}");
+                self.session_service
+                    .with_room("user_event: clock sync", |room_service| {
+                        room_service.publish_clock_sync(request)
+                    });
             }
             UserEvent::ParticipantConnected(participant) => {
-                log::info!("user_event: Participant connected: {participant:?}");
-                if self.remote_control.is_none() {
-                    log::warn!("user_event: remote control is none participant connected");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                if let Err(e) = remote_control.cursor_controller.add_controller(
-                    &mut remote_control.gfx,
-                    participant.sid,
-                    participant.name,
+                self.participant_connected(participant);
+            }
+            UserEvent::ParticipantDisconnected(participant) => {
+                self.participant_disconnected(participant);
+            }
+            UserEvent::ParticipantWaiting(participant, reason) => {
+                log::info!("user_event: Participant waiting: {participant:?} {reason:?}");
+                sentry_utils::add_breadcrumb(
+                    "room",
+                    format!("participant waiting: {} {reason:?}", participant.sid),
+                );
+                self.session_service
+                    .participant_waiting(participant, reason, &mut self.bus);
+            }
+            UserEvent::UpgradeRequired(identity, min_version) => {
+                self.session_service
+                    .with_room("user_event: upgrade required", |room_service| {
+                        room_service.publish_upgrade_required(identity, min_version)
+                    });
+            }
+            UserEvent::ParticipantCapabilities(sid, capabilities) => {
+                log::info!("user_event: Participant capabilities: {sid} {capabilities:?}");
+                self.session_service
+                    .capabilities_announced(sid, capabilities);
+            }
+            UserEvent::ParticipantQuality(participant, quality) => {
+                log::info!("user_event: Participant quality: {participant:?} {quality:?}");
+                if matches!(
+                    quality,
+                    ConnectionQualityLevel::Poor | ConnectionQualityLevel::Lost
                 ) {
-                    log::error!(
-                        "user_event: Participant connected: Error adding controller: {e:?}"
+                    sentry_utils::add_breadcrumb(
+                        "room",
+                        format!("participant quality: {} {quality:?}", participant.sid),
                     );
                 }
+                self.session_service
+                    .participant_quality(participant, quality, &mut self.bus);
             }
-            UserEvent::ParticipantDisconnected(participant) => {
-                log::info!("user_event: Participant disconnected: {participant:?}");
-                if self.remote_control.is_none() {
-                    log::warn!("user_event: remote control is none participant disconnected");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                remote_control
-                    .cursor_controller
-                    .remove_controller(participant.sid.as_str());
+            UserEvent::SetAdmissionPolicy(policy) => {
+                self.session_service.set_admission_policy(policy);
+            }
+            UserEvent::SetKeyboardArbitration(policy) => {
+                self.input_service.set_keyboard_arbitration(policy);
+            }
+            UserEvent::SetKeyboardAppAllowlist(apps) => {
+                self.input_service.set_keyboard_app_allowlist(apps);
+            }
+            UserEvent::SetKeyboardLayout(layout) => {
+                self.input_service.set_keyboard_layout(layout);
+            }
+            UserEvent::AdmitParticipant(sid) => {
+                self.session_service.admit_participant(sid, &mut self.bus);
+            }
+            UserEvent::KickParticipant(sid) => {
+                self.session_service.kick_participant(sid, &mut self.bus);
+            }
+            UserEvent::BanIdentity(identity) => {
+                self.session_service.ban_identity(identity, &mut self.bus);
+            }
+            UserEvent::SetInputAuthorized(sid, authorized) => {
+                self.session_service
+                    .set_input_authorized(sid, authorized, &mut self.bus);
+            }
+            UserEvent::SetCursorVisible(sid, visible) => {
+                self.overlay_service.set_cursor_visible(sid, visible);
+            }
+            UserEvent::SetThumbnailPrivacy(privacy) => {
+                self.capture_service.set_thumbnail_privacy(privacy);
+            }
+            UserEvent::SetCaptureExclusions(exclusions) => {
+                self.capture_service.set_capture_exclusions(exclusions);
+            }
+            UserEvent::SetOverlayTheme(theme) => {
+                self.overlay_service.set_theme(theme);
+            }
+            UserEvent::ReloadAssets => {
+                self.overlay_service.reload_assets();
+            }
+            UserEvent::OpenSharedMemory => {
+                self.open_shared_memory();
+            }
+            UserEvent::SetSharePreview(enabled) => {
+                self.set_share_preview(enabled);
+            }
+            UserEvent::SharePreviewTick => {
+                self.send_share_preview();
+            }
+            UserEvent::GetInputInjectionStatus => {
+                self.send_input_injection_status();
+            }
+            UserEvent::GetSessionState => {
+                self.send_session_state();
+            }
+            UserEvent::GetCameras => {
+                let cameras = list_cameras();
+                log::info!("user_event: {} cameras", cameras.len());
+                self.bus.tauri(Message::Cameras(cameras));
+            }
+            UserEvent::EnableCamera(device_id) => {
+                self.capture_service.enable_camera(
+                    device_id,
+                    self.session_service.room_service(),
+                    &mut self.bus,
+                );
+            }
+            UserEvent::DisableCamera => {
+                self.capture_service
+                    .disable_camera(self.session_service.room_service());
+            }
+            UserEvent::CameraFailed(error) => {
+                self.capture_service
+                    .disable_camera(self.session_service.room_service());
+                camera_failed(&mut self.bus, error);
+            }
+            UserEvent::GetMicrophones => {
+                let microphones = list_microphones();
+                log::info!("user_event: {} microphones", microphones.len());
+                self.bus.tauri(Message::Microphones(microphones));
+            }
+            UserEvent::EnableMicrophone(device_id, processing) => {
+                self.capture_service.enable_microphone(
+                    device_id,
+                    processing,
+                    self.session_service.room_service(),
+                    &mut self.bus,
+                );
+            }
+            UserEvent::DisableMicrophone => {
+                self.capture_service
+                    .disable_microphone(self.session_service.room_service());
+            }
+            UserEvent::MicrophoneFailed(error) => {
+                self.capture_service
+                    .disable_microphone(self.session_service.room_service());
+                microphone_failed(&mut self.bus, error);
+            }
+            UserEvent::SetNoiseSuppression(enabled) => {
+                self.capture_service.set_noise_suppression(
+                    enabled,
+                    self.session_service.room_service(),
+                    &mut self.bus,
+                );
+            }
+            UserEvent::GetSystemAudioSupport => {
+                let supported = SystemAudioCapture::supported();
+                log::info!("user_event: system audio supported: {supported}");
+                self.bus.tauri(Message::SystemAudioSupport(supported));
+            }
+            UserEvent::EnableSystemAudio => {
+                self.capture_service
+                    .enable_system_audio(self.session_service.room_service(), &mut self.bus);
+            }
+            UserEvent::DisableSystemAudio => {
+                self.capture_service
+                    .disable_system_audio(self.session_service.room_service());
+            }
+            UserEvent::SystemAudioFailed(error) => {
+                self.capture_service
+                    .disable_system_audio(self.session_service.room_service());
+                system_audio_failed(&mut self.bus, error);
+            }
+            UserEvent::MicrophoneOverloaded => {
+                sentry_utils::add_breadcrumb("state", "noise suppression overloaded");
+                self.capture_service.set_noise_suppression(
+                    false,
+                    self.session_service.room_service(),
+                    &mut self.bus,
+                );
+                self.bus.tauri(Message::NoiseSuppressionOverloaded);
+            }
+            UserEvent::StartMacroRecording => {
+                self.input_service.start_macro_recording();
+            }
+            UserEvent::StopMacroRecording(name) => {
+                self.input_service.stop_macro_recording(name, &mut self.bus);
+            }
+            UserEvent::LoadMacro(input_macro) => {
+                self.input_service.load_macro(input_macro, &mut self.bus);
+            }
+            UserEvent::PlayMacro(id) => {
+                self.input_service.play_macro(id, &mut self.bus);
+            }
+            UserEvent::ReplayMacroInput(playback, input) => {
+                self.input_service
+                    .replay_macro_input(playback, input, &mut self.bus);
+            }
+            UserEvent::MacroPlaybackFinished(playback) => {
+                self.input_service
+                    .macro_playback_finished(playback, &mut self.bus);
             }
             UserEvent::LivekitServerUrl(url) => {
                 log::info!("user_event: Livekit server url: {url}");
-                let room_service = RoomService::new(url, self.event_loop_proxy.clone());
-                if room_service.is_err() {
-                    log::error!(
-                        "user_event: Error creating room service: {:?}",
-                        room_service.err()
-                    );
+                if let Some(path) = livekit_url::cache_path() {
+                    if livekit_url::load(&path).as_deref() != Some(url.as_str()) {
+                        if let Err(e) = livekit_url::store(&path, &url) {
+                            log::error!("user_event: Error caching livekit server url: {e:?}");
+                        }
+                    }
+                }
+                self.session_service.connect(url);
+            }
+            UserEvent::CachedLivekitUrl(url) => {
+                /* The tauri app's answer is newer than the cache. */
+                if self.session_service.is_connected() {
+                    log::info!("user_event: Ignoring cached livekit server url {url}");
                     return;
                 }
-                log::info!("user_event: Room service created: {room_service:?}");
-                self.room_service = Some(room_service.unwrap());
+                log::info!("user_event: Using cached livekit server url: {url}");
+                self.session_service.connect(url);
+            }
+            UserEvent::RunNetworkPreflight => {
+                log::info!("user_event: Run network preflight");
+                sentry_utils::add_breadcrumb("state", "network preflight");
+                preflight::run_in_background(
+                    self.event_sender.clone(),
+                    self.session_service.livekit_url(),
+                );
+            }
+            UserEvent::NetworkPreflightFinished(report) => {
+                log::info!(
+                    "user_event: Network preflight finished, media path {:?}",
+                    report.media_path
+                );
+                self.bus.tauri(Message::NetworkPreflightResult(report));
             }
             UserEvent::ControllerTakesScreenShare => {
                 log::info!("user_event: Controller takes screen share");
                 self.stop_screenshare();
+                self.session_ended("a controller took over the screen share");
+            }
+            UserEvent::MediaControl(action, sid) => {
+                log::info!("user_event: Media control {action:?} from {sid}");
+                sentry_utils::add_breadcrumb(
+                    "room",
+                    format!("media control {action:?} from {sid}"),
+                );
+                self.bus.tauri(Message::MediaControl(action));
+            }
+            UserEvent::RaiseHand(raised, sid) => {
+                log::info!("user_event: Raise hand {raised} from {sid}");
+                let name = self.overlay_service.controller_name(&sid);
+                self.session_service
+                    .raise_hand(sid, raised, name, &mut self.bus);
+            }
+            UserEvent::ControlHold(active, sid) => {
+                if !self.input_service.control_hold(active, sid.as_str()) {
+                    return;
+                }
+                log::info!("user_event: Control hold {active} from {sid}");
+                self.overlay_service
+                    .set_controller_held(active, sid.as_str());
+            }
+            UserEvent::SetClipboardSharing(enabled) => {
+                self.set_clipboard_sharing(enabled);
+            }
+            UserEvent::ClipboardChanged(text) => {
+                self.input_service.clipboard_changed(text, &mut self.bus);
+            }
+            UserEvent::ClipboardUpdate(text, sid) => {
+                self.input_service.clipboard_update(text, &sid);
+            }
+            UserEvent::GrantNextControl => {
+                log::info!("user_event: Grant next control");
+                self.session_service.grant_next_control(&mut self.bus);
+            }
+            UserEvent::GetUncleanSession => {
+                self.bus
+                    .tauri(Message::UncleanSession(self.unclean_session.clone()));
+            }
+            UserEvent::DismissUncleanSession => {
+                log::info!("user_event: Dismiss unclean session");
+                self.unclean_session = None;
+            }
+        }
+    }
+}
+
+/// Sends a structured Sentry event for a share that failed to start.
+///
+/// # Arguments
+///
+/// * `message` - Which kind of share failed
+/// * `error` - Why it failed
+/// * `content` - The content that was going to be shared
+/// * `display_count` - Number of displays connected to the machine
+fn report_share_failure(
+    message: &str,
+    error: &ServerError,
+    content: Content,
+    display_count: usize,
+) {
+    let mut tags = capture_failure_tags(Some(content));
+    tags.push(("error", format!("{error:?}")));
+    tags.push(("display_count", display_count.to_string()));
+    sentry_utils::capture_failure(message, &tags);
+}
+
+/// Describes the published video of a session, see `StreamParameters`.
+///
+/// # Arguments
+///
+/// * `extent` - Size of the published video
+/// * `encoding` - How the transport encodes the video
+/// * `source_fps` - Frames per second of a source slower than the encoder, if known
+/// * `display_scale` - Scale factor of the shared display
+fn stream_parameters(
+    extent: Extent,
+    encoding: VideoEncodingInfo,
+    source_fps: Option<u32>,
+    display_scale: Option<f64>,
+) -> StreamParameters {
+    let fps = source_fps.map_or(encoding.max_framerate, |fps| {
+        fps.min(encoding.max_framerate)
+    });
+    StreamParameters {
+        resolution: extent.into(),
+        fps,
+        codec: encoding.codec.to_string(),
+        bitrate: encoding.max_bitrate,
+        display_scale,
+    }
+}
+
+impl<T: SessionTransport> Drop for Application<'_, T> {
+    fn drop(&mut self) {
+        /* Stops the socket, capture and room threads, each within SHUTDOWN_TIMEOUT. */
+        self.cancel.cancel();
+        /* Shutting the socket down wakes up the socket thread's blocked read. */
+        if let Err(e) = self.socket.shutdown() {
+            log::warn!("Application::drop: Error shutting down socket: {e:?}");
+        }
+
+        self.session_service.shutdown();
+        self.capture_service.shutdown();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScrollDelta {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl<'a, T: SessionTransport> ApplicationHandler<UserEvent> for Application<'a, T> {
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        match event {
+            UserEvent::Terminate => {
+                log::info!("user_event: Client disconnected, terminating.");
+                event_loop.exit();
+            }
+            UserEvent::GetAvailableContent => {
+                self.send_available_content(event_loop);
+            }
+            /* A duplicate share is rejected by `handle_user_event`. */
+            UserEvent::ScreenShare(data) if !self.session_active() => {
+                self.start_screenshare(data, event_loop);
             }
+            UserEvent::IdentifyDisplays(labels) => {
+                self.identify_displays(labels, event_loop);
+            }
+            UserEvent::IncomingCall { caller, room } => {
+                self.overlay_service
+                    .show_incoming_call(caller, room, event_loop);
+            }
+            event => self.handle_user_event(event),
         }
+        self.route(event_loop);
     }
 
-    fn resumed(&mut self, _event_loop: &ActiveEventLoop) {}
+    /* Delivers what was posted before the event loop started, e.g. `Message::NeedLivekitUrl`. */
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        self.route(event_loop);
+    }
 
     // Once we get movement input from guest, we will call Window::request_redraw
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _window_id: winit::window::WindowId,
+        window_id: winit::window::WindowId,
         event: WindowEvent,
     ) {
         match event {
-            WindowEvent::CloseRequested => {
+            /* Closing the card with the keyboard declines the call, the core keeps running. */
+            WindowEvent::CloseRequested
+                if !self
+                    .overlay_service
+                    .close_requested(window_id, &mut self.bus) =>
+            {
                 event_loop.exit();
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.overlay_service.cursor_moved(window_id, position);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.overlay_service.clicked(window_id, &mut self.bus);
+            }
             WindowEvent::RedrawRequested => {
-                // render the cursor
-                // The vertices should be in counter clockwise order because of the front face culling
-                if self.remote_control.is_none() {
-                    log::warn!("window_event: remote control is none redraw requested");
-                    return;
-                }
-                let remote_control = &mut self.remote_control.as_mut().unwrap();
-                let gfx = &mut remote_control.gfx;
-                let cursor_controller = &mut remote_control.cursor_controller;
-                gfx.draw(cursor_controller);
+                self.overlay_service.redraw(window_id, &mut self.bus);
             }
             _ => {}
         }
+        self.route(event_loop);
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct KeystrokeData {
-    key: String,
+    /// Keys pressed or released together, in the order they were pressed
+    keys: Vec<input::keys::Key>,
     meta: bool,
     shift: bool,
     ctrl: bool,
@@ -833,6 +1723,8 @@ pub struct MouseClickData {
 pub struct ParticipantData {
     pub name: String,
     pub sid: String,
+    /// Stays the same when the participant reconnects, unlike the sid
+    pub identity: String,
 }
 
 #[derive(Debug, Clone)]
@@ -841,20 +1733,113 @@ pub enum UserEvent {
     MouseClick(MouseClickData, String),
     ControllerCursorEnabled(bool),
     ControllerCursorVisible(bool, String),
-    Keystroke(KeystrokeData),
+    Keystroke(KeystrokeData, String),
     Scroll(ScrollDelta, String),
     GetAvailableContent,
+    AvailableContentChanged,
+    CaptureStalled,
+    FatalCaptureError(String),
+    /// A permission the session needs was revoked, see `Capturer::permission_revoked`
+    PermissionRevoked(Permission),
+    GetBreadcrumbs,
+    GetDiagnostics,
+    ShareInterrupted(ShareInterruption),
+    ShareResumed,
+    /// The shared window's frame in global physical pixels changed.
+    SharedWindowMoved(Frame),
     Terminate,
     ScreenShare(ScreenShareMessage),
+    TestPatternShare {
+        token: String,
+        resolution: socket_lib::Extent,
+        fps: u32,
+    },
+    ChangeResolution(socket_lib::Extent),
+    SetFramerate(u32),
+    IdentifyDisplays(Vec<DisplayLabel>),
+    HideDisplayLabels,
+    IncomingCall {
+        caller: String,
+        room: String,
+    },
+    DismissIncomingCall,
+    /// The sharer asked to rebuild the overlay, see `graphics::overlay_recovery`
+    RestartOverlay,
     StopScreenShare,
     RequestRedraw,
     SharerPosition(f64, f64),
+    SharerCursorShape(CursorShape),
     ResetState,
     Tick(u128),
+    ClockSync(ClockSyncRequest),
     ParticipantConnected(ParticipantData),
     ParticipantDisconnected(ParticipantData),
+    ParticipantWaiting(ParticipantData, WaitReason),
+    ParticipantQuality(ParticipantData, ConnectionQualityLevel),
+    ParticipantCapabilities(String, PeerCapabilities),
+    /// A participant's client is older than the minimum client version, by identity
+    UpgradeRequired(String, u32),
+    SetAdmissionPolicy(AdmissionPolicy),
+    SetKeyboardArbitration(KeyboardArbitration),
+    SetKeyboardAppAllowlist(Vec<String>),
+    SetKeyboardLayout(Option<KeyboardLayoutKind>),
+    AdmitParticipant(String),
+    KickParticipant(String),
+    BanIdentity(String),
+    SetInputAuthorized(String, bool),
+    SetCursorVisible(String, bool),
+    SetThumbnailPrivacy(ThumbnailPrivacy),
+    SetCaptureExclusions(Vec<String>),
+    SetOverlayTheme(OverlayTheme),
+    ReloadAssets,
+    OpenSharedMemory,
+    SetSharePreview(bool),
+    /// Time for the next frame of the sharer's preview, see `capture::preview`
+    SharePreviewTick,
+    GetInputInjectionStatus,
+    GetSessionState,
+    GetCameras,
+    EnableCamera(Option<String>),
+    DisableCamera,
+    /// The camera stopped delivering frames, see `CameraCapture`
+    CameraFailed(String),
+    GetMicrophones,
+    EnableMicrophone(Option<String>, AudioProcessing),
+    DisableMicrophone,
+    /// The microphone's stream failed, see `MicrophoneCapture`
+    MicrophoneFailed(String),
+    SetNoiseSuppression(bool),
+    /// The machine stayed too busy for noise suppression, see `MicrophoneCapture`
+    MicrophoneOverloaded,
+    GetSystemAudioSupport,
+    EnableSystemAudio,
+    DisableSystemAudio,
+    /// The system audio's stream failed, see `SystemAudioCapture`
+    SystemAudioFailed(String),
+    StartMacroRecording,
+    StopMacroRecording(String),
+    LoadMacro(InputMacro),
+    PlayMacro(String),
+    /// An input of the playback with the number, see `MacroPlayback`
+    ReplayMacroInput(u64, MacroInput),
+    MacroPlaybackFinished(u64),
     LivekitServerUrl(String),
+    CachedLivekitUrl(String),
+    RunNetworkPreflight,
+    NetworkPreflightFinished(NetworkPreflightReport),
     ControllerTakesScreenShare,
+    MediaControl(MediaControlAction, String),
+    RaiseHand(bool, String),
+    /// A controller in push-to-control mode pressed or released its modifier
+    ControlHold(bool, String),
+    SetClipboardSharing(bool),
+    /// Text was copied on the sharer's machine, see `ClipboardSync`
+    ClipboardChanged(String),
+    /// A controller's clipboard text, written to the sharer's clipboard
+    ClipboardUpdate(String, String),
+    GrantNextControl,
+    GetUncleanSession,
+    DismissUncleanSession,
 }
 
 pub struct RenderEventLoop {
@@ -863,11 +1848,47 @@ pub struct RenderEventLoop {
 
 pub struct RenderLoopRunArgs {
     pub textures_path: String,
+    /// Overlay render scale, picked from the display size when `None`
+    pub render_scale: Option<f64>,
+    /// Reload the overlay assets when the files in `textures_path` change
+    pub watch_assets: bool,
+    /// Socket the tauri app connects to, `CORE_SOCKET_NAME` or `DEFAULT_SOCKET_NAME` when `None`
+    pub socket_name: Option<String>,
+    /// Oldest protocol version of the clients whose input is injected, every client when `None`
+    pub min_client_version: Option<u32>,
+}
+
+impl RenderLoopRunArgs {
+    /// Returns the name of the socket the tauri app connects to.
+    fn socket_name(&self) -> String {
+        self.socket_name
+            .clone()
+            .or_else(|| std::env::var("CORE_SOCKET_NAME").ok())
+            .unwrap_or(DEFAULT_SOCKET_NAME.to_string())
+    }
+
+    /// Returns the name the files of this core process are kept apart with,
+    /// `None` for the one on `DEFAULT_SOCKET_NAME`.
+    ///
+    /// The tauri app can run a core process per session, each on a socket of
+    /// its own, they don't share a session journal or session directories.
+    fn instance_name(&self) -> Option<String> {
+        let socket_name = self.socket_name();
+        (socket_name != DEFAULT_SOCKET_NAME).then_some(socket_name)
+    }
 }
 
 impl fmt::Display for RenderLoopRunArgs {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Textures path: {}", self.textures_path)
+        write!(
+            f,
+            "Textures path: {} Render scale: {:?} Watch assets: {} Socket name: {} Min client version: {:?}",
+            self.textures_path,
+            self.render_scale,
+            self.watch_assets,
+            self.socket_name(),
+            self.min_client_version
+        )
     }
 }
 
@@ -905,12 +1926,14 @@ impl RenderEventLoop {
     pub fn run(self, input: RenderLoopRunArgs) -> Result<(), RenderLoopError> {
         log::info!("Starting RenderEventLoop with input: {input}");
 
-        let temp_dir = std::env::temp_dir();
-        let socket_name = std::env::var("CORE_SOCKET_NAME").unwrap_or("core-socket".to_string());
-        let socket_path = format!("{}/{socket_name}", temp_dir.display());
+        let socket_name = input.socket_name();
+        let socket_path = socket_lib::socket_path(&socket_name).map_err(|e| {
+            log::error!("Error creating socket directory: {e:?}");
+            RenderLoopError::SocketError(e)
+        })?;
 
         log::info!("Creating socket at path: {socket_path}");
-        let mut socket = CursorSocket::new_create(&socket_path).map_err(|e| {
+        let socket = CursorSocket::new_create(&socket_path).map_err(|e| {
             log::error!("Error creating socket: {e:?}");
             RenderLoopError::SocketError(e)
         })?;
@@ -920,66 +1943,34 @@ impl RenderEventLoop {
         })?;
 
         let event_loop_proxy = self.event_loop.create_proxy();
+        let cancel = CancellationToken::new();
         /*
          * Thread for processing messages from the tauri app.
          */
-        std::thread::spawn(move || loop {
-            let message = match socket.receive_message_with_timeout(std::time::Duration::from_secs(
-                SOCKET_MESSAGE_TIMEOUT_SECONDS,
-            )) {
-                Ok(message) => message,
-                Err(e) => {
-                    /* When the listener has been disconnected we terminate the process. */
-                    log::error!("RenderEventLoop::run Error receiving message: {e:?}");
-                    let res = event_loop_proxy.send_event(UserEvent::Terminate);
-                    if res.is_err() {
-                        log::error!(
-                            "RenderEventLoop::run Error sending terminate event: {:?}",
-                            res.err()
-                        );
-                    }
-
-                    /* We want to make sure the process is terminated. */
-                    std::thread::sleep(std::time::Duration::from_secs(1));
-                    std::process::exit(PROCESS_EXIT_CODE_ERROR);
-                }
-            };
-            log::info!("RenderEventLoop::run Received message: {message:?}");
-            let user_event = match message {
-                Message::GetAvailableContent => UserEvent::GetAvailableContent,
-                Message::StartScreenShare(screen_share_message) => {
-                    UserEvent::ScreenShare(screen_share_message)
-                }
-                Message::StopScreenshare => UserEvent::StopScreenShare,
-                Message::Reset => UserEvent::ResetState,
-                Message::ControllerCursorEnabled(enabled) => {
-                    UserEvent::ControllerCursorEnabled(enabled)
-                }
-                // Ping is on purpose empty. We use it only for stopping the above receive to timeout.
-                Message::Ping => {
-                    continue;
-                }
-                Message::LivekitServerUrl(url) => UserEvent::LivekitServerUrl(url),
-                _ => {
-                    log::error!("RenderEventLoop::run Unknown message: {message:?}");
-                    continue;
-                }
-            };
-            let res = event_loop_proxy.send_event(user_event);
-            if res.is_err() {
-                log::error!(
-                    "RenderEventLoop::run Error sending user event: {:?}",
-                    res.err()
-                );
-            }
+        let socket_cancel = cancel.clone();
+        let socket_thread = std::thread::spawn(move || {
+            socket_receive_thread(socket, event_loop_proxy, socket_cancel)
         });
 
         let proxy = self.event_loop.create_proxy();
-        let mut application = Application::new(input, socket_clone, proxy)?;
-        self.event_loop.run_app(&mut application).map_err(|e| {
+        let mut application = match Application::new(input, socket_clone, cancel.clone(), proxy) {
+            Ok(application) => application,
+            Err(e) => {
+                /* The socket thread would wait for the tauri app otherwise. */
+                cancel.cancel();
+                return Err(e.into());
+            }
+        };
+        let res = self.event_loop.run_app(&mut application).map_err(|e| {
             log::error!("Error running application: {e:?}");
             RenderLoopError::EventLoopError(e)
-        })
+        });
+
+        drop(application);
+        if socket_thread.join().is_err() {
+            log::error!("RenderEventLoop::run: socket thread panicked");
+        }
+        res
     }
 }
 
@@ -1,6 +1,7 @@
 use std::env;
 
 use clap::Parser;
+use hopp_core::utils::health::{start_heartbeats, HeartbeatConfig};
 use hopp_core::{RenderEventLoop, RenderLoopRunArgs};
 use sentry_utils::init_sentry;
 
@@ -15,6 +16,30 @@ struct Args {
     /// Sentry DSN
     #[arg(short, long)]
     sentry_dsn: Option<String>,
+
+    /// Overlay render scale between 0.25 and 1, picked from the display size when not set
+    #[arg(long)]
+    render_scale: Option<f64>,
+
+    /// Seconds between health heartbeats, heartbeats are disabled when not set
+    #[arg(long)]
+    heartbeat_interval: Option<u64>,
+
+    /// URL the health heartbeats are posted to, they are sent to Sentry when not set
+    #[arg(long)]
+    health_url: Option<String>,
+
+    /// Reload the overlay's markers and cursor badges when the files in the textures path change
+    #[arg(long)]
+    watch_assets: bool,
+
+    /// Name of the socket the tauri app connects to, CORE_SOCKET_NAME or core-socket when not set
+    #[arg(long)]
+    socket_name: Option<String>,
+
+    /// Oldest protocol version of the clients whose input is injected, every client when not set
+    #[arg(long)]
+    min_client_version: Option<u32>,
 }
 
 fn main() -> Result<(), impl std::error::Error> {
@@ -22,7 +47,17 @@ fn main() -> Result<(), impl std::error::Error> {
 
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
 
-    let _guard = init_sentry("Core crashed".to_string(), args.sentry_dsn);
+    let sentry_guard = init_sentry("Core crashed".to_string(), args.sentry_dsn);
+
+    if let Some(config) = HeartbeatConfig::new(
+        args.heartbeat_interval,
+        args.health_url,
+        sentry_guard.is_some(),
+    ) {
+        if let Err(e) = start_heartbeats(config) {
+            log::error!("main: Failed to start heartbeats: {e:?}");
+        }
+    }
 
     #[cfg(target_os = "linux")]
     {
@@ -43,7 +78,13 @@ fn main() -> Result<(), impl std::error::Error> {
         }
     };
 
-    let input_args = RenderLoopRunArgs { textures_path };
+    let input_args = RenderLoopRunArgs {
+        textures_path,
+        render_scale: args.render_scale,
+        watch_assets: args.watch_assets,
+        socket_name: args.socket_name,
+        min_client_version: args.min_client_version,
+    };
 
     let render_event_loop = RenderEventLoop::new();
     render_event_loop.run(input_args)
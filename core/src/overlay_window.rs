@@ -6,10 +6,15 @@
 //! accurate coordinate mapping for virtual cursors.
 
 use core::fmt;
+use std::sync::Mutex;
 
+use socket_lib::LabelSize;
 use winit::dpi::PhysicalPosition;
 
-use crate::utils::geometry::{Extent, Frame, Position};
+use crate::utils::display_size;
+use crate::utils::geometry::{
+    Extent, Frame, LogicalPoint, NormalizedPoint, PhysicalPoint, Position, StreamCrop,
+};
 
 /// Display information used for the overlay window.
 pub struct DisplayInfo {
@@ -19,6 +24,8 @@ pub struct DisplayInfo {
     pub display_position: PhysicalPosition<i32>,
     /* The display's scale factor. */
     pub display_scale: f64,
+    /* The display's physical size in millimeters, when the platform knows it. */
+    pub physical_size: Option<Extent>,
 }
 
 /// An overlay window that handles coordinate transformations between different coordinate systems.
@@ -32,14 +39,16 @@ pub struct DisplayInfo {
 /// It is used for properly showing the virtual cursor in the correct position and
 /// translating to global coordinates from display local when simulating mouse events.
 pub struct OverlayWindow {
-    /* The frame of the window/display being shared. */
-    sharing_window_frame: Frame,
+    /* The frame of the window being shared relative to the overlay window (pixels), empty when sharing a display. */
+    sharing_window_frame: Mutex<Frame>,
     /* The window's dimensions in pixels. */
     extent: Extent,
     /* The window's position in global coordinates (pixels). */
     position: PhysicalPosition<i32>,
     display_info: DisplayInfo,
     scaled: bool,
+    /* The part of the captured frames the stream shows, changes with the stream's resolution. */
+    stream_crop: Mutex<StreamCrop>,
 }
 
 impl OverlayWindow {
@@ -53,28 +62,17 @@ impl OverlayWindow {
     /// A new `OverlayWindow` instance with default values.
     pub fn default() -> Self {
         Self {
-            sharing_window_frame: Frame {
-                origin_x: 0.,
-                origin_y: 0.,
-                extent: Extent {
-                    width: 0.0,
-                    height: 0.0,
-                },
-            },
-            extent: Extent {
-                width: 0.0,
-                height: 0.0,
-            },
+            sharing_window_frame: Mutex::new(Frame::default()),
+            extent: Extent::default(),
             position: PhysicalPosition::new(0, 0),
             display_info: DisplayInfo {
-                display_extent: Extent {
-                    width: 0.0,
-                    height: 0.0,
-                },
+                display_extent: Extent::default(),
                 display_position: PhysicalPosition::new(0, 0),
                 display_scale: 1.0,
+                physical_size: None,
             },
             scaled: false,
+            stream_crop: Mutex::new(StreamCrop::default()),
         }
     }
 
@@ -82,7 +80,7 @@ impl OverlayWindow {
     ///
     /// # Arguments
     ///
-    /// * `sharing_window_frame` - The frame of the window/display being shared
+    /// * `sharing_window_frame` - The frame of the shared window relative to the overlay window, empty when sharing a display
     /// * `extent` - The window's dimensions in pixels
     /// * `display_extent` - The display's dimensions in pixels
     /// * `position` - The window's position in global coordinates (pixels)
@@ -102,14 +100,62 @@ impl OverlayWindow {
         scaled: bool,
     ) -> Self {
         Self {
-            sharing_window_frame,
+            sharing_window_frame: Mutex::new(sharing_window_frame),
             extent,
             position,
             display_info,
             scaled,
+            stream_crop: Mutex::new(StreamCrop::default()),
         }
     }
 
+    /// Sets the part of the captured frames the stream shows, see `AspectPolicy::Fill`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_crop` - The crop of the active stream
+    ///
+    /// # Returns
+    ///
+    /// The overlay window with the crop.
+    pub fn with_stream_crop(self, stream_crop: StreamCrop) -> Self {
+        self.set_stream_crop(stream_crop);
+        self
+    }
+
+    /// Follows the stream when its resolution changes the part of the frames it shows.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_crop` - The crop of the active stream
+    pub fn set_stream_crop(&self, stream_crop: StreamCrop) {
+        *self.stream_crop.lock().unwrap() = stream_crop;
+    }
+
+    /// Returns the part of the captured frames the stream shows.
+    pub fn stream_crop(&self) -> StreamCrop {
+        *self.stream_crop.lock().unwrap()
+    }
+
+    /// Follows the shared window when it moves or is resized.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The shared window's frame in global physical pixels
+    pub fn set_shared_window_frame(&self, frame: Frame) {
+        let local = Frame::new(
+            frame.origin_x - self.position.x as f64,
+            frame.origin_y - self.position.y as f64,
+            frame.extent,
+        );
+        log::debug!("set_shared_window_frame: {frame} local: {local}");
+        *self.sharing_window_frame.lock().unwrap() = local;
+    }
+
+    fn window_frame(&self) -> Frame {
+        *self.sharing_window_frame.lock().unwrap()
+    }
+
     /// Translates window local percentage coordinates to screen percentage coordinates.
     ///
     /// This function is essential for drawing virtual cursors in the correct position
@@ -122,36 +168,35 @@ impl OverlayWindow {
     ///
     /// # Arguments
     ///
-    /// * `x` - The x-coordinate as a percentage (0.0 to 1.0)
-    /// * `y` - The y-coordinate as a percentage (0.0 to 1.0)
+    /// * `point` - The position in the shared content
     ///
     /// # Returns
     ///
-    /// A `Position` struct containing the translated coordinates as percentages.
-    pub fn translate_location(&self, x: f64, y: f64) -> Position {
-        log::debug!("translate_location: x: {x}, y: {y}");
+    /// The position in the overlay window.
+    pub fn translate_location(&self, point: NormalizedPoint) -> NormalizedPoint {
+        log::debug!("translate_location: {point:?}");
 
-        if self.sharing_window_frame.extent.width == 0.0
-            || self.sharing_window_frame.extent.height == 0.0
-        {
+        let window_frame = self.window_frame();
+        if window_frame.extent.width == 0.0 || window_frame.extent.height == 0.0 {
             log::debug!("translate_point: client_frame extent is 0.0");
-            return Position { x, y };
+            return point;
         }
 
-        /* The following is unused. It will be needed when we support individual window sharing. */
-        let width_ratio = self.sharing_window_frame.extent.width / self.extent.width;
-        let width_offset = self.sharing_window_frame.origin_x / self.extent.width;
-        let x = x * width_ratio + width_offset;
+        /* When sharing a window the point is a fraction of the window. */
+        let width_ratio = window_frame.extent.width / self.extent.width;
+        let width_offset = window_frame.origin_x / self.extent.width;
+        let x = point.x * width_ratio + width_offset;
 
-        let height_ratio = (self.sharing_window_frame.extent.height / self.extent.height).min(1.0);
-        let height_offset = self.sharing_window_frame.origin_y / self.extent.height;
-        let y = y * height_ratio + height_offset;
+        let height_ratio = (window_frame.extent.height / self.extent.height).min(1.0);
+        let height_offset = window_frame.origin_y / self.extent.height;
+        let y = point.y * height_ratio + height_offset;
 
-        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
-            log::error!("translate_location: x: {x}, y: {y} is out of bounds");
+        let translated = NormalizedPoint::new(x, y);
+        if !translated.is_inside() {
+            log::error!("translate_location: {translated:?} is out of bounds");
         }
 
-        Position { x, y }
+        translated
     }
 
     /// Translates local percentage coordinates to global screen coordinates.
@@ -160,13 +205,11 @@ impl OverlayWindow {
     /// to global screen coordinates in pixels or points. The `scaled` parameter determines
     /// whether the output should be in points (scaled) or pixels (unscaled).
     ///
-    /// Note: This function currently only works when sharing a display, not for
-    /// window-local clicks.
+    /// When sharing a window the point is relative to the window.
     ///
     /// # Arguments
     ///
-    /// * `x` - The x-coordinate as a percentage (0.0 to 1.0)
-    /// * `y` - The y-coordinate as a percentage (0.0 to 1.0), includes menubar height
+    /// * `point` - The position in the shared content, includes menubar height
     ///
     /// # Returns
     ///
@@ -176,24 +219,9 @@ impl OverlayWindow {
     ///
     /// - macOS expects coordinates in points (scaled) for control commands
     /// - Windows expects coordinates in pixels (unscaled)
-    pub fn translate_to_global(&self, x: f64, y: f64) -> Position {
-        // This doesn't work in window local click, only works when sharing display
+    pub fn translate_to_global(&self, point: NormalizedPoint) -> Position {
         // Here in y the menubar heigh is included.
-        let mut x = x * self.display_info.display_extent.width
-            + (self.display_info.display_position.x as f64);
-        let mut y = y * self.display_info.display_extent.height
-            + (self.display_info.display_position.y as f64);
-        /*
-         * macOS expects the coords in points (scaled) in control commands while windows
-         * expects them unscaled.
-         * The same applies to every other function that uses the display scale.
-         */
-        if self.scaled {
-            x /= self.display_info.display_scale;
-            y /= self.display_info.display_scale;
-        }
-
-        Position { x, y }
+        self.physical_to_os(point.to_physical(self.shared_frame()))
     }
 
     /// Converts global coordinates to local window percentage coordinates.
@@ -204,66 +232,97 @@ impl OverlayWindow {
     ///
     /// # Arguments
     ///
-    /// * `x` - The global x-coordinate
-    /// * `y` - The global y-coordinate
+    /// * `position` - The global position
     ///
     /// # Returns
     ///
-    /// A `Position` struct containing the local percentage coordinates (0.0 to 1.0).
-    pub fn local_percentage_from_global(&self, x: f64, y: f64) -> Position {
-        let mut scale = 1.0;
-        if self.scaled {
-            scale = self.display_info.display_scale;
-        }
-        let x = ((x * scale) - self.position.x as f64) / self.extent.width;
-        let y = ((y * scale) - self.position.y as f64) / self.extent.height;
-
-        let (checked_x, checked_y) = out_of_bounds(x, y);
-
-        Position {
-            x: checked_x,
-            y: checked_y,
-        }
+    /// The position in the overlay window, clamped to the window.
+    pub fn local_percentage_from_global(&self, position: Position) -> NormalizedPoint {
+        let window_frame = Frame::new(self.position.x as f64, self.position.y as f64, self.extent);
+        out_of_bounds(self.os_to_physical(position).normalize(window_frame))
     }
 
     /// Converts global coordinates to global display percentage coordinates.
     ///
     /// This function takes global screen coordinates and converts them to percentage
-    /// coordinates relative to the entire display.
+    /// coordinates relative to the entire display, or to the window when sharing one.
     ///
     /// # Arguments
     ///
-    /// * `x` - The global x-coordinate
-    /// * `y` - The global y-coordinate
+    /// * `position` - The global position
     ///
     /// # Returns
     ///
-    /// A `Position` struct containing the global display percentage coordinates (0.0 to 1.0).
+    /// The position in the shared content, clamped to it.
     ///
     /// # Note
     ///
     /// Similar to `local_percentage_from_global`, this function handles the conversion
     /// between points and pixels using the display scale factor.
-    pub fn global_percentage_from_global(&self, x: f64, y: f64) -> Position {
-        let mut scale = 1.0;
-        if self.scaled {
-            scale = self.display_info.display_scale;
-        }
-        let x = ((x * scale) - (self.display_info.display_position.x as f64))
-            / self.display_info.display_extent.width;
-        let y = ((y * scale) - (self.display_info.display_position.y as f64))
-            / self.display_info.display_extent.height;
+    pub fn global_percentage_from_global(&self, position: Position) -> NormalizedPoint {
+        out_of_bounds(self.os_to_physical(position).normalize(self.shared_frame()))
+    }
 
-        let (checked_x, checked_y) = out_of_bounds(x, y);
+    pub fn get_display_scale(&self) -> f64 {
+        self.display_info.display_scale
+    }
 
+    /// Returns the scale of the badges drawn on the display, see `display_size::label_scale`.
+    pub fn label_scale(&self, label_size: LabelSize) -> f64 {
+        display_size::label_scale(
+            label_size,
+            self.display_info.physical_size,
+            self.display_info.display_extent,
+            self.display_info.display_scale,
+        )
+    }
+
+    /// The shared display's frame in global physical pixels.
+    fn display_frame(&self) -> Frame {
+        Frame::new(
+            self.display_info.display_position.x as f64,
+            self.display_info.display_position.y as f64,
+            self.display_info.display_extent,
+        )
+    }
+
+    /// The shared content's frame in global physical pixels, the window's when sharing one.
+    fn shared_frame(&self) -> Frame {
+        let window_frame = self.window_frame();
+        if window_frame.extent.width == 0.0 || window_frame.extent.height == 0.0 {
+            return self.display_frame();
+        }
+        Frame::new(
+            window_frame.origin_x + self.position.x as f64,
+            window_frame.origin_y + self.position.y as f64,
+            window_frame.extent,
+        )
+    }
+
+    /*
+     * macOS expects the coords in points (scaled) in control commands while windows
+     * expects them unscaled.
+     */
+    fn physical_to_os(&self, point: PhysicalPoint) -> Position {
+        if self.scaled {
+            let point = point.to_logical(self.display_info.display_scale);
+            return Position {
+                x: point.x,
+                y: point.y,
+            };
+        }
         Position {
-            x: checked_x,
-            y: checked_y,
+            x: point.x,
+            y: point.y,
         }
     }
 
-    pub fn get_display_scale(&self) -> f64 {
-        self.display_info.display_scale
+    fn os_to_physical(&self, position: Position) -> PhysicalPoint {
+        if self.scaled {
+            return LogicalPoint::new(position.x, position.y)
+                .to_physical(self.display_info.display_scale);
+        }
+        PhysicalPoint::new(position.x, position.y)
     }
 }
 
@@ -272,7 +331,7 @@ impl fmt::Display for OverlayWindow {
         write!(
             f,
             "sharing_window_frame: {}, extent: {}, display_extent: {}, position: {:?}, display_position: {:?}, display_scale: {}",
-            self.sharing_window_frame,
+            self.window_frame(),
             self.extent,
             self.display_info.display_extent,
             self.position,
@@ -282,7 +341,8 @@ impl fmt::Display for OverlayWindow {
     }
 }
 
-fn out_of_bounds(mut x: f64, mut y: f64) -> (f64, f64) {
+fn out_of_bounds(point: NormalizedPoint) -> NormalizedPoint {
+    let NormalizedPoint { mut x, mut y } = point;
     if !(0.0..=1.0).contains(&x) {
         if x < 0.0 {
             x = 0.0;
@@ -297,5 +357,102 @@ fn out_of_bounds(mut x: f64, mut y: f64) -> (f64, f64) {
             y = 0.995;
         }
     }
-    (x, y)
+    NormalizedPoint { x, y }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay_window(scaled: bool) -> OverlayWindow {
+        overlay_window_with_scale(2.0, scaled)
+    }
+
+    fn overlay_window_with_scale(display_scale: f64, scaled: bool) -> OverlayWindow {
+        OverlayWindow::new(
+            Frame::default(),
+            Extent::new(2000.0, 1000.0),
+            PhysicalPosition::new(1000, 100),
+            DisplayInfo {
+                display_extent: Extent::new(2000.0, 1100.0),
+                display_position: PhysicalPosition::new(1000, 0),
+                display_scale,
+                physical_size: None,
+            },
+            scaled,
+        )
+    }
+
+    #[test]
+    fn test_translate_to_global() {
+        let point = NormalizedPoint::new(0.5, 0.5);
+        let global = overlay_window(false).translate_to_global(point);
+        assert_eq!((global.x, global.y), (2000.0, 550.0));
+
+        /* Scaled windows use points. */
+        let global = overlay_window(true).translate_to_global(point);
+        assert_eq!((global.x, global.y), (1000.0, 275.0));
+    }
+
+    #[test]
+    fn test_percentage_from_global() {
+        let overlay = overlay_window(true);
+        let position = Position {
+            x: 1000.0,
+            y: 275.0,
+        };
+        assert_eq!(
+            overlay.global_percentage_from_global(position),
+            NormalizedPoint::new(0.5, 0.5)
+        );
+        assert_eq!(
+            overlay.local_percentage_from_global(position),
+            NormalizedPoint::new(0.5, 0.45)
+        );
+
+        /* Positions outside of the display are clamped. */
+        let outside = Position { x: 0.0, y: 10000.0 };
+        assert_eq!(
+            overlay.global_percentage_from_global(outside),
+            NormalizedPoint::new(0.0, 0.995)
+        );
+    }
+
+    #[test]
+    fn test_click_round_trip_per_scale() {
+        let point = NormalizedPoint::new(0.25, 0.75);
+        for scale in [1.0, 1.25, 2.0] {
+            /* The display is 2000x1100 pixels at x 1000, the click at 1500x825. */
+            let overlay = overlay_window_with_scale(scale, true);
+            let global = overlay.translate_to_global(point);
+            assert_eq!((global.x, global.y), (1500.0 / scale, 825.0 / scale));
+            assert_eq!(overlay.global_percentage_from_global(global), point);
+
+            let overlay = overlay_window_with_scale(scale, false);
+            let global = overlay.translate_to_global(point);
+            assert_eq!((global.x, global.y), (1500.0, 825.0));
+            assert_eq!(overlay.global_percentage_from_global(global), point);
+        }
+    }
+
+    #[test]
+    fn test_shared_window_frame() {
+        let overlay = overlay_window(false);
+        overlay.set_shared_window_frame(Frame::new(1500.0, 350.0, Extent::new(400.0, 200.0)));
+
+        let point = NormalizedPoint::new(0.5, 0.5);
+        let global = overlay.translate_to_global(point);
+        assert_eq!((global.x, global.y), (1700.0, 450.0));
+        assert_eq!(overlay.global_percentage_from_global(global), point);
+        /* The overlay starts at 1000x100, the window's center is at 700x350 in it. */
+        assert_eq!(
+            overlay.translate_location(point),
+            NormalizedPoint::new(0.35, 0.35)
+        );
+
+        /* The window moved. */
+        overlay.set_shared_window_frame(Frame::new(1100.0, 500.0, Extent::new(400.0, 200.0)));
+        let global = overlay.translate_to_global(point);
+        assert_eq!((global.x, global.y), (1300.0, 600.0));
+    }
 }
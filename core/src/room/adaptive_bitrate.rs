@@ -0,0 +1,274 @@
+//! Adaptive encoding of the screen share.
+//!
+//! The screen share is published with the max bitrate of its width, see
+//! `publisher::max_bitrate_for_width`. On a congested uplink the encoder
+//! keeps sending at it, the packets are lost or queued and the viewers see
+//! the picture freeze. While the video is published the transport samples
+//! the sender's RTC stats every `SAMPLE_INTERVAL`, and LiveKit reports the
+//! sharer's connection quality, the encoding is lowered by a step when the
+//! packet loss or the round trip time degrade.
+//!
+//! A step down is taken after `DEGRADE_SAMPLES` bad samples in a row, a step
+//! up after `RECOVER_SAMPLES` good ones and no sooner than `RECOVER_HOLD`
+//! after the last change, so a link on the edge doesn't make the encoding
+//! oscillate.
+
+use std::time::{Duration, Instant};
+
+use socket_lib::ConnectionQualityLevel;
+
+use super::publisher::{screen_share_encoding, VideoEncodingInfo};
+
+/// How often the sender's stats are sampled
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+/// Bad samples in a row before the encoding is lowered
+const DEGRADE_SAMPLES: u32 = 2;
+/// Good samples in a row before the encoding is raised
+const RECOVER_SAMPLES: u32 = 5;
+/// Shortest time between a change and raising the encoding
+const RECOVER_HOLD: Duration = Duration::from_secs(20);
+
+/// Packet loss, as a fraction, and round trip time above which a sample is bad
+const BAD_PACKET_LOSS: f64 = 0.05;
+const BAD_RTT: Duration = Duration::from_millis(400);
+/// Packet loss and round trip time below which a sample is good
+const GOOD_PACKET_LOSS: f64 = 0.01;
+const GOOD_RTT: Duration = Duration::from_millis(200);
+
+/// The encoding steps, the percentage of the width's max bitrate and the max framerate
+const STEPS: [(u64, u32); 4] = [(100, 30), (70, 30), (45, 24), (25, 15)];
+
+/// The network conditions of the published video, from the receivers' reports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkSample {
+    /// Fraction of the packets lost since the last report
+    pub packet_loss: f64,
+    pub rtt: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rating {
+    Bad,
+    Neutral,
+    Good,
+}
+
+/// Picks the encoding of the screen share from the network samples.
+#[derive(Debug)]
+pub struct BitrateAdapter {
+    width: u32,
+    /// The encoding of the width and the requested framerate, the first step
+    base: VideoEncodingInfo,
+    step: usize,
+    bad_samples: u32,
+    good_samples: u32,
+    last_change: Instant,
+    /// The sharer's connection quality reported by LiveKit, `None` before the first report
+    quality: Option<ConnectionQualityLevel>,
+}
+
+impl BitrateAdapter {
+    /// Creates the adapter of a video published at full encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the video track
+    /// * `max_framerate` - The framerate the share requested
+    /// * `now` - When the video was published
+    pub fn new(width: u32, max_framerate: u32, now: Instant) -> Self {
+        Self {
+            width,
+            base: screen_share_encoding(width, max_framerate),
+            step: 0,
+            bad_samples: 0,
+            good_samples: 0,
+            last_change: now,
+            quality: None,
+        }
+    }
+
+    /// Records the sharer's connection quality, it rates the next samples.
+    pub fn set_quality(&mut self, quality: ConnectionQualityLevel) {
+        self.quality = Some(quality);
+    }
+
+    /// Changes the framerate the share requested, the steps stay capped by it.
+    ///
+    /// # Returns
+    ///
+    /// The encoding of the current step at the new framerate.
+    pub fn set_max_framerate(&mut self, max_framerate: u32) -> VideoEncodingInfo {
+        self.base = screen_share_encoding(self.width, max_framerate);
+        self.encoding()
+    }
+
+    /// Returns the encoding of the current step.
+    pub fn encoding(&self) -> VideoEncodingInfo {
+        let (percentage, max_framerate) = STEPS[self.step];
+        VideoEncodingInfo {
+            codec: self.base.codec,
+            max_framerate: max_framerate.min(self.base.max_framerate),
+            max_bitrate: self
+                .base
+                .max_bitrate
+                .map(|max_bitrate| max_bitrate * percentage / 100),
+        }
+    }
+
+    /// Records a sample of the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - The sender's stats, `None` when they aren't available and
+    ///   only the connection quality rates the sample
+    /// * `now` - When the sample was taken
+    ///
+    /// # Returns
+    ///
+    /// The encoding to apply when the step changed.
+    pub fn sample(
+        &mut self,
+        sample: Option<NetworkSample>,
+        now: Instant,
+    ) -> Option<VideoEncodingInfo> {
+        match self.rate(sample) {
+            Rating::Bad => {
+                self.good_samples = 0;
+                self.bad_samples += 1;
+                if self.bad_samples < DEGRADE_SAMPLES || self.step + 1 == STEPS.len() {
+                    return None;
+                }
+                self.step += 1;
+            }
+            Rating::Neutral => {
+                self.good_samples = 0;
+                self.bad_samples = 0;
+                return None;
+            }
+            Rating::Good => {
+                self.bad_samples = 0;
+                self.good_samples += 1;
+                if self.good_samples < RECOVER_SAMPLES
+                    || self.step == 0
+                    || now.duration_since(self.last_change) < RECOVER_HOLD
+                {
+                    return None;
+                }
+                self.step -= 1;
+            }
+        }
+        self.bad_samples = 0;
+        self.good_samples = 0;
+        self.last_change = now;
+        Some(self.encoding())
+    }
+
+    fn rate(&self, sample: Option<NetworkSample>) -> Rating {
+        let quality = match self.quality {
+            Some(ConnectionQualityLevel::Poor | ConnectionQualityLevel::Lost) => Rating::Bad,
+            Some(ConnectionQualityLevel::Good) => Rating::Neutral,
+            Some(ConnectionQualityLevel::Excellent) | None => Rating::Good,
+        };
+        let Some(sample) = sample else {
+            /* Without stats a missing report doesn't vouch for the network. */
+            return match (quality, self.quality) {
+                (Rating::Good, None) => Rating::Neutral,
+                (quality, _) => quality,
+            };
+        };
+        if quality == Rating::Bad || sample.packet_loss > BAD_PACKET_LOSS || sample.rtt > BAD_RTT {
+            Rating::Bad
+        } else if quality == Rating::Good
+            && sample.packet_loss < GOOD_PACKET_LOSS
+            && sample.rtt < GOOD_RTT
+        {
+            Rating::Good
+        } else {
+            Rating::Neutral
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOOD: NetworkSample = NetworkSample {
+        packet_loss: 0.0,
+        rtt: Duration::from_millis(50),
+    };
+    const LOSSY: NetworkSample = NetworkSample {
+        packet_loss: 0.1,
+        rtt: Duration::from_millis(50),
+    };
+    const FAIR: NetworkSample = NetworkSample {
+        packet_loss: 0.02,
+        rtt: Duration::from_millis(250),
+    };
+
+    #[test]
+    fn test_degrades_after_consecutive_bad_samples() {
+        let start = Instant::now();
+        let s = |s: u64| start + Duration::from_secs(s);
+        let mut adapter = BitrateAdapter::new(2560, 30, start);
+        let full = adapter.encoding();
+        assert_eq!(full, screen_share_encoding(2560, 30));
+
+        assert_eq!(adapter.sample(Some(LOSSY), s(2)), None);
+        /* A fair sample breaks the streak. */
+        assert_eq!(adapter.sample(Some(FAIR), s(4)), None);
+        assert_eq!(adapter.sample(Some(LOSSY), s(6)), None);
+        let lowered = adapter.sample(Some(LOSSY), s(8)).unwrap();
+        assert_eq!(lowered.max_bitrate, Some(3_500_000));
+        assert_eq!(lowered.max_framerate, 30);
+
+        for i in 0..10 {
+            adapter.sample(Some(LOSSY), s(10 + i * 2));
+        }
+        let lowest = adapter.encoding();
+        assert_eq!(lowest.max_bitrate, Some(1_250_000));
+        assert_eq!(lowest.max_framerate, 15);
+
+        /* A lower requested framerate caps every step. */
+        assert_eq!(adapter.set_max_framerate(10).max_framerate, 10);
+        assert_eq!(adapter.set_max_framerate(60).max_framerate, 15);
+    }
+
+    #[test]
+    fn test_recovers_slowly() {
+        let start = Instant::now();
+        let s = |s: u64| start + Duration::from_secs(s);
+        let mut adapter = BitrateAdapter::new(1920, 30, start);
+        adapter.sample(Some(LOSSY), s(2));
+        assert!(adapter.sample(Some(LOSSY), s(4)).is_some());
+
+        /* Enough good samples, but too soon after the change. */
+        for i in 0..5 {
+            assert_eq!(adapter.sample(Some(GOOD), s(6 + i * 2)), None);
+        }
+        let raised = adapter.sample(Some(GOOD), s(24)).unwrap();
+        assert_eq!(raised, screen_share_encoding(1920, 30));
+        assert_eq!(adapter.sample(Some(GOOD), s(60)), None);
+    }
+
+    #[test]
+    fn test_connection_quality_rates_the_samples() {
+        let start = Instant::now();
+        let s = |s: u64| start + Duration::from_secs(s);
+        let mut adapter = BitrateAdapter::new(1920, 30, start);
+        /* Without stats nor quality nothing changes. */
+        for i in 0..5 {
+            assert_eq!(adapter.sample(None, s(i)), None);
+        }
+
+        adapter.set_quality(ConnectionQualityLevel::Poor);
+        adapter.sample(Some(GOOD), s(10));
+        assert!(adapter.sample(None, s(12)).is_some());
+
+        /* Good samples of a connection LiveKit rates good aren't enough. */
+        adapter.set_quality(ConnectionQualityLevel::Good);
+        for i in 0..20 {
+            assert_eq!(adapter.sample(Some(GOOD), s(40 + i * 2)), None);
+        }
+    }
+}
@@ -0,0 +1,449 @@
+//! Admission of participants to a session.
+//!
+//! The sharer can cap the number of participants and keep new ones in a
+//! waiting room until they are admitted. The decision is taken where the
+//! transports translate the room events: a participant that isn't admitted
+//! doesn't get a cursor and its data packets are dropped, so a client can't
+//! get around it by ignoring the wait message.
+//!
+//! Kicked participants and banned identities are handled the same way, their
+//! events are ignored until they disconnect and a banned identity is ignored
+//! when it rejoins, until the session ends.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use socket_lib::{AdmissionPolicy, WaitReason, WaitingParticipant};
+
+use crate::event_sender::EventSender;
+use crate::ParticipantData;
+
+/// Whether a participant that joined can take part in the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmissionDecision {
+    Admitted,
+    Waiting(WaitReason),
+    /// The participant was kicked or its identity is banned, it is ignored
+    Removed,
+}
+
+#[derive(Debug, Default)]
+struct AdmissionState {
+    policy: AdmissionPolicy,
+    /// Identities of the admitted participants by sid
+    admitted: HashMap<String, String>,
+    /// Waiting participants by sid, with the order they joined in
+    waiting: HashMap<String, (u64, ParticipantData, WaitReason)>,
+    next_waiting: u64,
+    /// Identities the sharer admitted, they skip the waiting room when they reconnect
+    approved: HashSet<String>,
+    /// Kicked participants by sid, until they disconnect
+    removed: HashSet<String>,
+    /// Identities the sharer banned from the session
+    banned: HashSet<String>,
+}
+
+/// The participants admitted to the current session.
+///
+/// Clones share the same state, the application changes the policy and
+/// admits participants while the transports check the incoming events.
+#[derive(Debug, Clone, Default)]
+pub struct Admission {
+    state: Arc<Mutex<AdmissionState>>,
+}
+
+impl Admission {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `policy` to the participants that join from now on.
+    pub fn set_policy(&self, policy: AdmissionPolicy) {
+        log::info!("Admission::set_policy: {policy:?}");
+        self.state.lock().unwrap().policy = policy;
+    }
+
+    /// Forgets the participants and bans of the previous session, the policy is kept.
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.admitted.clear();
+        state.waiting.clear();
+        state.approved.clear();
+        state.removed.clear();
+        state.banned.clear();
+    }
+
+    /// Decides whether a participant that joined is admitted.
+    ///
+    /// Deciding again for a participant returns the previous decision.
+    ///
+    /// # Arguments
+    ///
+    /// * `participant` - The participant that joined
+    ///
+    /// # Returns
+    ///
+    /// `AdmissionDecision::Admitted` if the participant can take part in the
+    /// session, `AdmissionDecision::Removed` if it was kicked or banned,
+    /// otherwise why it has to wait.
+    pub fn decide(&self, participant: &ParticipantData) -> AdmissionDecision {
+        let mut state = self.state.lock().unwrap();
+        if state.removed.contains(&participant.sid) || state.banned.contains(&participant.identity)
+        {
+            return AdmissionDecision::Removed;
+        }
+        if state.admitted.contains_key(&participant.sid) {
+            return AdmissionDecision::Admitted;
+        }
+        if let Some((_, _, reason)) = state.waiting.get(&participant.sid) {
+            return AdmissionDecision::Waiting(*reason);
+        }
+
+        let reason = match state.policy.max_participants {
+            Some(max) if state.admitted.len() >= max as usize => Some(WaitReason::RoomFull),
+            _ if state.policy.waiting_room && !state.approved.contains(&participant.identity) => {
+                Some(WaitReason::NeedsApproval)
+            }
+            _ => None,
+        };
+        match reason {
+            Some(reason) => {
+                let order = state.next_waiting;
+                state.next_waiting += 1;
+                state.waiting.insert(
+                    participant.sid.clone(),
+                    (order, participant.clone(), reason),
+                );
+                AdmissionDecision::Waiting(reason)
+            }
+            None => {
+                state
+                    .admitted
+                    .insert(participant.sid.clone(), participant.identity.clone());
+                AdmissionDecision::Admitted
+            }
+        }
+    }
+
+    /// Decides whether `participant` is admitted and reports it to `sink`.
+    ///
+    /// Admitted participants are sent as connected, the waiting ones as
+    /// waiting and removed ones aren't sent at all.
+    ///
+    /// # Returns
+    ///
+    /// The decision for the participant.
+    pub fn join(&self, participant: ParticipantData, sink: &impl EventSender) -> AdmissionDecision {
+        let decision = self.decide(&participant);
+        let res = match decision {
+            AdmissionDecision::Admitted => sink.send_participant_connected(participant),
+            AdmissionDecision::Waiting(reason) => {
+                log::info!("Admission::join: {} waits: {reason:?}", participant.sid);
+                sink.send_participant_waiting(participant, reason)
+            }
+            AdmissionDecision::Removed => {
+                log::info!(
+                    "Admission::join: ignoring removed {} {}",
+                    participant.sid,
+                    participant.identity
+                );
+                Ok(())
+            }
+        };
+        if let Err(e) = res {
+            log::error!("Admission::join: Failed to send participant event: {e:?}");
+        }
+        decision
+    }
+
+    /// Admits the waiting participant `sid`, the room cap doesn't apply.
+    ///
+    /// # Returns
+    ///
+    /// The admitted participant, `None` if it wasn't waiting.
+    pub fn approve(&self, sid: &str) -> Option<ParticipantData> {
+        let mut state = self.state.lock().unwrap();
+        let (_, participant, _) = state.waiting.remove(sid)?;
+        state
+            .admitted
+            .insert(participant.sid.clone(), participant.identity.clone());
+        state.approved.insert(participant.identity.clone());
+        Some(participant)
+    }
+
+    /// Kicks the participant `sid`, its events are ignored until it disconnects.
+    ///
+    /// The participant can rejoin, but goes through the waiting room again.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the participant was admitted or waiting.
+    pub fn kick(&self, sid: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let identity = match state.admitted.remove(sid) {
+            Some(identity) => Some(identity),
+            None => state
+                .waiting
+                .remove(sid)
+                .map(|(_, participant, _)| participant.identity),
+        };
+        state.removed.insert(sid.to_string());
+        match identity {
+            Some(identity) => {
+                state.approved.remove(&identity);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Bans `identity` until the session ends and kicks its participants.
+    ///
+    /// # Returns
+    ///
+    /// The sids of the kicked participants.
+    pub fn ban(&self, identity: &str) -> Vec<String> {
+        let mut state = self.state.lock().unwrap();
+        state.banned.insert(identity.to_string());
+        state.approved.remove(identity);
+
+        let mut sids: Vec<String> = state
+            .admitted
+            .iter()
+            .filter(|(_, admitted_identity)| *admitted_identity == identity)
+            .map(|(sid, _)| sid.clone())
+            .collect();
+        sids.extend(
+            state
+                .waiting
+                .iter()
+                .filter(|(_, (_, participant, _))| participant.identity == identity)
+                .map(|(sid, _)| sid.clone()),
+        );
+        sids.sort();
+        for sid in &sids {
+            state.admitted.remove(sid);
+            state.waiting.remove(sid);
+            state.removed.insert(sid.clone());
+        }
+        sids
+    }
+
+    /// Removes the participant `sid` that left the session.
+    pub fn leave(&self, sid: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.admitted.remove(sid);
+        state.waiting.remove(sid);
+        state.removed.remove(sid);
+    }
+
+    /// Returns `true` if the events of the participant `sid` can be handled.
+    pub fn is_admitted(&self, sid: &str) -> bool {
+        self.state.lock().unwrap().admitted.contains_key(sid)
+    }
+
+    /// Returns the identity of the admitted participant `sid`.
+    pub fn identity(&self, sid: &str) -> Option<String> {
+        self.state.lock().unwrap().admitted.get(sid).cloned()
+    }
+
+    /// Returns the number of admitted participants.
+    pub fn admitted_count(&self) -> usize {
+        self.state.lock().unwrap().admitted.len()
+    }
+
+    /// Returns `true` if the participant `sid` was kicked or banned.
+    pub fn is_removed(&self, sid: &str) -> bool {
+        self.state.lock().unwrap().removed.contains(sid)
+    }
+
+    /// Returns the waiting participants, in the order they joined.
+    pub fn waiting(&self) -> Vec<WaitingParticipant> {
+        let state = self.state.lock().unwrap();
+        let mut waiting: Vec<_> = state.waiting.values().collect();
+        waiting.sort_by_key(|(order, _, _)| *order);
+        waiting
+            .into_iter()
+            .map(|(_, participant, reason)| WaitingParticipant {
+                sid: participant.sid.clone(),
+                name: participant.name.clone(),
+                identity: participant.identity.clone(),
+                reason: *reason,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use crate::UserEvent;
+
+    fn participant(sid: &str, identity: &str) -> ParticipantData {
+        ParticipantData {
+            name: format!("Name {sid}"),
+            sid: sid.to_string(),
+            identity: identity.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_default_policy_admits_everyone() {
+        let admission = Admission::new();
+        for i in 0..20 {
+            let p = participant(&format!("sid{i}"), &format!("user{i}"));
+            assert_eq!(admission.decide(&p), AdmissionDecision::Admitted);
+            assert!(admission.is_admitted(&p.sid));
+        }
+        assert!(admission.waiting().is_empty());
+    }
+
+    #[test]
+    fn test_max_participants() {
+        let admission = Admission::new();
+        admission.set_policy(AdmissionPolicy {
+            max_participants: Some(1),
+            waiting_room: false,
+        });
+        let first = participant("a", "user_a");
+        let second = participant("b", "user_b");
+        assert_eq!(admission.decide(&first), AdmissionDecision::Admitted);
+        assert_eq!(
+            admission.decide(&second),
+            AdmissionDecision::Waiting(WaitReason::RoomFull)
+        );
+        assert!(!admission.is_admitted("b"));
+
+        /* A freed slot isn't taken over, the sharer admits the waiting participant. */
+        admission.leave("a");
+        assert_eq!(
+            admission.decide(&second),
+            AdmissionDecision::Waiting(WaitReason::RoomFull)
+        );
+        assert_eq!(admission.approve("b").unwrap().sid, "b");
+        assert!(admission.is_admitted("b"));
+        assert!(admission.approve("b").is_none());
+    }
+
+    #[test]
+    fn test_waiting_room() {
+        let admission = Admission::new();
+        admission.set_policy(AdmissionPolicy {
+            max_participants: None,
+            waiting_room: true,
+        });
+        let sink = EventCollector::new();
+        admission.join(participant("a", "user_a"), &sink);
+        admission.join(participant("b", "user_b"), &sink);
+        let events = sink.take();
+        assert!(matches!(
+            &events[0],
+            UserEvent::ParticipantWaiting(data, WaitReason::NeedsApproval) if data.sid == "a"
+        ));
+        assert_eq!(
+            admission
+                .waiting()
+                .iter()
+                .map(|p| p.sid.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+
+        admission.approve("a").unwrap();
+        assert!(admission.is_admitted("a"));
+        assert!(!admission.is_admitted("b"));
+
+        /* An admitted identity doesn't wait again when it reconnects. */
+        admission.leave("a");
+        admission.leave("b");
+        assert!(admission.waiting().is_empty());
+        admission.join(participant("a2", "user_a"), &sink);
+        assert!(matches!(
+            &sink.take()[0],
+            UserEvent::ParticipantConnected(data) if data.sid == "a2"
+        ));
+
+        admission.reset();
+        assert_eq!(
+            admission.decide(&participant("a3", "user_a")),
+            AdmissionDecision::Waiting(WaitReason::NeedsApproval)
+        );
+    }
+
+    #[test]
+    fn test_kick() {
+        let admission = Admission::new();
+        admission.set_policy(AdmissionPolicy {
+            max_participants: None,
+            waiting_room: true,
+        });
+        let sink = EventCollector::new();
+        admission.join(participant("a", "user_a"), &sink);
+        admission.approve("a").unwrap();
+
+        assert!(admission.kick("a"));
+        assert!(!admission.is_admitted("a"));
+        assert!(admission.is_removed("a"));
+        assert_eq!(
+            admission.decide(&participant("a", "user_a")),
+            AdmissionDecision::Removed
+        );
+        assert!(!admission.kick("unknown"));
+
+        /* A kicked participant can rejoin, but has to be admitted again. */
+        admission.leave("a");
+        assert!(!admission.is_removed("a"));
+        assert_eq!(
+            admission.join(participant("a2", "user_a"), &sink),
+            AdmissionDecision::Waiting(WaitReason::NeedsApproval)
+        );
+    }
+
+    #[test]
+    fn test_ban() {
+        let admission = Admission::new();
+        let sink = EventCollector::new();
+        admission.join(participant("a", "user_a"), &sink);
+        admission.join(participant("b", "user_a"), &sink);
+        admission.join(participant("c", "user_c"), &sink);
+        sink.take();
+
+        assert_eq!(admission.ban("user_a"), ["a", "b"]);
+        assert!(!admission.is_admitted("a"));
+        assert!(!admission.is_admitted("b"));
+        assert!(admission.is_admitted("c"));
+
+        /* Rejoining under the same identity is ignored without an event. */
+        admission.leave("a");
+        assert_eq!(
+            admission.join(participant("a2", "user_a"), &sink),
+            AdmissionDecision::Removed
+        );
+        assert!(sink.take().is_empty());
+        assert!(!admission.is_admitted("a2"));
+
+        /* The ban list is kept for the session only. */
+        admission.reset();
+        assert_eq!(
+            admission.join(participant("a3", "user_a"), &sink),
+            AdmissionDecision::Admitted
+        );
+    }
+
+    #[test]
+    fn test_admitted_count() {
+        let admission = Admission::new();
+        admission.set_policy(AdmissionPolicy {
+            max_participants: Some(1),
+            waiting_room: false,
+        });
+        assert_eq!(admission.admitted_count(), 0);
+        admission.decide(&participant("a", "user_a"));
+        /* Waiting participants don't watch the share. */
+        admission.decide(&participant("b", "user_b"));
+        assert_eq!(admission.admitted_count(), 1);
+        admission.leave("a");
+        assert_eq!(admission.admitted_count(), 0);
+    }
+}
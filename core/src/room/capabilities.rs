@@ -0,0 +1,167 @@
+//! Capabilities negotiated with the participants of a session.
+//!
+//! The sharer announces its protocol version and the optional features it
+//! handles on the `capabilities` topic when a participant is added, and the
+//! clients answer with theirs, see `client_event::CapabilitiesData`. The core
+//! keeps them per participant so a feature is only used when everyone in the
+//! session handles it. Older clients that never answer count as version 0
+//! without features, the session falls back to what they support.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::client_event::CapabilitiesData;
+
+/// Version of the data-channel protocol spoken by this build
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features the sharer handles, each is added with its implementation
+pub const SHARER_FEATURES: &[Feature] = &[];
+
+/// An optional feature of the data-channel protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Annotations,
+    Chat,
+    FileTransfer,
+    PenInput,
+    /// A feature of a newer client this build doesn't know
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a participant announced it supports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerCapabilities {
+    /// Protocol version, 0 until the participant announces one
+    pub version: u32,
+    pub features: HashSet<Feature>,
+}
+
+impl From<&CapabilitiesData> for PeerCapabilities {
+    fn from(data: &CapabilitiesData) -> Self {
+        Self {
+            version: data.version,
+            features: data
+                .features
+                .iter()
+                .copied()
+                .filter(|feature| *feature != Feature::Unknown)
+                .collect(),
+        }
+    }
+}
+
+/// The capabilities of the participants in the session, by sid.
+#[derive(Debug, Default)]
+pub struct ParticipantCapabilities {
+    participants: HashMap<String, PeerCapabilities>,
+}
+
+impl ParticipantCapabilities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracks a participant that joined, it has no capabilities until it announces them.
+    pub fn joined(&mut self, sid: &str) {
+        self.participants.entry(sid.to_string()).or_default();
+    }
+
+    /// Records the capabilities the participant `sid` announced.
+    ///
+    /// The announcement can arrive before the participant is reported as
+    /// joined, it is kept either way.
+    pub fn announced(&mut self, sid: String, capabilities: PeerCapabilities) {
+        log::info!("ParticipantCapabilities::announced: {sid}: {capabilities:?}");
+        self.participants.insert(sid, capabilities);
+    }
+
+    /// Forgets the participant `sid` that left the session.
+    pub fn left(&mut self, sid: &str) {
+        self.participants.remove(sid);
+    }
+
+    /// Forgets the participants of the previous session.
+    pub fn clear(&mut self) {
+        self.participants.clear();
+    }
+
+    /// Returns what the participant `sid` supports, `None` for an unknown participant.
+    pub fn get(&self, sid: &str) -> Option<&PeerCapabilities> {
+        self.participants.get(sid)
+    }
+
+    /// Returns `true` if `feature` can be used in the session, the sharer
+    /// and every participant have to handle it.
+    pub fn enabled(&self, feature: Feature) -> bool {
+        SHARER_FEATURES.contains(&feature) && self.supported_by_all(feature)
+    }
+
+    /// Returns `true` if every participant announced `feature`.
+    fn supported_by_all(&self, feature: Feature) -> bool {
+        self.participants
+            .values()
+            .all(|capabilities| capabilities.features.contains(&feature))
+    }
+
+    /// Returns the lowest protocol version in the session, `None` without participants.
+    pub fn min_version(&self) -> Option<u32> {
+        self.participants
+            .values()
+            .map(|capabilities| capabilities.version)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_announcement() {
+        let payload =
+            br#"{"compression":[],"version":2,"features":["chat","pen_input","holograms"]}"#;
+        let data: CapabilitiesData = serde_json::from_slice(payload).unwrap();
+        let capabilities = PeerCapabilities::from(&data);
+        assert_eq!(capabilities.version, 2);
+        assert_eq!(
+            capabilities.features,
+            HashSet::from([Feature::Chat, Feature::PenInput])
+        );
+
+        /* Clients older than the handshake fields. */
+        let data: CapabilitiesData = serde_json::from_slice(br#"{"compression":[]}"#).unwrap();
+        assert_eq!(PeerCapabilities::from(&data), PeerCapabilities::default());
+    }
+
+    #[test]
+    fn test_older_client_degrades_session() {
+        let mut capabilities = ParticipantCapabilities::new();
+        assert_eq!(capabilities.min_version(), None);
+
+        capabilities.announced(
+            "a".to_string(),
+            PeerCapabilities {
+                version: PROTOCOL_VERSION,
+                features: HashSet::from([Feature::Chat]),
+            },
+        );
+        /* An announcement that arrived first isn't reset by the join. */
+        capabilities.joined("a");
+        assert!(capabilities.supported_by_all(Feature::Chat));
+        assert!(!capabilities.supported_by_all(Feature::Annotations));
+
+        capabilities.joined("old");
+        assert!(!capabilities.supported_by_all(Feature::Chat));
+        assert_eq!(capabilities.min_version(), Some(0));
+        assert_eq!(capabilities.get("old"), Some(&PeerCapabilities::default()));
+
+        capabilities.left("old");
+        assert!(capabilities.supported_by_all(Feature::Chat));
+        assert_eq!(capabilities.min_version(), Some(PROTOCOL_VERSION));
+        assert_eq!(capabilities.get("old"), None);
+    }
+}
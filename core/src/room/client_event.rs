@@ -0,0 +1,862 @@
+//! Wire format of the data packets exchanged between room participants.
+//!
+//! Every packet is a `ClientEvent` serialized as JSON, compressed when it is
+//! bulky, see `compression`. The format doesn't depend on the transport
+//! that carries the packets, `SessionTransport` implementations publish and
+//! decode the same events.
+
+use serde::{Deserialize, Serialize};
+use socket_lib::{ContentType, MediaControlAction, ShareInterruption, WaitReason};
+
+use crate::capture::capturer::ColorMatrix;
+use crate::input::cursor_shape::CursorShape;
+use crate::input::keys::parse_keys;
+use crate::UserEvent;
+
+use super::capabilities::Feature;
+use super::clock_sync::{session_time_ms, ClockSyncRequest};
+use super::compression;
+use super::input_auth::InputAuth;
+
+pub const TOPIC_SHARER_LOCATION: &str = "participant_location";
+pub const TOPIC_REMOTE_CONTROL_ENABLED: &str = "remote_control_enabled";
+pub const TOPIC_TICK_RESPONSE: &str = "tick_response";
+pub const TOPIC_SHARE_INTERRUPTION: &str = "share_interruption";
+pub const TOPIC_ADMISSION: &str = "admission";
+pub const TOPIC_CLOCK_SYNC: &str = "clock_sync";
+pub const TOPIC_SESSION_MODE: &str = "session_mode";
+pub const TOPIC_PARTICIPANT_REMOVED: &str = "participant_removed";
+pub const TOPIC_SHARER_CURSOR_SHAPE: &str = "sharer_cursor_shape";
+pub const TOPIC_CAPABILITIES: &str = "capabilities";
+pub const TOPIC_INPUT_GRANT: &str = "input_grant";
+pub const TOPIC_CLIPBOARD: &str = "clipboard";
+pub const TOPIC_UPGRADE_REQUIRED: &str = "upgrade_required";
+pub const TOPIC_SHARE_CONFIG: &str = "share_config";
+
+/// Represents a 2D point with floating-point coordinates.
+///
+/// This structure is used to represent cursor positions, mouse coordinates,
+/// and other 2D locations within the room service.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientPoint {
+    /// The x-coordinate of the point
+    pub x: f64,
+    /// The y-coordinate of the point
+    pub y: f64,
+    /// Increasing number of the sender's mouse moves, older clients don't send it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+}
+
+/// Contains data for mouse click events.
+///
+/// This structure captures all the information needed to represent a mouse click,
+/// including position, button information, modifier keys, and click state.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MouseClickData {
+    /// The x-coordinate where the click occurred
+    pub x: f64,
+    /// The y-coordinate where the click occurred
+    pub y: f64,
+    /// The mouse button that was clicked (0=left, 1=right, 2=middle)
+    pub button: u32,
+    /// The number of clicks (1=single, 2=double, etc.)
+    pub clicks: u32,
+    /// Whether the button is being pressed down (true) or released (false)
+    pub down: bool,
+    /// Whether the Shift key was held during the click
+    pub shift: bool,
+    /// Whether the Meta/Cmd key was held during the click
+    pub meta: bool,
+    /// Whether the Ctrl key was held during the click
+    pub ctrl: bool,
+    /// Whether the Alt key was held during the click
+    pub alt: bool,
+}
+
+/// Contains data for mouse visibility events.
+///
+/// This structure is used to communicate whether the mouse cursor should be
+/// visible or hidden on remote clients.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MouseVisibleData {
+    /// Whether the mouse cursor should be visible
+    pub visible: bool,
+}
+
+/// Contains data for mouse wheel scroll events.
+///
+/// This structure represents the scroll delta values for both horizontal
+/// and vertical scrolling directions.
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct WheelDelta {
+    /// The horizontal scroll delta (positive = right, negative = left)
+    pub deltaX: f64,
+    /// The vertical scroll delta (positive = down, negative = up)
+    pub deltaY: f64,
+}
+
+/// Contains data for keyboard input events.
+///
+/// This structure captures keyboard input including the keys pressed
+/// and any modifier keys that were held during the keystroke.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeystrokeData {
+    /// The key(s) that were pressed (as string representations)
+    pub key: Vec<String>,
+    /// Whether the Meta/Cmd key was held during the keystroke
+    pub meta: bool,
+    /// Whether the Ctrl key was held during the keystroke
+    pub ctrl: bool,
+    /// Whether the Shift key was held during the keystroke
+    pub shift: bool,
+    /// Whether the Alt key was held during the keystroke
+    pub alt: bool,
+    /// Whether the key is being pressed down (true) or released (false)
+    pub down: bool,
+}
+
+/// Contains timing data for tick events.
+///
+/// This structure is used for synchronization and latency measurement
+/// between room participants.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TickData {
+    /// The timestamp value (typically in nanoseconds)
+    pub time: u128,
+}
+
+/// Contains a participant's request to sync its clock to the session clock.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClockSyncRequestData {
+    /// When the request was sent, on the participant's clock in milliseconds
+    pub t0: u64,
+}
+
+/// Contains the sharer's answer to a clock sync request.
+///
+/// The participant notes when the answer arrives, with the four timestamps
+/// it estimates the offset of its clock to the session clock.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClockSyncResponseData {
+    /// The participant that sent the request
+    pub sid: String,
+    /// When the request was sent, copied from the request
+    pub t0: u64,
+    /// When the request arrived, on the session clock in milliseconds
+    pub t1: u64,
+    /// When the answer was sent, on the session clock in milliseconds
+    pub t2: u64,
+}
+
+/// Contains the remote control enabled/disabled state.
+///
+/// This structure is used to communicate whether remote control
+/// functionality is currently enabled in the room.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoteControlEnabled {
+    /// Whether remote control is currently enabled
+    pub enabled: bool,
+}
+
+/// Contains a call control requested by a controller.
+///
+/// The sharer's UI can be hidden behind the shared content, this lets
+/// controllers reach the sharer's call controls.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MediaControlData {
+    /// The control to apply on the sharer's call
+    pub action: MediaControlAction,
+}
+
+/// Contains whether a participant raised or lowered its hand to get control.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RaiseHandData {
+    pub raised: bool,
+}
+
+/// Contains whether a controller in push-to-control mode holds its modifier.
+///
+/// The sharer only injects the controller's input while it is held.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ControlHoldData {
+    pub active: bool,
+}
+
+/// Contains the reason the shared display isn't showing the sharer's content.
+///
+/// Without it controllers only see a frozen or black frame while the
+/// sharer's display sleeps or is locked.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareInterruptedData {
+    /// Why the shared display is interrupted
+    pub reason: ShareInterruption,
+}
+
+/// Contains the admission of a participant to the session.
+///
+/// Participants that aren't admitted see the shared screen, but their input
+/// is ignored until the sharer admits them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdmissionData {
+    /// The participant the admission is for
+    pub sid: String,
+    /// Why the participant has to wait, `None` once it is admitted
+    pub waiting: Option<WaitReason>,
+}
+
+/// Contains the mode of the screen share session.
+///
+/// In a view-only session the sharer's core never created the keyboard and
+/// mouse injection, controllers can't take control for the whole session.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionModeData {
+    /// Whether the controllers' input is never simulated
+    pub view_only: bool,
+}
+
+/// Contains the configuration of the screen share.
+///
+/// The sharer sends it whenever the configuration changes and again when a
+/// participant is added, so late joiners know the stream's properties
+/// without waiting for the first frames.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShareConfigData {
+    /// Size of the published video in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Frames per second the share is captured at
+    pub framerate: u32,
+    /// What is shared, `None` for a test pattern
+    pub content_type: Option<ContentType>,
+    /// Whether no frames are published because nobody watches the share
+    pub paused: bool,
+    /// Whether the controllers' input is never simulated
+    pub view_only: bool,
+    /// The YUV matrix of the published video, the viewers decode with it
+    pub color_matrix: ColorMatrix,
+}
+
+/// Contains the participant the sharer removed from the session.
+///
+/// The sharer's core ignores the participant's events from then on, the
+/// participant's client is expected to leave the call when it gets this.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParticipantRemovedData {
+    /// The removed participant
+    pub sid: String,
+    /// Whether the participant's identity is banned until the session ends
+    pub banned: bool,
+}
+
+/// Contains the shape of the sharer's system cursor.
+///
+/// The shared frames don't have the cursor, the controllers use the shape
+/// for their own cursor over the video.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharerCursorShapeData {
+    /// The shape the sharer's cursor changed to
+    pub shape: CursorShape,
+}
+
+/// The data-channel features a participant supports.
+///
+/// The sharer sends its own when a participant is added and the controllers
+/// answer with theirs, see `compression::DataCompression` and
+/// `capabilities::ParticipantCapabilities`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapabilitiesData {
+    /// Compressions the participant inflates, `"deflate"` for now
+    #[serde(default)]
+    pub compression: Vec<String>,
+    /// Version of the data-channel protocol, 0 for clients older than the field
+    #[serde(default)]
+    pub version: u32,
+    /// Optional features the participant handles
+    #[serde(default)]
+    pub features: Vec<Feature>,
+}
+
+/// Contains the token a controller stamps its input events with.
+///
+/// Only sent to the controller's identity, see `input_auth`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputGrantData {
+    /// The token of the controller's identity, `None` when its input was revoked
+    pub token: Option<String>,
+}
+
+/// Asks a controller to upgrade, its input is dropped until it does.
+///
+/// Only sent to the controller's identity, once per participant, see
+/// `input_auth::InputAuthorization::check_version`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpgradeRequiredData {
+    /// The oldest protocol version the sharer accepts input from
+    pub min_version: u32,
+}
+
+/// Contains the clipboard text of the sender.
+///
+/// The sharer sends it when text is copied on its machine, a controller when
+/// its window gets the focus, see `clipboard::ClipboardSync`. The controllers
+/// only send theirs to the sharer after it shared its clipboard with them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClipboardUpdateData {
+    pub text: String,
+}
+
+/// Represents all possible client events that can be sent between room participants.
+///
+/// This enum defines the different types of events that can be transmitted through
+/// the session's data channel, including input events, cursor movements, and control messages.
+/// Events are serialized as JSON with a `type` field and `payload` field containing
+/// the event-specific data.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload")]
+pub enum ClientEvent {
+    /// Mouse cursor movement event from a remote controller
+    MouseMove(ClientPoint),
+    /// Mouse click event from a remote controller
+    MouseClick(MouseClickData),
+    /// Mouse visibility change event
+    MouseVisible(MouseVisibleData),
+    /// Keyboard input event from a remote controller
+    Keystroke(KeystrokeData),
+    /// Mouse wheel scroll event from a remote controller
+    WheelEvent(WheelDelta),
+    /// Timing synchronization request
+    Tick(TickData),
+    /// Response to a timing synchronization request
+    TickResponse(TickData),
+    /// Request to estimate the sender's offset to the session clock
+    ClockSyncRequest(ClockSyncRequestData),
+    /// The session clock's timestamps for a clock sync request
+    ClockSyncResponse(ClockSyncResponseData),
+    /// Remote control enabled/disabled status change
+    RemoteControlEnabled(RemoteControlEnabled),
+    /// Call control (mute, camera) requested by a controller
+    MediaControl(MediaControlData),
+    /// The sharer's shared display slept, locked or the screensaver started
+    ShareInterrupted(ShareInterruptedData),
+    /// The sharer's shared display shows the sharer's content again
+    ShareResumed,
+    /// A participant has to wait or was admitted to the session
+    Admission(AdmissionData),
+    /// The mode of the session, sent when a participant is added
+    SessionMode(SessionModeData),
+    /// A participant was kicked or banned by the sharer
+    ParticipantRemoved(ParticipantRemovedData),
+    /// The sharer's system cursor changed its shape
+    SharerCursorShape(SharerCursorShapeData),
+    /// The data-channel features the sender supports
+    Capabilities(CapabilitiesData),
+    /// The sharer allowed or revoked the input of the receiving controller
+    InputGrant(InputGrantData),
+    /// A participant asks the sharer for control, or withdraws its request
+    RaiseHand(RaiseHandData),
+    /// A controller in push-to-control mode pressed or released its modifier
+    ControlHold(ControlHoldData),
+    /// The sender's clipboard text, only while the sharer has clipboard sharing on
+    ClipboardUpdate(ClipboardUpdateData),
+    /// The receiving controller is older than the sharer accepts input from
+    UpgradeRequired(UpgradeRequiredData),
+    /// The configuration of the screen share, sent when it changes and when a participant is added
+    ShareConfig(ShareConfigData),
+}
+
+impl ClientEvent {
+    /// Serializes the event to the JSON payload sent over the data channel.
+    pub fn encode(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec(self)
+    }
+
+    /// Serializes the event and compresses the payload when it is bulky,
+    /// only when every participant can inflate it.
+    pub fn encode_compressed(&self) -> Result<Vec<u8>, serde_json::Error> {
+        Ok(compression::compress(self.encode()?))
+    }
+
+    /// Deserializes an event from a data channel payload, compressed or not.
+    pub fn decode(payload: &[u8]) -> Result<Self, serde_json::Error> {
+        let payload = compression::decompress(payload).map_err(serde_json::Error::io)?;
+        serde_json::from_slice(&payload)
+    }
+}
+
+/// A `ClientEvent` data packet with the authentication controllers add to
+/// their input events, see `room::input_auth`.
+#[derive(Debug, Deserialize)]
+pub struct ClientPacket {
+    #[serde(flatten)]
+    pub event: ClientEvent,
+    /// A malformed authentication is treated as a missing one
+    #[serde(default, deserialize_with = "lenient_auth")]
+    pub auth: Option<InputAuth>,
+}
+
+fn lenient_auth<'de, D>(deserializer: D) -> Result<Option<InputAuth>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value.and_then(|value| serde_json::from_value(value).ok()))
+}
+
+/// Translates a client event sent by the participant `sid` to a `UserEvent`.
+///
+/// # Returns
+///
+/// * `Some(UserEvent)` - The event that needs to be handled by the event loop
+/// * `None` - The event isn't handled by the sharer
+pub fn client_event_to_user_event(client_event: ClientEvent, sid: String) -> Option<UserEvent> {
+    match client_event {
+        ClientEvent::MouseMove(point) => Some(UserEvent::CursorPosition(
+            point.x as f32,
+            point.y as f32,
+            sid,
+        )),
+        ClientEvent::MouseClick(click) => Some(UserEvent::MouseClick(
+            crate::MouseClickData {
+                x: click.x as f32,
+                y: click.y as f32,
+                button: click.button,
+                clicks: click.clicks as f32,
+                down: click.down,
+                shift: click.shift,
+                meta: click.meta,
+                ctrl: click.ctrl,
+                alt: click.alt,
+            },
+            sid,
+        )),
+        ClientEvent::MouseVisible(visible_data) => Some(UserEvent::ControllerCursorVisible(
+            visible_data.visible,
+            sid,
+        )),
+        ClientEvent::Keystroke(key) => {
+            let keys = match parse_keys(&key.key) {
+                Ok(keys) => keys,
+                Err(e) => {
+                    log::warn!("client_event_to_user_event: Invalid keystroke: {e}");
+                    return None;
+                }
+            };
+            if keys.is_empty() {
+                log::warn!("client_event_to_user_event: Keystroke without valid keys");
+                return None;
+            }
+            Some(UserEvent::Keystroke(
+                crate::KeystrokeData {
+                    keys,
+                    meta: key.meta,
+                    ctrl: key.ctrl,
+                    shift: key.shift,
+                    alt: key.alt,
+                    down: key.down,
+                },
+                sid,
+            ))
+        }
+        ClientEvent::WheelEvent(wheel_data) => Some(UserEvent::Scroll(
+            crate::ScrollDelta {
+                x: wheel_data.deltaX,
+                y: wheel_data.deltaY,
+            },
+            sid,
+        )),
+        ClientEvent::Tick(tick_data) => {
+            if cfg!(debug_assertions) {
+                Some(UserEvent::Tick(tick_data.time))
+            } else {
+                None
+            }
+        }
+        ClientEvent::ClockSyncRequest(request) => Some(UserEvent::ClockSync(ClockSyncRequest {
+            sid,
+            sent: request.t0,
+            /* Stamped on arrival, the event loop's queue would count as network delay. */
+            received: session_time_ms(),
+        })),
+        ClientEvent::MediaControl(media_control) => {
+            Some(UserEvent::MediaControl(media_control.action, sid))
+        }
+        ClientEvent::RaiseHand(raise_hand) => Some(UserEvent::RaiseHand(raise_hand.raised, sid)),
+        ClientEvent::ControlHold(hold) => Some(UserEvent::ControlHold(hold.active, sid)),
+        ClientEvent::ClipboardUpdate(clipboard) => {
+            Some(UserEvent::ClipboardUpdate(clipboard.text, sid))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::keys::Key;
+
+    #[test]
+    fn test_decode_mouse_move() {
+        let payload = br#"{"type":"MouseMove","payload":{"x":0.25,"y":0.5}}"#;
+        let event = ClientEvent::decode(payload).unwrap();
+        match client_event_to_user_event(event, "sid".to_string()) {
+            Some(UserEvent::CursorPosition(x, y, sid)) => {
+                assert_eq!(x, 0.25);
+                assert_eq!(y, 0.5);
+                assert_eq!(sid, "sid");
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_mouse_move_seq() {
+        let payload = br#"{"type":"MouseMove","payload":{"x":0.25,"y":0.5,"seq":7}}"#;
+        match serde_json::from_slice::<ClientPacket>(payload)
+            .unwrap()
+            .event
+        {
+            ClientEvent::MouseMove(point) => assert_eq!(point.seq, Some(7)),
+            other => panic!("unexpected event {other:?}"),
+        }
+        let payload = br#"{"type":"MouseMove","payload":{"x":0.25,"y":0.5}}"#;
+        match serde_json::from_slice::<ClientPacket>(payload)
+            .unwrap()
+            .event
+        {
+            ClientEvent::MouseMove(point) => assert_eq!(point.seq, None),
+            other => panic!("unexpected event {other:?}"),
+        }
+
+        let event = ClientEvent::MouseMove(ClientPoint {
+            x: 0.5,
+            y: 0.5,
+            seq: None,
+        });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert!(value["payload"].get("seq").is_none());
+    }
+
+    #[test]
+    fn test_client_packet_auth() {
+        let key = r#""payload":{"key":["a"],"meta":false,"ctrl":false,"shift":false,"alt":false,"down":true}"#;
+        let packet: ClientPacket = serde_json::from_str(&format!(
+            r#"{{"type":"Keystroke",{key},"auth":{{"token":"ab","seq":7}}}}"#
+        ))
+        .unwrap();
+        assert!(matches!(packet.event, ClientEvent::Keystroke(_)));
+        assert_eq!(
+            packet.auth,
+            Some(InputAuth {
+                token: "ab".to_string(),
+                seq: 7
+            })
+        );
+
+        let packet: ClientPacket =
+            serde_json::from_str(&format!(r#"{{"type":"Keystroke",{key}}}"#)).unwrap();
+        assert_eq!(packet.auth, None);
+        let packet: ClientPacket = serde_json::from_str(&format!(
+            r#"{{"type":"Keystroke",{key},"auth":{{"token":"ab"}}}}"#
+        ))
+        .unwrap();
+        assert_eq!(packet.auth, None);
+        assert!(serde_json::from_slice::<ClientPacket>(b"garbage").is_err());
+    }
+
+    #[test]
+    fn test_clock_sync_request() {
+        let before = session_time_ms();
+        let payload = br#"{"type":"ClockSyncRequest","payload":{"t0":1234}}"#;
+        let event = ClientEvent::decode(payload).unwrap();
+        match client_event_to_user_event(event, "sid".to_string()) {
+            Some(UserEvent::ClockSync(request)) => {
+                assert_eq!(request.sid, "sid");
+                assert_eq!(request.sent, 1234);
+                assert!(request.received >= before);
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+
+        let event = ClientEvent::ClockSyncResponse(ClockSyncResponseData {
+            sid: "sid".to_string(),
+            t0: 1,
+            t1: 2,
+            t2: 3,
+        });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "ClockSyncResponse");
+        assert_eq!(value["payload"]["t2"], 3);
+    }
+
+    #[test]
+    fn test_admission_encoding() {
+        let event = ClientEvent::Admission(AdmissionData {
+            sid: "sid".to_string(),
+            waiting: Some(WaitReason::RoomFull),
+        });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "Admission");
+        assert_eq!(value["payload"]["sid"], "sid");
+        assert_eq!(value["payload"]["waiting"], "RoomFull");
+        /* Only the sharer sends admissions. */
+        let event = ClientEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(client_event_to_user_event(event, "sid".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_session_mode_encoding() {
+        let event = ClientEvent::SessionMode(SessionModeData { view_only: true });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "SessionMode");
+        assert_eq!(value["payload"]["view_only"], true);
+        /* Only the sharer sends the session mode. */
+        let event = ClientEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(client_event_to_user_event(event, "sid".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_participant_removed_encoding() {
+        let event = ClientEvent::ParticipantRemoved(ParticipantRemovedData {
+            sid: "sid".to_string(),
+            banned: true,
+        });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "ParticipantRemoved");
+        assert_eq!(value["payload"]["sid"], "sid");
+        assert_eq!(value["payload"]["banned"], true);
+        /* Only the sharer removes participants. */
+        let event = ClientEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(client_event_to_user_event(event, "sid".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_sharer_cursor_shape_encoding() {
+        let event = ClientEvent::SharerCursorShape(SharerCursorShapeData {
+            shape: CursorShape::IBeam,
+        });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "SharerCursorShape");
+        assert_eq!(value["payload"]["shape"], "IBeam");
+        /* Only the sharer sends its cursor shape. */
+        let event = ClientEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(client_event_to_user_event(event, "sid".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_capabilities_and_compressed_decoding() {
+        let event = ClientEvent::Capabilities(CapabilitiesData {
+            compression: vec![compression::COMPRESSION_DEFLATE.to_string()],
+            version: 1,
+            features: vec![Feature::Chat],
+        });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "Capabilities");
+        assert_eq!(value["payload"]["compression"][0], "deflate");
+        assert_eq!(value["payload"]["features"][0], "chat");
+        let payload = br#"{"type":"Capabilities","payload":{}}"#;
+        match ClientEvent::decode(payload).unwrap() {
+            ClientEvent::Capabilities(capabilities) => assert!(capabilities.compression.is_empty()),
+            other => panic!("unexpected event {other:?}"),
+        }
+
+        /* A bulky keystroke goes through compressed and decodes transparently. */
+        let event = ClientEvent::Keystroke(KeystrokeData {
+            key: vec!["a".to_string(); 400],
+            meta: false,
+            ctrl: false,
+            shift: false,
+            alt: false,
+            down: true,
+        });
+        let payload = event.encode_compressed().unwrap();
+        assert_eq!(payload[0], compression::COMPRESSED_PAYLOAD_FLAG);
+        assert!(payload.len() < event.encode().unwrap().len());
+        match ClientEvent::decode(&payload).unwrap() {
+            ClientEvent::Keystroke(key) => assert_eq!(key.key.len(), 400),
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_input_grant_encoding() {
+        let event = ClientEvent::InputGrant(InputGrantData {
+            token: Some("ab".to_string()),
+        });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "InputGrant");
+        assert_eq!(value["payload"]["token"], "ab");
+        /* Only the sharer grants input. */
+        let event = ClientEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(client_event_to_user_event(event, "sid".to_string()).is_none());
+
+        /* The authentication next to the event doesn't get in the way of decoding it. */
+        let payload = br#"{"type":"WheelEvent","payload":{"deltaX":0,"deltaY":1},"auth":{"token":"ab","seq":1}}"#;
+        let event = ClientEvent::decode(payload).unwrap();
+        assert!(matches!(
+            client_event_to_user_event(event, "sid".to_string()),
+            Some(UserEvent::Scroll(..))
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_required_encoding() {
+        let event = ClientEvent::UpgradeRequired(UpgradeRequiredData { min_version: 2 });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "UpgradeRequired");
+        assert_eq!(value["payload"]["min_version"], 2);
+    }
+
+    #[test]
+    fn test_share_config_encoding() {
+        let config = ShareConfigData {
+            width: 1920,
+            height: 1080,
+            framerate: 30,
+            content_type: Some(ContentType::Window { display_id: 1 }),
+            paused: false,
+            view_only: true,
+            color_matrix: ColorMatrix::Bt709,
+        };
+        let event = ClientEvent::ShareConfig(config.clone());
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "ShareConfig");
+        assert_eq!(value["payload"]["width"], 1920);
+        assert_eq!(value["payload"]["content_type"]["Window"]["display_id"], 1);
+        assert_eq!(value["payload"]["view_only"], true);
+        assert_eq!(value["payload"]["color_matrix"], "bt709");
+        /* Only the sharer sends the share configuration. */
+        let event = ClientEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(matches!(&event, ClientEvent::ShareConfig(decoded) if *decoded == config));
+        assert!(client_event_to_user_event(event, "sid".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_encode_uses_type_and_payload() {
+        let event = ClientEvent::RemoteControlEnabled(RemoteControlEnabled { enabled: true });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "RemoteControlEnabled");
+        assert_eq!(value["payload"]["enabled"], true);
+    }
+
+    #[test]
+    fn test_encode_share_interruption() {
+        let event = ClientEvent::ShareInterrupted(ShareInterruptedData {
+            reason: ShareInterruption::ScreenLocked,
+        });
+        let value: serde_json::Value = serde_json::from_slice(&event.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "ShareInterrupted");
+        assert_eq!(value["payload"]["reason"], "ScreenLocked");
+
+        let value: serde_json::Value =
+            serde_json::from_slice(&ClientEvent::ShareResumed.encode().unwrap()).unwrap();
+        assert_eq!(value["type"], "ShareResumed");
+    }
+
+    #[test]
+    fn test_decode_media_control() {
+        let payload = br#"{"type":"MediaControl","payload":{"action":"MuteMic"}}"#;
+        let event = ClientEvent::decode(payload).unwrap();
+        match client_event_to_user_event(event, "sid".to_string()) {
+            Some(UserEvent::MediaControl(action, sid)) => {
+                assert_eq!(action, MediaControlAction::MuteMic);
+                assert_eq!(sid, "sid");
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_decode_raise_hand() {
+        let payload = br#"{"type":"RaiseHand","payload":{"raised":true}}"#;
+        let event = ClientEvent::decode(payload).unwrap();
+        match client_event_to_user_event(event, "sid".to_string()) {
+            Some(UserEvent::RaiseHand(raised, sid)) => {
+                assert!(raised);
+                assert_eq!(sid, "sid");
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_decode_control_hold() {
+        let payload = br#"{"type":"ControlHold","payload":{"active":false}}"#;
+        let event = ClientEvent::decode(payload).unwrap();
+        match client_event_to_user_event(event, "sid".to_string()) {
+            Some(UserEvent::ControlHold(active, sid)) => {
+                assert!(!active);
+                assert_eq!(sid, "sid");
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_decode_clipboard_update() {
+        let payload = br#"{"type":"ClipboardUpdate","payload":{"text":"let x = 1;"},"auth":{"token":"ab","seq":3}}"#;
+        let event = ClientEvent::decode(payload).unwrap();
+        match client_event_to_user_event(event, "sid".to_string()) {
+            Some(UserEvent::ClipboardUpdate(text, sid)) => {
+                assert_eq!(text, "let x = 1;");
+                assert_eq!(sid, "sid");
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_empty_keystroke_is_dropped() {
+        let event = ClientEvent::Keystroke(KeystrokeData {
+            key: vec![],
+            meta: false,
+            ctrl: false,
+            shift: false,
+            alt: false,
+            down: true,
+        });
+        assert!(client_event_to_user_event(event, "sid".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_decode_keystroke_with_several_keys() {
+        let payload = r#"{"type":"Keystroke","payload":{"key":["Shift","ArrowLeft","ж","F13"],"meta":false,"ctrl":false,"shift":true,"alt":false,"down":true}}"#;
+        let event = ClientEvent::decode(payload.as_bytes()).unwrap();
+        match client_event_to_user_event(event, "sid".to_string()) {
+            Some(UserEvent::Keystroke(data, sid)) => {
+                assert_eq!(sid, "sid");
+                assert_eq!(
+                    data.keys,
+                    vec![
+                        Key::Named("Shift"),
+                        Key::Named("ArrowLeft"),
+                        Key::Character("ж".to_string())
+                    ]
+                );
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_malformed_keystrokes_are_dropped() {
+        let payloads = [
+            r#"{"type":"Keystroke","payload":{"key":"a","meta":false,"ctrl":false,"shift":false,"alt":false,"down":true}}"#,
+            r#"{"type":"Keystroke","payload":{"key":[1],"meta":false,"ctrl":false,"shift":false,"alt":false,"down":true}}"#,
+            r#"{"type":"Keystroke","payload":{"key":["a"]}}"#,
+            r#"{"type":"Keystroke","payload":{"key":["\u0000"],"meta":false,"ctrl":false,"shift":false,"alt":false,"down":true}}"#,
+            r#"{"type":"Keystroke","payload":{"key":["Home","","F1"],"meta":false,"ctrl":false,"shift":false,"alt":false,"down":true}}"#,
+            r#"{"type":"Keystroke","payload":{"key":["a","b","c","d","e","f","g","h","i"],"meta":false,"ctrl":false,"shift":false,"alt":false,"down":true}}"#,
+            r#"{"type":"Keystroke","payload":{"key":["aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"],"meta":false,"ctrl":false,"shift":false,"alt":false,"down":true}}"#,
+            r#"{"type":"Keystroke","payload":null}"#,
+            r#"{"type":"Keystroke"#,
+        ];
+        for payload in payloads {
+            let event = ClientEvent::decode(payload.as_bytes())
+                .ok()
+                .and_then(|event| client_event_to_user_event(event, "sid".to_string()));
+            assert!(event.is_none(), "{payload} was accepted");
+        }
+    }
+}
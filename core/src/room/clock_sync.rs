@@ -0,0 +1,208 @@
+//! Session timeline shared by the participants.
+//!
+//! The sharer's core keeps the session clock, the other participants
+//! estimate their offset to it NTP style over the data channel: a participant
+//! sends a `ClockSyncRequest` stamped with its clock (`t0`), the core stamps
+//! when the request arrived (`t1`) and when it answered (`t2`) on the session
+//! clock, and the participant notes when the answer arrived (`t3`). Features
+//! that need a common timeline (recording markers, annotation timestamps,
+//! latency display) convert their local times with the estimated offset.
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Number of recent samples the offset is estimated from.
+pub const CLOCK_SYNC_SAMPLES: usize = 8;
+
+/// Returns the current time on the session clock, in milliseconds.
+///
+/// The session clock is the sharer's monotonic clock expressed as unix time,
+/// so it keeps a consistent timeline when the wall clock is adjusted.
+pub fn session_time_ms() -> u64 {
+    static ORIGIN: OnceLock<(Instant, u64)> = OnceLock::new();
+    let (origin, origin_ms) = ORIGIN.get_or_init(|| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        (Instant::now(), now)
+    });
+    origin_ms + origin.elapsed().as_millis() as u64
+}
+
+/// A clock sync request received from the participant `sid`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockSyncRequest {
+    pub sid: String,
+    /// When the participant sent the request, on its clock
+    pub sent: u64,
+    /// When the request arrived, on the session clock
+    pub received: u64,
+}
+
+/// The four timestamps of a clock sync exchange, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    /// Request sent, local clock
+    pub t0: u64,
+    /// Request received, session clock
+    pub t1: u64,
+    /// Response sent, session clock
+    pub t2: u64,
+    /// Response received, local clock
+    pub t3: u64,
+}
+
+impl ClockSample {
+    /// Returns what needs to be added to the local clock to get the session clock.
+    pub fn offset(&self) -> i64 {
+        let (t0, t1, t2, t3) = (
+            self.t0 as i64,
+            self.t1 as i64,
+            self.t2 as i64,
+            self.t3 as i64,
+        );
+        ((t1 - t0) + (t2 - t3)) / 2
+    }
+
+    /// Returns the network round trip time of the exchange.
+    pub fn round_trip(&self) -> u64 {
+        self.t3
+            .saturating_sub(self.t0)
+            .saturating_sub(self.t2.saturating_sub(self.t1))
+    }
+
+    /// Returns `false` for samples with timestamps going backwards.
+    fn is_valid(&self) -> bool {
+        self.t3 >= self.t0 && self.t2 >= self.t1
+    }
+}
+
+/// Estimates the offset of the local clock to the session clock.
+///
+/// Queueing delays make most round trips asymmetric, like NTP the sample
+/// with the shortest round trip of the recent ones is trusted.
+#[derive(Debug, Default)]
+pub struct ClockEstimator {
+    samples: VecDeque<ClockSample>,
+}
+
+impl ClockEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the sample of a completed exchange.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the sample was dropped because its timestamps are inconsistent.
+    pub fn add(&mut self, sample: ClockSample) -> bool {
+        if !sample.is_valid() {
+            log::warn!("ClockEstimator::add: Dropping invalid sample {sample:?}");
+            return false;
+        }
+        if self.samples.len() == CLOCK_SYNC_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        true
+    }
+
+    fn best_sample(&self) -> Option<&ClockSample> {
+        self.samples.iter().min_by_key(|sample| sample.round_trip())
+    }
+
+    /// Returns the estimated offset, `None` before the first sample.
+    pub fn offset(&self) -> Option<i64> {
+        self.best_sample().map(ClockSample::offset)
+    }
+
+    /// Returns the round trip of the sample the offset is estimated from.
+    pub fn round_trip(&self) -> Option<u64> {
+        self.best_sample().map(ClockSample::round_trip)
+    }
+
+    /// Converts a local time to the session timeline.
+    pub fn to_session_time(&self, local_ms: u64) -> Option<u64> {
+        self.offset()
+            .map(|offset| local_ms.saturating_add_signed(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simulates an exchange with a local clock `offset` behind the session clock.
+    fn exchange(t0: u64, offset: u64, up: u64, processing: u64, down: u64) -> ClockSample {
+        let t1 = t0 + offset + up;
+        let t2 = t1 + processing;
+        ClockSample {
+            t0,
+            t1,
+            t2,
+            t3: t2 - offset + down,
+        }
+    }
+
+    #[test]
+    fn test_sample_offset() {
+        let sample = exchange(1_000, 500, 20, 5, 20);
+        assert_eq!(sample.offset(), 500);
+        assert_eq!(sample.round_trip(), 40);
+
+        /* The local clock can be ahead of the session clock. */
+        let sample = ClockSample {
+            t0: 1_000,
+            t1: 610,
+            t2: 615,
+            t3: 1_025,
+        };
+        assert_eq!(sample.offset(), -400);
+        assert_eq!(sample.round_trip(), 20);
+    }
+
+    #[test]
+    fn test_estimator_prefers_shortest_round_trip() {
+        let mut estimator = ClockEstimator::new();
+        assert_eq!(estimator.offset(), None);
+
+        /* A packet stuck in a queue on the way up skews its sample. */
+        assert!(estimator.add(exchange(1_000, 500, 200, 5, 10)));
+        assert!(estimator.add(exchange(2_000, 500, 10, 5, 10)));
+        assert!(estimator.add(exchange(3_000, 500, 10, 5, 150)));
+        assert_eq!(estimator.offset(), Some(500));
+        assert_eq!(estimator.round_trip(), Some(20));
+        assert_eq!(estimator.to_session_time(10_000), Some(10_500));
+    }
+
+    #[test]
+    fn test_estimator_keeps_recent_samples() {
+        let mut estimator = ClockEstimator::new();
+        estimator.add(exchange(0, 100, 1, 0, 1));
+        for i in 1..=CLOCK_SYNC_SAMPLES as u64 {
+            estimator.add(exchange(i * 1_000, 300, 10, 0, 10));
+        }
+        /* The old sample had the shortest round trip but is gone. */
+        assert_eq!(estimator.offset(), Some(300));
+
+        let invalid = ClockSample {
+            t0: 10,
+            t1: 5,
+            t2: 4,
+            t3: 9,
+        };
+        assert!(!estimator.add(invalid));
+        assert_eq!(estimator.offset(), Some(300));
+    }
+
+    #[test]
+    fn test_session_time_is_monotonic() {
+        let first = session_time_ms();
+        let second = session_time_ms();
+        assert!(second >= first);
+        assert!(first > 0);
+    }
+}
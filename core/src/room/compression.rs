@@ -0,0 +1,171 @@
+//! Compression of bulky data-channel payloads.
+//!
+//! Annotation strokes, chat backlogs and window lists can outgrow a
+//! comfortable data packet. Payloads above `COMPRESSION_THRESHOLD` are
+//! deflated and prefixed with `COMPRESSED_PAYLOAD_FLAG`, a byte a JSON payload
+//! never starts with, so uncompressed packets keep their format.
+//!
+//! Packets are broadcast to the whole room, so the sharer only compresses
+//! once every participant announced that it inflates them with a
+//! `ClientEvent::Capabilities` packet. Participants that never announce, like
+//! older clients, keep the session uncompressed. Compressed payloads are
+//! always accepted.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Name of the compression in the capabilities exchange
+pub const COMPRESSION_DEFLATE: &str = "deflate";
+/// Payloads shorter than this are sent as they are
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+/// First byte of a deflated payload
+pub const COMPRESSED_PAYLOAD_FLAG: u8 = 0x01;
+/// Larger inflated payloads are rejected, a small packet can't blow up the memory
+const MAX_INFLATED_SIZE: usize = 4 * 1024 * 1024;
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// Deflates `payload` when it is above the threshold and compressing saves space.
+pub fn compress(payload: Vec<u8>) -> Vec<u8> {
+    if payload.len() < COMPRESSION_THRESHOLD {
+        return payload;
+    }
+    let deflated = miniz_oxide::deflate::compress_to_vec(&payload, COMPRESSION_LEVEL);
+    if deflated.len() + 1 >= payload.len() {
+        return payload;
+    }
+
+    let mut compressed = Vec::with_capacity(deflated.len() + 1);
+    compressed.push(COMPRESSED_PAYLOAD_FLAG);
+    compressed.extend_from_slice(&deflated);
+    compressed
+}
+
+/// Inflates a payload sent with `compress`, other payloads are returned as they are.
+pub fn decompress(payload: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    match payload.split_first() {
+        Some((&COMPRESSED_PAYLOAD_FLAG, deflated)) => {
+            miniz_oxide::inflate::decompress_to_vec_with_limit(deflated, MAX_INFLATED_SIZE)
+                .map(Cow::Owned)
+                .map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("failed to inflate payload: {:?}", e.status),
+                    )
+                })
+        }
+        _ => Ok(Cow::Borrowed(payload)),
+    }
+}
+
+/// Which participants of the session inflate compressed payloads.
+///
+/// Cloning shares the state, the transport updates it from the room events
+/// and reads it when publishing.
+#[derive(Debug, Clone, Default)]
+pub struct DataCompression {
+    /// Whether each participant in the room announced deflate support
+    participants: Arc<Mutex<HashMap<String, bool>>>,
+}
+
+impl DataCompression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracks a participant that joined, it can't inflate until it announces it.
+    pub fn joined(&self, sid: &str) {
+        self.participants
+            .lock()
+            .unwrap()
+            .entry(sid.to_string())
+            .or_insert(false);
+    }
+
+    /// Records the compressions the participant `sid` announced.
+    pub fn announced(&self, sid: &str, compression: &[String]) {
+        let deflate = compression.iter().any(|name| name == COMPRESSION_DEFLATE);
+        log::info!("DataCompression::announced: {sid} inflates: {deflate}");
+        self.participants
+            .lock()
+            .unwrap()
+            .insert(sid.to_string(), deflate);
+    }
+
+    pub fn left(&self, sid: &str) {
+        self.participants.lock().unwrap().remove(sid);
+    }
+
+    /// Forgets the participants when the room is left.
+    pub fn reset(&self) {
+        self.participants.lock().unwrap().clear();
+    }
+
+    /// Returns whether the published payloads can be compressed, every
+    /// participant in the room has to inflate them.
+    pub fn enabled(&self) -> bool {
+        let participants = self.participants.lock().unwrap();
+        !participants.is_empty() && participants.values().all(|deflate| *deflate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_round_trip() {
+        let small = br#"{"MouseMove":{"x":0.5,"y":0.5}}"#.to_vec();
+        assert_eq!(compress(small.clone()), small);
+        assert_eq!(decompress(&small).unwrap(), small.as_slice());
+
+        let bulky = format!(r#"{{"Strokes":[{}]}}"#, "[0.25,0.75],".repeat(500)).into_bytes();
+        let compressed = compress(bulky.clone());
+        assert_eq!(compressed[0], COMPRESSED_PAYLOAD_FLAG);
+        assert!(compressed.len() < bulky.len() / 4);
+        assert_eq!(decompress(&compressed).unwrap(), bulky.as_slice());
+
+        assert!(decompress(&[COMPRESSED_PAYLOAD_FLAG, 0xff, 0x00]).is_err());
+        assert!(decompress(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_incompressible_payload_is_sent_as_is() {
+        let mut state = 0x2545_f491u32;
+        let noise: Vec<u8> = (0..COMPRESSION_THRESHOLD * 2)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        assert_eq!(compress(noise.clone()), noise);
+    }
+
+    #[test]
+    fn test_negotiation() {
+        let compression = DataCompression::new();
+        assert!(!compression.enabled());
+
+        compression.joined("a");
+        assert!(!compression.enabled());
+        compression.announced("a", &[COMPRESSION_DEFLATE.to_string()]);
+        assert!(compression.enabled());
+        /* A repeated join keeps the announcement. */
+        compression.joined("a");
+        assert!(compression.enabled());
+
+        /* An older client that never announces. */
+        compression.joined("b");
+        assert!(!compression.enabled());
+        compression.left("b");
+        assert!(compression.enabled());
+
+        compression.announced("c", &["zstd".to_string()]);
+        assert!(!compression.enabled());
+        compression.reset();
+        assert!(!compression.enabled());
+    }
+}
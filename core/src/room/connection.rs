@@ -0,0 +1,90 @@
+//! Connection lifecycle of the LiveKit room.
+//!
+//! `RoomConnection` owns the connected room, replacing it on reconnects and
+//! closing it on disconnect. Other parts of the room service only borrow the
+//! room through it.
+
+use livekit::{Room, RoomEvent, RoomOptions};
+use tokio::sync::{mpsc, Mutex, MutexGuard};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectionError {
+    #[error("Failed to connect to room: {0}")]
+    Connect(String),
+}
+
+/// Holds the connection to a single room on a server.
+#[derive(Debug)]
+pub struct RoomConnection {
+    /// URL of the server rooms are joined on.
+    url: String,
+    // TODO: See if we can use a sync::Mutex instead of tokio::sync::Mutex
+    room: Mutex<Option<Room>>,
+}
+
+impl RoomConnection {
+    /// Creates a disconnected connection for the server at `url`.
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            room: Mutex::new(None),
+        }
+    }
+
+    /// Connects to a room, closing the previous room if one exists.
+    ///
+    /// The room is not stored in the connection, callers store it with
+    /// `set_room` after they finished setting it up so no one publishes
+    /// data to a half set up room.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token to use to connect to the room
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Room, UnboundedReceiver<RoomEvent>))` - The room and its events
+    /// * `Err(ConnectionError)` - The server refused the connection
+    pub async fn connect(
+        &self,
+        token: &str,
+    ) -> Result<(Room, mpsc::UnboundedReceiver<RoomEvent>), ConnectionError> {
+        self.disconnect().await;
+        Room::connect(&self.url, token, RoomOptions::default())
+            .await
+            .map_err(|e| ConnectionError::Connect(format!("{e:?}")))
+    }
+
+    /// Stores a connected room as the active one.
+    pub async fn set_room(&self, room: Room) {
+        let mut inner_room = self.room.lock().await;
+        *inner_room = Some(room);
+    }
+
+    /// Closes the active room if there is one.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a room was closed.
+    pub async fn disconnect(&self) -> bool {
+        let room = {
+            let mut inner_room = self.room.lock().await;
+            inner_room.take()
+        };
+        match room {
+            Some(room) => {
+                log::info!("RoomConnection::disconnect: closing room");
+                if let Err(e) = room.close().await {
+                    log::error!("RoomConnection::disconnect: Failed to close room: {e:?}");
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the active room, the guard is `None` when disconnected.
+    pub async fn room(&self) -> MutexGuard<'_, Option<Room>> {
+        self.room.lock().await
+    }
+}
@@ -0,0 +1,153 @@
+//! Queue of the participants asking the sharer for control.
+//!
+//! Participants raise their hand with `ClientEvent::RaiseHand`, the requests
+//! are kept in the order they were raised and shown to the sharer in the app
+//! and next to the cursors. Granting control gives it to the first request
+//! and takes it from the participant that had it through the queue, so the
+//! sharer can hand control around with a single command.
+
+use socket_lib::ControlRequest;
+
+/// The raised hands of the current session.
+#[derive(Debug, Default)]
+pub struct ControlQueue {
+    /// Requests in the order they were raised
+    requests: Vec<ControlRequest>,
+    /// Sid of the participant that last got control through the queue
+    granted: Option<String>,
+}
+
+impl ControlQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the request, a participant that already raised its hand keeps its place.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the queue changed.
+    pub fn raise(&mut self, request: ControlRequest) -> bool {
+        if self.requests.iter().any(|queued| queued.sid == request.sid) {
+            return false;
+        }
+        log::info!("ControlQueue::raise: {}", request.sid);
+        self.requests.push(request);
+        true
+    }
+
+    /// Drops the request of `sid`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the queue changed.
+    pub fn lower(&mut self, sid: &str) -> bool {
+        let len = self.requests.len();
+        self.requests.retain(|request| request.sid != sid);
+        self.requests.len() != len
+    }
+
+    /// Forgets the participant `sid` that left the session.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the queue changed.
+    pub fn left(&mut self, sid: &str) -> bool {
+        if self.granted.as_deref() == Some(sid) {
+            self.granted = None;
+        }
+        self.lower(sid)
+    }
+
+    /// Removes the first request, its participant becomes the control holder.
+    ///
+    /// # Returns
+    ///
+    /// The granted request and the sid of the previous holder, `None` when
+    /// nobody raised its hand.
+    pub fn grant_next(&mut self) -> Option<(ControlRequest, Option<String>)> {
+        if self.requests.is_empty() {
+            return None;
+        }
+        let next = self.requests.remove(0);
+        let previous = self.granted.replace(next.sid.clone());
+        log::info!("ControlQueue::grant_next: {} after {previous:?}", next.sid);
+        Some((next, previous))
+    }
+
+    /// Returns the requests in the order they were raised.
+    pub fn requests(&self) -> Vec<ControlRequest> {
+        self.requests.clone()
+    }
+
+    /// Returns the sids of the requests in the order they were raised.
+    pub fn sids(&self) -> Vec<String> {
+        self.requests
+            .iter()
+            .map(|request| request.sid.clone())
+            .collect()
+    }
+
+    /// Forgets the requests of the previous session.
+    pub fn clear(&mut self) {
+        self.requests.clear();
+        self.granted = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(sid: &str) -> ControlRequest {
+        ControlRequest {
+            sid: sid.to_string(),
+            name: format!("name {sid}"),
+            identity: format!("identity {sid}"),
+        }
+    }
+
+    #[test]
+    fn test_requests_keep_their_order() {
+        let mut queue = ControlQueue::new();
+        assert!(queue.raise(request("a")));
+        assert!(queue.raise(request("b")));
+        assert!(!queue.raise(request("a")));
+        assert!(queue.raise(request("c")));
+        assert_eq!(queue.sids(), vec!["a", "b", "c"]);
+
+        assert!(queue.lower("b"));
+        assert!(!queue.lower("b"));
+        assert_eq!(queue.sids(), vec!["a", "c"]);
+
+        assert!(queue.left("a"));
+        assert_eq!(queue.requests(), vec![request("c")]);
+    }
+
+    #[test]
+    fn test_grant_next_revokes_previous() {
+        let mut queue = ControlQueue::new();
+        assert_eq!(queue.grant_next(), None);
+
+        queue.raise(request("a"));
+        queue.raise(request("b"));
+        assert_eq!(queue.grant_next(), Some((request("a"), None)));
+        /* The holder can ask again once it lost control. */
+        queue.raise(request("a"));
+        assert_eq!(
+            queue.grant_next(),
+            Some((request("b"), Some("a".to_string())))
+        );
+
+        /* A holder that left has nothing to revoke. */
+        queue.left("b");
+        assert_eq!(queue.grant_next(), Some((request("a"), None)));
+        assert_eq!(queue.grant_next(), None);
+
+        queue.raise(request("c"));
+        queue.clear();
+        assert!(queue.sids().is_empty());
+        queue.raise(request("d"));
+        assert_eq!(queue.grant_next(), Some((request("d"), None)));
+    }
+}
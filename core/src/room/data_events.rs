@@ -0,0 +1,358 @@
+//! Translation of LiveKit room events into `UserEvent`s for the event loop.
+//!
+//! The data packets are decoded with the `DataHandlerRegistry`, see
+//! `client_event` for their format. The translated events are delivered
+//! through an `EventSender` so it can be exercised without a winit event loop.
+
+use std::collections::{HashMap, HashSet};
+
+use livekit::participant::ConnectionQuality;
+use livekit::RoomEvent;
+use socket_lib::ConnectionQualityLevel;
+use tokio::sync::{mpsc, watch};
+
+use crate::event_sender::EventSender;
+use crate::{ParticipantData, UserEvent};
+
+use super::admission::Admission;
+use super::capabilities::PeerCapabilities;
+use super::client_event::{ClientEvent, TOPIC_CAPABILITIES};
+use super::compression::DataCompression;
+use super::data_handlers::DataHandlerRegistry;
+use super::input_auth::{is_input_event, InputAuthorization, InputRejection};
+
+/// A sequence number this much lower than the last one means the client
+/// restarted its counter, not that the packet is late.
+const MOUSE_MOVE_SEQ_RESET_GAP: u64 = 1024;
+
+/// Builds the participant data for a newly seen participant.
+///
+/// Audio only participants and participants without a name are not
+/// shown in the overlay, for them `None` is returned.
+pub fn participant_data(identity: &str, name: String, sid: String) -> Option<ParticipantData> {
+    if identity.contains("audio") || name.is_empty() {
+        return None;
+    }
+    Some(ParticipantData {
+        name,
+        sid,
+        identity: identity.to_string(),
+    })
+}
+
+/// Drops the mouse moves that arrive after a newer move of the same participant.
+///
+/// Data packets can be reordered, without this the controller's cursor would
+/// jump back to an older position. Moves without a sequence number are
+/// always accepted.
+#[derive(Debug, Default)]
+pub struct MouseMoveOrder {
+    last_seq: HashMap<String, u64>,
+}
+
+impl MouseMoveOrder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `false` when the move `seq` of `sid` is older than the last accepted one.
+    pub fn accept(&mut self, sid: &str, seq: Option<u64>) -> bool {
+        let Some(seq) = seq else {
+            return true;
+        };
+        match self.last_seq.get_mut(sid) {
+            Some(last) if seq <= *last && *last - seq < MOUSE_MOVE_SEQ_RESET_GAP => false,
+            Some(last) => {
+                *last = seq;
+                true
+            }
+            None => {
+                self.last_seq.insert(sid.to_string(), seq);
+                true
+            }
+        }
+    }
+
+    /// Forgets the participant, a reconnecting client restarts its sequence.
+    pub fn remove(&mut self, sid: &str) {
+        self.last_seq.remove(sid);
+    }
+}
+
+/// Maps LiveKit's connection quality to the one reported to the tauri app.
+fn quality_level(quality: ConnectionQuality) -> ConnectionQualityLevel {
+    match quality {
+        ConnectionQuality::Excellent => ConnectionQualityLevel::Excellent,
+        ConnectionQuality::Good => ConnectionQualityLevel::Good,
+        ConnectionQuality::Poor => ConnectionQualityLevel::Poor,
+        ConnectionQuality::Lost => ConnectionQualityLevel::Lost,
+    }
+}
+
+/// The state of a joined room that `handle_room_events` checks the events
+/// against and updates.
+pub struct RoomEventContext {
+    /// Sid of the local participant, its own data packets are skipped
+    pub user_sid: String,
+    /// Decode the data packets based on their topic
+    pub data_handlers: DataHandlerRegistry,
+    /// Decides which participants are admitted, the data packets of the
+    /// others are dropped
+    pub admission: Admission,
+    /// Updated with the participants and their capabilities
+    pub compression: DataCompression,
+    /// Decides which input events are injected, the others are dropped
+    pub input_authorization: InputAuthorization,
+    /// Updated with the sharer's own connection quality, see `room::adaptive_bitrate`
+    pub local_quality: watch::Sender<Option<ConnectionQualityLevel>>,
+}
+
+/// Translates room events to `UserEvent`s until the room's event channel closes.
+///
+/// # Arguments
+///
+/// * `receiver` - Room events from the connection
+/// * `sink` - Where the translated events are delivered
+/// * `context` - The state of the room the events belong to
+pub async fn handle_room_events(
+    mut receiver: mpsc::UnboundedReceiver<RoomEvent>,
+    sink: impl EventSender,
+    context: RoomEventContext,
+) {
+    let RoomEventContext {
+        user_sid,
+        mut data_handlers,
+        admission,
+        compression,
+        input_authorization,
+        local_quality,
+    } = context;
+    let mut mouse_move_order = MouseMoveOrder::new();
+    /* The outdated participants that were asked to upgrade. */
+    let mut upgrade_required = HashSet::new();
+    while let Some(msg) = receiver.recv().await {
+        match msg {
+            RoomEvent::DataReceived {
+                payload,
+                topic,
+                kind: _,
+                participant,
+            } => {
+                let (sid, identity) = if let Some(participant) = participant {
+                    (
+                        participant.sid().as_str().to_string(),
+                        participant.identity().as_str().to_string(),
+                    )
+                } else {
+                    log::warn!("handle_room_events: Participant is none");
+                    ("".to_string(), "".to_string())
+                };
+
+                /* Skip our own events. */
+                if sid == user_sid {
+                    log::debug!("handle_room_events: Skipping own event");
+                    continue;
+                }
+
+                /* Every participant receives the packets, admitted or not. */
+                if topic.as_deref() == Some(TOPIC_CAPABILITIES) {
+                    match ClientEvent::decode(&payload) {
+                        Ok(ClientEvent::Capabilities(capabilities)) => {
+                            compression.announced(&sid, &capabilities.compression);
+                            input_authorization.announced(&sid, capabilities.version);
+                            let res = sink.send_participant_capabilities(
+                                sid,
+                                PeerCapabilities::from(&capabilities),
+                            );
+                            if let Err(e) = res {
+                                log::error!(
+                                    "handle_room_events: Failed to send capabilities: {e:?}"
+                                );
+                            }
+                        }
+                        other => log::warn!("handle_room_events: Invalid capabilities: {other:?}"),
+                    }
+                    continue;
+                }
+
+                if !admission.is_admitted(&sid) {
+                    log::debug!("handle_room_events: Dropping event of not admitted {sid}");
+                    continue;
+                }
+
+                let packet = data_handlers.dispatch(topic.as_deref(), &payload, sid.clone());
+                if let Some(event) = packet.event {
+                    if matches!(event, UserEvent::CursorPosition(..))
+                        && !mouse_move_order.accept(&sid, packet.seq)
+                    {
+                        log::debug!("handle_room_events: Dropping out of order mouse move");
+                        continue;
+                    }
+                    if is_input_event(&event) {
+                        if let Err(InputRejection::Outdated {
+                            version,
+                            min_version,
+                        }) = input_authorization.check_version(&sid)
+                        {
+                            log::debug!(
+                                "handle_room_events: Dropping input of {sid} with version {version}"
+                            );
+                            if upgrade_required.insert(sid.clone()) {
+                                log::info!(
+                                    "handle_room_events: {sid} has to upgrade to {min_version}"
+                                );
+                                let res =
+                                    sink.send(UserEvent::UpgradeRequired(identity, min_version));
+                                if let Err(e) = res {
+                                    log::error!(
+                                        "handle_room_events: Failed to send upgrade required: {e:?}"
+                                    );
+                                }
+                            }
+                            continue;
+                        }
+                    }
+                    if (is_input_event(&event) || input_authorization.has_control(&sid))
+                        && !input_authorization.accepts(&event, &identity, packet.auth.as_ref())
+                    {
+                        continue;
+                    }
+                    if let Err(e) = sink.send(event) {
+                        log::error!("handle_room_events: Failed to send message: {e:?}");
+                    }
+                }
+            }
+            RoomEvent::ParticipantConnected(participant) => {
+                log::info!("handle_room_events: Participant connected: {participant:?}");
+
+                let data = participant_data(
+                    participant.identity().as_str(),
+                    participant.name(),
+                    participant.sid().as_str().to_string(),
+                );
+                let data = match data {
+                    Some(data) => data,
+                    None => {
+                        log::debug!("handle_room_events: Skipping participant: {participant:?}");
+                        continue;
+                    }
+                };
+
+                compression.joined(&data.sid);
+                admission.join(data, &sink);
+            }
+            RoomEvent::ParticipantDisconnected(participant) => {
+                log::info!("handle_room_events: Participant disconnected: {participant:?}");
+                mouse_move_order.remove(participant.sid().as_str());
+                upgrade_required.remove(participant.sid().as_str());
+                compression.left(participant.sid().as_str());
+                input_authorization.left(participant.sid().as_str());
+                admission.leave(participant.sid().as_str());
+
+                if let Err(e) = sink.send_participant_disconnected(ParticipantData {
+                    name: participant.name(),
+                    sid: participant.sid().as_str().to_string(),
+                    identity: participant.identity().as_str().to_string(),
+                }) {
+                    log::error!(
+                        "handle_room_events: Failed to send participant disconnected event: {e:?}"
+                    );
+                }
+            }
+            RoomEvent::ConnectionQualityChanged {
+                quality,
+                participant,
+            } => {
+                let sid = participant.sid().as_str().to_string();
+                /* The sharer's own quality rates its uplink. */
+                if sid == user_sid {
+                    local_quality.send_replace(Some(quality_level(quality)));
+                    continue;
+                }
+                /* Only the quality of the admitted viewers matters to the sharer. */
+                if !admission.is_admitted(&sid) {
+                    continue;
+                }
+                let data = ParticipantData {
+                    name: participant.name(),
+                    sid,
+                    identity: participant.identity().as_str().to_string(),
+                };
+                if let Err(e) = sink.send_participant_quality(data, quality_level(quality)) {
+                    log::error!(
+                        "handle_room_events: Failed to send participant quality event: {e:?}"
+                    );
+                }
+            }
+            RoomEvent::TrackPublished {
+                publication,
+                participant,
+            } => {
+                log::info!("handle_room_events: Track published: {publication:?}, {participant:?}");
+                let name = participant.name();
+                let participant_id = participant.identity().as_str().to_string();
+                if participant_id.contains("video") {
+                    log::info!("handle_room_events: Controller {name} takes screen share");
+                    if let Err(e) = sink.send_controller_takes_screen_share() {
+                        log::error!(
+                            "handle_room_events: Failed to send controller takes screen share event: {e:?}"
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    data_handlers.log_metrics();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mouse_move_order() {
+        let mut order = MouseMoveOrder::new();
+        assert!(order.accept("a", Some(1)));
+        assert!(order.accept("a", Some(3)));
+        assert!(!order.accept("a", Some(2)));
+        assert!(!order.accept("a", Some(3)));
+        /* Participants have their own sequences. */
+        assert!(order.accept("b", Some(2)));
+        /* Clients without sequence numbers are never dropped. */
+        assert!(order.accept("a", None));
+
+        /* A big jump back is a restarted counter. */
+        assert!(order.accept("a", Some(5000)));
+        assert!(order.accept("a", Some(1)));
+
+        order.remove("a");
+        assert!(order.accept("b", Some(3)));
+        assert!(!order.accept("b", Some(1)));
+    }
+
+    #[test]
+    fn test_quality_level() {
+        assert_eq!(
+            quality_level(ConnectionQuality::Excellent),
+            ConnectionQualityLevel::Excellent
+        );
+        assert_eq!(
+            quality_level(ConnectionQuality::Poor),
+            ConnectionQualityLevel::Poor
+        );
+        assert_eq!(
+            quality_level(ConnectionQuality::Lost),
+            ConnectionQualityLevel::Lost
+        );
+    }
+
+    #[test]
+    fn test_participant_data_filters_audio_and_unnamed() {
+        assert!(participant_data("user_audio", "Name".to_string(), "sid".to_string()).is_none());
+        assert!(participant_data("user", "".to_string(), "sid".to_string()).is_none());
+        let data = participant_data("user", "Name".to_string(), "sid".to_string()).unwrap();
+        assert_eq!(data.name, "Name");
+        assert_eq!(data.sid, "sid");
+    }
+}
@@ -0,0 +1,316 @@
+//! Per-topic handlers for incoming data packets.
+//!
+//! Every data packet is published on a topic. Subsystems (cursor, keyboard,
+//! chat, annotations, ...) register a handler for their topic in the
+//! `DataHandlerRegistry`, the handler decodes the payload and returns the
+//! `UserEvent` the event loop should process. Packets on topics without a
+//! handler, and packets without a topic, go to the fallback handler.
+//!
+//! Compressed payloads are inflated by the registry, the handlers always get
+//! the JSON payload and decode it once.
+//!
+//! The registry also keeps per-topic counters that are logged when the room
+//! closes.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::UserEvent;
+
+use super::client_event::{
+    client_event_to_user_event, ClientEvent, ClientPacket, TOPIC_SHARER_LOCATION,
+};
+use super::compression;
+use super::input_auth::InputAuth;
+
+/// Name used in the metrics for packets without a topic.
+const NO_TOPIC: &str = "<none>";
+
+#[derive(Debug, thiserror::Error)]
+pub enum DataHandlerError {
+    #[error("Failed to decode payload: {0}")]
+    Decode(#[from] serde_json::Error),
+    #[error("Failed to inflate payload: {0}")]
+    Inflate(#[from] std::io::Error),
+    #[error("Invalid payload: {0}")]
+    Invalid(String),
+}
+
+/// What a handler decoded from a data packet.
+#[derive(Debug, Default)]
+pub struct DecodedPacket {
+    /// The event that needs to be handled by the event loop, `None` when
+    /// nothing needs to be done
+    pub event: Option<UserEvent>,
+    /// Sequence number of a mouse move, see `MouseMoveOrder`
+    pub seq: Option<u64>,
+    /// The authentication a controller added to its input event
+    pub auth: Option<InputAuth>,
+}
+
+impl From<Option<UserEvent>> for DecodedPacket {
+    fn from(event: Option<UserEvent>) -> Self {
+        Self {
+            event,
+            ..Default::default()
+        }
+    }
+}
+
+/// Decodes the JSON payload of a data packet sent by the participant `sid`.
+///
+/// # Returns
+///
+/// * `Ok(DecodedPacket)` - The event of the packet and what it was sent with
+/// * `Err(DataHandlerError)` - The payload couldn't be decoded
+pub type DataHandler =
+    Arc<dyn Fn(&[u8], String) -> Result<DecodedPacket, DataHandlerError> + Send + Sync>;
+
+/// Packet counters of a single topic.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TopicMetrics {
+    /// Number of packets received
+    pub packets: u64,
+    /// Total payload size of the received packets
+    pub bytes: u64,
+    /// Number of packets the handler failed to decode
+    pub errors: u64,
+}
+
+/// Maps data packet topics to their handlers.
+///
+/// Cloning the registry shares the handlers but starts with empty metrics,
+/// transports clone their registry for every room they join.
+pub struct DataHandlerRegistry {
+    handlers: HashMap<String, DataHandler>,
+    fallback: DataHandler,
+    metrics: HashMap<String, TopicMetrics>,
+}
+
+impl Clone for DataHandlerRegistry {
+    fn clone(&self) -> Self {
+        Self {
+            handlers: self.handlers.clone(),
+            fallback: self.fallback.clone(),
+            metrics: HashMap::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for DataHandlerRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataHandlerRegistry")
+            .field("topics", &self.handlers.keys().collect::<Vec<_>>())
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
+
+/// Handler decoding the `ClientEvent` packets sent by the controllers.
+pub fn client_event_handler(
+    payload: &[u8],
+    sid: String,
+) -> Result<DecodedPacket, DataHandlerError> {
+    let packet: ClientPacket = serde_json::from_slice(payload)?;
+    log::debug!("client_event_handler: Data received: {:?}", packet.event);
+    let seq = match &packet.event {
+        ClientEvent::MouseMove(point) => point.seq,
+        _ => None,
+    };
+    Ok(DecodedPacket {
+        event: client_event_to_user_event(packet.event, sid),
+        seq,
+        auth: packet.auth,
+    })
+}
+
+impl DataHandlerRegistry {
+    /// Creates a registry without topic handlers, all packets go to `fallback`.
+    pub fn new(fallback: DataHandler) -> Self {
+        Self {
+            handlers: HashMap::new(),
+            fallback,
+            metrics: HashMap::new(),
+        }
+    }
+
+    /// Registers `handler` for the packets on `topic`, replacing the previous one.
+    pub fn register(&mut self, topic: &str, handler: DataHandler) {
+        if self.handlers.insert(topic.to_string(), handler).is_some() {
+            log::warn!("DataHandlerRegistry::register: replaced handler for {topic}");
+        }
+    }
+
+    /// Inflates `payload` if it was compressed and runs the handler of `topic` on it.
+    ///
+    /// Decoding failures are logged and counted in the topic's metrics.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Topic the packet was published on, if any
+    /// * `payload` - The packet's payload as it was received
+    /// * `sid` - Sid of the participant that sent the packet
+    ///
+    /// # Returns
+    ///
+    /// What the handler decoded, empty when the payload couldn't be decoded.
+    pub fn dispatch(&mut self, topic: Option<&str>, payload: &[u8], sid: String) -> DecodedPacket {
+        let handler = topic
+            .and_then(|topic| self.handlers.get(topic))
+            .unwrap_or(&self.fallback);
+        let res = compression::decompress(payload)
+            .map_err(DataHandlerError::from)
+            .and_then(|json| handler(&json, sid));
+
+        let metrics = self
+            .metrics
+            .entry(topic.unwrap_or(NO_TOPIC).to_string())
+            .or_default();
+        metrics.packets += 1;
+        metrics.bytes += payload.len() as u64;
+
+        match res {
+            Ok(packet) => packet,
+            Err(e) => {
+                metrics.errors += 1;
+                log::error!("DataHandlerRegistry::dispatch: topic {topic:?}: {e}");
+                DecodedPacket::default()
+            }
+        }
+    }
+
+    /// Returns the counters of `topic`, `None` for packets without a topic.
+    pub fn metrics(&self, topic: Option<&str>) -> TopicMetrics {
+        self.metrics
+            .get(topic.unwrap_or(NO_TOPIC))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Logs the counters of all the topics that received packets.
+    pub fn log_metrics(&self) {
+        for (topic, metrics) in &self.metrics {
+            log::info!("DataHandlerRegistry: topic {topic}: {metrics:?}");
+        }
+    }
+}
+
+impl Default for DataHandlerRegistry {
+    /// Registry with the handlers of the built-in remote control events.
+    ///
+    /// Controllers publish the cursor movements on the sharer location topic
+    /// and the rest of the events without a topic, both are `ClientEvent`s.
+    fn default() -> Self {
+        let mut registry = Self::new(Arc::new(client_event_handler));
+        registry.register(TOPIC_SHARER_LOCATION, Arc::new(client_event_handler));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispatch_uses_topic_handler() {
+        let mut registry =
+            DataHandlerRegistry::new(Arc::new(|_: &[u8], _: String| Ok(DecodedPacket::default())));
+        registry.register(
+            "chat",
+            Arc::new(|payload: &[u8], sid: String| {
+                assert_eq!(payload, b"hi");
+                Ok(
+                    Some(UserEvent::ParticipantDisconnected(crate::ParticipantData {
+                        name: "chat".to_string(),
+                        sid,
+                        identity: "chat".to_string(),
+                    }))
+                    .into(),
+                )
+            }),
+        );
+
+        match registry
+            .dispatch(Some("chat"), b"hi", "sid".to_string())
+            .event
+        {
+            Some(UserEvent::ParticipantDisconnected(data)) => assert_eq!(data.sid, "sid"),
+            other => panic!("unexpected event {other:?}"),
+        }
+        assert!(registry
+            .dispatch(Some("other"), b"hi", "sid".to_string())
+            .event
+            .is_none());
+        assert!(registry
+            .dispatch(None, b"hi", "sid".to_string())
+            .event
+            .is_none());
+    }
+
+    #[test]
+    fn test_default_registry_decodes_client_events() {
+        let mut registry = DataHandlerRegistry::default();
+        let payload = br#"{"type":"MouseMove","payload":{"x":0.25,"y":0.5,"seq":7}}"#;
+        let packet = registry.dispatch(Some(TOPIC_SHARER_LOCATION), payload, "sid".to_string());
+        assert!(matches!(packet.event, Some(UserEvent::CursorPosition(..))));
+        assert_eq!(packet.seq, Some(7));
+        assert!(matches!(
+            registry.dispatch(None, payload, "sid".to_string()).event,
+            Some(UserEvent::CursorPosition(..))
+        ));
+    }
+
+    #[test]
+    fn test_dispatch_inflates_once_for_the_handler() {
+        let mut registry = DataHandlerRegistry::default();
+        let padding = "a".repeat(compression::COMPRESSION_THRESHOLD);
+        let payload = format!(
+            r#"{{"type":"Keystroke","payload":{{"key":["{padding}"],"meta":false,"ctrl":false,"shift":false,"alt":false,"down":true}},"auth":{{"token":"ab","seq":8}}}}"#
+        );
+        let payload = compression::compress(payload.into_bytes());
+        assert_eq!(payload[0], compression::COMPRESSED_PAYLOAD_FLAG);
+
+        let packet = registry.dispatch(None, &payload, "sid".to_string());
+        assert_eq!(
+            packet.auth,
+            Some(InputAuth {
+                token: "ab".to_string(),
+                seq: 8
+            })
+        );
+        assert_eq!(packet.seq, None);
+        assert_eq!(registry.metrics(None).errors, 0);
+
+        let packet = registry.dispatch(
+            None,
+            &[compression::COMPRESSED_PAYLOAD_FLAG, 0xff],
+            "sid".to_string(),
+        );
+        assert!(packet.event.is_none());
+        assert_eq!(registry.metrics(None).errors, 1);
+    }
+
+    #[test]
+    fn test_metrics_count_packets_and_errors() {
+        let mut registry = DataHandlerRegistry::default();
+        registry.dispatch(None, b"not json", "sid".to_string());
+        registry.dispatch(
+            Some(TOPIC_SHARER_LOCATION),
+            br#"{"type":"MouseMove","payload":{"x":0.0,"y":0.0}}"#,
+            "sid".to_string(),
+        );
+
+        assert_eq!(
+            registry.metrics(None),
+            TopicMetrics {
+                packets: 1,
+                bytes: 8,
+                errors: 1
+            }
+        );
+        let metrics = registry.metrics(Some(TOPIC_SHARER_LOCATION));
+        assert_eq!(metrics.packets, 1);
+        assert_eq!(metrics.errors, 0);
+        assert_eq!(registry.metrics(Some("chat")), TopicMetrics::default());
+    }
+}
@@ -0,0 +1,483 @@
+//! Authorization of the controllers' input events.
+//!
+//! Admission only decides who takes part in the session, every admitted
+//! participant could still inject clicks and keystrokes, and nothing stopped a
+//! client from replaying packets it saw. The sharer authorizes identities for
+//! input explicitly, each of them gets a token of its own with a
+//! `ClientEvent::InputGrant` sent only to it. The controller stamps its
+//! clicks, keystrokes and scrolls with the token and an increasing sequence
+//! number:
+//!
+//! ```text
+//! {"type":"MouseClick","payload":{...},"auth":{"token":"...","seq":42}}
+//! ```
+//!
+//! Input events of identities that aren't authorized, without the token of
+//! their identity or with a sequence number that was already used are
+//! dropped. The cursor moves of the controller that has control move the
+//! sharer's pointer, they need the token too. They don't take a sequence
+//! number, a move that overtook a click mustn't make the click look replayed:
+//!
+//! ```text
+//! {"type":"MouseMove","payload":{...},"auth":{"token":"...","seq":41}}
+//! ```
+//!
+//! Revoking an identity also takes control back from its controllers, see
+//! `CursorController::take_back_control`. The tokens are new for every session, a packet recorded in one
+//! session is useless in the next.
+//!
+//! The input events' schema changes with the protocol version, see
+//! `capabilities::PROTOCOL_VERSION`. The core can be started with a minimum
+//! client version, the input of the clients that announced an older one, or
+//! never announced any, is dropped and they are asked once to upgrade with a
+//! `ClientEvent::UpgradeRequired`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::UserEvent;
+
+const INPUT_TOKEN_BYTES: usize = 16;
+
+/// The authentication a controller adds to its input events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputAuth {
+    /// The token the sharer granted to the controller's identity
+    pub token: String,
+    /// Increasing number of the controller's input events
+    pub seq: u64,
+}
+
+/// Why an input event was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum InputRejection {
+    #[error("identity isn't authorized for input")]
+    NotAuthorized,
+    #[error("event isn't authenticated")]
+    MissingAuth,
+    #[error("wrong token")]
+    WrongToken,
+    #[error("sequence number was already used")]
+    Replayed,
+    #[error("client version {version} is older than {min_version}")]
+    Outdated { version: u32, min_version: u32 },
+}
+
+#[derive(Debug, Default)]
+struct InputAuthState {
+    /// Tokens of the authorized identities
+    tokens: HashMap<String, String>,
+    /// Last accepted sequence number by sid
+    last_seq: HashMap<String, u64>,
+    /// Protocol versions the participants announced by sid
+    versions: HashMap<String, u32>,
+    /// Oldest protocol version whose input is injected, 0 accepts every client
+    min_client_version: u32,
+    /// The participant whose cursor moves are injected, see `set_in_control`
+    in_control: Option<String>,
+}
+
+/// The identities the sharer authorized for input in the current session.
+///
+/// Clones share the same state, the application authorizes identities while
+/// the transports check the incoming input events.
+#[derive(Debug, Clone, Default)]
+pub struct InputAuthorization {
+    state: Arc<Mutex<InputAuthState>>,
+}
+
+impl InputAuthorization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Authorizes `identity` for input.
+    ///
+    /// # Returns
+    ///
+    /// The token the identity stamps its input events with, the same one
+    /// when it was already authorized.
+    pub fn authorize(&self, identity: &str) -> String {
+        log::info!("InputAuthorization::authorize: {identity}");
+        let mut state = self.state.lock().unwrap();
+        state
+            .tokens
+            .entry(identity.to_string())
+            .or_insert_with(new_token)
+            .clone()
+    }
+
+    /// Revokes the input of `identity`, a later authorization gets a new token.
+    pub fn revoke(&self, identity: &str) {
+        log::info!("InputAuthorization::revoke: {identity}");
+        self.state.lock().unwrap().tokens.remove(identity);
+    }
+
+    /// Returns the token of `identity`, `None` if it isn't authorized.
+    pub fn token(&self, identity: &str) -> Option<String> {
+        self.state.lock().unwrap().tokens.get(identity).cloned()
+    }
+
+    pub fn is_authorized(&self, identity: &str) -> bool {
+        self.state.lock().unwrap().tokens.contains_key(identity)
+    }
+
+    /// Returns the authorized identities, sorted.
+    pub fn identities(&self) -> Vec<String> {
+        let mut identities: Vec<String> =
+            self.state.lock().unwrap().tokens.keys().cloned().collect();
+        identities.sort();
+        identities
+    }
+
+    /// Forgets the participant `sid`, a reconnecting client restarts its sequence.
+    pub fn left(&self, sid: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.last_seq.remove(sid);
+        state.versions.remove(sid);
+        if state.in_control.as_deref() == Some(sid) {
+            state.in_control = None;
+        }
+    }
+
+    /// Forgets the authorizations of the previous session, the minimum
+    /// client version is kept.
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.tokens.clear();
+        state.last_seq.clear();
+        state.versions.clear();
+        state.in_control = None;
+    }
+
+    /// Records the participant that has control of the sharer's cursor, its
+    /// cursor moves are injected and need the token from then on.
+    pub fn set_in_control(&self, sid: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        if state.in_control != sid {
+            log::info!("InputAuthorization::set_in_control: {sid:?}");
+            state.in_control = sid;
+        }
+    }
+
+    /// Returns `true` if the participant `sid` has control of the sharer's cursor.
+    pub fn has_control(&self, sid: &str) -> bool {
+        self.state.lock().unwrap().in_control.as_deref() == Some(sid)
+    }
+
+    /// Sets the oldest protocol version whose input is injected, 0 accepts every client.
+    pub fn set_min_client_version(&self, version: u32) {
+        log::info!("InputAuthorization::set_min_client_version: {version}");
+        self.state.lock().unwrap().min_client_version = version;
+    }
+
+    /// Records the protocol version the participant `sid` announced.
+    pub fn announced(&self, sid: &str, version: u32) {
+        self.state
+            .lock()
+            .unwrap()
+            .versions
+            .insert(sid.to_string(), version);
+    }
+
+    /// Checks the protocol version of the participant `sid` against the
+    /// minimum client version, for the input events of the handshaking
+    /// transports.
+    ///
+    /// # Returns
+    ///
+    /// `Err(InputRejection::Outdated)` if the participant announced an older
+    /// version, or none yet.
+    pub fn check_version(&self, sid: &str) -> Result<(), InputRejection> {
+        let state = self.state.lock().unwrap();
+        let version = state.versions.get(sid).copied().unwrap_or(0);
+        if version < state.min_client_version {
+            return Err(InputRejection::Outdated {
+                version,
+                min_version: state.min_client_version,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks an input event of the participant `sid`.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the participant that sent the event
+    /// * `sid` - The participant that sent the event
+    /// * `auth` - The authentication of the event, see `input_auth`
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the event can be injected, its sequence number can't be
+    /// used again.
+    pub fn check(
+        &self,
+        identity: &str,
+        sid: &str,
+        auth: Option<&InputAuth>,
+    ) -> Result<(), InputRejection> {
+        let mut state = self.state.lock().unwrap();
+        let token = state
+            .tokens
+            .get(identity)
+            .ok_or(InputRejection::NotAuthorized)?;
+        let auth = auth.ok_or(InputRejection::MissingAuth)?;
+        if auth.token != *token {
+            return Err(InputRejection::WrongToken);
+        }
+        match state.last_seq.get(sid) {
+            Some(last) if auth.seq <= *last => Err(InputRejection::Replayed),
+            _ => {
+                state.last_seq.insert(sid.to_string(), auth.seq);
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks the token of a cursor move, the sequence number isn't used.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the participant that sent the move
+    /// * `auth` - The authentication of the move
+    pub fn check_token(
+        &self,
+        identity: &str,
+        auth: Option<&InputAuth>,
+    ) -> Result<(), InputRejection> {
+        let state = self.state.lock().unwrap();
+        let token = state
+            .tokens
+            .get(identity)
+            .ok_or(InputRejection::NotAuthorized)?;
+        let auth = auth.ok_or(InputRejection::MissingAuth)?;
+        if auth.token != *token {
+            return Err(InputRejection::WrongToken);
+        }
+        Ok(())
+    }
+
+    /// Checks an input event, see `is_input_event`, and the cursor moves of
+    /// the participant in control, other events always pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The translated event
+    /// * `identity` - The identity of the participant that sent the event
+    /// * `auth` - The authentication of the event
+    ///
+    /// # Returns
+    ///
+    /// `true` if the event can be handled, rejections are logged.
+    pub fn accepts(&self, event: &UserEvent, identity: &str, auth: Option<&InputAuth>) -> bool {
+        let (sid, checked) = match event {
+            UserEvent::MouseClick(_, sid)
+            | UserEvent::Keystroke(_, sid)
+            | UserEvent::Scroll(_, sid)
+            | UserEvent::ClipboardUpdate(_, sid) => (sid, self.check(identity, sid, auth)),
+            UserEvent::CursorPosition(_, _, sid) if self.has_control(sid) => {
+                (sid, self.check_token(identity, auth))
+            }
+            _ => return true,
+        };
+        match checked {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("InputAuthorization::accepts: dropping input of {sid}: {e}");
+                false
+            }
+        }
+    }
+}
+
+/// Returns `true` for the events that are always injected on the sharer's machine.
+///
+/// Cursor moves only draw the controller's cursor in the overlay, unless the
+/// controller has control, see `InputAuthorization::has_control`. A clipboard
+/// update is written to the sharer's clipboard, it needs the authorization.
+pub fn is_input_event(event: &UserEvent) -> bool {
+    matches!(
+        event,
+        UserEvent::MouseClick(..)
+            | UserEvent::Keystroke(..)
+            | UserEvent::Scroll(..)
+            | UserEvent::ClipboardUpdate(..)
+    )
+}
+
+fn new_token() -> String {
+    let bytes: [u8; INPUT_TOKEN_BYTES] = rand::thread_rng().gen();
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(token: &str, seq: u64) -> InputAuth {
+        InputAuth {
+            token: token.to_string(),
+            seq,
+        }
+    }
+
+    #[test]
+    fn test_only_authorized_identities_pass() {
+        let authorization = InputAuthorization::new();
+        assert_eq!(
+            authorization.check("user_a", "a", Some(&auth("", 1))),
+            Err(InputRejection::NotAuthorized)
+        );
+
+        let token = authorization.authorize("user_a");
+        assert_eq!(token.len(), INPUT_TOKEN_BYTES * 2);
+        assert_eq!(authorization.authorize("user_a"), token);
+        assert_eq!(
+            authorization.check("user_a", "a", Some(&auth(&token, 1))),
+            Ok(())
+        );
+        assert_eq!(
+            authorization.check("user_a", "a", None),
+            Err(InputRejection::MissingAuth)
+        );
+
+        /* Another identity can't use the token it saw. */
+        let other = authorization.authorize("user_b");
+        assert_ne!(other, token);
+        assert_eq!(
+            authorization.check("user_b", "b", Some(&auth(&token, 1))),
+            Err(InputRejection::WrongToken)
+        );
+
+        assert_eq!(authorization.identities(), vec!["user_a", "user_b"]);
+
+        authorization.revoke("user_a");
+        assert_eq!(authorization.identities(), vec!["user_b"]);
+        assert_eq!(
+            authorization.check("user_a", "a", Some(&auth(&token, 2))),
+            Err(InputRejection::NotAuthorized)
+        );
+        assert_ne!(authorization.authorize("user_a"), token);
+    }
+
+    #[test]
+    fn test_replayed_events_are_rejected() {
+        let authorization = InputAuthorization::new();
+        let token = authorization.authorize("user_a");
+        assert_eq!(
+            authorization.check("user_a", "a", Some(&auth(&token, 1))),
+            Ok(())
+        );
+        assert_eq!(
+            authorization.check("user_a", "a", Some(&auth(&token, 3))),
+            Ok(())
+        );
+        assert_eq!(
+            authorization.check("user_a", "a", Some(&auth(&token, 3))),
+            Err(InputRejection::Replayed)
+        );
+        assert_eq!(
+            authorization.check("user_a", "a", Some(&auth(&token, 2))),
+            Err(InputRejection::Replayed)
+        );
+        /* A rejoining client restarts its sequence. */
+        authorization.left("a");
+        assert_eq!(
+            authorization.check("user_a", "a", Some(&auth(&token, 1))),
+            Ok(())
+        );
+
+        authorization.reset();
+        assert!(!authorization.is_authorized("user_a"));
+    }
+
+    #[test]
+    fn test_outdated_clients_are_rejected() {
+        let authorization = InputAuthorization::new();
+        /* Without a minimum every client passes, announced or not. */
+        assert_eq!(authorization.check_version("a"), Ok(()));
+
+        authorization.set_min_client_version(2);
+        assert_eq!(
+            authorization.check_version("a"),
+            Err(InputRejection::Outdated {
+                version: 0,
+                min_version: 2
+            })
+        );
+        authorization.announced("a", 1);
+        assert_eq!(
+            authorization.check_version("a"),
+            Err(InputRejection::Outdated {
+                version: 1,
+                min_version: 2
+            })
+        );
+        authorization.announced("b", 3);
+        assert_eq!(authorization.check_version("b"), Ok(()));
+
+        authorization.left("b");
+        assert!(authorization.check_version("b").is_err());
+        authorization.announced("b", 2);
+        authorization.reset();
+        assert!(authorization.check_version("b").is_err());
+        authorization.announced("b", 2);
+        assert_eq!(authorization.check_version("b"), Ok(()));
+    }
+
+    #[test]
+    fn test_only_injected_events_need_authorization() {
+        let authorization = InputAuthorization::new();
+        let event = UserEvent::CursorPosition(0.5, 0.5, "a".to_string());
+        assert!(!is_input_event(&event));
+        assert!(authorization.accepts(&event, "user_a", None));
+
+        let event = UserEvent::Scroll(crate::ScrollDelta { x: 0.0, y: 1.0 }, "a".to_string());
+        assert!(is_input_event(&event));
+        assert!(!authorization.accepts(&event, "user_a", None));
+
+        let token = authorization.authorize("user_a");
+        assert!(authorization.accepts(&event, "user_a", Some(&auth(&token, 1))));
+        assert!(!authorization.accepts(&event, "user_a", Some(&auth(&token, 1))));
+
+        let event = UserEvent::ClipboardUpdate("text".to_string(), "a".to_string());
+        assert!(is_input_event(&event));
+        assert!(!authorization.accepts(&event, "user_a", None));
+        assert!(authorization.accepts(&event, "user_a", Some(&auth(&token, 2))));
+    }
+
+    #[test]
+    fn test_moves_of_the_controller_in_control_need_the_token() {
+        let authorization = InputAuthorization::new();
+        let token = authorization.authorize("user_a");
+        let event = UserEvent::CursorPosition(0.5, 0.5, "a".to_string());
+        assert!(authorization.accepts(&event, "user_a", None));
+
+        authorization.set_in_control(Some("a".to_string()));
+        assert!(authorization.has_control("a"));
+        assert!(!authorization.accepts(&event, "user_a", None));
+        assert!(!authorization.accepts(&event, "user_a", Some(&auth("ab", 1))));
+        /* Moves don't use the sequence, the same number passes again. */
+        assert!(authorization.accepts(&event, "user_a", Some(&auth(&token, 1))));
+        assert!(authorization.accepts(&event, "user_a", Some(&auth(&token, 1))));
+        let click = UserEvent::Scroll(crate::ScrollDelta { x: 0.0, y: 1.0 }, "a".to_string());
+        assert!(authorization.accepts(&click, "user_a", Some(&auth(&token, 1))));
+
+        /* A revoked controller that still has control can't move the pointer. */
+        authorization.revoke("user_a");
+        assert!(!authorization.accepts(&event, "user_a", Some(&auth(&token, 2))));
+
+        /* Other participants only draw their cursors. */
+        let other = UserEvent::CursorPosition(0.5, 0.5, "b".to_string());
+        assert!(authorization.accepts(&other, "user_b", None));
+
+        authorization.left("a");
+        assert!(!authorization.has_control("a"));
+        assert!(authorization.accepts(&event, "user_a", None));
+    }
+}
@@ -0,0 +1,196 @@
+//! Transport-neutral media sinks.
+//!
+//! Publishing a track with a `SessionTransport` returns the sink its media
+//! is pushed to: a `VideoSink` for the screen share and the camera, an
+//! `AudioSink` for the microphone and the system audio. The capture code
+//! only knows these types, the transports convert the frames and samples to
+//! what their backend sends.
+//!
+//! The frames keep their pixels in the buffers the capture pipeline converts
+//! into, transports that encode with libwebrtc push them without a copy and
+//! others read the planes with `planes`.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use livekit::webrtc::prelude::{I420Buffer, NV12Buffer, VideoBuffer, VideoFrame, VideoRotation};
+
+/// A plane of a video frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Plane<'a> {
+    /// The pixels of the plane, `stride` bytes per row
+    pub data: &'a [u8],
+    /// Bytes per row of `data`
+    pub stride: u32,
+}
+
+/// An NV12 frame, a full resolution luma plane followed by a half resolution
+/// plane of interleaved chroma samples.
+pub struct Nv12Frame {
+    frame: VideoFrame<NV12Buffer>,
+}
+
+impl Nv12Frame {
+    /// Creates a frame of `width`x`height`.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            frame: VideoFrame {
+                rotation: VideoRotation::VideoRotation0,
+                buffer: NV12Buffer::new(width, height),
+                timestamp_us: 0,
+            },
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.frame.buffer.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.frame.buffer.height()
+    }
+
+    /// Returns the luma and the chroma plane.
+    pub fn planes(&self) -> [Plane<'_>; 2] {
+        let (stride_y, stride_uv) = self.frame.buffer.strides();
+        let (data_y, data_uv) = self.frame.buffer.data();
+        [
+            Plane {
+                data: data_y,
+                stride: stride_y,
+            },
+            Plane {
+                data: data_uv,
+                stride: stride_uv,
+            },
+        ]
+    }
+
+    pub(crate) fn buffer_mut(&mut self) -> &mut NV12Buffer {
+        &mut self.frame.buffer
+    }
+
+    pub(crate) fn webrtc(&self) -> &VideoFrame<NV12Buffer> {
+        &self.frame
+    }
+}
+
+/// An I420 frame, a full resolution luma plane followed by the two half
+/// resolution chroma planes.
+pub struct I420Frame {
+    frame: VideoFrame<I420Buffer>,
+}
+
+impl I420Frame {
+    /// Creates a frame of `width`x`height`.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            frame: VideoFrame {
+                rotation: VideoRotation::VideoRotation0,
+                buffer: I420Buffer::new(width, height),
+                timestamp_us: 0,
+            },
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.frame.buffer.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.frame.buffer.height()
+    }
+
+    /// Returns the luma, the U and the V plane.
+    pub fn planes(&self) -> [Plane<'_>; 3] {
+        let (stride_y, stride_u, stride_v) = self.frame.buffer.strides();
+        let (data_y, data_u, data_v) = self.frame.buffer.data();
+        [
+            Plane {
+                data: data_y,
+                stride: stride_y,
+            },
+            Plane {
+                data: data_u,
+                stride: stride_u,
+            },
+            Plane {
+                data: data_v,
+                stride: stride_v,
+            },
+        ]
+    }
+
+    pub(crate) fn buffer_mut(&mut self) -> &mut I420Buffer {
+        &mut self.frame.buffer
+    }
+
+    pub(crate) fn webrtc(&self) -> &VideoFrame<I420Buffer> {
+        &self.frame
+    }
+}
+
+/// A frame pushed to a `VideoSink`.
+#[derive(Clone, Copy)]
+pub enum VideoFrameRef<'a> {
+    /// A screen share frame
+    Nv12(&'a Nv12Frame),
+    /// A camera frame
+    I420(&'a I420Frame),
+}
+
+/// Where the frames of a published video track are pushed.
+pub trait VideoSink: Debug + Send + Sync {
+    /// Hands `frame` to the transport, it is encoded and sent when the
+    /// transport is ready for it.
+    fn push_frame(&self, frame: VideoFrameRef<'_>);
+}
+
+/// 16 bit PCM samples pushed to an `AudioSink`, the channels interleaved.
+#[derive(Debug, Clone, Copy)]
+pub struct AudioSamples<'a> {
+    pub data: &'a [i16],
+    pub sample_rate: u32,
+    pub num_channels: u32,
+    pub samples_per_channel: u32,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to push samples: {0}")]
+pub struct AudioSinkError(pub String);
+
+/// Where the samples of a published audio track are pushed.
+pub trait AudioSink: Debug + Send + Sync {
+    /// Hands `samples` to the transport, completes once it buffered them.
+    fn push_samples<'a>(
+        &'a self,
+        samples: AudioSamples<'a>,
+    ) -> BoxFuture<'a, Result<(), AudioSinkError>>;
+}
+
+/// The sink of a published video track, shared by the capture code.
+pub type SharedVideoSink = Arc<dyn VideoSink>;
+
+/// The sink of a published audio track, shared by the capture code.
+pub type SharedAudioSink = Arc<dyn AudioSink>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_planes() {
+        let frame = Nv12Frame::new(4, 2);
+        assert_eq!((frame.width(), frame.height()), (4, 2));
+        let [y, uv] = frame.planes();
+        assert_eq!(y.data.len(), (y.stride * 2) as usize);
+        assert_eq!(uv.data.len(), uv.stride as usize);
+
+        let frame = I420Frame::new(4, 2);
+        let [y, u, v] = frame.planes();
+        assert_eq!(y.data.len(), (y.stride * 2) as usize);
+        assert_eq!(u.data.len(), u.stride as usize);
+        assert_eq!(v.data.len(), v.stride as usize);
+    }
+}
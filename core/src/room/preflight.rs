@@ -0,0 +1,709 @@
+//! Network pre-flight of the LiveKit server.
+//!
+//! Restrictive networks block UDP, WebSockets or everything that doesn't go
+//! through their proxy, which users only notice when a call fails in the
+//! middle of a share. The pre-flight runs the steps a call needs one by one
+//! from the core process, where the media is published from, and reports
+//! each result with guidance the user can act on or forward to their IT
+//! department.
+//!
+//! Without a token the checks stop short of joining: the signaling endpoint
+//! rejects the upgrade and the TURN server challenges the allocation, both
+//! answers prove that the path to the server is open.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use base64::prelude::*;
+use socket_lib::{
+    MediaPath, NetworkPreflightReport, PreflightCheck, PreflightCheckKind, PreflightStatus,
+};
+use thiserror::Error;
+
+use crate::event_sender::EventSender;
+use crate::utils::livekit_url::{self, HealthCheckTarget};
+use crate::UserEvent;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+/// UDP checks send the request again when no answer arrived within this
+const STUN_RETRANSMIT_INTERVAL: Duration = Duration::from_secs(1);
+const STUN_ATTEMPTS: u32 = 3;
+
+/// LiveKit's default TURN port, for UDP and TCP
+const TURN_PORT: u16 = 3478;
+/// LiveKit's default ICE over TCP port
+const ICE_TCP_PORT: u16 = 7881;
+/// LiveKit's default ICE over UDP port range, quoted in the guidance
+const ICE_UDP_PORTS: &str = "50000-60000";
+const PUBLIC_STUN_SERVER: &str = "stun.l.google.com:19302";
+
+/// The LiveKit server has nothing large to download, the bandwidth is
+/// measured against a public speed test endpoint instead.
+const BANDWIDTH_TEST_URL: &str = "https://speed.cloudflare.com/__down?bytes=4000000";
+const BANDWIDTH_TEST_DURATION: Duration = Duration::from_secs(5);
+/// Below this a full HD share drops its resolution
+const MIN_SHARE_BANDWIDTH_MBPS: f64 = 3.0;
+
+const STUN_HEADER_LEN: usize = 20;
+const STUN_MAGIC_COOKIE: u32 = 0x2112_a442;
+const STUN_BINDING: u16 = 0x0001;
+const TURN_ALLOCATE: u16 = 0x0003;
+const ATTR_ERROR_CODE: u16 = 0x0009;
+const ATTR_REQUESTED_TRANSPORT: u16 = 0x0019;
+const IPPROTO_UDP: u8 = 17;
+
+#[derive(Error, Debug)]
+pub enum PreflightError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to create TLS connector: {0}")]
+    Tls(#[from] native_tls::Error),
+    #[error("TLS handshake failed: {0}")]
+    TlsHandshake(String),
+    #[error("Request failed: {0}")]
+    Http(String),
+    #[error("Unexpected answer")]
+    UnexpectedAnswer,
+}
+
+/// Answer of a STUN or TURN server.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StunResponse {
+    Success,
+    /// The error code, 401 when a TURN server wants credentials
+    Error(u16),
+}
+
+/// Encodes a STUN request, an allocation asks for a UDP relay.
+///
+/// # Arguments
+///
+/// * `method` - `STUN_BINDING` or `TURN_ALLOCATE`
+/// * `transaction_id` - Matches the answer to the request
+pub fn stun_request(method: u16, transaction_id: &[u8; 12]) -> Vec<u8> {
+    let mut attributes = vec![];
+    if method == TURN_ALLOCATE {
+        attributes.extend_from_slice(&ATTR_REQUESTED_TRANSPORT.to_be_bytes());
+        attributes.extend_from_slice(&4u16.to_be_bytes());
+        attributes.extend_from_slice(&[IPPROTO_UDP, 0, 0, 0]);
+    }
+
+    let mut request = Vec::with_capacity(STUN_HEADER_LEN + attributes.len());
+    request.extend_from_slice(&method.to_be_bytes());
+    request.extend_from_slice(&(attributes.len() as u16).to_be_bytes());
+    request.extend_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+    request.extend_from_slice(transaction_id);
+    request.extend_from_slice(&attributes);
+    request
+}
+
+/// Decodes the answer to the request with `transaction_id`.
+///
+/// # Returns
+///
+/// `None` when `buf` isn't a STUN answer to the request.
+pub fn parse_stun_response(buf: &[u8], transaction_id: &[u8; 12]) -> Option<StunResponse> {
+    if buf.len() < STUN_HEADER_LEN {
+        return None;
+    }
+    let message_type = u16::from_be_bytes([buf[0], buf[1]]);
+    let length = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+    if message_type & 0xc000 != 0
+        || buf[4..8] != STUN_MAGIC_COOKIE.to_be_bytes()
+        || buf[8..STUN_HEADER_LEN] != transaction_id[..]
+    {
+        return None;
+    }
+    let mut attributes = buf.get(STUN_HEADER_LEN..STUN_HEADER_LEN + length)?;
+
+    /* The class is encoded in bits 8 and 4 of the type. */
+    match message_type & 0x0110 {
+        0x0100 => Some(StunResponse::Success),
+        0x0110 => {
+            while attributes.len() >= 4 {
+                let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+                let len = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+                let value = attributes.get(4..4 + len)?;
+                if kind == ATTR_ERROR_CODE && len >= 4 {
+                    let code = (value[2] & 0x07) as u16 * 100 + value[3] as u16;
+                    return Some(StunResponse::Error(code));
+                }
+                /* Values are padded to a multiple of 4 bytes. */
+                attributes = attributes
+                    .get(4 + len.div_ceil(4) * 4..)
+                    .unwrap_or_default();
+            }
+            Some(StunResponse::Error(0))
+        }
+        _ => None,
+    }
+}
+
+/// Builds the request upgrading a connection to the signaling WebSocket.
+pub fn websocket_upgrade_request(host: &str, port: u16, key: &str) -> String {
+    let host = if host.contains(':') {
+        format!("[{host}]")
+    } else {
+        host.to_string()
+    };
+    format!(
+        "GET /rtc HTTP/1.1\r\nHost: {host}:{port}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+    )
+}
+
+/// Returns the status code of an HTTP response, `None` when it isn't HTTP.
+pub fn parse_status_line(response: &str) -> Option<u16> {
+    let mut parts = response.lines().next()?.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+/// Download speed in Mbit/s.
+pub fn mbps(bytes: u64, elapsed: Duration) -> f64 {
+    bytes as f64 * 8.0 / elapsed.as_secs_f64().max(0.001) / 1_000_000.0
+}
+
+/// Picks the best way for the media to reach the server from the checks.
+///
+/// A public STUN server answering over UDP is enough for UDP, the LiveKit
+/// server can run without TURN.
+pub fn media_path(checks: &[PreflightCheck]) -> MediaPath {
+    let passed = |kind: PreflightCheckKind| {
+        checks
+            .iter()
+            .any(|check| check.kind == kind && check.status == PreflightStatus::Passed)
+    };
+    if !passed(PreflightCheckKind::Dns) {
+        MediaPath::Blocked
+    } else if passed(PreflightCheckKind::TurnUdp) || passed(PreflightCheckKind::UdpEgress) {
+        MediaPath::Udp
+    } else if passed(PreflightCheckKind::TurnTcp) || passed(PreflightCheckKind::IceTcp) {
+        MediaPath::Tcp
+    } else if passed(PreflightCheckKind::Signaling) {
+        MediaPath::TlsOnly
+    } else {
+        MediaPath::Blocked
+    }
+}
+
+struct Outcome {
+    status: PreflightStatus,
+    detail: String,
+    guidance: Option<String>,
+}
+
+impl Outcome {
+    fn passed(detail: String) -> Self {
+        Self {
+            status: PreflightStatus::Passed,
+            detail,
+            guidance: None,
+        }
+    }
+
+    fn warning(detail: String, guidance: String) -> Self {
+        Self {
+            status: PreflightStatus::Warning,
+            detail,
+            guidance: Some(guidance),
+        }
+    }
+
+    fn failed(detail: String, guidance: String) -> Self {
+        Self {
+            status: PreflightStatus::Failed,
+            detail,
+            guidance: Some(guidance),
+        }
+    }
+}
+
+fn finish(kind: PreflightCheckKind, started: Instant, outcome: Outcome) -> PreflightCheck {
+    log::info!("finish: {kind:?} {:?}: {}", outcome.status, outcome.detail);
+    PreflightCheck {
+        kind,
+        status: outcome.status,
+        detail: outcome.detail,
+        guidance: outcome.guidance,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+fn timed(kind: PreflightCheckKind, check: impl FnOnce() -> Outcome) -> PreflightCheck {
+    let started = Instant::now();
+    finish(kind, started, check())
+}
+
+fn skipped(kind: PreflightCheckKind) -> PreflightCheck {
+    PreflightCheck {
+        kind,
+        status: PreflightStatus::Skipped,
+        detail: "an earlier check failed".to_string(),
+        guidance: None,
+        duration_ms: 0,
+    }
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    for chunk in bytes.chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish().to_be_bytes();
+        chunk.copy_from_slice(&random[..chunk.len()]);
+    }
+    bytes
+}
+
+fn exchange(stream: &mut (impl Read + Write), request: &str) -> io::Result<String> {
+    stream.write_all(request.as_bytes())?;
+    let mut buf = [0; 1024];
+    let read = stream.read(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf[..read]).into_owned())
+}
+
+fn signaling_handshake(
+    target: &HealthCheckTarget,
+    address: SocketAddr,
+) -> Result<String, PreflightError> {
+    let mut stream = TcpStream::connect_timeout(&address, CHECK_TIMEOUT)?;
+    stream.set_read_timeout(Some(CHECK_TIMEOUT))?;
+    stream.set_write_timeout(Some(CHECK_TIMEOUT))?;
+    let key = BASE64_STANDARD.encode(random_bytes::<16>());
+    let request = websocket_upgrade_request(&target.host, target.port, &key);
+
+    if !target.http_url.starts_with("https") {
+        return Ok(exchange(&mut stream, &request)?);
+    }
+    let connector = native_tls::TlsConnector::new()?;
+    let mut stream = connector
+        .connect(&target.host, stream)
+        .map_err(|e| PreflightError::TlsHandshake(e.to_string()))?;
+    Ok(exchange(&mut stream, &request)?)
+}
+
+fn check_signaling(target: &HealthCheckTarget, address: SocketAddr) -> Outcome {
+    let endpoint = format!("{}:{}", target.host, target.port);
+    let response = match signaling_handshake(target, address) {
+        Ok(response) => response,
+        Err(PreflightError::TlsHandshake(e)) => {
+            return Outcome::failed(
+                format!("TLS handshake with {endpoint} failed: {e}"),
+                "A proxy inspecting TLS traffic may be intercepting the connection. Ask your IT department to exempt the LiveKit server from TLS inspection.".to_string(),
+            )
+        }
+        Err(e) => {
+            return Outcome::failed(
+                format!("no answer from {endpoint}: {e}"),
+                format!("Connections to {endpoint} are blocked. Ask your IT department to allow secure WebSocket connections to it."),
+            )
+        }
+    };
+
+    match parse_status_line(&response) {
+        Some(407) => Outcome::failed(
+            "a proxy asked for authentication".to_string(),
+            "Your network only allows connections through an authenticating proxy. Ask your IT department to allow direct connections to the LiveKit server.".to_string(),
+        ),
+        Some(code @ (101 | 200..=406 | 408..=499)) => Outcome::passed(format!(
+            "{endpoint} answered the WebSocket upgrade with HTTP {code}"
+        )),
+        Some(code) => Outcome::warning(
+            format!("{endpoint} answered the WebSocket upgrade with HTTP {code}"),
+            "The server or a proxy in between is failing, try again later. If it persists, a proxy may be blocking WebSockets.".to_string(),
+        ),
+        None => Outcome::failed(
+            format!("{endpoint} didn't answer with HTTP"),
+            "A firewall or proxy is interfering with the connection. Ask your IT department to allow secure WebSocket connections to the LiveKit server.".to_string(),
+        ),
+    }
+}
+
+fn stun_udp(address: SocketAddr, method: u16) -> Result<StunResponse, PreflightError> {
+    let local = if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(STUN_RETRANSMIT_INTERVAL))?;
+    socket.connect(address)?;
+
+    let transaction_id = random_bytes();
+    let request = stun_request(method, &transaction_id);
+    let mut buf = [0; 1024];
+    for _ in 0..STUN_ATTEMPTS {
+        socket.send(&request)?;
+        match socket.recv(&mut buf) {
+            Ok(read) => {
+                return parse_stun_response(&buf[..read], &transaction_id)
+                    .ok_or(PreflightError::UnexpectedAnswer)
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "no answer").into())
+}
+
+fn stun_tcp(address: SocketAddr, method: u16) -> Result<StunResponse, PreflightError> {
+    let mut stream = TcpStream::connect_timeout(&address, CHECK_TIMEOUT)?;
+    stream.set_read_timeout(Some(CHECK_TIMEOUT))?;
+    stream.set_write_timeout(Some(CHECK_TIMEOUT))?;
+
+    let transaction_id = random_bytes();
+    stream.write_all(&stun_request(method, &transaction_id))?;
+    let mut response = vec![0; STUN_HEADER_LEN];
+    stream.read_exact(&mut response)?;
+    let length = u16::from_be_bytes([response[2], response[3]]) as usize;
+    response.resize(STUN_HEADER_LEN + length, 0);
+    stream.read_exact(&mut response[STUN_HEADER_LEN..])?;
+    parse_stun_response(&response, &transaction_id).ok_or(PreflightError::UnexpectedAnswer)
+}
+
+fn turn_outcome(
+    result: Result<StunResponse, PreflightError>,
+    endpoint: &str,
+    guidance: String,
+) -> Outcome {
+    match result {
+        Ok(StunResponse::Success) => Outcome::passed(format!("{endpoint} allocated a relay")),
+        Ok(StunResponse::Error(401)) => Outcome::passed(format!(
+            "{endpoint} asked for credentials, the allocation can go through"
+        )),
+        Ok(StunResponse::Error(code)) => {
+            Outcome::passed(format!("{endpoint} answered with error {code}"))
+        }
+        Err(e) => Outcome::failed(format!("no TURN answer from {endpoint}: {e}"), guidance),
+    }
+}
+
+fn check_udp_egress() -> Outcome {
+    let address = PUBLIC_STUN_SERVER
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| addresses.find(|address| address.is_ipv4()));
+    let Some(address) = address else {
+        return Outcome::warning(
+            format!("{PUBLIC_STUN_SERVER} doesn't resolve"),
+            "Public names don't resolve on this network, the UDP check couldn't run.".to_string(),
+        );
+    };
+    match stun_udp(address, STUN_BINDING) {
+        Ok(_) => Outcome::passed(format!("{PUBLIC_STUN_SERVER} answered over UDP")),
+        Err(e) => Outcome::warning(
+            format!("no answer from {PUBLIC_STUN_SERVER}: {e}"),
+            "Outgoing UDP is blocked on this network, calls fall back to TCP with a higher latency. Ask your IT department to allow outgoing UDP for a smoother call.".to_string(),
+        ),
+    }
+}
+
+fn measure_bandwidth() -> Result<f64, PreflightError> {
+    let agent = ureq::AgentBuilder::new()
+        .tls_connector(Arc::new(native_tls::TlsConnector::new()?))
+        .timeout_connect(CHECK_TIMEOUT)
+        .timeout_read(CHECK_TIMEOUT)
+        .build();
+    let response = agent
+        .get(BANDWIDTH_TEST_URL)
+        .call()
+        .map_err(|e| PreflightError::Http(e.to_string()))?;
+
+    /* Timed from the first byte, the connection setup isn't bandwidth. */
+    let started = Instant::now();
+    let mut reader = response.into_reader();
+    let mut buf = [0; 16 * 1024];
+    let mut total = 0u64;
+    while started.elapsed() < BANDWIDTH_TEST_DURATION {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        total += read as u64;
+    }
+    if total == 0 {
+        return Err(PreflightError::UnexpectedAnswer);
+    }
+    Ok(mbps(total, started.elapsed()))
+}
+
+fn check_bandwidth(bandwidth_mbps: &mut Option<f64>) -> Outcome {
+    match measure_bandwidth() {
+        Ok(measured) if measured >= MIN_SHARE_BANDWIDTH_MBPS => {
+            *bandwidth_mbps = Some(measured);
+            Outcome::passed(format!("about {measured:.1} Mbit/s"))
+        }
+        Ok(measured) => {
+            *bandwidth_mbps = Some(measured);
+            Outcome::warning(
+                format!("about {measured:.1} Mbit/s"),
+                "The connection is slow, shares will use a lower resolution. Close other downloads or move closer to the Wi-Fi access point.".to_string(),
+            )
+        }
+        Err(e) => Outcome::warning(
+            format!("couldn't measure the bandwidth: {e}"),
+            "The speed test endpoint is unreachable, this doesn't affect calls by itself."
+                .to_string(),
+        ),
+    }
+}
+
+/// Runs every check against the LiveKit server at `url`.
+///
+/// The checks that need the server are skipped when the URL is missing or
+/// doesn't resolve, the UDP and bandwidth checks always run. Takes from a few
+/// seconds to about half a minute on a network that drops the probes.
+///
+/// # Arguments
+///
+/// * `url` - The LiveKit server URL, `None` when the core has none
+pub fn run_preflight(url: Option<&str>) -> NetworkPreflightReport {
+    let mut checks = vec![];
+
+    let started = Instant::now();
+    let target = match url.map(livekit_url::health_check_target) {
+        Some(Ok(target)) => {
+            checks.push(finish(
+                PreflightCheckKind::ServerUrl,
+                started,
+                Outcome::passed(target.http_url.clone()),
+            ));
+            Some(target)
+        }
+        Some(Err(e)) => {
+            checks.push(finish(
+                PreflightCheckKind::ServerUrl,
+                started,
+                Outcome::failed(
+                    e.to_string(),
+                    "Sign out and in again to fetch the server address.".to_string(),
+                ),
+            ));
+            None
+        }
+        None => {
+            checks.push(finish(
+                PreflightCheckKind::ServerUrl,
+                started,
+                Outcome::failed(
+                    "the core has no LiveKit server URL".to_string(),
+                    "Restart the app so it fetches the server address.".to_string(),
+                ),
+            ));
+            None
+        }
+    };
+
+    let started = Instant::now();
+    let address = target.as_ref().and_then(|target| {
+        let address = (target.host.as_str(), target.port)
+            .to_socket_addrs()
+            .map(|mut addresses| addresses.next());
+        let outcome = match &address {
+            Ok(Some(address)) => Outcome::passed(format!("{} is {}", target.host, address.ip())),
+            Ok(None) => Outcome::failed(
+                format!("{} has no addresses", target.host),
+                "Check your DNS settings or VPN.".to_string(),
+            ),
+            Err(e) => Outcome::failed(
+                format!("{} doesn't resolve: {e}", target.host),
+                "Check your DNS settings or VPN, the network may only resolve internal names."
+                    .to_string(),
+            ),
+        };
+        checks.push(finish(PreflightCheckKind::Dns, started, outcome));
+        address.ok().flatten()
+    });
+    if target.is_none() {
+        checks.push(skipped(PreflightCheckKind::Dns));
+    }
+
+    match (&target, address) {
+        (Some(target), Some(address)) => {
+            let turn = SocketAddr::new(address.ip(), TURN_PORT);
+            let turn_endpoint = format!("{}:{TURN_PORT}", target.host);
+            checks.push(timed(PreflightCheckKind::Signaling, || {
+                check_signaling(target, address)
+            }));
+            checks.push(timed(PreflightCheckKind::TurnUdp, || {
+                turn_outcome(
+                    stun_udp(turn, TURN_ALLOCATE),
+                    &turn_endpoint,
+                    format!("UDP to {turn_endpoint} is blocked or the server has no TURN. Ask your IT department to allow UDP port {TURN_PORT} and {ICE_UDP_PORTS} to the LiveKit server."),
+                )
+            }));
+            checks.push(timed(PreflightCheckKind::TurnTcp, || {
+                turn_outcome(
+                    stun_tcp(turn, TURN_ALLOCATE),
+                    &turn_endpoint,
+                    format!("TCP to {turn_endpoint} is blocked or the server has no TURN. Ask your IT department to allow TCP port {TURN_PORT} to the LiveKit server."),
+                )
+            }));
+            checks.push(timed(PreflightCheckKind::IceTcp, || {
+                let ice = SocketAddr::new(address.ip(), ICE_TCP_PORT);
+                let endpoint = format!("{}:{ICE_TCP_PORT}", target.host);
+                match TcpStream::connect_timeout(&ice, CHECK_TIMEOUT) {
+                    Ok(_) => Outcome::passed(format!("{endpoint} accepts connections")),
+                    Err(e) => Outcome::failed(
+                        format!("can't connect to {endpoint}: {e}"),
+                        format!("Media can't fall back to TCP. Ask your IT department to allow TCP port {ICE_TCP_PORT} to the LiveKit server."),
+                    ),
+                }
+            }));
+        }
+        _ => {
+            for kind in [
+                PreflightCheckKind::Signaling,
+                PreflightCheckKind::TurnUdp,
+                PreflightCheckKind::TurnTcp,
+                PreflightCheckKind::IceTcp,
+            ] {
+                checks.push(skipped(kind));
+            }
+        }
+    }
+
+    checks.push(timed(PreflightCheckKind::UdpEgress, check_udp_egress));
+    let mut bandwidth_mbps = None;
+    checks.push(timed(PreflightCheckKind::Bandwidth, || {
+        check_bandwidth(&mut bandwidth_mbps)
+    }));
+
+    NetworkPreflightReport {
+        server: url.map(str::to_string),
+        media_path: media_path(&checks),
+        checks,
+        bandwidth_mbps,
+    }
+}
+
+/// Runs the pre-flight in a background thread and sends the report as
+/// `UserEvent::NetworkPreflightFinished`.
+///
+/// # Arguments
+///
+/// * `event_sender` - Where the report is sent
+/// * `url` - The LiveKit server URL, `None` when the core has none
+pub fn run_in_background(event_sender: Box<dyn EventSender>, url: Option<String>) {
+    std::thread::spawn(move || {
+        let report = run_preflight(url.as_deref());
+        log::info!(
+            "run_in_background: network preflight finished, media path {:?}",
+            report.media_path
+        );
+        if let Err(e) = event_sender.send(UserEvent::NetworkPreflightFinished(report)) {
+            log::error!("run_in_background: error sending report: {e:?}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(kind: PreflightCheckKind, status: PreflightStatus) -> PreflightCheck {
+        PreflightCheck {
+            kind,
+            status,
+            detail: String::new(),
+            guidance: None,
+            duration_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_stun_request() {
+        let id = [7; 12];
+        let binding = stun_request(STUN_BINDING, &id);
+        assert_eq!(binding.len(), STUN_HEADER_LEN);
+        assert_eq!(&binding[..8], &[0, 1, 0, 0, 0x21, 0x12, 0xa4, 0x42]);
+        assert_eq!(&binding[8..], &id);
+
+        let allocate = stun_request(TURN_ALLOCATE, &id);
+        assert_eq!(&allocate[..4], &[0, 3, 0, 8]);
+        assert_eq!(&allocate[STUN_HEADER_LEN..], &[0, 0x19, 0, 4, 17, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_parse_stun_response() {
+        let id = [3; 12];
+        let mut success = stun_request(STUN_BINDING, &id);
+        success[0..2].copy_from_slice(&0x0101u16.to_be_bytes());
+        assert_eq!(
+            parse_stun_response(&success, &id),
+            Some(StunResponse::Success)
+        );
+        assert_eq!(parse_stun_response(&success, &[4; 12]), None);
+        assert_eq!(parse_stun_response(&success[..10], &id), None);
+
+        /* An allocate error with a SOFTWARE attribute before the 401 ERROR-CODE. */
+        let mut error = stun_request(STUN_BINDING, &id);
+        error[0..2].copy_from_slice(&0x0113u16.to_be_bytes());
+        error.extend_from_slice(&[0x80, 0x22, 0, 5, b'h', b'o', b'p', b'p', b'!', 0, 0, 0]);
+        error.extend_from_slice(&[0, 0x09, 0, 4, 0, 0, 4, 1]);
+        let length = (error.len() - STUN_HEADER_LEN) as u16;
+        error[2..4].copy_from_slice(&length.to_be_bytes());
+        assert_eq!(
+            parse_stun_response(&error, &id),
+            Some(StunResponse::Error(401))
+        );
+
+        /* A request isn't an answer. */
+        assert_eq!(
+            parse_stun_response(&stun_request(TURN_ALLOCATE, &id), &id),
+            None
+        );
+    }
+
+    #[test]
+    fn test_http_helpers() {
+        assert_eq!(
+            parse_status_line("HTTP/1.1 401 Unauthorized\r\n\r\n"),
+            Some(401)
+        );
+        assert_eq!(
+            parse_status_line("HTTP/1.1 101 Switching Protocols"),
+            Some(101)
+        );
+        assert_eq!(parse_status_line("SSH-2.0-OpenSSH"), None);
+        assert_eq!(parse_status_line(""), None);
+
+        let request = websocket_upgrade_request("::1", 7880, "key");
+        assert!(request.starts_with("GET /rtc HTTP/1.1\r\nHost: [::1]:7880\r\n"));
+        assert!(request.ends_with("\r\n\r\n"));
+
+        assert_eq!(mbps(1_000_000, Duration::from_secs(2)), 4.0);
+    }
+
+    #[test]
+    fn test_media_path() {
+        use PreflightCheckKind::*;
+        use PreflightStatus::*;
+
+        let mut checks = vec![
+            check(Dns, Passed),
+            check(Signaling, Passed),
+            check(TurnUdp, Failed),
+            check(TurnTcp, Failed),
+            check(IceTcp, Failed),
+            check(UdpEgress, Warning),
+        ];
+        assert_eq!(media_path(&checks), MediaPath::TlsOnly);
+        checks[4].status = Passed;
+        assert_eq!(media_path(&checks), MediaPath::Tcp);
+        checks[5].status = Passed;
+        assert_eq!(media_path(&checks), MediaPath::Udp);
+
+        checks[0].status = Failed;
+        assert_eq!(media_path(&checks), MediaPath::Blocked);
+        assert_eq!(media_path(&[]), MediaPath::Blocked);
+    }
+}
@@ -0,0 +1,415 @@
+//! Track and data publishing to the room.
+//!
+//! The screen share, camera, microphone and system audio track setup and the
+//! data-channel publishing live here.
+//! Data publishing goes through the `DataPublisher` trait so the command
+//! handling doesn't depend on the LiveKit participant directly.
+
+use std::future::Future;
+use std::time::Duration;
+
+use livekit::options::{TrackPublishOptions, VideoCodec, VideoEncoding};
+use livekit::participant::LocalParticipant;
+use livekit::prelude::{ParticipantIdentity, TrackSid};
+use livekit::track::{LocalAudioTrack, LocalTrack, LocalVideoTrack, TrackSource};
+use livekit::webrtc::audio_source::native::NativeAudioSource;
+use livekit::webrtc::audio_source::{AudioSourceOptions, RtcAudioSource};
+use livekit::webrtc::prelude::{RtcVideoSource, VideoResolution};
+use livekit::webrtc::stats::RtcStats;
+use livekit::webrtc::video_source::native::NativeVideoSource;
+use livekit::DataPacket;
+use socket_lib::AudioProcessing;
+
+use super::adaptive_bitrate::NetworkSample;
+use super::client_event::ClientEvent;
+
+const VIDEO_TRACK_NAME: &str = "screen_share";
+const CAMERA_TRACK_NAME: &str = "camera";
+/* The camera is a small tile next to the screen share, it doesn't need the screen's bitrate. */
+const CAMERA_MAX_BITRATE: u64 = 1_000_000; // 1 Mbps
+const MICROPHONE_TRACK_NAME: &str = "microphone";
+/* Samples buffered in the audio source before the oldest are dropped. */
+const MICROPHONE_QUEUE_MS: u32 = 1000;
+const SYSTEM_AUDIO_TRACK_NAME: &str = "screen_share_audio";
+const MAX_FRAMERATE: f64 = 30.0;
+/// Max framerate of the screen share when the share doesn't request one
+pub const DEFAULT_SCREEN_SHARE_FRAMERATE: u32 = MAX_FRAMERATE as u32;
+/* Must match the codec in `publish_screen_share_track`. */
+const VIDEO_CODEC_NAME: &str = "VP9";
+
+// Bitrate constants (in bits per second)
+const BITRATE_1920: u64 = 2_000_000; // 2 Mbps
+const BITRATE_2048: u64 = 3_500_000; // 3.5 Mbps
+const BITRATE_2560: u64 = 5_000_000; // 5 Mbps
+const BITRATE_DEFAULT: u64 = 8_000_000; // 8 Mbps
+
+// Resolution thresholds
+const WIDTH_THRESHOLD_1920: u32 = 1920;
+const WIDTH_THRESHOLD_2048: u32 = 2048;
+const WIDTH_THRESHOLD_2560: u32 = 2560;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PublishError {
+    #[error("Failed to serialize event: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Failed to publish: {0}")]
+    Publish(String),
+}
+
+/// How a transport encodes the published video, reported to the tauri app
+/// when a share starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoEncodingInfo {
+    pub codec: &'static str,
+    pub max_framerate: u32,
+    /// Max bitrate in bits per second, `None` when it isn't limited
+    pub max_bitrate: Option<u64>,
+}
+
+/// Publishes client events on a data-channel topic.
+pub trait DataPublisher {
+    /// Publishes `event` on `topic`.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - The data-channel topic
+    /// * `event` - The event to publish
+    /// * `reliable` - Whether the event is retransmitted until it arrives, an
+    ///   unreliable one can be lost or overtaken but doesn't wait for the ones before it
+    /// * `compress` - Whether a bulky payload is compressed, only when every
+    ///   participant can inflate it
+    /// * `destination` - The identity the event is sent to, `None` for everyone
+    fn publish_event(
+        &self,
+        topic: &str,
+        event: &ClientEvent,
+        reliable: bool,
+        compress: bool,
+        destination: Option<&str>,
+    ) -> impl Future<Output = Result<(), PublishError>> + Send;
+}
+
+impl DataPublisher for LocalParticipant {
+    fn publish_event(
+        &self,
+        topic: &str,
+        event: &ClientEvent,
+        reliable: bool,
+        compress: bool,
+        destination: Option<&str>,
+    ) -> impl Future<Output = Result<(), PublishError>> + Send {
+        let payload = if compress {
+            event.encode_compressed()
+        } else {
+            event.encode()
+        };
+        let topic = topic.to_string();
+        let destination_identities = destination
+            .map(|identity| vec![ParticipantIdentity(identity.to_string())])
+            .unwrap_or_default();
+        async move {
+            self.publish_data(DataPacket {
+                payload: payload?,
+                reliable,
+                topic: Some(topic),
+                destination_identities,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| PublishError::Publish(format!("{e:?}")))
+        }
+    }
+}
+
+/// Returns the max bitrate of the screen share track for a stream width.
+pub fn max_bitrate_for_width(width: u32) -> u64 {
+    match width {
+        WIDTH_THRESHOLD_1920 => BITRATE_1920,
+        WIDTH_THRESHOLD_2048 => BITRATE_2048,
+        WIDTH_THRESHOLD_2560 => BITRATE_2560,
+        _ => BITRATE_DEFAULT,
+    }
+}
+
+/// Returns the encoding `publish_screen_share_track` configures for a stream
+/// width and max framerate.
+pub fn screen_share_encoding(width: u32, max_framerate: u32) -> VideoEncodingInfo {
+    VideoEncodingInfo {
+        codec: VIDEO_CODEC_NAME,
+        max_framerate,
+        max_bitrate: Some(max_bitrate_for_width(width)),
+    }
+}
+
+/// Creates the screen share video track and publishes it to the room.
+///
+/// The track is configured with VP9 codec and a max bitrate based on width.
+///
+/// # Arguments
+///
+/// * `local_participant` - The local participant of the connected room
+/// * `width` - The width of the video track
+/// * `height` - The height of the video track
+/// * `max_framerate` - The framerate the encoder is capped at
+///
+/// # Returns
+///
+/// * `Ok((NativeVideoSource, LocalVideoTrack, TrackSid))` - The source frames
+///   need to be pushed to, the track, see `set_video_encoding`, and the
+///   published track, see `unpublish_track`
+/// * `Err(PublishError)` - The track couldn't be published
+pub async fn publish_screen_share_track(
+    local_participant: &LocalParticipant,
+    width: u32,
+    height: u32,
+    max_framerate: u32,
+) -> Result<(NativeVideoSource, LocalVideoTrack, TrackSid), PublishError> {
+    let buffer_source = NativeVideoSource::new(VideoResolution { width, height });
+    let track = LocalVideoTrack::create_video_track(
+        VIDEO_TRACK_NAME,
+        RtcVideoSource::Native(buffer_source.clone()),
+    );
+
+    let publication = local_participant
+        .publish_track(
+            LocalTrack::Video(track.clone()),
+            TrackPublishOptions {
+                source: TrackSource::Screenshare,
+                video_codec: VideoCodec::VP9,
+                video_encoding: Some(VideoEncoding {
+                    max_bitrate: max_bitrate_for_width(width),
+                    max_framerate: max_framerate as f64,
+                }),
+                simulcast: false,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| PublishError::Publish(format!("{e:?}")))?;
+
+    Ok((buffer_source, track, publication.sid()))
+}
+
+/// Changes the encoding of the published screen share track, without
+/// publishing it again.
+///
+/// # Arguments
+///
+/// * `track` - The track returned by `publish_screen_share_track`
+/// * `encoding` - The encoding to apply, see `adaptive_bitrate::BitrateAdapter`
+pub fn set_video_encoding(
+    track: &LocalVideoTrack,
+    encoding: VideoEncodingInfo,
+) -> Result<(), PublishError> {
+    let sender = track
+        .transceiver()
+        .ok_or_else(|| PublishError::Publish("track isn't published".to_string()))?
+        .sender();
+    let mut parameters = sender.parameters();
+    for parameters in parameters.encodings.iter_mut() {
+        parameters.max_bitrate = encoding.max_bitrate;
+        parameters.max_framerate = Some(encoding.max_framerate as f64);
+    }
+    sender
+        .set_parameters(parameters)
+        .map_err(|e| PublishError::Publish(format!("{e:?}")))
+}
+
+/// Returns the network conditions of the published screen share track,
+/// from the receivers' last report.
+///
+/// # Returns
+///
+/// `None` when the stats can't be read or no report arrived yet.
+pub async fn video_network_sample(track: &LocalVideoTrack) -> Option<NetworkSample> {
+    let stats = match track.get_stats().await {
+        Ok(stats) => stats,
+        Err(e) => {
+            log::warn!("video_network_sample: Failed to get stats: {e:?}");
+            return None;
+        }
+    };
+    stats.into_iter().find_map(|stats| match stats {
+        RtcStats::RemoteInboundRtp(stats) => Some(NetworkSample {
+            packet_loss: stats.remote_inbound.fraction_lost,
+            rtt: Duration::from_secs_f64(stats.remote_inbound.round_trip_time.max(0.0)),
+        }),
+        _ => None,
+    })
+}
+
+/// Creates the camera video track and publishes it next to the screen share.
+///
+/// # Arguments
+///
+/// * `local_participant` - The local participant of the connected room
+/// * `width` - The width of the camera frames
+/// * `height` - The height of the camera frames
+///
+/// # Returns
+///
+/// * `Ok((NativeVideoSource, TrackSid))` - The source frames need to be pushed
+///   to and the published track, see `unpublish_track`
+/// * `Err(PublishError)` - The track couldn't be published
+pub async fn publish_camera_track(
+    local_participant: &LocalParticipant,
+    width: u32,
+    height: u32,
+) -> Result<(NativeVideoSource, TrackSid), PublishError> {
+    let buffer_source = NativeVideoSource::new(VideoResolution { width, height });
+    let track = LocalVideoTrack::create_video_track(
+        CAMERA_TRACK_NAME,
+        RtcVideoSource::Native(buffer_source.clone()),
+    );
+
+    let publication = local_participant
+        .publish_track(
+            LocalTrack::Video(track),
+            TrackPublishOptions {
+                source: TrackSource::Camera,
+                video_codec: VideoCodec::VP8,
+                video_encoding: Some(VideoEncoding {
+                    max_bitrate: CAMERA_MAX_BITRATE,
+                    max_framerate: MAX_FRAMERATE,
+                }),
+                simulcast: false,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| PublishError::Publish(format!("{e:?}")))?;
+
+    Ok((buffer_source, publication.sid()))
+}
+
+/// Creates the microphone audio track and publishes it to the room.
+///
+/// The samples go through the webrtc audio processing module before they
+/// are encoded, `processing` picks its stages.
+///
+/// # Arguments
+///
+/// * `local_participant` - The local participant of the connected room
+/// * `processing` - The audio processing stages to enable
+/// * `sample_rate` - The sample rate of the microphone
+/// * `num_channels` - The channels of the microphone
+///
+/// # Returns
+///
+/// * `Ok((NativeAudioSource, TrackSid))` - The source samples need to be
+///   pushed to and the published track, see `unpublish_track`
+/// * `Err(PublishError)` - The track couldn't be published
+pub async fn publish_microphone_track(
+    local_participant: &LocalParticipant,
+    processing: AudioProcessing,
+    sample_rate: u32,
+    num_channels: u32,
+) -> Result<(NativeAudioSource, TrackSid), PublishError> {
+    let audio_source = NativeAudioSource::new(
+        AudioSourceOptions {
+            echo_cancellation: processing.echo_cancellation,
+            noise_suppression: processing.noise_suppression,
+            auto_gain_control: processing.auto_gain_control,
+        },
+        sample_rate,
+        num_channels,
+        MICROPHONE_QUEUE_MS,
+    );
+    let track = LocalAudioTrack::create_audio_track(
+        MICROPHONE_TRACK_NAME,
+        RtcAudioSource::Native(audio_source.clone()),
+    );
+
+    let publication = local_participant
+        .publish_track(
+            LocalTrack::Audio(track),
+            TrackPublishOptions {
+                source: TrackSource::Microphone,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| PublishError::Publish(format!("{e:?}")))?;
+
+    Ok((audio_source, publication.sid()))
+}
+
+/// Creates the system audio track and publishes it to the room.
+///
+/// The audio the machine plays isn't a voice, the webrtc audio processing is
+/// off so music and calls aren't cut or leveled.
+///
+/// # Arguments
+///
+/// * `local_participant` - The local participant of the connected room
+/// * `sample_rate` - The sample rate of the output device
+/// * `num_channels` - The channels of the output device
+///
+/// # Returns
+///
+/// * `Ok((NativeAudioSource, TrackSid))` - The source samples need to be
+///   pushed to and the published track, see `unpublish_track`
+/// * `Err(PublishError)` - The track couldn't be published
+pub async fn publish_audio_track(
+    local_participant: &LocalParticipant,
+    sample_rate: u32,
+    num_channels: u32,
+) -> Result<(NativeAudioSource, TrackSid), PublishError> {
+    let audio_source = NativeAudioSource::new(
+        AudioSourceOptions::default(),
+        sample_rate,
+        num_channels,
+        MICROPHONE_QUEUE_MS,
+    );
+    let track = LocalAudioTrack::create_audio_track(
+        SYSTEM_AUDIO_TRACK_NAME,
+        RtcAudioSource::Native(audio_source.clone()),
+    );
+
+    let publication = local_participant
+        .publish_track(
+            LocalTrack::Audio(track),
+            TrackPublishOptions {
+                source: TrackSource::ScreenshareAudio,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| PublishError::Publish(format!("{e:?}")))?;
+
+    Ok((audio_source, publication.sid()))
+}
+
+/// Stops publishing the track `sid` of the local participant.
+pub async fn unpublish_track(
+    local_participant: &LocalParticipant,
+    sid: &TrackSid,
+) -> Result<(), PublishError> {
+    local_participant
+        .unpublish_track(sid)
+        .await
+        .map(|_| ())
+        .map_err(|e| PublishError::Publish(format!("{e:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_bitrate_for_width() {
+        assert_eq!(max_bitrate_for_width(1920), BITRATE_1920);
+        assert_eq!(max_bitrate_for_width(2048), BITRATE_2048);
+        assert_eq!(max_bitrate_for_width(2560), BITRATE_2560);
+        assert_eq!(max_bitrate_for_width(3840), BITRATE_DEFAULT);
+    }
+
+    #[test]
+    fn test_screen_share_encoding() {
+        let encoding = screen_share_encoding(2560, DEFAULT_SCREEN_SHARE_FRAMERATE);
+        assert_eq!(encoding.codec, "VP9");
+        assert_eq!(encoding.max_framerate, 30);
+        assert_eq!(screen_share_encoding(2560, 15).max_framerate, 15);
+        assert_eq!(encoding.max_bitrate, Some(BITRATE_2560));
+    }
+}
@@ -0,0 +1,127 @@
+//! Connection quality of the participants, relayed to the sharer.
+//!
+//! LiveKit estimates the quality of every participant's connection from the
+//! stats of its tracks. The core keeps the latest quality of the admitted
+//! participants and forwards the map to the tauri app when it changes, so
+//! the sharer can see who is on a poor connection and lower the resolution
+//! or stop the video.
+
+use std::collections::HashMap;
+
+use socket_lib::{ConnectionQualityLevel, ParticipantQuality};
+
+/// The latest connection quality of the participants, by sid.
+#[derive(Debug, Default)]
+pub struct ParticipantQualities {
+    qualities: HashMap<String, (String, ConnectionQualityLevel)>,
+}
+
+impl ParticipantQualities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the connection quality of the participant `sid`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - Sid of the participant
+    /// * `name` - Name shown to the sharer
+    /// * `quality` - The new connection quality
+    ///
+    /// # Returns
+    ///
+    /// `true` if the quality changed and the map needs to be sent again.
+    pub fn update(&mut self, sid: String, name: String, quality: ConnectionQualityLevel) -> bool {
+        match self.qualities.insert(sid, (name, quality)) {
+            Some((_, previous)) => previous != quality,
+            None => true,
+        }
+    }
+
+    /// Forgets the participant `sid` that left the session.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the participant had a quality.
+    pub fn remove(&mut self, sid: &str) -> bool {
+        self.qualities.remove(sid).is_some()
+    }
+
+    /// Forgets the participants of the previous session.
+    pub fn clear(&mut self) {
+        self.qualities.clear();
+    }
+
+    /// Returns the qualities sorted by participant name.
+    pub fn snapshot(&self) -> Vec<ParticipantQuality> {
+        let mut qualities: Vec<ParticipantQuality> = self
+            .qualities
+            .iter()
+            .map(|(sid, (name, quality))| ParticipantQuality {
+                sid: sid.clone(),
+                name: name.clone(),
+                quality: *quality,
+            })
+            .collect();
+        qualities.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.sid.cmp(&b.sid)));
+        qualities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_reports_changes() {
+        let mut qualities = ParticipantQualities::new();
+        assert!(qualities.update(
+            "b".to_string(),
+            "Bob".to_string(),
+            ConnectionQualityLevel::Excellent
+        ));
+        assert!(!qualities.update(
+            "b".to_string(),
+            "Bob".to_string(),
+            ConnectionQualityLevel::Excellent
+        ));
+        assert!(qualities.update(
+            "b".to_string(),
+            "Bob".to_string(),
+            ConnectionQualityLevel::Poor
+        ));
+        assert_eq!(
+            qualities.snapshot(),
+            [ParticipantQuality {
+                sid: "b".to_string(),
+                name: "Bob".to_string(),
+                quality: ConnectionQualityLevel::Poor,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_is_sorted_and_remove() {
+        let mut qualities = ParticipantQualities::new();
+        qualities.update(
+            "c".to_string(),
+            "Carol".to_string(),
+            ConnectionQualityLevel::Good,
+        );
+        qualities.update(
+            "a".to_string(),
+            "Alice".to_string(),
+            ConnectionQualityLevel::Lost,
+        );
+        let names: Vec<_> = qualities.snapshot().into_iter().map(|q| q.name).collect();
+        assert_eq!(names, ["Alice", "Carol"]);
+
+        assert!(qualities.remove("a"));
+        assert!(!qualities.remove("a"));
+        assert_eq!(qualities.snapshot().len(), 1);
+
+        qualities.clear();
+        assert!(qualities.snapshot().is_empty());
+    }
+}
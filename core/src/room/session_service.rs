@@ -0,0 +1,673 @@
+//! The room side of a screen share session.
+//!
+//! The service owns the room service of the LiveKit server and what the
+//! sharer decides about the participants: who is admitted, whose input is
+//! injected, the raised hands and their connection quality. The state of the
+//! share is published to the room from here, so the participants joining
+//! later get it too.
+//!
+//! What the overlay, the journal and the tauri app need from it is posted on
+//! the `ServiceBus`, see `bus`.
+
+use socket_lib::{
+    AdmissionPolicy, ConnectionQualityLevel, ControlRequest, Message, SessionParticipant,
+    ShareInterruption, WaitReason,
+};
+
+use crate::bus::{BusMessage, ServiceBus};
+use crate::capture::capture_service::CaptureService;
+use crate::event_sender::EventSender;
+use crate::input::cursor_shape::CursorShape;
+use crate::room_service::{RoomService, RoomServiceError};
+use crate::utils::cancellation::{CancellationToken, SHUTDOWN_TIMEOUT};
+use crate::utils::geometry::Extent;
+use crate::utils::session_journal::JournalEntry;
+use crate::{ParticipantData, ServerError};
+
+use super::admission::Admission;
+use super::capabilities::{ParticipantCapabilities, PeerCapabilities};
+use super::client_event::ShareConfigData;
+use super::control_queue::ControlQueue;
+use super::input_auth::InputAuthorization;
+use super::publisher::VideoEncodingInfo;
+use super::quality::ParticipantQualities;
+use super::transport::{LiveKitTransport, SessionTransport};
+
+/// The room of the active session and its participants, see the module docs.
+///
+/// The room service is created once the tauri app sent the LiveKit server URL
+/// and kept for the following sessions, each session joins its own room with it.
+pub struct SessionService<T: SessionTransport = LiveKitTransport> {
+    /// Creates the transport for a LiveKit server URL
+    new_transport: fn(String) -> T,
+    /// `None` until the tauri app sent the LiveKit server URL
+    room_service: Option<RoomService<T>>,
+    /// URL of the LiveKit server the room service connects to, checked by the network pre-flight
+    livekit_url: Option<String>,
+    event_sender: Box<dyn EventSender>,
+    cancel: CancellationToken,
+    admission: Admission,
+    /// The identities the sharer allowed to inject input
+    input_authorization: InputAuthorization,
+    participant_qualities: ParticipantQualities,
+    /// What the participants announced they support, see `room::capabilities`
+    participant_capabilities: ParticipantCapabilities,
+    /// The participants that raised their hand to get control
+    control_queue: ControlQueue,
+    /// Why the shared display isn't showing the sharer's content, `None` while it is
+    share_interruption: Option<ShareInterruption>,
+    /// Last shape of the sharer's system cursor, sent to the participants joining later
+    sharer_cursor_shape: Option<CursorShape>,
+    /// Last configuration of the share published to the room, see `publish_share_config`
+    share_config: Option<ShareConfigData>,
+}
+
+impl SessionService {
+    /// Creates the service for sessions carried by LiveKit rooms.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_sender` - Where the rooms' events are sent
+    /// * `cancel` - Cancellation of the application, the room service leaves the room on it
+    /// * `min_client_version` - Oldest protocol version of the clients whose input is injected
+    pub fn new(
+        event_sender: impl EventSender,
+        cancel: CancellationToken,
+        min_client_version: Option<u32>,
+    ) -> Self {
+        Self::with_transport(
+            LiveKitTransport::new,
+            event_sender,
+            cancel,
+            min_client_version,
+        )
+    }
+}
+
+impl<T: SessionTransport> SessionService<T> {
+    /// Creates the service for sessions carried by another transport.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_transport` - Creates the transport for the server URL the tauri app sends
+    /// * `event_sender` - Where the rooms' events are sent
+    /// * `cancel` - Cancellation of the application, the room service leaves the room on it
+    /// * `min_client_version` - Oldest protocol version of the clients whose input is injected
+    pub fn with_transport(
+        new_transport: fn(String) -> T,
+        event_sender: impl EventSender,
+        cancel: CancellationToken,
+        min_client_version: Option<u32>,
+    ) -> Self {
+        let input_authorization = InputAuthorization::new();
+        if let Some(version) = min_client_version {
+            input_authorization.set_min_client_version(version);
+        }
+        Self {
+            new_transport,
+            room_service: None,
+            livekit_url: None,
+            event_sender: Box::new(event_sender),
+            cancel,
+            admission: Admission::new(),
+            input_authorization,
+            participant_qualities: ParticipantQualities::new(),
+            participant_capabilities: ParticipantCapabilities::new(),
+            control_queue: ControlQueue::new(),
+            share_interruption: None,
+            sharer_cursor_shape: None,
+            share_config: None,
+        }
+    }
+
+    /// Creates the room service for the LiveKit server at `url`, replacing the previous one.
+    pub fn connect(&mut self, url: String) {
+        let room_service = RoomService::with_transport(
+            (self.new_transport)(url.clone()),
+            self.event_sender.clone(),
+            self.admission.clone(),
+            self.input_authorization.clone(),
+            self.cancel.clone(),
+        );
+        match room_service {
+            Ok(room_service) => {
+                log::info!("SessionService::connect: Room service created: {room_service:?}");
+                self.room_service = Some(room_service);
+                self.livekit_url = Some(url);
+            }
+            Err(e) => log::error!("SessionService::connect: Error creating room service: {e:?}"),
+        }
+    }
+
+    /// Returns whether the room service exists, it does once the LiveKit server URL arrived.
+    pub fn is_connected(&self) -> bool {
+        self.room_service.is_some()
+    }
+
+    pub fn livekit_url(&self) -> Option<String> {
+        self.livekit_url.clone()
+    }
+
+    /// Returns the room service the sharer's camera and audio are published with.
+    pub fn room_service(&self) -> Option<&RoomService<T>> {
+        self.room_service.as_ref()
+    }
+
+    /// Runs `f` with the room service.
+    ///
+    /// # Arguments
+    ///
+    /// * `caller` - Logged when there is no room service yet
+    /// * `f` - What is done with the room service
+    ///
+    /// # Returns
+    ///
+    /// What `f` returned, `None` without a room service.
+    pub fn with_room<R>(&self, caller: &str, f: impl FnOnce(&RoomService<T>) -> R) -> Option<R> {
+        let Some(room_service) = &self.room_service else {
+            log::warn!("{caller}: room service is none");
+            return None;
+        };
+        Some(f(room_service))
+    }
+
+    /// Creates the room of a session and connects its video source to the capture.
+    ///
+    /// # Returns
+    ///
+    /// How the transport encodes the published video.
+    pub fn create_room(
+        &self,
+        capture_service: &CaptureService,
+        token: String,
+        extent: Extent,
+    ) -> Result<VideoEncodingInfo, ServerError> {
+        let Some(room_service) = &self.room_service else {
+            return Err(ServerError::RoomServiceNotFound);
+        };
+        let res = room_service.create_room(
+            token,
+            extent.width as u32,
+            extent.height as u32,
+            self.event_sender.clone(),
+        );
+        match res {
+            Ok(()) => {}
+            Err(RoomServiceError::TokenExpired) => return Err(ServerError::RoomTokenExpired),
+            Err(RoomServiceError::InvalidToken(_)) => return Err(ServerError::InvalidRoomToken),
+            Err(error) => {
+                log::error!("create_room: error creating room: {error:?}");
+                return Err(ServerError::RoomCreationError);
+            }
+        }
+        log::info!("create_room: room created");
+
+        capture_service.set_buffer_source(room_service.get_buffer_source());
+        Ok(room_service.video_encoding(extent.width as u32))
+    }
+
+    /// Publishes the session's video again for frames of `extent` and
+    /// connects the new video source to the capture.
+    ///
+    /// # Returns
+    ///
+    /// How the transport encodes the republished video, or
+    /// `Err(ServerError::VideoRepublishError)` when the previous track was
+    /// unpublished and the new one couldn't be published.
+    pub fn republish_video(
+        &self,
+        capture_service: &CaptureService,
+        extent: Extent,
+    ) -> Result<VideoEncodingInfo, ServerError> {
+        let Some(room_service) = &self.room_service else {
+            return Err(ServerError::RoomServiceNotFound);
+        };
+        if let Err(e) = room_service.republish_video(extent.width as u32, extent.height as u32) {
+            log::error!("republish_video: error republishing video: {e:?}");
+            return Err(ServerError::VideoRepublishError);
+        }
+        capture_service.set_buffer_source(room_service.get_buffer_source());
+        Ok(room_service.video_encoding(extent.width as u32))
+    }
+
+    pub fn room_connected(&self) -> bool {
+        self.room_service
+            .as_ref()
+            .is_some_and(|room_service| room_service.room_connected())
+    }
+
+    /// Leaves the session's room and forgets its participants and the share's state.
+    pub fn end_session(&mut self) {
+        self.with_room("end_session", RoomService::destroy_room);
+        self.reset();
+        self.share_interruption = None;
+        self.sharer_cursor_shape = None;
+        self.share_config = None;
+    }
+
+    /// Forgets the qualities, the capabilities and the raised hands of the participants.
+    pub fn reset(&mut self) {
+        self.participant_qualities.clear();
+        self.participant_capabilities.clear();
+        self.control_queue.clear();
+    }
+
+    /// Lets the controllers and the tauri app know why the shared display isn't updating.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the display is interrupted, `None` when it was resumed
+    pub fn publish_share_interruption(
+        &mut self,
+        reason: Option<ShareInterruption>,
+        bus: &mut ServiceBus,
+    ) {
+        self.share_interruption = reason;
+        self.with_room("publish_share_interruption", |room_service| {
+            room_service.publish_share_interruption(reason)
+        });
+        bus.tauri(match reason {
+            Some(reason) => Message::ShareInterrupted(reason),
+            None => Message::ShareResumed,
+        });
+    }
+
+    pub fn share_interruption(&self) -> Option<ShareInterruption> {
+        self.share_interruption
+    }
+
+    /// Publishes the configuration of the active share to the room, so the
+    /// viewers know the stream's properties without waiting for its frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the active share
+    /// * `force` - Publish even when the configuration didn't change since
+    ///   it was last published, for the participants joining later
+    pub fn publish_share_config(&mut self, config: ShareConfigData, force: bool) {
+        if !force && self.share_config.as_ref() == Some(&config) {
+            return;
+        }
+        let published = self.with_room("publish_share_config", |room_service| {
+            room_service.publish_share_config(config.clone())
+        });
+        if published.is_some() {
+            self.share_config = Some(config);
+        }
+    }
+
+    /// Publishes the shape of the sharer's system cursor, see `input::cursor_shape`.
+    pub fn set_sharer_cursor_shape(&mut self, shape: CursorShape) {
+        self.sharer_cursor_shape = Some(shape);
+        self.with_room("set_sharer_cursor_shape", |room_service| {
+            room_service.publish_sharer_cursor_shape(shape)
+        });
+    }
+
+    /// Fills in the connection quality and the input authorization of the
+    /// session's participants.
+    pub fn update_participant_states(&self, participants: &mut [SessionParticipant]) {
+        let qualities = self.participant_qualities.snapshot();
+        for participant in participants.iter_mut() {
+            participant.quality = qualities
+                .iter()
+                .find(|quality| quality.sid == participant.sid)
+                .map(|quality| quality.quality);
+            participant.input_authorized = self
+                .admission
+                .identity(&participant.sid)
+                .is_some_and(|identity| self.input_authorization.is_authorized(&identity));
+        }
+    }
+
+    /// Sends the session's state to the participant that joined.
+    ///
+    /// # Arguments
+    ///
+    /// * `participant` - The admitted participant
+    /// * `view_only` - Whether the session injects no input
+    pub fn participant_connected(&mut self, participant: &ParticipantData, view_only: bool) {
+        self.participant_capabilities.joined(&participant.sid);
+        let cursor_shape = self.sharer_cursor_shape;
+        /* A reconnecting client of an authorized identity needs its token again. */
+        let input_token = self.input_authorization.token(&participant.identity);
+        self.with_room("participant_connected", |room_service| {
+            room_service.publish_session_mode(view_only);
+            room_service.publish_capabilities();
+            if let Some(shape) = cursor_shape {
+                room_service.publish_sharer_cursor_shape(shape);
+            }
+            if input_token.is_some() {
+                room_service.publish_input_grant(participant.identity.clone(), input_token);
+            }
+        });
+    }
+
+    /// Forgets the participant `sid` that left the session.
+    pub fn participant_disconnected(&mut self, sid: &str, bus: &mut ServiceBus) {
+        if self.participant_qualities.remove(sid) {
+            self.send_participant_qualities(bus);
+        }
+        self.participant_capabilities.left(sid);
+        if self.control_queue.left(sid) {
+            self.control_queue_changed(bus);
+        }
+        /* The participant could have left the waiting room. */
+        self.send_waiting_participants(bus);
+    }
+
+    /// Returns the number of participants admitted to the session.
+    pub fn admitted_count(&self) -> usize {
+        self.admission.admitted_count()
+    }
+
+    /// Tells the participant that joined why it waits and the tauri app who waits.
+    pub fn participant_waiting(
+        &mut self,
+        participant: ParticipantData,
+        reason: WaitReason,
+        bus: &mut ServiceBus,
+    ) {
+        self.with_room("participant_waiting", |room_service| {
+            room_service.publish_admission(participant.sid, Some(reason))
+        });
+        self.send_waiting_participants(bus);
+    }
+
+    pub fn capabilities_announced(&mut self, sid: String, capabilities: PeerCapabilities) {
+        self.participant_capabilities.announced(sid, capabilities);
+    }
+
+    /// Records the connection quality of a participant, the tauri app gets
+    /// the qualities when it changed.
+    pub fn participant_quality(
+        &mut self,
+        participant: ParticipantData,
+        quality: ConnectionQualityLevel,
+        bus: &mut ServiceBus,
+    ) {
+        if self
+            .participant_qualities
+            .update(participant.sid, participant.name, quality)
+        {
+            self.send_participant_qualities(bus);
+        }
+    }
+
+    pub fn set_admission_policy(&self, policy: AdmissionPolicy) {
+        self.admission.set_policy(policy);
+    }
+
+    /// Admits the waiting participant `sid`, it connects like a participant
+    /// that didn't have to wait.
+    pub fn admit_participant(&mut self, sid: String, bus: &mut ServiceBus) {
+        let Some(participant) = self.admission.approve(&sid) else {
+            log::warn!("admit_participant: {sid} isn't waiting");
+            return;
+        };
+        log::info!("admit_participant: {participant:?}");
+        self.with_room("admit_participant", |room_service| {
+            room_service.publish_admission(sid, None)
+        });
+        if let Err(e) = self.event_sender.send_participant_connected(participant) {
+            log::error!("admit_participant: Error sending participant connected: {e:?}");
+        }
+        self.send_waiting_participants(bus);
+    }
+
+    /// Kicks the participant `sid` out of the session.
+    pub fn kick_participant(&mut self, sid: String, bus: &mut ServiceBus) {
+        /* A kicked participant asks to be admitted again, and to get its input back. */
+        if let Some(identity) = self.admission.identity(&sid) {
+            self.input_authorization.revoke(&identity);
+        }
+        if !self.admission.kick(&sid) {
+            log::warn!("kick_participant: {sid} isn't in the session");
+        }
+        self.remove_participants(vec![sid], false, bus);
+    }
+
+    /// Bans `identity` until the session ends and kicks its participants.
+    pub fn ban_identity(&mut self, identity: String, bus: &mut ServiceBus) {
+        self.input_authorization.revoke(&identity);
+        let sids = self.admission.ban(&identity);
+        log::info!("ban_identity: {identity} removes {sids:?}");
+        self.remove_participants(sids, true, bus);
+    }
+
+    /// Cleans up after the participants the admission removed.
+    ///
+    /// The cursors are removed, control goes back to the sharer and the
+    /// participants' clients are told to leave the call.
+    ///
+    /// # Arguments
+    ///
+    /// * `sids` - The removed participants
+    /// * `banned` - Whether their identity is banned
+    fn remove_participants(&mut self, sids: Vec<String>, banned: bool, bus: &mut ServiceBus) {
+        let mut qualities_changed = false;
+        for sid in sids {
+            sentry_utils::add_breadcrumb(
+                "room",
+                format!("participant removed: {sid} banned: {banned}"),
+            );
+            bus.post(BusMessage::RevokeController(sid.clone()));
+            qualities_changed |= self.participant_qualities.remove(&sid);
+            self.with_room("remove_participants", |room_service| {
+                room_service.publish_participant_removed(sid, banned)
+            });
+        }
+        if qualities_changed {
+            self.send_participant_qualities(bus);
+        }
+        self.send_waiting_participants(bus);
+    }
+
+    /// Allows or revokes the input of the participant `sid`'s identity, see
+    /// `room::input_auth`.
+    ///
+    /// The participant gets its token, or learns its input was revoked, with
+    /// an input grant only its identity receives.
+    pub fn set_input_authorized(&mut self, sid: String, authorized: bool, bus: &mut ServiceBus) {
+        let Some(identity) = self.admission.identity(&sid) else {
+            log::warn!("set_input_authorized: {sid} isn't admitted");
+            return;
+        };
+        sentry_utils::add_breadcrumb("room", format!("input authorized: {sid} {authorized}"));
+        bus.post(BusMessage::Journal(JournalEntry::ControlChanged {
+            sid,
+            authorized,
+        }));
+        let token = if authorized {
+            Some(self.input_authorization.authorize(&identity))
+        } else {
+            self.input_authorization.revoke(&identity);
+            bus.post(BusMessage::TakeBackControl(identity.clone()));
+            None
+        };
+        self.with_room("set_input_authorized", |room_service| {
+            room_service.publish_input_grant(identity, token)
+        });
+    }
+
+    /// Returns the identity of the admitted participant `sid`.
+    pub fn identity(&self, sid: &str) -> Option<String> {
+        self.admission.identity(sid)
+    }
+
+    /// Records the controller that has control, `None` when the sharer has it back.
+    pub fn set_in_control(&self, sid: Option<String>) {
+        self.input_authorization.set_in_control(sid);
+    }
+
+    /// Queues or drops the control request of the participant `sid`, see
+    /// `room::control_queue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The participant that raised or lowered its hand
+    /// * `raised` - Whether the hand was raised
+    /// * `name` - The name of the participant's cursor, its identity is shown without one
+    pub fn raise_hand(
+        &mut self,
+        sid: String,
+        raised: bool,
+        name: Option<String>,
+        bus: &mut ServiceBus,
+    ) {
+        let changed = if raised {
+            let Some(identity) = self.admission.identity(&sid) else {
+                log::warn!("raise_hand: {sid} isn't admitted");
+                return;
+            };
+            self.control_queue.raise(ControlRequest {
+                sid,
+                name: name.unwrap_or_else(|| identity.clone()),
+                identity,
+            })
+        } else {
+            self.control_queue.lower(&sid)
+        };
+        if changed {
+            self.control_queue_changed(bus);
+        }
+    }
+
+    /// Gives control to the first raised hand, the participant that got it
+    /// from the queue before loses its input.
+    pub fn grant_next_control(&mut self, bus: &mut ServiceBus) {
+        let Some((next, previous)) = self.control_queue.grant_next() else {
+            log::info!("grant_next_control: no raised hands");
+            return;
+        };
+        sentry_utils::add_breadcrumb("room", format!("control granted to {}", next.sid));
+        if let Some(previous) = previous {
+            self.set_input_authorized(previous, false, bus);
+        }
+        self.set_input_authorized(next.sid, true, bus);
+        self.control_queue_changed(bus);
+    }
+
+    /// Sends the raised hands to the tauri app and shows their order in the cursors' badges.
+    fn control_queue_changed(&self, bus: &mut ServiceBus) {
+        bus.tauri(Message::ControlRequests(self.control_queue.requests()));
+        bus.post(BusMessage::ControlQueue(self.control_queue.sids()));
+    }
+
+    /// Sends the participants in the waiting room to the tauri app.
+    fn send_waiting_participants(&self, bus: &mut ServiceBus) {
+        bus.tauri(Message::WaitingParticipants(self.admission.waiting()));
+    }
+
+    /// Sends the connection quality of the participants to the tauri app.
+    fn send_participant_qualities(&self, bus: &mut ServiceBus) {
+        bus.tauri(Message::ParticipantQualities(
+            self.participant_qualities.snapshot(),
+        ));
+    }
+
+    /// Leaves the room and stops the room service, see `RoomService::shutdown`.
+    pub fn shutdown(&mut self) {
+        if let Some(room_service) = self.room_service.take() {
+            room_service.shutdown(SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use crate::room::transport::MockTransport;
+
+    fn participant(sid: &str, identity: &str) -> ParticipantData {
+        ParticipantData {
+            name: format!("{identity} name"),
+            sid: sid.to_string(),
+            identity: identity.to_string(),
+        }
+    }
+
+    fn service() -> SessionService<MockTransport> {
+        let mut service = SessionService::with_transport(
+            |_url| MockTransport::new(),
+            EventCollector::new(),
+            CancellationToken::new(),
+            None,
+        );
+        service.connect("wss://mock".to_string());
+        service
+    }
+
+    #[test]
+    fn test_revoking_input_takes_control_back() {
+        let mut service = service();
+        let sink = EventCollector::new();
+        service.admission.join(participant("PA_1", "alice"), &sink);
+        let mut bus = ServiceBus::new();
+
+        service.set_input_authorized("PA_1".to_string(), true, &mut bus);
+        assert!(service.input_authorization.is_authorized("alice"));
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::Journal(JournalEntry::ControlChanged {
+                authorized: true,
+                ..
+            }))
+        ));
+        assert!(bus.is_empty());
+
+        service.set_input_authorized("PA_1".to_string(), false, &mut bus);
+        assert!(!service.input_authorization.is_authorized("alice"));
+        assert!(matches!(bus.take(), Some(BusMessage::Journal(_))));
+        assert!(matches!(
+            bus.take(),
+            Some(BusMessage::TakeBackControl(identity)) if identity == "alice"
+        ));
+        service.shutdown();
+    }
+
+    #[test]
+    fn test_raised_hands_are_shown_in_order() {
+        let mut service = service();
+        let sink = EventCollector::new();
+        service.admission.join(participant("PA_1", "alice"), &sink);
+        service.admission.join(participant("PA_2", "bob"), &sink);
+        let mut bus = ServiceBus::new();
+
+        service.raise_hand("PA_2".to_string(), true, None, &mut bus);
+        service.raise_hand(
+            "PA_1".to_string(),
+            true,
+            Some("Alice".to_string()),
+            &mut bus,
+        );
+        /* The hand of a participant that isn't admitted is ignored. */
+        service.raise_hand("PA_3".to_string(), true, None, &mut bus);
+
+        let mut queues = vec![];
+        while let Some(message) = bus.take() {
+            match message {
+                BusMessage::ControlQueue(sids) => queues.push(sids),
+                BusMessage::Tauri(Message::ControlRequests(requests)) => {
+                    assert_eq!(requests.len(), queues.len() + 1)
+                }
+                message => panic!("unexpected {message:?}"),
+            }
+        }
+        assert_eq!(queues.last().unwrap(), &["PA_2", "PA_1"]);
+
+        /* The first hand gets control and leaves the queue. */
+        service.grant_next_control(&mut bus);
+        assert!(service.input_authorization.is_authorized("bob"));
+        service.participant_disconnected("PA_1", &mut bus);
+        let queue = std::iter::from_fn(|| bus.take())
+            .filter_map(|message| match message {
+                BusMessage::ControlQueue(sids) => Some(sids),
+                _ => None,
+            })
+            .last();
+        assert_eq!(queue, Some(vec![]));
+        service.shutdown();
+    }
+}
@@ -0,0 +1,170 @@
+//! Publishing of the sharer's cursor position.
+//!
+//! The sharer's cursor moves are published at the mouse's rate, sending each
+//! one reliably makes a lost packet hold back the ones after it while it is
+//! retransmitted, and the controllers see the cursor stall and then rush.
+//! The moves are sent unreliably instead, with an increasing sequence number
+//! in `ClientPoint::seq`, and a move is sent reliably as a keyframe every
+//! `KEYFRAME_INTERVAL`. A lost move is replaced by the next one, but the last
+//! move before the cursor rests has no next one, so it is repeated as a
+//! keyframe once the cursor rested for `REST_KEYFRAME_DELAY`.
+//!
+//! The reliable and unreliable packets take different channels and can
+//! arrive out of order, a keyframe repeats the sequence number of the move it
+//! carries. Consumers keep the highest sequence number per participant and
+//! drop the moves at or below it, a much lower one means the sharer restarted
+//! its counter, see `MouseMoveOrder`. The web clients do the same.
+
+use std::time::{Duration, Instant};
+
+use super::client_event::ClientPoint;
+
+/// Longest time between two reliable moves while the cursor moves
+const KEYFRAME_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the cursor rests before its last move is repeated reliably
+const REST_KEYFRAME_DELAY: Duration = Duration::from_millis(150);
+
+/// A move to publish.
+#[derive(Debug)]
+pub struct LocationSample {
+    pub point: ClientPoint,
+    /// Whether the move is a keyframe and is sent reliably
+    pub reliable: bool,
+}
+
+/// The last move sent unreliably.
+#[derive(Debug, Clone, Copy)]
+struct UnconfirmedMove {
+    x: f64,
+    y: f64,
+    seq: u64,
+    at: Instant,
+}
+
+/// Decides which of the sharer's moves are keyframes.
+#[derive(Debug, Default)]
+pub struct SharerLocationPublisher {
+    /// Sequence number of the last move
+    seq: u64,
+    last_keyframe: Option<Instant>,
+    /// Repeated as a keyframe when the cursor rests, `None` after a keyframe
+    unconfirmed: Option<UnconfirmedMove>,
+}
+
+impl SharerLocationPublisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Numbers a move of the sharer's cursor.
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - The position as a percentage of the shared display
+    /// * `now` - When the move happened
+    ///
+    /// # Returns
+    ///
+    /// The move, a keyframe when the last one is older than `KEYFRAME_INTERVAL`.
+    pub fn sample(&mut self, x: f64, y: f64, now: Instant) -> LocationSample {
+        self.seq += 1;
+        let reliable = self
+            .last_keyframe
+            .is_none_or(|last_keyframe| now.duration_since(last_keyframe) >= KEYFRAME_INTERVAL);
+        if reliable {
+            self.last_keyframe = Some(now);
+            self.unconfirmed = None;
+        } else {
+            self.unconfirmed = Some(UnconfirmedMove {
+                x,
+                y,
+                seq: self.seq,
+                at: now,
+            });
+        }
+        LocationSample {
+            point: ClientPoint {
+                x,
+                y,
+                seq: Some(self.seq),
+            },
+            reliable,
+        }
+    }
+
+    /// Returns when the last move is repeated as a keyframe, `None` when the
+    /// last move was a keyframe.
+    pub fn rest_keyframe_at(&self) -> Option<Instant> {
+        self.unconfirmed
+            .map(|unconfirmed| unconfirmed.at + REST_KEYFRAME_DELAY)
+    }
+
+    /// Returns the last move as a keyframe once the cursor rested, with its
+    /// sequence number, so a consumer that got it drops the repeat.
+    pub fn rest_keyframe(&mut self, now: Instant) -> Option<LocationSample> {
+        if self.rest_keyframe_at()? > now {
+            return None;
+        }
+        let unconfirmed = self.unconfirmed.take()?;
+        self.last_keyframe = Some(now);
+        Some(LocationSample {
+            point: ClientPoint {
+                x: unconfirmed.x,
+                y: unconfirmed.y,
+                seq: Some(unconfirmed.seq),
+            },
+            reliable: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyframes_while_moving() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut publisher = SharerLocationPublisher::new();
+
+        let first = publisher.sample(0.1, 0.1, ms(0));
+        assert!(first.reliable);
+        assert_eq!(first.point.seq, Some(1));
+
+        let moves: Vec<LocationSample> = (1..=30)
+            .map(|i| publisher.sample(0.1, 0.1, ms(i * 20)))
+            .collect();
+        let keyframes: Vec<u64> = moves
+            .iter()
+            .filter(|sample| sample.reliable)
+            .filter_map(|sample| sample.point.seq)
+            .collect();
+        /* 500ms after the first keyframe. */
+        assert_eq!(keyframes, vec![26]);
+        assert_eq!(moves.last().unwrap().point.seq, Some(31));
+    }
+
+    #[test]
+    fn test_last_move_is_repeated_when_resting() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut publisher = SharerLocationPublisher::new();
+        publisher.sample(0.1, 0.1, ms(0));
+        assert_eq!(publisher.rest_keyframe_at(), None);
+
+        publisher.sample(0.2, 0.3, ms(20));
+        assert_eq!(publisher.rest_keyframe_at(), Some(ms(170)));
+        assert!(publisher.rest_keyframe(ms(100)).is_none());
+
+        let keyframe = publisher.rest_keyframe(ms(170)).unwrap();
+        assert!(keyframe.reliable);
+        assert_eq!(keyframe.point.seq, Some(2));
+        assert_eq!((keyframe.point.x, keyframe.point.y), (0.2, 0.3));
+        assert_eq!(publisher.rest_keyframe_at(), None);
+        assert!(publisher.rest_keyframe(ms(500)).is_none());
+
+        /* The next move counts from the repeated keyframe. */
+        assert!(!publisher.sample(0.3, 0.3, ms(300)).reliable);
+    }
+}
@@ -0,0 +1,157 @@
+//! Local checks of LiveKit access tokens.
+//!
+//! Access tokens are JWTs signed by the backend. The core can't verify the
+//! signature, but it can read the claims to catch an expired token, or one
+//! without the grants a sharer needs, before connecting to the room. This lets
+//! the UI ask for a new token instead of reporting a generic connect failure.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::prelude::*;
+use serde::Deserialize;
+
+/// Allowed difference between this machine's clock and the server's.
+const CLOCK_LEEWAY_SECS: u64 = 30;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum TokenError {
+    #[error("Malformed token: {0}")]
+    Malformed(String),
+    #[error("Token expired at {0}")]
+    Expired(u64),
+    #[error("Token is not valid before {0}")]
+    NotYetValid(u64),
+    #[error("Token is missing the {0} grant")]
+    MissingGrant(&'static str),
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    exp: Option<u64>,
+    nbf: Option<u64>,
+    #[serde(default)]
+    video: VideoGrants,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VideoGrants {
+    #[serde(default)]
+    room_join: bool,
+    /* LiveKit allows publishing when the grant is omitted. */
+    can_publish: Option<bool>,
+}
+
+/// Checks that `token` can be used for sharing at `now`.
+///
+/// # Arguments
+///
+/// * `token` - The LiveKit access token
+/// * `now` - The current time
+///
+/// # Returns
+///
+/// `Ok(())` when the token isn't expired and grants joining the room and
+/// publishing, the reason it can't be used otherwise.
+pub fn check_token(token: &str, now: SystemTime) -> Result<(), TokenError> {
+    let mut parts = token.split('.');
+    let payload = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(payload), Some(_), None) => payload,
+        _ => return Err(TokenError::Malformed("expected three parts".to_string())),
+    };
+    let payload = BASE64_URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|e| TokenError::Malformed(e.to_string()))?;
+    let claims: Claims =
+        serde_json::from_slice(&payload).map_err(|e| TokenError::Malformed(e.to_string()))?;
+
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    if let Some(exp) = claims.exp {
+        if now >= exp + CLOCK_LEEWAY_SECS {
+            return Err(TokenError::Expired(exp));
+        }
+    }
+    if let Some(nbf) = claims.nbf {
+        if now + CLOCK_LEEWAY_SECS < nbf {
+            return Err(TokenError::NotYetValid(nbf));
+        }
+    }
+
+    if !claims.video.room_join {
+        return Err(TokenError::MissingGrant("roomJoin"));
+    }
+    if claims.video.can_publish == Some(false) {
+        return Err(TokenError::MissingGrant("canPublish"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn token(claims: serde_json::Value) -> String {
+        let header = BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+        let claims = BASE64_URL_SAFE_NO_PAD.encode(claims.to_string());
+        format!("{header}.{claims}.signature")
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_check_token() {
+        let valid = token(serde_json::json!({
+            "exp": 2000,
+            "nbf": 1000,
+            "video": { "roomJoin": true, "room": "room" },
+        }));
+        assert_eq!(check_token(&valid, at(1500)), Ok(()));
+        assert_eq!(check_token(&valid, at(2010)), Ok(()));
+        assert_eq!(
+            check_token(&valid, at(2030)),
+            Err(TokenError::Expired(2000))
+        );
+        assert_eq!(
+            check_token(&valid, at(900)),
+            Err(TokenError::NotYetValid(1000))
+        );
+
+        let no_join = token(serde_json::json!({ "exp": 2000, "video": {} }));
+        assert_eq!(
+            check_token(&no_join, at(1500)),
+            Err(TokenError::MissingGrant("roomJoin"))
+        );
+
+        let no_publish = token(serde_json::json!({
+            "exp": 2000,
+            "video": { "roomJoin": true, "canPublish": false },
+        }));
+        assert_eq!(
+            check_token(&no_publish, at(1500)),
+            Err(TokenError::MissingGrant("canPublish"))
+        );
+    }
+
+    #[test]
+    fn test_check_token_malformed() {
+        assert!(matches!(
+            check_token("not a token", at(0)),
+            Err(TokenError::Malformed(_))
+        ));
+        assert!(matches!(
+            check_token("a.!!!.c", at(0)),
+            Err(TokenError::Malformed(_))
+        ));
+        let not_json = format!("a.{}.c", BASE64_URL_SAFE_NO_PAD.encode("nope"));
+        assert!(matches!(
+            check_token(&not_json, at(0)),
+            Err(TokenError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,698 @@
+//! Session transport abstraction.
+//!
+//! `SessionTransport` covers everything the room service needs from the
+//! backend that carries a session: joining, publishing the screen share,
+//! camera, microphone and system audio and exchanging data messages. LiveKit
+//! is the default implementation, other backends (a raw WebRTC peer
+//! connection, a WebSocket relay) implement the same trait and are plugged in
+//! with `RoomService::with_transport` without changes to the Application or
+//! Capturer layers.
+//!
+//! The trait only uses crate types: the published tracks are fed through the
+//! sinks of `room::media` and the data messages are `ClientEvent`s, see
+//! `room::client_event`. The LiveKit conversions stay in `LiveKitTransport`.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+
+use livekit::prelude::TrackSid;
+use livekit::track::LocalVideoTrack;
+use livekit::webrtc::audio_frame::AudioFrame;
+use livekit::webrtc::audio_source::native::NativeAudioSource;
+use livekit::webrtc::video_source::native::NativeVideoSource;
+use socket_lib::{AudioProcessing, ConnectionQualityLevel};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::event_sender::EventSender;
+use crate::ParticipantData;
+
+use super::adaptive_bitrate::{BitrateAdapter, SAMPLE_INTERVAL};
+use super::admission::Admission;
+use super::client_event::ClientEvent;
+use super::compression::DataCompression;
+use super::connection::{ConnectionError, RoomConnection};
+use super::data_events::{handle_room_events, participant_data, RoomEventContext};
+use super::data_handlers::DataHandlerRegistry;
+use super::input_auth::InputAuthorization;
+use super::media::{
+    AudioSamples, AudioSink, AudioSinkError, SharedAudioSink, SharedVideoSink, VideoFrameRef,
+    VideoSink,
+};
+use super::publisher::{
+    publish_audio_track, publish_camera_track, publish_microphone_track,
+    publish_screen_share_track, screen_share_encoding, set_video_encoding, unpublish_track,
+    video_network_sample, DataPublisher, PublishError, VideoEncodingInfo,
+    DEFAULT_SCREEN_SHARE_FRAMERATE,
+};
+use super::token::TokenError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error("Failed to join session: {0}")]
+    Join(#[from] ConnectionError),
+    #[error("Failed to publish: {0}")]
+    Publish(#[from] PublishError),
+    #[error("Not connected to a session")]
+    NotConnected,
+    #[error("Not supported by the transport")]
+    Unsupported,
+}
+
+/// Backend that carries a screen sharing session.
+///
+/// All methods are called from the room service's async runtime.
+pub trait SessionTransport: Debug + Send + Sync + 'static {
+    /// Checks locally that `token` can be used for joining, before connecting.
+    ///
+    /// Transports whose tokens can't be inspected accept all of them.
+    fn check_token(&self, _token: &str) -> Result<(), TokenError> {
+        Ok(())
+    }
+
+    /// Joins the session identified by `token`, leaving any previous session.
+    ///
+    /// Events from the session (data messages, participants joining and
+    /// leaving) are translated and delivered to `sink` until the session ends.
+    /// Participants that `admission` doesn't admit are reported as waiting
+    /// and their events are dropped, input events that `input_authorization`
+    /// doesn't accept are dropped too.
+    fn join(
+        &self,
+        token: &str,
+        sink: impl EventSender,
+        admission: Admission,
+        input_authorization: InputAuthorization,
+    ) -> impl Future<Output = Result<(), TransportError>> + Send;
+
+    /// Publishes the screen share video and returns the sink frames are pushed to.
+    fn publish_video(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> impl Future<Output = Result<SharedVideoSink, TransportError>> + Send;
+
+    /// Publishes the screen share video again for frames of `width`x`height`,
+    /// the track published by `publish_video` is replaced.
+    ///
+    /// Returns the new sink frames are pushed to, `None` when the transport
+    /// keeps its sink and follows the frames' size.
+    fn republish_video(
+        &self,
+        _width: u32,
+        _height: u32,
+    ) -> impl Future<Output = Result<Option<SharedVideoSink>, TransportError>> + Send {
+        async { Ok(None) }
+    }
+
+    /// Returns how the video published with `publish_video` is encoded when
+    /// it is `width` wide.
+    fn video_encoding(&self, width: u32) -> VideoEncodingInfo;
+
+    /// Caps the framerate of the published video, the transport's default when `None`.
+    ///
+    /// Applies to the published video right away and to the videos published
+    /// after it. Transports whose video follows the captured frames ignore it.
+    fn set_video_framerate(&self, _framerate: Option<u32>) {}
+
+    /// Publishes the sharer's camera as a second video track and returns the
+    /// sink its frames are pushed to.
+    ///
+    /// Transports that only carry the screen share don't support it.
+    fn publish_camera(
+        &self,
+        _width: u32,
+        _height: u32,
+    ) -> impl Future<Output = Result<SharedVideoSink, TransportError>> + Send {
+        async { Err(TransportError::Unsupported) }
+    }
+
+    /// Stops publishing the camera track, if it was published.
+    fn unpublish_camera(&self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Publishes the sharer's microphone and returns the sink its samples
+    /// are pushed to.
+    ///
+    /// Transports that only carry the screen share don't support it.
+    fn publish_microphone(
+        &self,
+        _processing: AudioProcessing,
+        _sample_rate: u32,
+        _num_channels: u32,
+    ) -> impl Future<Output = Result<SharedAudioSink, TransportError>> + Send {
+        async { Err(TransportError::Unsupported) }
+    }
+
+    /// Stops publishing the microphone track, if it was published.
+    fn unpublish_microphone(&self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Publishes the audio the sharer's machine plays and returns the sink
+    /// its samples are pushed to.
+    ///
+    /// Transports that only carry the screen share don't support it.
+    fn publish_audio(
+        &self,
+        _sample_rate: u32,
+        _num_channels: u32,
+    ) -> impl Future<Output = Result<SharedAudioSink, TransportError>> + Send {
+        async { Err(TransportError::Unsupported) }
+    }
+
+    /// Stops publishing the system audio track, if it was published.
+    fn unpublish_audio(&self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Sends `event` to the other participants on `topic`.
+    fn publish_event(
+        &self,
+        topic: &str,
+        event: &ClientEvent,
+    ) -> impl Future<Output = Result<(), TransportError>> + Send;
+
+    /// Sends `event` to the other participants on `topic` without waiting for
+    /// the events before it, it can be lost or arrive out of order.
+    ///
+    /// Transports without a lossy channel send it like `publish_event`.
+    fn publish_event_unreliable(
+        &self,
+        topic: &str,
+        event: &ClientEvent,
+    ) -> impl Future<Output = Result<(), TransportError>> + Send {
+        self.publish_event(topic, event)
+    }
+
+    /// Sends `event` on `topic` only to the participants with `identity`.
+    fn publish_event_to(
+        &self,
+        topic: &str,
+        event: &ClientEvent,
+        identity: &str,
+    ) -> impl Future<Output = Result<(), TransportError>> + Send;
+
+    /// Returns the remote participants that should be shown in the overlay,
+    /// before admission.
+    fn remote_participants(&self) -> impl Future<Output = Vec<ParticipantData>> + Send;
+
+    /// Leaves the current session.
+    ///
+    /// # Returns
+    ///
+    /// `true` if there was a session to leave.
+    fn leave(&self) -> impl Future<Output = bool> + Send;
+}
+
+impl VideoSink for NativeVideoSource {
+    fn push_frame(&self, frame: VideoFrameRef<'_>) {
+        match frame {
+            VideoFrameRef::Nv12(frame) => self.capture_frame(frame.webrtc()),
+            VideoFrameRef::I420(frame) => self.capture_frame(frame.webrtc()),
+        }
+    }
+}
+
+impl AudioSink for NativeAudioSource {
+    fn push_samples<'a>(
+        &'a self,
+        samples: AudioSamples<'a>,
+    ) -> BoxFuture<'a, Result<(), AudioSinkError>> {
+        Box::pin(async move {
+            let frame = AudioFrame {
+                data: samples.data.into(),
+                sample_rate: samples.sample_rate,
+                num_channels: samples.num_channels,
+                samples_per_channel: samples.samples_per_channel,
+            };
+            self.capture_frame(&frame)
+                .await
+                .map_err(|e| AudioSinkError(format!("{e:?}")))
+        })
+    }
+}
+
+/// `SessionTransport` implementation backed by a LiveKit room.
+#[derive(Debug)]
+pub struct LiveKitTransport {
+    connection: RoomConnection,
+    data_handlers: DataHandlerRegistry,
+    /// Whether the participants of the room inflate compressed payloads
+    compression: DataCompression,
+    /// The published screen share track, `None` until the room is joined
+    video_track: Mutex<Option<TrackSid>>,
+    /// The sharer's connection quality in the joined room, `None` until the room is joined
+    local_quality: Mutex<Option<watch::Receiver<Option<ConnectionQualityLevel>>>>,
+    /// Adapts the encoding of the screen share track, see `adapt_video_encoding`
+    video_adaptation: Mutex<Option<JoinHandle<()>>>,
+    /// Max framerate of the screen share, followed by the adaptation task
+    video_framerate: watch::Sender<u32>,
+    /// The published camera track, `None` while the camera is off
+    camera_track: Mutex<Option<TrackSid>>,
+    /// The published microphone track, `None` while the microphone is off
+    microphone_track: Mutex<Option<TrackSid>>,
+    /// The published system audio track, `None` while the system audio is off
+    audio_track: Mutex<Option<TrackSid>>,
+}
+
+impl LiveKitTransport {
+    /// Creates a transport for the LiveKit server at `url`.
+    pub fn new(url: String) -> Self {
+        Self::with_data_handlers(url, DataHandlerRegistry::default())
+    }
+
+    /// Creates a transport that decodes the incoming data packets with `data_handlers`.
+    pub fn with_data_handlers(url: String, data_handlers: DataHandlerRegistry) -> Self {
+        Self {
+            connection: RoomConnection::new(url),
+            data_handlers,
+            compression: DataCompression::new(),
+            video_track: Mutex::new(None),
+            local_quality: Mutex::new(None),
+            video_adaptation: Mutex::new(None),
+            video_framerate: watch::channel(DEFAULT_SCREEN_SHARE_FRAMERATE).0,
+            camera_track: Mutex::new(None),
+            microphone_track: Mutex::new(None),
+            audio_track: Mutex::new(None),
+        }
+    }
+
+    /// Stops publishing the track `sid`, the room may have been left already.
+    async fn unpublish(&self, sid: &TrackSid) {
+        let room = self.connection.room().await;
+        if let Some(room) = room.as_ref() {
+            if let Err(e) = unpublish_track(&room.local_participant(), sid).await {
+                log::error!("LiveKitTransport::unpublish: {e}");
+            }
+        }
+    }
+
+    /// Stops adapting the encoding of the screen share track.
+    fn stop_video_adaptation(&self) {
+        if let Some(adaptation) = self.video_adaptation.lock().unwrap().take() {
+            adaptation.abort();
+        }
+    }
+}
+
+/// Adapts the encoding of the published screen share to the network until
+/// the room's events end or the task is aborted, see `room::adaptive_bitrate`.
+///
+/// # Arguments
+///
+/// * `track` - The published screen share track
+/// * `width` - The width of the track, picks its full encoding
+/// * `local_quality` - The sharer's connection quality reported by LiveKit
+/// * `framerate` - The max framerate requested for the share
+async fn adapt_video_encoding(
+    track: LocalVideoTrack,
+    width: u32,
+    mut local_quality: watch::Receiver<Option<ConnectionQualityLevel>>,
+    mut framerate: watch::Receiver<u32>,
+) {
+    let max_framerate = *framerate.borrow_and_update();
+    let mut adapter = BitrateAdapter::new(width, max_framerate, Instant::now());
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+    /* The first tick completes right away, the receivers haven't reported yet. */
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            res = local_quality.changed() => {
+                if res.is_err() {
+                    break;
+                }
+                if let Some(quality) = *local_quality.borrow_and_update() {
+                    adapter.set_quality(quality);
+                }
+                continue;
+            }
+            res = framerate.changed() => {
+                if res.is_err() {
+                    break;
+                }
+                let encoding = adapter.set_max_framerate(*framerate.borrow_and_update());
+                log::info!("adapt_video_encoding: {encoding:?} for the requested framerate");
+                if let Err(e) = set_video_encoding(&track, encoding) {
+                    log::error!("adapt_video_encoding: Failed to set the framerate: {e}");
+                }
+                continue;
+            }
+        }
+        let sample = video_network_sample(&track).await;
+        log::debug!("adapt_video_encoding: {sample:?}");
+        let Some(encoding) = adapter.sample(sample, Instant::now()) else {
+            continue;
+        };
+        log::info!("adapt_video_encoding: {encoding:?} after {sample:?}");
+        if let Err(e) = set_video_encoding(&track, encoding) {
+            log::error!("adapt_video_encoding: Failed to set the encoding: {e}");
+        }
+    }
+    log::info!("adapt_video_encoding: room events ended");
+}
+
+impl SessionTransport for LiveKitTransport {
+    fn check_token(&self, token: &str) -> Result<(), TokenError> {
+        super::token::check_token(token, std::time::SystemTime::now())
+    }
+
+    async fn join(
+        &self,
+        token: &str,
+        sink: impl EventSender,
+        admission: Admission,
+        input_authorization: InputAuthorization,
+    ) -> Result<(), TransportError> {
+        let (room, rx) = self.connection.connect(token).await?;
+
+        let user_sid = room.local_participant().sid().as_str().to_string();
+        /* The participants already in the room never show up as connected. */
+        self.compression.reset();
+        for (identity, participant) in room.remote_participants() {
+            let sid = participant.sid().as_str().to_string();
+            if let Some(data) = participant_data(identity.as_str(), participant.name(), sid) {
+                self.compression.joined(&data.sid);
+            }
+        }
+        let (local_quality_tx, local_quality_rx) = watch::channel(None);
+        *self.local_quality.lock().unwrap() = Some(local_quality_rx);
+        // TODO: Check if this will need cleanup
+        /* Spawn thread for handling livekit data events. */
+        tokio::spawn(handle_room_events(
+            rx,
+            sink,
+            RoomEventContext {
+                user_sid,
+                data_handlers: self.data_handlers.clone(),
+                admission,
+                compression: self.compression.clone(),
+                input_authorization,
+                local_quality: local_quality_tx,
+            },
+        ));
+
+        self.connection.set_room(room).await;
+        Ok(())
+    }
+
+    async fn publish_video(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Result<SharedVideoSink, TransportError> {
+        let local_participant = {
+            let room = self.connection.room().await;
+            match room.as_ref() {
+                Some(room) => room.local_participant(),
+                None => return Err(TransportError::NotConnected),
+            }
+        };
+        let framerate = *self.video_framerate.borrow();
+        let (buffer_source, track, sid) =
+            publish_screen_share_track(&local_participant, width, height, framerate).await?;
+        *self.video_track.lock().unwrap() = Some(sid);
+
+        self.stop_video_adaptation();
+        let local_quality = self.local_quality.lock().unwrap().clone();
+        if let Some(local_quality) = local_quality {
+            let adaptation = tokio::spawn(adapt_video_encoding(
+                track,
+                width,
+                local_quality,
+                self.video_framerate.subscribe(),
+            ));
+            *self.video_adaptation.lock().unwrap() = Some(adaptation);
+        }
+        Ok(Arc::new(buffer_source))
+    }
+
+    async fn republish_video(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Result<Option<SharedVideoSink>, TransportError> {
+        /* The encoder's bitrate follows the width, the track is published again for it. */
+        let sid = self.video_track.lock().unwrap().take();
+        if let Some(sid) = sid {
+            self.unpublish(&sid).await;
+        }
+        self.publish_video(width, height).await.map(Some)
+    }
+
+    fn video_encoding(&self, width: u32) -> VideoEncodingInfo {
+        screen_share_encoding(width, *self.video_framerate.borrow())
+    }
+
+    fn set_video_framerate(&self, framerate: Option<u32>) {
+        let framerate = framerate.unwrap_or(DEFAULT_SCREEN_SHARE_FRAMERATE);
+        if self.video_framerate.send_replace(framerate) != framerate {
+            log::info!("LiveKitTransport::set_video_framerate: {framerate}");
+        }
+    }
+
+    async fn publish_camera(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Result<SharedVideoSink, TransportError> {
+        let local_participant = {
+            let room = self.connection.room().await;
+            match room.as_ref() {
+                Some(room) => room.local_participant(),
+                None => return Err(TransportError::NotConnected),
+            }
+        };
+        let (buffer_source, sid) = publish_camera_track(&local_participant, width, height).await?;
+        *self.camera_track.lock().unwrap() = Some(sid);
+        Ok(Arc::new(buffer_source))
+    }
+
+    async fn unpublish_camera(&self) {
+        let sid = self.camera_track.lock().unwrap().take();
+        if let Some(sid) = sid {
+            self.unpublish(&sid).await;
+        }
+    }
+
+    async fn publish_microphone(
+        &self,
+        processing: AudioProcessing,
+        sample_rate: u32,
+        num_channels: u32,
+    ) -> Result<SharedAudioSink, TransportError> {
+        let local_participant = {
+            let room = self.connection.room().await;
+            match room.as_ref() {
+                Some(room) => room.local_participant(),
+                None => return Err(TransportError::NotConnected),
+            }
+        };
+        let (audio_source, sid) =
+            publish_microphone_track(&local_participant, processing, sample_rate, num_channels)
+                .await?;
+        *self.microphone_track.lock().unwrap() = Some(sid);
+        Ok(Arc::new(audio_source))
+    }
+
+    async fn unpublish_microphone(&self) {
+        let sid = self.microphone_track.lock().unwrap().take();
+        if let Some(sid) = sid {
+            self.unpublish(&sid).await;
+        }
+    }
+
+    async fn publish_audio(
+        &self,
+        sample_rate: u32,
+        num_channels: u32,
+    ) -> Result<SharedAudioSink, TransportError> {
+        let local_participant = {
+            let room = self.connection.room().await;
+            match room.as_ref() {
+                Some(room) => room.local_participant(),
+                None => return Err(TransportError::NotConnected),
+            }
+        };
+        let (audio_source, sid) =
+            publish_audio_track(&local_participant, sample_rate, num_channels).await?;
+        *self.audio_track.lock().unwrap() = Some(sid);
+        Ok(Arc::new(audio_source))
+    }
+
+    async fn unpublish_audio(&self) {
+        let sid = self.audio_track.lock().unwrap().take();
+        if let Some(sid) = sid {
+            self.unpublish(&sid).await;
+        }
+    }
+
+    async fn publish_event(&self, topic: &str, event: &ClientEvent) -> Result<(), TransportError> {
+        let room = self.connection.room().await;
+        match room.as_ref() {
+            Some(room) => Ok(room
+                .local_participant()
+                .publish_event(topic, event, true, self.compression.enabled(), None)
+                .await?),
+            None => Err(TransportError::NotConnected),
+        }
+    }
+
+    async fn publish_event_unreliable(
+        &self,
+        topic: &str,
+        event: &ClientEvent,
+    ) -> Result<(), TransportError> {
+        let room = self.connection.room().await;
+        match room.as_ref() {
+            Some(room) => Ok(room
+                .local_participant()
+                .publish_event(topic, event, false, self.compression.enabled(), None)
+                .await?),
+            None => Err(TransportError::NotConnected),
+        }
+    }
+
+    async fn publish_event_to(
+        &self,
+        topic: &str,
+        event: &ClientEvent,
+        identity: &str,
+    ) -> Result<(), TransportError> {
+        let room = self.connection.room().await;
+        match room.as_ref() {
+            Some(room) => Ok(room
+                .local_participant()
+                .publish_event(
+                    topic,
+                    event,
+                    true,
+                    self.compression.enabled(),
+                    Some(identity),
+                )
+                .await?),
+            None => Err(TransportError::NotConnected),
+        }
+    }
+
+    async fn remote_participants(&self) -> Vec<ParticipantData> {
+        let room = self.connection.room().await;
+        let room = match room.as_ref() {
+            Some(room) => room,
+            None => return vec![],
+        };
+        let mut participants = vec![];
+        for (identity, participant) in room.remote_participants() {
+            log::info!("remote_participants: Participant: {identity:?}");
+            if let Some(data) = participant_data(
+                identity.as_str(),
+                participant.name(),
+                participant.sid().as_str().to_string(),
+            ) {
+                participants.push(data);
+            }
+        }
+        participants
+    }
+
+    async fn leave(&self) -> bool {
+        self.compression.reset();
+        self.stop_video_adaptation();
+        self.local_quality.lock().unwrap().take();
+        /* The video, camera and audio tracks go away with the room. */
+        self.video_track.lock().unwrap().take();
+        self.camera_track.lock().unwrap().take();
+        self.microphone_track.lock().unwrap().take();
+        self.audio_track.lock().unwrap().take();
+        self.connection.disconnect().await
+    }
+}
+
+/// Video sink dropping the frames.
+#[derive(Debug)]
+struct NullSink;
+
+impl VideoSink for NullSink {
+    fn push_frame(&self, _frame: VideoFrameRef<'_>) {}
+}
+
+/// `SessionTransport` without a session, for tests.
+///
+/// Joining and publishing succeed and the published video is dropped.
+/// Clones share whether the session was left.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    left: Arc<AtomicBool>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the session was left.
+    pub fn left(&self) -> bool {
+        self.left.load(Ordering::SeqCst)
+    }
+}
+
+impl SessionTransport for MockTransport {
+    async fn join(
+        &self,
+        _token: &str,
+        _sink: impl EventSender,
+        _admission: Admission,
+        _input_authorization: InputAuthorization,
+    ) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    async fn publish_video(
+        &self,
+        _width: u32,
+        _height: u32,
+    ) -> Result<SharedVideoSink, TransportError> {
+        Ok(Arc::new(NullSink))
+    }
+
+    fn video_encoding(&self, _width: u32) -> VideoEncodingInfo {
+        VideoEncodingInfo {
+            codec: "mock",
+            max_framerate: 30,
+            max_bitrate: None,
+        }
+    }
+
+    async fn publish_event(
+        &self,
+        _topic: &str,
+        _event: &ClientEvent,
+    ) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    async fn publish_event_to(
+        &self,
+        _topic: &str,
+        _event: &ClientEvent,
+        _identity: &str,
+    ) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    async fn remote_participants(&self) -> Vec<ParticipantData> {
+        vec![]
+    }
+
+    async fn leave(&self) -> bool {
+        self.left.store(true, Ordering::SeqCst);
+        true
+    }
+}
@@ -1,35 +1,36 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use livekit::options::{TrackPublishOptions, VideoCodec, VideoEncoding};
-use livekit::track::{LocalTrack, LocalVideoTrack, TrackSource};
-use livekit::webrtc::prelude::{RtcVideoSource, VideoResolution};
-use livekit::webrtc::video_source::native::NativeVideoSource;
-use livekit::{DataPacket, Room, RoomEvent, RoomOptions};
-
-use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
-use tokio::sync::Mutex;
-use winit::event_loop::EventLoopProxy;
-
-use crate::{ParticipantData, UserEvent};
 
-// Constants for magic values
-const TOPIC_SHARER_LOCATION: &str = "participant_location";
-const TOPIC_REMOTE_CONTROL_ENABLED: &str = "remote_control_enabled";
-const TOPIC_TICK_RESPONSE: &str = "tick_response";
-const VIDEO_TRACK_NAME: &str = "screen_share";
-const MAX_FRAMERATE: f64 = 30.0;
+use crate::event_sender::EventSender;
+use crate::input::cursor_shape::CursorShape;
+use crate::room::admission::Admission;
+use crate::room::capabilities::{PROTOCOL_VERSION, SHARER_FEATURES};
+use crate::room::client_event::{
+    AdmissionData, CapabilitiesData, ClientEvent, ClipboardUpdateData, ClockSyncResponseData,
+    InputGrantData, ParticipantRemovedData, RemoteControlEnabled, SessionModeData, ShareConfigData,
+    ShareInterruptedData, SharerCursorShapeData, TickData, UpgradeRequiredData, TOPIC_ADMISSION,
+    TOPIC_CAPABILITIES, TOPIC_CLIPBOARD, TOPIC_CLOCK_SYNC, TOPIC_INPUT_GRANT,
+    TOPIC_PARTICIPANT_REMOVED, TOPIC_REMOTE_CONTROL_ENABLED, TOPIC_SESSION_MODE,
+    TOPIC_SHARER_CURSOR_SHAPE, TOPIC_SHARER_LOCATION, TOPIC_SHARE_CONFIG, TOPIC_SHARE_INTERRUPTION,
+    TOPIC_TICK_RESPONSE, TOPIC_UPGRADE_REQUIRED,
+};
+use crate::room::clock_sync::{session_time_ms, ClockSyncRequest};
+use crate::room::compression::COMPRESSION_DEFLATE;
+use crate::room::input_auth::InputAuthorization;
+use crate::room::media::{SharedAudioSink, SharedVideoSink};
+use crate::room::publisher::VideoEncodingInfo;
+use crate::room::sharer_location::{LocationSample, SharerLocationPublisher};
+use crate::room::token::TokenError;
+use crate::room::transport::{LiveKitTransport, SessionTransport};
+use crate::utils::cancellation::CancellationToken;
+use socket_lib::{AudioProcessing, ShareInterruption, WaitReason};
 
-// Bitrate constants (in bits per second)
-const BITRATE_1920: u64 = 2_000_000; // 2 Mbps
-const BITRATE_2048: u64 = 3_500_000; // 3.5 Mbps
-const BITRATE_2560: u64 = 5_000_000; // 5 Mbps
-const BITRATE_DEFAULT: u64 = 8_000_000; // 8 Mbps
-
-// Resolution thresholds
-const WIDTH_THRESHOLD_1920: u32 = 1920;
-const WIDTH_THRESHOLD_2048: u32 = 2048;
-const WIDTH_THRESHOLD_2560: u32 = 2560;
+/* Publishing and data events are light, they don't need a worker per core. */
+const RUNTIME_WORKER_THREADS: usize = 2;
+/* Blocking threads exit after being idle for this long. */
+const RUNTIME_THREAD_KEEP_ALIVE: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 enum RoomServiceCommand {
@@ -37,12 +38,43 @@ enum RoomServiceCommand {
         token: String,
         width: u32,
         height: u32,
-        event_loop_proxy: EventLoopProxy<UserEvent>,
+        event_sender: Box<dyn EventSender>,
+    },
+    RepublishVideo {
+        width: u32,
+        height: u32,
     },
     PublishSharerLocation(f64, f64, bool),
     PublishControllerCursorEnabled(bool),
+    PublishShareInterruption(Option<ShareInterruption>),
+    PublishAdmission(String, Option<WaitReason>),
+    PublishSessionMode(bool),
+    PublishShareConfig(ShareConfigData),
+    PublishParticipantRemoved(String, bool),
+    PublishSharerCursorShape(CursorShape),
+    PublishClipboard(String),
+    PublishCapabilities,
+    PublishInputGrant(String, Option<String>),
+    PublishUpgradeRequired(String, u32),
+    PublishCamera {
+        width: u32,
+        height: u32,
+    },
+    UnpublishCamera,
+    PublishMicrophone {
+        processing: AudioProcessing,
+        sample_rate: u32,
+        num_channels: u32,
+    },
+    UnpublishMicrophone,
+    PublishAudioTrack {
+        sample_rate: u32,
+        num_channels: u32,
+    },
+    UnpublishAudioTrack,
     DestroyRoom,
     TickResponse(u128),
+    ClockSyncResponse(ClockSyncRequest),
     IterateParticipants,
 }
 
@@ -56,6 +88,18 @@ enum RoomServiceCommandResult {
 pub enum RoomServiceError {
     #[error("Failed to create room: {0}")]
     CreateRoom(String),
+    #[error("Invalid room token: {0}")]
+    InvalidToken(String),
+    #[error("Room token expired")]
+    TokenExpired,
+    #[error("Failed to republish video: {0}")]
+    RepublishVideo(String),
+    #[error("Failed to publish camera: {0}")]
+    PublishCamera(String),
+    #[error("Failed to publish microphone: {0}")]
+    PublishMicrophone(String),
+    #[error("Failed to publish system audio: {0}")]
+    PublishAudio(String),
 }
 
 /*
@@ -63,43 +107,91 @@ pub enum RoomServiceError {
  * from a thread in the async runtime.
  */
 #[derive(Debug)]
-struct RoomServiceInner {
-    // TODO: See if we can use a sync::Mutex instead of tokio::sync::Mutex
-    room: Mutex<Option<Room>>,
-    buffer_source: Arc<std::sync::Mutex<Option<NativeVideoSource>>>,
+struct RoomServiceInner<T: SessionTransport> {
+    transport: T,
+    buffer_source: Arc<std::sync::Mutex<Option<SharedVideoSink>>>,
+    /* Source of the camera track, `None` while the camera is off. */
+    camera_source: Arc<std::sync::Mutex<Option<SharedVideoSink>>>,
+    /* Source of the microphone track, `None` while the microphone is off. */
+    microphone_source: Arc<std::sync::Mutex<Option<SharedAudioSink>>>,
+    /* Source of the system audio track, `None` while the system audio is off. */
+    audio_source: Arc<std::sync::Mutex<Option<SharedAudioSink>>>,
+    admission: Admission,
+    input_authorization: InputAuthorization,
 }
 
 /// RoomService is a wrapper around the LiveKit room, on creation it
-/// spawns a thread for handling async code.
+/// spawns a task handling its commands on the async runtime shared by all
+/// the room services, see `shared_runtime`.
 /// It exposes a few functions for sending commands to the room service.
 ///
+/// The connection lifecycle, track/data publishing and data event translation
+/// are implemented in the `room::connection`, `room::publisher` and
+/// `room::data_events` modules, this struct only sequences them through
+/// a `SessionTransport`. LiveKit is the default transport.
+///
 /// The room service is responsible for:
 /// - Creating a room
 /// - Destroying a room
+/// - Republishing the video at another resolution
 /// - Publishing sharer location
 /// - Publishing controller cursor enabled
+/// - Publishing share interruptions
+/// - Publishing participant admissions
+/// - Granting input to the authorized controllers
+/// - Publishing the sharer's camera, microphone and system audio
 /// - Publishing tick response
+/// - Answering clock sync requests
 #[derive(Debug)]
-pub struct RoomService {
-    /* The runtime is used to spawn a thread for handling room events. */
-    _async_runtime: tokio::runtime::Runtime,
+pub struct RoomService<T: SessionTransport = LiveKitTransport> {
+    /* Dropping the sender ends the command task, which leaves the room. */
     service_command_tx: mpsc::UnboundedSender<RoomServiceCommand>,
-    /* This is used to receive the result of the command, now only for create room. */
+    /* This is used to receive the result of the command, for create room, republishing the video and the camera and audio tracks. */
     service_command_res_rx: std::sync::mpsc::Receiver<RoomServiceCommandResult>,
-    inner: Arc<RoomServiceInner>,
+    inner: Arc<RoomServiceInner<T>>,
+    /* The command task, awaited by `shutdown`. */
+    task: tokio::task::JoinHandle<()>,
+}
+
+/// Returns the async runtime of the room services, created by the first one.
+///
+/// The room services share one runtime with a couple of workers
+/// instead of a worker per core each. Idle workers are parked and the
+/// blocking threads exit after `RUNTIME_THREAD_KEEP_ALIVE`, so no threads
+/// spin while no room is active.
+///
+/// # Returns
+///
+/// * `Ok(&Runtime)` - The shared runtime
+/// * `Err(std::io::Error)` - If the runtime could not be created
+fn shared_runtime() -> Result<&'static tokio::runtime::Runtime, std::io::Error> {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(RUNTIME_WORKER_THREADS)
+        .thread_keep_alive(RUNTIME_THREAD_KEEP_ALIVE)
+        .thread_name("room-service")
+        .enable_all()
+        .build()?;
+    Ok(RUNTIME.get_or_init(|| runtime))
 }
 
 impl RoomService {
     /// Creates a new RoomService instance.
     ///
-    /// This function initializes a multi-threaded async runtime and spawns a background
-    /// task to handle room service commands. The service manages LiveKit room connections
+    /// This function spawns a background task on the shared async runtime
+    /// to handle room service commands. The service manages LiveKit room connections
     /// and provides methods for publishing data to the room.
     ///
     /// # Arguments
     ///
     /// * `livekit_server_url` - The URL of the LiveKit server to connect to
-    /// * `event_loop_proxy` - The event loop proxy to send events to
+    /// * `event_sender` - Where the participant events are sent
+    /// * `admission` - Decides which participants are admitted to the rooms
+    /// * `input_authorization` - Decides which input events are injected
+    /// * `cancel` - Cancellation of the application, the command task leaves the room on it
     ///
     /// # Returns
     ///
@@ -107,65 +199,139 @@ impl RoomService {
     /// * `Err(std::io::Error)` - If the async runtime could not be created
     pub fn new(
         livekit_server_url: String,
-        event_loop_proxy: EventLoopProxy<UserEvent>,
+        event_sender: impl EventSender,
+        admission: Admission,
+        input_authorization: InputAuthorization,
+        cancel: CancellationToken,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_transport(
+            LiveKitTransport::new(livekit_server_url),
+            event_sender,
+            admission,
+            input_authorization,
+            cancel,
+        )
+    }
+}
+
+impl<T: SessionTransport> RoomService<T> {
+    /// Creates a new RoomService instance on top of a custom transport.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - The backend used for joining sessions and publishing
+    /// * `event_sender` - Where the participant events are sent
+    /// * `admission` - Decides which participants are admitted to the sessions
+    /// * `input_authorization` - Decides which input events are injected
+    /// * `cancel` - Cancellation of the application, the command task leaves the session on it
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RoomService)` - A new room service instance
+    /// * `Err(std::io::Error)` - If the async runtime could not be created
+    pub fn with_transport(
+        transport: T,
+        event_sender: impl EventSender,
+        admission: Admission,
+        input_authorization: InputAuthorization,
+        cancel: CancellationToken,
     ) -> Result<Self, std::io::Error> {
-        let async_runtime = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()?;
+        let async_runtime = shared_runtime()?;
 
         let inner = Arc::new(RoomServiceInner {
-            room: Mutex::new(None),
+            transport,
             buffer_source: Arc::new(std::sync::Mutex::new(None)),
+            camera_source: Arc::new(std::sync::Mutex::new(None)),
+            microphone_source: Arc::new(std::sync::Mutex::new(None)),
+            audio_source: Arc::new(std::sync::Mutex::new(None)),
+            admission,
+            input_authorization,
         });
         let (service_command_tx, service_command_rx) = mpsc::unbounded_channel();
         let (service_command_res_tx, service_command_res_rx) = std::sync::mpsc::channel();
-        async_runtime.spawn(room_service_commands(
+        let task = async_runtime.spawn(room_service_commands(
             service_command_rx,
             service_command_res_tx,
             inner.clone(),
-            livekit_server_url,
-            event_loop_proxy,
+            Box::new(event_sender),
+            cancel,
         ));
 
         Ok(Self {
-            _async_runtime: async_runtime,
             service_command_tx,
             service_command_res_rx,
             inner,
+            task,
         })
     }
 
+    /// Stops the command task and waits for it to leave the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Longest time to wait, the task keeps leaving in the background after it
+    pub fn shutdown(self, timeout: Duration) {
+        let Self {
+            service_command_tx,
+            task,
+            ..
+        } = self;
+        drop(service_command_tx);
+        let Ok(async_runtime) = shared_runtime() else {
+            return;
+        };
+        /* The timer has to be created inside the runtime. */
+        match async_runtime.block_on(async { tokio::time::timeout(timeout, task).await }) {
+            Ok(_) => log::info!("RoomService::shutdown: command task stopped"),
+            Err(_) => log::warn!("RoomService::shutdown: command task didn't stop in {timeout:?}"),
+        }
+    }
+
     /// Creates a room, this will block until the room is created.
     ///
     /// This function will block until the room is created in the
-    /// async runtime thread.
+    /// async runtime thread. The token is checked locally first, an
+    /// expired token or one without the needed grants fails early.
     ///
     /// # Arguments
     ///
     /// * `token` - The token to use to connect to the room
     /// * `width` - The width of the video track
     /// * `height` - The height of the video track
-    /// * `event_loop_proxy` - The event loop proxy to send events to
+    /// * `event_sender` - Where the room's events are sent
     ///
     /// # Returns
     ///
     /// * `Ok(())` - The room was created successfully
-    /// * `Err(())` - The room was not created successfully
+    /// * `Err(RoomServiceError::TokenExpired)` - The token has expired
+    /// * `Err(RoomServiceError::InvalidToken)` - The token is malformed or lacks the needed grants
+    /// * `Err(RoomServiceError::CreateRoom)` - The room was not created successfully
     pub fn create_room(
         &self,
         token: String,
         width: u32,
         height: u32,
-        event_loop_proxy: EventLoopProxy<UserEvent>,
+        event_sender: impl EventSender,
     ) -> Result<(), RoomServiceError> {
         log::info!("create_room: {token:?}, {width:?}, {height:?}");
+        match self.inner.transport.check_token(&token) {
+            Ok(()) => {}
+            Err(TokenError::Expired(exp)) => {
+                log::warn!("create_room: token expired at {exp}");
+                return Err(RoomServiceError::TokenExpired);
+            }
+            Err(e) => {
+                log::warn!("create_room: invalid token: {e}");
+                return Err(RoomServiceError::InvalidToken(e.to_string()));
+            }
+        }
         let res = self
             .service_command_tx
             .send(RoomServiceCommand::CreateRoom {
                 token,
                 width,
                 height,
-                event_loop_proxy,
+                event_sender: Box::new(event_sender),
             });
         if let Err(e) = res {
             return Err(RoomServiceError::CreateRoom(format!(
@@ -195,18 +361,57 @@ impl RoomService {
         }
     }
 
-    /// Retrieves the native video source buffer for screen sharing.
+    /// Publishes the video again for frames of `width`x`height`, the room stays joined.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The new width of the video track
+    /// * `height` - The new height of the video track
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The video is published at the new size, `get_buffer_source`
+    ///   returns the source frames need to be pushed to
+    /// * `Err(RoomServiceError::RepublishVideo)` - No room is joined or the
+    ///   transport couldn't publish the track
+    pub fn republish_video(&self, width: u32, height: u32) -> Result<(), RoomServiceError> {
+        log::info!("republish_video: {width}x{height}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::RepublishVideo { width, height });
+        if let Err(e) = res {
+            return Err(RoomServiceError::RepublishVideo(format!(
+                "Failed to send command: {e:?}"
+            )));
+        }
+        match self.service_command_res_rx.recv() {
+            Ok(RoomServiceCommandResult::Success) => Ok(()),
+            Ok(RoomServiceCommandResult::Failure) => Err(RoomServiceError::RepublishVideo(
+                "Failed to publish track".to_string(),
+            )),
+            Err(e) => Err(RoomServiceError::RepublishVideo(format!(
+                "Failed to receive result: {e:?}"
+            ))),
+        }
+    }
+
+    /// Returns `true` while a room is joined and publishing the video.
+    pub fn room_connected(&self) -> bool {
+        self.inner.buffer_source.lock().unwrap().is_some()
+    }
+
+    /// Retrieves the video sink for screen sharing.
     ///
-    /// This function returns a clone of the `NativeVideoSource` that was created
-    /// when the room was established. The buffer source is used to send video
-    /// frames to the LiveKit room for screen sharing.
+    /// This function returns a clone of the sink that was created when the
+    /// room was established. The sink is used to send video frames to the
+    /// transport for screen sharing.
     ///
     /// This is only called after the room has been created otherwise it will panic.
     ///
     /// # Returns
     ///
-    /// * `NativeVideoSource` - The video source buffer for sending frames
-    pub fn get_buffer_source(&self) -> NativeVideoSource {
+    /// * `SharedVideoSink` - The video sink for sending frames
+    pub fn get_buffer_source(&self) -> SharedVideoSink {
         log::info!("get_buffer_source");
         let buffer_source = {
             let inner = self.inner.buffer_source.lock().unwrap();
@@ -215,6 +420,195 @@ impl RoomService {
         buffer_source.expect("get_buffer_source: Buffer source not found (this shouldn't happen)")
     }
 
+    /// Publishes the sharer's camera as a second video track, this will block
+    /// until the track is published.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the camera frames
+    /// * `height` - The height of the camera frames
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SharedVideoSink)` - The sink the camera frames are pushed to
+    /// * `Err(RoomServiceError::PublishCamera)` - No room is joined or the
+    ///   transport couldn't publish the track
+    pub fn publish_camera(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Result<SharedVideoSink, RoomServiceError> {
+        log::info!("publish_camera: {width}x{height}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishCamera { width, height });
+        if let Err(e) = res {
+            return Err(RoomServiceError::PublishCamera(format!(
+                "Failed to send command: {e:?}"
+            )));
+        }
+        match self.service_command_res_rx.recv() {
+            Ok(RoomServiceCommandResult::Success) => {
+                let camera_source = self.inner.camera_source.lock().unwrap().clone();
+                camera_source.ok_or_else(|| {
+                    RoomServiceError::PublishCamera("Camera source not found".to_string())
+                })
+            }
+            Ok(RoomServiceCommandResult::Failure) => Err(RoomServiceError::PublishCamera(
+                "Failed to publish track".to_string(),
+            )),
+            Err(e) => Err(RoomServiceError::PublishCamera(format!(
+                "Failed to receive result: {e:?}"
+            ))),
+        }
+    }
+
+    /// Stops publishing the camera track.
+    pub fn unpublish_camera(&self) {
+        log::info!("unpublish_camera");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::UnpublishCamera);
+        if let Err(e) = res {
+            log::error!("unpublish_camera: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Publishes the sharer's microphone as an audio track, this will block
+    /// until the track is published.
+    ///
+    /// # Arguments
+    ///
+    /// * `processing` - The webrtc audio processing stages to enable
+    /// * `sample_rate` - The sample rate of the microphone
+    /// * `num_channels` - The channels of the microphone
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SharedAudioSink)` - The sink the microphone samples are pushed to
+    /// * `Err(RoomServiceError::PublishMicrophone)` - No room is joined or the
+    ///   transport couldn't publish the track
+    pub fn publish_microphone(
+        &self,
+        processing: AudioProcessing,
+        sample_rate: u32,
+        num_channels: u32,
+    ) -> Result<SharedAudioSink, RoomServiceError> {
+        log::info!("publish_microphone: {sample_rate}Hz {num_channels} channels {processing:?}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishMicrophone {
+                processing,
+                sample_rate,
+                num_channels,
+            });
+        if let Err(e) = res {
+            return Err(RoomServiceError::PublishMicrophone(format!(
+                "Failed to send command: {e:?}"
+            )));
+        }
+        match self.service_command_res_rx.recv() {
+            Ok(RoomServiceCommandResult::Success) => {
+                let microphone_source = self.inner.microphone_source.lock().unwrap().clone();
+                microphone_source.ok_or_else(|| {
+                    RoomServiceError::PublishMicrophone("Microphone source not found".to_string())
+                })
+            }
+            Ok(RoomServiceCommandResult::Failure) => Err(RoomServiceError::PublishMicrophone(
+                "Failed to publish track".to_string(),
+            )),
+            Err(e) => Err(RoomServiceError::PublishMicrophone(format!(
+                "Failed to receive result: {e:?}"
+            ))),
+        }
+    }
+
+    /// Stops publishing the microphone track.
+    pub fn unpublish_microphone(&self) {
+        log::info!("unpublish_microphone");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::UnpublishMicrophone);
+        if let Err(e) = res {
+            log::error!("unpublish_microphone: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Publishes the audio the sharer's machine plays as a second audio track,
+    /// this will block until the track is published.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - The sample rate of the output device
+    /// * `num_channels` - The channels of the output device
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SharedAudioSink)` - The sink the system audio samples are pushed to
+    /// * `Err(RoomServiceError::PublishAudio)` - No room is joined or the
+    ///   transport couldn't publish the track
+    pub fn publish_audio_track(
+        &self,
+        sample_rate: u32,
+        num_channels: u32,
+    ) -> Result<SharedAudioSink, RoomServiceError> {
+        log::info!("publish_audio_track: {sample_rate}Hz {num_channels} channels");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishAudioTrack {
+                sample_rate,
+                num_channels,
+            });
+        if let Err(e) = res {
+            return Err(RoomServiceError::PublishAudio(format!(
+                "Failed to send command: {e:?}"
+            )));
+        }
+        match self.service_command_res_rx.recv() {
+            Ok(RoomServiceCommandResult::Success) => {
+                let audio_source = self.inner.audio_source.lock().unwrap().clone();
+                audio_source.ok_or_else(|| {
+                    RoomServiceError::PublishAudio("Audio source not found".to_string())
+                })
+            }
+            Ok(RoomServiceCommandResult::Failure) => Err(RoomServiceError::PublishAudio(
+                "Failed to publish track".to_string(),
+            )),
+            Err(e) => Err(RoomServiceError::PublishAudio(format!(
+                "Failed to receive result: {e:?}"
+            ))),
+        }
+    }
+
+    /// Stops publishing the system audio track.
+    pub fn unpublish_audio_track(&self) {
+        log::info!("unpublish_audio_track");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::UnpublishAudioTrack);
+        if let Err(e) = res {
+            log::error!("unpublish_audio_track: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Returns how the transport encodes the published video.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the video track
+    pub fn video_encoding(&self, width: u32) -> VideoEncodingInfo {
+        self.inner.transport.video_encoding(width)
+    }
+
+    /// Caps the framerate of the published video, see `SessionTransport::set_video_framerate`.
+    ///
+    /// # Arguments
+    ///
+    /// * `framerate` - Frames per second, the transport's default when `None`
+    pub fn set_video_framerate(&self, framerate: Option<u32>) {
+        self.inner.transport.set_video_framerate(framerate);
+    }
+
     /// Publishes the sharer's cursor location to the room.
     ///
     /// This function sends the current cursor position of the person sharing their screen
@@ -251,6 +645,167 @@ impl RoomService {
         }
     }
 
+    /// Publishes the shared display's interruption to the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the display is interrupted, `None` when it was resumed
+    pub fn publish_share_interruption(&self, reason: Option<ShareInterruption>) {
+        log::info!("publish_share_interruption: {reason:?}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishShareInterruption(reason));
+        if let Err(e) = res {
+            log::error!("publish_share_interruption: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Tells a participant whether it has to wait or was admitted to the session.
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The participant the admission is for
+    /// * `waiting` - Why the participant has to wait, `None` when it was admitted
+    pub fn publish_admission(&self, sid: String, waiting: Option<WaitReason>) {
+        log::info!("publish_admission: {sid} {waiting:?}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishAdmission(sid, waiting));
+        if let Err(e) = res {
+            log::error!("publish_admission: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Publishes the mode of the session to the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `view_only` - Whether the session never simulates the controllers' input
+    pub fn publish_session_mode(&self, view_only: bool) {
+        log::info!("publish_session_mode: view only: {view_only}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishSessionMode(view_only));
+        if let Err(e) = res {
+            log::error!("publish_session_mode: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Publishes the configuration of the screen share to the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The published video's size and framerate, the shared content and the session's state
+    pub fn publish_share_config(&self, config: ShareConfigData) {
+        log::info!("publish_share_config: {config:?}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishShareConfig(config));
+        if let Err(e) = res {
+            log::error!("publish_share_config: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Publishes the shape of the sharer's system cursor to the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `shape` - The shape the cursor changed to
+    pub fn publish_sharer_cursor_shape(&self, shape: CursorShape) {
+        log::debug!("publish_sharer_cursor_shape: {shape:?}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishSharerCursorShape(shape));
+        if let Err(e) = res {
+            log::error!("publish_sharer_cursor_shape: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Sends the text copied on the sharer's machine to the participants of
+    /// the identities authorized for input, the others never see it.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The copied text, see `clipboard::ClipboardSync`
+    pub fn publish_clipboard(&self, text: String) {
+        log::debug!("publish_clipboard: {} bytes", text.len());
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishClipboard(text));
+        if let Err(e) = res {
+            log::error!("publish_clipboard: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Announces the data-channel features the sharer supports, the
+    /// participants answer with theirs and bulky payloads are compressed once
+    /// all of them inflate them.
+    pub fn publish_capabilities(&self) {
+        log::debug!("publish_capabilities");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishCapabilities);
+        if let Err(e) = res {
+            log::error!("publish_capabilities: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Sends its input token to the participants with `identity`, only they
+    /// see it.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity the input was allowed or revoked for
+    /// * `token` - The token the identity stamps its input with, `None` when
+    ///   its input was revoked
+    pub fn publish_input_grant(&self, identity: String, token: Option<String>) {
+        log::info!(
+            "publish_input_grant: {identity} authorized: {}",
+            token.is_some()
+        );
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishInputGrant(identity, token));
+        if let Err(e) = res {
+            log::error!("publish_input_grant: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Asks the participants with `identity` to upgrade, only they see it.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity whose client is older than the sharer accepts input from
+    /// * `min_version` - The oldest protocol version the sharer accepts input from
+    pub fn publish_upgrade_required(&self, identity: String, min_version: u32) {
+        log::info!("publish_upgrade_required: {identity} min version: {min_version}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishUpgradeRequired(
+                identity,
+                min_version,
+            ));
+        if let Err(e) = res {
+            log::error!("publish_upgrade_required: Failed to send command: {e:?}");
+        }
+    }
+
+    /// Tells the room that a participant was removed, its client leaves the call.
+    ///
+    /// # Arguments
+    ///
+    /// * `sid` - The removed participant
+    /// * `banned` - Whether the participant's identity is banned for the session
+    pub fn publish_participant_removed(&self, sid: String, banned: bool) {
+        log::info!("publish_participant_removed: {sid} banned: {banned}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::PublishParticipantRemoved(sid, banned));
+        if let Err(e) = res {
+            log::error!("publish_participant_removed: Failed to send command: {e:?}");
+        }
+    }
+
     /// This was used for latency measurement, needs to
     /// be integrated properly for production usage.
     pub fn tick_response(&self, time: u128) {
@@ -263,8 +818,24 @@ impl RoomService {
         }
     }
 
+    /// Answers a participant's clock sync request with the session clock's timestamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request, stamped with when it arrived
+    pub fn publish_clock_sync(&self, request: ClockSyncRequest) {
+        log::debug!("publish_clock_sync: {request:?}");
+        let res = self
+            .service_command_tx
+            .send(RoomServiceCommand::ClockSyncResponse(request));
+        if let Err(e) = res {
+            log::error!("publish_clock_sync: Failed to send command: {e:?}");
+        }
+    }
+
     /// Iterates over the participants in the room and sends an event to the event loop
-    /// for each participant that is not an audio participant.
+    /// for each participant that is not an audio participant, as connected or waiting
+    /// depending on its admission.
     pub fn iterate_participants(&self) {
         log::info!("iterate_participants");
         let res = self
@@ -280,31 +851,83 @@ impl RoomService {
 ///
 /// This function processes commands sent through the `service_rx` channel and executes
 /// corresponding actions on the LiveKit room. It runs continuously until the channel
-/// is closed or an unrecoverable error occurs.
+/// is closed, i.e. the service was dropped, or `cancel` is cancelled, then it leaves the room.
 ///
 /// # Arguments
 ///
 /// * `service_rx` - Unbounded receiver for room service commands
 /// * `tx` - Synchronous sender for command results (Success/Failure)
 /// * `inner` - Shared reference to the room service inner state
+/// * `event_sender` - Where the participant events are sent
+/// * `cancel` - Cancellation of the application
 ///
 /// # Commands Handled
 ///
 /// * `CreateRoom` - Creates a new LiveKit room connection, publishes a video track,
 ///   and sets up event handling. If a room already exists, it will be closed first.
-///   The video track is configured with VP9 codec and adaptive bitrate based on width.
 ///
 /// * `DestroyRoom` - Closes the current room connection and cleans up associated
 ///   resources including the buffer source.
 ///
+/// * `RepublishVideo` - Replaces the video track with one of another size, the
+///   buffer source is replaced when the transport publishes a new one. The result
+///   is sent back through the `tx` channel.
+///
 /// * `PublishSharerLocation` - Publishes sharer cursor position data to the room
-///   with topic "sharer_location".
+///   with topic "participant_location", mostly unreliably with periodic reliable
+///   keyframes, see `room::sharer_location`. The last move is repeated reliably
+///   once the cursor rests, the task wakes up for it between the commands.
 ///
 /// * `PublishControllerCursorEnabled` - Publishes remote control enable/disable
 ///   status to the room with topic "remote_control_enabled".
 ///
+/// * `PublishShareInterruption` - Publishes the shared display's interruption or
+///   resumption to the room with topic "share_interruption".
+///
+/// * `PublishAdmission` - Publishes whether a participant has to wait or was
+///   admitted to the room with topic "admission".
+///
+/// * `PublishSessionMode` - Publishes whether the session is view-only to the room
+///   with topic "session_mode".
+///
+/// * `PublishShareConfig` - Publishes the configuration of the screen share to the
+///   room with topic "share_config".
+///
+/// * `PublishParticipantRemoved` - Publishes that a participant was kicked or banned
+///   to the room with topic "participant_removed".
+///
+/// * `PublishClipboard` - Publishes the text copied on the sharer's machine only to
+///   the identities authorized for input with topic "clipboard".
+///
+/// * `PublishCapabilities` - Publishes the data-channel features the sharer supports
+///   to the room with topic "capabilities".
+///
+/// * `PublishInputGrant` - Publishes the input token of an identity only to its
+///   participants with topic "input_grant".
+///
+/// * `PublishUpgradeRequired` - Asks the participants of an outdated identity to
+///   upgrade with topic "upgrade_required".
+///
+/// * `PublishCamera` - Publishes the sharer's camera as a second video track, the
+///   result is sent back through the `tx` channel.
+///
+/// * `UnpublishCamera` - Stops publishing the camera track.
+///
+/// * `PublishMicrophone` - Publishes the sharer's microphone as an audio track, the
+///   result is sent back through the `tx` channel.
+///
+/// * `UnpublishMicrophone` - Stops publishing the microphone track.
+///
+/// * `PublishAudioTrack` - Publishes the audio the sharer's machine plays as an
+///   audio track, the result is sent back through the `tx` channel.
+///
+/// * `UnpublishAudioTrack` - Stops publishing the system audio track.
+///
 /// * `TickResponse` - Publishes timing data to the room with topic "tick_response".
 ///
+/// * `ClockSyncResponse` - Publishes the session clock's timestamps for a clock sync
+///   request to the room with topic "clock_sync".
+///
 /// * `IterateParticipants` - Iterates over the participants in the room and sends an event
 ///   to the event loop for each participant that is not an audio participant.
 ///
@@ -314,14 +937,34 @@ impl RoomService {
 /// subsequent commands. Command results are sent back through the `tx` channel.
 /// Room state validation is performed before executing commands that require an
 /// active room connection.
-async fn room_service_commands(
+async fn room_service_commands<T: SessionTransport>(
     mut service_rx: mpsc::UnboundedReceiver<RoomServiceCommand>,
     tx: std::sync::mpsc::Sender<RoomServiceCommandResult>,
-    inner: Arc<RoomServiceInner>,
-    livekit_server_url: String,
-    event_loop_proxy: EventLoopProxy<UserEvent>,
+    inner: Arc<RoomServiceInner<T>>,
+    event_sender: Box<dyn EventSender>,
+    cancel: CancellationToken,
 ) {
-    while let Some(command) = service_rx.recv().await {
+    let mut sharer_location = SharerLocationPublisher::new();
+    loop {
+        let command = match sharer_location.rest_keyframe_at() {
+            Some(rest_keyframe_at) => tokio::select! {
+                command = service_rx.recv() => command,
+                _ = cancel.cancelled() => None,
+                _ = tokio::time::sleep_until(rest_keyframe_at.into()) => {
+                    if let Some(sample) = sharer_location.rest_keyframe(std::time::Instant::now()) {
+                        publish_sharer_location(&inner, sample).await;
+                    }
+                    continue;
+                }
+            },
+            None => tokio::select! {
+                command = service_rx.recv() => command,
+                _ = cancel.cancelled() => None,
+            },
+        };
+        let Some(command) = command else {
+            break;
+        };
         log::debug!("room_service_commands: Received command {command:?}");
         match command {
             // TODO: Break this into create room and publish track commands
@@ -329,27 +972,36 @@ async fn room_service_commands(
                 token,
                 width,
                 height,
-                event_loop_proxy,
+                event_sender,
             } => {
-                {
-                    let mut inner_room = inner.room.lock().await;
-                    if inner_room.is_some() {
-                        log::warn!("room_service_commands: Room already exists, killing it.");
-                        let room = inner_room.take().unwrap();
-                        let res = room.close().await;
-                        if let Err(e) = res {
-                            log::error!("room_service_commands: Failed to close room: {e:?}");
-                        }
+                /* The participants of the previous room don't count against the new one. */
+                inner.admission.reset();
+                sharer_location = SharerLocationPublisher::new();
+                /* Input is authorized again in every session, with new tokens. */
+                inner.input_authorization.reset();
+                let res = inner
+                    .transport
+                    .join(
+                        &token,
+                        event_sender,
+                        inner.admission.clone(),
+                        inner.input_authorization.clone(),
+                    )
+                    .await;
+                if let Err(e) = res {
+                    log::error!("room_service_commands: {e}");
+                    let res = tx.send(RoomServiceCommandResult::Failure);
+                    if let Err(e) = res {
+                        log::error!("room_service_commands: Failed to send result: {e:?}");
                     }
+                    continue;
                 }
 
-                let url = livekit_server_url.clone();
-
-                let connect_result = Room::connect(&url, &token, RoomOptions::default()).await;
-                let (room, rx) = match connect_result {
-                    Ok((room, rx)) => (room, rx),
-                    Err(_) => {
-                        log::error!("room_service_commands: Failed to connect to room");
+                let buffer_source = match inner.transport.publish_video(width, height).await {
+                    Ok(buffer_source) => buffer_source,
+                    Err(e) => {
+                        log::error!("room_service_command: Failed to publish track: {e:?}");
+                        inner.transport.leave().await;
                         let res = tx.send(RoomServiceCommandResult::Failure);
                         if let Err(e) = res {
                             log::error!("room_service_commands: Failed to send result: {e:?}");
@@ -358,52 +1010,6 @@ async fn room_service_commands(
                     }
                 };
 
-                let user_sid = room.local_participant().sid().as_str().to_string();
-                // TODO: Check if this will need cleanup
-                /* Spawn thread for handling livekit data events. */
-                tokio::spawn(handle_room_events(rx, event_loop_proxy, user_sid));
-
-                let buffer_source = NativeVideoSource::new(VideoResolution { width, height });
-                let track = LocalVideoTrack::create_video_track(
-                    VIDEO_TRACK_NAME,
-                    RtcVideoSource::Native(buffer_source.clone()),
-                );
-
-                /* Have different max_bitrate based on width. */
-                let max_bitrate = match width {
-                    WIDTH_THRESHOLD_1920 => BITRATE_1920,
-                    WIDTH_THRESHOLD_2048 => BITRATE_2048,
-                    WIDTH_THRESHOLD_2560 => BITRATE_2560,
-                    _ => BITRATE_DEFAULT,
-                };
-
-                let res = room
-                    .local_participant()
-                    .publish_track(
-                        LocalTrack::Video(track),
-                        TrackPublishOptions {
-                            source: TrackSource::Screenshare,
-                            video_codec: VideoCodec::VP9,
-                            video_encoding: Some(VideoEncoding {
-                                max_bitrate,
-                                max_framerate: MAX_FRAMERATE,
-                            }),
-                            simulcast: false,
-                            ..Default::default()
-                        },
-                    )
-                    .await;
-                if let Err(e) = res {
-                    log::error!("room_service_command: Failed to publish track: {e:?}");
-                    let res = tx.send(RoomServiceCommandResult::Failure);
-                    if let Err(e) = res {
-                        log::error!("room_service_commands: Failed to send result: {e:?}");
-                    }
-                    continue;
-                }
-
-                let mut inner_room = inner.room.lock().await;
-                *inner_room = Some(room);
                 let mut inner_buffer_source = inner.buffer_source.lock().unwrap();
                 *inner_buffer_source = Some(buffer_source);
                 let res = tx.send(RoomServiceCommandResult::Success);
@@ -412,68 +1018,124 @@ async fn room_service_commands(
                 }
             }
             RoomServiceCommand::DestroyRoom => {
-                let room = {
-                    let mut inner_room = inner.room.lock().await;
-                    if inner_room.is_none() {
-                        log::warn!("room_service_commands: Room doesn't exist");
-                        continue;
-                    }
-                    inner_room.take()
-                };
-                if let Some(room) = room {
-                    let res = room.close().await;
-                    if let Err(e) = res {
-                        log::error!("room_service_commands: Failed to close room: {e:?}");
-                    }
+                if !inner.transport.leave().await {
+                    log::warn!("room_service_commands: Room doesn't exist");
+                    continue;
                 }
 
                 let _buffer_source = {
                     let mut inner_buffer_source = inner.buffer_source.lock().unwrap();
                     inner_buffer_source.take()
                 };
+                inner.camera_source.lock().unwrap().take();
+                inner.microphone_source.lock().unwrap().take();
+                inner.audio_source.lock().unwrap().take();
             }
-            RoomServiceCommand::PublishSharerLocation(x, y, _pointer) => {
-                let inner_room = inner.room.lock().await;
-                if inner_room.is_none() {
-                    log::warn!("room_service_commands: Room doesn't exist");
-                    continue;
+            RoomServiceCommand::RepublishVideo { width, height } => {
+                let result = if inner.buffer_source.lock().unwrap().is_none() {
+                    log::warn!("room_service_commands: No video to republish");
+                    RoomServiceCommandResult::Failure
+                } else {
+                    match inner.transport.republish_video(width, height).await {
+                        Ok(buffer_source) => {
+                            if let Some(buffer_source) = buffer_source {
+                                *inner.buffer_source.lock().unwrap() = Some(buffer_source);
+                            }
+                            RoomServiceCommandResult::Success
+                        }
+                        Err(e) => {
+                            log::error!("room_service_commands: Failed to republish video: {e:?}");
+                            RoomServiceCommandResult::Failure
+                        }
+                    }
+                };
+                if let Err(e) = tx.send(result) {
+                    log::error!("room_service_commands: Failed to send result: {e:?}");
                 }
-                let room = inner_room.as_ref().unwrap();
-                let local_participant = room.local_participant();
-                let res = local_participant
-                    .publish_data(DataPacket {
-                        payload: serde_json::to_vec(&ClientEvent::MouseMove(ClientPoint { x, y }))
-                            .unwrap(),
-                        reliable: true,
-                        topic: Some(TOPIC_SHARER_LOCATION.to_string()),
-                        ..Default::default()
-                    })
-                    .await;
-                if let Err(e) = res {
-                    log::error!("room_service_commands: Failed to publish sharer location: {e:?}");
+            }
+            RoomServiceCommand::PublishCamera { width, height } => {
+                let result = match inner.transport.publish_camera(width, height).await {
+                    Ok(camera_source) => {
+                        *inner.camera_source.lock().unwrap() = Some(camera_source);
+                        RoomServiceCommandResult::Success
+                    }
+                    Err(e) => {
+                        log::error!("room_service_commands: Failed to publish camera: {e:?}");
+                        RoomServiceCommandResult::Failure
+                    }
+                };
+                if let Err(e) = tx.send(result) {
+                    log::error!("room_service_commands: Failed to send result: {e:?}");
                 }
-                log::debug!(
-                    "Published sharer location with x: {x:?}, y: {y:?} to topic: {TOPIC_SHARER_LOCATION:?}"
-                );
             }
-            RoomServiceCommand::PublishControllerCursorEnabled(enabled) => {
-                let inner_room = inner.room.lock().await;
-                if inner_room.is_none() {
-                    log::warn!("room_service_commands: Room doesn't exist");
-                    continue;
+            RoomServiceCommand::UnpublishCamera => {
+                inner.transport.unpublish_camera().await;
+                inner.camera_source.lock().unwrap().take();
+            }
+            RoomServiceCommand::PublishMicrophone {
+                processing,
+                sample_rate,
+                num_channels,
+            } => {
+                let result = match inner
+                    .transport
+                    .publish_microphone(processing, sample_rate, num_channels)
+                    .await
+                {
+                    Ok(microphone_source) => {
+                        *inner.microphone_source.lock().unwrap() = Some(microphone_source);
+                        RoomServiceCommandResult::Success
+                    }
+                    Err(e) => {
+                        log::error!("room_service_commands: Failed to publish microphone: {e:?}");
+                        RoomServiceCommandResult::Failure
+                    }
+                };
+                if let Err(e) = tx.send(result) {
+                    log::error!("room_service_commands: Failed to send result: {e:?}");
                 }
-                let room = inner_room.as_ref().unwrap();
-                let local_participant = room.local_participant();
-                let res = local_participant
-                    .publish_data(DataPacket {
-                        payload: serde_json::to_vec(&ClientEvent::RemoteControlEnabled(
-                            RemoteControlEnabled { enabled },
-                        ))
-                        .unwrap(),
-                        reliable: true,
-                        topic: Some(TOPIC_REMOTE_CONTROL_ENABLED.to_string()),
-                        ..Default::default()
-                    })
+            }
+            RoomServiceCommand::UnpublishMicrophone => {
+                inner.transport.unpublish_microphone().await;
+                inner.microphone_source.lock().unwrap().take();
+            }
+            RoomServiceCommand::PublishAudioTrack {
+                sample_rate,
+                num_channels,
+            } => {
+                let result = match inner
+                    .transport
+                    .publish_audio(sample_rate, num_channels)
+                    .await
+                {
+                    Ok(audio_source) => {
+                        *inner.audio_source.lock().unwrap() = Some(audio_source);
+                        RoomServiceCommandResult::Success
+                    }
+                    Err(e) => {
+                        log::error!("room_service_commands: Failed to publish system audio: {e:?}");
+                        RoomServiceCommandResult::Failure
+                    }
+                };
+                if let Err(e) = tx.send(result) {
+                    log::error!("room_service_commands: Failed to send result: {e:?}");
+                }
+            }
+            RoomServiceCommand::UnpublishAudioTrack => {
+                inner.transport.unpublish_audio().await;
+                inner.audio_source.lock().unwrap().take();
+            }
+            RoomServiceCommand::PublishSharerLocation(x, y, _pointer) => {
+                let sample = sharer_location.sample(x, y, std::time::Instant::now());
+                publish_sharer_location(&inner, sample).await;
+            }
+            RoomServiceCommand::PublishControllerCursorEnabled(enabled) => {
+                let res = inner
+                    .transport
+                    .publish_event(
+                        TOPIC_REMOTE_CONTROL_ENABLED,
+                        &ClientEvent::RemoteControlEnabled(RemoteControlEnabled { enabled }),
+                    )
                     .await;
                 if let Err(e) = res {
                     log::error!(
@@ -481,335 +1143,238 @@ async fn room_service_commands(
                     );
                 }
             }
-            RoomServiceCommand::TickResponse(time) => {
-                let inner_room = inner.room.lock().await;
-                if inner_room.is_none() {
-                    log::warn!("room_service_commands: Room doesn't exist");
-                    continue;
-                }
-                let room = inner_room.as_ref().unwrap();
-                let local_participant = room.local_participant();
-                let res = local_participant
-                    .publish_data(DataPacket {
-                        payload: serde_json::to_vec(&ClientEvent::TickResponse(TickData { time }))
-                            .unwrap(),
-                        reliable: true,
-                        topic: Some(TOPIC_TICK_RESPONSE.to_string()),
-                        ..Default::default()
-                    })
+            RoomServiceCommand::PublishShareInterruption(reason) => {
+                let event = match reason {
+                    Some(reason) => ClientEvent::ShareInterrupted(ShareInterruptedData { reason }),
+                    None => ClientEvent::ShareResumed,
+                };
+                let res = inner
+                    .transport
+                    .publish_event(TOPIC_SHARE_INTERRUPTION, &event)
                     .await;
                 if let Err(e) = res {
-                    log::error!("room_service_commands: Failed to publish tick response: {e:?}");
+                    log::error!(
+                        "room_service_commands: Failed to publish share interruption: {e:?}"
+                    );
                 }
             }
-            RoomServiceCommand::IterateParticipants => {
-                log::info!("room_service_commands: Iterating participants");
-                let room = inner.room.lock().await;
-                if room.is_none() {
-                    log::warn!("room_service_commands: Room doesn't exist");
-                    continue;
-                }
-                let room = room.as_ref().unwrap();
-                for participant in room.remote_participants() {
-                    log::info!("room_service_commands: Participant: {participant:?}");
-
-                    let name = participant.1.name();
-                    if participant.0.as_str().contains("audio") || name.is_empty() {
-                        continue;
-                    }
-
-                    if let Err(e) = event_loop_proxy.send_event(UserEvent::ParticipantConnected(
-                        ParticipantData {
-                            name,
-                            sid: participant.1.sid().as_str().to_string(),
-                        },
-                    )) {
-                        log::error!(
-                            "handle_room_events: Failed to send participant disconnected event: {e:?}"
-                        );
-                    }
+            RoomServiceCommand::PublishAdmission(sid, waiting) => {
+                let res = inner
+                    .transport
+                    .publish_event(
+                        TOPIC_ADMISSION,
+                        &ClientEvent::Admission(AdmissionData { sid, waiting }),
+                    )
+                    .await;
+                if let Err(e) = res {
+                    log::error!("room_service_commands: Failed to publish admission: {e:?}");
                 }
             }
-        }
-    }
-}
-
-/// Represents a 2D point with floating-point coordinates.
-///
-/// This structure is used to represent cursor positions, mouse coordinates,
-/// and other 2D locations within the room service.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ClientPoint {
-    /// The x-coordinate of the point
-    pub x: f64,
-    /// The y-coordinate of the point
-    pub y: f64,
-}
-
-/// Contains data for mouse click events.
-///
-/// This structure captures all the information needed to represent a mouse click,
-/// including position, button information, modifier keys, and click state.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MouseClickData {
-    /// The x-coordinate where the click occurred
-    pub x: f64,
-    /// The y-coordinate where the click occurred
-    pub y: f64,
-    /// The mouse button that was clicked (0=left, 1=right, 2=middle)
-    pub button: u32,
-    /// The number of clicks (1=single, 2=double, etc.)
-    pub clicks: u32,
-    /// Whether the button is being pressed down (true) or released (false)
-    pub down: bool,
-    /// Whether the Shift key was held during the click
-    pub shift: bool,
-    /// Whether the Meta/Cmd key was held during the click
-    pub meta: bool,
-    /// Whether the Ctrl key was held during the click
-    pub ctrl: bool,
-    /// Whether the Alt key was held during the click
-    pub alt: bool,
-}
-
-/// Contains data for mouse visibility events.
-///
-/// This structure is used to communicate whether the mouse cursor should be
-/// visible or hidden on remote clients.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MouseVisibleData {
-    /// Whether the mouse cursor should be visible
-    pub visible: bool,
-}
-
-/// Contains data for mouse wheel scroll events.
-///
-/// This structure represents the scroll delta values for both horizontal
-/// and vertical scrolling directions.
-#[derive(Debug, Serialize, Deserialize)]
-#[allow(non_snake_case)]
-pub struct WheelDelta {
-    /// The horizontal scroll delta (positive = right, negative = left)
-    pub deltaX: f64,
-    /// The vertical scroll delta (positive = down, negative = up)
-    pub deltaY: f64,
-}
-
-/// Contains data for keyboard input events.
-///
-/// This structure captures keyboard input including the keys pressed
-/// and any modifier keys that were held during the keystroke.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct KeystrokeData {
-    /// The key(s) that were pressed (as string representations)
-    pub key: Vec<String>,
-    /// Whether the Meta/Cmd key was held during the keystroke
-    pub meta: bool,
-    /// Whether the Ctrl key was held during the keystroke
-    pub ctrl: bool,
-    /// Whether the Shift key was held during the keystroke
-    pub shift: bool,
-    /// Whether the Alt key was held during the keystroke
-    pub alt: bool,
-    /// Whether the key is being pressed down (true) or released (false)
-    pub down: bool,
-}
-
-/// Contains timing data for tick events.
-///
-/// This structure is used for synchronization and latency measurement
-/// between room participants.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TickData {
-    /// The timestamp value (typically in nanoseconds)
-    pub time: u128,
-}
-
-/// Contains the remote control enabled/disabled state.
-///
-/// This structure is used to communicate whether remote control
-/// functionality is currently enabled in the room.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RemoteControlEnabled {
-    /// Whether remote control is currently enabled
-    pub enabled: bool,
-}
-
-/// Represents all possible client events that can be sent between room participants.
-///
-/// This enum defines the different types of events that can be transmitted through
-/// the LiveKit room, including input events, cursor movements, and control messages.
-/// Events are serialized as JSON with a `type` field and `payload` field containing
-/// the event-specific data.
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type", content = "payload")]
-pub enum ClientEvent {
-    /// Mouse cursor movement event from a remote controller
-    MouseMove(ClientPoint),
-    /// Mouse click event from a remote controller
-    MouseClick(MouseClickData),
-    /// Mouse visibility change event
-    MouseVisible(MouseVisibleData),
-    /// Keyboard input event from a remote controller
-    Keystroke(KeystrokeData),
-    /// Mouse wheel scroll event from a remote controller
-    WheelEvent(WheelDelta),
-    /// Timing synchronization request
-    Tick(TickData),
-    /// Response to a timing synchronization request
-    TickResponse(TickData),
-    /// Remote control enabled/disabled status change
-    RemoteControlEnabled(RemoteControlEnabled),
-}
-
-async fn handle_room_events(
-    mut receiver: mpsc::UnboundedReceiver<RoomEvent>,
-    event_loop_proxy: EventLoopProxy<UserEvent>,
-    user_sid: String,
-) {
-    while let Some(msg) = receiver.recv().await {
-        match msg {
-            RoomEvent::DataReceived {
-                payload,
-                topic: _,
-                kind: _,
-                participant,
-            } => {
-                let client_event: ClientEvent = match serde_json::from_slice(&payload) {
-                    Ok(event) => event,
-                    Err(e) => {
-                        log::error!("handle_room_events: Failed to deserialize event: {e:?}");
-                        continue;
-                    }
-                };
-                log::debug!("handle_room_events: Data received: {client_event:?}");
-                let sid = if let Some(participant) = participant {
-                    participant.sid().as_str().to_string()
-                } else {
-                    log::warn!("handle_room_events: Participant is none");
-                    "".to_string()
-                };
-
-                /* Skip our own events. */
-                if sid == user_sid {
-                    log::debug!("handle_room_events: Skipping own event");
-                    continue;
-                }
-
-                let res = match client_event {
-                    ClientEvent::MouseMove(point) => {
-                        /* let point = translate_mouse_position(point, menu_perc); */
-                        event_loop_proxy.send_event(UserEvent::CursorPosition(
-                            point.x as f32,
-                            point.y as f32,
-                            sid,
-                        ))
-                    }
-                    ClientEvent::MouseClick(click) => {
-                        event_loop_proxy.send_event(UserEvent::MouseClick(
-                            crate::MouseClickData {
-                                x: click.x as f32,
-                                y: click.y as f32,
-                                button: click.button,
-                                clicks: click.clicks as f32,
-                                down: click.down,
-                                shift: click.shift,
-                                meta: click.meta,
-                                ctrl: click.ctrl,
-                                alt: click.alt,
-                            },
-                            sid,
-                        ))
-                    }
-                    ClientEvent::MouseVisible(visible_data) => event_loop_proxy.send_event(
-                        UserEvent::ControllerCursorVisible(visible_data.visible, sid),
-                    ),
-                    ClientEvent::Keystroke(key) => {
-                        event_loop_proxy.send_event(UserEvent::Keystroke(crate::KeystrokeData {
-                            key: key.key[0].clone(),
-                            meta: key.meta,
-                            ctrl: key.ctrl,
-                            shift: key.shift,
-                            alt: key.alt,
-                            down: key.down,
-                        }))
-                    }
-                    ClientEvent::WheelEvent(wheel_data) => {
-                        event_loop_proxy.send_event(UserEvent::Scroll(
-                            crate::ScrollDelta {
-                                x: wheel_data.deltaX,
-                                y: wheel_data.deltaY,
-                            },
-                            sid,
-                        ))
-                    }
-                    ClientEvent::Tick(tick_data) => {
-                        if cfg!(debug_assertions) {
-                            event_loop_proxy.send_event(UserEvent::Tick(tick_data.time))
-                        } else {
-                            Ok(())
-                        }
-                    }
-                    _ => Ok(()),
-                };
+            RoomServiceCommand::PublishSessionMode(view_only) => {
+                let res = inner
+                    .transport
+                    .publish_event(
+                        TOPIC_SESSION_MODE,
+                        &ClientEvent::SessionMode(SessionModeData { view_only }),
+                    )
+                    .await;
                 if let Err(e) = res {
-                    log::error!("handle_room_events: Failed to send message: {e:?}");
+                    log::error!("room_service_commands: Failed to publish session mode: {e:?}");
                 }
             }
-            RoomEvent::ParticipantConnected(participant) => {
-                log::info!("handle_room_events: Participant connected: {participant:?}");
-
-                let name = participant.name();
-                let participant_id = participant.identity().as_str().to_string();
-                if participant_id.contains("audio") || name.is_empty() {
-                    log::debug!("handle_room_events: Skipping participant: {participant:?}");
-                    continue;
+            RoomServiceCommand::PublishShareConfig(config) => {
+                let res = inner
+                    .transport
+                    .publish_event(TOPIC_SHARE_CONFIG, &ClientEvent::ShareConfig(config))
+                    .await;
+                if let Err(e) = res {
+                    log::error!("room_service_commands: Failed to publish share config: {e:?}");
                 }
-
-                if let Err(e) =
-                    event_loop_proxy.send_event(UserEvent::ParticipantConnected(ParticipantData {
-                        name,
-                        sid: participant.sid().as_str().to_string(),
-                    }))
-                {
+            }
+            RoomServiceCommand::PublishParticipantRemoved(sid, banned) => {
+                let res = inner
+                    .transport
+                    .publish_event(
+                        TOPIC_PARTICIPANT_REMOVED,
+                        &ClientEvent::ParticipantRemoved(ParticipantRemovedData { sid, banned }),
+                    )
+                    .await;
+                if let Err(e) = res {
                     log::error!(
-                        "handle_room_events: Failed to send participant connected event: {e:?}"
+                        "room_service_commands: Failed to publish participant removal: {e:?}"
                     );
                 }
             }
-            RoomEvent::ParticipantDisconnected(participant) => {
-                log::info!("handle_room_events: Participant disconnected: {participant:?}");
-
-                if let Err(e) = event_loop_proxy.send_event(UserEvent::ParticipantDisconnected(
-                    ParticipantData {
-                        name: participant.name(),
-                        sid: participant.sid().as_str().to_string(),
-                    },
-                )) {
+            RoomServiceCommand::PublishSharerCursorShape(shape) => {
+                let res = inner
+                    .transport
+                    .publish_event(
+                        TOPIC_SHARER_CURSOR_SHAPE,
+                        &ClientEvent::SharerCursorShape(SharerCursorShapeData { shape }),
+                    )
+                    .await;
+                if let Err(e) = res {
                     log::error!(
-                        "handle_room_events: Failed to send participant disconnected event: {e:?}"
+                        "room_service_commands: Failed to publish sharer cursor shape: {e:?}"
                     );
                 }
             }
-            RoomEvent::TrackPublished {
-                publication,
-                participant,
-            } => {
-                log::info!("handle_room_events: Track published: {publication:?}, {participant:?}");
-                let name = participant.name();
-                let participant_id = participant.identity().as_str().to_string();
-                if participant_id.contains("video") {
-                    log::info!("handle_room_events: Controller {name} takes screen share");
-                    if let Err(e) =
-                        event_loop_proxy.send_event(UserEvent::ControllerTakesScreenShare)
-                    {
+            RoomServiceCommand::PublishClipboard(text) => {
+                let event = ClientEvent::ClipboardUpdate(ClipboardUpdateData { text });
+                for identity in inner.input_authorization.identities() {
+                    let res = inner
+                        .transport
+                        .publish_event_to(TOPIC_CLIPBOARD, &event, &identity)
+                        .await;
+                    if let Err(e) = res {
                         log::error!(
-                            "handle_room_events: Failed to send controller takes screen share event: {e:?}"
+                            "room_service_commands: Failed to publish clipboard to {identity}: {e:?}"
                         );
                     }
                 }
             }
-            _ => {}
+            RoomServiceCommand::PublishCapabilities => {
+                let event = ClientEvent::Capabilities(CapabilitiesData {
+                    compression: vec![COMPRESSION_DEFLATE.to_string()],
+                    version: PROTOCOL_VERSION,
+                    features: SHARER_FEATURES.to_vec(),
+                });
+                let res = inner
+                    .transport
+                    .publish_event(TOPIC_CAPABILITIES, &event)
+                    .await;
+                if let Err(e) = res {
+                    log::error!("room_service_commands: Failed to publish capabilities: {e:?}");
+                }
+            }
+            RoomServiceCommand::PublishInputGrant(identity, token) => {
+                let res = inner
+                    .transport
+                    .publish_event_to(
+                        TOPIC_INPUT_GRANT,
+                        &ClientEvent::InputGrant(InputGrantData { token }),
+                        &identity,
+                    )
+                    .await;
+                if let Err(e) = res {
+                    log::error!("room_service_commands: Failed to publish input grant: {e:?}");
+                }
+            }
+            RoomServiceCommand::PublishUpgradeRequired(identity, min_version) => {
+                let res = inner
+                    .transport
+                    .publish_event_to(
+                        TOPIC_UPGRADE_REQUIRED,
+                        &ClientEvent::UpgradeRequired(UpgradeRequiredData { min_version }),
+                        &identity,
+                    )
+                    .await;
+                if let Err(e) = res {
+                    log::error!("room_service_commands: Failed to publish upgrade required: {e:?}");
+                }
+            }
+            RoomServiceCommand::TickResponse(time) => {
+                let res = inner
+                    .transport
+                    .publish_event(
+                        TOPIC_TICK_RESPONSE,
+                        &ClientEvent::TickResponse(TickData { time }),
+                    )
+                    .await;
+                if let Err(e) = res {
+                    log::error!("room_service_commands: Failed to publish tick response: {e:?}");
+                }
+            }
+            RoomServiceCommand::ClockSyncResponse(request) => {
+                let event = ClientEvent::ClockSyncResponse(ClockSyncResponseData {
+                    sid: request.sid,
+                    t0: request.sent,
+                    t1: request.received,
+                    t2: session_time_ms(),
+                });
+                let res = inner
+                    .transport
+                    .publish_event(TOPIC_CLOCK_SYNC, &event)
+                    .await;
+                if let Err(e) = res {
+                    log::error!("room_service_commands: Failed to publish clock sync: {e:?}");
+                }
+            }
+            RoomServiceCommand::IterateParticipants => {
+                log::info!("room_service_commands: Iterating participants");
+                for data in inner.transport.remote_participants().await {
+                    inner.admission.join(data, &event_sender);
+                }
+            }
+        }
+    }
+
+    /* The service was dropped or cancelled, the runtime outlives it so the room is left here. */
+    if inner.transport.leave().await {
+        log::info!("room_service_commands: Left the room of the stopped service");
+    }
+    inner.buffer_source.lock().unwrap().take();
+    inner.camera_source.lock().unwrap().take();
+    inner.microphone_source.lock().unwrap().take();
+    inner.audio_source.lock().unwrap().take();
+}
+
+/// Publishes a move of the sharer's cursor, reliably only when it is a keyframe.
+async fn publish_sharer_location<T: SessionTransport>(
+    inner: &RoomServiceInner<T>,
+    sample: LocationSample,
+) {
+    let reliable = sample.reliable;
+    let event = ClientEvent::MouseMove(sample.point);
+    let res = if reliable {
+        inner
+            .transport
+            .publish_event(TOPIC_SHARER_LOCATION, &event)
+            .await
+    } else {
+        inner
+            .transport
+            .publish_event_unreliable(TOPIC_SHARER_LOCATION, &event)
+            .await
+    };
+    if let Err(e) = res {
+        log::error!("publish_sharer_location: Failed to publish sharer location: {e:?}");
+    }
+    log::debug!(
+        "Published sharer location {event:?} reliable: {reliable} to topic: {TOPIC_SHARER_LOCATION:?}"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventCollector;
+    use crate::room::transport::MockTransport;
+    use crate::utils::cancellation::SHUTDOWN_TIMEOUT;
+    use std::time::Instant;
+
+    #[test]
+    fn test_cancellation_stops_room_service_commands() {
+        let cancel = CancellationToken::new();
+        let transport = MockTransport::new();
+        let service = RoomService::with_transport(
+            transport.clone(),
+            EventCollector::new(),
+            Admission::new(),
+            InputAuthorization::new(),
+            cancel.clone(),
+        )
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!service.task.is_finished());
+
+        /* The service is kept, only the cancellation stops the command task. */
+        let cancelled_at = Instant::now();
+        cancel.cancel();
+        while !service.task.is_finished() {
+            assert!(cancelled_at.elapsed() < SHUTDOWN_TIMEOUT);
+            std::thread::sleep(Duration::from_millis(10));
         }
+        assert!(transport.left());
     }
 }
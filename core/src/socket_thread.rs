@@ -0,0 +1,494 @@
+//! Receiving side of the socket to the tauri app.
+//!
+//! The tauri app talks to the core over a `CursorSocket`, the socket thread
+//! translates its messages to `UserEvent`s for the event loop and asks the
+//! application to terminate when the tauri app goes away. The replies are
+//! sent by the application on its duplicate of the socket.
+//!
+//! The thread stops once the application's `CancellationToken` is cancelled,
+//! the application shuts the socket down to wake up the blocked read.
+//!
+//! The thread only needs an `EventSender`, the tests drive it over a real
+//! socket pair with a headless stand-in for the event loop.
+
+use std::time::Duration;
+
+use socket_lib::{CursorSocket, Message};
+
+use crate::event_sender::EventSender;
+use crate::utils::cancellation::CancellationToken;
+use crate::UserEvent;
+
+/// Timeout in seconds for socket message reception
+const SOCKET_MESSAGE_TIMEOUT_SECONDS: u64 = 30;
+
+/// Process exit code for errors
+const PROCESS_EXIT_CODE_ERROR: i32 = 1;
+
+/// Time in seconds the socket thread waits for the application to shut down
+/// after asking it to terminate, before exiting the process
+const TERMINATE_GRACE_PERIOD_SECONDS: u64 = 5;
+
+/// Why receiving a message from the tauri app failed.
+#[derive(Debug, PartialEq, Eq)]
+enum SocketReceiveError {
+    /// The tauri app closed its end of the socket
+    Disconnected,
+    /// No message arrived before the timeout
+    Timeout,
+    /// The read was interrupted and can be retried
+    Interrupted,
+    /// Any other error, the connection can't be trusted anymore
+    Other,
+}
+
+impl From<&std::io::Error> for SocketReceiveError {
+    fn from(e: &std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::NotConnected => SocketReceiveError::Disconnected,
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                SocketReceiveError::Timeout
+            }
+            std::io::ErrorKind::Interrupted => SocketReceiveError::Interrupted,
+            _ => SocketReceiveError::Other,
+        }
+    }
+}
+
+/// Receives the messages of the tauri app and forwards them to the event loop.
+///
+/// The thread exits when `cancel` is cancelled, the application does this
+/// and shuts the socket down when it is dropped. When the tauri app
+/// disconnects, stops pinging or the socket fails, the application is asked
+/// to terminate.
+///
+/// # Arguments
+///
+/// * `socket` - Socket connected to the tauri app
+/// * `event_sender` - Where the translated messages are sent, the event loop's proxy
+/// * `cancel` - Cancellation of the application
+pub(crate) fn socket_receive_thread(
+    mut socket: CursorSocket,
+    event_sender: impl EventSender,
+    cancel: CancellationToken,
+) {
+    let message_timeout = Duration::from_secs(SOCKET_MESSAGE_TIMEOUT_SECONDS);
+    let mut last_message = std::time::Instant::now();
+    loop {
+        let res = socket.receive_message_with_timeout(message_timeout);
+        if cancel.is_cancelled() {
+            log::info!("socket_receive_thread: shutting down");
+            return;
+        }
+
+        let message = match res {
+            Ok(message) => message,
+            Err(e) => match SocketReceiveError::from(&e) {
+                SocketReceiveError::Interrupted => continue,
+                /* A read can time out early, only give up when pings really stopped. */
+                SocketReceiveError::Timeout if last_message.elapsed() < message_timeout => continue,
+                SocketReceiveError::Disconnected => {
+                    log::info!("socket_receive_thread: tauri app disconnected: {e:?}");
+                    request_termination(&event_sender, &cancel);
+                    return;
+                }
+                kind => {
+                    log::error!("socket_receive_thread: Error receiving message ({kind:?}): {e:?}");
+                    sentry_utils::add_breadcrumb("socket", format!("receive failed: {kind:?}"));
+                    request_termination(&event_sender, &cancel);
+                    return;
+                }
+            },
+        };
+        last_message = std::time::Instant::now();
+        log::info!("socket_receive_thread: Received message: {message:?}");
+        if !matches!(message, Message::Ping) {
+            sentry_utils::add_breadcrumb("socket", format!("received {}", message.name()));
+        }
+        let Some(user_event) = message_to_user_event(message) else {
+            continue;
+        };
+        if let Err(e) = event_sender.send(user_event) {
+            log::error!("socket_receive_thread: Error sending user event: {e:?}");
+        }
+    }
+}
+
+/// Translates a message of the tauri app to the event the application handles.
+///
+/// # Returns
+///
+/// * `Some(UserEvent)` - The event that needs to be handled by the event loop
+/// * `None` - Pings and the messages the core only sends
+fn message_to_user_event(message: Message) -> Option<UserEvent> {
+    let user_event = match message {
+        Message::GetAvailableContent => UserEvent::GetAvailableContent,
+        Message::StartScreenShare(screen_share_message) => {
+            UserEvent::ScreenShare(screen_share_message)
+        }
+        Message::StartTestPatternShare {
+            token,
+            resolution,
+            fps,
+        } => UserEvent::TestPatternShare {
+            token,
+            resolution,
+            fps,
+        },
+        Message::StopScreenshare => UserEvent::StopScreenShare,
+        Message::Reset => UserEvent::ResetState,
+        Message::ControllerCursorEnabled(enabled) => UserEvent::ControllerCursorEnabled(enabled),
+        // Ping is on purpose empty. We use it only for stopping the receive to timeout.
+        Message::Ping => return None,
+        Message::LivekitServerUrl(url) => UserEvent::LivekitServerUrl(url),
+        Message::GetBreadcrumbs => UserEvent::GetBreadcrumbs,
+        Message::GetDiagnostics => UserEvent::GetDiagnostics,
+        Message::RunNetworkPreflight => UserEvent::RunNetworkPreflight,
+        Message::IdentifyDisplays(labels) => UserEvent::IdentifyDisplays(labels),
+        Message::ChangeResolution(resolution) => UserEvent::ChangeResolution(resolution),
+        Message::SetFramerate(framerate) => UserEvent::SetFramerate(framerate),
+        Message::SetAdmissionPolicy(policy) => UserEvent::SetAdmissionPolicy(policy),
+        Message::SetKeyboardArbitration(policy) => UserEvent::SetKeyboardArbitration(policy),
+        Message::SetKeyboardAppAllowlist(apps) => UserEvent::SetKeyboardAppAllowlist(apps),
+        Message::SetKeyboardLayout(layout) => UserEvent::SetKeyboardLayout(layout),
+        Message::AdmitParticipant(sid) => UserEvent::AdmitParticipant(sid),
+        Message::KickParticipant { sid } => UserEvent::KickParticipant(sid),
+        Message::BanIdentity { identity } => UserEvent::BanIdentity(identity),
+        Message::SetInputAuthorized { sid, authorized } => {
+            UserEvent::SetInputAuthorized(sid, authorized)
+        }
+        Message::SetCursorVisible { sid, visible } => UserEvent::SetCursorVisible(sid, visible),
+        Message::SetThumbnailPrivacy(privacy) => UserEvent::SetThumbnailPrivacy(privacy),
+        Message::SetCaptureExclusions(exclusions) => UserEvent::SetCaptureExclusions(exclusions),
+        Message::SetClipboardSharing(enabled) => UserEvent::SetClipboardSharing(enabled),
+        Message::SetOverlayTheme(theme) => UserEvent::SetOverlayTheme(theme),
+        Message::ReloadAssets => UserEvent::ReloadAssets,
+        Message::OpenSharedMemory => UserEvent::OpenSharedMemory,
+        Message::SetSharePreview(enabled) => UserEvent::SetSharePreview(enabled),
+        Message::GetInputInjectionStatus => UserEvent::GetInputInjectionStatus,
+        Message::GetSessionState => UserEvent::GetSessionState,
+        Message::GetCameras => UserEvent::GetCameras,
+        Message::EnableCamera { device_id } => UserEvent::EnableCamera(device_id),
+        Message::DisableCamera => UserEvent::DisableCamera,
+        Message::GetMicrophones => UserEvent::GetMicrophones,
+        Message::EnableMic {
+            device_id,
+            processing,
+        } => UserEvent::EnableMicrophone(device_id, processing),
+        Message::DisableMic => UserEvent::DisableMicrophone,
+        Message::SetNoiseSuppression(enabled) => UserEvent::SetNoiseSuppression(enabled),
+        Message::GetSystemAudioSupport => UserEvent::GetSystemAudioSupport,
+        Message::EnableSystemAudio => UserEvent::EnableSystemAudio,
+        Message::DisableSystemAudio => UserEvent::DisableSystemAudio,
+        Message::GrantNextControl => UserEvent::GrantNextControl,
+        Message::GetUncleanSession => UserEvent::GetUncleanSession,
+        Message::DismissUncleanSession => UserEvent::DismissUncleanSession,
+        Message::IncomingCall { caller, room } => UserEvent::IncomingCall { caller, room },
+        Message::DismissIncomingCall => UserEvent::DismissIncomingCall,
+        Message::RestartOverlay => UserEvent::RestartOverlay,
+        Message::StartMacroRecording => UserEvent::StartMacroRecording,
+        Message::StopMacroRecording(name) => UserEvent::StopMacroRecording(name),
+        Message::LoadMacro(input_macro) => UserEvent::LoadMacro(input_macro),
+        Message::PlayMacro { id } => UserEvent::PlayMacro(id),
+        _ => {
+            log::error!("message_to_user_event: Unknown message: {message:?}");
+            return None;
+        }
+    };
+    Some(user_event)
+}
+
+/// Asks the application to terminate and waits for it to shut down.
+///
+/// If the application doesn't cancel the token in time the process is
+/// exited, so it never outlives the tauri app.
+fn request_termination(event_sender: &impl EventSender, cancel: &CancellationToken) {
+    if let Err(e) = event_sender.send(UserEvent::Terminate) {
+        log::error!("request_termination: Error sending terminate event: {e:?}");
+    }
+
+    let grace_period = Duration::from_secs(TERMINATE_GRACE_PERIOD_SECONDS);
+    if !cancel.wait_timeout(grace_period) {
+        log::error!("request_termination: application didn't shut down, exiting");
+        std::process::exit(PROCESS_EXIT_CODE_ERROR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_sender::EventSendError;
+    use crate::room::transport::MockTransport;
+    use crate::utils::cancellation::SHUTDOWN_TIMEOUT;
+    use crate::utils::session_dirs;
+    use crate::{Application, RenderLoopRunArgs};
+    use socket_lib::{Content, ContentType, Extent, ScreenShareMessage};
+    use std::sync::mpsc;
+    use std::thread::JoinHandle;
+    use std::time::Instant;
+
+    const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Delivers the events over a channel, as the proxy does to the event loop.
+    #[derive(Debug, Clone)]
+    struct ChannelSender(mpsc::Sender<UserEvent>);
+
+    impl EventSender for ChannelSender {
+        fn send(&self, event: UserEvent) -> Result<(), EventSendError> {
+            self.0
+                .send(event)
+                .map_err(|mpsc::SendError(event)| EventSendError(event))
+        }
+
+        fn boxed_clone(&self) -> Box<dyn EventSender> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn display(id: u32) -> Content {
+        Content {
+            content_type: ContentType::Display,
+            id,
+        }
+    }
+
+    fn screen_share(id: u32, width: f64) -> ScreenShareMessage {
+        ScreenShareMessage {
+            content: display(id),
+            token: "token".to_string(),
+            resolution: Extent {
+                width,
+                height: 1080.0,
+            },
+            view_only: false,
+            aspect_policy: Default::default(),
+            framerate: None,
+        }
+    }
+
+    fn test_pattern_share() -> Message {
+        Message::StartTestPatternShare {
+            token: "token".to_string(),
+            resolution: Extent {
+                width: 640.0,
+                height: 360.0,
+            },
+            fps: 15,
+        }
+    }
+
+    /// Runs the application in place of the event loop, its room sessions on
+    /// a `MockTransport`.
+    ///
+    /// The events that need the event loop aren't sent by the tests, the
+    /// messages the services post are delivered after each event like
+    /// `Application::route` does.
+    fn run_application(
+        name: String,
+        socket: CursorSocket,
+        event_sender: ChannelSender,
+        events: mpsc::Receiver<UserEvent>,
+        cancel: CancellationToken,
+    ) {
+        let input = RenderLoopRunArgs {
+            textures_path: String::new(),
+            render_scale: None,
+            watch_assets: false,
+            socket_name: Some(name),
+            min_client_version: None,
+        };
+        let mut application = Application::with_transport(
+            |_url| MockTransport::new(),
+            input,
+            socket,
+            cancel.clone(),
+            event_sender,
+        )
+        .unwrap();
+        /* Not through `LivekitServerUrl`, it would replace the user's cached URL. */
+        application
+            .session_service
+            .connect("wss://mock".to_string());
+        while !cancel.is_cancelled() {
+            while let Some(message) = application.bus.take() {
+                application.deliver(message);
+            }
+            match events.recv_timeout(Duration::from_millis(10)) {
+                Ok(UserEvent::Terminate) => break,
+                Ok(event) => application.handle_user_event(event),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        /* Cancels the socket thread and shuts its socket down. */
+        drop(application);
+    }
+
+    /// The core's side of a socket pair, with the socket thread and the application running.
+    struct HeadlessCore {
+        socket_thread: JoinHandle<()>,
+        app_thread: JoinHandle<()>,
+        cancel: CancellationToken,
+        /// The application's duplicate of the socket, shut down like the application does when dropped
+        socket: CursorSocket,
+        /// Names the files of the application, see `RenderLoopRunArgs::instance_name`
+        name: String,
+    }
+
+    impl HeadlessCore {
+        /// Waits for the threads and removes the application's session directories.
+        fn join(self) {
+            self.app_thread.join().unwrap();
+            self.socket_thread.join().unwrap();
+            if let Some(root) = session_dirs::default_root(Some(&self.name)) {
+                let _ = std::fs::remove_dir_all(root);
+            }
+        }
+    }
+
+    /// Creates the socket pair, returns the tauri app's side and the running core.
+    fn start_core(name: &str) -> (CursorSocket, HeadlessCore) {
+        let name = format!("{name}-{}", std::process::id());
+        let path = socket_lib::socket_path(&name).unwrap();
+        let server_path = path.clone();
+        let server = std::thread::spawn(move || CursorSocket::new_create(&server_path).unwrap());
+
+        /* The socket exists once the core listens. */
+        let started = Instant::now();
+        let client = loop {
+            match CursorSocket::new(&path) {
+                Ok(socket) => break socket,
+                Err(e) if started.elapsed() < REPLY_TIMEOUT => {
+                    log::debug!("start_core: waiting for the socket: {e:?}");
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => panic!("failed to connect to {path}: {e:?}"),
+            }
+        };
+        let socket = server.join().unwrap();
+
+        let app_socket = socket.duplicate().unwrap();
+        let shutdown_socket = socket.duplicate().unwrap();
+        let (event_tx, event_rx) = mpsc::channel();
+        let cancel = CancellationToken::new();
+        let socket_cancel = cancel.clone();
+        let socket_sender = ChannelSender(event_tx.clone());
+        let socket_thread =
+            std::thread::spawn(move || socket_receive_thread(socket, socket_sender, socket_cancel));
+        let app_name = name.clone();
+        let app_cancel = cancel.clone();
+        let app_thread = std::thread::spawn(move || {
+            run_application(
+                app_name,
+                app_socket,
+                ChannelSender(event_tx),
+                event_rx,
+                app_cancel,
+            )
+        });
+        (
+            client,
+            HeadlessCore {
+                socket_thread,
+                app_thread,
+                cancel,
+                socket: shutdown_socket,
+                name,
+            },
+        )
+    }
+
+    fn receive(client: &mut CursorSocket) -> Message {
+        client.receive_message_with_timeout(REPLY_TIMEOUT).unwrap()
+    }
+
+    #[test]
+    fn test_message_to_user_event() {
+        assert!(message_to_user_event(Message::Ping).is_none());
+        assert!(matches!(
+            message_to_user_event(Message::Reset),
+            Some(UserEvent::ResetState)
+        ));
+        assert!(matches!(
+            message_to_user_event(Message::ControllerCursorEnabled(true)),
+            Some(UserEvent::ControllerCursorEnabled(true))
+        ));
+        assert!(matches!(
+            message_to_user_event(Message::StartScreenShare(screen_share(3, 1920.0))),
+            Some(UserEvent::ScreenShare(data)) if data.content == display(3)
+        ));
+        assert!(matches!(
+            message_to_user_event(Message::SetSharePreview(true)),
+            Some(UserEvent::SetSharePreview(true))
+        ));
+        /* Messages the core only sends aren't forwarded. */
+        assert!(message_to_user_event(Message::StartScreenShareResult(None)).is_none());
+    }
+
+    #[test]
+    fn test_socket_protocol() {
+        let (mut client, core) = start_core("core-protocol-test");
+        assert!(matches!(receive(&mut client), Message::NeedLivekitUrl));
+
+        /* Sent without waiting, the replies come back in the same order. */
+        client.send_message(test_pattern_share()).unwrap();
+        client.send_message(Message::Ping).unwrap();
+        client
+            .send_message(Message::StartScreenShare(screen_share(1, 1920.0)))
+            .unwrap();
+        client.send_message(test_pattern_share()).unwrap();
+        client.send_message(Message::GetSessionState).unwrap();
+
+        match receive(&mut client) {
+            Message::StartScreenShareResult(Some(parameters)) => {
+                assert_eq!(parameters.resolution.width, 640.0);
+                assert_eq!(parameters.fps, 15);
+                assert_eq!(parameters.codec, "mock");
+            }
+            message => panic!("expected a share result, got {message:?}"),
+        }
+        /* Both duplicate starts are rejected, the first share keeps running. */
+        for _ in 0..2 {
+            assert!(matches!(receive(&mut client), Message::AlreadySharing));
+            assert!(matches!(
+                receive(&mut client),
+                Message::StartScreenShareResult(None)
+            ));
+        }
+        match receive(&mut client) {
+            Message::SessionState(state) => assert!(state.sharing),
+            message => panic!("expected the session state, got {message:?}"),
+        }
+
+        client.send_message(Message::StopScreenshare).unwrap();
+        client.send_message(Message::GetSessionState).unwrap();
+        match receive(&mut client) {
+            Message::SessionState(state) => assert!(!state.sharing),
+            message => panic!("expected the session state, got {message:?}"),
+        }
+
+        /* Closing the tauri app's side terminates the application. */
+        drop(client);
+        core.join();
+    }
+
+    #[test]
+    fn test_cancellation_stops_the_socket_thread() {
+        let (mut client, core) = start_core("core-shutdown-test");
+        assert!(matches!(receive(&mut client), Message::NeedLivekitUrl));
+
+        /* The application is dropped while the tauri app is still connected and idle. */
+        let started = Instant::now();
+        core.cancel.cancel();
+        core.socket.shutdown().unwrap();
+        core.socket_thread.join().unwrap();
+        assert!(started.elapsed() < SHUTDOWN_TIMEOUT);
+
+        core.app_thread.join().unwrap();
+        drop(client);
+    }
+}
@@ -0,0 +1,141 @@
+//! Cancellation shared by the application's threads.
+//!
+//! The render loop creates one `CancellationToken` and hands clones of it to
+//! the socket thread, the capture threads and the room services' tasks. When
+//! the application terminates, or is dropped, the token is cancelled and
+//! every thread notices it within a bounded time: the blocking loops check
+//! it between their timed waits, the async tasks await `cancelled` next to
+//! their commands. `SHUTDOWN_TIMEOUT` bounds how long the application waits
+//! for them.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+/// Longest time the application waits for a thread to stop after cancelling.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: Mutex<bool>,
+    /// Wakes the threads blocked in `wait_timeout`
+    condvar: Condvar,
+    /// Wakes the tasks awaiting `cancelled`
+    notify: Notify,
+}
+
+/// Cancels the threads holding a clone of it, clones share the cancellation.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<CancellationState>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token and its clones, the waiting threads and tasks are woken up.
+    pub fn cancel(&self) {
+        let mut cancelled = self.state.cancelled.lock().unwrap();
+        if *cancelled {
+            return;
+        }
+        log::info!("CancellationToken::cancel");
+        *cancelled = true;
+        self.state.condvar.notify_all();
+        self.state.notify.notify_waiters();
+    }
+
+    /// Returns `true` once the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        *self.state.cancelled.lock().unwrap()
+    }
+
+    /// Blocks until the token is cancelled or the timeout elapsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Longest time to block
+    ///
+    /// # Returns
+    ///
+    /// `true` if the token was cancelled.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut cancelled = self.state.cancelled.lock().unwrap();
+        while !*cancelled {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            cancelled = self
+                .state
+                .condvar
+                .wait_timeout(cancelled, remaining)
+                .unwrap()
+                .0;
+        }
+        *cancelled
+    }
+
+    /// Completes once the token is cancelled, for the async tasks.
+    pub async fn cancelled(&self) {
+        let notified = self.state.notify.notified();
+        tokio::pin!(notified);
+        /* Registered before checking, a cancel in between isn't missed. */
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_wakes_the_waiting_threads() {
+        let token = CancellationToken::new();
+        assert!(!token.wait_timeout(Duration::from_millis(10)));
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let token = token.clone();
+                std::thread::spawn(move || token.wait_timeout(Duration::from_secs(10)))
+            })
+            .collect();
+        let started = Instant::now();
+        token.cancel();
+        for waiter in waiters {
+            assert!(waiter.join().unwrap());
+        }
+        assert!(started.elapsed() < SHUTDOWN_TIMEOUT);
+        assert!(token.is_cancelled());
+        assert!(token.wait_timeout(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_cancel_completes_the_waiting_tasks() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let token = CancellationToken::new();
+        let task = {
+            let token = token.clone();
+            runtime.spawn(async move { token.cancelled().await })
+        };
+        token.cancel();
+        runtime.block_on(async {
+            tokio::time::timeout(SHUTDOWN_TIMEOUT, task)
+                .await
+                .expect("the task wasn't cancelled")
+                .unwrap();
+            /* Already cancelled, completes right away. */
+            token.cancelled().await;
+        });
+    }
+}
@@ -0,0 +1,198 @@
+//! Mirrored displays.
+//!
+//! A display mirrored to a projector or a second screen shows up as several
+//! monitors with the same position and size. Depending on the platform an
+//! overlay on one of them shows on every mirror or only on the monitor it
+//! was put on, so the overlay is put on a canonical monitor of the set and
+//! the other monitors of the set can get mirror overlays.
+
+use winit::monitor::MonitorHandle;
+
+/// Position and size of a monitor in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MonitorGeometry {
+    pub fn of(monitor: &MonitorHandle) -> Self {
+        let position = monitor.position();
+        let size = monitor.size();
+        Self {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+        }
+    }
+}
+
+/// Monitors showing the same content, as indices in the monitor list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirrorSet {
+    /// The monitor the overlay is put on
+    pub canonical: usize,
+    /// The other monitors of the set, in list order
+    pub mirrors: Vec<usize>,
+}
+
+/// Finds the monitors mirroring the `selected` one.
+///
+/// # Arguments
+///
+/// * `geometries` - Geometry of every monitor
+/// * `selected` - Index of the monitor showing the shared content
+/// * `primary` - Index of the primary monitor, if known
+///
+/// # Returns
+///
+/// The mirror set of `selected`. The primary monitor is canonical when it is
+/// in the set, mirroring shows the primary monitor's desktop on the others,
+/// otherwise `selected` is.
+pub fn find_mirror_set(
+    geometries: &[MonitorGeometry],
+    selected: usize,
+    primary: Option<usize>,
+) -> MirrorSet {
+    let Some(selected_geometry) = geometries.get(selected) else {
+        return MirrorSet {
+            canonical: selected,
+            mirrors: vec![],
+        };
+    };
+
+    let members: Vec<usize> = geometries
+        .iter()
+        .enumerate()
+        .filter(|(_, geometry)| *geometry == selected_geometry)
+        .map(|(i, _)| i)
+        .collect();
+    let canonical = primary
+        .filter(|primary| members.contains(primary))
+        .unwrap_or(selected);
+
+    MirrorSet {
+        canonical,
+        mirrors: members.into_iter().filter(|&i| i != canonical).collect(),
+    }
+}
+
+/// The monitors of a mirror set, see `find_mirror_set`.
+#[derive(Debug, Clone)]
+pub struct MonitorMirrors {
+    pub canonical: MonitorHandle,
+    pub mirrors: Vec<MonitorHandle>,
+}
+
+/// Finds the monitors mirroring `selected` among `monitors`.
+///
+/// A `selected` monitor missing from `monitors` is canonical without mirrors.
+pub fn monitor_mirrors(
+    monitors: &[MonitorHandle],
+    selected: MonitorHandle,
+    primary: Option<MonitorHandle>,
+) -> MonitorMirrors {
+    let Some(selected_index) = monitors.iter().position(|monitor| *monitor == selected) else {
+        return MonitorMirrors {
+            canonical: selected,
+            mirrors: vec![],
+        };
+    };
+    let primary_index =
+        primary.and_then(|primary| monitors.iter().position(|monitor| *monitor == primary));
+    let geometries: Vec<MonitorGeometry> = monitors.iter().map(MonitorGeometry::of).collect();
+
+    let set = find_mirror_set(&geometries, selected_index, primary_index);
+    MonitorMirrors {
+        canonical: monitors[set.canonical].clone(),
+        mirrors: set.mirrors.iter().map(|&i| monitors[i].clone()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geometry(x: i32, y: i32, width: u32, height: u32) -> MonitorGeometry {
+        MonitorGeometry {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_no_mirrors() {
+        let geometries = [geometry(0, 0, 1920, 1080), geometry(1920, 0, 1920, 1080)];
+        assert_eq!(
+            find_mirror_set(&geometries, 1, Some(0)),
+            MirrorSet {
+                canonical: 1,
+                mirrors: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_primary_is_canonical() {
+        let geometries = [
+            geometry(0, 0, 1920, 1080),
+            geometry(1920, 0, 2560, 1440),
+            geometry(0, 0, 1920, 1080),
+        ];
+        /* The capturer matched the projector, the overlay goes on the primary. */
+        assert_eq!(
+            find_mirror_set(&geometries, 2, Some(0)),
+            MirrorSet {
+                canonical: 0,
+                mirrors: vec![2],
+            }
+        );
+        assert_eq!(
+            find_mirror_set(&geometries, 0, Some(0)),
+            MirrorSet {
+                canonical: 0,
+                mirrors: vec![2],
+            }
+        );
+    }
+
+    #[test]
+    fn test_selected_is_canonical_without_primary_in_set() {
+        let geometries = [
+            geometry(0, 0, 1920, 1080),
+            geometry(1920, 0, 1920, 1080),
+            geometry(1920, 0, 1920, 1080),
+            geometry(1920, 0, 1920, 1080),
+        ];
+        assert_eq!(
+            find_mirror_set(&geometries, 2, Some(0)),
+            MirrorSet {
+                canonical: 2,
+                mirrors: vec![1, 3],
+            }
+        );
+        assert_eq!(
+            find_mirror_set(&geometries, 1, None),
+            MirrorSet {
+                canonical: 1,
+                mirrors: vec![2, 3],
+            }
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_selection() {
+        assert_eq!(
+            find_mirror_set(&[geometry(0, 0, 1920, 1080)], 3, Some(0)),
+            MirrorSet {
+                canonical: 3,
+                mirrors: vec![],
+            }
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Physical size of the displays and the size of the overlay's labels.
+//!
+//! The badges are sized by the display scale, which keeps them readable on
+//! a desk but not on a TV across the room. The label size of the overlay
+//! theme scales them on top of it, `LabelSize::Auto` estimates the viewing
+//! distance from the display's physical size, as reported by its EDID.
+
+use socket_lib::LabelSize;
+use winit::monitor::MonitorHandle;
+
+use super::geometry::Extent;
+
+/// Scale of the labels of each fixed size
+const SMALL_LABEL_SCALE: f64 = 0.75;
+const MEDIUM_LABEL_SCALE: f64 = 1.0;
+const LARGE_LABEL_SCALE: f64 = 1.5;
+/// Largest scale picked by `LabelSize::Auto`
+const MAX_AUTO_LABEL_SCALE: f64 = 3.0;
+
+/// Displays up to this diagonal are assumed to be on a desk
+const DESK_DISPLAY_DIAGONAL_MM: f64 = 810.0;
+const DESK_VIEWING_DISTANCE_MM: f64 = 600.0;
+/// Larger displays are watched from this many times their diagonal
+const VIEWING_DISTANCE_PER_DIAGONAL: f64 = 1.5;
+/// Millimeters per pixel at scale 1 divided by the viewing distance that
+/// badges were designed for, a 27" 1440p monitor on a desk
+const REFERENCE_ANGULAR_PIXEL: f64 = 0.2331 / DESK_VIEWING_DISTANCE_MM;
+
+/// Returns the physical size of the display in millimeters from its EDID.
+///
+/// Projectors and some TVs leave the size out, for them `None` is
+/// returned, like for an EDID that isn't valid.
+pub fn parse_edid_size(edid: &[u8]) -> Option<Extent> {
+    const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+    if edid.len() < 128 || edid[..8] != HEADER {
+        return None;
+    }
+
+    /* The first detailed timing descriptor has the size in millimeters. */
+    let descriptor = &edid[54..72];
+    if descriptor[0] != 0 || descriptor[1] != 0 {
+        let width = descriptor[12] as u32 | ((descriptor[14] as u32 & 0xF0) << 4);
+        let height = descriptor[13] as u32 | ((descriptor[14] as u32 & 0x0F) << 8);
+        if width > 0 && height > 0 {
+            return Some(Extent::new(width as f64, height as f64));
+        }
+    }
+
+    /* The basic parameters only have it in centimeters. */
+    let (width, height) = (edid[21], edid[22]);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some(Extent::new(width as f64 * 10., height as f64 * 10.))
+}
+
+/// Returns the scale of the labels drawn on a display.
+///
+/// # Arguments
+///
+/// * `label_size` - Label size of the overlay theme
+/// * `physical_size` - Size of the display in millimeters, when it is known
+/// * `display_extent` - Size of the display in pixels
+/// * `display_scale` - Display scale the badges are already sized by
+///
+/// # Returns
+///
+/// The factor the badges are scaled by on top of the display scale,
+/// `LabelSize::Auto` falls back to the medium size when the physical size
+/// is unknown.
+pub fn label_scale(
+    label_size: LabelSize,
+    physical_size: Option<Extent>,
+    display_extent: Extent,
+    display_scale: f64,
+) -> f64 {
+    match label_size {
+        LabelSize::Small => SMALL_LABEL_SCALE,
+        LabelSize::Medium => MEDIUM_LABEL_SCALE,
+        LabelSize::Large => LARGE_LABEL_SCALE,
+        LabelSize::Auto => physical_size
+            .and_then(|size| auto_label_scale(size, display_extent, display_scale))
+            .unwrap_or(MEDIUM_LABEL_SCALE),
+    }
+}
+
+/// Scales the labels so they look as large from the estimated viewing
+/// distance as on a desk monitor.
+fn auto_label_scale(
+    physical_size: Extent,
+    display_extent: Extent,
+    display_scale: f64,
+) -> Option<f64> {
+    if display_extent.height <= 0. || display_scale <= 0. {
+        return None;
+    }
+    let diagonal = physical_size.width.hypot(physical_size.height);
+    let viewing_distance = if diagonal <= DESK_DISPLAY_DIAGONAL_MM {
+        DESK_VIEWING_DISTANCE_MM
+    } else {
+        diagonal * VIEWING_DISTANCE_PER_DIAGONAL
+    };
+
+    let pixel = physical_size.height / display_extent.height * display_scale;
+    let scale = REFERENCE_ANGULAR_PIXEL / (pixel / viewing_distance);
+    if !scale.is_finite() {
+        return None;
+    }
+    Some(scale.clamp(SMALL_LABEL_SCALE, MAX_AUTO_LABEL_SCALE))
+}
+
+/// Returns the physical size of the monitor in millimeters, `None` when the
+/// platform doesn't know it.
+#[cfg(target_os = "macos")]
+pub fn physical_size(monitor: &MonitorHandle) -> Option<Extent> {
+    use core_graphics::display::CGDisplay;
+    use winit::platform::macos::MonitorHandleExtMacOS;
+
+    /* Core Graphics reads the size from the EDID. */
+    let size = CGDisplay::new(monitor.native_id()).screen_size();
+    if size.width <= 0. || size.height <= 0. {
+        return None;
+    }
+    Some(Extent::new(size.width, size.height))
+}
+
+/// Returns the physical size of the monitor in millimeters, `None` when the
+/// platform doesn't know it.
+#[cfg(target_os = "windows")]
+pub fn physical_size(monitor: &MonitorHandle) -> Option<Extent> {
+    use windows::core::{w, HSTRING};
+    use windows::Win32::Graphics::Gdi::{CreateDCW, DeleteDC, GetDeviceCaps, HORZSIZE, VERTSIZE};
+    use winit::platform::windows::MonitorHandleExtWindows;
+
+    /* The display driver reports the size from the EDID. */
+    let device = HSTRING::from(monitor.native_id());
+    unsafe {
+        let hdc = CreateDCW(w!("DISPLAY"), &device, None, None);
+        if hdc.is_invalid() {
+            return None;
+        }
+        let width = GetDeviceCaps(hdc, HORZSIZE);
+        let height = GetDeviceCaps(hdc, VERTSIZE);
+        let _ = DeleteDC(hdc);
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+        Some(Extent::new(width as f64, height as f64))
+    }
+}
+
+/// Returns the physical size of the monitor in millimeters, `None` when the
+/// platform doesn't know it.
+///
+/// The EDIDs are read from the DRM connectors, the monitor is matched by its
+/// connector name or is the only connected display.
+#[cfg(target_os = "linux")]
+pub fn physical_size(monitor: &MonitorHandle) -> Option<Extent> {
+    let name = monitor.name().unwrap_or_default().replace('-', "");
+    let connectors = std::fs::read_dir("/sys/class/drm").ok()?;
+    let connected = connectors
+        .flatten()
+        .filter(|entry| {
+            std::fs::read_to_string(entry.path().join("status"))
+                .is_ok_and(|status| status.trim() == "connected")
+        })
+        .filter_map(|entry| {
+            let edid = std::fs::read(entry.path().join("edid")).ok()?;
+            let connector = entry.file_name().to_string_lossy().replace('-', "");
+            Some((connector, parse_edid_size(&edid)))
+        })
+        .collect::<Vec<_>>();
+
+    match connected.as_slice() {
+        [(_, size)] => *size,
+        _ => connected
+            .iter()
+            .find(|(connector, _)| !name.is_empty() && connector.ends_with(&name))
+            .and_then(|(_, size)| *size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edid(basic_cm: (u8, u8), descriptor_mm: Option<(u32, u32)>) -> Vec<u8> {
+        let mut edid = vec![0u8; 128];
+        edid[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+        edid[21] = basic_cm.0;
+        edid[22] = basic_cm.1;
+        if let Some((width, height)) = descriptor_mm {
+            /* Pixel clock, a zero one marks a display descriptor. */
+            edid[54] = 0x01;
+            edid[66] = (width & 0xFF) as u8;
+            edid[67] = (height & 0xFF) as u8;
+            edid[68] = (((width >> 8) & 0x0F) << 4) as u8 | ((height >> 8) & 0x0F) as u8;
+        }
+        edid
+    }
+
+    fn size_mm(edid: &[u8]) -> Option<(f64, f64)> {
+        parse_edid_size(edid).map(|size| (size.width, size.height))
+    }
+
+    #[test]
+    fn test_parse_edid_size() {
+        assert_eq!(
+            size_mm(&edid((121, 68), Some((1210, 680)))),
+            Some((1210., 680.))
+        );
+        assert_eq!(size_mm(&edid((60, 34), None)), Some((600., 340.)));
+        /* Projectors leave the size out. */
+        assert_eq!(size_mm(&edid((0, 0), None)), None);
+        assert_eq!(size_mm(&[0u8; 128]), None);
+        assert_eq!(size_mm(&edid((60, 34), None)[..64]), None);
+    }
+
+    #[test]
+    fn test_label_scale() {
+        let monitor = Extent::new(597., 336.);
+        let monitor_pixels = Extent::new(2560., 1440.);
+        assert_eq!(
+            label_scale(LabelSize::Large, Some(monitor), monitor_pixels, 1.),
+            LARGE_LABEL_SCALE
+        );
+
+        /* The desk monitor the badges were designed for. */
+        let scale = label_scale(LabelSize::Auto, Some(monitor), monitor_pixels, 1.);
+        assert!((scale - 1.).abs() < 0.01, "{scale}");
+
+        /* A 55" 4K TV across the room. */
+        let tv = Extent::new(1210., 680.);
+        let scale = label_scale(LabelSize::Auto, Some(tv), Extent::new(3840., 2160.), 1.);
+        assert!(scale > 2. && scale <= MAX_AUTO_LABEL_SCALE, "{scale}");
+
+        /* The same TV scaled by the OS needs less help. */
+        let scaled = label_scale(LabelSize::Auto, Some(tv), Extent::new(3840., 2160.), 2.);
+        assert!(scaled < scale, "{scaled}");
+
+        assert_eq!(
+            label_scale(LabelSize::Auto, None, monitor_pixels, 1.),
+            MEDIUM_LABEL_SCALE
+        );
+    }
+}
@@ -1,15 +1,68 @@
+//! Geometry types shared by the capture, overlay and input code.
+//!
+//! The overlay and the input simulation deal with three coordinate spaces:
+//! - physical: pixels in the global desktop space, this is what winit reports
+//! - logical: physical divided by the display's scale factor, macOS input
+//!   APIs use these (points)
+//! - normalized: fractions (0.0 to 1.0) of a frame, the controllers send
+//!   their cursor positions in this form
+//!
+//! Each space has its own point type and converting between them requires
+//! the scale factor or the frame explicitly, so they can't be mixed up.
+//!
+//! The socket protocol sends sizes in physical pixels and tags its positions
+//! with `socket_lib::Units`, they are converted to the types here as soon as
+//! they arrive.
+
 use core::fmt;
-use std::cmp::max;
+use std::cmp::{max, min};
 
 use serde::{Deserialize, Serialize};
+use socket_lib::{AspectPolicy, ResolutionPreset, Units, WindowFrameMessage};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize,
+)]
 pub struct Extent {
     pub width: f64,
     pub height: f64,
 }
 
+impl Extent {
+    pub fn new(width: f64, height: f64) -> Self {
+        Self { width, height }
+    }
+}
+
+impl Default for Extent {
+    fn default() -> Self {
+        Self::new(0., 0.)
+    }
+}
+
+impl From<PhysicalSize<u32>> for Extent {
+    fn from(size: PhysicalSize<u32>) -> Self {
+        Self::new(size.width as f64, size.height as f64)
+    }
+}
+
+impl From<socket_lib::Extent> for Extent {
+    fn from(extent: socket_lib::Extent) -> Self {
+        Self::new(extent.width, extent.height)
+    }
+}
+
+impl From<Extent> for socket_lib::Extent {
+    fn from(extent: Extent) -> Self {
+        Self {
+            width: extent.width,
+            height: extent.height,
+        }
+    }
+}
+
 impl fmt::Display for Extent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "width: {}, height: {}", self.width, self.height)
@@ -17,7 +70,7 @@ impl fmt::Display for Extent {
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Frame {
     pub origin_x: f64,
     pub origin_y: f64,
@@ -26,14 +79,7 @@ pub struct Frame {
 
 impl Default for Frame {
     fn default() -> Self {
-        Self {
-            origin_x: 0.,
-            origin_y: 0.,
-            extent: Extent {
-                width: 0.,
-                height: 0.,
-            },
-        }
+        Self::new(0., 0., Extent::default())
     }
 }
 
@@ -47,6 +93,10 @@ impl fmt::Display for Frame {
     }
 }
 
+/// Global cursor coordinates in the units the OS input APIs expect.
+///
+/// These are points on macOS and pixels on Windows and Linux, use
+/// `OverlayWindow` for converting them from and to the typed points.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Position {
@@ -60,6 +110,147 @@ impl Default for Position {
     }
 }
 
+/// A point in physical pixels.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PhysicalPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A point in logical points, physical pixels divided by the scale factor.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct LogicalPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A point as a fraction (0.0 to 1.0) of a frame's width and height.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct NormalizedPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl PhysicalPoint {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    /// Converts to logical points using the display's `scale_factor`.
+    pub fn to_logical(self, scale_factor: f64) -> LogicalPoint {
+        LogicalPoint {
+            x: self.x / scale_factor,
+            y: self.y / scale_factor,
+        }
+    }
+
+    /// Returns the point's position relative to `frame`, both in physical pixels.
+    ///
+    /// Points outside of the frame produce values outside of the 0.0 to 1.0
+    /// range. An empty frame maps every point to the origin.
+    pub fn normalize(self, frame: Frame) -> NormalizedPoint {
+        if frame.extent.width == 0.0 || frame.extent.height == 0.0 {
+            return NormalizedPoint::default();
+        }
+        NormalizedPoint {
+            x: (self.x - frame.origin_x) / frame.extent.width,
+            y: (self.y - frame.origin_y) / frame.extent.height,
+        }
+    }
+}
+
+impl PhysicalPoint {
+    /// Converts a position received over the socket to physical pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - The position in `units`
+    /// * `units` - The units the sender tagged the position with
+    /// * `scale_factor` - The scale factor of the display the position is on
+    pub fn from_protocol(x: f64, y: f64, units: Units, scale_factor: f64) -> Self {
+        Self::new(
+            units.to_physical(x, scale_factor),
+            units.to_physical(y, scale_factor),
+        )
+    }
+}
+
+impl From<PhysicalPosition<i32>> for PhysicalPoint {
+    fn from(position: PhysicalPosition<i32>) -> Self {
+        Self::new(position.x as f64, position.y as f64)
+    }
+}
+
+impl LogicalPoint {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    /// Converts to physical pixels using the display's `scale_factor`.
+    pub fn to_physical(self, scale_factor: f64) -> PhysicalPoint {
+        PhysicalPoint {
+            x: self.x * scale_factor,
+            y: self.y * scale_factor,
+        }
+    }
+}
+
+impl NormalizedPoint {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    /// Returns the physical position of the point inside `frame`.
+    pub fn to_physical(self, frame: Frame) -> PhysicalPoint {
+        PhysicalPoint {
+            x: self.x * frame.extent.width + frame.origin_x,
+            y: self.y * frame.extent.height + frame.origin_y,
+        }
+    }
+
+    /// Returns `true` if both coordinates are in the 0.0 to 1.0 range.
+    pub fn is_inside(&self) -> bool {
+        (0.0..=1.0).contains(&self.x) && (0.0..=1.0).contains(&self.y)
+    }
+}
+
+impl Frame {
+    pub fn new(origin_x: f64, origin_y: f64, extent: Extent) -> Self {
+        Self {
+            origin_x,
+            origin_y,
+            extent,
+        }
+    }
+
+    /// Converts a window frame received over the socket to physical pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The frame, in the units it is tagged with
+    /// * `scale_factor` - The scale factor of the display the window is on
+    pub fn from_protocol(message: &WindowFrameMessage, scale_factor: f64) -> Self {
+        let origin = PhysicalPoint::from_protocol(
+            message.origin_x,
+            message.origin_y,
+            message.units,
+            scale_factor,
+        );
+        Self::new(
+            origin.x,
+            origin.y,
+            Extent::new(
+                message.units.to_physical(message.width, scale_factor),
+                message.units.to_physical(message.height, scale_factor),
+            ),
+        )
+    }
+}
+
+/// Scales `width`x`height` so its longest side matches the longest side of the target.
+///
+/// The aspect ratio is preserved, the result is used as the size of
+/// thumbnails and stream buffers.
 pub fn aspect_fit(width: u32, height: u32, target_width: u32, target_height: u32) -> (u32, u32) {
     let size = max(target_width, target_height);
     if width >= height {
@@ -67,6 +258,496 @@ pub fn aspect_fit(width: u32, height: u32, target_width: u32, target_height: u32
         (size, ((size as f32) * aspect_ratio) as u32)
     } else {
         let aspect_ratio = width as f32 / height as f32;
-        (((size as f32) / aspect_ratio) as u32, size)
+        (((size as f32) * aspect_ratio) as u32, size)
+    }
+}
+
+/// Part of a captured frame, in the frame's pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropRect {
+    /// The whole `width`x`height` frame.
+    pub fn full(width: u32, height: u32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the crop in normalized coordinates of the `width`x`height` frame.
+    pub fn normalized(&self, width: u32, height: u32) -> StreamCrop {
+        if width == 0 || height == 0 {
+            return StreamCrop::default();
+        }
+        StreamCrop {
+            origin: NormalizedPoint::new(
+                self.x as f64 / width as f64,
+                self.y as f64 / height as f64,
+            ),
+            width: self.width as f64 / width as f64,
+            height: self.height as f64 / height as f64,
+        }
+    }
+}
+
+/// The part of the captured frame that is streamed, in normalized coordinates.
+///
+/// The controllers' positions are relative to the streamed frames, when the
+/// stream is cropped they cover only this part of the captured frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamCrop {
+    /// The crop's top left corner in the captured frame
+    pub origin: NormalizedPoint,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Default for StreamCrop {
+    fn default() -> Self {
+        Self {
+            origin: NormalizedPoint::default(),
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+impl StreamCrop {
+    /// Converts a position in the streamed frame to a position in the captured frame.
+    pub fn to_frame(&self, point: NormalizedPoint) -> NormalizedPoint {
+        NormalizedPoint::new(
+            self.origin.x + point.x * self.width,
+            self.origin.y + point.y * self.height,
+        )
+    }
+
+    /// Converts a position in the captured frame to a position in the streamed frame.
+    pub fn to_stream(&self, point: NormalizedPoint) -> NormalizedPoint {
+        if self.width == 0.0 || self.height == 0.0 {
+            return point;
+        }
+        NormalizedPoint::new(
+            (point.x - self.origin.x) / self.width,
+            (point.y - self.origin.y) / self.height,
+        )
+    }
+}
+
+/// Size of the streamed frames and the part of the captured frame they show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamLayout {
+    pub width: u32,
+    pub height: u32,
+    pub crop: CropRect,
+}
+
+/// Returns how a `width`x`height` frame is streamed at the target resolution.
+///
+/// # Arguments
+///
+/// * `policy` - How the frame is scaled, see `AspectPolicy`
+/// * `width`, `height` - The captured frame's size
+/// * `target_width`, `target_height` - The requested stream resolution, turned
+///   to the frame's orientation
+///
+/// # Returns
+///
+/// The stream's size and the part of the frame it shows.
+pub fn stream_layout(
+    policy: AspectPolicy,
+    width: u32,
+    height: u32,
+    target_width: u32,
+    target_height: u32,
+) -> StreamLayout {
+    let full = CropRect::full(width, height);
+    let fit = |crop| {
+        let (width, height) = aspect_fit(width, height, target_width, target_height);
+        StreamLayout {
+            width,
+            height,
+            crop,
+        }
+    };
+    if width == 0 || height == 0 || target_width == 0 || target_height == 0 {
+        return fit(full);
+    }
+
+    let (target_width, target_height) = if (width >= height) == (target_width >= target_height) {
+        (target_width, target_height)
+    } else {
+        (target_height, target_width)
+    };
+    match policy {
+        AspectPolicy::Fit => fit(full),
+        AspectPolicy::Native => {
+            if max(width, height) <= max(target_width, target_height) {
+                StreamLayout {
+                    width,
+                    height,
+                    crop: full,
+                }
+            } else {
+                fit(full)
+            }
+        }
+        AspectPolicy::Stretch => StreamLayout {
+            width: target_width,
+            height: target_height,
+            crop: full,
+        },
+        AspectPolicy::Fill => {
+            /* Cross multiplied, a wider frame loses its sides, a taller one its top and bottom. */
+            let crop = if width as u64 * target_height as u64 > height as u64 * target_width as u64
+            {
+                let crop_width =
+                    (height as u64 * target_width as u64 / target_height as u64) as u32;
+                CropRect {
+                    x: (width - crop_width) / 2,
+                    y: 0,
+                    width: crop_width,
+                    height,
+                }
+            } else {
+                let crop_height =
+                    (width as u64 * target_height as u64 / target_width as u64) as u32;
+                CropRect {
+                    x: 0,
+                    y: (height - crop_height) / 2,
+                    width,
+                    height: crop_height,
+                }
+            };
+            StreamLayout {
+                width: target_width,
+                height: target_height,
+                crop,
+            }
+        }
+    }
+}
+
+/// Heights, or widths of portrait displays, of the suggested stream resolutions.
+const PRESET_SHORT_SIDES: [u32; 4] = [720, 1080, 1440, 2160];
+/// The long sides of the presets are rounded down to a multiple of this,
+/// which lands on the usual 16:9 and 21:9 resolutions.
+const PRESET_LONG_SIDE_ALIGNMENT: u32 = 64;
+
+/// Returns the stream resolutions suggested for a `width`x`height` display.
+///
+/// # Arguments
+///
+/// * `width`, `height` - The display's size in pixels
+///
+/// # Returns
+///
+/// The presets below the display's resolution with its aspect ratio,
+/// like 2560x1080 for a 3440x1440 display, followed by the display's own
+/// resolution. Portrait displays get portrait presets, a display of unknown
+/// size gets none.
+pub fn resolution_presets(width: u32, height: u32) -> Vec<ResolutionPreset> {
+    if width == 0 || height == 0 {
+        return vec![];
+    }
+    let (long, short) = (max(width, height), min(width, height));
+    let preset = |label: String, long: u32, short: u32| {
+        let (width, height) = if width >= height {
+            (long, short)
+        } else {
+            (short, long)
+        };
+        ResolutionPreset {
+            label,
+            resolution: socket_lib::Extent {
+                width: width as f64,
+                height: height as f64,
+            },
+        }
+    };
+
+    let mut presets: Vec<ResolutionPreset> = PRESET_SHORT_SIDES
+        .iter()
+        .filter(|&&side| side < short)
+        .map(|&side| {
+            let scaled = (long as u64 * side as u64 / short as u64) as u32;
+            let aligned = scaled / PRESET_LONG_SIDE_ALIGNMENT * PRESET_LONG_SIDE_ALIGNMENT;
+            preset(format!("{side}p"), aligned.max(side), side)
+        })
+        .collect();
+    presets.push(preset("Native".to_string(), long, short));
+    presets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(origin_x: f64, origin_y: f64, width: f64, height: f64) -> Frame {
+        Frame::new(origin_x, origin_y, Extent { width, height })
+    }
+
+    #[test]
+    fn test_physical_logical_round_trip() {
+        let physical = PhysicalPoint::new(300.0, 150.0);
+        assert_eq!(physical.to_logical(2.0), LogicalPoint::new(150.0, 75.0));
+        assert_eq!(physical.to_logical(1.0), LogicalPoint::new(300.0, 150.0));
+        assert_eq!(physical.to_logical(1.5).to_physical(1.5), physical);
+        assert_eq!(
+            LogicalPoint::new(-10.0, 20.0).to_physical(2.0),
+            PhysicalPoint::new(-20.0, 40.0)
+        );
+    }
+
+    #[test]
+    fn test_normalize_and_back() {
+        let display = frame(1920.0, -200.0, 2560.0, 1440.0);
+        let physical = PhysicalPoint::new(1920.0 + 640.0, -200.0 + 1080.0);
+        let normalized = physical.normalize(display);
+        assert_eq!(normalized, NormalizedPoint::new(0.25, 0.75));
+        assert!(normalized.is_inside());
+        assert_eq!(normalized.to_physical(display), physical);
+
+        assert_eq!(
+            NormalizedPoint::new(0.0, 0.0).to_physical(display),
+            PhysicalPoint::new(1920.0, -200.0)
+        );
+        assert_eq!(
+            NormalizedPoint::new(1.0, 1.0).to_physical(display),
+            PhysicalPoint::new(4480.0, 1240.0)
+        );
+    }
+
+    #[test]
+    fn test_normalize_outside_and_empty_frame() {
+        let display = frame(0.0, 0.0, 100.0, 100.0);
+        let outside = PhysicalPoint::new(-50.0, 150.0).normalize(display);
+        assert_eq!(outside, NormalizedPoint::new(-0.5, 1.5));
+        assert!(!outside.is_inside());
+
+        let empty = frame(10.0, 10.0, 0.0, 0.0);
+        assert_eq!(
+            PhysicalPoint::new(20.0, 20.0).normalize(empty),
+            NormalizedPoint::default()
+        );
+    }
+
+    const SCALE_FACTORS: [f64; 3] = [1.0, 1.25, 2.0];
+
+    #[test]
+    fn test_protocol_positions() {
+        for scale in SCALE_FACTORS {
+            assert_eq!(
+                PhysicalPoint::from_protocol(800.0, 600.0, Units::Physical, scale),
+                PhysicalPoint::new(800.0, 600.0)
+            );
+            let logical = PhysicalPoint::from_protocol(800.0, 600.0, Units::Logical, scale);
+            assert_eq!(logical, LogicalPoint::new(800.0, 600.0).to_physical(scale));
+            /* A click in logical points lands on the same normalized spot as in pixels. */
+            let display = frame(0.0, 0.0, 1600.0 * scale, 1200.0 * scale);
+            assert_eq!(logical.normalize(display), NormalizedPoint::new(0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn test_protocol_window_frame() {
+        for scale in SCALE_FACTORS {
+            let message = |units| WindowFrameMessage {
+                origin_x: 100.0,
+                origin_y: -40.0,
+                width: 640.0,
+                height: 480.0,
+                units,
+            };
+            let physical = Frame::from_protocol(&message(Units::Physical), scale);
+            assert_eq!((physical.origin_x, physical.origin_y), (100.0, -40.0));
+            assert_eq!(
+                (physical.extent.width, physical.extent.height),
+                (640.0, 480.0)
+            );
+
+            let logical = Frame::from_protocol(&message(Units::Logical), scale);
+            assert_eq!(
+                (logical.origin_x, logical.origin_y),
+                (100.0 * scale, -40.0 * scale)
+            );
+            assert_eq!(
+                (logical.extent.width, logical.extent.height),
+                (640.0 * scale, 480.0 * scale)
+            );
+        }
+        /* Messages of senders that don't tag their units are physical. */
+        let untagged: WindowFrameMessage =
+            serde_json::from_str(r#"{"origin_x":0.0,"origin_y":0.0,"width":10.0,"height":10.0}"#)
+                .unwrap();
+        assert_eq!(untagged.units, Units::Physical);
+    }
+
+    #[test]
+    fn test_protocol_extent() {
+        for scale in SCALE_FACTORS {
+            let extent = socket_lib::Extent::from_logical(1280.0, 720.0, scale);
+            let extent = Extent::from(extent);
+            assert_eq!(
+                (extent.width, extent.height),
+                (1280.0 * scale, 720.0 * scale)
+            );
+            let back = socket_lib::Extent::from(extent);
+            assert_eq!((back.width, back.height), (extent.width, extent.height));
+        }
+    }
+
+    #[test]
+    fn test_winit_conversions() {
+        let extent = Extent::from(PhysicalSize::new(2560u32, 1440u32));
+        assert_eq!((extent.width, extent.height), (2560.0, 1440.0));
+        assert_eq!(
+            PhysicalPoint::from(PhysicalPosition::new(-1920, 0)),
+            PhysicalPoint::new(-1920.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_aspect_fit() {
+        assert_eq!(aspect_fit(1920, 1080, 480, 360), (480, 270));
+        assert_eq!(aspect_fit(1080, 1920, 480, 360), (270, 480));
+        assert_eq!(aspect_fit(1000, 1000, 480, 360), (480, 480));
+    }
+
+    #[test]
+    fn test_stream_layout() {
+        let ultra_wide = (5120, 1440);
+        let layout = |policy| stream_layout(policy, ultra_wide.0, ultra_wide.1, 1920, 1080);
+
+        let fit = layout(AspectPolicy::Fit);
+        assert_eq!((fit.width, fit.height), (1920, 540));
+        assert_eq!(fit.crop, CropRect::full(5120, 1440));
+
+        let fill = layout(AspectPolicy::Fill);
+        assert_eq!((fill.width, fill.height), (1920, 1080));
+        assert_eq!(
+            fill.crop,
+            CropRect {
+                x: 1280,
+                y: 0,
+                width: 2560,
+                height: 1440
+            }
+        );
+
+        let stretch = layout(AspectPolicy::Stretch);
+        assert_eq!((stretch.width, stretch.height), (1920, 1080));
+        assert_eq!(stretch.crop, CropRect::full(5120, 1440));
+
+        /* A small display isn't upscaled. */
+        let native = stream_layout(AspectPolicy::Native, 1280, 800, 1920, 1080);
+        assert_eq!((native.width, native.height), (1280, 800));
+        assert_eq!(
+            stream_layout(AspectPolicy::Native, 5120, 1440, 1920, 1080),
+            fit
+        );
+    }
+
+    #[test]
+    fn test_stream_layout_follows_orientation() {
+        /* A portrait display is cropped to 9:16, not to a landscape band. */
+        let fill = stream_layout(AspectPolicy::Fill, 1080, 2560, 1920, 1080);
+        assert_eq!((fill.width, fill.height), (1080, 1920));
+        assert_eq!(
+            fill.crop,
+            CropRect {
+                x: 0,
+                y: 320,
+                width: 1080,
+                height: 1920
+            }
+        );
+        assert_eq!(
+            stream_layout(AspectPolicy::Fill, 0, 0, 1920, 1080).crop,
+            CropRect::full(0, 0)
+        );
+    }
+
+    #[test]
+    fn test_stream_crop() {
+        let crop = CropRect {
+            x: 1280,
+            y: 0,
+            width: 2560,
+            height: 1440,
+        }
+        .normalized(5120, 1440);
+        assert_eq!(
+            crop.to_frame(NormalizedPoint::new(0.0, 0.5)),
+            NormalizedPoint::new(0.25, 0.5)
+        );
+        assert_eq!(
+            crop.to_frame(NormalizedPoint::new(1.0, 1.0)),
+            NormalizedPoint::new(0.75, 1.0)
+        );
+        assert_eq!(
+            crop.to_stream(NormalizedPoint::new(0.5, 0.5)),
+            NormalizedPoint::new(0.5, 0.5)
+        );
+        /* The sharer's pointer outside of the crop. */
+        assert!(!crop.to_stream(NormalizedPoint::new(0.1, 0.5)).is_inside());
+
+        let full = StreamCrop::default();
+        let point = NormalizedPoint::new(0.3, 0.7);
+        assert_eq!(full.to_frame(point), point);
+        assert_eq!(full.to_stream(point), point);
+    }
+
+    fn preset_sizes(width: u32, height: u32) -> Vec<(String, f64, f64)> {
+        resolution_presets(width, height)
+            .into_iter()
+            .map(|preset| {
+                (
+                    preset.label,
+                    preset.resolution.width,
+                    preset.resolution.height,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolution_presets() {
+        let preset = |label: &str, width: f64, height: f64| (label.to_string(), width, height);
+        assert_eq!(
+            preset_sizes(3440, 1440),
+            vec![
+                preset("720p", 1664., 720.),
+                preset("1080p", 2560., 1080.),
+                preset("Native", 3440., 1440.),
+            ]
+        );
+        assert_eq!(
+            preset_sizes(3840, 2160),
+            vec![
+                preset("720p", 1280., 720.),
+                preset("1080p", 1920., 1080.),
+                preset("1440p", 2560., 1440.),
+                preset("Native", 3840., 2160.),
+            ]
+        );
+        /* A rotated display keeps its portrait orientation. */
+        assert_eq!(
+            preset_sizes(1440, 2560),
+            vec![
+                preset("720p", 720., 1280.),
+                preset("1080p", 1080., 1920.),
+                preset("Native", 1440., 2560.),
+            ]
+        );
+        assert_eq!(preset_sizes(1024, 640), vec![preset("Native", 1024., 640.)]);
+        assert!(resolution_presets(0, 0).is_empty());
     }
 }
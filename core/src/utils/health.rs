@@ -0,0 +1,225 @@
+//! Periodic health pings of the core process.
+//!
+//! A core sidecar that crashes silently on a user's machine is invisible to
+//! the operators of a self-hosted deployment. When the user opted in to
+//! telemetry and an interval is configured, the core periodically sends a
+//! heartbeat with anonymous counters to Sentry or to a health URL, a missing
+//! heartbeat means the process is gone.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Heartbeats aren't sent more often than this, whatever the configuration.
+pub const MIN_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+const HEALTH_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const HEARTBEAT_MESSAGE: &str = "Core heartbeat";
+
+/* Number of screen share sessions started by the process. */
+static SESSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts a screen share session that started, reported with the heartbeats.
+pub fn record_session() {
+    SESSIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Error, Debug)]
+pub enum HealthError {
+    #[error("Failed to create TLS connector: {0}")]
+    Tls(#[from] native_tls::Error),
+    #[error("Failed to serialize heartbeat: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Failed to send heartbeat: {0}")]
+    Request(#[from] Box<ureq::Error>),
+}
+
+/// Where the heartbeats are sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeartbeatTarget {
+    Sentry,
+    /// The heartbeats are posted as JSON to the URL
+    Url(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub target: HeartbeatTarget,
+}
+
+impl HeartbeatConfig {
+    /// Builds the heartbeat configuration from the process arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval_secs` - Seconds between heartbeats, `None` when the user didn't opt in
+    /// * `health_url` - URL the heartbeats are posted to, Sentry is used when `None`
+    /// * `sentry_enabled` - Whether Sentry was initialized
+    ///
+    /// # Returns
+    ///
+    /// `None` when heartbeats are disabled or there is nowhere to send them.
+    pub fn new(
+        interval_secs: Option<u64>,
+        health_url: Option<String>,
+        sentry_enabled: bool,
+    ) -> Option<Self> {
+        let interval = Duration::from_secs(interval_secs?).max(MIN_HEARTBEAT_INTERVAL);
+        let target = match health_url.filter(|url| !url.trim().is_empty()) {
+            Some(url) => HeartbeatTarget::Url(url),
+            None if sentry_enabled => HeartbeatTarget::Sentry,
+            None => {
+                log::warn!("HeartbeatConfig::new: no health url and sentry is disabled");
+                return None;
+            }
+        };
+        Some(Self { interval, target })
+    }
+}
+
+/// Anonymous counters reported by a heartbeat.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// Heartbeats sent before this one by the process, it starts over when
+    /// the process restarts
+    pub sequence: u64,
+    pub uptime_secs: u64,
+    pub sessions: u64,
+    pub failures: u64,
+    pub version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+}
+
+impl Heartbeat {
+    /// Builds the heartbeat from the counters of the process.
+    fn collect(sequence: u64, uptime: Duration) -> Self {
+        Self {
+            sequence,
+            uptime_secs: uptime.as_secs(),
+            sessions: SESSIONS.load(Ordering::Relaxed),
+            failures: sentry_utils::failure_count(),
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        }
+    }
+
+    fn tags(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("sequence", self.sequence.to_string()),
+            ("uptime_secs", self.uptime_secs.to_string()),
+            ("sessions", self.sessions.to_string()),
+            ("failures", self.failures.to_string()),
+            ("version", self.version.to_string()),
+            ("os", self.os.to_string()),
+            ("arch", self.arch.to_string()),
+        ]
+    }
+}
+
+fn post_heartbeat(url: &str, heartbeat: &Heartbeat) -> Result<(), HealthError> {
+    let connector = native_tls::TlsConnector::new()?;
+    let agent = ureq::AgentBuilder::new()
+        .tls_connector(Arc::new(connector))
+        .timeout(HEALTH_REQUEST_TIMEOUT)
+        .build();
+    let body = serde_json::to_string(heartbeat)?;
+    agent
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map_err(Box::new)?;
+    Ok(())
+}
+
+fn send_heartbeat(target: &HeartbeatTarget, heartbeat: &Heartbeat) -> Result<(), HealthError> {
+    match target {
+        HeartbeatTarget::Sentry => {
+            sentry_utils::capture_heartbeat(HEARTBEAT_MESSAGE, &heartbeat.tags());
+            Ok(())
+        }
+        HeartbeatTarget::Url(url) => post_heartbeat(url, heartbeat),
+    }
+}
+
+/// Sends the heartbeats from a background thread for the lifetime of the process.
+///
+/// The first heartbeat is sent after one interval, so processes that crash
+/// at startup are noticed by their missing heartbeat.
+///
+/// # Arguments
+///
+/// * `config` - When and where the heartbeats are sent
+pub fn start_heartbeats(config: HeartbeatConfig) -> std::io::Result<JoinHandle<()>> {
+    log::info!("start_heartbeats: {config:?}");
+    let started = Instant::now();
+    std::thread::Builder::new()
+        .name("heartbeat".to_string())
+        .spawn(move || {
+            for sequence in 0.. {
+                std::thread::sleep(config.interval);
+                let heartbeat = Heartbeat::collect(sequence, started.elapsed());
+                log::debug!("start_heartbeats: {heartbeat:?}");
+                /* A failed heartbeat is only logged, the next one may get through. */
+                if let Err(e) = send_heartbeat(&config.target, &heartbeat) {
+                    log::warn!("start_heartbeats: {e}");
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_config() {
+        assert_eq!(HeartbeatConfig::new(None, None, true), None);
+        assert_eq!(
+            HeartbeatConfig::new(Some(600), None, true),
+            Some(HeartbeatConfig {
+                interval: Duration::from_secs(600),
+                target: HeartbeatTarget::Sentry,
+            })
+        );
+        assert_eq!(HeartbeatConfig::new(Some(600), None, false), None);
+        assert_eq!(
+            HeartbeatConfig::new(Some(600), Some(" ".to_string()), false),
+            None
+        );
+
+        let config = HeartbeatConfig::new(
+            Some(1),
+            Some("https://health.example.com/ping".to_string()),
+            false,
+        )
+        .unwrap();
+        assert_eq!(config.interval, MIN_HEARTBEAT_INTERVAL);
+        assert_eq!(
+            config.target,
+            HeartbeatTarget::Url("https://health.example.com/ping".to_string())
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_counters() {
+        let before = Heartbeat::collect(0, Duration::ZERO);
+        record_session();
+        let heartbeat = Heartbeat::collect(4, Duration::from_millis(90_500));
+        assert_eq!(heartbeat.sequence, 4);
+        assert_eq!(heartbeat.uptime_secs, 90);
+        assert!(heartbeat.sessions > before.sessions);
+
+        let tags = heartbeat.tags();
+        assert!(tags.contains(&("uptime_secs", "90".to_string())));
+        assert!(tags.contains(&("os", std::env::consts::OS.to_string())));
+
+        let json = serde_json::to_value(&heartbeat).unwrap();
+        assert_eq!(json["sequence"], 4);
+        assert_eq!(json["uptime_secs"], 90);
+    }
+}
@@ -0,0 +1,222 @@
+//! Last known LiveKit server URL.
+//!
+//! The tauri app sends the URL once after it fetched it, a core process that
+//! was restarted while the tauri app kept running never gets it again and has
+//! no room service to share with. The core caches the URL on disk and asks
+//! the tauri app for it with `Message::NeedLivekitUrl` on startup. Until the
+//! answer arrives the cached URL is used, once its host resolves and the
+//! server answers.
+
+use std::io;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::event_sender::EventSender;
+use crate::UserEvent;
+
+const CACHE_DIR: &str = "com.hopp.app";
+const CACHE_FILE: &str = "core_livekit_url";
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum LivekitUrlError {
+    #[error("Invalid LiveKit server URL: {0}")]
+    InvalidUrl(String),
+    #[error("Failed to resolve {0}: {1}")]
+    Dns(String, io::Error),
+    #[error("Failed to create TLS connector: {0}")]
+    Tls(#[from] native_tls::Error),
+    #[error("LiveKit server unreachable: {0}")]
+    Unreachable(String),
+}
+
+/// Where the URL is cached, `None` when the platform has no data directory.
+pub fn cache_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|path| path.join(CACHE_DIR).join(CACHE_FILE))
+}
+
+/// Reads the cached URL, `None` when nothing usable is cached.
+pub fn load(path: &Path) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(url) => {
+            let url = url.trim();
+            (!url.is_empty()).then(|| url.to_string())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            log::error!("livekit_url::load: failed to read {path:?}: {e:?}");
+            None
+        }
+    }
+}
+
+/// Caches `url`, creating the directory if needed.
+pub fn store(path: &Path, url: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, url)
+}
+
+/// Address and HTTP URL the health check of a LiveKit server URL uses.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HealthCheckTarget {
+    pub host: String,
+    pub port: u16,
+    pub http_url: String,
+}
+
+/// Splits a `ws`, `wss`, `http` or `https` URL into the server's address and
+/// the HTTP URL of its root, which LiveKit answers without a token.
+pub fn health_check_target(url: &str) -> Result<HealthCheckTarget, LivekitUrlError> {
+    let invalid = || LivekitUrlError::InvalidUrl(url.to_string());
+    let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+    let (http_scheme, default_port) = match scheme.to_ascii_lowercase().as_str() {
+        "ws" | "http" => ("http", 80),
+        "wss" | "https" => ("https", 443),
+        _ => return Err(invalid()),
+    };
+
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    /* IPv6 hosts are bracketed, the port follows the closing bracket. */
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => {
+            (host, port.parse::<u16>().map_err(|_| invalid())?)
+        }
+        _ => (authority, default_port),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(HealthCheckTarget {
+        host: host.to_string(),
+        port,
+        http_url: format!("{http_scheme}://{authority}/"),
+    })
+}
+
+/// Checks that the host of `url` resolves and the server answers.
+///
+/// Any HTTP response counts, the root of a LiveKit server may answer with an
+/// error status depending on the deployment.
+pub fn validate(url: &str) -> Result<(), LivekitUrlError> {
+    let target = health_check_target(url)?;
+    let addresses = (target.host.as_str(), target.port)
+        .to_socket_addrs()
+        .map_err(|e| LivekitUrlError::Dns(target.host.clone(), e))?;
+    if addresses.count() == 0 {
+        return Err(LivekitUrlError::Dns(
+            target.host,
+            io::Error::new(io::ErrorKind::NotFound, "no addresses"),
+        ));
+    }
+
+    let connector = native_tls::TlsConnector::new()?;
+    let agent = ureq::AgentBuilder::new()
+        .tls_connector(Arc::new(connector))
+        .timeout(HEALTH_CHECK_TIMEOUT)
+        .build();
+    match agent.get(&target.http_url).call() {
+        Ok(_) | Err(ureq::Error::Status(..)) => Ok(()),
+        Err(e) => Err(LivekitUrlError::Unreachable(e.to_string())),
+    }
+}
+
+/// Validates the cached URL in a background thread and sends it as
+/// `UserEvent::CachedLivekitUrl` when it is usable.
+///
+/// # Arguments
+///
+/// * `event_sender` - Where the URL is sent
+/// * `path` - The cache file
+pub fn revalidate_cached(event_sender: Box<dyn EventSender>, path: PathBuf) {
+    std::thread::spawn(move || {
+        let Some(url) = load(&path) else {
+            log::info!("revalidate_cached: no cached LiveKit server URL");
+            return;
+        };
+        if let Err(e) = validate(&url) {
+            log::warn!("revalidate_cached: cached URL {url} isn't usable: {e}");
+            return;
+        }
+        log::info!("revalidate_cached: cached URL {url} is reachable");
+        if let Err(e) = event_sender.send(UserEvent::CachedLivekitUrl(url)) {
+            log::error!("revalidate_cached: error sending url: {e:?}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_check_target() {
+        assert_eq!(
+            health_check_target("wss://hopp.livekit.cloud").unwrap(),
+            HealthCheckTarget {
+                host: "hopp.livekit.cloud".to_string(),
+                port: 443,
+                http_url: "https://hopp.livekit.cloud/".to_string(),
+            }
+        );
+        assert_eq!(
+            health_check_target("ws://localhost:7880/rtc?access_token=x").unwrap(),
+            HealthCheckTarget {
+                host: "localhost".to_string(),
+                port: 7880,
+                http_url: "http://localhost:7880/".to_string(),
+            }
+        );
+        assert_eq!(
+            health_check_target("ws://[::1]:7880").unwrap(),
+            HealthCheckTarget {
+                host: "::1".to_string(),
+                port: 7880,
+                http_url: "http://[::1]:7880/".to_string(),
+            }
+        );
+        assert_eq!(health_check_target("WSS://[::1]").unwrap().port, 443);
+    }
+
+    #[test]
+    fn test_invalid_urls() {
+        for url in [
+            "",
+            "hopp.livekit.cloud",
+            "ftp://hopp.livekit.cloud",
+            "wss://",
+            "wss://:443",
+            "ws://localhost:port",
+        ] {
+            assert!(
+                matches!(
+                    health_check_target(url),
+                    Err(LivekitUrlError::InvalidUrl(_))
+                ),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("hopp-livekit-url-{}", std::process::id()))
+            .join(CACHE_FILE);
+        assert_eq!(load(&path), None);
+
+        store(&path, "wss://hopp.livekit.cloud").unwrap();
+        assert_eq!(load(&path).as_deref(), Some("wss://hopp.livekit.cloud"));
+
+        store(&path, "  \n").unwrap();
+        assert_eq!(load(&path), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+//! Scratch space of the active session.
+//!
+//! Each session gets a directory of its own under the user's directory, see
+//! `socket_lib::user_dir`, so the directory is the only thing to remove when
+//! the session ends and no other user can read or replace what is in it.
+//!
+//! The directory is named after the core process, the directories of
+//! processes that crashed or were killed are removed when the next core
+//! process starts. The directories of a core process that is still running,
+//! e.g. one started with another instance name, are left alone.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+const SESSION_DIRS_ROOT: &str = "sessions";
+
+/// Where the session directories are created, `None` when the user's
+/// directory can't be used.
+///
+/// A core process started next to the default one passes its `instance`
+/// name, so each process only looks at its own directories.
+pub fn default_root(instance: Option<&str>) -> Option<PathBuf> {
+    let dir = match socket_lib::user_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            log::error!("default_root: can't use the user's directory: {e:?}");
+            return None;
+        }
+    };
+    match instance {
+        Some(instance) => Some(dir.join(format!("{SESSION_DIRS_ROOT}-{instance}"))),
+        None => Some(dir.join(SESSION_DIRS_ROOT)),
+    }
+}
+
+/// Returns the pid of the core process that created the session directory `name`.
+fn owner_pid(name: &str) -> Option<u32> {
+    let (pid, session) = name.split_once('-')?;
+    session.parse::<u32>().ok()?;
+    pid.parse().ok()
+}
+
+/// Creates and removes the directory of the active session.
+#[derive(Debug)]
+pub struct SessionDirs {
+    /// Parent of the session directories, without it no directory is created
+    root: Option<PathBuf>,
+    /// Sessions started by this process, numbers the directories
+    sessions: u32,
+    /// The directory of the active session, `None` between sessions
+    active: Option<PathBuf>,
+}
+
+impl SessionDirs {
+    /// Creates the manager and removes the directories dead core processes left under `root`.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - Parent of the session directories, see `default_root`
+    pub fn new(root: Option<PathBuf>) -> Self {
+        let session_dirs = Self {
+            root,
+            sessions: 0,
+            active: None,
+        };
+        session_dirs.remove_stale();
+        session_dirs
+    }
+
+    /// Removes the session directories of processes that aren't running anymore.
+    ///
+    /// Only the directories named like the ones `start_session` creates are
+    /// considered, anything else under the root is left alone.
+    fn remove_stale(&self) {
+        let Some(root) = &self.root else {
+            return;
+        };
+        let Ok(entries) = std::fs::read_dir(root) else {
+            return;
+        };
+        let own_pid = std::process::id();
+        for entry in entries.flatten() {
+            let Some(pid) = owner_pid(&entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+            if pid == own_pid || socket_lib::process_alive(pid) {
+                continue;
+            }
+            let path = entry.path();
+            log::info!("SessionDirs::remove_stale: removing {path:?} of process {pid}");
+            if let Err(e) = std::fs::remove_dir_all(&path) {
+                log::error!("SessionDirs::remove_stale: failed to remove {path:?}: {e:?}");
+            }
+        }
+    }
+
+    /// Creates the directory of a new session, the previous session's is removed.
+    pub fn start_session(&mut self) {
+        self.end_session();
+        let Some(root) = &self.root else {
+            log::warn!("SessionDirs::start_session: no root");
+            return;
+        };
+        self.sessions += 1;
+        let dir = root.join(format!("{}-{}", std::process::id(), self.sessions));
+        match std::fs::create_dir_all(&dir) {
+            Ok(()) => {
+                log::info!("SessionDirs::start_session: {dir:?}");
+                self.active = Some(dir);
+            }
+            Err(e) => log::error!("SessionDirs::start_session: failed to create {dir:?}: {e:?}"),
+        }
+    }
+
+    /// Removes the active session's directory and everything in it.
+    pub fn end_session(&mut self) {
+        let Some(dir) = self.active.take() else {
+            return;
+        };
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => log::info!("SessionDirs::end_session: removed {dir:?}"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => log::error!("SessionDirs::end_session: failed to remove {dir:?}: {e:?}"),
+        }
+    }
+
+    /// Returns the directory of the active session, `None` between sessions.
+    pub fn dir(&self) -> Option<&Path> {
+        self.active.as_deref()
+    }
+}
+
+impl Drop for SessionDirs {
+    fn drop(&mut self) {
+        self.end_session();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_root(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("hopp_session_dirs_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        let root = test_root("lifecycle");
+        let mut session_dirs = SessionDirs::new(Some(root.clone()));
+        assert!(session_dirs.dir().is_none());
+
+        session_dirs.start_session();
+        let first = session_dirs.dir().unwrap().to_path_buf();
+        assert!(first.is_dir());
+        std::fs::write(first.join("a.png"), [0u8; 16]).unwrap();
+
+        /* A new session doesn't see the files of the previous one. */
+        session_dirs.start_session();
+        let second = session_dirs.dir().unwrap().to_path_buf();
+        assert_ne!(first, second);
+        assert!(!first.exists());
+
+        session_dirs.end_session();
+        assert!(!second.exists());
+        assert!(session_dirs.dir().is_none());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_without_root_nothing_is_created() {
+        let mut session_dirs = SessionDirs::new(None);
+        session_dirs.start_session();
+        assert!(session_dirs.dir().is_none());
+    }
+
+    #[test]
+    fn test_only_dirs_of_dead_processes_are_removed() {
+        let root = test_root("stale");
+        /* Pids are far below this on every supported system. */
+        let dead = root.join(format!("{}-1", i32::MAX as u32 - 1));
+        std::fs::create_dir_all(&dead).unwrap();
+        let own = root.join(format!("{}-7", std::process::id()));
+        std::fs::create_dir_all(&own).unwrap();
+        #[cfg(unix)]
+        let alive = root.join(format!("{}-1", std::os::unix::process::parent_id()));
+        #[cfg(unix)]
+        std::fs::create_dir_all(&alive).unwrap();
+        let unrelated = root.join("notes");
+        std::fs::create_dir_all(&unrelated).unwrap();
+
+        let _session_dirs = SessionDirs::new(Some(root.clone()));
+        assert!(!dead.exists());
+        assert!(own.exists());
+        #[cfg(unix)]
+        assert!(alive.exists());
+        assert!(unrelated.exists());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_owner_pid() {
+        assert_eq!(owner_pid("1234-2"), Some(1234));
+        assert_eq!(owner_pid("1234"), None);
+        assert_eq!(owner_pid("1234-notes"), None);
+        assert_eq!(owner_pid("notes-1"), None);
+    }
+}
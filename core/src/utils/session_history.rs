@@ -0,0 +1,152 @@
+//! # Session History
+//!
+//! Keeps a short summary of the recent screen share sessions, they are
+//! attached to the support bundles exported by the tauri app.
+//!
+
+use std::collections::VecDeque;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use socket_lib::{Content, SessionSummary};
+
+/// Number of finished sessions kept, older ones are dropped.
+pub const MAX_SESSION_SUMMARIES: usize = 10;
+
+struct ActiveSession {
+    summary: SessionSummary,
+    started: Instant,
+}
+
+impl ActiveSession {
+    fn summary(&self) -> SessionSummary {
+        SessionSummary {
+            duration_secs: self.started.elapsed().as_secs(),
+            ..self.summary.clone()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SessionHistory {
+    sessions: VecDeque<SessionSummary>,
+    active: Option<ActiveSession>,
+}
+
+impl SessionHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the start of a session, ending the active one if it exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The shared content
+    pub fn session_started(&mut self, content: Content) {
+        self.session_ended("replaced by a new session");
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.active = Some(ActiveSession {
+            summary: SessionSummary {
+                started_at,
+                duration_secs: 0,
+                content: content.to_string(),
+                controllers: 0,
+                capture_stalls: 0,
+                end_reason: None,
+            },
+            started: Instant::now(),
+        });
+    }
+
+    pub fn controller_joined(&mut self) {
+        if let Some(active) = self.active.as_mut() {
+            active.summary.controllers += 1;
+        }
+    }
+
+    pub fn capture_stalled(&mut self) {
+        if let Some(active) = self.active.as_mut() {
+            active.summary.capture_stalls += 1;
+        }
+    }
+
+    /// Records the end of the active session, does nothing when there is none.
+    pub fn session_ended(&mut self, reason: &str) {
+        let active = match self.active.take() {
+            Some(active) => active,
+            None => return,
+        };
+        let mut summary = active.summary();
+        summary.end_reason = Some(reason.to_string());
+        if self.sessions.len() == MAX_SESSION_SUMMARIES {
+            self.sessions.pop_front();
+        }
+        self.sessions.push_back(summary);
+    }
+
+    /// Returns the recent sessions oldest first, the active one is last.
+    pub fn summaries(&self) -> Vec<SessionSummary> {
+        let mut summaries: Vec<SessionSummary> = self.sessions.iter().cloned().collect();
+        if let Some(active) = &self.active {
+            summaries.push(active.summary());
+        }
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket_lib::ContentType;
+
+    fn display(id: u32) -> Content {
+        Content {
+            content_type: ContentType::Display,
+            id,
+        }
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        let mut history = SessionHistory::new();
+        history.controller_joined();
+        history.session_ended("stopped");
+        assert!(history.summaries().is_empty());
+
+        history.session_started(display(1));
+        history.controller_joined();
+        history.controller_joined();
+        history.capture_stalled();
+        let summaries = history.summaries();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].content, "Display 1");
+        assert_eq!(summaries[0].controllers, 2);
+        assert_eq!(summaries[0].capture_stalls, 1);
+        assert_eq!(summaries[0].end_reason, None);
+
+        history.session_started(display(2));
+        history.session_ended("stopped");
+        let summaries = history.summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(
+            summaries[0].end_reason.as_deref(),
+            Some("replaced by a new session")
+        );
+        assert_eq!(summaries[1].end_reason.as_deref(), Some("stopped"));
+    }
+
+    #[test]
+    fn test_session_history_keeps_the_latest() {
+        let mut history = SessionHistory::new();
+        for i in 0..MAX_SESSION_SUMMARIES as u32 + 3 {
+            history.session_started(display(i));
+            history.session_ended("stopped");
+        }
+        let summaries = history.summaries();
+        assert_eq!(summaries.len(), MAX_SESSION_SUMMARIES);
+        assert_eq!(summaries[0].content, "Display 3");
+    }
+}
@@ -0,0 +1,299 @@
+//! Crash-consistent journal of the active session.
+//!
+//! The session history only lives in memory, a core that crashes or is
+//! killed takes it along. The journal appends each transition of the active
+//! session to a file and syncs it to disk before going on, so the next core
+//! process can tell the session didn't end cleanly. It then attaches the
+//! journal to Sentry and keeps it for the tauri app, which offers the user
+//! a report and to resume the share.
+//!
+//! Every record is one JSON line written with a single write, a record torn
+//! by the crash is the last line and is skipped when the journal is read.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use socket_lib::{AspectPolicy, Content, Extent, UncleanSession};
+
+const JOURNAL_DIR: &str = "com.hopp.app";
+const JOURNAL_FILE: &str = "core_session_journal";
+/// Extension of the journal of the previous core process once it was read
+const RECOVERED_EXTENSION: &str = "last";
+
+/// A transition of the active session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEntry {
+    Started {
+        content: Content,
+        resolution: Extent,
+        view_only: bool,
+        aspect_policy: AspectPolicy,
+    },
+    ParticipantJoined {
+        sid: String,
+    },
+    ParticipantLeft {
+        sid: String,
+    },
+    /// The sharer allowed or revoked the input of a participant
+    ControlChanged {
+        sid: String,
+        authorized: bool,
+    },
+    Error {
+        message: String,
+    },
+    Ended {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalRecord {
+    /// Seconds since the unix epoch
+    timestamp: u64,
+    #[serde(flatten)]
+    entry: JournalEntry,
+}
+
+/// Where the journal is written, `None` when the platform has no data directory.
+///
+/// # Arguments
+///
+/// * `instance` - Name of a core process started next to the default one, it gets a journal of its own
+pub fn journal_path(instance: Option<&str>) -> Option<PathBuf> {
+    let file = match instance {
+        Some(instance) => format!("{JOURNAL_FILE}-{instance}"),
+        None => JOURNAL_FILE.to_string(),
+    };
+    dirs::data_local_dir().map(|path| path.join(JOURNAL_DIR).join(file))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Appends the transitions of the active session to the journal file.
+#[derive(Debug, Default)]
+pub struct SessionJournal {
+    path: Option<PathBuf>,
+    /// The journal of the active session, `None` between sessions
+    file: Option<File>,
+}
+
+impl SessionJournal {
+    /// Creates a journal writing to `path`, without a path nothing is written.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path, file: None }
+    }
+
+    /// Records a transition and syncs it to disk.
+    ///
+    /// A `Started` entry replaces the journal of the previous session, the
+    /// other entries are dropped while no session is active.
+    pub fn record(&mut self, entry: JournalEntry) {
+        if let JournalEntry::Started { .. } = entry {
+            self.file = self.path.as_deref().and_then(|path| {
+                let created = path
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|_| File::create(path));
+                match created {
+                    Ok(file) => Some(file),
+                    Err(e) => {
+                        log::error!("SessionJournal::record: failed to create {path:?}: {e:?}");
+                        None
+                    }
+                }
+            });
+        }
+        let ended = matches!(entry, JournalEntry::Ended { .. });
+
+        let Some(file) = self.file.as_mut() else {
+            return;
+        };
+        let record = JournalRecord {
+            timestamp: now_secs(),
+            entry,
+        };
+        if let Err(e) = write_record(file, &record) {
+            log::error!("SessionJournal::record: failed to write {record:?}: {e:?}");
+        }
+        if ended {
+            self.file = None;
+        }
+    }
+}
+
+/// Writes `record` as one line with a single write and waits until it is on disk.
+fn write_record(file: &mut File, record: &JournalRecord) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    file.sync_data()
+}
+
+/// Returns the session of the journal if it didn't end, `None` for a clean
+/// journal or one without a session.
+fn unclean_session(journal: &str) -> Option<UncleanSession> {
+    let mut lines = vec![];
+    let mut started = None;
+    let mut last_event_at = 0;
+    let mut ended = false;
+    for line in journal.lines() {
+        /* A record torn by the crash can only be the last one. */
+        let Ok(record) = serde_json::from_str::<JournalRecord>(line) else {
+            log::warn!("unclean_session: skipping unreadable record {line:?}");
+            continue;
+        };
+        last_event_at = record.timestamp;
+        match record.entry {
+            JournalEntry::Started {
+                content,
+                resolution,
+                view_only,
+                aspect_policy,
+            } => {
+                started = Some((
+                    record.timestamp,
+                    content,
+                    resolution,
+                    view_only,
+                    aspect_policy,
+                ));
+            }
+            JournalEntry::Ended { .. } => ended = true,
+            _ => {}
+        }
+        lines.push(line.to_string());
+    }
+
+    let (started_at, content, resolution, view_only, aspect_policy) = started?;
+    if ended {
+        return None;
+    }
+    Some(UncleanSession {
+        started_at,
+        last_event_at,
+        content,
+        resolution,
+        view_only,
+        aspect_policy,
+        journal: lines,
+    })
+}
+
+/// Reads the journal a previous core process left at `path`.
+///
+/// The journal is moved aside once read, so the same session isn't
+/// reported by the next core process again.
+///
+/// # Returns
+///
+/// The session if it didn't end cleanly, `None` otherwise.
+pub fn recover(path: &Path) -> Option<UncleanSession> {
+    let journal = match std::fs::read_to_string(path) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            log::error!("session_journal::recover: failed to read {path:?}: {e:?}");
+            return None;
+        }
+    };
+    if let Err(e) = std::fs::rename(path, path.with_extension(RECOVERED_EXTENSION)) {
+        log::error!("session_journal::recover: failed to move {path:?} aside: {e:?}");
+    }
+    unclean_session(&journal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket_lib::ContentType;
+
+    fn started() -> JournalEntry {
+        JournalEntry::Started {
+            content: Content {
+                content_type: ContentType::Display,
+                id: 2,
+            },
+            resolution: Extent {
+                width: 1920.,
+                height: 1080.,
+            },
+            view_only: false,
+            aspect_policy: AspectPolicy::default(),
+        }
+    }
+
+    fn journal_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "hopp_session_journal_{name}_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(path.with_extension(RECOVERED_EXTENSION));
+        path
+    }
+
+    #[test]
+    fn test_clean_end_isnt_recovered() {
+        let path = journal_file("clean");
+        let mut journal = SessionJournal::new(Some(path.clone()));
+        journal.record(started());
+        journal.record(JournalEntry::ParticipantJoined {
+            sid: "a".to_string(),
+        });
+        journal.record(JournalEntry::Ended {
+            reason: "stopped by the sharer".to_string(),
+        });
+        /* Nothing is recorded between sessions. */
+        journal.record(JournalEntry::Error {
+            message: "late".to_string(),
+        });
+
+        assert!(recover(&path).is_none());
+        assert!(!path.exists());
+        assert!(recover(&path).is_none());
+    }
+
+    #[test]
+    fn test_unclean_end_is_recovered() {
+        let path = journal_file("unclean");
+        let mut journal = SessionJournal::new(Some(path.clone()));
+        journal.record(started());
+        journal.record(JournalEntry::ControlChanged {
+            sid: "a".to_string(),
+            authorized: true,
+        });
+        drop(journal);
+        /* The crash tore the last record. */
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(br#"{"timestamp":1,"event":"err"#).unwrap();
+
+        let session = recover(&path).unwrap();
+        assert_eq!(session.content.id, 2);
+        assert_eq!(session.resolution.width, 1920.);
+        assert_eq!(session.journal.len(), 2);
+        assert!(session.journal[1].contains("control_changed"));
+        /* Reported once. */
+        assert!(recover(&path).is_none());
+        let _ = std::fs::remove_file(path.with_extension(RECOVERED_EXTENSION));
+    }
+
+    #[test]
+    fn test_journal_without_session() {
+        assert!(unclean_session("").is_none());
+        assert!(unclean_session("not json\n").is_none());
+    }
+}
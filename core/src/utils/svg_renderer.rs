@@ -6,8 +6,47 @@
 
 use fontdb::Database;
 use resvg::{tiny_skia, usvg};
+use socket_lib::OverlayTheme;
 use thiserror::Error;
 
+use crate::utils::geometry::{Extent, Frame};
+
+/// Size in pixels of the display labels
+pub const DISPLAY_LABEL_SIZE: u32 = 320;
+
+/// Size in logical pixels of the incoming call card
+pub const CALL_CARD_EXTENT: Extent = Extent {
+    width: 360.0,
+    height: 128.0,
+};
+/// The card's decline button, in logical pixels from the card's top left corner
+pub const CALL_DECLINE_BUTTON: Frame = Frame {
+    origin_x: 16.0,
+    origin_y: 76.0,
+    extent: Extent {
+        width: 156.0,
+        height: 36.0,
+    },
+};
+/// The card's accept button, in logical pixels from the card's top left corner
+pub const CALL_ACCEPT_BUTTON: Frame = Frame {
+    origin_x: 188.0,
+    origin_y: 76.0,
+    extent: Extent {
+        width: 156.0,
+        height: 36.0,
+    },
+};
+/// Caller names are shortened to fit the card
+const CALL_CARD_NAME_GLYPHS: usize = 28;
+
+/// Names are never shortened below this, whatever the theme asks for
+const MIN_NAME_GLYPHS: usize = 3;
+
+/// Widest badge box, names measured wider are shortened to `WIDE_NAME_GLYPHS`
+const MAX_BOX_WIDTH: f32 = 152.0;
+const WIDE_NAME_GLYPHS: usize = 17;
+
 #[derive(Error, Debug)]
 pub enum SvgRenderError {
     #[error("Failed to parse SVG: {0}")]
@@ -18,6 +57,83 @@ pub enum SvgRenderError {
     PngSaveError(String),
 }
 
+/// Returns whether `c` is kept out of the badges.
+///
+/// Besides the control characters this covers the zero width and bidi
+/// formatting characters, which can hide or reorder parts of a name.
+fn is_hidden_char(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2069}' | '\u{FEFF}'
+        )
+}
+
+/// Shortens `text` to `max_glyphs` characters by replacing its middle with an ellipsis.
+fn truncate_middle(text: &str, max_glyphs: usize) -> String {
+    let count = text.chars().count();
+    if count <= max_glyphs {
+        return text.to_string();
+    }
+
+    let kept = max_glyphs.saturating_sub(1);
+    let head = kept.div_ceil(2);
+    let tail = kept - head;
+    let mut truncated: String = text.chars().take(head).collect();
+    truncated.push('…');
+    truncated.extend(text.chars().skip(count - tail));
+    truncated
+}
+
+/// Prepares a participant's name for a cursor badge
+///
+/// Drops the characters that can't be drawn or that hide or reorder the
+/// name, collapses whitespace and shortens the name to `max_glyphs`
+/// characters with an ellipsis in the middle, so both the first and the
+/// last name stay recognizable.
+///
+/// # Arguments
+///
+/// * `name` - Name as the participant set it
+/// * `max_glyphs` - Longest label in characters, at least `MIN_NAME_GLYPHS`
+///
+/// # Returns
+///
+/// The label to draw, "?" when nothing drawable is left
+pub fn sanitize_badge_name(name: &str, max_glyphs: usize) -> String {
+    let cleaned = name
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| !is_hidden_char(*c))
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if cleaned.is_empty() {
+        return "?".to_string();
+    }
+
+    truncate_middle(&cleaned, max_glyphs.max(MIN_NAME_GLYPHS))
+}
+
+/// Escapes `text` for the content of an SVG element
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 /// Calculate dynamic box width based on text length
 /// Increases box width for longer text to ensure it fits comfortably
 fn calculate_box_width(text: &str) -> f32 {
@@ -64,15 +180,82 @@ fn get_box_width(text: &str, fontdb: std::sync::Arc<Database>) -> Result<f32, Sv
     }
 }
 
+/// Prepares the name of a badge and measures the box around it
+///
+/// # Returns
+///
+/// The sanitized name escaped for the SVG and the width of its box
+fn badge_label(
+    name: &str,
+    theme: &OverlayTheme,
+    fontdb: std::sync::Arc<Database>,
+) -> (String, f32) {
+    let mut name = sanitize_badge_name(name, theme.max_name_glyphs as usize);
+    let mut box_width = if let Ok(width) = get_box_width(&escape_xml(&name), fontdb) {
+        width
+    } else {
+        log::error!("Failed to get box width for name: {name} using fallback");
+        calculate_box_width(&name)
+    };
+
+    /* Wide glyphs can overflow the badge even within the theme's length. */
+    if box_width > MAX_BOX_WIDTH {
+        box_width = MAX_BOX_WIDTH;
+        name = truncate_middle(&name, WIDE_NAME_GLYPHS);
+    };
+    (escape_xml(&name), box_width)
+}
+
+/// Renders a user badge to PNG data using a template from the textures directory
+///
+/// The template is an SVG document in which `{color}`, `{name}` and
+/// `{box_width}` are replaced like in the built-in templates, the name goes
+/// through `sanitize_badge_name` and is escaped first.
+///
+/// # Arguments
+///
+/// * `template` - SVG document with the placeholders
+/// * `color` - Hex color code for the badge background
+/// * `name` - Name text to display in the badge
+/// * `theme` - Overlay theme limiting the name's length
+/// * `label_scale` - Scale of the badge on top of the display scale, see `display_size::label_scale`
+///
+/// # Returns
+///
+/// Returns `Ok(Vec<u8>)` containing PNG data on success or `Err(SvgRenderError)` on failure
+pub fn render_badge_template_to_png(
+    template: &str,
+    color: &str,
+    name: &str,
+    theme: &OverlayTheme,
+    label_scale: f64,
+) -> Result<Vec<u8>, SvgRenderError> {
+    let mut fontdb = Database::new();
+    fontdb.load_system_fonts();
+    let fontdb = std::sync::Arc::new(fontdb);
+
+    let (name, box_width) = badge_label(name, theme, fontdb.clone());
+    let svg = template
+        .replace("{color}", color)
+        .replace("{box_width}", &box_width.to_string())
+        .replace("{name}", &name);
+
+    render_svg_to_png(&svg, fontdb, label_scale)
+}
+
 /// Renders a user avatar badge to PNG data using a predefined SVG template
 ///
 /// This function uses a specific SVG template that creates a speech bubble design
-/// with customizable color and name text.
+/// with customizable color and name text. The name goes through
+/// `sanitize_badge_name` first, callers keep the full name for the UIs.
 ///
 /// # Arguments
 ///
 /// * `color` - Hex color code (e.g., "#FF5733" or "red") for the badge background
 /// * `name` - Name text to display in the badge
+/// * `pointer` - Draws the pointing hand instead of the arrow
+/// * `theme` - Overlay theme limiting the name's length
+/// * `label_scale` - Scale of the badge on top of the display scale, see `display_size::label_scale`
 ///
 /// # Returns
 ///
@@ -81,34 +264,76 @@ pub fn render_user_badge_to_png(
     color: &str,
     name: &str,
     pointer: bool,
+    theme: &OverlayTheme,
+    label_scale: f64,
 ) -> Result<Vec<u8>, SvgRenderError> {
-    // Calculate dynamic box width based on text length
-    let scale_factor = 3;
-    let filter_width = 120;
-    let view_box_width = 200;
-    let view_box_height = 60;
-
     // Create font database
     let mut fontdb = Database::new();
     fontdb.load_system_fonts();
     let fontdb = std::sync::Arc::new(fontdb);
 
-    let mut box_width = if let Ok(width) = get_box_width(name, fontdb.clone()) {
-        width
-    } else {
-        log::error!("Failed to get box width for name: {name} using fallback");
-        calculate_box_width(name)
-    };
+    let (name, box_width) = badge_label(name, theme, fontdb.clone());
+    let svg_template = user_badge_svg(color, Some((&name, box_width)), pointer);
+
+    render_svg_to_png(&svg_template, fontdb, label_scale)
+}
+
+/// Renders the cursor of a user badge without the name box
+///
+/// The image is cropped to the arrow or the pointing hand, it takes a
+/// fraction of the memory of a badge. The overlay draws these when the
+/// textures of the names don't fit its memory budget.
+///
+/// # Arguments
+///
+/// * `color` - Hex color code for the cursor, like the badge's background
+/// * `pointer` - Draws the pointing hand instead of the arrow
+/// * `label_scale` - Scale of the cursor on top of the display scale, see `display_size::label_scale`
+///
+/// # Returns
+///
+/// Returns `Ok(Vec<u8>)` containing PNG data on success or `Err(SvgRenderError)` on failure
+pub fn render_user_cursor_to_png(
+    color: &str,
+    pointer: bool,
+    label_scale: f64,
+) -> Result<Vec<u8>, SvgRenderError> {
+    /* Nothing to write, the system fonts aren't loaded. */
+    let fontdb = std::sync::Arc::new(Database::new());
+    let svg_template = user_badge_svg(color, None, pointer);
+
+    render_svg_to_png(&svg_template, fontdb, label_scale)
+}
 
-    let mut name = name.to_string();
-    /* This might not work perfectly for every name. */
-    if box_width > 152.0 {
-        box_width = 152.0;
-        name = name.chars().take(17).collect::<String>() + "...";
+/// Builds the SVG of a user badge
+///
+/// # Arguments
+///
+/// * `color` - Hex color code for the badge background
+/// * `label` - The escaped name and the width of its box, `None` for the cursor alone
+/// * `pointer` - Draws the pointing hand instead of the arrow
+fn user_badge_svg(color: &str, label: Option<(&str, f32)>, pointer: bool) -> String {
+    let scale_factor = 3;
+    let filter_width = 120;
+    /* Without the name box the view box is cropped to the cursor. */
+    let (view_box_width, view_box_height) = match (label, pointer) {
+        (Some(_), _) => (200, 60),
+        (None, true) => (40, 60),
+        (None, false) => (30, 30),
     };
 
     // Choose SVG template based on pointer flag
-    let svg_template = if pointer {
+    if pointer {
+        let label = match label {
+            Some((name, box_width)) => format!(
+                r#"<g filter="url(#filter1_d_3690_153)" transform="scale({scale_factor}) translate(14, 0)">
+<rect x="16.8486" y="22" width="{box_width}" height="21.9191" rx="10.9596" fill="{color}" shape-rendering="crispEdges"/>
+<rect x="17.2022" y="22.5645" width="{box_width}" height="21.2121" rx="10.606" stroke="black" stroke-opacity="0.05" stroke-width="0.707069" shape-rendering="crispEdges"/>
+<text fill="white" xml:space="preserve" style="white-space: pre" font-family="Inter, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif" font-size="11.606" font-weight="600" letter-spacing="0.05em"><tspan x="22.9243" y="37.0946">{name}</tspan></text>
+</g>"#
+            ),
+            None => String::new(),
+        };
         // Pointer template
         format!(
             r#"<svg width="100%" height="100%" viewBox="{view_port_x} {view_port_y} {view_box_width} {view_box_height}" fill="none" xmlns="http://www.w3.org/2000/svg">
@@ -116,11 +341,7 @@ pub fn render_user_badge_to_png(
 <path fill="{color}" d="M-368.99-226.1v-9h-4v-8h-4v-8h-4v-9h-5v-4h-4v-8h9v4h4v12h4v-54h8v38h4v-17h9v17h4v-13h8v17h4v-13h5v5h4v29h-4v12h-5v9z" transform="translate(94.337 75.2)scale(.23944)"/>
   <path fill="white" d="M-372.99-222.1v-13h-4v-8h-4v-8h-5v-9h-4v-4h-4v-12h13v4h4v-38h4v-4h8v4h4v17h9v4h12v4h9v4h4v5h4v29h-4v12h-4v13zm37-4v-9h5v-12h4v-29h-4v-5h-5v13h-4v-17h-8v13h-4v-17h-9v17h-4v-38h-8v54h-4v-12h-4v-4h-9v8h4v4h5v9h4v8h4v8h4v9z" transform="translate(94.337 75.2)scale(.23944)"/>
 </g>
-<g filter="url(#filter1_d_3690_153)" transform="scale({scale_factor}) translate(14, 0)">
-<rect x="16.8486" y="22" width="{box_width}" height="21.9191" rx="10.9596" fill="{color}" shape-rendering="crispEdges"/>
-<rect x="17.2022" y="22.5645" width="{box_width}" height="21.2121" rx="10.606" stroke="black" stroke-opacity="0.05" stroke-width="0.707069" shape-rendering="crispEdges"/>
-<text fill="white" xml:space="preserve" style="white-space: pre" font-family="Inter, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif" font-size="11.606" font-weight="600" letter-spacing="0.05em"><tspan x="22.9243" y="37.0946">{name}</tspan></text>
-</g>
+{label}
 <defs>
 <filter id="filter0_d_3690_153" x="0" y="0" width="24.8572" height="28.0661" filterUnits="userSpaceOnUse" color-interpolation-filters="sRGB">
 <feFlood flood-opacity="0" result="BackgroundImageFix" />
@@ -147,14 +368,21 @@ pub fn render_user_badge_to_png(
             view_port_x = 5 * scale_factor,
             view_port_y = -6 * scale_factor,
             color = color,
-            scale_factor = scale_factor,
-            name = name,
-            box_width = box_width,
             view_box_width = view_box_width * scale_factor,
             view_box_height = view_box_height * scale_factor,
             filter_width = filter_width * scale_factor,
         )
     } else {
+        let label = match label {
+            Some((name, box_width)) => format!(
+                r#"<g filter="url(#filter1_d_3690_153)" transform="scale({scale_factor})">
+<rect x="16.8486" y="22.2109" width="{box_width}" height="21.9191" rx="10.9596" fill="{color}" shape-rendering="crispEdges"/>
+<rect x="17.2022" y="22.5645" width="{box_width}" height="21.2121" rx="10.606" stroke="black" stroke-opacity="0.05" stroke-width="0.707069" shape-rendering="crispEdges"/>
+<text fill="white" xml:space="preserve" style="white-space: pre" font-family="Inter, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif" font-size="11.606" font-weight="600" letter-spacing="0.05em"><tspan x="22.9243" y="37.0946">{name}</tspan></text>
+</g>"#
+            ),
+            None => String::new(),
+        };
         // Regular template with dynamic dimensions
         format!(
             r#"<svg width="100%" height="100%" viewBox="0 0 {view_box_width} {view_box_height}" fill="none" xmlns="http://www.w3.org/2000/svg">
@@ -162,11 +390,7 @@ pub fn render_user_badge_to_png(
 <path d="M9.21246 25.5608C8.52706 26.643 6.87099 26.3292 6.62908 25.0712L2.59236 4.08025C2.3623 2.88395 3.65167 1.97598 4.70046 2.59573L22.485 13.1048C23.5565 13.7379 23.3466 15.3466 22.1485 15.6836L14.542 17.8229C14.206 17.9174 13.9169 18.1328 13.7302 18.4276L9.21246 25.5608Z" fill="{color}"/>
 <path d="M2.93945 4.01367C2.76691 3.11645 3.73391 2.43558 4.52051 2.90039L22.3047 13.4092C23.1083 13.884 22.9512 15.09 22.0527 15.3428L14.4463 17.4824C14.0264 17.6005 13.665 17.8698 13.4316 18.2383L8.91406 25.3721C8.40002 26.1834 7.15831 25.9479 6.97656 25.0049L2.93945 4.01367Z" stroke="white" stroke-opacity="0.4" stroke-width="0.707069"/>
 </g>
-<g filter="url(#filter1_d_3690_153)" transform="scale({scale_factor})">
-<rect x="16.8486" y="22.2109" width="{box_width}" height="21.9191" rx="10.9596" fill="{color}" shape-rendering="crispEdges"/>
-<rect x="17.2022" y="22.5645" width="{box_width}" height="21.2121" rx="10.606" stroke="black" stroke-opacity="0.05" stroke-width="0.707069" shape-rendering="crispEdges"/>
-<text fill="white" xml:space="preserve" style="white-space: pre" font-family="Inter, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif" font-size="11.606" font-weight="600" letter-spacing="0.05em"><tspan x="22.9243" y="37.0946">{name}</tspan></text>
-</g>
+{label}
 <defs>
 <filter id="filter0_d_3690_153" x="0.444222" y="0.981369" width="24.8572" height="28.0661" filterUnits="userSpaceOnUse" color-interpolation-filters="sRGB">
 <feFlood flood-opacity="0" result="BackgroundImageFix"/>
@@ -189,35 +413,126 @@ pub fn render_user_badge_to_png(
 </filter>
 </defs>
 </svg>"#,
-            scale_factor = scale_factor,
-            box_width = box_width,
             color = color,
-            name = name,
             view_box_width = view_box_width * scale_factor,
             view_box_height = view_box_height * scale_factor,
             filter_width = filter_width * scale_factor,
         )
+    }
+}
+
+/// Renders the label flashed on a display when identifying the displays
+///
+/// The label is a dark rounded square with `label`, usually the display's
+/// number in the content picker, written in large white text.
+///
+/// # Arguments
+///
+/// * `label` - Text of the label, it should be short
+///
+/// # Returns
+///
+/// Returns `Ok(Vec<u8>)` containing PNG data on success or `Err(SvgRenderError)` on failure
+pub fn render_display_label_to_png(label: &str) -> Result<Vec<u8>, SvgRenderError> {
+    let mut fontdb = Database::new();
+    fontdb.load_system_fonts();
+    let fontdb = std::sync::Arc::new(fontdb);
+
+    let svg_template = format!(
+        r##"<svg width="{size}" height="{size}" viewBox="0 0 {size} {size}" fill="none" xmlns="http://www.w3.org/2000/svg">
+<rect x="0" y="0" width="{size}" height="{size}" rx="48" fill="#0F172A" fill-opacity="0.85"/>
+<rect x="4" y="4" width="{inner_size}" height="{inner_size}" rx="44" stroke="white" stroke-opacity="0.3" stroke-width="8"/>
+<text fill="white" x="50%" y="50%" text-anchor="middle" dominant-baseline="central" font-family="Inter, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif" font-size="160" font-weight="700">{label}</text>
+</svg>"##,
+        size = DISPLAY_LABEL_SIZE,
+        inner_size = DISPLAY_LABEL_SIZE - 8,
+    );
+
+    render_svg_to_png(&svg_template, fontdb, 1.0)
+}
+
+/// Renders the card of an incoming call
+///
+/// The card is a dark rounded rectangle with the caller's name and a decline
+/// and an accept button, laid out at `CALL_DECLINE_BUTTON` and
+/// `CALL_ACCEPT_BUTTON`.
+///
+/// # Arguments
+///
+/// * `caller` - Name of the caller, it is sanitized like the badge names
+/// * `zoom` - Scale of the rendered image, the display's scale keeps the text sharp
+///
+/// # Returns
+///
+/// Returns `Ok(Vec<u8>)` containing PNG data on success or `Err(SvgRenderError)` on failure
+pub fn render_call_notification_to_png(caller: &str, zoom: f64) -> Result<Vec<u8>, SvgRenderError> {
+    let mut fontdb = Database::new();
+    fontdb.load_system_fonts();
+    let fontdb = std::sync::Arc::new(fontdb);
+
+    let button = |frame: Frame, color: &str, label: &str| {
+        format!(
+            r##"<rect x="{x}" y="{y}" width="{width}" height="{height}" rx="8" fill="{color}"/>
+<text fill="white" x="{center_x}" y="{center_y}" text-anchor="middle" dominant-baseline="central" font-family="Inter, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif" font-size="14" font-weight="600">{label}</text>"##,
+            x = frame.origin_x,
+            y = frame.origin_y,
+            width = frame.extent.width,
+            height = frame.extent.height,
+            center_x = frame.origin_x + frame.extent.width / 2.0,
+            center_y = frame.origin_y + frame.extent.height / 2.0,
+        )
     };
+    let svg_template = format!(
+        r##"<svg width="{width}" height="{height}" viewBox="0 0 {width} {height}" fill="none" xmlns="http://www.w3.org/2000/svg">
+<rect x="0" y="0" width="{width}" height="{height}" rx="16" fill="#0F172A" fill-opacity="0.95"/>
+<text fill="#94A3B8" x="16" y="28" font-family="Inter, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif" font-size="12" font-weight="500">Incoming call</text>
+<text fill="white" x="16" y="54" font-family="Inter, -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif" font-size="18" font-weight="600">{caller}</text>
+{decline}
+{accept}
+</svg>"##,
+        width = CALL_CARD_EXTENT.width,
+        height = CALL_CARD_EXTENT.height,
+        caller = escape_xml(&sanitize_badge_name(caller, CALL_CARD_NAME_GLYPHS)),
+        decline = button(CALL_DECLINE_BUTTON, "#DC2626", "Decline"),
+        accept = button(CALL_ACCEPT_BUTTON, "#16A34A", "Accept"),
+    );
+
+    render_svg_to_png(&svg_template, fontdb, zoom)
+}
 
+/// Renders an SVG document to PNG data
+///
+/// The document is rasterized `zoom` times its size, so scaled up badges
+/// stay sharp instead of being stretched by the GPU.
+fn render_svg_to_png(
+    svg_template: &str,
+    fontdb: std::sync::Arc<Database>,
+    zoom: f64,
+) -> Result<Vec<u8>, SvgRenderError> {
     // Parse the SVG with font database
     let usvg_options = usvg::Options {
         fontdb,
         ..Default::default()
     };
-    let tree = usvg::Tree::from_str(&svg_template, &usvg_options)
+    let tree = usvg::Tree::from_str(svg_template, &usvg_options)
         .map_err(|e| SvgRenderError::SvgParseError(e.to_string()))?;
 
     // Get the SVG size
+    let zoom = zoom as f32;
     let svg_size = tree.size();
-    let width = svg_size.width() as u32;
-    let height = svg_size.height() as u32;
+    let width = (svg_size.width() * zoom) as u32;
+    let height = (svg_size.height() * zoom) as u32;
 
     // Create a pixmap to render into
     let mut pixmap =
         tiny_skia::Pixmap::new(width, height).ok_or(SvgRenderError::PixmapCreationError)?;
 
     // Render the SVG
-    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(zoom, zoom),
+        &mut pixmap.as_mut(),
+    );
 
     // Encode as PNG and return the data
     pixmap
@@ -231,7 +546,8 @@ mod tests {
 
     #[test]
     fn test_render_user_badge_to_png() {
-        let png_data = render_user_badge_to_png("#FF5733", "Alice", false).unwrap();
+        let theme = OverlayTheme::default();
+        let png_data = render_user_badge_to_png("#FF5733", "Alice", false, &theme, 1.0).unwrap();
 
         // Verify it's valid PNG data by checking PNG signature
         assert_eq!(&png_data[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
@@ -240,7 +556,7 @@ mod tests {
         assert!(png_data.len() > 100);
 
         // Test with different parameters
-        let png_data2 = render_user_badge_to_png("#00FF00", "Bob Doe", false).unwrap();
+        let png_data2 = render_user_badge_to_png("#00FF00", "Bob Doe", false, &theme, 1.0).unwrap();
         assert_eq!(&png_data2[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
         assert!(png_data2.len() > 100);
 
@@ -248,6 +564,23 @@ mod tests {
         assert_ne!(png_data, png_data2);
     }
 
+    #[test]
+    fn test_render_display_label_to_png() {
+        let png_data = render_display_label_to_png("2").unwrap();
+        assert_eq!(&png_data[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
+        assert_ne!(png_data, render_display_label_to_png("3").unwrap());
+    }
+
+    #[test]
+    fn test_render_call_notification_to_png() {
+        let png_data = render_call_notification_to_png("Alice <Doe>", 2.0).unwrap();
+        assert_eq!(&png_data[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
+        assert_ne!(
+            png_data,
+            render_call_notification_to_png("Bob", 2.0).unwrap()
+        );
+    }
+
     #[test]
     fn test_calculate_box_width() {
         // Short names should use base width
@@ -268,15 +601,19 @@ mod tests {
 
     #[test]
     fn test_different_name_lengths() {
+        let render_user_badge_to_png_default = |color: &str, name: &str, pointer: bool| {
+            render_user_badge_to_png(color, name, pointer, &OverlayTheme::default(), 1.0)
+        };
         // Test badges with different name lengths (now with dynamic box width)
-        let very_short_badge = render_user_badge_to_png("#9FB8E8", "Me", false).unwrap();
-        let short_badge = render_user_badge_to_png("#9FB8E8", "Joe", false).unwrap();
-        let medium_badge = render_user_badge_to_png("#9FB8E8", "Alice Doe", false).unwrap();
-        let long_badge = render_user_badge_to_png("#9FB8E8", "Iason Parask", false).unwrap();
+        let very_short_badge = render_user_badge_to_png_default("#9FB8E8", "Me", false).unwrap();
+        let short_badge = render_user_badge_to_png_default("#9FB8E8", "Joe", false).unwrap();
+        let medium_badge = render_user_badge_to_png_default("#9FB8E8", "Alice Doe", false).unwrap();
+        let long_badge =
+            render_user_badge_to_png_default("#9FB8E8", "Iason Parask", false).unwrap();
         let extra_long_badge =
-            render_user_badge_to_png("#9FB8E8", "AlexanderGGGGGGGGGGG", false).unwrap();
+            render_user_badge_to_png_default("#9FB8E8", "AlexanderGGGGGGGGGGG", false).unwrap();
         let extra_long_badge_two =
-            render_user_badge_to_png("#9FB8E8", "Lykourgos Mpezentakos", false).unwrap();
+            render_user_badge_to_png_default("#9FB8E8", "Lykourgos Mpezentakos", false).unwrap();
 
         // All should generate valid PNG data
         assert_eq!(&short_badge[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
@@ -295,8 +632,10 @@ mod tests {
 
     #[test]
     fn test_pointer_badge() {
+        let theme = OverlayTheme::default();
         // Test the pointer template
-        let pointer_badge = render_user_badge_to_png("#FF5733", "Costa", true).unwrap();
+        let pointer_badge =
+            render_user_badge_to_png("#FF5733", "Costa", true, &theme, 1.0).unwrap();
 
         // Verify it's valid PNG data by checking PNG signature
         assert_eq!(&pointer_badge[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
@@ -305,7 +644,8 @@ mod tests {
         assert!(pointer_badge.len() > 100);
 
         // Test regular badge for comparison
-        let regular_badge = render_user_badge_to_png("#FF5733", "Costa", false).unwrap();
+        let regular_badge =
+            render_user_badge_to_png("#FF5733", "Costa", false, &theme, 1.0).unwrap();
 
         // The two images should be different (different templates)
         assert_ne!(pointer_badge, regular_badge);
@@ -313,4 +653,77 @@ mod tests {
         // Save example for visual inspection
         std::fs::write("test_pointer_badge.png", pointer_badge).unwrap();
     }
+
+    #[test]
+    fn test_render_user_cursor_to_png() {
+        let theme = OverlayTheme::default();
+        for pointer in [false, true] {
+            let badge = render_user_badge_to_png("#FF5733", "Costa", pointer, &theme, 1.0).unwrap();
+            let badge = image::load_from_memory(&badge).unwrap();
+            let cursor = render_user_cursor_to_png("#FF5733", pointer, 1.0).unwrap();
+            let cursor = image::load_from_memory(&cursor).unwrap();
+
+            /* Cropped to the cursor, the image is a fraction of the badge. */
+            assert!(cursor.height() <= badge.height());
+            assert!(cursor.width() * 4 < badge.width(), "pointer: {pointer}");
+            assert!(cursor.to_rgba8().pixels().any(|pixel| pixel[3] > 0));
+        }
+    }
+
+    #[test]
+    fn test_render_badge_template_to_png() {
+        let theme = OverlayTheme::default();
+        let template = r#"<svg width="120" height="40" xmlns="http://www.w3.org/2000/svg">
+<rect width="{box_width}" height="40" fill="{color}"/>
+<text x="4" y="24">{name}</text>
+</svg>"#;
+        let png_data =
+            render_badge_template_to_png(template, "#FF5733", "Tom & Jerry", &theme, 1.0).unwrap();
+        assert_eq!(&png_data[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
+        assert_ne!(
+            png_data,
+            render_badge_template_to_png(template, "#00FF00", "Tom & Jerry", &theme, 1.0).unwrap()
+        );
+
+        assert!(render_badge_template_to_png("<svg", "#FF5733", "Tom", &theme, 1.0).is_err());
+
+        /* Larger labels are rasterized larger, not stretched. */
+        let large =
+            render_badge_template_to_png(template, "#FF5733", "Tom & Jerry", &theme, 2.0).unwrap();
+        let large = image::load_from_memory(&large).unwrap();
+        assert_eq!((large.width(), large.height()), (240, 80));
+    }
+
+    #[test]
+    fn test_sanitize_badge_name() {
+        assert_eq!(sanitize_badge_name("Alice", 20), "Alice");
+        assert_eq!(sanitize_badge_name("  Bob \n\t Doe ", 20), "Bob Doe");
+        assert_eq!(sanitize_badge_name("Ev\u{202E}il\u{200B}\u{7}", 20), "Evil");
+        assert_eq!(sanitize_badge_name("\u{202E}\u{200D} ", 20), "?");
+
+        /* The start and the end of long names survive. */
+        assert_eq!(sanitize_badge_name("Alexander Hamilton", 11), "Alexa…ilton");
+        assert_eq!(sanitize_badge_name("Ἀλέξανδρος", 4), "Ἀλ…ς");
+        assert_eq!(sanitize_badge_name("Alexander", 0), "A…r");
+
+        let label = sanitize_badge_name(&"W".repeat(10_000), 20);
+        assert_eq!(label.chars().count(), 20);
+    }
+
+    #[test]
+    fn test_hostile_names_render() {
+        let theme = OverlayTheme {
+            max_name_glyphs: 8,
+            ..Default::default()
+        };
+        for name in [
+            "</text><script/>",
+            "Tom & \"Jerry\"",
+            "\u{202E}evil\u{0}",
+            &"Long".repeat(1000),
+        ] {
+            let png_data = render_user_badge_to_png("#9FB8E8", name, false, &theme, 1.0).unwrap();
+            assert_eq!(&png_data[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
+        }
+    }
 }
@@ -0,0 +1,80 @@
+//! Input authorization of the simulated controllers.
+//!
+//! The sharer's core drops the clicks, keystrokes and scrolls of identities
+//! the sharer didn't authorize. The tests authorize their participants through
+//! the socket, like the sharer's UI does, and stamp their input with the
+//! token the core grants them.
+
+use crate::events::ClientEvent;
+use livekit::prelude::*;
+use socket_lib::{CursorSocket, Message};
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+const INPUT_GRANT_TOPIC: &str = "input_grant";
+/// The core only authorizes admitted participants, the first requests can
+/// arrive before it saw the participant join.
+const GRANT_ATTEMPTS: u32 = 10;
+const GRANT_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct Grant {
+    token: String,
+    seq: u64,
+}
+
+/// Grants of the authorized identities
+static GRANTS: Mutex<BTreeMap<String, Grant>> = Mutex::new(BTreeMap::new());
+
+/// Authorizes the input of the room's participant and waits for its token.
+pub async fn authorize(
+    socket: &mut CursorSocket,
+    room: &Room,
+    rx: &mut UnboundedReceiver<RoomEvent>,
+) -> io::Result<()> {
+    let sid = room.local_participant().sid().as_str().to_string();
+    let identity = room.local_participant().identity().as_str().to_string();
+    for _ in 0..GRANT_ATTEMPTS {
+        socket.send_message(Message::SetInputAuthorized {
+            sid: sid.clone(),
+            authorized: true,
+        })?;
+        let deadline = tokio::time::Instant::now() + GRANT_TIMEOUT;
+        while let Ok(Some(event)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+            let RoomEvent::DataReceived { payload, topic, .. } = event else {
+                continue;
+            };
+            if topic.as_deref() != Some(INPUT_GRANT_TOPIC) {
+                continue;
+            }
+            let grant: serde_json::Value =
+                serde_json::from_slice(&payload).map_err(io::Error::other)?;
+            if let Some(token) = grant["payload"]["token"].as_str() {
+                println!("{identity} was granted input");
+                GRANTS.lock().unwrap().insert(
+                    identity,
+                    Grant {
+                        token: token.to_string(),
+                        seq: 0,
+                    },
+                );
+                return Ok(());
+            }
+        }
+    }
+    Err(io::Error::other(format!("{identity} wasn't granted input")))
+}
+
+/// Serializes `event`, stamped with the token of the room's participant when
+/// it was authorized.
+pub fn encode(room: &Room, event: &ClientEvent) -> io::Result<Vec<u8>> {
+    let mut value = serde_json::to_value(event).map_err(io::Error::other)?;
+    let identity = room.local_participant().identity().as_str().to_string();
+    if let Some(grant) = GRANTS.lock().unwrap().get_mut(&identity) {
+        grant.seq += 1;
+        value["auth"] = serde_json::json!({ "token": grant.token, "seq": grant.seq });
+    }
+    serde_json::to_vec(&value).map_err(io::Error::other)
+}
@@ -0,0 +1,332 @@
+//! Load test of the sharer's event pipeline.
+//!
+//! Simulated controllers join the room and send mouse moves, clicks and
+//! scrolls at fixed rates. Next to the input they send ticks, which the
+//! sharer's core answers after they went through the same event loop as the
+//! input, so the round trip of a tick is the handling latency under load.
+//!
+//! The core only answers ticks in debug builds. The simulated controllers
+//! aren't authorized for input, the sharer drops their clicks and scrolls
+//! before they reach the event loop, see `input_auth`.
+
+use crate::events::{ClientEvent, ClientPoint, MouseClickData, TickData, WheelDelta};
+use crate::livekit_utils;
+use crate::screenshare_client;
+use livekit::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::io;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// The core broadcasts the tick responses, a tick's time is the send time in
+/// microseconds times this plus the controller's index, so each controller
+/// only counts its own responses.
+const TICK_PARTICIPANT_SLOTS: u128 = 1000;
+/// How long the controllers wait for the last tick responses after sending.
+const TICK_DRAIN: Duration = Duration::from_secs(2);
+const TICK_RESPONSE_TOPIC: &str = "tick_response";
+
+#[derive(Debug, Clone)]
+pub struct LoadTestConfig {
+    /// Number of simulated controllers
+    pub participants: usize,
+    /// How long the controllers send events
+    pub duration: Duration,
+    /// Events per second per controller, 0 disables the event
+    pub move_rate: f64,
+    pub click_rate: f64,
+    pub scroll_rate: f64,
+    pub tick_rate: f64,
+    /// Use the running share instead of starting one through the socket
+    pub attach: bool,
+}
+
+#[derive(Debug, Default)]
+struct ParticipantReport {
+    name: String,
+    moves: u64,
+    clicks: u64,
+    scrolls: u64,
+    send_errors: u64,
+    ticks: u64,
+    /// Round trips of the answered ticks in milliseconds
+    latencies: Vec<f64>,
+}
+
+/// When the next event of a kind is due.
+struct Schedule {
+    period: Option<Duration>,
+    next: Instant,
+}
+
+impl Schedule {
+    /// Creates a schedule of `rate` events per second, the first one is due
+    /// at a random point of the first period so the controllers don't send
+    /// in lockstep.
+    fn new(rate: f64, start: Instant, rng: &mut StdRng) -> Self {
+        let period = (rate > 0.0).then(|| Duration::from_secs_f64(1.0 / rate));
+        let next = match period {
+            Some(period) => start + period.mul_f64(rng.gen_range(0.0..1.0)),
+            None => start,
+        };
+        Self { period, next }
+    }
+
+    /// Returns `true` if an event is due at `now` and schedules the next one.
+    fn due(&mut self, now: Instant) -> bool {
+        let Some(period) = self.period else {
+            return false;
+        };
+        if now < self.next {
+            return false;
+        }
+        self.next += period;
+        /* A stalled controller skips the missed events instead of bursting them. */
+        if self.next < now {
+            self.next = now + period;
+        }
+        true
+    }
+}
+
+fn micros_since_epoch() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros()
+}
+
+async fn publish(room: &Room, event: &ClientEvent, reliable: bool) -> io::Result<()> {
+    let payload = serde_json::to_vec(event).map_err(io::Error::other)?;
+    room.local_participant()
+        .publish_data(DataPacket {
+            payload,
+            reliable,
+            ..Default::default()
+        })
+        .await
+        .map_err(io::Error::other)
+}
+
+/// Collects the round trips of the controller `index`'s ticks until `until`.
+async fn collect_tick_responses(
+    mut events: UnboundedReceiver<RoomEvent>,
+    index: usize,
+    until: Instant,
+) -> Vec<f64> {
+    let mut latencies = vec![];
+    while let Ok(Some(event)) = tokio::time::timeout_at(until.into(), events.recv()).await {
+        let RoomEvent::DataReceived { payload, topic, .. } = event else {
+            continue;
+        };
+        if topic.as_deref() != Some(TICK_RESPONSE_TOPIC) {
+            continue;
+        }
+        let Ok(ClientEvent::TickResponse(TickData { time })) = serde_json::from_slice(&payload)
+        else {
+            continue;
+        };
+        if time % TICK_PARTICIPANT_SLOTS != index as u128 {
+            continue;
+        }
+        let sent = time / TICK_PARTICIPANT_SLOTS;
+        latencies.push(micros_since_epoch().saturating_sub(sent) as f64 / 1000.0);
+    }
+    latencies
+}
+
+/// Joins the room as controller `index` and sends events until `end`.
+async fn run_participant(
+    index: usize,
+    config: LoadTestConfig,
+    url: String,
+    end: Instant,
+) -> io::Result<ParticipantReport> {
+    let name = format!("Load Tester {index}");
+    let token = livekit_utils::generate_token(&name);
+    let (room, events) = Room::connect(&url, &token, RoomOptions::default())
+        .await
+        .map_err(io::Error::other)?;
+    let responses = tokio::spawn(collect_tick_responses(events, index, end + TICK_DRAIN));
+
+    let mut rng = StdRng::seed_from_u64(index as u64);
+    let start = Instant::now();
+    let mut moves = Schedule::new(config.move_rate, start, &mut rng);
+    let mut clicks = Schedule::new(config.click_rate, start, &mut rng);
+    let mut scrolls = Schedule::new(config.scroll_rate, start, &mut rng);
+    let mut ticks = Schedule::new(config.tick_rate, start, &mut rng);
+    let mut report = ParticipantReport {
+        name,
+        ..Default::default()
+    };
+    let (mut x, mut y): (f64, f64) = (rng.gen_range(0.1..0.9), rng.gen_range(0.1..0.9));
+
+    let step = Duration::from_millis(1);
+    while Instant::now() < end {
+        let now = Instant::now();
+        let mut results = vec![];
+        if moves.due(now) {
+            x = (x + rng.gen_range(-0.01..0.01)).clamp(0.05, 0.95);
+            y = (y + rng.gen_range(-0.01..0.01)).clamp(0.05, 0.95);
+            let event = ClientEvent::MouseMove(ClientPoint {
+                x,
+                y,
+                pointer: false,
+            });
+            results.push(publish(&room, &event, false).await);
+            report.moves += 1;
+        }
+        if clicks.due(now) {
+            for down in [true, false] {
+                let event = ClientEvent::MouseClick(MouseClickData {
+                    x,
+                    y,
+                    button: 0,
+                    clicks: 1,
+                    down,
+                    shift: false,
+                    meta: false,
+                    ctrl: false,
+                    alt: false,
+                });
+                results.push(publish(&room, &event, true).await);
+            }
+            report.clicks += 1;
+        }
+        if scrolls.due(now) {
+            let event = ClientEvent::WheelEvent(WheelDelta {
+                deltaX: 0.0,
+                deltaY: rng.gen_range(-3.0..3.0),
+            });
+            results.push(publish(&room, &event, false).await);
+            report.scrolls += 1;
+        }
+        if ticks.due(now) {
+            let time = micros_since_epoch() * TICK_PARTICIPANT_SLOTS + index as u128;
+            results.push(publish(&room, &ClientEvent::Tick(TickData { time }), true).await);
+            report.ticks += 1;
+        }
+        report.send_errors += results.iter().filter(|res| res.is_err()).count() as u64;
+        tokio::time::sleep(step).await;
+    }
+
+    report.latencies = responses.await.map_err(io::Error::other)?;
+    room.close().await.map_err(io::Error::other)?;
+    Ok(report)
+}
+
+/// Returns the nearest rank percentile of sorted `samples`, 0 when there are none.
+fn percentile(samples: &[f64], percentile: f64) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let rank = ((percentile / 100.0) * samples.len() as f64).ceil() as usize;
+    samples[rank.clamp(1, samples.len()) - 1]
+}
+
+fn print_report(config: &LoadTestConfig, reports: &[ParticipantReport], failed: usize) {
+    println!();
+    println!(
+        "{:<16} {:>8} {:>7} {:>8} {:>7} {:>9} {:>9} {:>9} {:>9}",
+        "participant",
+        "moves",
+        "clicks",
+        "scrolls",
+        "errors",
+        "ticks",
+        "answered",
+        "p50 ms",
+        "p95 ms"
+    );
+    let mut all_latencies = vec![];
+    for report in reports {
+        let mut latencies = report.latencies.clone();
+        latencies.sort_by(|a, b| a.total_cmp(b));
+        println!(
+            "{:<16} {:>8} {:>7} {:>8} {:>7} {:>9} {:>9} {:>9.1} {:>9.1}",
+            report.name,
+            report.moves,
+            report.clicks,
+            report.scrolls,
+            report.send_errors,
+            report.ticks,
+            latencies.len(),
+            percentile(&latencies, 50.0),
+            percentile(&latencies, 95.0),
+        );
+        all_latencies.extend(latencies);
+    }
+    all_latencies.sort_by(|a, b| a.total_cmp(b));
+
+    let seconds = config.duration.as_secs_f64();
+    let events: u64 = reports
+        .iter()
+        .map(|report| report.moves + report.clicks * 2 + report.scrolls + report.ticks)
+        .sum();
+    let ticks: u64 = reports.iter().map(|report| report.ticks).sum();
+    println!();
+    println!(
+        "{} controllers ({failed} failed to run), {events} events in {seconds:.0}s, {:.0} events/s",
+        reports.len(),
+        events as f64 / seconds
+    );
+    println!(
+        "Ticks answered: {}/{ticks}, latency p50 {:.1} ms, p95 {:.1} ms, p99 {:.1} ms, max {:.1} ms",
+        all_latencies.len(),
+        percentile(&all_latencies, 50.0),
+        percentile(&all_latencies, 95.0),
+        percentile(&all_latencies, 99.0),
+        all_latencies.last().copied().unwrap_or(0.0),
+    );
+    if ticks > 0 && all_latencies.is_empty() {
+        println!("No tick was answered, ticks are only answered by debug builds of the core.");
+    }
+}
+
+/// Runs the load test against the sharer and prints a report.
+pub async fn run_loadtest(config: LoadTestConfig) -> io::Result<()> {
+    let mut socket = if config.attach {
+        None
+    } else {
+        println!("Starting screenshare session...");
+        let (socket, _) = screenshare_client::start_screenshare_session()?;
+        Some(socket)
+    };
+
+    let url = std::env::var("LIVEKIT_URL").expect("LIVEKIT_URL environment variable not set");
+    println!(
+        "Starting {} controllers for {}s: {} moves/s, {} clicks/s, {} scrolls/s, {} ticks/s each",
+        config.participants,
+        config.duration.as_secs(),
+        config.move_rate,
+        config.click_rate,
+        config.scroll_rate,
+        config.tick_rate
+    );
+    let end = Instant::now() + config.duration;
+    let tasks: Vec<_> = (0..config.participants)
+        .map(|index| tokio::spawn(run_participant(index, config.clone(), url.clone(), end)))
+        .collect();
+
+    let mut reports = vec![];
+    let mut failed = 0;
+    for task in tasks {
+        match task.await {
+            Ok(Ok(report)) => reports.push(report),
+            Ok(Err(e)) => {
+                println!("Controller failed: {e:?}");
+                failed += 1;
+            }
+            Err(e) => {
+                println!("Controller task failed: {e:?}");
+                failed += 1;
+            }
+        }
+    }
+    print_report(&config, &reports, failed);
+
+    if let Some(socket) = socket.as_mut() {
+        screenshare_client::stop_screenshare_session(socket)?;
+    }
+    Ok(())
+}
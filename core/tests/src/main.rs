@@ -1,8 +1,11 @@
 use clap::{Parser, Subcommand, ValueEnum};
 use std::io;
+use std::time::Duration;
 
 mod events;
+mod input_auth;
 mod livekit_utils;
+mod loadtest;
 mod remote_cursor;
 mod remote_keyboard;
 mod screenshare_client;
@@ -26,6 +29,36 @@ enum Commands {
     Keyboard,
     /// Test screenshare functionality
     Screenshare,
+    /// Test that a second screenshare is rejected while sharing
+    DuplicateScreenshare,
+    /// Test that a view-only screenshare creates no input injection
+    ViewOnlyScreenshare,
+    /// Test sharing a generated test pattern instead of the screen
+    TestPatternScreenshare,
+    /// Load test the sharer's event pipeline with simulated controllers
+    Loadtest {
+        /// Number of simulated controllers
+        #[arg(long, default_value_t = 20)]
+        participants: usize,
+        /// How long the controllers send events, in seconds
+        #[arg(long, default_value_t = 30)]
+        duration: u64,
+        /// Mouse moves per second per controller
+        #[arg(long, default_value_t = 60.0)]
+        move_rate: f64,
+        /// Clicks per second per controller, they land on the sharer's desktop
+        #[arg(long, default_value_t = 0.0)]
+        click_rate: f64,
+        /// Scrolls per second per controller
+        #[arg(long, default_value_t = 2.0)]
+        scroll_rate: f64,
+        /// Latency ticks per second per controller
+        #[arg(long, default_value_t = 5.0)]
+        tick_rate: f64,
+        /// Use the running share instead of starting one
+        #[arg(long)]
+        attach: bool,
+    },
 }
 
 #[derive(Clone, ValueEnum, Debug)]
@@ -131,6 +164,43 @@ async fn main() -> io::Result<()> {
             println!("Running screenshare test...");
             screenshare_client::screenshare_test()?;
         }
+        Commands::DuplicateScreenshare => {
+            println!("Running duplicate screenshare test...");
+            screenshare_client::duplicate_screenshare_test()?;
+            println!("Duplicate screenshare test finished.");
+        }
+        Commands::ViewOnlyScreenshare => {
+            println!("Running view-only screenshare test...");
+            screenshare_client::view_only_screenshare_test()?;
+            println!("View-only screenshare test finished.");
+        }
+        Commands::TestPatternScreenshare => {
+            println!("Running test pattern screenshare test...");
+            screenshare_client::test_pattern_screenshare_test()?;
+            println!("Test pattern screenshare test finished.");
+        }
+        Commands::Loadtest {
+            participants,
+            duration,
+            move_rate,
+            click_rate,
+            scroll_rate,
+            tick_rate,
+            attach,
+        } => {
+            println!("Running load test...");
+            loadtest::run_loadtest(loadtest::LoadTestConfig {
+                participants,
+                duration: Duration::from_secs(duration),
+                move_rate,
+                click_rate,
+                scroll_rate,
+                tick_rate,
+                attach,
+            })
+            .await?;
+            println!("Load test finished.");
+        }
     }
 
     Ok(())
@@ -1,4 +1,5 @@
 use crate::events::{ClientEvent, ClientPoint, MouseClickData, WheelDelta};
+use crate::input_auth;
 use crate::livekit_utils;
 use crate::screenshare_client;
 use livekit::prelude::*;
@@ -21,7 +22,7 @@ async fn send_mouse_click(room: &Room, x: f64, y: f64, button: u32) -> io::Resul
         alt: false,
     };
     let event_down = ClientEvent::MouseClick(click_down_data);
-    let payload_down = serde_json::to_vec(&event_down).map_err(io::Error::other)?;
+    let payload_down = input_auth::encode(room, &event_down)?;
     room.local_participant()
         .publish_data(DataPacket {
             payload: payload_down,
@@ -47,7 +48,7 @@ async fn send_mouse_click(room: &Room, x: f64, y: f64, button: u32) -> io::Resul
         alt: false,
     };
     let event_up = ClientEvent::MouseClick(click_up_data);
-    let payload_up = serde_json::to_vec(&event_up).map_err(io::Error::other)?;
+    let payload_up = input_auth::encode(room, &event_up)?;
     room.local_participant()
         .publish_data(DataPacket {
             payload: payload_up,
@@ -87,7 +88,7 @@ async fn send_mouse_wheel(room: &Room, delta_x: f64, delta_y: f64) -> io::Result
         deltaY: delta_y,
     };
     let event = ClientEvent::WheelEvent(wheel_delta);
-    let payload = serde_json::to_vec(&event).map_err(io::Error::other)?;
+    let payload = input_auth::encode(room, &event)?;
     room.local_participant()
         .publish_data(DataPacket {
             payload,
@@ -230,10 +231,11 @@ pub async fn test_cursor() -> io::Result<()> {
     let token = livekit_utils::generate_token("Test Cursor");
     let url = std::env::var("LIVEKIT_URL").expect("LIVEKIT_URL environment variable not set");
 
-    let (room, mut _rx) = Room::connect(&url, &token, RoomOptions::default())
+    let (room, mut rx) = Room::connect(&url, &token, RoomOptions::default())
         .await
         .unwrap();
     println!("Connected to room: {}", room.name());
+    input_auth::authorize(&mut cursor_socket, &room, &mut rx).await?;
 
     internal_cursor_move(&room, 0.0, 0.2, 0.5).await?;
     internal_cursor_click(&room, 0.7, 0.3).await?;
@@ -252,10 +254,11 @@ pub async fn test_cursor_click(x: f64, y: f64) -> io::Result<()> {
     let token = livekit_utils::generate_token("Test Cursor Click");
     let url = std::env::var("LIVEKIT_URL").expect("LIVEKIT_URL environment variable not set");
 
-    let (room, mut _rx) = Room::connect(&url, &token, RoomOptions::default())
+    let (room, mut rx) = Room::connect(&url, &token, RoomOptions::default())
         .await
         .unwrap();
     println!("Connected to room: {}", room.name());
+    input_auth::authorize(&mut cursor_socket, &room, &mut rx).await?;
 
     internal_cursor_click(&room, x, y).await?;
     screenshare_client::stop_screenshare_session(&mut cursor_socket)?;
@@ -305,10 +308,11 @@ pub async fn test_cursor_scroll() -> io::Result<()> {
     let token = livekit_utils::generate_token("Test Cursor Scroll");
     let url = std::env::var("LIVEKIT_URL").expect("LIVEKIT_URL environment variable not set");
 
-    let (room, mut _rx) = Room::connect(&url, &token, RoomOptions::default())
+    let (room, mut rx) = Room::connect(&url, &token, RoomOptions::default())
         .await
         .unwrap();
     println!("Connected to room: {}", room.name());
+    input_auth::authorize(&mut cursor_socket, &room, &mut rx).await?;
 
     internal_cursor_scroll(&room).await?;
     screenshare_client::stop_screenshare_session(&mut cursor_socket)?;
@@ -423,20 +427,24 @@ pub async fn test_multiple_cursors_with_control() -> io::Result<()> {
     let token_3 = livekit_utils::generate_token("Christopher");
     let token_4 = livekit_utils::generate_token("Christopher Martin");
 
-    let (room_1, _rx_1) = Room::connect(&url, &token_1, RoomOptions::default())
+    let (room_1, mut rx_1) = Room::connect(&url, &token_1, RoomOptions::default())
         .await
         .unwrap();
-    let (room_2, _rx_2) = Room::connect(&url, &token_2, RoomOptions::default())
+    let (room_2, mut rx_2) = Room::connect(&url, &token_2, RoomOptions::default())
         .await
         .unwrap();
-    let (room_3, _rx_3) = Room::connect(&url, &token_3, RoomOptions::default())
+    let (room_3, mut rx_3) = Room::connect(&url, &token_3, RoomOptions::default())
         .await
         .unwrap();
-    let (room_4, _rx_4) = Room::connect(&url, &token_4, RoomOptions::default())
+    let (room_4, mut rx_4) = Room::connect(&url, &token_4, RoomOptions::default())
         .await
         .unwrap();
 
     println!("All 4 participants connected with persistent connections.");
+    input_auth::authorize(&mut cursor_socket, &room_1, &mut rx_1).await?;
+    input_auth::authorize(&mut cursor_socket, &room_2, &mut rx_2).await?;
+    input_auth::authorize(&mut cursor_socket, &room_3, &mut rx_3).await?;
+    input_auth::authorize(&mut cursor_socket, &room_4, &mut rx_4).await?;
 
     // We need to wait for the textures to load
     tokio::time::sleep(std::time::Duration::from_secs(5)).await;
@@ -1101,14 +1109,16 @@ pub async fn test_concurrent_scrolling() -> io::Result<()> {
     let token_1 = livekit_utils::generate_token("Alice Scroll");
     let token_2 = livekit_utils::generate_token("Bob Scroll");
 
-    let (room_1, _rx_1) = Room::connect(&url, &token_1, RoomOptions::default())
+    let (room_1, mut rx_1) = Room::connect(&url, &token_1, RoomOptions::default())
         .await
         .unwrap();
-    let (room_2, _rx_2) = Room::connect(&url, &token_2, RoomOptions::default())
+    let (room_2, mut rx_2) = Room::connect(&url, &token_2, RoomOptions::default())
         .await
         .unwrap();
 
     println!("Both participants connected to room");
+    input_auth::authorize(&mut cursor_socket, &room_1, &mut rx_1).await?;
+    input_auth::authorize(&mut cursor_socket, &room_2, &mut rx_2).await?;
 
     sleep(Duration::from_secs(2)).await;
 
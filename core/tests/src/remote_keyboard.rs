@@ -1,4 +1,5 @@
 use crate::events::{ClientEvent, KeystrokeData};
+use crate::input_auth;
 use crate::screenshare_client;
 use livekit::prelude::*;
 use std::{io, time::Duration};
@@ -15,7 +16,7 @@ async fn send_keystroke(room: &Room, key: &str, shift: bool, down: bool) -> io::
         down,
     };
     let event = ClientEvent::Keystroke(keystroke_data);
-    let payload = serde_json::to_vec(&event).map_err(io::Error::other)?;
+    let payload = input_auth::encode(room, &event)?;
     room.local_participant()
         .publish_data(DataPacket {
             payload,
@@ -90,10 +91,11 @@ pub async fn test_keyboard_chars() -> io::Result<()> {
     let token = crate::livekit_utils::generate_token("Test Keyboard");
     let url = std::env::var("LIVEKIT_URL").expect("LIVEKIT_URL environment variable not set");
 
-    let (room, mut _rx) = Room::connect(&url, &token, RoomOptions::default())
+    let (room, mut rx) = Room::connect(&url, &token, RoomOptions::default())
         .await
         .unwrap();
     println!("Connected to room: {}", room.name());
+    input_auth::authorize(&mut cursor_socket, &room, &mut rx).await?;
 
     internal_test_keyboard_chars(&room).await?;
 
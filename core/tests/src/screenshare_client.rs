@@ -1,14 +1,16 @@
 use crate::livekit_utils;
 use socket_lib::CaptureContent;
-use socket_lib::{Content, ContentType, CursorSocket, Extent, Message, ScreenShareMessage};
+use socket_lib::{
+    AspectPolicy, Content, ContentType, CursorSocket, Extent, InputInjectionStatus, Message,
+    ScreenShareMessage,
+};
 use std::env;
 use std::io;
 
 /// Creates and connects to the cursor socket.
 pub fn connect_socket() -> io::Result<CursorSocket> {
-    let tmp_folder = std::env::temp_dir();
     // Consider making the socket name configurable or discoverable if needed
-    let socket_path = format!("{}/core-socket", tmp_folder.display());
+    let socket_path = socket_lib::socket_path("core-socket")?;
     println!("Connecting to socket: {socket_path}");
     // Use the function from the new module
     CursorSocket::new(&socket_path)
@@ -18,7 +20,13 @@ pub fn connect_socket() -> io::Result<CursorSocket> {
 pub fn get_available_content(socket: &mut CursorSocket) -> io::Result<Message> {
     let message = Message::GetAvailableContent;
     socket.send_message(message)?;
-    socket.receive_message()
+    loop {
+        match socket.receive_message()? {
+            /* The core asks for the LiveKit URL when it starts, the tests send it themselves. */
+            Message::NeedLivekitUrl => println!("Skipping message: NeedLivekitUrl"),
+            message => return Ok(message),
+        }
+    }
 }
 
 /// Sends a request to start screen sharing.
@@ -27,6 +35,18 @@ pub fn request_screenshare(
     content_id: u32,
     width: f64,
     height: f64,
+) -> io::Result<()> {
+    request_screenshare_with_mode(socket, content_id, width, height, false)
+}
+
+/// Sends a request to start screen sharing, `view_only` sessions don't
+/// create the input injection.
+pub fn request_screenshare_with_mode(
+    socket: &mut CursorSocket,
+    content_id: u32,
+    width: f64,
+    height: f64,
+    view_only: bool,
 ) -> io::Result<()> {
     let token = livekit_utils::generate_token("Test Screenshare");
 
@@ -37,6 +57,9 @@ pub fn request_screenshare(
         },
         token,
         resolution: Extent { width, height },
+        view_only,
+        aspect_policy: AspectPolicy::Fit,
+        framerate: None,
     });
     socket.send_message(message)
 }
@@ -80,6 +103,76 @@ pub fn screenshare_test() -> io::Result<()> {
     Ok(())
 }
 
+/// Waits for the result of a start screen share request.
+///
+/// Returns whether the share started and whether the core rejected it
+/// because a session was already active. Other notifications are skipped.
+fn wait_for_screenshare_result(socket: &mut CursorSocket) -> io::Result<(bool, bool)> {
+    let mut already_sharing = false;
+    loop {
+        match socket.receive_message()? {
+            Message::StartScreenShareResult(parameters) => {
+                if let Some(parameters) = &parameters {
+                    println!("Publishing: {parameters:?}");
+                }
+                return Ok((parameters.is_some(), already_sharing));
+            }
+            Message::AlreadySharing => already_sharing = true,
+            message => println!("Skipping message: {}", message.name()),
+        }
+    }
+}
+
+/// Starting a share while one is active is rejected and keeps the active
+/// session, after stopping it a new share starts cleanly.
+pub fn duplicate_screenshare_test() -> io::Result<()> {
+    let mut socket = connect_socket()?;
+    println!("Connected to socket.");
+
+    let livekit_server_url =
+        env::var("LIVEKIT_URL").expect("LIVEKIT_URL environment variable not set");
+    socket.send_message(Message::LivekitServerUrl(livekit_server_url))?;
+
+    let available_content = match get_available_content(&mut socket)? {
+        Message::AvailableContent(available_content) => available_content,
+        _ => return Err(io::Error::other("Failed to get available content")),
+    };
+    let content_id = available_content.content[0].content.id;
+    let (width, height) = (1920.0, 1080.0);
+
+    request_screenshare(&mut socket, content_id, width, height)?;
+    let (started, _) = wait_for_screenshare_result(&mut socket)?;
+    if !started {
+        return Err(io::Error::other("First screen share failed"));
+    }
+    println!("Screen share started.");
+
+    request_screenshare(&mut socket, content_id, width, height)?;
+    let (started, already_sharing) = wait_for_screenshare_result(&mut socket)?;
+    if started || !already_sharing {
+        return Err(io::Error::other(format!(
+            "Duplicate screen share wasn't rejected: started {started} already sharing {already_sharing}"
+        )));
+    }
+    println!("Duplicate screen share rejected.");
+
+    stop_screenshare(&mut socket)?;
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    request_screenshare(&mut socket, content_id, width, height)?;
+    let (started, _) = wait_for_screenshare_result(&mut socket)?;
+    if !started {
+        return Err(io::Error::other("Screen share after stopping failed"));
+    }
+    println!("Screen share restarted.");
+
+    std::thread::sleep(std::time::Duration::from_secs(5));
+    stop_screenshare(&mut socket)?;
+    println!("Screen share stopped.");
+
+    Ok(())
+}
+
 pub fn start_screenshare_session() -> io::Result<(CursorSocket, Vec<CaptureContent>)> {
     println!("Connecting to screenshare socket...");
     let mut socket = connect_socket()?;
@@ -115,3 +208,98 @@ pub fn stop_screenshare_session(socket: &mut CursorSocket) -> io::Result<()> {
     println!("Screenshare stopped.");
     Ok(())
 }
+
+/// Asks the core which input injection the active session created.
+fn get_input_injection_status(socket: &mut CursorSocket) -> io::Result<InputInjectionStatus> {
+    socket.send_message(Message::GetInputInjectionStatus)?;
+    loop {
+        match socket.receive_message()? {
+            Message::InputInjectionStatus(status) => return Ok(status),
+            message => println!("Skipping message: {}", message.name()),
+        }
+    }
+}
+
+/// A view-only share starts without keyboard and mouse injection and the
+/// core reports it, after stopping it nothing is reported as sharing.
+pub fn view_only_screenshare_test() -> io::Result<()> {
+    let mut socket = connect_socket()?;
+    println!("Connected to socket.");
+
+    let livekit_server_url =
+        env::var("LIVEKIT_URL").expect("LIVEKIT_URL environment variable not set");
+    socket.send_message(Message::LivekitServerUrl(livekit_server_url))?;
+
+    let available_content = match get_available_content(&mut socket)? {
+        Message::AvailableContent(available_content) => available_content,
+        _ => return Err(io::Error::other("Failed to get available content")),
+    };
+    let content_id = available_content.content[0].content.id;
+
+    request_screenshare_with_mode(&mut socket, content_id, 1920.0, 1080.0, true)?;
+    let (started, _) = wait_for_screenshare_result(&mut socket)?;
+    if !started {
+        return Err(io::Error::other("View-only screen share failed"));
+    }
+    println!("View-only screen share started.");
+
+    let status = get_input_injection_status(&mut socket)?;
+    println!("Input injection status: {status:?}");
+    if !status.sharing || !status.view_only || status.keyboard || status.mouse {
+        return Err(io::Error::other(format!(
+            "Unexpected input injection in a view-only session: {status:?}"
+        )));
+    }
+
+    std::thread::sleep(std::time::Duration::from_secs(5));
+    stop_screenshare(&mut socket)?;
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    let status = get_input_injection_status(&mut socket)?;
+    if status != InputInjectionStatus::default() {
+        return Err(io::Error::other(format!(
+            "Unexpected input injection after stopping: {status:?}"
+        )));
+    }
+    println!("View-only screen share stopped.");
+
+    Ok(())
+}
+
+/// Shares a generated test pattern, checks that it starts without capturing
+/// the screen and stops it.
+pub fn test_pattern_screenshare_test() -> io::Result<()> {
+    let mut socket = connect_socket()?;
+    println!("Connected to socket.");
+
+    let livekit_server_url =
+        env::var("LIVEKIT_URL").expect("LIVEKIT_URL environment variable not set");
+    socket.send_message(Message::LivekitServerUrl(livekit_server_url))?;
+
+    socket.send_message(Message::StartTestPatternShare {
+        token: livekit_utils::generate_token("Test Screenshare"),
+        resolution: Extent {
+            width: 1920.0,
+            height: 1080.0,
+        },
+        fps: 30,
+    })?;
+    let (started, _) = wait_for_screenshare_result(&mut socket)?;
+    if !started {
+        return Err(io::Error::other("Test pattern share failed"));
+    }
+    println!("Test pattern share started, join the room to see it.");
+
+    let status = get_input_injection_status(&mut socket)?;
+    if !status.sharing || !status.view_only {
+        return Err(io::Error::other(format!(
+            "Unexpected session for a test pattern share: {status:?}"
+        )));
+    }
+
+    std::thread::sleep(std::time::Duration::from_secs(10));
+    stop_screenshare(&mut socket)?;
+    println!("Test pattern share stopped.");
+
+    Ok(())
+}
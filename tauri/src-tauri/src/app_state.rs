@@ -2,20 +2,52 @@ use std::{
     fs,
     path::{Path, PathBuf},
     sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use socket_lib::{OverlayTheme, ThumbnailPrivacy};
+
+use crate::scheduler::ScheduledShare;
 
 const APP_STATE_FILE: &str = "app_state.json";
 const OLD_TRAY_NOTIFICATION_FILE: &str = "tray_notification.json";
 
+/// A step migrating the persisted state one version up.
+type Migration = fn(&mut Map<String, Value>);
+
+/// Migration steps, `MIGRATIONS[n]` migrates a state from version `n` to `n + 1`.
+///
+/// New settings only need a default in `AppStateInternal`, a step is needed
+/// when a setting is renamed, removed or its meaning changes.
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1, migrate_v1_to_v2];
+
+/// Version of the state written by this build.
+const APP_STATE_VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Debug, thiserror::Error)]
+enum AppStateError {
+    #[error("Failed to read app state: {0}")]
+    Read(#[from] std::io::Error),
+    #[error("Failed to parse app state: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("App state is not a JSON object")]
+    NotAnObject,
+}
+
 /// Current version of the application state structure.
 ///
 /// This struct represents the complete application state that gets
 /// persisted to disk. It includes all user preferences and settings
-/// that should survive between application restarts.
+/// that should survive between application restarts. Missing fields
+/// take their default value, so adding a setting doesn't need a migration.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 struct AppStateInternal {
+    /// Schema version of the persisted state.
+    pub version: u32,
+
     /// Whether the notifications which shows that hopp is in the menu bar will be shown
     pub tray_notification: bool,
 
@@ -24,13 +56,21 @@ struct AppStateInternal {
 
     /// Flag indicating if this is the user's first time running the application.
     pub first_run: bool,
-}
 
-/// Legacy version of the application state structure.
-#[derive(Debug, Serialize, Deserialize)]
-struct OldAppStateInternal {
-    pub tray_notification: bool,
-    pub last_used_mic: Option<String>,
+    /// Apps whose windows are redacted in the content picker thumbnails.
+    pub thumbnail_privacy: ThumbnailPrivacy,
+
+    /// Pids or app names excluded from the shared content.
+    pub capture_exclusions: Vec<String>,
+
+    /// Whether the user opted in to the anonymous health heartbeats of the core process.
+    pub health_pings: bool,
+
+    /// How the overlay draws the controllers' cursors.
+    pub overlay_theme: OverlayTheme,
+
+    /// The share started automatically at a given time, see `scheduler`.
+    pub scheduled_share: Option<ScheduledShare>,
 }
 
 impl Default for AppStateInternal {
@@ -40,11 +80,22 @@ impl Default for AppStateInternal {
     /// - Tray notification: enabled
     /// - Last used microphone: none
     /// - First run: true
+    /// - Thumbnail privacy: no blocked apps
+    /// - Capture exclusions: none besides the app's own processes
+    /// - Health pings: disabled
+    /// - Overlay theme: names of up to 20 characters
+    /// - Scheduled share: none
     fn default() -> Self {
         AppStateInternal {
+            version: APP_STATE_VERSION,
             tray_notification: true,
             last_used_mic: None,
             first_run: true,
+            thumbnail_privacy: ThumbnailPrivacy::default(),
+            capture_exclusions: vec![],
+            health_pings: false,
+            overlay_theme: OverlayTheme::default(),
+            scheduled_share: None,
         }
     }
 }
@@ -91,12 +142,74 @@ fn retrieve_old_tray(root_folder: &Path) -> bool {
     true
 }
 
+/// v0 states were written before the first run flag existed, they come from
+/// users that already went through the first run.
+fn migrate_v0_to_v1(state: &mut Map<String, Value>) {
+    state.entry("first_run").or_insert(Value::Bool(false));
+}
+
+/// v2 adds the version field, it is set after the migrations run.
+fn migrate_v1_to_v2(_state: &mut Map<String, Value>) {}
+
+/// Returns the schema version of a persisted state.
+fn state_version(state: &Map<String, Value>) -> u32 {
+    match state.get("version").and_then(Value::as_u64) {
+        Some(version) => version as u32,
+        /* States written before the schema was versioned. */
+        None if state.contains_key("first_run") => 1,
+        None => 0,
+    }
+}
+
+/// Parses a persisted state, migrating it to the current version.
+///
+/// # Returns
+///
+/// The state and whether it was migrated, the error when the contents aren't a valid state.
+fn parse_state(contents: &str) -> Result<(AppStateInternal, bool), AppStateError> {
+    let mut value: Value = serde_json::from_str(contents)?;
+    let fields = value.as_object_mut().ok_or(AppStateError::NotAnObject)?;
+
+    let version = state_version(fields);
+    if version > APP_STATE_VERSION {
+        /* Written by a newer build, the settings this build knows are kept. */
+        log::warn!("parse_state: app state version {version} is newer than {APP_STATE_VERSION}");
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::info!(
+            "parse_state: migrating app state from v{from} to v{}",
+            from + 1
+        );
+        migration(fields);
+    }
+    let migrated = version < APP_STATE_VERSION;
+    if migrated {
+        fields.insert("version".to_string(), Value::from(APP_STATE_VERSION));
+    }
+
+    Ok((serde_json::from_value(value)?, migrated))
+}
+
+/// Moves a state file that couldn't be loaded aside, so it can be inspected later.
+fn backup_state_file(path: &Path) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let backup_path = path.with_extension(format!("json.{timestamp}.bak"));
+    match fs::rename(path, &backup_path) {
+        Ok(()) => log::info!("backup_state_file: backed up to {}", backup_path.display()),
+        Err(e) => log::error!("backup_state_file: Failed to back up app state: {e}"),
+    }
+}
+
 impl AppState {
     /// Creates a new AppState instance, loading from disk or using defaults.
     ///
     /// This constructor handles the complete initialization process including:
     /// - Loading existing state from disk
-    /// - Migrating from legacy formats
+    /// - Migrating older versions of the state to the current one
+    /// - Backing up a corrupted state and resetting to defaults
     /// - Creating default state if no existing state is found
     /// - Setting up thread-safe access
     ///
@@ -109,71 +222,57 @@ impl AppState {
     /// A new `AppState` instance ready for use
     pub fn new(root_folder: &Path) -> Self {
         let app_state_path = root_folder.join(APP_STATE_FILE);
-        if !app_state_path.exists() {
+        let state = if app_state_path.exists() {
+            Self::load(&app_state_path)
+        } else {
             let state = AppStateInternal {
                 tray_notification: retrieve_old_tray(root_folder),
                 ..Default::default()
             };
-
-            if let Ok(serialized) = serde_json::to_string_pretty(&state) {
-                let _ = fs::write(app_state_path, serialized);
+            if !Self::write_file(&app_state_path, &state) {
+                log::error!("Failed to write new app state to file.");
             }
+            state
+        };
 
-            return AppState {
-                state,
-                root_folder: root_folder.to_path_buf(),
-                lock: Mutex::new(()),
-            };
+        AppState {
+            state,
+            root_folder: root_folder.to_path_buf(),
+            lock: Mutex::new(()),
         }
+    }
 
-        match fs::read_to_string(app_state_path) {
-            Ok(contents) => match serde_json::from_str::<AppStateInternal>(&contents) {
-                Ok(state) => {
-                    return AppState {
-                        state,
-                        root_folder: root_folder.to_path_buf(),
-                        lock: Mutex::new(()),
-                    };
+    /// Loads the state file, migrating it to the current version.
+    ///
+    /// A file that can't be read or parsed is backed up and replaced with the
+    /// defaults, so a corrupted state never prevents the app from starting.
+    fn load(path: &PathBuf) -> AppStateInternal {
+        let res = fs::read_to_string(path)
+            .map_err(AppStateError::from)
+            .and_then(|contents| parse_state(&contents));
+        match res {
+            Ok((state, migrated)) => {
+                if migrated && !Self::write_file(path, &state) {
+                    log::error!("Failed to write migrated app state to file.");
                 }
-                Err(_) => {
-                    log::error!("Failed to parse app state from file, using default state.");
-                    /* Fallback for migration from old app state. */
-                    match serde_json::from_str::<OldAppStateInternal>(&contents) {
-                        Ok(state) => {
-                            let new_state = AppStateInternal {
-                                tray_notification: state.tray_notification,
-                                last_used_mic: state.last_used_mic,
-                                first_run: false,
-                            };
-
-                            let app_state_path = root_folder.join(APP_STATE_FILE);
-                            if !Self::write_file(&app_state_path, &new_state) {
-                                log::error!("Failed to write new app state to file.");
-                            }
-
-                            return AppState {
-                                state: new_state,
-                                root_folder: root_folder.to_path_buf(),
-                                lock: Mutex::new(()),
-                            };
-                        }
-                        Err(_) => {
-                            log::error!(
-                                "Failed to parse old app state from file, using default state."
-                            );
-                        }
-                    }
+                state
+            }
+            Err(e) => {
+                log::error!("Failed to load app state, using default state: {e}");
+                sentry_utils::add_breadcrumb("app_state", format!("reset to defaults: {e}"));
+                backup_state_file(path);
+
+                /* The file existed, so this isn't the first run. */
+                let state = AppStateInternal {
+                    first_run: false,
+                    ..Default::default()
+                };
+                if !Self::write_file(path, &state) {
+                    log::error!("Failed to write default app state to file.");
                 }
-            },
-            Err(_) => {
-                log::error!("Failed to read app state file, using default state.");
+                state
             }
         }
-        AppState {
-            state: AppStateInternal::default(),
-            root_folder: root_folder.to_path_buf(),
-            lock: Mutex::new(()),
-        }
     }
 
     /// Gets the current tray notification setting.
@@ -208,6 +307,87 @@ impl AppState {
         }
     }
 
+    /// Gets the apps whose thumbnails are redacted.
+    pub fn thumbnail_privacy(&self) -> ThumbnailPrivacy {
+        let _lock = self.lock.lock().unwrap();
+        self.state.thumbnail_privacy.clone()
+    }
+
+    /// Updates the apps whose thumbnails are redacted and saves to disk.
+    pub fn set_thumbnail_privacy(&mut self, privacy: ThumbnailPrivacy) {
+        log::info!("set_thumbnail_privacy: {privacy:?}");
+        let _lock = self.lock.lock().unwrap();
+        self.state.thumbnail_privacy = privacy;
+        if !self.save() {
+            log::error!("set_thumbnail_privacy: Failed to save app state");
+        }
+    }
+
+    /// Gets the apps excluded from the shared content.
+    pub fn capture_exclusions(&self) -> Vec<String> {
+        let _lock = self.lock.lock().unwrap();
+        self.state.capture_exclusions.clone()
+    }
+
+    /// Updates the apps excluded from the shared content and saves to disk.
+    pub fn set_capture_exclusions(&mut self, exclusions: Vec<String>) {
+        log::info!("set_capture_exclusions: {exclusions:?}");
+        let _lock = self.lock.lock().unwrap();
+        self.state.capture_exclusions = exclusions;
+        if !self.save() {
+            log::error!("set_capture_exclusions: Failed to save app state");
+        }
+    }
+
+    /// Gets whether the user opted in to the health heartbeats.
+    pub fn health_pings(&self) -> bool {
+        let _lock = self.lock.lock().unwrap();
+        self.state.health_pings
+    }
+
+    /// Updates the health heartbeats opt-in and saves to disk, it applies
+    /// from the next start of the core process.
+    pub fn set_health_pings(&mut self, value: bool) {
+        log::info!("set_health_pings: {value}");
+        let _lock = self.lock.lock().unwrap();
+        self.state.health_pings = value;
+        if !self.save() {
+            log::error!("set_health_pings: Failed to save app state");
+        }
+    }
+
+    /// Gets how the overlay draws the controllers' cursors.
+    pub fn overlay_theme(&self) -> OverlayTheme {
+        let _lock = self.lock.lock().unwrap();
+        self.state.overlay_theme
+    }
+
+    /// Updates the overlay theme and saves to disk.
+    pub fn set_overlay_theme(&mut self, theme: OverlayTheme) {
+        log::info!("set_overlay_theme: {theme:?}");
+        let _lock = self.lock.lock().unwrap();
+        self.state.overlay_theme = theme;
+        if !self.save() {
+            log::error!("set_overlay_theme: Failed to save app state");
+        }
+    }
+
+    /// Gets the scheduled share.
+    pub fn scheduled_share(&self) -> Option<ScheduledShare> {
+        let _lock = self.lock.lock().unwrap();
+        self.state.scheduled_share.clone()
+    }
+
+    /// Updates the scheduled share and saves to disk, `None` cancels it.
+    pub fn set_scheduled_share(&mut self, share: Option<ScheduledShare>) {
+        log::info!("set_scheduled_share: {share:?}");
+        let _lock = self.lock.lock().unwrap();
+        self.state.scheduled_share = share;
+        if !self.save() {
+            log::error!("set_scheduled_share: Failed to save app state");
+        }
+    }
+
     /// Checks if this is the user's first time running the application.
     pub fn first_run(&self) -> bool {
         let _lock = self.lock.lock().unwrap();
@@ -262,3 +442,81 @@ impl AppState {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_v0_state() {
+        let (state, migrated) =
+            parse_state(r#"{"tray_notification": false, "last_used_mic": "mic"}"#).unwrap();
+        assert!(migrated);
+        assert_eq!(state.version, APP_STATE_VERSION);
+        /* v0 users already went through the first run. */
+        assert!(!state.first_run);
+        assert!(!state.tray_notification);
+        assert_eq!(state.last_used_mic.as_deref(), Some("mic"));
+    }
+
+    #[test]
+    fn test_parse_v1_state() {
+        let (state, migrated) =
+            parse_state(r#"{"first_run": true, "tray_notification": true}"#).unwrap();
+        assert!(migrated);
+        assert_eq!(state.version, APP_STATE_VERSION);
+        assert!(state.first_run);
+        assert!(state.capture_exclusions.is_empty());
+    }
+
+    #[test]
+    fn test_parse_current_state() {
+        let written = AppStateInternal {
+            first_run: false,
+            health_pings: true,
+            capture_exclusions: vec!["zoom.us".to_string()],
+            ..Default::default()
+        };
+        let contents = serde_json::to_string(&written).unwrap();
+        let (state, migrated) = parse_state(&contents).unwrap();
+        assert!(!migrated);
+        assert_eq!(state.version, APP_STATE_VERSION);
+        assert!(!state.first_run);
+        assert!(state.health_pings);
+        assert_eq!(state.capture_exclusions, vec!["zoom.us".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_newer_state() {
+        let contents = format!(
+            r#"{{"version": {}, "tray_notification": false, "setting_of_a_newer_build": 1}}"#,
+            APP_STATE_VERSION + 1
+        );
+        let (state, migrated) = parse_state(&contents).unwrap();
+        /* The settings this build knows are kept, the file isn't rewritten. */
+        assert!(!migrated);
+        assert_eq!(state.version, APP_STATE_VERSION + 1);
+        assert!(!state.tray_notification);
+    }
+
+    #[test]
+    fn test_parse_invalid_state() {
+        for contents in ["[]", "\"state\"", "42", "null"] {
+            assert!(
+                matches!(parse_state(contents), Err(AppStateError::NotAnObject)),
+                "{contents}"
+            );
+        }
+        for contents in [
+            "",
+            "{\"first_run\": tr",
+            "\u{0}\u{1}garbage",
+            r#"{"first_run": "yes"}"#,
+        ] {
+            assert!(
+                matches!(parse_state(contents), Err(AppStateError::Parse(_))),
+                "{contents}"
+            );
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Latency of the requests to the core process.
+//!
+//! Every request/response pair sent over the core socket is timed, so
+//! reports like "clicking share takes ten seconds" can be narrowed down to
+//! the core, the socket or the waiting for the `AppData` lock.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Number of recent round trips the percentiles are computed from, per request.
+const MAX_SAMPLES: usize = 200;
+
+/// Round trips slower than this are logged as they happen.
+pub const SLOW_REQUEST: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct RequestSamples {
+    /// Recent round trips in milliseconds, oldest first
+    round_trips: VecDeque<f64>,
+    /// Recent waits for the `AppData` lock in milliseconds, oldest first
+    lock_waits: VecDeque<f64>,
+    count: u64,
+    errors: u64,
+}
+
+/// Latency summary of one kind of request.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct IpcRequestStats {
+    pub request: String,
+    pub count: u64,
+    pub errors: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+    /// 95th percentile of the time spent waiting for the `AppData` lock
+    pub lock_wait_p95_ms: f64,
+}
+
+/// Timings of the requests sent to the core process since the app started.
+#[derive(Debug, Default)]
+pub struct IpcStats {
+    requests: HashMap<&'static str, RequestSamples>,
+}
+
+fn push_sample(samples: &mut VecDeque<f64>, duration: Duration) {
+    if samples.len() == MAX_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back(duration.as_secs_f64() * 1000.);
+}
+
+/// Returns the nearest rank percentile of `samples`, 0 when there are none.
+fn percentile(samples: &VecDeque<f64>, percentile: f64) -> f64 {
+    if samples.is_empty() {
+        return 0.;
+    }
+    let mut sorted: Vec<f64> = samples.iter().copied().collect();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = ((percentile / 100.) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl IpcStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a request to the core process.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Name of the request, usually the tauri command
+    /// * `lock_wait` - Time spent waiting for the `AppData` lock before sending
+    /// * `round_trip` - Time from sending the request to receiving its reply
+    /// * `ok` - Whether the request got a reply
+    pub fn record(
+        &mut self,
+        request: &'static str,
+        lock_wait: Duration,
+        round_trip: Duration,
+        ok: bool,
+    ) {
+        let samples = self.requests.entry(request).or_default();
+        samples.count += 1;
+        if !ok {
+            samples.errors += 1;
+        }
+        push_sample(&mut samples.round_trips, round_trip);
+        push_sample(&mut samples.lock_waits, lock_wait);
+    }
+
+    /// Returns the summary of every request, sorted by name.
+    pub fn snapshot(&self) -> Vec<IpcRequestStats> {
+        let mut stats: Vec<IpcRequestStats> = self
+            .requests
+            .iter()
+            .map(|(request, samples)| IpcRequestStats {
+                request: request.to_string(),
+                count: samples.count,
+                errors: samples.errors,
+                p50_ms: percentile(&samples.round_trips, 50.),
+                p95_ms: percentile(&samples.round_trips, 95.),
+                max_ms: samples.round_trips.iter().copied().fold(0., f64::max),
+                lock_wait_p95_ms: percentile(&samples.lock_waits, 95.),
+            })
+            .collect();
+        stats.sort_by(|a, b| a.request.cmp(&b.request));
+        stats
+    }
+}
@@ -1,14 +1,18 @@
 pub mod app_state;
+pub mod ipc_stats;
 pub mod permissions;
+pub mod scheduler;
 pub mod sounds;
+pub mod support_bundle;
 
+use ipc_stats::{IpcStats, SLOW_REQUEST};
 use log::LevelFilter;
 use sounds::SoundEntry;
+use std::collections::HashMap;
 use std::env;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-#[cfg(target_os = "macos")]
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tauri::async_runtime::Receiver;
 use tauri::path::BaseDirectory;
 #[cfg(target_os = "macos")]
@@ -19,7 +23,8 @@ use tauri::{Rect, WebviewWindow};
 use tauri_plugin_autostart::AutoLaunchManager;
 use tauri_plugin_shell::{process::CommandChild, process::CommandEvent, ShellExt};
 
-use socket_lib::{CursorSocket, Message};
+use socket_lib::shm::SharedRegion;
+use socket_lib::{BulkPayload, BulkPayloadKind, CursorSocket, Message};
 #[cfg(target_os = "macos")]
 use tauri::{LogicalPosition, PhysicalPosition, PhysicalSize};
 
@@ -28,6 +33,13 @@ use smappservice_rs::*;
 
 const PING_SLEEP_SECS: u64 = 30;
 const PING_CORE_PROCESS_INTERVAL_SECS: u64 = 15;
+const CORE_NOTIFICATIONS_POLL_MS: u64 = 500;
+const CORE_NOTIFICATIONS_READ_TIMEOUT_MS: u64 = 50;
+/// Default seconds between the core's health heartbeats.
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 15 * 60;
+/// Session of the core process started with the app, the commands that
+/// don't name a session go to it.
+pub const DEFAULT_SESSION_ID: &str = "default";
 
 #[derive(Debug, thiserror::Error)]
 pub enum CoreProcessCreationError {
@@ -35,20 +47,35 @@ pub enum CoreProcessCreationError {
     SocketCreationFailed,
     #[error("Failed to send message to core process")]
     SendMessageFailed,
+    #[error("Invalid session id: {0:?}")]
+    InvalidSessionId(String),
+    #[error("A core process is already running for session {0}")]
+    SessionExists(String),
 }
 
-/// Wrapper for the core process child handle.
+/// A core process and the socket connected to it.
 pub struct CoreProcess {
     pub process: CommandChild,
+    /// Socket connection to the core process for inter-process communication.
+    /// Used to send commands like screen sharing requests, cursor control,
+    /// and receive responses from the native core process.
+    pub socket: CursorSocket,
+}
+
+/// Returns the error of a request to a session without a core process.
+fn unknown_session(session_id: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("no core process for session {session_id}"),
+    )
 }
 
 /// Central application data structure that holds all the runtime state and resources
 /// needed by the Tauri application.
 pub struct AppData {
-    /// Socket connection to the core process for inter-process communication.
-    /// Used to send commands like screen sharing requests, cursor control,
-    /// and receive responses from the native core process.
-    pub socket: CursorSocket,
+    /// The running core processes by session id, each shares to a room of
+    /// its own. The one started with the app is `DEFAULT_SESSION_ID`.
+    pub cores: HashMap<String, CoreProcess>,
 
     /// Active sound entries currently being played by the application.
     /// Each entry contains the sound name and a channel transmitter to control playback.
@@ -72,6 +99,9 @@ pub struct AppData {
 
     /// Livekit server URL.
     pub livekit_server_url: String,
+
+    /// Latency of the requests sent to the core process, see `request_core`.
+    pub ipc_stats: IpcStats,
 }
 
 impl AppData {
@@ -79,7 +109,7 @@ impl AppData {
     ///
     /// # Arguments
     ///
-    /// * `socket` - The cursor socket for communicating with the core process
+    /// * `core` - The core process of `DEFAULT_SESSION_ID`
     /// * `deactivate_hiding` - Shared flag to control window hiding behavior
     /// * `dock_enabled` - Shared flag to control dock icon visibility
     /// * `app_state` - Persistent application state manager
@@ -88,24 +118,63 @@ impl AppData {
     ///
     /// A new `AppData` instance with empty sound entries and the provided state.
     pub fn new(
-        socket: CursorSocket,
+        core: CoreProcess,
         deactivate_hiding: Arc<Mutex<bool>>,
         dock_enabled: Arc<Mutex<bool>>,
         app_state: app_state::AppState,
     ) -> Self {
         AppData {
-            socket,
+            cores: HashMap::from([(DEFAULT_SESSION_ID.to_string(), core)]),
             sound_entries: Vec::new(),
             deactivate_hiding,
             dock_enabled,
             app_state,
             livekit_server_url: "".to_string(),
+            ipc_stats: IpcStats::new(),
+        }
+    }
+
+    /// Returns the socket of a session's core process.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session, `None` for `DEFAULT_SESSION_ID`
+    pub fn socket(
+        &mut self,
+        session_id: Option<&str>,
+    ) -> Result<&mut CursorSocket, std::io::Error> {
+        let session_id = session_id.unwrap_or(DEFAULT_SESSION_ID);
+        self.cores
+            .get_mut(session_id)
+            .map(|core| &mut core.socket)
+            .ok_or_else(|| unknown_session(session_id))
+    }
+
+    /// Sends a message to a session's core process, `None` for `DEFAULT_SESSION_ID`.
+    pub fn send_to_core(
+        &mut self,
+        session_id: Option<&str>,
+        message: Message,
+    ) -> Result<(), std::io::Error> {
+        self.socket(session_id)?.send_message(message)
+    }
+
+    /// Sends an app-wide setting to every core process, `message` makes the message for each.
+    pub fn broadcast_to_cores(&mut self, message: impl Fn() -> Message) {
+        for (session_id, core) in self.cores.iter_mut() {
+            if let Err(e) = core.socket.send_message(message()) {
+                log::error!("broadcast_to_cores: failed to send to {session_id}: {e:?}");
+            }
         }
     }
 }
 
-/// Monitors core process output and emits crash events.
-async fn show_stdout(mut receiver: Receiver<CommandEvent>, app_handle: AppHandle) {
+/// Monitors core process output and emits crash events with the session of the core process.
+async fn show_stdout(
+    mut receiver: Receiver<CommandEvent>,
+    app_handle: AppHandle,
+    session_id: String,
+) {
     while let Some(event) = receiver.recv().await {
         match event {
             CommandEvent::Stdout(line) => {
@@ -125,24 +194,33 @@ async fn show_stdout(mut receiver: Receiver<CommandEvent>, app_handle: AppHandle
             _ => {}
         }
     }
-    log::info!("show_stdout: Finished");
+    log::info!("show_stdout: Finished {session_id}");
 
     // Communicate to the frontend that the core process has crashed.
-    let res = app_handle.emit("core_process_crashed", ());
+    let res = app_handle.emit("core_process_crashed", session_id);
     if let Err(e) = res {
         log::error!("Failed to emit core_process_crashed: {e:?}");
     }
 }
 
 /// Spawns the core process sidecar with required arguments.
+///
+/// When `health_pings` is set the core sends its heartbeats every
+/// `HOPP_HEARTBEAT_INTERVAL_SECS`, to `HOPP_HEALTH_URL` when it is set.
+/// The core listens on `socket_name`.
 fn start_sidecar(
     app: &tauri::AppHandle,
     textures_path: &Path,
+    health_pings: bool,
+    socket_name: &str,
 ) -> (Receiver<CommandEvent>, CommandChild) {
-    log::info!("start_sidecar: Creating core process texture_path: {textures_path:?}");
+    log::info!("start_sidecar: Creating core process texture_path: {textures_path:?} socket_name: {socket_name}");
 
-    /* First we check if the process is already running and kill it. */
-    if !cfg!(debug_assertions) {
+    /*
+     * First we check if the process is already running and kill it, the
+     * processes of the other sessions are only started after the default one.
+     */
+    if !cfg!(debug_assertions) && socket_name == core_socket_name(DEFAULT_SESSION_ID) {
         let system = sysinfo::System::new_all();
         for process in system.processes().values() {
             if let Some(name) = process.name().to_str() {
@@ -154,7 +232,12 @@ fn start_sidecar(
         }
     }
 
-    let mut args = vec!["--textures-path", textures_path.to_str().unwrap()];
+    let mut args = vec![
+        "--textures-path",
+        textures_path.to_str().unwrap(),
+        "--socket-name",
+        socket_name,
+    ];
 
     let sentry_dsn = get_sentry_dsn();
     if !cfg!(debug_assertions) {
@@ -162,6 +245,23 @@ fn start_sidecar(
         args.push(&sentry_dsn);
     }
 
+    let heartbeat_interval = get_heartbeat_interval().to_string();
+    let health_url = get_health_url();
+    if health_pings {
+        args.push("--heartbeat-interval");
+        args.push(&heartbeat_interval);
+        if let Some(health_url) = &health_url {
+            args.push("--health-url");
+            args.push(health_url);
+        }
+    }
+
+    let min_client_version = get_min_client_version().map(|version| version.to_string());
+    if let Some(min_client_version) = &min_client_version {
+        args.push("--min-client-version");
+        args.push(min_client_version);
+    }
+
     let mut hopp_core_name = "hopp_core".to_string();
     if cfg!(debug_assertions) {
         hopp_core_name = format!("hopp_core{}", env::var("HOPP_SUFFIX").unwrap_or_default());
@@ -171,22 +271,50 @@ fn start_sidecar(
     (rx, child)
 }
 
+/// Returns the name of the socket of a session's core process.
+///
+/// The default session keeps the name of the single core process, so
+/// `CORE_SOCKET_NAME` still selects it, the other sessions add their id to it.
+fn core_socket_name(session_id: &str) -> String {
+    let socket_name = std::env::var("CORE_SOCKET_NAME").unwrap_or("core-socket".to_string());
+    if session_id == DEFAULT_SESSION_ID {
+        socket_name
+    } else {
+        format!("{socket_name}-{session_id}")
+    }
+}
+
+/// Returns `true` if `session_id` can be part of a socket name.
+fn valid_session_id(session_id: &str) -> bool {
+    !session_id.is_empty()
+        && session_id.len() <= 32
+        && session_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
 /// Creates a socket connection to communicate with the core process.
-fn create_core_process_socket() -> Result<CursorSocket, CoreProcessCreationError> {
+fn create_core_process_socket(socket_name: &str) -> Result<CursorSocket, CoreProcessCreationError> {
     let max_tries = 10;
     let mut tries = 0;
+    let socket_path = socket_lib::socket_path(socket_name).map_err(|e| {
+        log::error!("create_core_process_socket: Failed to get socket path: {e:?}");
+        CoreProcessCreationError::SocketCreationFailed
+    })?;
     loop {
-        let temp_dir = std::env::temp_dir();
-
-        let socket_name = std::env::var("CORE_SOCKET_NAME").unwrap_or("core-socket".to_string());
-        let socket_path = format!("{}/{socket_name}", temp_dir.display());
-        match CursorSocket::new(&socket_path) {
-            Ok(socket) => return Ok(socket),
-            Err(_) => {
-                log::debug!(
-                    "create_render_process_socket: Failed to create socket, retrying in 1 second"
-                );
-                std::thread::sleep(std::time::Duration::from_secs(1));
+        /* A socket left by a crashed core can't be used, wait for the new core to replace it. */
+        if socket_lib::is_stale_socket(&socket_path) {
+            log::debug!("create_core_process_socket: Stale socket, retrying in 1 second");
+            std::thread::sleep(std::time::Duration::from_secs(1));
+        } else {
+            match CursorSocket::new(&socket_path) {
+                Ok(socket) => return Ok(socket),
+                Err(_) => {
+                    log::debug!(
+                        "create_render_process_socket: Failed to create socket, retrying in 1 second"
+                    );
+                    std::thread::sleep(std::time::Duration::from_secs(1));
+                }
             }
         }
         tries += 1;
@@ -217,10 +345,28 @@ async fn send_ping(mut socket: CursorSocket) {
     log::info!("send_ping: Finished");
 }
 
-/// Creates and initializes the core process with socket communication.
+/// Creates and initializes the core process of a session with socket communication.
+///
+/// # Arguments
+///
+/// * `app` - The app handle the sidecar is spawned with
+/// * `health_pings` - The user's opt-in to the core's health heartbeats
+/// * `session_id` - The session the core process shares in, letters, digits,
+///   `-` and `_` only, it is part of the socket name
+///
+/// # Returns
+///
+/// The core process with its connected socket, pinged until the core process goes away.
 pub fn create_core_process(
     app: &tauri::AppHandle,
-) -> Result<(CoreProcess, CursorSocket), CoreProcessCreationError> {
+    health_pings: bool,
+    session_id: &str,
+) -> Result<CoreProcess, CoreProcessCreationError> {
+    if !valid_session_id(session_id) {
+        return Err(CoreProcessCreationError::InvalidSessionId(
+            session_id.to_string(),
+        ));
+    }
     let mut resources_dir = app
         .path()
         .resolve("resources", BaseDirectory::Resource)
@@ -241,17 +387,16 @@ pub fn create_core_process(
     }
     log::info!("create_core_process: resources_dir: {resources_dir:?}");
 
-    let (rx, core_process) = start_sidecar(app, &resources_dir);
-    tauri::async_runtime::spawn(show_stdout(rx, app.clone()));
-    let socket = create_core_process_socket()?;
+    let socket_name = core_socket_name(session_id);
+    let (rx, core_process) = start_sidecar(app, &resources_dir, health_pings, &socket_name);
+    tauri::async_runtime::spawn(show_stdout(rx, app.clone(), session_id.to_string()));
+    let socket = create_core_process_socket(&socket_name)?;
     let socket_clone = socket.duplicate().unwrap();
     tauri::async_runtime::spawn(send_ping(socket_clone));
-    Ok((
-        CoreProcess {
-            process: core_process,
-        },
+    Ok(CoreProcess {
+        process: core_process,
         socket,
-    ))
+    })
 }
 
 /// This is a workaround which we use in order to wake up the
@@ -268,6 +413,386 @@ pub fn ping_frontend(app: AppHandle) {
     }
 }
 
+/// Forwards a notification sent by the core process to the frontend.
+///
+/// Returns `true` if `message` was a notification, `false` if it is a
+/// reply to a request and needs to be handled by the caller.
+pub fn handle_core_notification(app: &AppHandle, message: &Message) -> bool {
+    match message {
+        Message::AvailableContentChanged => {
+            log::info!("handle_core_notification: available content changed");
+            if let Err(e) = app.emit("available_content_changed", ()) {
+                log::error!("Failed to emit available_content_changed: {e:?}");
+            }
+            true
+        }
+        Message::MediaControl(action) => {
+            log::info!("handle_core_notification: media control {action:?}");
+            if let Err(e) = app.emit("media_control", action) {
+                log::error!("Failed to emit media_control: {e:?}");
+            }
+            true
+        }
+        Message::FatalCaptureError(reason) => {
+            log::error!("handle_core_notification: fatal capture error: {reason}");
+            if let Err(e) = app.emit("fatal_capture_error", reason) {
+                log::error!("Failed to emit fatal_capture_error: {e:?}");
+            }
+            true
+        }
+        Message::PermissionRevoked(permission) => {
+            log::error!("handle_core_notification: {permission:?} permission revoked");
+            if let Err(e) = app.emit("permission_revoked", permission) {
+                log::error!("Failed to emit permission_revoked: {e:?}");
+            }
+            true
+        }
+        Message::CaptureStalled => {
+            log::warn!("handle_core_notification: capture stalled");
+            if let Err(e) = app.emit("capture_stalled", ()) {
+                log::error!("Failed to emit capture_stalled: {e:?}");
+            }
+            true
+        }
+        Message::ShareInterrupted(reason) => {
+            log::info!("handle_core_notification: share interrupted {reason:?}");
+            if let Err(e) = app.emit("share_interrupted", reason) {
+                log::error!("Failed to emit share_interrupted: {e:?}");
+            }
+            true
+        }
+        Message::ShareResumed => {
+            log::info!("handle_core_notification: share resumed");
+            if let Err(e) = app.emit("share_resumed", ()) {
+                log::error!("Failed to emit share_resumed: {e:?}");
+            }
+            true
+        }
+        Message::OverlayDegraded { reason } => {
+            log::warn!("handle_core_notification: overlay degraded: {reason}");
+            if let Err(e) = app.emit("overlay_degraded", reason) {
+                log::error!("Failed to emit overlay_degraded: {e:?}");
+            }
+            true
+        }
+        Message::OverlayRestored => {
+            log::info!("handle_core_notification: overlay restored");
+            if let Err(e) = app.emit("overlay_restored", ()) {
+                log::error!("Failed to emit overlay_restored: {e:?}");
+            }
+            true
+        }
+        Message::WaitingParticipants(participants) => {
+            log::info!(
+                "handle_core_notification: {} waiting participants",
+                participants.len()
+            );
+            if let Err(e) = app.emit("waiting_participants", participants) {
+                log::error!("Failed to emit waiting_participants: {e:?}");
+            }
+            true
+        }
+        Message::ParticipantQualities(qualities) => {
+            log::info!(
+                "handle_core_notification: {} participant qualities",
+                qualities.len()
+            );
+            if let Err(e) = app.emit("participant_qualities", qualities) {
+                log::error!("Failed to emit participant_qualities: {e:?}");
+            }
+            true
+        }
+        Message::MacroFinished { id } => {
+            log::info!("handle_core_notification: macro {id} finished");
+            if let Err(e) = app.emit("macro_finished", id) {
+                log::error!("Failed to emit macro_finished: {e:?}");
+            }
+            true
+        }
+        Message::MacroError(reason) => {
+            log::warn!("handle_core_notification: macro error: {reason}");
+            if let Err(e) = app.emit("macro_error", reason) {
+                log::error!("Failed to emit macro_error: {e:?}");
+            }
+            true
+        }
+        Message::CameraError(reason) => {
+            log::warn!("handle_core_notification: camera error: {reason}");
+            if let Err(e) = app.emit("camera_error", reason) {
+                log::error!("Failed to emit camera_error: {e:?}");
+            }
+            true
+        }
+        Message::MicError(reason) => {
+            log::warn!("handle_core_notification: microphone error: {reason}");
+            if let Err(e) = app.emit("mic_error", reason) {
+                log::error!("Failed to emit mic_error: {e:?}");
+            }
+            true
+        }
+        Message::SystemAudioError(reason) => {
+            log::warn!("handle_core_notification: system audio error: {reason}");
+            if let Err(e) = app.emit("system_audio_error", reason) {
+                log::error!("Failed to emit system_audio_error: {e:?}");
+            }
+            true
+        }
+        Message::NoiseSuppressionOverloaded => {
+            log::warn!("handle_core_notification: noise suppression overloaded");
+            if let Err(e) = app.emit("noise_suppression_overloaded", ()) {
+                log::error!("Failed to emit noise_suppression_overloaded: {e:?}");
+            }
+            true
+        }
+        Message::ControlRequests(requests) => {
+            log::info!(
+                "handle_core_notification: {} control requests",
+                requests.len()
+            );
+            if let Err(e) = app.emit("control_requests", requests) {
+                log::error!("Failed to emit control_requests: {e:?}");
+            }
+            true
+        }
+        Message::IncomingCallAnswered { room, accepted } => {
+            log::info!("handle_core_notification: incoming call answered: {accepted}");
+            /* The webview matches the room with the ringing call. */
+            if let Err(e) = app.emit("incoming_call_answered", (room, accepted)) {
+                log::error!("Failed to emit incoming_call_answered: {e:?}");
+            }
+            true
+        }
+        Message::AlreadySharing => {
+            log::warn!("handle_core_notification: already sharing");
+            if let Err(e) = app.emit("already_sharing", ()) {
+                log::error!("Failed to emit already_sharing: {e:?}");
+            }
+            true
+        }
+        Message::BulkPayload(payload) => {
+            let Some(data) = read_bulk_payload(app, payload) else {
+                return true;
+            };
+            match payload.kind {
+                BulkPayloadKind::PreviewFrame => {
+                    if let Some(preview) = app.try_state::<SharePreview>() {
+                        *preview.0.lock().unwrap() = Some(data);
+                    }
+                    if let Err(e) = app.emit("share_preview_updated", ()) {
+                        log::error!("Failed to emit share_preview_updated: {e:?}");
+                    }
+                }
+            }
+            true
+        }
+        Message::RoomTokenRejected(reason) => {
+            log::warn!("handle_core_notification: room token rejected {reason:?}");
+            if let Err(e) = app.emit("room_token_rejected", reason) {
+                log::error!("Failed to emit room_token_rejected: {e:?}");
+            }
+            true
+        }
+        Message::NetworkPreflightResult(report) => {
+            log::info!(
+                "handle_core_notification: network preflight result {:?}",
+                report.media_path
+            );
+            if let Err(e) = app.emit("network_preflight_result", report) {
+                log::error!("Failed to emit network_preflight_result: {e:?}");
+            }
+            true
+        }
+        Message::NeedLivekitUrl => {
+            log::info!("handle_core_notification: core needs the livekit url");
+            /* The caller holds the AppData lock, the url is sent once it is released. */
+            let app = app.clone();
+            std::thread::spawn(move || resend_livekit_url(&app));
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Sends the LiveKit server URL to the core processes, one of them asked
+/// for it, e.g. after it was restarted.
+///
+/// Before the frontend fetched the URL there is nothing to send, the
+/// frontend sends it when it arrives.
+fn resend_livekit_url(app: &AppHandle) {
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if data.livekit_server_url.is_empty() {
+        log::warn!("resend_livekit_url: the url isn't known yet");
+        return;
+    }
+    let url = data.livekit_server_url.clone();
+    data.broadcast_to_cores(|| Message::LivekitServerUrl(url.clone()));
+}
+
+/// Receives the reply to a request sent to the core process.
+///
+/// The core process can push notifications at any time, the ones that
+/// arrive before the reply are forwarded to the frontend.
+pub fn receive_core_reply(
+    app: &AppHandle,
+    socket: &mut CursorSocket,
+) -> Result<Message, std::io::Error> {
+    loop {
+        let message = socket.receive_message()?;
+        if !handle_core_notification(app, &message) {
+            return Ok(message);
+        }
+    }
+}
+
+/// The core's shared memory region for bulk payloads, `None` until it is
+/// negotiated or when the core couldn't create it.
+///
+/// Kept apart from `AppData` because payloads are announced while requests
+/// hold the `AppData` lock.
+#[derive(Debug, Default)]
+pub struct CoreSharedMemory(pub Mutex<Option<SharedRegion>>);
+
+/// The latest JPEG frame of the sharer's preview, `None` until the core sends
+/// one, see `Message::SetSharePreview`.
+///
+/// The webview fetches it when `share_preview_updated` is emitted, so the
+/// frames don't go through the event's JSON.
+#[derive(Debug, Default)]
+pub struct SharePreview(pub Mutex<Option<Vec<u8>>>);
+
+/// Asks the core process for its shared memory region and maps it.
+///
+/// Only the core process of `DEFAULT_SESSION_ID` gets a region, the bulk
+/// payloads of the other sessions stay on their sockets. Without a region
+/// the bulk payloads stay on the socket, so failures are only logged.
+pub fn negotiate_shared_memory(app: &AppHandle) {
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let info = match request_core(
+        app,
+        &mut data,
+        None,
+        "open_shared_memory",
+        lock_wait,
+        Message::OpenSharedMemory,
+    ) {
+        Ok(Message::SharedMemoryReady(info)) => info,
+        Ok(Message::SharedMemoryUnavailable(reason)) => {
+            log::warn!("negotiate_shared_memory: core has no shared memory: {reason}");
+            return;
+        }
+        Ok(message) => {
+            log::error!(
+                "negotiate_shared_memory: unexpected message {}",
+                message.name()
+            );
+            return;
+        }
+        Err(e) => {
+            log::error!("negotiate_shared_memory: request failed: {e:?}");
+            return;
+        }
+    };
+    drop(data);
+
+    match SharedRegion::open(&info) {
+        Ok(region) => {
+            log::info!("negotiate_shared_memory: mapped {info:?}");
+            *app.state::<CoreSharedMemory>().0.lock().unwrap() = Some(region);
+        }
+        Err(e) => log::error!("negotiate_shared_memory: failed to open {info:?}: {e:?}"),
+    }
+}
+
+/// Copies an announced payload out of the shared memory region, freeing its slot.
+fn read_bulk_payload(app: &AppHandle, payload: &BulkPayload) -> Option<Vec<u8>> {
+    let shared_memory = app.try_state::<CoreSharedMemory>()?;
+    let mut region = shared_memory.0.lock().unwrap();
+    let region = match region.as_mut() {
+        Some(region) => region,
+        None => {
+            log::error!("read_bulk_payload: payload {payload:?} without a region");
+            return None;
+        }
+    };
+    match region.read(payload) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            log::error!("read_bulk_payload: {e:?}");
+            None
+        }
+    }
+}
+
+/// Sends a request to the core process and receives its reply, recording
+/// the latency in `AppData::ipc_stats`.
+///
+/// # Arguments
+///
+/// * `app` - Handle the notifications received before the reply are forwarded with
+/// * `data` - The locked app data holding the core sockets
+/// * `session_id` - The session whose core process is asked, `None` for `DEFAULT_SESSION_ID`
+/// * `request` - Name the latency is recorded under
+/// * `lock_wait` - How long the caller waited for the `AppData` lock
+/// * `message` - The request
+///
+/// # Returns
+///
+/// The reply, or the error of sending the request or receiving the reply.
+pub fn request_core(
+    app: &AppHandle,
+    data: &mut AppData,
+    session_id: Option<&str>,
+    request: &'static str,
+    lock_wait: Duration,
+    message: Message,
+) -> Result<Message, std::io::Error> {
+    let started = Instant::now();
+    let res = data.socket(session_id).and_then(|socket| {
+        socket
+            .send_message(message)
+            .and_then(|()| receive_core_reply(app, socket))
+    });
+    let round_trip = started.elapsed();
+    data.ipc_stats
+        .record(request, lock_wait, round_trip, res.is_ok());
+    if round_trip + lock_wait >= SLOW_REQUEST {
+        log::warn!(
+            "request_core: {request} took {round_trip:?} after waiting {lock_wait:?} for the lock"
+        );
+    }
+    res
+}
+
+/// Forwards the notifications the core process sends while no request is in flight.
+///
+/// Requests hold the `AppData` lock until they get their reply, so only
+/// notifications can be read here.
+pub fn listen_core_notifications(app: AppHandle) {
+    loop {
+        std::thread::sleep(Duration::from_millis(CORE_NOTIFICATIONS_POLL_MS));
+        let data = app.state::<Mutex<AppData>>();
+        let mut data = data.lock().unwrap();
+        for (session_id, core) in data.cores.iter_mut() {
+            while let Ok(message) = core
+                .socket
+                .receive_message_with_timeout(Duration::from_millis(
+                    CORE_NOTIFICATIONS_READ_TIMEOUT_MS,
+                ))
+            {
+                if !handle_core_notification(&app, &message) {
+                    log::warn!(
+                        "listen_core_notifications: unexpected message from {session_id}: {message:?}"
+                    );
+                }
+            }
+        }
+    }
+}
+
 /// Returns the platform-specific log file path.
 pub fn get_log_path() -> Option<PathBuf> {
     #[cfg(target_os = "macos")]
@@ -432,6 +957,9 @@ pub fn setup_tray_icon(
                 if event.id.as_ref() == "quit" {
                     log::info!("Quit menu item clicked");
                     app.exit(0);
+                } else if event.id.as_ref() == scheduler::CANCEL_SCHEDULED_SHARE_MENU_ID {
+                    log::info!("Cancel scheduled share menu item clicked");
+                    scheduler::set_scheduled_share(app, None);
                 }
             })
             .build(app)?;
@@ -502,3 +1030,27 @@ pub fn get_sentry_dsn() -> String {
         .unwrap_or_default()
         .to_string()
 }
+
+/// Seconds between the core's health heartbeats, self-hosted deployments
+/// can change it with `HOPP_HEARTBEAT_INTERVAL_SECS`.
+pub fn get_heartbeat_interval() -> u64 {
+    std::env::var("HOPP_HEARTBEAT_INTERVAL_SECS")
+        .ok()
+        .and_then(|interval| interval.parse().ok())
+        .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECS)
+}
+
+/// Oldest protocol version of the controllers whose input the core injects,
+/// deployments can require newer clients with `HOPP_MIN_CLIENT_VERSION`.
+pub fn get_min_client_version() -> Option<u32> {
+    std::env::var("HOPP_MIN_CLIENT_VERSION")
+        .ok()
+        .and_then(|version| version.parse().ok())
+}
+
+/// URL the core posts its health heartbeats to, they go to Sentry when it isn't set.
+pub fn get_health_url() -> Option<String> {
+    std::env::var("HOPP_HEALTH_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+}
@@ -1,9 +1,16 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use hopp::ipc_stats::IpcRequestStats;
 use hopp::sounds::{self, SoundConfig};
+use hopp::support_bundle::{SupportBundle, SystemInfo};
 use log::LevelFilter;
-use socket_lib::{CaptureContent, Content, Extent, Message, ScreenShareMessage};
+use socket_lib::{
+    AdmissionPolicy, AspectPolicy, AudioProcessing, CameraDevice, CaptureContent, Content,
+    DisplayLabel, Extent, InputInjectionStatus, InputMacro, KeyboardArbitration,
+    KeyboardLayoutKind, Message, MicrophoneDevice, OverlayTheme, ScreenShareMessage, SessionState,
+    StreamParameters, ThumbnailPrivacy, UncleanSession,
+};
 use tauri::Manager;
 use tauri::{
     menu::{MenuBuilder, MenuItemBuilder},
@@ -15,11 +22,15 @@ use tauri_plugin_autostart::{MacosLauncher, ManagerExt};
 use tauri_plugin_log::{Target, TargetKind};
 
 use hopp::{
-    app_state::AppState, create_core_process, get_log_level, get_log_path, get_sentry_dsn,
-    get_token_filename, permissions, ping_frontend, setup_start_on_launch, setup_tray_icon,
-    AppData,
+    app_state::AppState,
+    create_core_process, get_log_level, get_log_path, get_sentry_dsn, get_token_filename,
+    listen_core_notifications, negotiate_shared_memory, permissions, ping_frontend, request_core,
+    scheduler::{self, ScheduleMenuItem, ScheduledShare},
+    setup_start_on_launch, setup_tray_icon, AppData, CoreProcessCreationError, CoreSharedMemory,
+    SharePreview, DEFAULT_SESSION_ID,
 };
 use std::sync::Mutex;
+use std::time::Instant;
 use std::{env, sync::Arc};
 
 #[cfg(target_os = "macos")]
@@ -30,84 +41,178 @@ use tauri::PhysicalPosition;
 
 //testab
 
+/// Sends the overlay theme before a share, the core process may have been
+/// restarted since the last one.
+fn send_overlay_theme(data: &mut AppData, session_id: Option<&str>) {
+    let theme = data.app_state.overlay_theme();
+    if let Err(e) = data.send_to_core(session_id, Message::SetOverlayTheme(theme)) {
+        log::error!("send_overlay_theme: failed to send message: {e:?}");
+    }
+}
+
+/// Sends the apps excluded from the capture before the streams are created,
+/// the core process may have been restarted since the last one.
+fn send_capture_exclusions(data: &mut AppData, session_id: Option<&str>) {
+    let exclusions = data.app_state.capture_exclusions();
+    if let Err(e) = data.send_to_core(session_id, Message::SetCaptureExclusions(exclusions)) {
+        log::error!("send_capture_exclusions: failed to send message: {e:?}");
+    }
+}
+
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn screenshare(
     app: tauri::AppHandle,
     content: Content,
     token: String,
     resolution: Extent,
-) -> bool {
-    log::info!("screenshare: content: {content:?}, token: {token}, resolution: {resolution:?}");
-
+    view_only: bool,
+    aspect_policy: AspectPolicy,
+    framerate: Option<u32>,
+    session_id: Option<String>,
+) -> Option<StreamParameters> {
+    log::info!(
+        "screenshare: content: {content:?}, token: {token}, resolution: {resolution:?}, view_only: {view_only}, aspect_policy: {aspect_policy:?}, framerate: {framerate:?}"
+    );
+
+    let lock_started = Instant::now();
     let data = app.state::<Mutex<AppData>>();
     let mut data = data.lock().unwrap();
-    let res = data
-        .socket
-        .send_message(Message::StartScreenShare(ScreenShareMessage {
+    let lock_wait = lock_started.elapsed();
+    send_overlay_theme(&mut data, session_id.as_deref());
+    send_capture_exclusions(&mut data, session_id.as_deref());
+    // TODO: Add a timeout
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "screenshare",
+        lock_wait,
+        Message::StartScreenShare(ScreenShareMessage {
             content,
             token: token.clone(),
             resolution,
-        }));
-    if let Err(e) = res {
-        log::error!("screenshare: failed to send message: {e:?}");
-        return false;
-    }
-
-    // TODO: Add a timeout
-    let res = data.socket.receive_message();
+            view_only,
+            aspect_policy,
+            framerate,
+        }),
+    );
     if let Err(e) = res {
-        log::error!("screenshare: failed to receive message: {e:?}");
-        return false;
+        log::error!("screenshare: request failed: {e:?}");
+        return None;
     }
     match res.unwrap() {
-        Message::StartScreenShareResult(result) => {
-            if !result {
-                log::error!("screenshare: failed to start screenshare");
-                return false;
-            }
+        Message::StartScreenShareResult(Some(parameters)) => {
+            log::info!("screenshare: started: {parameters:?}");
+            Some(parameters)
+        }
+        Message::StartScreenShareResult(None) => {
+            log::error!("screenshare: failed to start screenshare");
+            None
         }
         _ => {
             log::error!("screenshare: unexpected message");
+            None
         }
     }
+}
+
+#[tauri::command]
+async fn change_resolution(
+    app: tauri::AppHandle,
+    resolution: Extent,
+    session_id: Option<String>,
+) -> Option<StreamParameters> {
+    log::info!("change_resolution: resolution: {resolution:?}");
 
-    true
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "change_resolution",
+        lock_wait,
+        Message::ChangeResolution(resolution),
+    );
+    match res {
+        Ok(Message::ChangeResolutionResult(parameters)) => {
+            log::info!("change_resolution: result: {parameters:?}");
+            parameters
+        }
+        Ok(message) => {
+            log::error!("change_resolution: unexpected message {}", message.name());
+            None
+        }
+        Err(e) => {
+            log::error!("change_resolution: request failed: {e:?}");
+            None
+        }
+    }
 }
 
 #[tauri::command]
-async fn stop_sharing(app: tauri::AppHandle) {
+async fn stop_sharing(app: tauri::AppHandle, session_id: Option<String>) {
     log::info!("stop_sharing");
     let data = app.state::<Mutex<AppData>>();
     let mut data = data.lock().unwrap();
-    let res = data.socket.send_message(Message::StopScreenshare);
+    let res = data.send_to_core(session_id.as_deref(), Message::StopScreenshare);
     if let Err(e) = res {
         log::error!("screenshare: failed to send message: {e:?}");
     }
 }
 
 #[tauri::command]
-async fn get_available_content(app: tauri::AppHandle) -> Vec<CaptureContent> {
-    log::info!("get_available_content");
+fn identify_displays(
+    app: tauri::AppHandle,
+    displays: Vec<DisplayLabel>,
+    session_id: Option<String>,
+) {
+    log::info!("identify_displays: {displays:?}");
     let data = app.state::<Mutex<AppData>>();
     let mut data = data.lock().unwrap();
-    let res = data.socket.send_message(Message::GetAvailableContent);
+    let res = data.send_to_core(session_id.as_deref(), Message::IdentifyDisplays(displays));
     if let Err(e) = res {
-        log::error!("get_available_content: failed to send message: {e:?}");
-        return vec![];
+        log::error!("identify_displays: failed to send message: {e:?}");
     }
-    let res = data.socket.receive_message();
+}
+
+#[tauri::command]
+async fn get_available_content(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+) -> Vec<CaptureContent> {
+    log::info!("get_available_content");
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    /* Sent every time, the core process may have been restarted since the last one. */
+    let privacy = data.app_state.thumbnail_privacy();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::SetThumbnailPrivacy(privacy))
+    {
+        log::error!("get_available_content: failed to send thumbnail privacy: {e:?}");
+    }
+    /* The content picker warms up a stream, it uses the exclusions. */
+    send_capture_exclusions(&mut data, session_id.as_deref());
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "get_available_content",
+        lock_wait,
+        Message::GetAvailableContent,
+    );
     if let Err(e) = res {
-        log::error!("get_available_content: failed to receive message: {e:?}");
+        log::error!("get_available_content: request failed: {e:?}");
         return vec![];
     }
     match res.unwrap() {
         Message::AvailableContent(content) => {
             for c in &content.content {
-                log::info!(
-                    "get_available_content: possible content {}, content {:?}",
-                    c.title,
-                    c.content
-                );
+                log::info!("get_available_content: possible content {:?}", c.content);
             }
             content.content
         }
@@ -115,6 +220,34 @@ async fn get_available_content(app: tauri::AppHandle) -> Vec<CaptureContent> {
     }
 }
 
+#[tauri::command]
+async fn get_core_breadcrumbs(app: tauri::AppHandle, session_id: Option<String>) -> Vec<String> {
+    log::info!("get_core_breadcrumbs");
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "get_core_breadcrumbs",
+        lock_wait,
+        Message::GetBreadcrumbs,
+    );
+    match res {
+        Ok(Message::Breadcrumbs(breadcrumbs)) => breadcrumbs,
+        Ok(_) => {
+            log::error!("get_core_breadcrumbs: unexpected message");
+            vec![]
+        }
+        Err(e) => {
+            log::error!("get_core_breadcrumbs: request failed: {e:?}");
+            vec![]
+        }
+    }
+}
+
 #[tauri::command]
 fn play_sound(app: tauri::AppHandle, sound_name: String) {
     log::info!("play_sound: {sound_name}");
@@ -198,10 +331,10 @@ fn stop_sound(app: tauri::AppHandle, sound_name: String) {
 }
 
 #[tauri::command]
-fn reset_core_process(app: tauri::AppHandle) {
+fn reset_core_process(app: tauri::AppHandle, session_id: Option<String>) {
     let data = app.state::<Mutex<AppData>>();
     let mut data = data.lock().unwrap();
-    let res = data.socket.send_message(Message::Reset);
+    let res = data.send_to_core(session_id.as_deref(), Message::Reset);
     if let Err(e) = res {
         log::error!("reset_core_process: failed to send message: {e:?}");
     }
@@ -286,6 +419,63 @@ fn get_logs(_app: tauri::AppHandle) -> String {
     }
 }
 
+#[tauri::command]
+async fn export_support_bundle(
+    app: tauri::AppHandle,
+    path: String,
+    session_id: Option<String>,
+) -> bool {
+    log::info!("export_support_bundle: {path}");
+    let diagnostics = {
+        let lock_started = Instant::now();
+        let data = app.state::<Mutex<AppData>>();
+        let mut data = data.lock().unwrap();
+        let lock_wait = lock_started.elapsed();
+        match request_core(
+            &app,
+            &mut data,
+            session_id.as_deref(),
+            "get_diagnostics",
+            lock_wait,
+            Message::GetDiagnostics,
+        ) {
+            Ok(Message::Diagnostics(diagnostics)) => Some(diagnostics),
+            Ok(_) => {
+                log::error!("export_support_bundle: unexpected message");
+                None
+            }
+            Err(e) => {
+                log::error!("export_support_bundle: request failed: {e:?}");
+                None
+            }
+        }
+    };
+
+    let logs = get_log_path().and_then(|path| match std::fs::read(&path) {
+        Ok(logs) => Some(logs),
+        Err(e) => {
+            log::warn!("export_support_bundle: failed to read logs from {path:?}: {e:?}");
+            None
+        }
+    });
+
+    let bundle = SupportBundle {
+        system: SystemInfo::new(
+            app.package_info().version.to_string(),
+            diagnostics.as_ref().map(|d| d.core_version.clone()),
+        ),
+        logs,
+        diagnostics,
+    };
+    match bundle.write(std::path::Path::new(&path)) {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("export_support_bundle: {e}");
+            false
+        }
+    }
+}
+
 #[tauri::command]
 fn set_deactivate_hiding(app: tauri::AppHandle, deactivate: bool) {
     log::debug!("set_deactivate_hiding: {deactivate}");
@@ -296,18 +486,620 @@ fn set_deactivate_hiding(app: tauri::AppHandle, deactivate: bool) {
 }
 
 #[tauri::command]
-fn set_controller_cursor(app: tauri::AppHandle, enabled: bool) {
+fn set_controller_cursor(app: tauri::AppHandle, enabled: bool, session_id: Option<String>) {
     log::info!("set_controller_cursor: {enabled}");
     let data = app.state::<Mutex<AppData>>();
     let mut data = data.lock().unwrap();
-    let res = data
-        .socket
-        .send_message(Message::ControllerCursorEnabled(enabled));
+    let res = data.send_to_core(
+        session_id.as_deref(),
+        Message::ControllerCursorEnabled(enabled),
+    );
     if let Err(e) = res {
         log::error!("set_controller_cursor: failed to send message: {e:?}");
     }
 }
 
+#[tauri::command]
+fn set_admission_policy(
+    app: tauri::AppHandle,
+    max_participants: Option<u32>,
+    waiting_room: bool,
+    session_id: Option<String>,
+) {
+    log::info!("set_admission_policy: max: {max_participants:?} waiting room: {waiting_room}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(
+        session_id.as_deref(),
+        Message::SetAdmissionPolicy(AdmissionPolicy {
+            max_participants,
+            waiting_room,
+        }),
+    );
+    if let Err(e) = res {
+        log::error!("set_admission_policy: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn set_keyboard_arbitration(
+    app: tauri::AppHandle,
+    policy: KeyboardArbitration,
+    session_id: Option<String>,
+) {
+    log::info!("set_keyboard_arbitration: {policy:?}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(
+        session_id.as_deref(),
+        Message::SetKeyboardArbitration(policy),
+    );
+    if let Err(e) = res {
+        log::error!("set_keyboard_arbitration: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn set_framerate(app: tauri::AppHandle, framerate: u32, session_id: Option<String>) {
+    log::info!("set_framerate: {framerate}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(session_id.as_deref(), Message::SetFramerate(framerate));
+    if let Err(e) = res {
+        log::error!("set_framerate: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn set_clipboard_sharing(app: tauri::AppHandle, enabled: bool, session_id: Option<String>) {
+    log::info!("set_clipboard_sharing: {enabled}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(session_id.as_deref(), Message::SetClipboardSharing(enabled));
+    if let Err(e) = res {
+        log::error!("set_clipboard_sharing: failed to send message: {e:?}");
+    }
+}
+
+/* The preview frames come through the shared memory, only the default core process has it. */
+#[tauri::command]
+fn set_share_preview(app: tauri::AppHandle, enabled: bool) {
+    log::info!("set_share_preview: {enabled}");
+    if !enabled {
+        *app.state::<SharePreview>().0.lock().unwrap() = None;
+    }
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(None, Message::SetSharePreview(enabled));
+    if let Err(e) = res {
+        log::error!("set_share_preview: failed to send message: {e:?}");
+    }
+}
+
+/* Returns the latest preview frame as raw JPEG bytes, empty before the first frame. */
+#[tauri::command]
+fn get_share_preview(app: tauri::AppHandle) -> tauri::ipc::Response {
+    let frame = app.state::<SharePreview>().0.lock().unwrap().clone();
+    tauri::ipc::Response::new(frame.unwrap_or_default())
+}
+
+#[tauri::command]
+fn set_keyboard_app_allowlist(
+    app: tauri::AppHandle,
+    apps: Vec<String>,
+    session_id: Option<String>,
+) {
+    log::info!("set_keyboard_app_allowlist: {apps:?}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(
+        session_id.as_deref(),
+        Message::SetKeyboardAppAllowlist(apps),
+    );
+    if let Err(e) = res {
+        log::error!("set_keyboard_app_allowlist: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn set_keyboard_layout(
+    app: tauri::AppHandle,
+    layout: Option<KeyboardLayoutKind>,
+    session_id: Option<String>,
+) {
+    log::info!("set_keyboard_layout: {layout:?}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(session_id.as_deref(), Message::SetKeyboardLayout(layout));
+    if let Err(e) = res {
+        log::error!("set_keyboard_layout: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn run_network_preflight(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("run_network_preflight");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    /* The core answers with NetworkPreflightResult once the checks finished. */
+    let res = data.send_to_core(session_id.as_deref(), Message::RunNetworkPreflight);
+    if let Err(e) = res {
+        log::error!("run_network_preflight: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn admit_participant(app: tauri::AppHandle, sid: String, session_id: Option<String>) {
+    log::info!("admit_participant: {sid}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(session_id.as_deref(), Message::AdmitParticipant(sid));
+    if let Err(e) = res {
+        log::error!("admit_participant: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn kick_participant(app: tauri::AppHandle, sid: String, session_id: Option<String>) {
+    log::info!("kick_participant: {sid}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(session_id.as_deref(), Message::KickParticipant { sid });
+    if let Err(e) = res {
+        log::error!("kick_participant: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn ban_identity(app: tauri::AppHandle, identity: String, session_id: Option<String>) {
+    log::info!("ban_identity: {identity}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(session_id.as_deref(), Message::BanIdentity { identity });
+    if let Err(e) = res {
+        log::error!("ban_identity: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn set_input_authorized(
+    app: tauri::AppHandle,
+    sid: String,
+    authorized: bool,
+    session_id: Option<String>,
+) {
+    log::info!("set_input_authorized: {sid} {authorized}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(
+        session_id.as_deref(),
+        Message::SetInputAuthorized { sid, authorized },
+    );
+    if let Err(e) = res {
+        log::error!("set_input_authorized: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn set_cursor_visible(
+    app: tauri::AppHandle,
+    sid: String,
+    visible: bool,
+    session_id: Option<String>,
+) {
+    log::info!("set_cursor_visible: {sid} {visible}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(
+        session_id.as_deref(),
+        Message::SetCursorVisible { sid, visible },
+    );
+    if let Err(e) = res {
+        log::error!("set_cursor_visible: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn start_macro_recording(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("start_macro_recording");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::StartMacroRecording) {
+        log::error!("start_macro_recording: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+async fn stop_macro_recording(
+    app: tauri::AppHandle,
+    name: String,
+    session_id: Option<String>,
+) -> Result<InputMacro, String> {
+    log::info!("stop_macro_recording: {name}");
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "stop_macro_recording",
+        lock_wait,
+        Message::StopMacroRecording(name),
+    );
+    match res {
+        Ok(Message::MacroRecorded(recorded)) => Ok(recorded),
+        Ok(Message::MacroRecordingFailed(reason)) => Err(reason),
+        Ok(message) => {
+            log::error!(
+                "stop_macro_recording: unexpected message {}",
+                message.name()
+            );
+            Err("Unexpected reply from the core".to_string())
+        }
+        Err(e) => {
+            log::error!("stop_macro_recording: request failed: {e:?}");
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_input_injection_status(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+) -> Result<InputInjectionStatus, String> {
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "get_input_injection_status",
+        lock_wait,
+        Message::GetInputInjectionStatus,
+    );
+    match res {
+        Ok(Message::InputInjectionStatus(status)) => Ok(status),
+        Ok(message) => {
+            log::error!(
+                "get_input_injection_status: unexpected message {}",
+                message.name()
+            );
+            Err("Unexpected reply from the core".to_string())
+        }
+        Err(e) => {
+            log::error!("get_input_injection_status: request failed: {e:?}");
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_session_state(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+) -> Result<SessionState, String> {
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "get_session_state",
+        lock_wait,
+        Message::GetSessionState,
+    );
+    match res {
+        Ok(Message::SessionState(state)) => Ok(state),
+        Ok(message) => {
+            log::error!("get_session_state: unexpected message {}", message.name());
+            Err("Unexpected reply from the core".to_string())
+        }
+        Err(e) => {
+            log::error!("get_session_state: request failed: {e:?}");
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_cameras(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+) -> Result<Vec<CameraDevice>, String> {
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "get_cameras",
+        lock_wait,
+        Message::GetCameras,
+    );
+    match res {
+        Ok(Message::Cameras(cameras)) => Ok(cameras),
+        Ok(message) => {
+            log::error!("get_cameras: unexpected message {}", message.name());
+            Err("Unexpected reply from the core".to_string())
+        }
+        Err(e) => {
+            log::error!("get_cameras: request failed: {e:?}");
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+fn enable_camera(app: tauri::AppHandle, device_id: Option<String>, session_id: Option<String>) {
+    log::info!("enable_camera: {device_id:?}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    /* The core answers with CameraError when the camera can't be published. */
+    let res = data.send_to_core(session_id.as_deref(), Message::EnableCamera { device_id });
+    if let Err(e) = res {
+        log::error!("enable_camera: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn disable_camera(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("disable_camera");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::DisableCamera) {
+        log::error!("disable_camera: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+async fn get_microphones(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+) -> Result<Vec<MicrophoneDevice>, String> {
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "get_microphones",
+        lock_wait,
+        Message::GetMicrophones,
+    );
+    match res {
+        Ok(Message::Microphones(microphones)) => Ok(microphones),
+        Ok(message) => {
+            log::error!("get_microphones: unexpected message {}", message.name());
+            Err("Unexpected reply from the core".to_string())
+        }
+        Err(e) => {
+            log::error!("get_microphones: request failed: {e:?}");
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+fn enable_mic(
+    app: tauri::AppHandle,
+    device_id: Option<String>,
+    processing: AudioProcessing,
+    session_id: Option<String>,
+) {
+    log::info!("enable_mic: {device_id:?} {processing:?}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    /* The core answers with MicError when the microphone can't be published. */
+    let res = data.send_to_core(
+        session_id.as_deref(),
+        Message::EnableMic {
+            device_id,
+            processing,
+        },
+    );
+    if let Err(e) = res {
+        log::error!("enable_mic: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn disable_mic(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("disable_mic");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::DisableMic) {
+        log::error!("disable_mic: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+async fn get_system_audio_support(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+) -> Result<bool, String> {
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "get_system_audio_support",
+        lock_wait,
+        Message::GetSystemAudioSupport,
+    );
+    match res {
+        Ok(Message::SystemAudioSupport(supported)) => Ok(supported),
+        Ok(message) => {
+            log::error!(
+                "get_system_audio_support: unexpected message {}",
+                message.name()
+            );
+            Err("Unexpected reply from the core".to_string())
+        }
+        Err(e) => {
+            log::error!("get_system_audio_support: request failed: {e:?}");
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+fn enable_system_audio(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("enable_system_audio");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    /* The core answers with SystemAudioError when the system audio can't be published. */
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::EnableSystemAudio) {
+        log::error!("enable_system_audio: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn disable_system_audio(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("disable_system_audio");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::DisableSystemAudio) {
+        log::error!("disable_system_audio: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn set_noise_suppression(app: tauri::AppHandle, enabled: bool, session_id: Option<String>) {
+    log::info!("set_noise_suppression: {enabled}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let res = data.send_to_core(session_id.as_deref(), Message::SetNoiseSuppression(enabled));
+    if let Err(e) = res {
+        log::error!("set_noise_suppression: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn grant_next_control(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("grant_next_control");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::GrantNextControl) {
+        log::error!("grant_next_control: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn restart_overlay(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("restart_overlay");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::RestartOverlay) {
+        log::error!("restart_overlay: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+async fn get_unclean_session(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+) -> Result<Option<UncleanSession>, String> {
+    let lock_started = Instant::now();
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let lock_wait = lock_started.elapsed();
+    let res = request_core(
+        &app,
+        &mut data,
+        session_id.as_deref(),
+        "get_unclean_session",
+        lock_wait,
+        Message::GetUncleanSession,
+    );
+    match res {
+        Ok(Message::UncleanSession(session)) => Ok(session),
+        Ok(message) => {
+            log::error!("get_unclean_session: unexpected message {}", message.name());
+            Err("Unexpected reply from the core".to_string())
+        }
+        Err(e) => {
+            log::error!("get_unclean_session: request failed: {e:?}");
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+fn dismiss_unclean_session(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("dismiss_unclean_session");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::DismissUncleanSession) {
+        log::error!("dismiss_unclean_session: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn show_incoming_call(
+    app: tauri::AppHandle,
+    caller: String,
+    room: String,
+    session_id: Option<String>,
+) {
+    log::info!("show_incoming_call: room: {room}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(
+        session_id.as_deref(),
+        Message::IncomingCall { caller, room },
+    ) {
+        log::error!("show_incoming_call: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn dismiss_incoming_call(app: tauri::AppHandle, session_id: Option<String>) {
+    log::info!("dismiss_incoming_call");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::DismissIncomingCall) {
+        log::error!("dismiss_incoming_call: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn load_macro(app: tauri::AppHandle, input_macro: InputMacro, session_id: Option<String>) {
+    log::info!(
+        "load_macro: {} with {} steps",
+        input_macro.id,
+        input_macro.steps.len()
+    );
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::LoadMacro(input_macro)) {
+        log::error!("load_macro: failed to send message: {e:?}");
+    }
+}
+
+#[tauri::command]
+fn play_macro(app: tauri::AppHandle, id: String, session_id: Option<String>) {
+    log::info!("play_macro: {id}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if let Err(e) = data.send_to_core(session_id.as_deref(), Message::PlayMacro { id }) {
+        log::error!("play_macro: failed to send message: {e:?}");
+    }
+}
+
 #[tauri::command]
 fn open_accessibility_settings(_app: tauri::AppHandle) {
     log::info!("open_accessibility_settings");
@@ -419,6 +1211,109 @@ fn set_last_used_mic(app: tauri::AppHandle, mic: String) {
     data.app_state.set_last_used_mic(mic);
 }
 
+#[tauri::command]
+fn get_thumbnail_privacy(app: tauri::AppHandle) -> ThumbnailPrivacy {
+    log::info!("get_thumbnail_privacy");
+    let data = app.state::<Mutex<AppData>>();
+    let data = data.lock().unwrap();
+    data.app_state.thumbnail_privacy()
+}
+
+#[tauri::command]
+fn set_thumbnail_privacy(app: tauri::AppHandle, privacy: ThumbnailPrivacy) {
+    log::info!("set_thumbnail_privacy: {privacy:?}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    data.app_state.set_thumbnail_privacy(privacy);
+}
+
+#[tauri::command]
+fn get_capture_exclusions(app: tauri::AppHandle) -> Vec<String> {
+    log::info!("get_capture_exclusions");
+    let data = app.state::<Mutex<AppData>>();
+    let data = data.lock().unwrap();
+    data.app_state.capture_exclusions()
+}
+
+#[tauri::command]
+fn set_capture_exclusions(app: tauri::AppHandle, exclusions: Vec<String>) {
+    log::info!("set_capture_exclusions: {exclusions:?}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    data.app_state.set_capture_exclusions(exclusions.clone());
+    data.broadcast_to_cores(|| Message::SetCaptureExclusions(exclusions.clone()));
+}
+
+#[tauri::command]
+fn get_overlay_theme(app: tauri::AppHandle) -> OverlayTheme {
+    log::info!("get_overlay_theme");
+    let data = app.state::<Mutex<AppData>>();
+    let data = data.lock().unwrap();
+    data.app_state.overlay_theme()
+}
+
+#[tauri::command]
+fn set_overlay_theme(app: tauri::AppHandle, theme: OverlayTheme) {
+    log::info!("set_overlay_theme: {theme:?}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    data.app_state.set_overlay_theme(theme);
+    /* Applies to the controllers joining the active shares too. */
+    data.broadcast_to_cores(|| Message::SetOverlayTheme(theme));
+}
+
+#[tauri::command]
+fn schedule_screenshare(app: tauri::AppHandle, share: ScheduledShare) {
+    log::info!("schedule_screenshare: {share:?}");
+    scheduler::set_scheduled_share(&app, Some(share));
+}
+
+#[tauri::command]
+fn cancel_scheduled_screenshare(app: tauri::AppHandle) {
+    log::info!("cancel_scheduled_screenshare");
+    scheduler::set_scheduled_share(&app, None);
+}
+
+#[tauri::command]
+fn get_scheduled_screenshare(app: tauri::AppHandle) -> Option<ScheduledShare> {
+    log::info!("get_scheduled_screenshare");
+    let data = app.state::<Mutex<AppData>>();
+    let data = data.lock().unwrap();
+    data.app_state.scheduled_share()
+}
+
+#[tauri::command]
+fn reload_overlay_assets(app: tauri::AppHandle) {
+    log::info!("reload_overlay_assets");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    data.broadcast_to_cores(|| Message::ReloadAssets);
+}
+
+#[tauri::command]
+fn get_health_pings(app: tauri::AppHandle) -> bool {
+    log::info!("get_health_pings");
+    let data = app.state::<Mutex<AppData>>();
+    let data = data.lock().unwrap();
+    data.app_state.health_pings()
+}
+
+#[tauri::command]
+fn set_health_pings(app: tauri::AppHandle, enabled: bool) {
+    log::info!("set_health_pings: {enabled}");
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    data.app_state.set_health_pings(enabled);
+}
+
+#[tauri::command]
+fn get_ipc_stats(app: tauri::AppHandle) -> Vec<IpcRequestStats> {
+    log::info!("get_ipc_stats");
+    let data = app.state::<Mutex<AppData>>();
+    let data = data.lock().unwrap();
+    data.ipc_stats.snapshot()
+}
+
 #[tauri::command]
 fn minimize_main_window(app: tauri::AppHandle) {
     log::info!("minimize_main_window");
@@ -438,11 +1333,62 @@ fn set_livekit_url(app: tauri::AppHandle, url: String) {
     let mut data = data.lock().unwrap();
     if data.livekit_server_url != url {
         data.livekit_server_url = url.clone();
-        let res = data.socket.send_message(Message::LivekitServerUrl(url));
-        if let Err(e) = res {
-            log::error!("set_livekit_url: failed to send message: {e:?}");
+        data.broadcast_to_cores(|| Message::LivekitServerUrl(url.clone()));
+    }
+}
+
+/// Starts a core process for `session_id`, the commands given the session
+/// id go to it, e.g. to share a second display to another room.
+#[tauri::command]
+async fn start_core_session(app: tauri::AppHandle, session_id: String) -> Result<(), String> {
+    log::info!("start_core_session: {session_id}");
+    let health_pings = {
+        let data = app.state::<Mutex<AppData>>();
+        let data = data.lock().unwrap();
+        if data.cores.contains_key(&session_id) {
+            return Err(CoreProcessCreationError::SessionExists(session_id).to_string());
         }
+        data.app_state.health_pings()
+    };
+
+    /* Not holding the lock, connecting to the new core process takes a while. */
+    let core_process = create_core_process(&app, health_pings, &session_id).map_err(|e| {
+        log::error!("start_core_session: failed to create core process: {e:?}");
+        e.to_string()
+    })?;
+
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    if data.cores.contains_key(&session_id) {
+        let _ = core_process.process.kill();
+        return Err(CoreProcessCreationError::SessionExists(session_id).to_string());
+    }
+    data.cores.insert(session_id.clone(), core_process);
+    let url = data.livekit_server_url.clone();
+    if let Err(e) = data.send_to_core(Some(&session_id), Message::LivekitServerUrl(url)) {
+        log::error!("start_core_session: failed to send livekit url: {e:?}");
     }
+    Ok(())
+}
+
+/// Stops the core process of `session_id`, the one of `DEFAULT_SESSION_ID`
+/// runs as long as the app.
+#[tauri::command]
+fn stop_core_session(app: tauri::AppHandle, session_id: String) -> Result<(), String> {
+    log::info!("stop_core_session: {session_id}");
+    if session_id == DEFAULT_SESSION_ID {
+        return Err(format!("the {DEFAULT_SESSION_ID} session can't be stopped"));
+    }
+    let data = app.state::<Mutex<AppData>>();
+    let mut data = data.lock().unwrap();
+    let core_process = data
+        .cores
+        .remove(&session_id)
+        .ok_or_else(|| format!("no core process for session {session_id}"))?;
+    core_process.process.kill().map_err(|e| {
+        log::error!("stop_core_session: failed to kill core process: {e:?}");
+        e.to_string()
+    })
 }
 
 #[tauri::command]
@@ -540,7 +1486,14 @@ fn main() {
                 .id("quit")
                 .accelerator("Cmd+Q")
                 .build(app)?;
-            let menu = MenuBuilder::new(app).items(&[&quit]).build()?;
+            let cancel_scheduled_share = MenuItemBuilder::new("Cancel scheduled share")
+                .id(scheduler::CANCEL_SCHEDULED_SHARE_MENU_ID)
+                .enabled(false)
+                .build(app)?;
+            let menu = MenuBuilder::new(app)
+                .items(&[&cancel_scheduled_share, &quit])
+                .build()?;
+            app.manage(ScheduleMenuItem(cancel_scheduled_share));
 
             setup_tray_icon(app, &menu, location_set_setup.clone())?;
 
@@ -555,17 +1508,33 @@ fn main() {
                 }
             }
 
-            let (_core_process, socket) =
-                create_core_process(app.handle()).expect("Failed to create core process");
-
             let app_state = AppState::new(&app_data_dir);
+            let core_process =
+                create_core_process(app.handle(), app_state.health_pings(), DEFAULT_SESSION_ID)
+                    .expect("Failed to create core process");
+
             let data = Mutex::new(AppData::new(
-                socket,
+                core_process,
                 deactivate_hiding_clone,
                 dock_enabled,
                 app_state,
             ));
             app.manage(data);
+            app.manage(CoreSharedMemory::default());
+            app.manage(SharePreview::default());
+            negotiate_shared_memory(app.handle());
+
+            /* Start the shares scheduled in a previous run too. */
+            let scheduled = {
+                let data = app.state::<Mutex<AppData>>();
+                let data = data.lock().unwrap();
+                data.app_state.scheduled_share().is_some()
+            };
+            scheduler::update_schedule_menu(app.handle(), scheduled);
+            let app_handle = app.handle().clone();
+            std::thread::spawn(move || {
+                scheduler::run_scheduler(app_handle);
+            });
 
             /* Clear app logs in the beginning of a session. */
             let dir = app.path().app_log_dir();
@@ -591,6 +1560,12 @@ fn main() {
                 ping_frontend(app_handle);
             });
 
+            /* Forward the notifications of the core process, e.g. a display was plugged in. */
+            let app_handle = app.handle().clone();
+            std::thread::spawn(move || {
+                listen_core_notifications(app_handle);
+            });
+
             let first_run = {
                 let data = app.state::<Mutex<AppData>>();
                 let data = data.lock().unwrap();
@@ -759,7 +1734,11 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             screenshare,
             stop_sharing,
+            change_resolution,
             get_available_content,
+            identify_displays,
+            get_core_breadcrumbs,
+            export_support_bundle,
             store_token_cmd,
             get_stored_token,
             delete_stored_token,
@@ -769,6 +1748,42 @@ fn main() {
             get_logs,
             set_deactivate_hiding,
             set_controller_cursor,
+            set_admission_policy,
+            set_keyboard_arbitration,
+            set_framerate,
+            set_clipboard_sharing,
+            set_share_preview,
+            get_share_preview,
+            set_keyboard_app_allowlist,
+            set_keyboard_layout,
+            run_network_preflight,
+            admit_participant,
+            kick_participant,
+            ban_identity,
+            set_input_authorized,
+            set_cursor_visible,
+            start_macro_recording,
+            stop_macro_recording,
+            load_macro,
+            play_macro,
+            get_input_injection_status,
+            get_session_state,
+            get_cameras,
+            enable_camera,
+            disable_camera,
+            get_microphones,
+            enable_mic,
+            disable_mic,
+            set_noise_suppression,
+            get_system_audio_support,
+            enable_system_audio,
+            disable_system_audio,
+            grant_next_control,
+            restart_overlay,
+            get_unclean_session,
+            dismiss_unclean_session,
+            show_incoming_call,
+            dismiss_incoming_call,
             open_accessibility_settings,
             open_microphone_settings,
             open_screenshare_settings,
@@ -780,9 +1795,24 @@ fn main() {
             set_dock_icon_visible,
             set_last_used_mic,
             get_last_used_mic,
+            get_thumbnail_privacy,
+            set_thumbnail_privacy,
+            get_capture_exclusions,
+            set_capture_exclusions,
+            get_overlay_theme,
+            set_overlay_theme,
+            schedule_screenshare,
+            cancel_scheduled_screenshare,
+            get_scheduled_screenshare,
+            reload_overlay_assets,
+            get_health_pings,
+            set_health_pings,
+            get_ipc_stats,
             minimize_main_window,
             set_livekit_url,
             get_livekit_url,
+            start_core_session,
+            stop_core_session,
         ])
         .build(tauri::generate_context!())
         .expect("error while running tauri application");
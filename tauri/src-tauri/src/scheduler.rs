@@ -0,0 +1,148 @@
+//! Screen shares scheduled to start at a given time, e.g. the demo of the
+//! daily standup.
+//!
+//! The schedule is persisted in the app state, so it survives restarts. When
+//! it is due the frontend gets a `scheduled_screenshare` event and starts the
+//! share through the `screenshare` command, it owns the call's video token.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use socket_lib::{AspectPolicy, Content, Extent};
+use tauri::menu::MenuItem;
+use tauri::{AppHandle, Emitter, Manager, Wry};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::AppData;
+
+/// Id of the tray menu item cancelling the scheduled share.
+pub const CANCEL_SCHEDULED_SHARE_MENU_ID: &str = "cancel_scheduled_share";
+
+const SCHEDULER_POLL_SECS: u64 = 1;
+/// Shares missed by more than this, e.g. the app wasn't running, are skipped.
+const MISSED_SHARE_SECS: u64 = 5 * 60;
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// A share the user scheduled from the content picker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledShare {
+    /// Unix time in seconds the share starts at.
+    pub start_at: u64,
+    pub content: Content,
+    pub resolution: Extent,
+    pub view_only: bool,
+    pub aspect_policy: AspectPolicy,
+    /// Whether the share repeats every day at the same time.
+    pub daily: bool,
+}
+
+/// The tray menu item cancelling the scheduled share, managed by the app.
+pub struct ScheduleMenuItem(pub MenuItem<Wry>);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Due {
+    NotYet,
+    Now,
+    Missed,
+}
+
+fn due(share: &ScheduledShare, now: u64) -> Due {
+    if now < share.start_at {
+        Due::NotYet
+    } else if now - share.start_at > MISSED_SHARE_SECS {
+        Due::Missed
+    } else {
+        Due::Now
+    }
+}
+
+/// Returns the schedule left once `share` fired or was missed, the next
+/// occurrence of a daily share.
+fn next_occurrence(share: &ScheduledShare, now: u64) -> Option<ScheduledShare> {
+    if !share.daily {
+        return None;
+    }
+    let mut next = share.clone();
+    while next.start_at <= now {
+        next.start_at += DAY_SECS;
+    }
+    Some(next)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Enables the tray item cancelling the schedule only when a share is scheduled.
+pub fn update_schedule_menu(app: &AppHandle, scheduled: bool) {
+    if let Some(item) = app.try_state::<ScheduleMenuItem>() {
+        if let Err(e) = item.0.set_enabled(scheduled) {
+            log::error!("update_schedule_menu: failed to update menu item: {e:?}");
+        }
+    }
+}
+
+/// Replaces the scheduled share, `None` cancels it.
+///
+/// # Arguments
+///
+/// * `app` - The app handle
+/// * `share` - The share to schedule
+pub fn set_scheduled_share(app: &AppHandle, share: Option<ScheduledShare>) {
+    let scheduled = share.is_some();
+    {
+        let data = app.state::<Mutex<AppData>>();
+        let mut data = data.lock().unwrap();
+        data.app_state.set_scheduled_share(share);
+    }
+    update_schedule_menu(app, scheduled);
+}
+
+/// Fires the scheduled share when it is due, runs for the lifetime of the app.
+pub fn run_scheduler(app: AppHandle) {
+    loop {
+        std::thread::sleep(Duration::from_secs(SCHEDULER_POLL_SECS));
+
+        let share = {
+            let data = app.state::<Mutex<AppData>>();
+            let data = data.lock().unwrap();
+            data.app_state.scheduled_share()
+        };
+        let Some(share) = share else {
+            continue;
+        };
+
+        let now = now_secs();
+        let state = due(&share, now);
+        if state == Due::NotYet {
+            continue;
+        }
+        set_scheduled_share(&app, next_occurrence(&share, now));
+        if state == Due::Missed {
+            log::warn!(
+                "run_scheduler: skipping share missed since {}",
+                share.start_at
+            );
+            continue;
+        }
+
+        log::info!("run_scheduler: starting scheduled share: {share:?}");
+        if let Err(e) = app.emit("scheduled_screenshare", &share) {
+            log::error!("run_scheduler: failed to emit scheduled share: {e:?}");
+        }
+        /* Outside of a call nobody can start the share, the notification reminds the user. */
+        if let Err(e) = app
+            .notification()
+            .builder()
+            .title("Scheduled screen share")
+            .body("It's time for your scheduled screen share")
+            .show()
+        {
+            log::error!("run_scheduler: failed to show notification: {e:?}");
+        }
+    }
+}
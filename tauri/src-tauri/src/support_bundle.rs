@@ -0,0 +1,111 @@
+//! Support bundle export.
+//!
+//! A support bundle is a zip the user can attach to a support ticket, it
+//! contains the logs, system information and the core's diagnostics. It is
+//! written locally and never sent anywhere.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+use socket_lib::DiagnosticsMessage;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::permissions;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SupportBundleError {
+    #[error("Failed to write support bundle: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to create zip: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Failed to serialize support bundle entry: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// State of the permissions the app needs.
+#[derive(Debug, Serialize)]
+pub struct PermissionStates {
+    pub screenshare: bool,
+    pub accessibility: bool,
+    pub microphone: bool,
+}
+
+impl PermissionStates {
+    pub fn current() -> Self {
+        Self {
+            screenshare: permissions::screenshare(),
+            accessibility: permissions::accessibility(),
+            microphone: permissions::microphone(),
+        }
+    }
+}
+
+/// Versions and environment the app is running in.
+#[derive(Debug, Serialize)]
+pub struct SystemInfo {
+    pub app_version: String,
+    /// `None` when the core process didn't reply
+    pub core_version: Option<String>,
+    pub os: String,
+    pub arch: String,
+    pub permissions: PermissionStates,
+}
+
+impl SystemInfo {
+    pub fn new(app_version: String, core_version: Option<String>) -> Self {
+        Self {
+            app_version,
+            core_version,
+            os: os_info::get().to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            permissions: PermissionStates::current(),
+        }
+    }
+}
+
+/// Everything that goes in a support bundle.
+pub struct SupportBundle {
+    pub system: SystemInfo,
+    /// The app's log file, the core process output is logged there too
+    pub logs: Option<Vec<u8>>,
+    /// `None` when the core process didn't reply
+    pub diagnostics: Option<DiagnosticsMessage>,
+}
+
+impl SupportBundle {
+    /// Writes the bundle as a zip at `path`, replacing any existing file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Where the zip is written
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` when the zip was written, the error of the failed step otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), SupportBundleError> {
+        let mut zip = ZipWriter::new(File::create(path)?);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file("system.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&self.system)?)?;
+
+        if let Some(logs) = &self.logs {
+            zip.start_file("hopp.log", options)?;
+            zip.write_all(logs)?;
+        }
+
+        if let Some(diagnostics) = &self.diagnostics {
+            zip.start_file("core_breadcrumbs.txt", options)?;
+            zip.write_all(diagnostics.breadcrumbs.join("\n").as_bytes())?;
+
+            zip.start_file("sessions.json", options)?;
+            zip.write_all(&serde_json::to_vec_pretty(&diagnostics.sessions)?)?;
+        }
+
+        zip.finish()?;
+        Ok(())
+    }
+}
@@ -2,19 +2,41 @@ import ReactJson from "react-json-view";
 import Draggable from "react-draggable";
 import { throttle } from "lodash";
 import { RiDraggable } from "react-icons/ri";
-import { LiveKitRoom, useDataChannel, useLocalParticipant, useTracks, VideoTrack } from "@livekit/components-react";
+import {
+  LiveKitRoom,
+  useDataChannel,
+  useLocalParticipant,
+  useRoomContext,
+  useTracks,
+  VideoTrack,
+} from "@livekit/components-react";
 import { Track } from "livekit-client";
 import React, { useEffect, useMemo, useRef, useState } from "react";
 import { resizeWindow } from "./utils";
 import { useSharingContext } from "@/windows/screensharing/context";
 import { useResizeListener } from "@/lib/hooks";
+import { COMPRESSION_DEFLATE, decodePayload } from "@/lib/payloadCompression";
+import { useSessionClockSync } from "@/lib/sessionClock";
 import { cn, getAbsolutePosition, getRelativePosition } from "@/lib/utils";
 import {
+  TPAdmission,
+  TPCapabilities,
+  TPClipboardUpdate,
+  TPControlHold,
+  TPInputAuth,
+  TPInputGrant,
   TPKeystroke,
   TPMouseClick,
   TPMouseMove,
   TPMouseVisible,
+  TPParticipantRemoved,
+  TPRaiseHand,
   TPRemoteControlEnabled,
+  TPSessionMode,
+  TPShareConfig,
+  TPShareInterruption,
+  TPSharerCursorShape,
+  TPUpgradeRequired,
   TPWheelEvent,
 } from "@/payloads";
 import { useHover } from "@uidotdev/usehooks";
@@ -25,6 +47,46 @@ import useStore from "@/store/store";
 
 const CURSORS_TOPIC = "participant_location";
 
+// A sequence number this much lower than the last one means the sender restarted its counter, not a late move
+const MOUSE_MOVE_SEQ_RESET_GAP = 1024;
+
+// Held while controlling in push-to-control mode, it isn't sent to the sharer as a keystroke
+const PUSH_TO_CONTROL_KEY = "Alt";
+
+// What this client announces to the sharer, it doesn't handle optional features yet
+const CLIENT_CAPABILITIES: TPCapabilities["payload"] = {
+  compression: [COMPRESSION_DEFLATE],
+  version: 1,
+  features: [],
+};
+
+const SHARE_INTERRUPTION_MESSAGES = {
+  DisplaySleep: "Sharer's display is asleep",
+  ScreenLocked: "Screen locked",
+  Screensaver: "Sharer's screensaver is on",
+  WindowNotVisible: "Shared window is minimized or hidden",
+};
+
+// CSS cursors matching the shapes of the sharer's system cursor
+const SHARER_CURSOR_SHAPES: Record<TPSharerCursorShape["payload"]["shape"], string> = {
+  Arrow: "default",
+  IBeam: "text",
+  Hand: "pointer",
+  Crosshair: "crosshair",
+  ResizeHorizontal: "ew-resize",
+  ResizeVertical: "ns-resize",
+  ResizeNwse: "nwse-resize",
+  ResizeNesw: "nesw-resize",
+  Move: "move",
+  Wait: "wait",
+  NotAllowed: "not-allowed",
+};
+
+const ADMISSION_WAIT_MESSAGES = {
+  RoomFull: "The session is full, waiting for the sharer to let you in",
+  NeedsApproval: "Waiting for the sharer to let you in",
+};
+
 type SharingScreenProps = {
   serverURL: string;
   token: string;
@@ -47,6 +109,7 @@ export function SharingScreen(props: SharingScreenProps) {
 interface CursorSlot {
   participantId: string | null;
   participantName: string;
+  fullName?: string;
   x: number;
   y: number;
   lastActivity: number;
@@ -55,6 +118,12 @@ interface CursorSlot {
 const ConsumerComponent = React.memo(() => {
   // All state hooks first
   const [updateMouseControls, setUpdateMouseControls] = useState(false);
+  const [shareInterruption, setShareInterruption] = useState<string | null>(null);
+  const [admissionWait, setAdmissionWait] = useState<string | null>(null);
+  const [handRaised, setHandRaised] = useState(false);
+  const [pushToControl, setPushToControl] = useState(false);
+  const [sharerCursorShape, setSharerCursorShape] = useState<TPSharerCursorShape["payload"]["shape"]>("Arrow");
+  const [shareConfig, setShareConfig] = useState<TPShareConfig["payload"] | null>(null);
 
   // Hand-picked colors for the tailwind colors page:
   // https://tailwindcss.com/docs/colors
@@ -72,6 +141,14 @@ const ConsumerComponent = React.memo(() => {
 
   // All refs
   const videoRef = useRef<HTMLVideoElement>(null);
+  // Lets the sharer drop mouse moves that arrive out of order
+  const mouseMoveSeq = useRef(0);
+  // Highest sequence number of the moves received from each participant
+  const lastMoveSeqs = useRef(new Map<string, number>());
+  // The token the sharer granted to our input, the sequence never restarts so a new grant doesn't look like a replay
+  const inputToken = useRef<string | null>(null);
+  const inputSeq = useRef(0);
+  const controlHeld = useRef(false);
 
   // All context hooks
   const tracks = useTracks([Track.Source.ScreenShare], {
@@ -83,6 +160,7 @@ const ConsumerComponent = React.memo(() => {
   const { updateCallTokens } = useStore();
 
   // Data channel hooks - must be called unconditionally
+  // Moves are far below the compression threshold, they're parsed right away
   const { message: latestMessage, send } = useDataChannel(CURSORS_TOPIC, (msg) => {
     const decoder = new TextDecoder();
     const payload: TPMouseMove = JSON.parse(decoder.decode(msg.payload));
@@ -97,6 +175,19 @@ const ConsumerComponent = React.memo(() => {
     /* We need the id to be unique for each participant */
     if (participantId === "Unknown") return;
 
+    /*
+     * The sharer sends most moves unreliably and repeats some as reliable
+     * keyframes, they can arrive late, twice or out of order. A move at or
+     * below the last one would jump the cursor back. Moves without a sequence
+     * number come from older clients and are always shown.
+     */
+    const seq = payload.payload.seq;
+    if (seq !== undefined) {
+      const lastSeq = lastMoveSeqs.current.get(participantId);
+      if (lastSeq !== undefined && seq <= lastSeq && lastSeq - seq < MOUSE_MOVE_SEQ_RESET_GAP) return;
+      lastMoveSeqs.current.set(participantId, seq);
+    }
+
     /*
      * We are keeping it simple for now and just set a slot to a participant
      * the first time they move their mouse.
@@ -145,6 +236,7 @@ const ConsumerComponent = React.memo(() => {
         updated[slotIndex] = {
           participantId,
           participantName: name,
+          fullName: participantName,
           x: absoluteX,
           y: absoluteY,
           lastActivity: Date.now(),
@@ -156,25 +248,207 @@ const ConsumerComponent = React.memo(() => {
   });
 
   useDataChannel("remote_control_enabled", (msg) => {
-    const decoder = new TextDecoder();
-    const payload: TPRemoteControlEnabled = JSON.parse(decoder.decode(msg.payload));
-    if (payload.payload.enabled == false) {
+    decodePayload<TPRemoteControlEnabled>(msg.payload, (payload) => {
+      if (payload.payload.enabled == false) {
+        updateCallTokens({
+          isRemoteControlEnabled: false,
+        });
+        toast("Sharer disabled remote control", {
+          icon: "🔒",
+          duration: 1500,
+        });
+      } else {
+        updateCallTokens({
+          isRemoteControlEnabled: true,
+        });
+        toast("Sharer enabled remote control", {
+          icon: "🔓",
+          duration: 1500,
+        });
+      }
+    });
+  });
+
+  // View-only shares never accept our input, no matter what the sharer toggles
+  useDataChannel("session_mode", (msg) => {
+    decodePayload<TPSessionMode>(msg.payload, (payload) => {
+      if (!payload.payload.view_only) return;
       updateCallTokens({
         isRemoteControlEnabled: false,
       });
-      toast("Sharer disabled remote control", {
-        icon: "🔒",
-        duration: 1500,
+      toast("The sharer is sharing in view-only mode", {
+        icon: "👀",
+        duration: 3000,
       });
-    } else {
+    });
+  });
+
+  useDataChannel("share_config", (msg) => {
+    decodePayload<TPShareConfig>(msg.payload, (payload) => {
+      setShareConfig(payload.payload);
+    });
+  });
+
+  useDataChannel("share_interruption", (msg) => {
+    decodePayload<TPShareInterruption>(msg.payload, (payload) => {
+      if (payload.type === "ShareInterrupted") {
+        setShareInterruption(SHARE_INTERRUPTION_MESSAGES[payload.payload.reason]);
+      } else {
+        setShareInterruption(null);
+      }
+    });
+  });
+
+  // The video has no cursor, while controlling ours takes the shape of the sharer's
+  useDataChannel("sharer_cursor_shape", (msg) => {
+    decodePayload<TPSharerCursorShape>(msg.payload, (payload) => {
+      setSharerCursorShape(payload.payload.shape);
+    });
+  });
+
+  // Sent only to the sharer, answering every participant's capabilities would never end
+  useDataChannel("capabilities", (msg) => {
+    if (!msg.from) return;
+    const encoder = new TextEncoder();
+    const payload: TPCapabilities = { type: "Capabilities", payload: CLIENT_CAPABILITIES };
+    localParticipant.localParticipant?.publishData(encoder.encode(JSON.stringify(payload)), {
+      reliable: true,
+      topic: "capabilities",
+      destinationIdentities: [msg.from.identity],
+    });
+  });
+
+  useSessionClockSync();
+
+  // The sharer ignores our input until it lets us in
+  useDataChannel("admission", (msg) => {
+    decodePayload<TPAdmission>(msg.payload, (payload) => {
+      if (payload.payload.sid !== localParticipant.localParticipant?.sid) return;
+      setAdmissionWait(payload.payload.waiting ? ADMISSION_WAIT_MESSAGES[payload.payload.waiting] : null);
+    });
+  });
+
+  // Only sent to our identity, without it the sharer drops our clicks, keystrokes and scrolls
+  useDataChannel("input_grant", (msg) => {
+    decodePayload<TPInputGrant>(msg.payload, (payload) => {
+      inputToken.current = payload.payload.token;
+      // Getting control takes us out of the sharer's queue
+      if (payload.payload.token) setHandRaised(false);
+    });
+  });
+
+  // The sharer drops our input until the app is updated, it only tells us once
+  useDataChannel("upgrade_required", (msg) => {
+    decodePayload<TPUpgradeRequired>(msg.payload, (payload) => {
+      toast.error(
+        `The sharer requires a newer version of Hopp (protocol ${payload.payload.min_version}) to control their screen, please update`,
+        { id: "upgrade_required", duration: 10000 },
+      );
+    });
+  });
+
+  const toggleHand = () => {
+    const payload: TPRaiseHand = { type: "RaiseHand", payload: { raised: !handRaised } };
+    localParticipant.localParticipant?.publishData(encoder.encode(JSON.stringify(payload)), { reliable: true });
+    setHandRaised(!handRaised);
+  };
+
+  const sendControlHold = (active: boolean) => {
+    controlHeld.current = active;
+    const payload: TPControlHold = { type: "ControlHold", payload: { active } };
+    localParticipant.localParticipant?.publishData(encoder.encode(JSON.stringify(payload)), { reliable: true });
+  };
+
+  // Leaving push-to-control holds for good, the sharer keeps us in the mode until we leave
+  const togglePushToControl = () => {
+    sendControlHold(pushToControl);
+    setPushToControl(!pushToControl);
+  };
+
+  useEffect(() => {
+    if (!pushToControl) return;
+
+    const handleKeyDown = (e: KeyboardEvent) => {
+      if (e.key === PUSH_TO_CONTROL_KEY && !controlHeld.current) sendControlHold(true);
+    };
+    const handleKeyUp = (e: KeyboardEvent) => {
+      if (e.key === PUSH_TO_CONTROL_KEY && controlHeld.current) sendControlHold(false);
+    };
+    // The key up never arrives when the window loses focus while it is held
+    const handleBlur = () => {
+      if (controlHeld.current) sendControlHold(false);
+    };
+
+    window.addEventListener("keydown", handleKeyDown);
+    window.addEventListener("keyup", handleKeyUp);
+    window.addEventListener("blur", handleBlur);
+
+    return () => {
+      window.removeEventListener("keydown", handleKeyDown);
+      window.removeEventListener("keyup", handleKeyUp);
+      window.removeEventListener("blur", handleBlur);
+    };
+  }, [pushToControl]);
+
+  const inputAuth = (): TPInputAuth | undefined =>
+    inputToken.current ? { token: inputToken.current, seq: ++inputSeq.current } : undefined;
+
+  // Moves don't advance the sequence, a move overtaking a click mustn't make the click look replayed
+  const moveAuth = (): TPInputAuth | undefined =>
+    inputToken.current ? { token: inputToken.current, seq: inputSeq.current } : undefined;
+
+  // Set once the sharer shared its clipboard, ours is only sent to it from then on
+  const clipboardSharer = useRef<string | null>(null);
+  const lastClipboardText = useRef<string | null>(null);
+
+  useDataChannel("clipboard", (msg) => {
+    if (!msg.from) return;
+    const sharer = msg.from.identity;
+    decodePayload<TPClipboardUpdate>(msg.payload, (payload) => {
+      clipboardSharer.current = sharer;
+      lastClipboardText.current = payload.payload.text;
+      navigator.clipboard.writeText(payload.payload.text).catch(console.error);
+    });
+  });
+
+  // What we copied in another app is on the sharer's clipboard before we paste
+  useEffect(() => {
+    const handleFocus = () => {
+      const sharer = clipboardSharer.current;
+      if (!sharer) return;
+      navigator.clipboard
+        .readText()
+        .then((text) => {
+          if (text === lastClipboardText.current) return;
+          lastClipboardText.current = text;
+          const payload: TPClipboardUpdate = { type: "ClipboardUpdate", payload: { text }, auth: inputAuth() };
+          localParticipant.localParticipant?.publishData(encoder.encode(JSON.stringify(payload)), {
+            reliable: true,
+            destinationIdentities: [sharer],
+          });
+        })
+        .catch(console.error);
+    };
+
+    window.addEventListener("focus", handleFocus);
+    return () => window.removeEventListener("focus", handleFocus);
+  }, [localParticipant.localParticipant]);
+
+  // The sharer's core already ignores a removed participant, leaving stops the video too
+  const room = useRoomContext();
+  useDataChannel("participant_removed", (msg) => {
+    decodePayload<TPParticipantRemoved>(msg.payload, (payload) => {
+      if (payload.payload.sid !== localParticipant.localParticipant?.sid) return;
       updateCallTokens({
-        isRemoteControlEnabled: true,
-      });
-      toast("Sharer enabled remote control", {
-        icon: "🔓",
-        duration: 1500,
+        isRemoteControlEnabled: false,
       });
-    }
+      setAdmissionWait(
+        payload.payload.banned ?
+          "The sharer removed you from this session"
+        : "The sharer removed you, rejoin the call to ask to be admitted again",
+      );
+      room.disconnect();
+    });
   });
 
   // Hide cursors after 5 seconds of inactivity
@@ -211,8 +485,9 @@ const ConsumerComponent = React.memo(() => {
     return tracks[tracks.length - 1];
   }, [tracks]);
 
-  const streamWidth = track?.publication.dimensions?.width || 16;
-  const streamHeight = track?.publication.dimensions?.height || 9;
+  // The sharer's configuration sizes the video until the track reports its dimensions
+  const streamWidth = track?.publication.dimensions?.width || shareConfig?.width || 16;
+  const streamHeight = track?.publication.dimensions?.height || shareConfig?.height || 9;
   const aspectRatio = streamWidth / streamHeight;
 
   const throttledResize = useMemo(
@@ -254,7 +529,8 @@ const ConsumerComponent = React.memo(() => {
 
         const payload: TPMouseMove = {
           type: "MouseMove",
-          payload: { x: relativeX, y: relativeY, pointer: true },
+          payload: { x: relativeX, y: relativeY, pointer: true, seq: ++mouseMoveSeq.current },
+          auth: moveAuth(),
         };
 
         localParticipant.localParticipant?.publishData(encoder.encode(JSON.stringify(payload)), {
@@ -278,10 +554,11 @@ const ConsumerComponent = React.memo(() => {
             clicks: e.detail,
             down: true,
             shift: e.shiftKey,
-            alt: e.altKey,
+            alt: e.altKey && !pushToControl,
             ctrl: e.ctrlKey,
             meta: e.metaKey,
           },
+          auth: inputAuth(),
         };
 
         localParticipant.localParticipant?.publishData(encoder.encode(JSON.stringify(payload)), { reliable: true });
@@ -302,10 +579,11 @@ const ConsumerComponent = React.memo(() => {
             clicks: e.detail,
             down: false,
             shift: e.shiftKey,
-            alt: e.altKey,
+            alt: e.altKey && !pushToControl,
             ctrl: e.ctrlKey,
             meta: e.metaKey,
           },
+          auth: inputAuth(),
         };
 
         localParticipant.localParticipant?.publishData(encoder.encode(JSON.stringify(payload)), { reliable: true });
@@ -331,6 +609,7 @@ const ConsumerComponent = React.memo(() => {
         const payload: TPWheelEvent = {
           type: "WheelEvent",
           payload: { deltaX: deltaX, deltaY: deltaY },
+          auth: inputAuth(),
         };
 
         localParticipant.localParticipant?.publishData(encoder.encode(JSON.stringify(payload)), { reliable: true });
@@ -366,7 +645,7 @@ const ConsumerComponent = React.memo(() => {
         videoElement.removeEventListener("contextmenu", handleContextMenu);
       }
     };
-  }, [isSharingMouse, updateMouseControls]);
+  }, [isSharingMouse, updateMouseControls, pushToControl]);
 
   /**
    * Keyboard sharing logic
@@ -382,6 +661,7 @@ const ConsumerComponent = React.memo(() => {
 
     const handleKeyDown = (e: KeyboardEvent) => {
       e.preventDefault();
+      if (pushToControl && e.key === PUSH_TO_CONTROL_KEY) return;
       if (isMouseInside && isSharingKeyEvents) {
         e.preventDefault();
         /*
@@ -406,11 +686,12 @@ const ConsumerComponent = React.memo(() => {
           payload: {
             key: [key],
             meta: e.metaKey,
-            alt: e.altKey,
+            alt: e.altKey && !pushToControl,
             ctrl: e.ctrlKey,
             shift: e.shiftKey,
             down: true,
           },
+          auth: inputAuth(),
         };
 
         // console.debug("Sending keystroke", payload);
@@ -420,6 +701,7 @@ const ConsumerComponent = React.memo(() => {
     };
     const handleKeyUp = (e: KeyboardEvent) => {
       e.preventDefault();
+      if (pushToControl && e.key === PUSH_TO_CONTROL_KEY) return;
       if (isMouseInside && isSharingKeyEvents) {
         e.preventDefault();
         /*
@@ -444,11 +726,12 @@ const ConsumerComponent = React.memo(() => {
           payload: {
             key: [key],
             meta: e.metaKey,
-            alt: e.altKey,
+            alt: e.altKey && !pushToControl,
             ctrl: e.ctrlKey,
             shift: e.shiftKey,
             down: false,
           },
+          auth: inputAuth(),
         };
 
         // console.debug("Sending keystroke", payload);
@@ -464,7 +747,7 @@ const ConsumerComponent = React.memo(() => {
       parentKeyTrap?.removeEventListener("keydown", handleKeyDown);
       parentKeyTrap?.removeEventListener("keyup", handleKeyUp);
     };
-  }, [isMouseInside, isSharingKeyEvents, parentKeyTrap]);
+  }, [isMouseInside, isSharingKeyEvents, parentKeyTrap, pushToControl]);
 
   useEffect(() => {
     // TODO: remove and make this enabled only on debug mode
@@ -510,8 +793,30 @@ const ConsumerComponent = React.memo(() => {
         style={{
           aspectRatio: `${aspectRatio}`,
           width: "100%",
+          cursor: isSharingMouse ? SHARER_CURSOR_SHAPES[sharerCursorShape] : undefined,
         }}
       />
+      {(admissionWait || shareInterruption) && (
+        <div className="w-full h-full absolute top-0 left-0 z-10 flex items-center justify-center bg-slate-900/70">
+          <span className="text-slate-50 text-lg font-medium">{admissionWait ?? shareInterruption}</span>
+        </div>
+      )}
+      {!admissionWait && (
+        <button
+          className="absolute bottom-2 right-2 z-10 rounded-md bg-slate-900/70 px-2 py-1 text-sm text-slate-50"
+          onClick={toggleHand}
+        >
+          {handRaised ? "Lower hand" : "Raise hand"}
+        </button>
+      )}
+      {!admissionWait && (
+        <button
+          className="absolute bottom-2 right-28 z-10 rounded-md bg-slate-900/70 px-2 py-1 text-sm text-slate-50"
+          onClick={togglePushToControl}
+        >
+          {pushToControl ? "Hold Alt to control" : "Push to control"}
+        </button>
+      )}
       {cursorSlots.map((slot, index) => {
         const color = SVG_BADGE_COLORS[index % SVG_BADGE_COLORS.length];
 
@@ -519,6 +824,7 @@ const ConsumerComponent = React.memo(() => {
           <Cursor
             key={index}
             name={slot.participantName}
+            fullName={slot.fullName}
             color={color}
             style={{
               left: `${slot.x}px`,
@@ -1,6 +1,6 @@
 import { OS } from "@/constants";
 import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
-import { getCurrentWindow, PhysicalSize, LogicalSize } from "@tauri-apps/api/window";
+import { getCurrentWindow, PhysicalSize } from "@tauri-apps/api/window";
 import { currentMonitor } from "@tauri-apps/api/window";
 
 const appWindow = getCurrentWebviewWindow();
@@ -69,7 +69,8 @@ export async function resizeWindow(streamWidth: number, streamHeight: number, re
     } else {
       maxHeight = Math.floor(maxWidth / aspectRatio);
     }
-    appWindow.setMaxSize(new LogicalSize(maxWidth, maxHeight + streamExtraOffset));
+    // The monitor size and the offsets are physical pixels
+    appWindow.setMaxSize(new PhysicalSize(maxWidth, maxHeight + streamExtraOffset));
   }
 
   let size = await appWindow.innerSize();
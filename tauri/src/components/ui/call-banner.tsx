@@ -8,6 +8,8 @@ import { socketService } from "@/services/socket";
 import { TWebSocketMessage } from "@/payloads";
 import { sounds } from "@/constants/sounds";
 import { HoppAvatar } from "./hopp-avatar";
+import { listen } from "@tauri-apps/api/event";
+import { tauriUtils } from "@/windows/window-utils";
 
 export const CallBanner = ({ callerId, toastId }: { callerId: string; toastId: string }) => {
   let caller = useStore((state) => state?.teammates?.find((user) => user.id === callerId));
@@ -111,6 +113,27 @@ export const CallBanner = ({ callerId, toastId }: { callerId: string; toastId: s
     };
   }, [callerId, toastId]);
 
+  // Show the call on the core's card too, the webview can be hidden while the app is in the tray
+  useEffect(() => {
+    const name = `${caller.first_name} ${caller.last_name}`.trim() || "Someone";
+    tauriUtils.showIncomingCall(name, callerId);
+
+    const unlistenPromise = listen<[string, boolean]>("incoming_call_answered", (event) => {
+      const [room, accepted] = event.payload;
+      if (room !== callerId) return;
+      if (accepted) {
+        handleAnswer();
+      } else {
+        handleReject();
+      }
+    });
+
+    return () => {
+      tauriUtils.dismissIncomingCall();
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, [callerId, handleAnswer, handleReject]);
+
   return (
     <div className="flex flex-col items-start justify-center gap-2">
       <div className="flex flex-row gap-2">
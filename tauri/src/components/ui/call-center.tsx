@@ -1,6 +1,6 @@
 import { formatDistanceToNow } from "date-fns";
 import { HiMiniComputerDesktop, HiOutlineMicrophone, HiOutlinePhoneXMark } from "react-icons/hi2";
-import useStore, { CallState } from "@/store/store";
+import useStore, { CallState, type SharedStream } from "@/store/store";
 import { useKrispNoiseFilter } from "@livekit/components-react/krisp";
 import { Separator } from "@/components/ui/separator";
 import { ToggleIconButton } from "@/components/ui/toggle-icon-button";
@@ -19,14 +19,27 @@ import {
 } from "@livekit/components-react";
 import { Track, RemoteParticipant, LocalTrack, ConnectionState, RoomEvent } from "livekit-client";
 import { useCallback, useEffect, useRef, useState } from "react";
-import { Select, SelectContent, SelectItem, SelectTrigger } from "./select";
+import { listen } from "@tauri-apps/api/event";
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "./select";
 import { SelectPortal } from "@radix-ui/react-select";
 import { Button } from "./button";
-import { tauriUtils } from "@/windows/window-utils";
+import { Input } from "./input";
+import {
+  tauriUtils,
+  type CameraDevice,
+  type InputInjectionStatus,
+  type MicrophoneDevice,
+  type KeyboardArbitration,
+  type KeyboardLayoutKind,
+  type ScheduledShare,
+  type SessionParticipant,
+  type UncleanSession,
+} from "@/windows/window-utils";
 import { HoppAvatar } from "./hopp-avatar";
 import { HiOutlineCursorClick, HiOutlineEye } from "react-icons/hi";
 import { Tooltip, TooltipContent, TooltipProvider, TooltipTrigger } from "@/components/ui/tooltip";
 import clsx from "clsx";
+import toast from "react-hot-toast";
 import { usePostHog } from "posthog-js/react";
 import { ChevronDownIcon } from "@radix-ui/react-icons";
 
@@ -129,6 +142,23 @@ export function ConnectedActions({ token }: { token: string }) {
     [callTokens],
   );
 
+  // The store doesn't survive a webview reload, the core knows whether we are still sharing
+  useEffect(() => {
+    tauriUtils
+      .getSessionState()
+      .then((state) => {
+        const { callTokens, updateCallTokens } = useStore.getState();
+        if (!callTokens || callTokens.isSharer === state.sharing) return;
+
+        updateCallTokens({
+          isSharer: state.sharing,
+          isController: callTokens.isController && !state.sharing,
+          isRemoteControlEnabled: !state.view_only,
+        });
+      })
+      .catch(console.error);
+  }, []);
+
   // Stop call when teammate disconnects
   useEffect(() => {
     if (!callTokens || !callParticipant) return;
@@ -145,6 +175,7 @@ export function ConnectedActions({ token }: { token: string }) {
         updateState={handleControllerChange}
         setIsSharer={handleIsSharerChange}
       />
+      <ScheduledShareListener />
       {/* <ConnectionsHealthDebug /> */}
       <div
         className={clsx("gap-2 px-4 flex-nowrap grid mb-4", {
@@ -188,7 +219,7 @@ export function ConnectedActions({ token }: { token: string }) {
               </Button>
             )}
             <div className="w-full flex flex-row gap-2">
-              {callTokens?.isSharer && (
+              {callTokens?.isSharer && callTokens.isRemoteControlEnabled !== false && (
                 <TooltipProvider>
                   <Tooltip delayDuration={100}>
                     <TooltipTrigger>
@@ -233,6 +264,21 @@ export function ConnectedActions({ token }: { token: string }) {
               </Button>
             </div>
           </div>
+          {callTokens && !callTokens.isSharer && <UncleanSessionReport />}
+          {callTokens?.isSharer && <WaitingRoom />}
+          {callTokens?.isSharer && <SharerCamera />}
+          {callTokens?.isSharer && <CoreMicrophone />}
+          {callTokens?.isSharer && <SystemAudio />}
+          {callTokens?.isSharer && callTokens.isRemoteControlEnabled !== false && <KeyboardArbitrationToggle />}
+          {callTokens?.isSharer && callTokens.isRemoteControlEnabled !== false && <KeyboardAppAllowlist />}
+          {callTokens?.isSharer && callTokens.isRemoteControlEnabled !== false && <KeyboardLayoutSelect />}
+          {callTokens?.isSharer && callTokens.isRemoteControlEnabled !== false && <ClipboardSharingToggle />}
+          {callTokens?.isSharer && callTokens.isRemoteControlEnabled !== false && <InputAuthorizations />}
+          {callTokens?.isSharer && callTokens.isRemoteControlEnabled !== false && <ControlRequestQueue />}
+          {callTokens?.isSharer && <ParticipantQualities />}
+          {callTokens?.isSharer && callTokens.sharedStream && <SharedStreamStatus stream={callTokens.sharedStream} />}
+          {callTokens?.isSharer && callTokens.sharedStream && <SharePreview />}
+          {callTokens?.isSharer && callTokens.isRemoteControlEnabled === false && <ViewOnlyStatus />}
         </div>
       </div>
       <ListenToRemoteAudio />
@@ -271,6 +317,21 @@ function MicrophoneIcon() {
     }
   }, [roomState, hasAudioEnabled, localParticipant, roomConnected]);
 
+  /* Controllers can mute the sharer when the sharer's window is hidden behind the shared content */
+  useEffect(() => {
+    const unlistenPromise = listen<"MuteMic" | "ToggleCamera">("media_control", (event) => {
+      if (event.payload === "MuteMic") {
+        updateCallTokens({ hasAudioEnabled: !hasAudioEnabled });
+      } else {
+        console.debug(`Unsupported media control: ${event.payload}`);
+      }
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, [hasAudioEnabled, updateCallTokens]);
+
   /* Force re enumeration of mic devices on dropdown open */
   const errorCallback = useCallback(
     (error: Error) => {
@@ -373,11 +434,123 @@ function ScreenShareIcon({ callTokens, setCallTokens }: { callTokens: CallState
         ...callTokens,
         isSharer: false,
         isRemoteControlEnabled: true,
+        sharedStream: undefined,
       });
       tauriUtils.stopSharing();
     }
   }, [callTokens, callTokens?.videoToken]);
 
+  /*
+   * The core process ends the share when the capture keeps failing,
+   * reset the sharing state and let the user know how to recover.
+   */
+  useEffect(() => {
+    const unlistenPromise = listen<string>("fatal_capture_error", (event) => {
+      console.error(`Screen sharing failed: ${event.payload}`);
+      if (callTokens?.isSharer) {
+        setCallTokens({
+          ...callTokens,
+          isSharer: false,
+          isRemoteControlEnabled: true,
+          sharedStream: undefined,
+        });
+      }
+      toast.error("Screen sharing stopped unexpectedly. Try sharing again, if it keeps failing restart Hopp.", {
+        duration: 10_000,
+        position: "top-center",
+      });
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, [callTokens, setCallTokens]);
+
+  /*
+   * Revoking the screen recording permission ends the share, it only
+   * works again after it is granted and Hopp is restarted.
+   */
+  useEffect(() => {
+    const unlistenPromise = listen<"ScreenCapture">("permission_revoked", () => {
+      if (callTokens?.isSharer) {
+        setCallTokens({
+          ...callTokens,
+          isSharer: false,
+          isRemoteControlEnabled: true,
+          sharedStream: undefined,
+        });
+      }
+      toast.error(
+        (t) => (
+          <div className="flex flex-row items-center gap-2">
+            Screen recording permission was revoked. Grant it again and restart Hopp to share.
+            <Button
+              variant="default"
+              className="ml-4"
+              size="sm"
+              onClick={() => {
+                tauriUtils.openScreenShareSettings();
+                toast.dismiss(t.id);
+              }}
+            >
+              Open settings
+            </Button>
+          </div>
+        ),
+        { duration: 15_000, position: "top-center" },
+      );
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, [callTokens, setCallTokens]);
+
+  /*
+   * The overlay drawing the controllers' cursors failed, the core rebuilds
+   * it and the sharer can restart it meanwhile.
+   */
+  useEffect(() => {
+    const unlistenDegraded = listen<string>("overlay_degraded", (event) => {
+      console.error(`Overlay degraded: ${event.payload}`);
+      toast.error(
+        (t) => (
+          <div className="flex flex-row items-center gap-2">
+            The cursors of the participants aren't visible on your screen.
+            <Button
+              variant="default"
+              className="ml-4"
+              size="sm"
+              onClick={() => {
+                tauriUtils.restartOverlay().catch(console.error);
+                toast.dismiss(t.id);
+              }}
+            >
+              Restart overlay
+            </Button>
+          </div>
+        ),
+        { id: "overlay_degraded", duration: Infinity, position: "top-center" },
+      );
+    });
+    const unlistenRestored = listen("overlay_restored", () => {
+      toast.dismiss("overlay_degraded");
+      toast.success("The cursors of the participants are visible again", {
+        duration: 3_000,
+        position: "top-center",
+      });
+    });
+
+    return () => {
+      unlistenDegraded.then((unlisten) => unlisten());
+      unlistenRestored.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (!callTokens?.isSharer) toast.dismiss("overlay_degraded");
+  }, [callTokens?.isSharer]);
+
   const changeScreenShare = useCallback(() => {
     if (!callTokens || !callTokens.videoToken || !callTokens.isSharer) return;
     tauriUtils.createContentPickerWindow(callTokens.videoToken);
@@ -405,7 +578,8 @@ function ScreenShareIcon({ callTokens, setCallTokens }: { callTokens: CallState
 }
 
 const ListenToRemoteAudio = () => {
-  const tracks = useTracks([Track.Source.Microphone], {
+  const { callTokens } = useStore();
+  const tracks = useTracks([Track.Source.Microphone, Track.Source.ScreenShareAudio], {
     onlySubscribed: true,
   });
 
@@ -413,6 +587,8 @@ const ListenToRemoteAudio = () => {
     <>
       {tracks
         .filter((track) => track.participant instanceof RemoteParticipant)
+        // The sharer's core publishes the sharer's own system audio, playing it would capture it again
+        .filter((track) => !(callTokens?.isSharer && track.source === Track.Source.ScreenShareAudio))
         .map((track) => (
           <ParticipantTile key={`${track.participant.identity}_${track.publication.trackSid}`} trackRef={track}>
             <StartAudio label="Click to allow audio playback" />
@@ -472,3 +648,724 @@ function ScreensharingEventListener({
   }, [tracks]);
   return <div />;
 }
+
+type WaitingParticipant = {
+  sid: string;
+  name: string;
+  identity: string;
+  reason: "RoomFull" | "NeedsApproval";
+};
+
+/* The core keeps participants that aren't admitted away from the shared screen's input until the sharer lets them in */
+function WaitingRoom() {
+  const [waitingRoom, setWaitingRoom] = useState(false);
+  const [waiting, setWaiting] = useState<WaitingParticipant[]>([]);
+
+  useEffect(() => {
+    const unlistenPromise = listen<WaitingParticipant[]>("waiting_participants", (event) => {
+      setWaiting(event.payload);
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const toggleWaitingRoom = useCallback(() => {
+    const enabled = !waitingRoom;
+    tauriUtils.setAdmissionPolicy(null, enabled);
+    setWaitingRoom(enabled);
+  }, [waitingRoom]);
+
+  return (
+    <div className="flex flex-col gap-2 w-full">
+      <Button
+        className="w-full border-gray-500 text-gray-600 flex flex-row gap-2"
+        variant="gradient-white"
+        onClick={toggleWaitingRoom}
+      >
+        {waitingRoom ? "Disable" : "Enable"} waiting room
+      </Button>
+      {waiting.map((participant) => (
+        <div key={participant.sid} className="flex flex-row items-center justify-between gap-2 w-full">
+          <span className="text-sm truncate">
+            {participant.name}
+            {participant.reason === "RoomFull" && <span className="text-slate-500"> (session full)</span>}
+          </span>
+          <div className="flex flex-row gap-1">
+            <Button variant="gradient-white" size="sm" onClick={() => tauriUtils.admitParticipant(participant.sid)}>
+              Admit
+            </Button>
+            <Button variant="gradient-white" size="sm" onClick={() => tauriUtils.kickParticipant(participant.sid)}>
+              Remove
+            </Button>
+            <Button variant="gradient-white" size="sm" onClick={() => tauriUtils.banIdentity(participant.identity)}>
+              Ban
+            </Button>
+          </div>
+        </div>
+      ))}
+    </div>
+  );
+}
+
+/* Starts the share the user scheduled in the content picker when it is due */
+function ScheduledShareListener() {
+  const { callTokens, updateCallTokens } = useStore();
+  const callTokensRef = useRef(callTokens);
+  callTokensRef.current = callTokens;
+
+  useEffect(() => {
+    const unlistenPromise = listen<ScheduledShare>("scheduled_screenshare", async (event) => {
+      const tokens = callTokensRef.current;
+      if (!tokens || tokens.isSharer) return;
+      try {
+        const stream = await tauriUtils.startScheduledShare(event.payload, tokens.videoToken);
+        if (!stream) {
+          toast.error("Couldn't start your scheduled share", { duration: 5_000, position: "top-center" });
+          return;
+        }
+        updateCallTokens({
+          isSharer: true,
+          isRemoteControlEnabled: !event.payload.view_only,
+          sharedStream: { ...stream, label: "Scheduled share" },
+        });
+        toast.success("Your scheduled share started", { position: "top-center" });
+      } catch (error) {
+        console.error(error);
+      }
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  return null;
+}
+
+/* The core journals the active session, a session a crashed core left unfinished is offered to resume */
+function UncleanSessionReport() {
+  const { callTokens, updateCallTokens } = useStore();
+  const [session, setSession] = useState<UncleanSession | null>(null);
+
+  useEffect(() => {
+    tauriUtils.getUncleanSession().then(setSession).catch(console.error);
+  }, []);
+
+  if (!session) return null;
+
+  const dismiss = () => {
+    setSession(null);
+    tauriUtils.dismissUncleanSession();
+  };
+
+  const resume = async () => {
+    if (!callTokens) return;
+    try {
+      const stream = await tauriUtils.resumeUncleanSession(session, callTokens.videoToken);
+      if (!stream) {
+        toast.error("Couldn't resume your last session", { duration: 5_000, position: "top-center" });
+        return;
+      }
+      updateCallTokens({
+        isSharer: true,
+        isRemoteControlEnabled: !session.view_only,
+        sharedStream: { ...stream, label: "Resumed share" },
+      });
+      dismiss();
+    } catch (error) {
+      console.error(error);
+    }
+  };
+
+  return (
+    <div className="flex flex-col gap-1 w-full">
+      <span className="text-sm">
+        Your last session ended unexpectedly{" "}
+        <span className="text-slate-500">
+          {formatDistanceToNow(new Date(session.last_event_at * 1000), { addSuffix: true })}
+        </span>
+        , we sent a report to help us fix it.
+      </span>
+      <div className="flex flex-row gap-1">
+        <Button variant="gradient-white" size="sm" onClick={resume}>
+          Resume sharing
+        </Button>
+        <Button variant="gradient-white" size="sm" onClick={dismiss}>
+          Dismiss
+        </Button>
+      </div>
+    </div>
+  );
+}
+
+/* The core publishes the camera next to the screen share, the webview never opens it */
+function SharerCamera() {
+  const [cameras, setCameras] = useState<CameraDevice[]>([]);
+  const [deviceId, setDeviceId] = useState<string | null>(null);
+  const [enabled, setEnabled] = useState(false);
+
+  useEffect(() => {
+    tauriUtils
+      .getCameras()
+      .then((cameras) => {
+        setCameras(cameras);
+        setDeviceId(cameras[0]?.id ?? null);
+      })
+      .catch(console.error);
+
+    const unlistenPromise = listen<string>("camera_error", (event) => {
+      console.error(`Camera failed: ${event.payload}`);
+      setEnabled(false);
+      toast.error(`Couldn't share your camera: ${event.payload}`, {
+        duration: 5_000,
+        position: "top-center",
+      });
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const toggleCamera = useCallback(() => {
+    if (enabled) {
+      tauriUtils.disableCamera().catch(console.error);
+    } else {
+      tauriUtils.enableCamera(deviceId).catch(console.error);
+    }
+    setEnabled(!enabled);
+  }, [enabled, deviceId]);
+
+  const changeCamera = useCallback(
+    (id: string) => {
+      setDeviceId(id);
+      // The core switches the published camera
+      if (enabled) {
+        tauriUtils.enableCamera(id).catch(console.error);
+      }
+    },
+    [enabled],
+  );
+
+  if (cameras.length === 0) return null;
+
+  return (
+    <div className="flex flex-row items-center gap-2 w-full">
+      <Button variant="gradient-white" size="sm" onClick={toggleCamera}>
+        {enabled ? "Stop camera" : "Share camera"}
+      </Button>
+      {cameras.length > 1 && (
+        <Select value={deviceId ?? undefined} onValueChange={changeCamera}>
+          <SelectTrigger className="text-xs">
+            <SelectValue />
+          </SelectTrigger>
+          <SelectContent>
+            {cameras.map((camera) => (
+              <SelectItem key={camera.id} value={camera.id}>
+                <span className="text-xs truncate">{camera.name}</span>
+              </SelectItem>
+            ))}
+          </SelectContent>
+        </Select>
+      )}
+    </div>
+  );
+}
+
+/* The core publishes the audio the sharer's machine plays next to the screen share, where the platform allows it */
+function SystemAudio() {
+  const [supported, setSupported] = useState(false);
+  const [enabled, setEnabled] = useState(false);
+
+  useEffect(() => {
+    tauriUtils
+      .getSystemAudioSupport()
+      .then(setSupported)
+      .catch((e) => console.error("Failed to get the system audio support", e));
+  }, []);
+
+  useEffect(() => {
+    const unlistenPromise = listen<string>("system_audio_error", (event) => {
+      console.error(`System audio failed: ${event.payload}`);
+      setEnabled(false);
+      toast.error(`Couldn't share your computer's audio: ${event.payload}`, {
+        duration: 5_000,
+        position: "top-center",
+      });
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const toggleSystemAudio = useCallback(() => {
+    if (enabled) {
+      tauriUtils.disableSystemAudio().catch(console.error);
+    } else {
+      tauriUtils.enableSystemAudio().catch(console.error);
+    }
+    setEnabled(!enabled);
+  }, [enabled]);
+
+  if (!supported) return null;
+
+  return (
+    <div className="flex flex-row items-center gap-2 w-full">
+      <Button variant="gradient-white" size="sm" onClick={toggleSystemAudio}>
+        {enabled ? "Stop computer audio" : "Share computer audio"}
+      </Button>
+    </div>
+  );
+}
+
+/* The core publishes the microphone with webrtc's own echo cancellation instead of the webview's audio constraints */
+function CoreMicrophone() {
+  const { updateCallTokens } = useStore();
+  const [microphones, setMicrophones] = useState<MicrophoneDevice[]>([]);
+  const [deviceId, setDeviceId] = useState<string | null>(null);
+  const [echoCancellation, setEchoCancellation] = useState(true);
+  const [noiseSuppression, setNoiseSuppression] = useState(true);
+  const [enabled, setEnabled] = useState(false);
+
+  useEffect(() => {
+    tauriUtils
+      .getMicrophones()
+      .then((microphones) => {
+        setMicrophones(microphones);
+        setDeviceId(microphones[0]?.id ?? null);
+      })
+      .catch(console.error);
+
+    const unlistenPromise = listen<string>("mic_error", (event) => {
+      console.error(`Microphone failed: ${event.payload}`);
+      setEnabled(false);
+      toast.error(`Couldn't send your microphone: ${event.payload}`, {
+        duration: 5_000,
+        position: "top-center",
+      });
+    });
+
+    const unlistenOverloadedPromise = listen("noise_suppression_overloaded", () => {
+      setNoiseSuppression(false);
+      toast("Noise suppression was turned off, your computer is too busy", {
+        duration: 5_000,
+        position: "top-center",
+      });
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+      unlistenOverloadedPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const publish = useCallback(
+    (id: string | null, withEchoCancellation: boolean) => {
+      // The webview stops publishing its microphone, the participants would hear the sharer twice
+      updateCallTokens({ hasAudioEnabled: false });
+      tauriUtils
+        .enableMic(id, {
+          echo_cancellation: withEchoCancellation,
+          noise_suppression: noiseSuppression,
+          auto_gain_control: true,
+        })
+        .catch(console.error);
+    },
+    [updateCallTokens, noiseSuppression],
+  );
+
+  const toggleMicrophone = useCallback(() => {
+    if (enabled) {
+      tauriUtils.disableMic().catch(console.error);
+    } else {
+      publish(deviceId, echoCancellation);
+    }
+    setEnabled(!enabled);
+  }, [enabled, deviceId, echoCancellation, publish]);
+
+  const changeMicrophone = useCallback(
+    (id: string) => {
+      setDeviceId(id);
+      // The core republishes the track with the new microphone
+      if (enabled) publish(id, echoCancellation);
+    },
+    [enabled, echoCancellation, publish],
+  );
+
+  const toggleEchoCancellation = useCallback(() => {
+    setEchoCancellation(!echoCancellation);
+    if (enabled) publish(deviceId, !echoCancellation);
+  }, [enabled, deviceId, echoCancellation, publish]);
+
+  const toggleNoiseSuppression = useCallback(() => {
+    setNoiseSuppression(!noiseSuppression);
+    // The core republishes the published microphone, the next one gets it with enableMic
+    tauriUtils.setNoiseSuppression(!noiseSuppression).catch(console.error);
+  }, [noiseSuppression]);
+
+  if (microphones.length === 0) return null;
+
+  return (
+    <div className="flex flex-row flex-wrap items-center gap-2 w-full">
+      <Button variant="gradient-white" size="sm" onClick={toggleMicrophone}>
+        {enabled ? "Stop Hopp microphone" : "Send microphone from Hopp"}
+      </Button>
+      <Button variant="gradient-white" size="sm" onClick={toggleEchoCancellation}>
+        {echoCancellation ? "Echo cancellation on" : "Echo cancellation off"}
+      </Button>
+      <Button variant="gradient-white" size="sm" onClick={toggleNoiseSuppression}>
+        {noiseSuppression ? "Noise suppression on" : "Noise suppression off"}
+      </Button>
+      {microphones.length > 1 && (
+        <Select value={deviceId ?? undefined} onValueChange={changeMicrophone}>
+          <SelectTrigger className="text-xs">
+            <SelectValue />
+          </SelectTrigger>
+          <SelectContent>
+            {microphones.map((microphone) => (
+              <SelectItem key={microphone.id} value={microphone.id}>
+                <span className="text-xs truncate">{microphone.name}</span>
+              </SelectItem>
+            ))}
+          </SelectContent>
+        </Select>
+      )}
+    </div>
+  );
+}
+
+/* By default the core only types the keystrokes of the controller that clicked last, so a controller can't type into another one's drag */
+function KeyboardArbitrationToggle() {
+  const [policy, setPolicy] = useState<KeyboardArbitration>("LastClicked");
+
+  const togglePolicy = useCallback(() => {
+    const next = policy === "LastClicked" ? "Anyone" : "LastClicked";
+    tauriUtils.setKeyboardArbitration(next);
+    setPolicy(next);
+  }, [policy]);
+
+  return (
+    <Button
+      className="w-full border-gray-500 text-gray-600 flex flex-row gap-2"
+      variant="gradient-white"
+      onClick={togglePolicy}
+    >
+      {policy === "LastClicked" ? "Let every controller type" : "Only the last controller to click types"}
+    </Button>
+  );
+}
+
+const KEYBOARD_LAYOUTS: { value: KeyboardLayoutKind | "Detected"; label: string }[] = [
+  { value: "Detected", label: "Detected layout" },
+  { value: "Us", label: "US" },
+  { value: "French", label: "French (AZERTY)" },
+  { value: "German", label: "German (QWERTZ)" },
+  { value: "Greek", label: "Greek" },
+];
+
+/* The controllers' keys are mapped through the sharer's detected layout unless one is forced here */
+function KeyboardLayoutSelect() {
+  const [layout, setLayout] = useState<KeyboardLayoutKind | "Detected">("Detected");
+  const [detected, setDetected] = useState<string | null>(null);
+
+  useEffect(() => {
+    tauriUtils
+      .getInputInjectionStatus()
+      .then((status) => setDetected(status.keyboard_layout))
+      .catch(console.error);
+  }, []);
+
+  const changeLayout = useCallback((value: string) => {
+    const next = value as KeyboardLayoutKind | "Detected";
+    tauriUtils.setKeyboardLayout(next === "Detected" ? null : next).catch(console.error);
+    setLayout(next);
+  }, []);
+
+  return (
+    <div className="flex flex-col gap-1 w-full">
+      <Select value={layout} onValueChange={changeLayout}>
+        <SelectTrigger className="text-xs">
+          <SelectValue />
+        </SelectTrigger>
+        <SelectContent>
+          {KEYBOARD_LAYOUTS.map(({ value, label }) => (
+            <SelectItem key={value} value={value}>
+              <span className="text-xs">{label}</span>
+            </SelectItem>
+          ))}
+        </SelectContent>
+      </Select>
+      {detected && <span className="text-xs text-slate-500 truncate">Detected: {detected}</span>}
+    </div>
+  );
+}
+
+/* The core syncs the copied text with the controllers only while this is on, every session starts with it off */
+function ClipboardSharingToggle() {
+  const [enabled, setEnabled] = useState(false);
+
+  const toggle = useCallback(() => {
+    tauriUtils.setClipboardSharing(!enabled).catch(console.error);
+    setEnabled(!enabled);
+  }, [enabled]);
+
+  return (
+    <Button
+      className="w-full border-gray-500 text-gray-600 flex flex-row gap-2"
+      variant="gradient-white"
+      onClick={toggle}
+    >
+      {enabled ? "Stop sharing the clipboard" : "Share the clipboard"}
+    </Button>
+  );
+}
+
+/* Shows the sharer what the participants see, the core sends a few frames per second while it is open */
+function SharePreview() {
+  const [enabled, setEnabled] = useState(false);
+  const [frameUrl, setFrameUrl] = useState<string | null>(null);
+
+  const replaceFrame = useCallback((url: string | null) => {
+    setFrameUrl((previous) => {
+      if (previous) URL.revokeObjectURL(previous);
+      return url;
+    });
+  }, []);
+
+  useEffect(() => {
+    if (!enabled) return;
+    tauriUtils.setSharePreview(true).catch(console.error);
+    const unlistenPromise = listen("share_preview_updated", async () => {
+      const frame = await tauriUtils.getSharePreview();
+      if (frame.byteLength === 0) return;
+      replaceFrame(URL.createObjectURL(new Blob([frame], { type: "image/jpeg" })));
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+      tauriUtils.setSharePreview(false).catch(console.error);
+      replaceFrame(null);
+    };
+  }, [enabled, replaceFrame]);
+
+  return (
+    <div className="flex flex-col items-start gap-2 w-full">
+      <Button
+        className="w-full border-gray-500 text-gray-600 flex flex-row gap-2"
+        variant="gradient-white"
+        onClick={() => setEnabled(!enabled)}
+      >
+        {enabled ? "Hide the preview" : "Preview the share"}
+      </Button>
+      {enabled && frameUrl && <img src={frameUrl} alt="Shared content preview" className="w-full rounded-md" />}
+    </div>
+  );
+}
+
+/* Keeps the controllers' typing in the apps worked on together, a focus change can't send it to a chat or an email */
+function KeyboardAppAllowlist() {
+  const [apps, setApps] = useState("");
+
+  const saveApps = useCallback(() => {
+    const allowlist = apps
+      .split(",")
+      .map((app) => app.trim())
+      .filter((app) => app.length > 0);
+    tauriUtils.setKeyboardAppAllowlist(allowlist).catch(console.error);
+  }, [apps]);
+
+  return (
+    <div className="flex flex-col items-start gap-2 w-full">
+      <span className="small">Only type into apps:</span>
+      <Input
+        value={apps}
+        placeholder="Code, com.apple.dt.Xcode"
+        onChange={(event) => setApps(event.target.value)}
+        onBlur={saveApps}
+        onKeyDown={(event) => event.key === "Enter" && saveApps()}
+      />
+    </div>
+  );
+}
+
+const INPUT_AUTHORIZATIONS_POLL_MS = 2000;
+
+/* The core only injects the clicks, keystrokes and scrolls of the controllers the sharer allowed, every session starts with nobody allowed. The sharer can also hide a participant's cursor to declutter the overlay */
+function InputAuthorizations() {
+  const [participants, setParticipants] = useState<SessionParticipant[]>([]);
+
+  const refresh = useCallback(() => {
+    tauriUtils
+      .getSessionState()
+      .then((state) => setParticipants(state.participants))
+      .catch(console.error);
+  }, []);
+
+  useEffect(() => {
+    refresh();
+    // The core doesn't notify about joining controllers, poll the session
+    const interval = setInterval(refresh, INPUT_AUTHORIZATIONS_POLL_MS);
+    return () => clearInterval(interval);
+  }, [refresh]);
+
+  const toggle = useCallback(
+    async (participant: SessionParticipant) => {
+      await tauriUtils.setInputAuthorized(participant.sid, !participant.input_authorized);
+      refresh();
+    },
+    [refresh],
+  );
+
+  const toggleCursor = useCallback(
+    async (participant: SessionParticipant) => {
+      await tauriUtils.setCursorVisible(participant.sid, participant.cursor_hidden);
+      refresh();
+    },
+    [refresh],
+  );
+
+  if (participants.length === 0) return null;
+
+  return (
+    <div className="flex flex-col gap-1 w-full">
+      {participants.map((participant) => (
+        <div key={participant.sid} className="flex flex-row items-center justify-between gap-2 w-full">
+          <span className="text-sm truncate">
+            {participant.name}
+            {!participant.input_authorized && <span className="text-slate-500"> (view only)</span>}
+          </span>
+          <div className="flex flex-row gap-1">
+            <Button variant="gradient-white" size="sm" onClick={() => toggleCursor(participant)}>
+              {participant.cursor_hidden ? "Show cursor" : "Hide cursor"}
+            </Button>
+            <Button variant="gradient-white" size="sm" onClick={() => toggle(participant)}>
+              {participant.input_authorized ? "Revoke control" : "Allow control"}
+            </Button>
+          </div>
+        </div>
+      ))}
+    </div>
+  );
+}
+
+type ControlRequest = {
+  sid: string;
+  name: string;
+  identity: string;
+};
+
+/* Participants raise their hand to ask for control, the core keeps them in order and the sharer hands control to the next one */
+function ControlRequestQueue() {
+  const [requests, setRequests] = useState<ControlRequest[]>([]);
+
+  useEffect(() => {
+    const unlistenPromise = listen<ControlRequest[]>("control_requests", (event) => {
+      setRequests(event.payload);
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  if (requests.length === 0) return null;
+
+  return (
+    <div className="flex flex-col gap-1 w-full">
+      {requests.map((request, index) => (
+        <span key={request.sid} className="text-sm truncate">
+          <span className="text-slate-500">#{index + 1}</span> {request.name}
+        </span>
+      ))}
+      <Button
+        className="w-full border-gray-500 text-gray-600"
+        variant="gradient-white"
+        onClick={() => tauriUtils.grantNextControl()}
+      >
+        Give control to {requests[0]?.name}
+      </Button>
+    </div>
+  );
+}
+
+type ParticipantQuality = {
+  sid: string;
+  name: string;
+  quality: "Excellent" | "Good" | "Poor" | "Lost";
+};
+
+/* The core relays LiveKit's connection quality of the viewers, so the sharer can lower the resolution or stop the video for them */
+function ParticipantQualities() {
+  const [qualities, setQualities] = useState<ParticipantQuality[]>([]);
+
+  useEffect(() => {
+    // Only sent when a quality changes, seed the list after a reload
+    tauriUtils
+      .getSessionState()
+      .then((state) =>
+        setQualities(
+          state.participants.flatMap(({ sid, name, quality }) => (quality ? [{ sid, name, quality }] : [])),
+        ),
+      )
+      .catch(console.error);
+
+    const unlistenPromise = listen<ParticipantQuality[]>("participant_qualities", (event) => {
+      setQualities(event.payload);
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const degraded = qualities.filter((participant) => participant.quality === "Poor" || participant.quality === "Lost");
+  if (degraded.length === 0) return null;
+
+  return (
+    <div className="flex flex-col gap-1 w-full">
+      {degraded.map((participant) => (
+        <span key={participant.sid} className="text-sm text-amber-700 truncate">
+          {participant.name} {participant.quality === "Lost" ? "lost their connection" : "is on a poor connection"}
+        </span>
+      ))}
+    </div>
+  );
+}
+
+// Asks the core instead of trusting the picker, the share is view-only only if no injection was created
+// What the core publishes, lets the sharer check that the picked resolution was honored
+function SharedStreamStatus({ stream }: { stream: SharedStream }) {
+  const { width, height } = stream.resolution;
+  const details = [
+    stream.bitrate !== null ? `up to ${(stream.bitrate / 1_000_000).toFixed(1)} Mbps` : null,
+    stream.display_scale !== null ? `display scale ${stream.display_scale}x` : null,
+  ].filter(Boolean);
+
+  return (
+    <span className="text-sm w-full text-slate-600" title={details.join(", ")}>
+      {`Sharing ${stream.label} at ${width}×${height} @${stream.fps}fps ${stream.codec}`}
+    </span>
+  );
+}
+
+function ViewOnlyStatus() {
+  const [status, setStatus] = useState<InputInjectionStatus | null>(null);
+
+  useEffect(() => {
+    tauriUtils.getInputInjectionStatus().then(setStatus).catch(console.error);
+  }, []);
+
+  if (!status?.sharing) return null;
+
+  const verified = status.view_only && !status.keyboard && !status.mouse;
+  return (
+    <span className={clsx("text-sm w-full", verified ? "text-slate-600" : "text-amber-700")}>
+      {verified ?
+        "View only, no keyboard or mouse input can reach this computer"
+      : "View only requested, but remote input is still enabled"}
+    </span>
+  );
+}
@@ -39,13 +39,16 @@ const SvgComponent = (props: any) => (
 export interface CursorProps extends React.SVGAttributes<SVGSVGElement> {
   color?: string;
   name?: string;
+  // Shown on hover when the name is shortened
+  fullName?: string;
 }
 
 const Cursor = (props: CursorProps) => {
+  const { fullName, ...svgProps } = props;
   return (
     <div className="absolute" style={{ ...props.style }}>
       <div className="relative flex flex-col justify-start max-w-[120px]">
-        <SvgComponent {...props} />
+        <SvgComponent {...svgProps} />
         <div
           className="outline outline-[1px] outline-slate-200/50 outline-offset-[-1px] shadow-sm font-mono text-ellipsis overflow-hidden text-[10px] max-w-min text-white whitespace-nowrap px-2 py-[0px] leading-[22px] rounded-xl"
           style={{
@@ -53,6 +56,7 @@ const Cursor = (props: CursorProps) => {
             marginLeft: "12px",
             marginTop: "-6px",
           }}
+          title={fullName ?? props.name}
         >
           {props.name}
         </div>
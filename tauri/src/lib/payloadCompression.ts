@@ -0,0 +1,48 @@
+import logger from "./logger";
+
+/**
+ * Decoding of the sharer's data-channel payloads.
+ *
+ * Payloads above the core's compression threshold are raw deflate streams
+ * prefixed with COMPRESSED_PAYLOAD_FLAG, a byte a JSON payload never starts with.
+ * The sharer only compresses once we announced COMPRESSION_DEFLATE, see
+ * `core/src/room/compression.rs`.
+ */
+
+// The compression this client inflates, announced in its capabilities
+export const COMPRESSION_DEFLATE = "deflate";
+
+// First byte of a deflated payload
+const COMPRESSED_PAYLOAD_FLAG = 0x01;
+
+const decoder = new TextDecoder();
+
+// Set while a compressed payload is inflated, the payloads after it wait so they're handled in order
+let pending: Promise<void> | null = null;
+
+async function inflate(deflated: Uint8Array): Promise<Uint8Array> {
+  const stream = new Blob([deflated]).stream().pipeThrough(new DecompressionStream("deflate-raw"));
+  return new Uint8Array(await new Response(stream).arrayBuffer());
+}
+
+/**
+ * Parses a JSON payload, inflating it first if it was compressed, and passes it to `handle`.
+ * Uncompressed payloads are handled right away unless a compressed one is still inflating.
+ */
+export function decodePayload<T>(payload: Uint8Array, handle: (payload: T) => void) {
+  if (!pending && payload[0] !== COMPRESSED_PAYLOAD_FLAG) {
+    handle(JSON.parse(decoder.decode(payload)));
+    return;
+  }
+
+  const current = (pending ?? Promise.resolve())
+    .then(async () => {
+      const json = payload[0] === COMPRESSED_PAYLOAD_FLAG ? await inflate(payload.subarray(1)) : payload;
+      handle(JSON.parse(decoder.decode(json)));
+    })
+    .catch((e) => logger.error("Failed to decode payload", e));
+  pending = current;
+  current.finally(() => {
+    if (pending === current) pending = null;
+  });
+}
@@ -0,0 +1,88 @@
+import { useDataChannel, useLocalParticipant } from "@livekit/components-react";
+import { useEffect } from "react";
+import { TPClockSyncRequest, TPClockSyncResponse } from "@/payloads";
+import { decodePayload } from "@/lib/payloadCompression";
+
+/**
+ * Session timeline shared by the participants.
+ *
+ * The sharer's core keeps the session clock, we estimate our offset to it
+ * NTP style: the request carries our send time (t0), the core answers with
+ * when it received the request (t1) and when it answered (t2), and we note
+ * when the answer arrived (t3). Mirrors `room::clock_sync` in the core.
+ */
+
+const CLOCK_SYNC_TOPIC = "clock_sync";
+// Number of recent samples the offset is estimated from
+const CLOCK_SYNC_SAMPLES = 8;
+const CLOCK_SYNC_INTERVAL_MS = 10_000;
+// Syncs quickly until the first samples are in
+const CLOCK_SYNC_FAST_INTERVAL_MS = 1_000;
+
+type ClockSample = { t0: number; t1: number; t2: number; t3: number };
+
+const encoder = new TextEncoder();
+let samples: ClockSample[] = [];
+
+const roundTrip = ({ t0, t1, t2, t3 }: ClockSample) => t3 - t0 - (t2 - t1);
+
+const addSample = (sample: ClockSample) => {
+  if (sample.t3 < sample.t0 || sample.t2 < sample.t1) return;
+  samples = [...samples.slice(-(CLOCK_SYNC_SAMPLES - 1)), sample];
+};
+
+/**
+ * Returns what needs to be added to `Date.now()` to get the session time,
+ * `null` before the first sync. Like NTP the sample with the shortest round
+ * trip is trusted, queueing delays make the others asymmetric.
+ */
+export const sessionClockOffset = (): number | null => {
+  if (samples.length === 0) return null;
+  const best = samples.reduce((a, b) => (roundTrip(b) < roundTrip(a) ? b : a));
+  return Math.round((best.t1 - best.t0 + (best.t2 - best.t3)) / 2);
+};
+
+/**
+ * Returns the current time on the session timeline in milliseconds, `null` before the first sync.
+ */
+export const sessionNow = (): number | null => {
+  const offset = sessionClockOffset();
+  return offset === null ? null : Date.now() + offset;
+};
+
+/**
+ * Keeps the session clock offset up to date while mounted in a LiveKit room.
+ */
+export const useSessionClockSync = () => {
+  const { localParticipant } = useLocalParticipant();
+
+  useDataChannel(CLOCK_SYNC_TOPIC, (msg) => {
+    const t3 = Date.now();
+    decodePayload<TPClockSyncResponse>(msg.payload, (payload) => {
+      if (payload.type !== "ClockSyncResponse" || payload.payload.sid !== localParticipant.sid) return;
+      const { t0, t1, t2 } = payload.payload;
+      addSample({ t0, t1, t2, t3 });
+    });
+  });
+
+  useEffect(() => {
+    samples = [];
+    let timeout: ReturnType<typeof setTimeout>;
+
+    const sync = () => {
+      const request: TPClockSyncRequest = {
+        type: "ClockSyncRequest",
+        payload: { t0: Date.now() },
+      };
+      localParticipant.publishData(encoder.encode(JSON.stringify(request)), {
+        reliable: true,
+        topic: CLOCK_SYNC_TOPIC,
+      });
+      const interval = samples.length < CLOCK_SYNC_SAMPLES / 2 ? CLOCK_SYNC_FAST_INTERVAL_MS : CLOCK_SYNC_INTERVAL_MS;
+      timeout = setTimeout(sync, interval);
+    };
+    sync();
+
+    return () => clearTimeout(timeout);
+  }, [localParticipant]);
+};
@@ -6,13 +6,22 @@ export const PToken = z.object({
 
 export type TPToken = z.infer<typeof PToken>;
 
+export const PInputAuth = z.object({
+  token: z.string(),
+  seq: z.number(),
+});
+export type TPInputAuth = z.infer<typeof PInputAuth>;
+
 export const PMouseMove = z.object({
   type: z.literal("MouseMove"),
   payload: z.object({
     x: z.number(),
     y: z.number(),
     pointer: z.boolean(),
+    seq: z.number().optional(),
   }),
+  // The moves of the controller in control move the sharer's pointer, the core checks only the token
+  auth: PInputAuth.optional(),
 });
 export type TPMouseMove = z.infer<typeof PMouseMove>;
 
@@ -24,6 +33,68 @@ export const PRemoteControlEnabled = z.object({
 });
 export type TPRemoteControlEnabled = z.infer<typeof PRemoteControlEnabled>;
 
+export const PSessionMode = z.object({
+  type: z.literal("SessionMode"),
+  payload: z.object({
+    view_only: z.boolean(),
+  }),
+});
+export type TPSessionMode = z.infer<typeof PSessionMode>;
+
+// Sent by the sharer whenever the share changes and when we join, before the first frames arrive
+export const PShareConfig = z.object({
+  type: z.literal("ShareConfig"),
+  payload: z.object({
+    width: z.number(),
+    height: z.number(),
+    framerate: z.number(),
+    content_type: z
+      .union([z.literal("Display"), z.object({ Window: z.object({ display_id: z.number() }) })])
+      .nullable(),
+    paused: z.boolean(),
+    view_only: z.boolean(),
+    // The YUV matrix the video was encoded with, older sharers only sent BT.601
+    color_matrix: z.enum(["bt601", "bt709"]).optional(),
+  }),
+});
+export type TPShareConfig = z.infer<typeof PShareConfig>;
+
+// The sharer only injects input stamped with the token it granted to our identity
+export const PInputGrant = z.object({
+  type: z.literal("InputGrant"),
+  payload: z.object({
+    token: z.string().nullable(),
+  }),
+});
+export type TPInputGrant = z.infer<typeof PInputGrant>;
+
+// Only sent to our identity, the sharer drops the input of clients older than min_version
+export const PUpgradeRequired = z.object({
+  type: z.literal("UpgradeRequired"),
+  payload: z.object({
+    min_version: z.number(),
+  }),
+});
+export type TPUpgradeRequired = z.infer<typeof PUpgradeRequired>;
+
+// Asks the sharer for control, the core queues the raised hands in order
+export const PRaiseHand = z.object({
+  type: z.literal("RaiseHand"),
+  payload: z.object({
+    raised: z.boolean(),
+  }),
+});
+export type TPRaiseHand = z.infer<typeof PRaiseHand>;
+
+// Push-to-control, the sharer only injects our input while the modifier is held
+export const PControlHold = z.object({
+  type: z.literal("ControlHold"),
+  payload: z.object({
+    active: z.boolean(),
+  }),
+});
+export type TPControlHold = z.infer<typeof PControlHold>;
+
 export const PMouseClick = z.object({
   type: z.literal("MouseClick"),
   payload: z.object({
@@ -37,6 +108,7 @@ export const PMouseClick = z.object({
     ctrl: z.boolean(),
     meta: z.boolean(),
   }),
+  auth: PInputAuth.optional(),
 });
 export type TPMouseClick = z.infer<typeof PMouseClick>;
 
@@ -54,6 +126,7 @@ export const PWheelEvent = z.object({
     deltaX: z.number(),
     deltaY: z.number(),
   }),
+  auth: PInputAuth.optional(),
 });
 export type TPWheelEvent = z.infer<typeof PWheelEvent>;
 
@@ -67,9 +140,101 @@ export const PKeystroke = z.object({
     shift: z.boolean(),
     down: z.boolean(),
   }),
+  auth: PInputAuth.optional(),
 });
 export type TPKeystroke = z.infer<typeof PKeystroke>;
 
+// Clipboard sync, the sharer sends what it copies and we send ours when our window gets the focus
+export const PClipboardUpdate = z.object({
+  type: z.literal("ClipboardUpdate"),
+  payload: z.object({
+    text: z.string(),
+  }),
+  auth: PInputAuth.optional(),
+});
+export type TPClipboardUpdate = z.infer<typeof PClipboardUpdate>;
+
+export const PShareInterruption = z.discriminatedUnion("type", [
+  z.object({
+    type: z.literal("ShareInterrupted"),
+    payload: z.object({
+      reason: z.enum(["DisplaySleep", "ScreenLocked", "Screensaver", "WindowNotVisible"]),
+    }),
+  }),
+  z.object({
+    type: z.literal("ShareResumed"),
+  }),
+]);
+export type TPShareInterruption = z.infer<typeof PShareInterruption>;
+
+// Answer to the sharer's capabilities, the core only uses the features every participant handles
+export const PCapabilities = z.object({
+  type: z.literal("Capabilities"),
+  payload: z.object({
+    compression: z.array(z.string()),
+    version: z.number(),
+    features: z.array(z.enum(["annotations", "chat", "file_transfer", "pen_input"])),
+  }),
+});
+export type TPCapabilities = z.infer<typeof PCapabilities>;
+
+export const PSharerCursorShape = z.object({
+  type: z.literal("SharerCursorShape"),
+  payload: z.object({
+    shape: z.enum([
+      "Arrow",
+      "IBeam",
+      "Hand",
+      "Crosshair",
+      "ResizeHorizontal",
+      "ResizeVertical",
+      "ResizeNwse",
+      "ResizeNesw",
+      "Move",
+      "Wait",
+      "NotAllowed",
+    ]),
+  }),
+});
+export type TPSharerCursorShape = z.infer<typeof PSharerCursorShape>;
+
+export const PClockSyncRequest = z.object({
+  type: z.literal("ClockSyncRequest"),
+  payload: z.object({
+    t0: z.number(),
+  }),
+});
+export type TPClockSyncRequest = z.infer<typeof PClockSyncRequest>;
+
+export const PClockSyncResponse = z.object({
+  type: z.literal("ClockSyncResponse"),
+  payload: z.object({
+    sid: z.string(),
+    t0: z.number(),
+    t1: z.number(),
+    t2: z.number(),
+  }),
+});
+export type TPClockSyncResponse = z.infer<typeof PClockSyncResponse>;
+
+export const PAdmission = z.object({
+  type: z.literal("Admission"),
+  payload: z.object({
+    sid: z.string(),
+    waiting: z.enum(["RoomFull", "NeedsApproval"]).nullable(),
+  }),
+});
+export type TPAdmission = z.infer<typeof PAdmission>;
+
+export const PParticipantRemoved = z.object({
+  type: z.literal("ParticipantRemoved"),
+  payload: z.object({
+    sid: z.string(),
+    banned: z.boolean(),
+  }),
+});
+export type TPParticipantRemoved = z.infer<typeof PParticipantRemoved>;
+
 // WebSocket Message Types
 export const MessageType = z.enum([
   "success",
@@ -6,6 +6,7 @@ import { isEqual } from "lodash";
 import { emit, listen } from "@tauri-apps/api/event";
 import { TCallTokensMessage } from "@/payloads";
 import { getCurrentWindow } from "@tauri-apps/api/window";
+import type { StreamParameters } from "@/windows/window-utils";
 
 const windowName = getCurrentWindow().label;
 
@@ -20,8 +21,15 @@ export type CallState = {
   isController: boolean;
   isRemoteControlEnabled: boolean;
   isRoomCall?: boolean;
+  // What the sharer publishes, set by the content picker when the share started
+  sharedStream?: SharedStream;
 } & TCallTokensMessage["payload"];
 
+export type SharedStream = StreamParameters & {
+  // Name of the shared content, e.g. "Display 2"
+  label: string;
+};
+
 type State = {
   authToken: string | null;
   needsUpdate: boolean;
@@ -1,22 +1,34 @@
 import "@/services/sentry";
 import "../../App.css";
-import React, { useCallback, useEffect, useState } from "react";
+import React, { useCallback, useEffect, useRef, useState } from "react";
 import ReactDOM from "react-dom/client";
 import { invoke, isTauri } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 import { AspectRatio } from "@/components/ui/aspect-ratio";
 import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select";
 import { toast, Toaster } from "react-hot-toast";
-import useStore from "@/store/store";
+import useStore, { type SharedStream } from "@/store/store";
 import { Button } from "@/components/ui/button";
 import { Alert, AlertDescription, AlertTitle } from "@/components/ui/alert";
-import { HiOutlineExclamationCircle } from "react-icons/hi2";
+import { HiOutlineExclamationCircle, HiOutlineEyeSlash } from "react-icons/hi2";
+import { Input } from "@/components/ui/input";
 import { useDisableNativeContextMenu } from "@/lib/hooks";
-import { tauriUtils } from "../window-utils";
+import { tauriUtils, type StreamParameters, type ThumbnailPrivacy } from "../window-utils";
 
 const appWindow = getCurrentWebviewWindow();
 
 type ResolutionKey = "1080p" | "2K" | "1440p" | "2160p" | "4K";
+type Resolution = { width: number; height: number };
+const RESOLUTIONS: Record<ResolutionKey, Resolution> = {
+  "1080p": { width: 1920, height: 1080 },
+  "2K": { width: 2048, height: 1080 },
+  "1440p": { width: 2560, height: 1440 },
+  "2160p": { width: 3840, height: 2160 },
+  "4K": { width: 4096, height: 2160 },
+};
+// How the core scales the shared display to the resolution
+type AspectPolicy = "Fit" | "Fill" | "Stretch" | "Native";
 
 ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
@@ -31,6 +43,20 @@ interface CaptureContent {
   };
   base64: string;
   title: string;
+  // The core redacted the thumbnail because the content shows a blocked app
+  redacted?: boolean;
+  // Resolutions matching the display's geometry, empty when the core doesn't know its size
+  presets?: ResolutionPreset[];
+}
+
+type ResolutionPreset = {
+  label: string;
+  resolution: Resolution;
+};
+
+// The preset named like the chosen resolution, otherwise the display's own resolution
+function defaultPreset(presets: ResolutionPreset[], resolution: ResolutionKey) {
+  return presets.find((preset) => preset.label === resolution) ?? presets[presets.length - 1];
 }
 
 async function getContent(setContent: React.Dispatch<React.SetStateAction<CaptureContent[]>>) {
@@ -39,23 +65,120 @@ async function getContent(setContent: React.Dispatch<React.SetStateAction<Captur
   setContent(message);
 }
 
-async function screenshare(content: CaptureContent["content"], resolution: ResolutionKey, videoToken: string) {
-  const resolutionMap: Record<ResolutionKey, { width: number; height: number }> = {
-    "1080p": { width: 1920, height: 1080 },
-    "2K": { width: 2048, height: 1080 },
-    "1440p": { width: 2560, height: 1440 },
-    "2160p": { width: 3840, height: 2160 },
-    "4K": { width: 4096, height: 2160 },
-  };
+// Numbers the displays in the order they are shown, the core flashes the same numbers on the displays
+function displayLabels(content: CaptureContent[]) {
+  const labels = new Map<number, string>();
+  content
+    .filter((item) => item.content.content_type === "Display")
+    .forEach((item, index) => labels.set(item.content.id, `${index + 1}`));
+  return labels;
+}
 
-  const message: boolean = await invoke("screenshare", {
+async function identifyDisplays(labels: Map<number, string>) {
+  const displays = Array.from(labels, ([id, label]) => ({ id, label }));
+  await invoke("identify_displays", { displays });
+}
+
+async function screenshare(
+  content: CaptureContent["content"],
+  resolution: Resolution,
+  videoToken: string,
+  viewOnly: boolean,
+  aspectPolicy: AspectPolicy,
+) {
+  // null when the share didn't start
+  const message: StreamParameters | null = await invoke("screenshare", {
     content: content,
     token: videoToken,
-    resolution: resolutionMap[resolution],
+    resolution,
+    viewOnly,
+    aspectPolicy,
   });
   return message;
 }
 
+// Unix time in seconds of the next "HH:MM", tomorrow's when it already passed today
+function nextStartAt(time: string) {
+  const [hours, minutes] = time.split(":").map(Number);
+  const start = new Date();
+  start.setHours(hours, minutes, 0, 0);
+  if (start.getTime() <= Date.now()) {
+    start.setDate(start.getDate() + 1);
+  }
+  return Math.floor(start.getTime() / 1000);
+}
+
+// Lets the user block apps whose windows are kept out of the thumbnails
+function ThumbnailPrivacySettings({ onChange }: { onChange: () => void }) {
+  const [privacy, setPrivacy] = useState<ThumbnailPrivacy | null>(null);
+  const [apps, setApps] = useState("");
+
+  useEffect(() => {
+    tauriUtils
+      .getThumbnailPrivacy()
+      .then((privacy) => {
+        setPrivacy(privacy);
+        setApps(privacy.blocked_apps.join(", "));
+      })
+      .catch(console.error);
+  }, []);
+
+  const save = async (update: Partial<ThumbnailPrivacy>) => {
+    if (!privacy) return;
+    const next = { ...privacy, ...update };
+    setPrivacy(next);
+    await tauriUtils.setThumbnailPrivacy(next);
+    onChange();
+  };
+
+  const saveApps = () => {
+    const blocked_apps = apps
+      .split(",")
+      .map((app) => app.trim())
+      .filter((app) => app.length > 0);
+    save({ blocked_apps }).catch(console.error);
+  };
+
+  if (!privacy) return null;
+
+  return (
+    <div className="flex flex-col items-start gap-2 px-4 py-2">
+      <span className="small">Hide previews of apps:</span>
+      <div className="flex flex-row w-full gap-2">
+        <Input
+          value={apps}
+          placeholder="1Password, com.apple.Passwords"
+          onChange={(event) => setApps(event.target.value)}
+          onBlur={saveApps}
+          onKeyDown={(event) => event.key === "Enter" && saveApps()}
+        />
+        <Select
+          value={privacy.redaction}
+          onValueChange={(value) =>
+            save({ redaction: value as ThumbnailPrivacy["redaction"] }).catch(console.error)
+          }
+        >
+          <SelectTrigger className="w-[140px]">
+            <SelectValue />
+          </SelectTrigger>
+          <SelectContent>
+            <SelectItem value="Blur">Blur</SelectItem>
+            <SelectItem value="Placeholder">Placeholder</SelectItem>
+          </SelectContent>
+        </Select>
+      </div>
+      <label className="flex flex-row items-center gap-2 small">
+        <input
+          type="checkbox"
+          checked={privacy.generic_titles}
+          onChange={(event) => save({ generic_titles: event.target.checked }).catch(console.error)}
+        />
+        Hide window and display titles
+      </label>
+    </div>
+  );
+}
+
 function Window() {
   useDisableNativeContextMenu();
   const [content, setContent] = useState<CaptureContent[]>([]);
@@ -63,6 +186,9 @@ function Window() {
   const [hasEmptyContentFromBackend, setHasEmptyContentFromBackend] = useState(false);
   const videoToken = tauriUtils.getVideoTokenParam();
   const { callTokens, setCallTokens } = useStore();
+  // Set by the core before a failed screenshare when the video token can't be used
+  const tokenRejection = useRef<"Expired" | "Invalid" | null>(null);
+  const alreadySharing = useRef(false);
 
   useEffect(() => {
     if (!hasFetched) {
@@ -74,28 +200,106 @@ function Window() {
     }
   }, [hasFetched]);
 
-  const handleStateUpdate = useCallback(() => {
-    // This should never happen
-    if (!callTokens) return;
+  // Refresh the list when a display is plugged in or removed while the picker is open
+  useEffect(() => {
+    if (!isTauri()) return;
+    const unlistenPromise = listen("available_content_changed", () => {
+      getContent((newContent) => {
+        setContent(newContent);
+        setHasEmptyContentFromBackend(newContent.length === 0);
+      });
+    });
 
-    setCallTokens({
-      ...callTokens,
-      isSharer: true,
-      isRemoteControlEnabled: true,
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (!isTauri()) return;
+    const unlistenPromise = listen<"Expired" | "Invalid">("room_token_rejected", (event) => {
+      tokenRejection.current = event.payload;
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (!isTauri()) return;
+    const unlistenPromise = listen("already_sharing", () => {
+      alreadySharing.current = true;
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const handleStateUpdate = useCallback(
+    (viewOnly: boolean, sharedStream: SharedStream) => {
+      // This should never happen
+      if (!callTokens) return;
+
+      setCallTokens({
+        ...callTokens,
+        isSharer: true,
+        isRemoteControlEnabled: !viewOnly,
+        sharedStream,
+      });
+    },
+    [callTokens],
+  );
+
+  const labels = displayLabels(content);
+
+  // When set, picking content schedules it instead of sharing it now
+  const [scheduleAt, setScheduleAt] = useState("");
+  const [daily, setDaily] = useState(false);
+
+  const scheduleShare = async (item: CaptureContent) => {
+    await tauriUtils.scheduleScreenshare({
+      start_at: nextStartAt(scheduleAt),
+      content: item.content,
+      resolution: resolutionFor(item),
+      view_only: viewOnly,
+      aspect_policy: aspectPolicy,
+      daily,
     });
-  }, [callTokens]);
+    toast.success(`${item.title} will be shared at ${scheduleAt}`);
+  };
 
-  const handleItemClick = async (content: CaptureContent["content"]) => {
+  const handleItemClick = async (item: CaptureContent) => {
     // TODO make this faster
     try {
+      if (scheduleAt) {
+        await scheduleShare(item);
+        return;
+      }
       if (videoToken == null || videoToken == "") {
         toast.error("No video token found");
         return;
       }
-      const success = await screenshare(content, resolution, videoToken);
-      if (success) {
-        handleStateUpdate();
+      tokenRejection.current = null;
+      alreadySharing.current = false;
+      const stream = await screenshare(item.content, resolutionFor(item), videoToken, viewOnly, aspectPolicy);
+      if (stream) {
+        const label = labels.has(item.content.id) ? `Display ${labels.get(item.content.id)}` : item.title;
+        handleStateUpdate(viewOnly, { ...stream, label });
         await appWindow.close();
+      } else if (tokenRejection.current) {
+        toast.error(
+          tokenRejection.current === "Expired" ?
+            "Your call token expired, rejoin the call to share your screen"
+          : "Your call token doesn't allow sharing, rejoin the call to get a new one",
+          { duration: 5000 },
+        );
+        return;
+      } else if (alreadySharing.current) {
+        /* The core keeps the running share, switching content needs it stopped first */
+        toast.error("You are already sharing, stop the current share to pick other content", { duration: 5000 });
+        return;
       } else {
         toast.error(
           (t) => (
@@ -120,6 +324,21 @@ function Window() {
   const updateResolution = (value: string) => {
     setResolution(value as ResolutionKey);
   };
+  // The preset picked for each display, by content id
+  const [presetLabels, setPresetLabels] = useState<Record<number, string>>({});
+
+  const presetFor = (item: CaptureContent) => {
+    const presets = item.presets ?? [];
+    if (presets.length === 0) return null;
+    const label = presetLabels[item.content.id];
+    return presets.find((preset) => preset.label === label) ?? defaultPreset(presets, resolution);
+  };
+
+  // Displays use their preset, the rest of the content the chosen resolution
+  const resolutionFor = (item: CaptureContent) => presetFor(item)?.resolution ?? RESOLUTIONS[resolution];
+  // The core doesn't create any keyboard or mouse injection for view-only shares
+  const [viewOnly, setViewOnly] = useState(false);
+  const [aspectPolicy, setAspectPolicy] = useState<AspectPolicy>("Fit");
 
   return (
     <div className="h-full overflow-hidden dark" tabIndex={0}>
@@ -142,7 +361,56 @@ function Window() {
             <SelectItem value="4K">4K</SelectItem>
           </SelectContent>
         </Select>
+        <span className="mr-2 small">Fit the display:</span>
+        <Select onValueChange={(value) => setAspectPolicy(value as AspectPolicy)} value={aspectPolicy}>
+          <SelectTrigger className="w-[180px]">
+            <SelectValue />
+          </SelectTrigger>
+          <SelectContent>
+            <SelectItem value="Fit">Letterbox</SelectItem>
+            <SelectItem value="Fill">Crop to fill</SelectItem>
+            <SelectItem value="Stretch">Stretch</SelectItem>
+            <SelectItem value="Native">Letterbox, no upscaling</SelectItem>
+          </SelectContent>
+        </Select>
+        <label className="flex flex-row items-center gap-2 small cursor-pointer">
+          <input type="checkbox" checked={viewOnly} onChange={(event) => setViewOnly(event.target.checked)} />
+          View only, nobody can control my computer
+        </label>
+        <span className="mr-2 small">Share later at (the share starts in the call you are in):</span>
+        <div className="flex flex-row items-center gap-2">
+          <Input
+            type="time"
+            className="w-[120px]"
+            value={scheduleAt}
+            onChange={(event) => setScheduleAt(event.target.value)}
+          />
+          <label className="flex flex-row items-center gap-2 small cursor-pointer">
+            <input type="checkbox" checked={daily} onChange={(event) => setDaily(event.target.checked)} />
+            Every day
+          </label>
+          {scheduleAt && (
+            <Button variant="secondary" size="sm" onClick={() => setScheduleAt("")}>
+              Share now instead
+            </Button>
+          )}
+        </div>
       </div>
+      <ThumbnailPrivacySettings
+        onChange={() =>
+          getContent((newContent) => {
+            setContent(newContent);
+            setHasEmptyContentFromBackend(newContent.length === 0);
+          })
+        }
+      />
+      {labels.size > 1 && (
+        <div className="flex flex-row px-4">
+          <Button variant="secondary" size="sm" onClick={() => identifyDisplays(labels).catch(console.error)}>
+            Identify displays
+          </Button>
+        </div>
+      )}
       <div className="content px-4 pb-4 pt-[10px] overflow-auto grid grid-cols-2 gap-4">
         {hasEmptyContentFromBackend ?
           <div className="col-span-2 flex justify-center">
@@ -159,7 +427,7 @@ function Window() {
             <div
               key={item.content.id}
               className="flex flex-col group items-start gap-3 cursor-pointer transition-all duration-300 hover:bg-slate-500 p-2 rounded-md"
-              onClick={() => handleItemClick(item.content)}
+              onClick={() => handleItemClick(item)}
             >
               <AspectRatio ratio={16 / 9}>
                 <img
@@ -167,8 +435,41 @@ function Window() {
                   alt={`Content ${item.content.id}`}
                   className="w-full max-h-full object-contain rounded-md group-hover:scale-[100.5%] transition-all duration-300 overflow-hidden bg-slate-600 bg-opacity-40"
                 />
+                {item.redacted && (
+                  <span
+                    title="This display shows an app whose previews are hidden"
+                    className="absolute top-2 right-2 flex h-7 w-7 items-center justify-center rounded-md bg-slate-900 bg-opacity-85 text-white"
+                  >
+                    <HiOutlineEyeSlash className="h-4 w-4" />
+                  </span>
+                )}
+                {labels.has(item.content.id) && (
+                  <span className="absolute top-2 left-2 flex h-7 min-w-7 items-center justify-center rounded-md bg-slate-900 bg-opacity-85 px-2 text-sm font-bold text-white">
+                    {labels.get(item.content.id)}
+                  </span>
+                )}
               </AspectRatio>
               <span className="text-center small ml-0.5">{`${item.title}`}</span>
+              {item.presets && item.presets.length > 0 && (
+                // Picking a preset doesn't start the share
+                <div onClick={(event) => event.stopPropagation()}>
+                  <Select
+                    value={presetFor(item)?.label}
+                    onValueChange={(label) => setPresetLabels({ ...presetLabels, [item.content.id]: label })}
+                  >
+                    <SelectTrigger className="w-[180px]">
+                      <SelectValue />
+                    </SelectTrigger>
+                    <SelectContent>
+                      {item.presets.map((preset) => (
+                        <SelectItem key={preset.label} value={preset.label}>
+                          {`${preset.label} (${preset.resolution.width}×${preset.resolution.height})`}
+                        </SelectItem>
+                      ))}
+                    </SelectContent>
+                  </Select>
+                </div>
+              )}
             </div>
           ))
         }
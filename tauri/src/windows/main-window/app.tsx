@@ -254,6 +254,18 @@ function App() {
       return unlistenFn;
     };
 
+    const setupCaptureStalledListener = async () => {
+      const unlistenFn = await listen("capture_stalled", () => {
+        console.debug("Screen capture stalled, restarting");
+        toast("Screen sharing stalled, restarting it.", {
+          duration: 5_000,
+          position: "top-center",
+        });
+      });
+
+      return unlistenFn;
+    };
+
     // Update auth token when it changes in the backend
     const setupChangeTokenListener = async () => {
       const unlistenFn = await listen("token_changed", (event) => {
@@ -271,6 +283,11 @@ function App() {
       unlisten = fn;
     });
 
+    let unlistenCaptureStalled: (() => void) | undefined;
+    setupCaptureStalledListener().then((fn) => {
+      unlistenCaptureStalled = fn;
+    });
+
     let unlistenChangeToken: (() => void) | undefined;
     setupChangeTokenListener().then((fn) => {
       unlistenChangeToken = fn;
@@ -278,6 +295,7 @@ function App() {
 
     return () => {
       if (unlisten) unlisten();
+      if (unlistenCaptureStalled) unlistenCaptureStalled();
       if (unlistenChangeToken) unlistenChangeToken();
     };
   }, []);
@@ -6,6 +6,7 @@ import { Textarea } from "@/components/ui/textarea";
 import { FileInput } from "@/components/ui/file-input";
 import * as Sentry from "@sentry/react";
 import { readTextFile } from "@tauri-apps/plugin-fs";
+import { save } from "@tauri-apps/plugin-dialog";
 import useStore from "@/store/store";
 
 const getLogs = async () => {
@@ -13,6 +14,22 @@ const getLogs = async () => {
   return logs;
 };
 
+const exportSupportBundle = async () => {
+  const path = await save({
+    defaultPath: `hopp-support-${new Date().toISOString().slice(0, 10)}.zip`,
+    filters: [{ name: "Zip", extensions: ["zip"] }],
+  });
+  if (path === null) {
+    return;
+  }
+  const exported = await invoke<boolean>("export_support_bundle", { path });
+  if (exported) {
+    toast.success("Support bundle exported");
+  } else {
+    toast.error("Failed to export support bundle");
+  }
+};
+
 const deactivateHiding = async (value: boolean) => {
   await invoke("set_deactivate_hiding", { deactivate: value });
 };
@@ -112,6 +129,9 @@ export function Report() {
         <Button type="submit" className="w-full" disabled={isSubmitting}>
           {isSubmitting ? "Submitting..." : "Submit Report"}
         </Button>
+        <Button type="button" variant="outline" className="w-full" onClick={exportSupportBundle}>
+          Export support bundle
+        </Button>
       </form>
     </div>
   );
@@ -3,16 +3,85 @@ import { Label } from "@/components/ui/label";
 import useStore from "@/store/store";
 import { socketService } from "@/services/socket";
 import { Button } from "@/components/ui/button";
-import { useRef, useState } from "react";
+import { useEffect, useRef, useState } from "react";
 import { Textarea } from "@/components/ui/textarea";
 import { soundUtils } from "@/lib/sound_utils";
-import { tauriUtils } from "@/windows/window-utils.ts";
+import {
+  tauriUtils,
+  type IpcRequestStats,
+  type LabelSize,
+  type NetworkPreflightReport,
+  type OverlayTheme,
+} from "@/windows/window-utils.ts";
 import { validateAndSetAuthToken } from "@/lib/authUtils";
+import { listen } from "@tauri-apps/api/event";
+import { SegmentedControl } from "@/components/ui/segmented-control";
+
+const LABEL_SIZES: { id: LabelSize; content: string }[] = [
+  { id: "Small", content: "Small" },
+  { id: "Medium", content: "Medium" },
+  { id: "Large", content: "Large" },
+  { id: "Auto", content: "Auto" },
+];
+
+const MEDIA_PATH_LABELS: Record<NetworkPreflightReport["media_path"], string> = {
+  Udp: "Calls can use UDP, the best connection.",
+  Tcp: "UDP is blocked, calls fall back to TCP with a higher latency.",
+  TlsOnly: "Only secure web traffic is allowed, calls work only if the server relays media over TLS.",
+  Blocked: "Calls can't reach the server from this network.",
+};
 
 export const Debug = () => {
   const { callTokens, setCallTokens, authToken } = useStore();
   const [isPlaying, setIsPlaying] = useState(false);
   const soundRef = useRef(soundUtils.createPlayer("incoming-call"));
+  const [healthPings, setHealthPings] = useState<boolean | null>(null);
+  const [ipcStats, setIpcStats] = useState<IpcRequestStats[]>([]);
+  const [overlayTheme, setOverlayTheme] = useState<OverlayTheme | null>(null);
+  const [preflightRunning, setPreflightRunning] = useState(false);
+  const [preflight, setPreflight] = useState<NetworkPreflightReport | null>(null);
+
+  useEffect(() => {
+    tauriUtils.getHealthPings().then(setHealthPings).catch(console.error);
+    tauriUtils.getOverlayTheme().then(setOverlayTheme).catch(console.error);
+  }, []);
+
+  useEffect(() => {
+    const unlistenPromise = listen<NetworkPreflightReport>("network_preflight_result", (event) => {
+      setPreflight(event.payload);
+      setPreflightRunning(false);
+    });
+
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const runNetworkPreflight = async () => {
+    setPreflightRunning(true);
+    setPreflight(null);
+    try {
+      await tauriUtils.runNetworkPreflight();
+    } catch (error) {
+      console.error("Error running network preflight:", error);
+      setPreflightRunning(false);
+    }
+  };
+
+  const saveOverlayTheme = async (theme: OverlayTheme) => {
+    setOverlayTheme(theme);
+    await tauriUtils.setOverlayTheme(theme);
+  };
+
+  const toggleHealthPings = async () => {
+    const enabled = !healthPings;
+    await tauriUtils.setHealthPings(enabled);
+    setHealthPings(enabled);
+  };
+
+  const refreshIpcStats = () => {
+    tauriUtils.getIpcStats().then(setIpcStats).catch(console.error);
+  };
 
   const toggleSound = async () => {
     console.log("Toggling sound");
@@ -87,6 +156,123 @@ export const Debug = () => {
         </Button>
         <Button onClick={toggleSound}>{isPlaying ? "Stop call sound" : "Play call sound"}</Button>
       </div>
+      {healthPings !== null && (
+        <div className="grid w-full max-w-sm items-center gap-1.5">
+          <Label>Health pings</Label>
+          <span className="muted">
+            Periodically send anonymous uptime and failure counts, so crashes of the screen sharing process are
+            noticed. Applies after restarting Hopp.
+          </span>
+          <Button variant="outline" onClick={toggleHealthPings}>
+            {healthPings ? "Disable" : "Enable"} health pings
+          </Button>
+        </div>
+      )}
+      {overlayTheme !== null && (
+        <div className="grid w-full max-w-sm items-center gap-1.5 mt-4">
+          <Label htmlFor="maxNameGlyphs">Cursor name length</Label>
+          <span className="muted">
+            Longest name shown next to the cursors on your screen, longer names are shortened in the middle.
+          </span>
+          <Input
+            id="maxNameGlyphs"
+            type="number"
+            min={3}
+            max={40}
+            value={overlayTheme.max_name_glyphs}
+            onChange={(e) => {
+              const maxNameGlyphs = Number(e.target.value);
+              if (!Number.isInteger(maxNameGlyphs) || maxNameGlyphs < 3) return;
+              saveOverlayTheme({ ...overlayTheme, max_name_glyphs: maxNameGlyphs }).catch(console.error);
+            }}
+          />
+          <Label>Cursor name size</Label>
+          <span className="muted">
+            Auto makes the names larger on displays watched from afar, like a TV across the room.
+          </span>
+          <SegmentedControl
+            items={LABEL_SIZES}
+            value={overlayTheme.label_size}
+            onValueChange={(labelSize) =>
+              saveOverlayTheme({ ...overlayTheme, label_size: labelSize as LabelSize }).catch(console.error)
+            }
+          />
+          <Label htmlFor="cursorSmoothing">Cursor smoothing (ms)</Label>
+          <span className="muted">
+            Remote cursors trail their position by this long so they glide instead of jumping on a bad connection, 0
+            turns it off.
+          </span>
+          <Input
+            id="cursorSmoothing"
+            type="number"
+            min={0}
+            max={500}
+            value={overlayTheme.cursor_smoothing_ms}
+            onChange={(e) => {
+              const cursorSmoothing = Number(e.target.value);
+              if (!Number.isInteger(cursorSmoothing) || cursorSmoothing < 0 || cursorSmoothing > 500) return;
+              saveOverlayTheme({ ...overlayTheme, cursor_smoothing_ms: cursorSmoothing }).catch(console.error);
+            }}
+          />
+          <span className="muted">
+            Show the cursors on every copy of a mirrored display, for example a projector. Applies to the next share.
+          </span>
+          <Button
+            variant="outline"
+            onClick={() =>
+              saveOverlayTheme({ ...overlayTheme, mirror_overlays: !overlayTheme.mirror_overlays }).catch(
+                console.error,
+              )
+            }
+          >
+            {overlayTheme.mirror_overlays ? "Disable" : "Enable"} cursors on mirrored displays
+          </Button>
+          <span className="muted">
+            Redraw the cursors and markers of the active share from the textures directory, with the current name
+            length.
+          </span>
+          <Button variant="outline" onClick={() => tauriUtils.reloadOverlayAssets().catch(console.error)}>
+            Reload overlay assets
+          </Button>
+        </div>
+      )}
+      <div className="grid w-full max-w-sm items-center gap-1.5 mt-4">
+        <Label>Network check</Label>
+        <span className="muted">
+          Check how this network reaches the call server before a call, share the results with your IT department if
+          something is blocked.
+        </span>
+        <Button variant="outline" disabled={preflightRunning} onClick={runNetworkPreflight}>
+          {preflightRunning ? "Checking the network..." : "Check network"}
+        </Button>
+        {preflight && (
+          <>
+            <span className="text-sm">{MEDIA_PATH_LABELS[preflight.media_path]}</span>
+            {preflight.checks.map((check) => (
+              <div key={check.kind} className="flex flex-col">
+                <span className="text-xs font-mono">
+                  {check.kind}: {check.status} ({check.duration_ms} ms) {check.detail}
+                </span>
+                {check.guidance && <span className="muted text-xs">{check.guidance}</span>}
+              </div>
+            ))}
+          </>
+        )}
+      </div>
+      <div className="grid w-full max-w-sm items-center gap-1.5 mt-4">
+        <Label>Core requests</Label>
+        <span className="muted">Round trips to the screen sharing process since Hopp started, in milliseconds.</span>
+        {ipcStats.map((stats) => (
+          <span key={stats.request} className="text-xs font-mono">
+            {stats.request}: p50 {stats.p50_ms.toFixed(0)} p95 {stats.p95_ms.toFixed(0)} max{" "}
+            {stats.max_ms.toFixed(0)} lock p95 {stats.lock_wait_p95_ms.toFixed(0)} ({stats.count} requests,{" "}
+            {stats.errors} errors)
+          </span>
+        ))}
+        <Button variant="outline" onClick={refreshIpcStats}>
+          Refresh request timings
+        </Button>
+      </div>
     </div>
   );
 };
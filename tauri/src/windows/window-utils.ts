@@ -90,8 +90,19 @@ const deleteStoredToken = async () => {
   }
 };
 
-const stopSharing = async () => {
-  await invoke("stop_sharing");
+// Stops the share of a session's core process, the default one when no session is given
+const stopSharing = async (sessionId?: string) => {
+  await invoke("stop_sharing", { sessionId });
+};
+
+// Rescales the active share's video without leaving the room, null when it wasn't changed
+const changeResolution = async (resolution: { width: number; height: number }) => {
+  return await invoke<StreamParameters | null>("change_resolution", { resolution });
+};
+
+// Frames per second of the capture and the published video during a share, capped at 60 by the core
+const setFramerate = async (framerate: number) => {
+  await invoke("set_framerate", { framerate });
 };
 
 const showMainWindow = async () => {
@@ -145,6 +156,412 @@ const setControllerCursor = async (enabled: boolean) => {
   await invoke("set_controller_cursor", { enabled: enabled });
 };
 
+const setAdmissionPolicy = async (maxParticipants: number | null, waitingRoom: boolean) => {
+  await invoke("set_admission_policy", { maxParticipants, waitingRoom });
+};
+
+// Whose keystrokes reach the shared computer when several controllers are in control
+export type KeyboardArbitration = "Anyone" | "LastClicked";
+
+const setKeyboardArbitration = async (policy: KeyboardArbitration) => {
+  await invoke("set_keyboard_arbitration", { policy });
+};
+
+// Copied text is synced with the controllers until the session ends, every session starts with it off
+const setClipboardSharing = async (enabled: boolean) => {
+  await invoke("set_clipboard_sharing", { enabled });
+};
+
+// The core sends the shared content's latest frame while the preview is on, see getSharePreview
+const setSharePreview = async (enabled: boolean) => {
+  await invoke("set_share_preview", { enabled });
+};
+
+// The latest preview frame as JPEG bytes, empty before the first frame
+const getSharePreview = async () => {
+  return await invoke<ArrayBuffer>("get_share_preview");
+};
+
+// Key presses only reach the shared computer while one of the apps is in the foreground, an empty list allows every app
+const setKeyboardAppAllowlist = async (apps: string[]) => {
+  await invoke("set_keyboard_app_allowlist", { apps });
+};
+
+// Layouts the core knows the keys of, the controllers' keys are mapped through the forced one instead of the detected one
+export type KeyboardLayoutKind = "Us" | "French" | "German" | "Greek";
+
+// null follows the sharer's detected layout again
+const setKeyboardLayout = async (layout: KeyboardLayoutKind | null) => {
+  await invoke("set_keyboard_layout", { layout });
+};
+
+const admitParticipant = async (sid: string) => {
+  await invoke("admit_participant", { sid });
+};
+
+const kickParticipant = async (sid: string) => {
+  await invoke("kick_participant", { sid });
+};
+
+const banIdentity = async (identity: string) => {
+  await invoke("ban_identity", { identity });
+};
+
+const setInputAuthorized = async (sid: string, authorized: boolean) => {
+  await invoke("set_input_authorized", { sid, authorized });
+};
+
+// Hides or shows the participant's cursor in the sharer's overlay, it keeps watching and controlling
+const setCursorVisible = async (sid: string, visible: boolean) => {
+  await invoke("set_cursor_visible", { sid, visible });
+};
+
+// Gives control to the first participant that raised its hand, the previous one loses it
+const grantNextControl = async () => {
+  await invoke("grant_next_control");
+};
+
+// Rebuilds the overlay drawing the controllers' cursors, after the core gave up on it
+const restartOverlay = async () => {
+  await invoke("restart_overlay");
+};
+
+export type MacroInput =
+  | { MouseMove: { x: number; y: number } }
+  | {
+      MouseClick: {
+        x: number;
+        y: number;
+        button: number;
+        clicks: number;
+        down: boolean;
+        shift: boolean;
+        meta: boolean;
+        ctrl: boolean;
+        alt: boolean;
+      };
+    }
+  | {
+      Keystroke: {
+        keys: string[];
+        shift: boolean;
+        meta: boolean;
+        ctrl: boolean;
+        alt: boolean;
+        down: boolean;
+      };
+    }
+  | { Scroll: { x: number; y: number } };
+
+export type InputMacro = {
+  id: string;
+  name: string;
+  steps: { delay_ms: number; input: MacroInput }[];
+};
+
+const startMacroRecording = async () => {
+  await invoke("start_macro_recording");
+};
+
+const stopMacroRecording = async (name: string) => {
+  return await invoke<InputMacro>("stop_macro_recording", { name });
+};
+
+const loadMacro = async (inputMacro: InputMacro) => {
+  await invoke("load_macro", { inputMacro });
+};
+
+const playMacro = async (id: string) => {
+  await invoke("play_macro", { id });
+};
+
+// What the core created for the active share, view-only shares have no keyboard or mouse injection
+export type InputInjectionStatus = {
+  sharing: boolean;
+  view_only: boolean;
+  keyboard: boolean;
+  mouse: boolean;
+  // The sharer's layout as the platform names it, e.g. "com.apple.keylayout.French"
+  keyboard_layout: string | null;
+};
+
+const getInputInjectionStatus = async () => {
+  return await invoke<InputInjectionStatus>("get_input_injection_status");
+};
+
+export type SessionParticipant = {
+  sid: string;
+  name: string;
+  can_control: boolean;
+  has_control: boolean;
+  quality: "Excellent" | "Good" | "Poor" | "Lost" | null;
+  input_authorized: boolean;
+  cursor_hidden: boolean;
+};
+
+// Snapshot of the core's session, the other fields are empty when not sharing
+export type SessionState = {
+  sharing: boolean;
+  view_only: boolean;
+  content: { content_type: "Display" | { Window: { display_id: number } }; id: number } | null;
+  resolution: { width: number; height: number } | null;
+  participants: SessionParticipant[];
+  room_connected: boolean;
+  stream_health:
+    | "Healthy"
+    | "Stalled"
+    | { Interrupted: "DisplaySleep" | "ScreenLocked" | "Screensaver" | "WindowNotVisible" }
+    | null;
+};
+
+const getSessionState = async () => {
+  return await invoke<SessionState>("get_session_state");
+};
+
+// A camera the core can publish next to the screen share
+export type CameraDevice = {
+  id: string;
+  name: string;
+};
+
+const getCameras = async () => {
+  return await invoke<CameraDevice[]>("get_cameras");
+};
+
+// The core publishes the camera as a second video track, null picks the default camera
+const enableCamera = async (deviceId: string | null) => {
+  await invoke("enable_camera", { deviceId });
+};
+
+const disableCamera = async () => {
+  await invoke("disable_camera");
+};
+
+// A microphone the core can publish
+export type MicrophoneDevice = {
+  id: string;
+  name: string;
+};
+
+// The webrtc audio processing the core applies to the microphone
+export type AudioProcessing = {
+  echo_cancellation: boolean;
+  noise_suppression: boolean;
+  auto_gain_control: boolean;
+};
+
+const getMicrophones = async () => {
+  return await invoke<MicrophoneDevice[]>("get_microphones");
+};
+
+// The core publishes the microphone instead of the webview, null picks the default microphone
+const enableMic = async (deviceId: string | null, processing: AudioProcessing) => {
+  await invoke("enable_mic", { deviceId, processing });
+};
+
+const disableMic = async () => {
+  await invoke("disable_mic");
+};
+
+// Republishes the core's microphone, the core also turns it off when the machine stays busy
+const setNoiseSuppression = async (enabled: boolean) => {
+  await invoke("set_noise_suppression", { enabled });
+};
+
+// Whether the core can capture the audio the machine plays, only Windows can
+const getSystemAudioSupport = async () => {
+  return await invoke<boolean>("get_system_audio_support");
+};
+
+// The core publishes the audio the machine plays next to the screen share
+const enableSystemAudio = async () => {
+  await invoke("enable_system_audio");
+};
+
+const disableSystemAudio = async () => {
+  await invoke("disable_system_audio");
+};
+
+// Parameters of the published video, the core sends them when a share started
+export type StreamParameters = {
+  resolution: { width: number; height: number };
+  fps: number;
+  codec: string;
+  // Max bitrate in bits per second, null when the transport doesn't limit it
+  bitrate: number | null;
+  // null when no display is shared
+  display_scale: number | null;
+};
+
+export type ThumbnailPrivacy = {
+  blocked_apps: string[];
+  redaction: "Blur" | "Placeholder";
+  // Replaces the content titles with generic labels, e.g. "Window 2"
+  generic_titles: boolean;
+};
+
+const getThumbnailPrivacy = async () => {
+  return await invoke<ThumbnailPrivacy>("get_thumbnail_privacy");
+};
+
+const setThumbnailPrivacy = async (privacy: ThumbnailPrivacy) => {
+  await invoke("set_thumbnail_privacy", { privacy });
+};
+
+// Pids or app names kept out of the shared content, the app's own windows always are
+const getCaptureExclusions = async () => {
+  return await invoke<string[]>("get_capture_exclusions");
+};
+
+const setCaptureExclusions = async (exclusions: string[]) => {
+  await invoke("set_capture_exclusions", { exclusions });
+};
+
+// Size of the cursors' names, Auto picks it from the physical size of the shared display
+export type LabelSize = "Small" | "Medium" | "Large" | "Auto";
+
+export type OverlayTheme = {
+  max_name_glyphs: number;
+  // Draws the cursors on every mirror of a mirrored shared display
+  mirror_overlays: boolean;
+  label_size: LabelSize;
+  // Milliseconds the remote cursors trail their positions to glide over dropped packets, 0 turns it off
+  cursor_smoothing_ms: number;
+};
+
+const getOverlayTheme = async () => {
+  return await invoke<OverlayTheme>("get_overlay_theme");
+};
+
+const setOverlayTheme = async (theme: OverlayTheme) => {
+  await invoke("set_overlay_theme", { theme });
+};
+
+// A share started automatically at a given time, e.g. the demo of the daily standup
+export type ScheduledShare = {
+  // Unix time in seconds
+  start_at: number;
+  content: {
+    content_type: "Display" | { Window: { display_id: number } };
+    id: number;
+  };
+  resolution: { width: number; height: number };
+  view_only: boolean;
+  aspect_policy: "Fit" | "Fill" | "Stretch" | "Native";
+  // Repeats every day at the same time
+  daily: boolean;
+};
+
+const scheduleScreenshare = async (share: ScheduledShare) => {
+  await invoke("schedule_screenshare", { share });
+};
+
+const cancelScheduledScreenshare = async () => {
+  await invoke("cancel_scheduled_screenshare");
+};
+
+const getScheduledScreenshare = async () => {
+  return await invoke<ScheduledShare | null>("get_scheduled_screenshare");
+};
+
+// Starts a scheduled share through the same command as the content picker
+const startScheduledShare = async (share: ScheduledShare, videoToken: string) => {
+  return await invoke<StreamParameters | null>("screenshare", {
+    content: share.content,
+    token: videoToken,
+    resolution: share.resolution,
+    viewOnly: share.view_only,
+    aspectPolicy: share.aspect_policy,
+  });
+};
+
+// The last session of a previous core process that ended without being stopped
+export type UncleanSession = {
+  // Unix time in seconds
+  started_at: number;
+  last_event_at: number;
+  content: ScheduledShare["content"];
+  resolution: { width: number; height: number };
+  view_only: boolean;
+  aspect_policy: ScheduledShare["aspect_policy"];
+  journal: string[];
+};
+
+const getUncleanSession = async () => {
+  return await invoke<UncleanSession | null>("get_unclean_session");
+};
+
+const dismissUncleanSession = async () => {
+  await invoke("dismiss_unclean_session");
+};
+
+// Shows the incoming call on a card of the core, so it is seen while the app is in the tray
+const showIncomingCall = async (caller: string, room: string) => {
+  await invoke("show_incoming_call", { caller, room });
+};
+
+const dismissIncomingCall = async () => {
+  await invoke("dismiss_incoming_call");
+};
+
+// Shares the content of the unclean session again with its settings
+const resumeUncleanSession = async (session: UncleanSession, videoToken: string) => {
+  return await invoke<StreamParameters | null>("screenshare", {
+    content: session.content,
+    token: videoToken,
+    resolution: session.resolution,
+    viewOnly: session.view_only,
+    aspectPolicy: session.aspect_policy,
+  });
+};
+
+const reloadOverlayAssets = async () => {
+  await invoke("reload_overlay_assets");
+};
+
+const getHealthPings = async () => {
+  return await invoke<boolean>("get_health_pings");
+};
+
+const setHealthPings = async (enabled: boolean) => {
+  await invoke("set_health_pings", { enabled });
+};
+
+export type IpcRequestStats = {
+  request: string;
+  count: number;
+  errors: number;
+  p50_ms: number;
+  p95_ms: number;
+  max_ms: number;
+  lock_wait_p95_ms: number;
+};
+
+const getIpcStats = async () => {
+  return await invoke<IpcRequestStats[]>("get_ipc_stats");
+};
+
+export type PreflightCheck = {
+  kind: "ServerUrl" | "Dns" | "Signaling" | "TurnUdp" | "TurnTcp" | "IceTcp" | "UdpEgress" | "Bandwidth";
+  status: "Passed" | "Warning" | "Failed" | "Skipped";
+  detail: string;
+  // What the user can do about a warning or a failure
+  guidance: string | null;
+  duration_ms: number;
+};
+
+// Sent as the network_preflight_result event, the checks take several seconds
+export type NetworkPreflightReport = {
+  server: string | null;
+  checks: PreflightCheck[];
+  media_path: "Udp" | "Tcp" | "TlsOnly" | "Blocked";
+  bandwidth_mbps: number | null;
+};
+
+const runNetworkPreflight = async () => {
+  await invoke("run_network_preflight");
+};
+
 const openAccessibilitySettings = async () => {
   return await invoke("open_accessibility_settings");
 };
@@ -202,6 +619,15 @@ const getLivekitUrl = async () => {
   return url;
 };
 
+// Starts another core process, the commands given its session id go to it, e.g. to share a second display
+const startCoreSession = async (sessionId: string) => {
+  return await invoke("start_core_session", { sessionId });
+};
+
+const stopCoreSession = async (sessionId: string) => {
+  return await invoke("stop_core_session", { sessionId });
+};
+
 export const tauriUtils = {
   createScreenShareWindow,
   closeScreenShareWindow,
@@ -211,9 +637,61 @@ export const tauriUtils = {
   getStoredToken,
   deleteStoredToken,
   stopSharing,
+  changeResolution,
+  setFramerate,
   endCallCleanup,
   hideTrayIconInstruction,
   setControllerCursor,
+  setAdmissionPolicy,
+  setKeyboardArbitration,
+  setClipboardSharing,
+  setSharePreview,
+  getSharePreview,
+  setKeyboardAppAllowlist,
+  setKeyboardLayout,
+  admitParticipant,
+  kickParticipant,
+  banIdentity,
+  setInputAuthorized,
+  setCursorVisible,
+  grantNextControl,
+  restartOverlay,
+  startMacroRecording,
+  stopMacroRecording,
+  loadMacro,
+  playMacro,
+  getInputInjectionStatus,
+  getSessionState,
+  getCameras,
+  enableCamera,
+  disableCamera,
+  getMicrophones,
+  enableMic,
+  disableMic,
+  getSystemAudioSupport,
+  enableSystemAudio,
+  disableSystemAudio,
+  setNoiseSuppression,
+  getThumbnailPrivacy,
+  setThumbnailPrivacy,
+  getCaptureExclusions,
+  setCaptureExclusions,
+  getOverlayTheme,
+  setOverlayTheme,
+  scheduleScreenshare,
+  cancelScheduledScreenshare,
+  getScheduledScreenshare,
+  startScheduledShare,
+  getUncleanSession,
+  dismissUncleanSession,
+  showIncomingCall,
+  dismissIncomingCall,
+  resumeUncleanSession,
+  reloadOverlayAssets,
+  getHealthPings,
+  setHealthPings,
+  getIpcStats,
+  runNetworkPreflight,
   getVideoTokenParam,
   openAccessibilitySettings,
   openMicrophoneSettings,
@@ -228,4 +706,6 @@ export const tauriUtils = {
   minimizeMainWindow,
   setLivekitUrl,
   getLivekitUrl,
+  startCoreSession,
+  stopCoreSession,
 };